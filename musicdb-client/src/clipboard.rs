@@ -0,0 +1,105 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/*
+
+speedy2d has no clipboard access, so we use the platform's command-line tools.
+This works with any event loop and doesn't need extra dependencies,
+but requires wl-clipboard, xclip or xsel to be installed on linux.
+
+*/
+
+/// commands which print the clipboard's text, tried in order
+fn paste_commands() -> Vec<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        vec![&["pbpaste"]]
+    } else if cfg!(windows) {
+        vec![&["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"]]
+    } else {
+        let mut cmds: Vec<&'static [&'static str]> = vec![];
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            cmds.push(&["wl-paste", "--no-newline"]);
+        }
+        cmds.push(&["xclip", "-selection", "clipboard", "-out"]);
+        cmds.push(&["xsel", "--clipboard", "--output"]);
+        cmds
+    }
+}
+
+/// commands which replace the clipboard's text with their input, tried in order
+fn copy_commands() -> Vec<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        vec![&["pbcopy"]]
+    } else if cfg!(windows) {
+        vec![&["clip"]]
+    } else {
+        let mut cmds: Vec<&'static [&'static str]> = vec![];
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            cmds.push(&["wl-copy"]);
+        }
+        cmds.push(&["xclip", "-selection", "clipboard", "-in"]);
+        cmds.push(&["xsel", "--clipboard", "--input"]);
+        cmds
+    }
+}
+
+/// the text in the system clipboard, or `None` if it is empty or can't be read.
+pub fn get() -> Option<String> {
+    for cmd in paste_commands() {
+        if let Ok(out) = Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        {
+            if out.status.success() {
+                let text = String::from_utf8_lossy(&out.stdout).into_owned();
+                return if text.is_empty() { None } else { Some(text) };
+            }
+        }
+    }
+    eprintln!("[warn] Couldn't read the clipboard, is wl-clipboard, xclip or xsel installed?");
+    None
+}
+
+/// Makes clipboard text fit into a single-line field:
+/// line breaks and tabs become spaces and other control characters are removed.
+pub fn single_line(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // \r\n is one line break
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' | '\n' | '\t' => out.push(' '),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Puts the text into the system clipboard. Returns `false` if none of the commands worked.
+pub fn set(text: &str) -> bool {
+    for cmd in copy_commands() {
+        if let Ok(mut child) = Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            let written = child
+                .stdin
+                .take()
+                .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+            if child.wait().is_ok_and(|status| status.success()) && written {
+                return true;
+            }
+        }
+    }
+    eprintln!("[warn] Couldn't copy to the clipboard, is wl-clipboard, xclip or xsel installed?");
+    false
+}
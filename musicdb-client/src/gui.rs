@@ -2,26 +2,27 @@ use std::{
     any::Any,
     collections::{BTreeMap, HashMap},
     io::Cursor,
-    net::TcpStream,
-    sync::{mpsc::Sender, Arc, Mutex},
-    thread::JoinHandle,
+    sync::{
+        atomic::AtomicBool,
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
 use musicdb_lib::{
     data::{
-        database::{ClientIo, Database},
+        database::{cover_version, ClientIo, Database},
         queue::Queue,
         song::Song,
         AlbumId, ArtistId, CoverId, SongId,
     },
-    load::ToFromBytes,
     server::{get, Action},
 };
 use speedy2d::{
     color::Color,
     dimen::{UVec2, Vec2},
-    font::Font,
+    font::{Font, TextLayout, TextOptions},
     image::ImageHandle,
     shape::Rectangle,
     window::{
@@ -34,13 +35,14 @@ use speedy2d::{
 #[cfg(feature = "merscfg")]
 use crate::merscfg::MersCfg;
 use crate::{
-    gui_base::{Panel, ScrollBox},
+    gui_base::{ContextMenu, Panel},
+    gui_edit::{EditTarget, Editor},
     gui_edit_song::EditorForSongs,
-    gui_notif::{NotifInfo, NotifOverlay},
+    gui_notif::{song_notif, text_notif, NotifInfo, NotifOverlay},
     gui_screen::GuiScreen,
     gui_song_adder::SongAdder,
     gui_text::Label,
-    textcfg,
+    textcfg, CommandConnection,
 };
 
 pub enum GuiEvent {
@@ -49,6 +51,14 @@ pub enum GuiEvent {
     RefreshMers,
     UpdatedQueue,
     UpdatedLibrary,
+    /// playback was paused, resumed or stopped
+    UpdatedPlayback,
+    /// the connection to the server was lost, the client is reconnecting
+    Disconnected,
+    /// the client is connected to the server again and synced with it
+    Reconnected,
+    #[cfg(feature = "media-keys")]
+    MediaKey(crate::media_keys::MediaKey),
     Exit,
 }
 
@@ -83,8 +93,9 @@ pub fn hotkey_select_songs(modifiers: &ModifiersState, key: Option<VirtualKeyCod
 
 pub fn main(
     database: Arc<Mutex<Database>>,
-    connection: TcpStream,
-    get_con: Arc<Mutex<get::Client<Box<dyn ClientIo + 'static>>>>,
+    connection: CommandConnection,
+    offline: bool,
+    get_con: Arc<get::Pool<Box<dyn ClientIo + 'static>>>,
     event_sender_arc: Arc<Mutex<Option<UserEventSender<GuiEvent>>>>,
     #[cfg(feature = "merscfg")] after_db_cmd: &Arc<
         Mutex<Option<Box<dyn FnMut(Command) + Send + Sync + 'static>>>,
@@ -93,7 +104,7 @@ pub fn main(
     let config_dir = super::get_config_file_path();
     let config_file = config_dir.join("config_gui.toml");
     let mut font = None;
-    let mut line_height = 32.0;
+    let mut line_height = LINE_HEIGHT_DEFAULT;
     let mut scroll_pixels_multiplier = 1.0;
     let mut scroll_lines_multiplier = 3.0;
     let mut scroll_pages_multiplier = 0.75;
@@ -101,6 +112,22 @@ pub fn main(
     let idle_top_text;
     let idle_side1_text;
     let idle_side2_text;
+    let mut idle_clock_text = None;
+    let mut idle_clock_pos = Rectangle::from_tuples((0.01, 0.86), (0.3, 0.94));
+    let mut idle_upcoming_text = None;
+    let mut idle_upcoming_count = 4;
+    let mut idle_upcoming_pos = Rectangle::from_tuples((0.55, 0.21), (0.99, 0.79));
+    let mut filter_presets_song = crate::gui_library::default_filter_presets();
+    let mut filter_presets_album = crate::gui_library::default_filter_presets();
+    let mut filter_presets_artist = crate::gui_library::default_filter_presets();
+    let mut library_sort = crate::gui_library::LibrarySort::default();
+    let mut library_grid = false;
+    let mut song_notifications = false;
+    let mut cover_cache_mib = COVER_CACHE_DEFAULT_MIB;
+    let mut theme = crate::gui_theme::Theme::default();
+    let mut keys = HashMap::new();
+    #[cfg(feature = "media-keys")]
+    let mut media_keys = true;
     match std::fs::read_to_string(&config_file) {
         Ok(cfg) => {
             if let Ok(table) = cfg.parse::<toml::Table>() {
@@ -185,10 +212,177 @@ pub fn main(
                         eprintln!("[toml] missing the required `text.idle_side2` string value.");
                         std::process::exit(30);
                     }
+                    if let Some(v) = t.get("idle_clock").and_then(|v| v.as_str()) {
+                        match v.parse() {
+                            Ok(v) => idle_clock_text = Some(v),
+                            Err(e) => {
+                                eprintln!("[toml] `text.idle_clock couldn't be parsed: {e}`");
+                                std::process::exit(30);
+                            }
+                        }
+                    }
+                    if let Some(v) = t.get("idle_upcoming").and_then(|v| v.as_str()) {
+                        match v.parse() {
+                            Ok(v) => idle_upcoming_text = Some(v),
+                            Err(e) => {
+                                eprintln!("[toml] `text.idle_upcoming couldn't be parsed: {e}`");
+                                std::process::exit(30);
+                            }
+                        }
+                    }
+                    if let Some(v) = t.get("idle_upcoming_count") {
+                        match v.as_integer().and_then(|v| usize::try_from(v).ok()) {
+                            Some(v) => idle_upcoming_count = v,
+                            None => {
+                                eprintln!("[toml] `text.idle_upcoming_count` should be a positive integer.");
+                                std::process::exit(30);
+                            }
+                        }
+                    }
+                    for (key, pos) in [
+                        ("idle_clock_pos", &mut idle_clock_pos),
+                        ("idle_upcoming_pos", &mut idle_upcoming_pos),
+                    ] {
+                        if let Some(v) = t.get(key) {
+                            match rect_from_toml(v) {
+                                Some(v) => *pos = v,
+                                None => {
+                                    eprintln!("[toml] `text.{key}` should be an array of four numbers: [left, top, right, bottom].");
+                                    std::process::exit(30);
+                                }
+                            }
+                        }
+                    }
                 } else {
                     eprintln!("[toml] missing the required `[text]` section!");
                     std::process::exit(30);
                 }
+                if let Some(v) = table.get("library_sort") {
+                    match v
+                        .as_str()
+                        .and_then(crate::gui_library::LibrarySort::from_name)
+                    {
+                        Some(v) => library_sort = v,
+                        None => eprintln!(
+                            "[toml] `library_sort` should be one of {}, using the default.",
+                            crate::gui_library::LibrarySort::ALL
+                                .map(|v| format!("\"{}\"", v.name()))
+                                .join(", ")
+                        ),
+                    }
+                }
+                if let Some(v) = table.get("library_grid") {
+                    match v.as_bool() {
+                        Some(v) => library_grid = v,
+                        None => eprintln!("[toml] `library_grid` should be true or false."),
+                    }
+                }
+                if let Some(v) = table.get("song_notifications") {
+                    match v.as_bool() {
+                        Some(v) => song_notifications = v,
+                        None => eprintln!("[toml] `song_notifications` should be true or false."),
+                    }
+                }
+                if let Some(v) = table.get("cover_cache_mib") {
+                    match v.as_integer().and_then(|v| u64::try_from(v).ok()) {
+                        Some(v) => cover_cache_mib = v,
+                        None => eprintln!(
+                            "[toml] `cover_cache_mib` should be a number of MiB, like 512."
+                        ),
+                    }
+                }
+                if let Some(v) = table.get("theme") {
+                    let Some(v) = v.as_table() else {
+                        eprintln!("[toml] `theme` must be a table, like `[theme]`.");
+                        std::process::exit(30);
+                    };
+                    for (name, color) in v {
+                        let Some(target) = theme.get_mut(name) else {
+                            eprintln!(
+                                "[toml] unknown `theme.{name}`, expected one of {}.",
+                                crate::gui_theme::Theme::NAMES.join(", ")
+                            );
+                            std::process::exit(30);
+                        };
+                        match color.as_str().and_then(crate::gui_theme::parse_color) {
+                            Some(color) => *target = color,
+                            None => {
+                                eprintln!("[toml] `theme.{name}` should be a hex color, like \"#RRGGBB\" or \"#RRGGBBAA\".");
+                                std::process::exit(30);
+                            }
+                        }
+                    }
+                }
+                if let Some(filters) = table.get("filters") {
+                    let Some(filters) = filters.as_table() else {
+                        eprintln!("[toml] `filters` must be a table, like `[filters.song]`.");
+                        std::process::exit(30);
+                    };
+                    for (kind, presets) in filters {
+                        let target = match kind.as_str() {
+                            "song" => &mut filter_presets_song,
+                            "album" => &mut filter_presets_album,
+                            "artist" => &mut filter_presets_artist,
+                            _ => {
+                                eprintln!("[toml] unknown `filters.{kind}`, expected `filters.song`, `filters.album` or `filters.artist`.");
+                                std::process::exit(30);
+                            }
+                        };
+                        match crate::gui_library::filter_presets_from_toml(presets) {
+                            Ok(v) => *target = v,
+                            Err((Some(name), e)) => {
+                                eprintln!("[toml] `filters.{kind}.{name}` couldn't be parsed: {e}");
+                                std::process::exit(30);
+                            }
+                            Err((None, e)) => {
+                                eprintln!("[toml] `filters.{kind}` couldn't be parsed: {e}");
+                                std::process::exit(30);
+                            }
+                        }
+                    }
+                }
+                #[cfg(feature = "media-keys")]
+                if let Some(v) = table.get("media_keys") {
+                    match v.as_bool() {
+                        Some(v) => media_keys = v,
+                        None => eprintln!("[toml] `media_keys` should be true or false."),
+                    }
+                }
+                if let Some(v) = table.get("keys") {
+                    let Some(v) = v.as_table() else {
+                        eprintln!("[toml] `keys` must be a table, like `[keys]`.");
+                        std::process::exit(30);
+                    };
+                    for (name, binds) in v {
+                        if !crate::gui_screen::KEY_ACTION_NAMES.contains(&name.as_str()) {
+                            eprintln!(
+                                "[toml] unknown `keys.{name}`, expected one of {}.",
+                                crate::gui_screen::KEY_ACTION_NAMES.join(", ")
+                            );
+                            std::process::exit(30);
+                        }
+                        let binds = match binds {
+                            toml::Value::String(v) => vec![v.parse::<KeyBinding>()],
+                            toml::Value::Array(v) => v
+                                .iter()
+                                .map(|v| match v.as_str() {
+                                    Some(v) => v.parse::<KeyBinding>(),
+                                    None => Err(format!("expected a string, found `{v}`")),
+                                })
+                                .collect(),
+                            v => vec![Err(format!(
+                                "expected a key or a list of keys, found `{v}`"
+                            ))],
+                        };
+                        match binds.into_iter().collect::<Result<Vec<_>, _>>() {
+                            Ok(binds) => _ = keys.insert(name.clone(), binds),
+                            Err(e) => {
+                                eprintln!("[toml] `keys.{name}` couldn't be parsed: {e}");
+                                std::process::exit(30);
+                            }
+                        }
+                    }
+                }
             } else {
                 eprintln!("Couldn't parse config file {config_file:?} as toml!");
                 std::process::exit(30);
@@ -213,7 +407,7 @@ pub fn main(
     };
 
     let window = speedy2d::Window::<GuiEvent>::new_with_user_events(
-        "MusicDB Client",
+        WINDOW_TITLE,
         WindowCreationOptions::new_windowed(
             speedy2d::window::WindowSize::MarginPhysicalPixels(0),
             None,
@@ -221,11 +415,16 @@ pub fn main(
     )
     .expect("couldn't open window");
     *event_sender_arc.lock().unwrap() = Some(window.create_user_event_sender());
+    #[cfg(feature = "media-keys")]
+    if media_keys {
+        crate::media_keys::start(Arc::clone(&database), window.create_user_event_sender());
+    }
     let sender = window.create_user_event_sender();
     window.run_loop(Gui::new(
         font,
         Arc::clone(&database),
         connection,
+        offline,
         get_con,
         event_sender_arc,
         Arc::new(sender),
@@ -238,36 +437,20 @@ pub fn main(
             idle_top_text,
             idle_side1_text,
             idle_side2_text,
-            filter_presets_song: vec![
-                (
-                    "Fav".to_owned(),
-                    crate::gui_library::FilterType::TagEq("Fav".to_owned()),
-                ),
-                (
-                    "Year".to_owned(),
-                    crate::gui_library::FilterType::TagWithValueInt("Year=".to_owned(), 1990, 2000),
-                ),
-            ],
-            filter_presets_album: vec![
-                (
-                    "Fav".to_owned(),
-                    crate::gui_library::FilterType::TagEq("Fav".to_owned()),
-                ),
-                (
-                    "Year".to_owned(),
-                    crate::gui_library::FilterType::TagWithValueInt("Year=".to_owned(), 1990, 2000),
-                ),
-            ],
-            filter_presets_artist: vec![
-                (
-                    "Fav".to_owned(),
-                    crate::gui_library::FilterType::TagEq("Fav".to_owned()),
-                ),
-                (
-                    "Year".to_owned(),
-                    crate::gui_library::FilterType::TagWithValueInt("Year=".to_owned(), 1990, 2000),
-                ),
-            ],
+            idle_clock_text,
+            idle_clock_pos,
+            idle_upcoming_text,
+            idle_upcoming_count,
+            idle_upcoming_pos,
+            filter_presets_song,
+            filter_presets_album,
+            filter_presets_artist,
+            library_sort,
+            library_grid,
+            song_notifications,
+            cover_cache_mib,
+            theme,
+            keys,
             #[cfg(feature = "merscfg")]
             merscfg: crate::merscfg::MersCfg::new(config_dir.join("dynamic_config.mers"), database),
         },
@@ -276,14 +459,73 @@ pub fn main(
     ));
 }
 
+/// Changes a top-level value in config_gui.toml, for settings which are changed in the gui.
+/// Only the line of that value is changed, so the comments in the file are kept.
+pub fn save_config_value(key: &str, value: toml::Value) {
+    let config_file = super::get_config_file_path().join("config_gui.toml");
+    let result = std::fs::read_to_string(&config_file)
+        .and_then(|cfg| std::fs::write(&config_file, set_config_value(&cfg, key, &value)));
+    if let Err(e) = result {
+        eprintln!("[warn] couldn't save `{key}` to {config_file:?}: {e}");
+    }
+}
+/// Replaces the line `key = ...` before the first `[section]`, or adds it before the first section.
+fn set_config_value(cfg: &str, key: &str, value: &toml::Value) -> String {
+    let new_line = format!("{key} = {value}");
+    let mut out = Vec::new();
+    let mut done = false;
+    for line in cfg.lines() {
+        if !done {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with('[') {
+                out.push(new_line.as_str());
+                out.push("");
+                done = true;
+            } else if trimmed
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+            {
+                out.push(new_line.as_str());
+                done = true;
+                continue;
+            }
+        }
+        out.push(line);
+    }
+    if !done {
+        out.push(&new_line);
+    }
+    let mut out = out.join("\n");
+    out.push('\n');
+    out
+}
+
 pub struct GuiConfig {
     pub status_bar_text: textcfg::TextBuilder,
     pub idle_top_text: textcfg::TextBuilder,
     pub idle_side1_text: textcfg::TextBuilder,
     pub idle_side2_text: textcfg::TextBuilder,
+    /// the idle screen's clock, hidden if `None`
+    pub idle_clock_text: Option<textcfg::TextBuilder>,
+    pub idle_clock_pos: Rectangle,
+    /// generated once for each of the next `idle_upcoming_count` songs on the idle screen, hidden if `None`
+    pub idle_upcoming_text: Option<textcfg::TextBuilder>,
+    pub idle_upcoming_count: usize,
+    pub idle_upcoming_pos: Rectangle,
     pub filter_presets_song: Vec<(String, crate::gui_library::FilterType)>,
     pub filter_presets_album: Vec<(String, crate::gui_library::FilterType)>,
     pub filter_presets_artist: Vec<(String, crate::gui_library::FilterType)>,
+    pub library_sort: crate::gui_library::LibrarySort,
+    /// show the library's albums as a grid of covers instead of a list
+    pub library_grid: bool,
+    /// show a notification when another song starts playing, unless it was started from this client
+    pub song_notifications: bool,
+    /// how much memory the full-size covers may use, see `Covers`. only read when the gui is created.
+    pub cover_cache_mib: u64,
+    /// colors from the `[theme]` section
+    pub theme: crate::gui_theme::Theme,
+    /// bindings from the `[keys]` section, which replace the defaults of the key actions with these names
+    pub keys: HashMap<String, Vec<KeyBinding>>,
     #[cfg(feature = "merscfg")]
     pub merscfg: crate::merscfg::MersCfg,
 }
@@ -291,8 +533,18 @@ pub struct GuiConfig {
 pub struct Gui {
     pub event_sender: Arc<UserEventSender<GuiEvent>>,
     pub database: Arc<Mutex<Database>>,
-    pub connection: TcpStream,
-    pub get_con: Arc<Mutex<get::Client<Box<dyn ClientIo + 'static>>>>,
+    pub connection: CommandConnection,
+    /// set while reconnecting to the server, ends the "reconnecting" notification once it is set to `true`
+    pub reconnecting: Option<Arc<AtomicBool>>,
+    /// hides the last notification of `scale_to`, so that only the newest scale is shown
+    pub scale_notif: Option<Arc<AtomicBool>>,
+    /// the title the window currently has, see `window_title`
+    pub window_title: String,
+    /// the song which was playing when the queue was last updated, to notice when another one starts
+    pub current_song: Option<SongId>,
+    /// when this client last sent an action which changes the song, see `GuiConfig::song_notifications`
+    pub local_song_change: Option<Instant>,
+    pub get_con: Arc<get::Pool<Box<dyn ClientIo + 'static>>>,
     pub gui: GuiScreen,
     pub notif_sender:
         Sender<Box<dyn FnOnce(&NotifOverlay) -> (Box<dyn GuiElem>, NotifInfo) + Send>>,
@@ -301,15 +553,22 @@ pub struct Gui {
     pub font: Font,
     pub keybinds: BTreeMap<KeyBinding, KeyActionRef>,
     pub key_actions: KeyActions,
-    pub covers: Option<HashMap<CoverId, GuiServerImage>>,
+    pub covers: Option<Covers>,
+    pub thumbnails: Option<Thumbnails>,
     pub custom_images: Option<HashMap<String, GuiServerImage>>,
+    /// the server's custom files, loaded in the background when the gui is created
+    pub custom_files: Arc<Mutex<Vec<String>>>,
     pub modifiers: ModifiersState,
+    /// counts the context menus which were opened, to notice when an action opens a new one
+    pub context_menus_opened: usize,
     pub dragging: Option<(
         Dragging,
         Option<Box<dyn FnMut(&mut DrawInfo, &mut Graphics2D)>>,
     )>,
     pub high_performance: bool,
     pub line_height: f32,
+    /// the `line_height` in config_gui.toml, it is saved there when the gui is closed if it was changed
+    pub line_height_saved: f32,
     pub last_height: f32,
     pub scroll_pixels_multiplier: f64,
     pub scroll_lines_multiplier: f64,
@@ -318,13 +577,52 @@ pub struct Gui {
     last_performance_check: Instant,
     average_frame_time_ms: u32,
     frames_drawn: u32,
+    /// loaded covers are checked for changes on the server every `COVER_VERSION_CHECK_INTERVAL`
+    last_cover_version_check: Instant,
+    cover_version_check: Option<mpsc::Receiver<Vec<(CoverId, u64)>>>,
+    /// when the mouse stopped moving. `None` after a click, until the mouse is moved again.
+    mouse_rest: Option<Instant>,
+}
+const COVER_VERSION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// how long the mouse has to rest on an element before its tooltip is shown
+const TOOLTIP_DELAY: Duration = Duration::from_millis(600);
+/// the height of the tooltip's text, relative to `line_height`
+const TOOLTIP_TEXT_HEIGHT: f32 = 0.5;
+
+/// draws `text` in a box below the mouse (or above it, if there is no space), always inside the window
+fn draw_tooltip(info: &DrawInfo, text: &str, g: &mut Graphics2D) {
+    let l = info.font.layout_text(text, 1.0, TextOptions::new());
+    if l.height() <= 0.0 {
+        return;
+    }
+    let l = info.font.layout_text(
+        text,
+        TOOLTIP_TEXT_HEIGHT * info.line_height / l.height(),
+        TextOptions::new(),
+    );
+    let padding = 0.2 * info.line_height * TOOLTIP_TEXT_HEIGHT;
+    let size = l.size() + Vec2::new(2.0 * padding, 2.0 * padding);
+    let window = info.pos.size();
+    let mut top_left = info.mouse_pos + Vec2::new(0.0, 0.6 * info.line_height);
+    if top_left.y + size.y > window.y {
+        top_left.y = info.mouse_pos.y - size.y - 0.2 * info.line_height;
+    }
+    top_left.x = top_left.x.min(window.x - size.x).max(0.0);
+    top_left.y = top_left.y.min(window.y - size.y).max(0.0);
+    let theme = &info.gui_config.theme;
+    g.draw_rectangle(
+        Rectangle::new(top_left, top_left + size),
+        theme.notif_background,
+    );
+    g.draw_text(top_left + Vec2::new(padding, padding), theme.text, &l);
 }
 impl Gui {
     fn new(
         font: Font,
         database: Arc<Mutex<Database>>,
-        connection: TcpStream,
-        get_con: Arc<Mutex<get::Client<Box<dyn ClientIo + 'static>>>>,
+        connection: CommandConnection,
+        offline: bool,
+        get_con: Arc<get::Pool<Box<dyn ClientIo + 'static>>>,
         event_sender_arc: Arc<Mutex<Option<UserEventSender<GuiEvent>>>>,
         event_sender: Arc<UserEventSender<GuiEvent>>,
         line_height: f32,
@@ -378,12 +676,31 @@ impl Gui {
                         >,
                     ) {
                         match action {
-                            Action::Resume
-                            | Action::Pause
-                            | Action::Stop
-                            | Action::Save
+                            Action::Resume | Action::Pause | Action::Stop => {
+                                if let Some(s) = &*event_sender_arc.lock().unwrap() {
+                                    _ = s.send_event(GuiEvent::UpdatedPlayback);
+                                }
+                            }
+                            Action::Save
+                            | Action::Ping
+                            | Action::RescanLibrary
+                            | Action::SetDurationBackfill(_)
+                            | Action::VerifyLibrary(_)
+                            | Action::PlaybackPosition(..)
+                            | Action::NextSongIfCurrent(..)
+                            | Action::Seek(_)
+                            | Action::SeekBy(_)
+                            | Action::SetVolume(_)
+                            | Action::SetCrossfade(_)
+                            | Action::SetReplayGain(..)
+                            | Action::SetSleepTimer(..)
+                            | Action::PlaylistSave(..)
+                            | Action::PlaylistLoad(..)
+                            | Action::PlaylistDelete(_)
+                            | Action::PlaylistRename(..)
                             | Action::InitComplete => {}
                             Action::NextSong
+                            | Action::QueueFinished
                             | Action::QueueUpdate(..)
                             | Action::QueueAdd(..)
                             | Action::QueueInsert(..)
@@ -393,7 +710,15 @@ impl Gui {
                             | Action::QueueGoto(..)
                             | Action::QueueShuffle(..)
                             | Action::QueueSetShuffle(..)
-                            | Action::QueueUnshuffle(..) => {
+                            | Action::QueueUnshuffle(..)
+                            | Action::QueueClear(..)
+                            | Action::QueueShuffleInPlace(..)
+                            | Action::QueueDuplicate(..)
+                            | Action::QueueLoopBreak(..)
+                            | Action::QueueDedupSongs(..)
+                            | Action::QueuePlayNext(..)
+                            | Action::QueueSetLabel(..)
+                            | Action::SetAutoplayOnEmpty(..) => {
                                 if let Some(s) = &*event_sender_arc.lock().unwrap() {
                                     _ = s.send_event(GuiEvent::UpdatedQueue);
                                 }
@@ -436,7 +761,7 @@ impl Gui {
                                 notif_sender_two
                                     .send(Box::new(move |_| {
                                         (
-                                            Box::new(Panel::with_background(
+                                            Box::new(Panel::with_theme_background(
                                                 GuiElemCfg::default(),
                                                 [Label::new(
                                                     GuiElemCfg::default(),
@@ -449,7 +774,7 @@ impl Gui {
                                                     None,
                                                     Vec2::new(0.5, 0.5),
                                                 )],
-                                                Color::from_rgba(0.0, 0.0, 0.0, 0.8),
+                                                |t| t.notif_background,
                                             )),
                                             if t.is_empty() {
                                                 NotifInfo::new(Duration::from_secs(2))
@@ -466,7 +791,7 @@ impl Gui {
                                 notif_sender_two
                                     .send(Box::new(move |_| {
                                         (
-                                            Box::new(Panel::with_background(
+                                            Box::new(Panel::with_theme_background(
                                                 GuiElemCfg::default(),
                                                 [Label::new(
                                                     GuiElemCfg::default(),
@@ -482,7 +807,7 @@ impl Gui {
                                                     None,
                                                     Vec2::new(0.5, 0.5),
                                                 )],
-                                                Color::from_rgba(0.0, 0.0, 0.0, 0.8),
+                                                |t| t.notif_background,
                                             )),
                                             NotifInfo::new(Duration::from_secs(1)),
                                         )
@@ -494,11 +819,47 @@ impl Gui {
                 })),
             ));
         }
+        let custom_files = Arc::new(Mutex::new(vec![]));
+        {
+            let custom_files = Arc::clone(&custom_files);
+            let get_con = Arc::clone(&get_con);
+            let event_sender = Arc::clone(&event_sender);
+            std::thread::spawn(move || {
+                let files = get_con.with_reconnect(|get_con| get_con.custom_files_list());
+                match files {
+                    Ok(Ok(files)) => {
+                        *custom_files.lock().unwrap() =
+                            files.into_iter().map(|(path, _)| path).collect();
+                        _ = event_sender.send_event(GuiEvent::Refresh);
+                    }
+                    Ok(Err(e)) => musicdb_lib::log_info!("No custom files: {}", e.trim()),
+                    Err(e) => musicdb_lib::log_warn!("Couldn't load list of custom files: {e}"),
+                }
+            });
+        }
+        // redraw regularly while playing, so the seek bar and the elapsed time keep moving
+        {
+            let database = Arc::clone(&database);
+            let event_sender = Arc::clone(&event_sender);
+            std::thread::spawn(move || loop {
+                std::thread::sleep(Duration::from_millis(500));
+                if database.lock().unwrap().playing
+                    && event_sender.send_event(GuiEvent::Refresh).is_err()
+                {
+                    break;
+                }
+            });
+        }
         let no_animations = false;
-        Gui {
+        let mut gui = Gui {
             event_sender,
             database,
             connection,
+            reconnecting: None,
+            scale_notif: None,
+            window_title: WINDOW_TITLE.to_owned(),
+            current_song: None,
+            local_song_change: None,
             get_con,
             gui: GuiScreen::new(
                 GuiElemCfg::default(),
@@ -515,13 +876,17 @@ impl Gui {
             font,
             keybinds: BTreeMap::new(),
             key_actions: KeyActions::default(),
-            covers: Some(HashMap::new()),
+            context_menus_opened: 0,
+            covers: Some(Covers::new(gui_config.cover_cache_mib * 1024 * 1024)),
+            thumbnails: Some(Thumbnails::default()),
             custom_images: Some(HashMap::new()),
+            custom_files,
             // font: Font::new(include_bytes!("/usr/share/fonts/TTF/FiraSans-Regular.ttf")).unwrap(),
             modifiers: ModifiersState::default(),
             dragging: None,
             high_performance: no_animations,
             line_height,
+            line_height_saved: line_height,
             last_height: 720.0,
             scroll_pixels_multiplier,
             scroll_lines_multiplier,
@@ -530,31 +895,41 @@ impl Gui {
             last_performance_check: Instant::now(),
             average_frame_time_ms: 0,
             frames_drawn: 0,
+            last_cover_version_check: Instant::now(),
+            cover_version_check: None,
+            mouse_rest: None,
+        };
+        if offline {
+            // until the client can connect to the server
+            gui.show_reconnecting(
+                "Can't connect to the server\nshowing the cached library (read-only)",
+            );
         }
+        gui
     }
 
     fn get_specific_gui_elem_config(&mut self, elem: SpecificGuiElem) -> &mut GuiElemCfg {
         match elem {
-            SpecificGuiElem::SearchArtist => self
+            SpecificGuiElem::Search => self
                 .gui
                 .c_main_view
                 .children
                 .library_browser
-                .c_search_artist
+                .c_search
                 .config_mut(),
-            SpecificGuiElem::SearchAlbum => self
+            SpecificGuiElem::SearchArtist => self
                 .gui
                 .c_main_view
                 .children
                 .library_browser
-                .c_search_album
+                .c_search_artist
                 .config_mut(),
-            SpecificGuiElem::SearchSong => self
+            SpecificGuiElem::SearchAlbum => self
                 .gui
                 .c_main_view
                 .children
                 .library_browser
-                .c_search_song
+                .c_search_album
                 .config_mut(),
         }
     }
@@ -667,6 +1042,9 @@ pub(crate) trait GuiElemInternal: GuiElem {
         info.mouse_pos_in_bounds = info.pos.contains(info.mouse_pos);
         if !info.mouse_pos_in_bounds {
             self.config_mut().mouse_down = (false, false, false);
+        } else if self.config().mouse_events || self.config().tooltip.is_some() {
+            // elements drawn later are on top, so the last one wins
+            info.tooltip = self.config().tooltip.clone();
         }
         // call trait's draw function
         self.draw(info, g);
@@ -1164,6 +1542,8 @@ pub struct GuiElemCfg {
     pub request_keyboard_focus: bool,
     /// if this is true, things can be dragged into this element via drag-n-drop
     pub drag_target: bool,
+    /// shown near the mouse once it has rested on this element for `TOOLTIP_DELAY`
+    pub tooltip: Option<String>,
 }
 #[allow(unused)]
 impl GuiElemCfg {
@@ -1201,6 +1581,10 @@ impl GuiElemCfg {
         self.enabled = false;
         self
     }
+    pub fn w_tooltip(mut self, tooltip: String) -> Self {
+        self.tooltip = Some(tooltip);
+        self
+    }
 }
 impl Default for GuiElemCfg {
     fn default() -> Self {
@@ -1219,15 +1603,16 @@ impl Default for GuiElemCfg {
             keyboard_focus_index: usize::MAX,
             request_keyboard_focus: false,
             drag_target: false,
+            tooltip: None,
         }
     }
 }
 #[allow(unused)]
 pub enum GuiAction {
     OpenMain,
-    /// Add a key action (and optionally bind it) and then call the given function
+    /// Add a key action (and bind it, unless config_gui.toml replaces these default bindings) and then call the given function
     AddKeybind(
-        Option<(KeyBinding, bool)>,
+        Vec<(KeyBinding, bool)>,
         KeyAction,
         Box<dyn FnOnce(KeyActionId)>,
     ),
@@ -1258,22 +1643,26 @@ pub enum GuiAction {
     Do(Box<dyn FnOnce(&mut Gui)>),
     Exit,
     EditSongs(Vec<Song>),
+    /// opens the editor for a song, album or artist
+    Edit(EditTarget),
     // EditAlbums(Vec<Album>),
     // EditArtists(Vec<Artist>),
     OpenAddSongsMenu,
     CloseAddSongsMenu,
+    /// scrolls the queue and the library to the song which is currently playing
+    JumpToCurrent,
 }
 pub enum Dragging {
     Artist(ArtistId),
     Album(AlbumId),
     Song(SongId),
     Queue(Result<Queue, Vec<usize>>),
-    Queues(Vec<Queue>),
+    Queues(Result<Vec<Queue>, Vec<Vec<usize>>>),
 }
 pub enum SpecificGuiElem {
+    Search,
     SearchArtist,
     SearchAlbum,
-    SearchSong,
 }
 
 /// GuiElems have access to this within draw.
@@ -1290,9 +1679,12 @@ pub struct DrawInfo<'a> {
     /// true if `info.pos.contains(info.mouse_pos)`.
     pub mouse_pos_in_bounds: bool,
     pub helper: Option<&'a mut WindowHelper<GuiEvent>>,
-    pub get_con: Arc<Mutex<get::Client<Box<dyn ClientIo + 'static>>>>,
-    pub covers: &'a mut HashMap<CoverId, GuiServerImage>,
+    pub get_con: Arc<get::Pool<Box<dyn ClientIo + 'static>>>,
+    pub covers: &'a mut Covers,
+    pub thumbnails: &'a mut Thumbnails,
     pub custom_images: &'a mut HashMap<String, GuiServerImage>,
+    /// paths of the server's custom files, may be empty if they haven't been loaded yet
+    pub custom_files: &'a [String],
     pub has_keyboard_focus: bool,
     pub child_has_keyboard_focus: bool,
     /// the height of one line of text (in pixels)
@@ -1303,6 +1695,8 @@ pub struct DrawInfo<'a> {
     )>,
     pub gui_config: &'a mut GuiConfig,
     pub high_performance: bool,
+    /// the tooltip of the element under the mouse, set while drawing
+    pub tooltip: Option<String>,
 }
 
 pub fn adjust_area(outer: &Rectangle, rel_area: &Rectangle) -> Rectangle {
@@ -1319,6 +1713,111 @@ pub fn adjust_pos(outer: &Rectangle, rel_pos: &Vec2) -> Vec2 {
 }
 
 impl Gui {
+    /// Shows a `song_notif` if another song started playing, see `GuiConfig::song_notifications`.
+    fn check_song_changed(&mut self) {
+        let db = self.database.lock().unwrap();
+        let song = db.queue.get_current_song(&db).copied();
+        if song == self.current_song {
+            return;
+        }
+        self.current_song = song;
+        let local = self
+            .local_song_change
+            .take()
+            .is_some_and(|t| t.elapsed() < LOCAL_SONG_CHANGE_TIMEOUT);
+        let enabled = self
+            .gui_config
+            .as_ref()
+            .is_some_and(|cfg| cfg.song_notifications);
+        // while syncing with the server, the song didn't actually change
+        if local || !enabled || !db.is_client_init() {
+            return;
+        }
+        if let Some(song) = song.and_then(|id| db.get_song(&id)) {
+            let action = song_notif(
+                song.title.clone(),
+                db.artists().get(&song.artist).map(|a| a.name.clone()),
+                crate::gui_playback::song_cover(&db, &song.id),
+            );
+            drop(db);
+            self.exec_gui_action(action);
+        }
+    }
+    /// Sets the window's title to the current song, must be called on the main thread.
+    fn update_window_title(&mut self, helper: &mut WindowHelper<GuiEvent>) {
+        let title = window_title(&self.database.lock().unwrap());
+        if title != self.window_title {
+            helper.set_title(&title);
+            self.window_title = title;
+        }
+    }
+    /// Sets the line height (the size of text and most of the layout) and briefly shows the new scale.
+    pub fn scale_to(&mut self, line_height: f32) {
+        let line_height = line_height.clamp(LINE_HEIGHT_MIN, LINE_HEIGHT_MAX);
+        self.exec_gui_action(GuiAction::SetLineHeight(line_height));
+        let done = Arc::new(AtomicBool::new(false));
+        if let Some(prev) = self.scale_notif.replace(Arc::clone(&done)) {
+            prev.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+        self.exec_gui_action(GuiAction::ShowNotification(Box::new(move |_| {
+            (
+                Box::new(Panel::with_theme_background(
+                    GuiElemCfg::default(),
+                    [Label::new(
+                        GuiElemCfg::default(),
+                        format!("Scale: {:.0}%", 100.0 * line_height / LINE_HEIGHT_DEFAULT),
+                        Color::WHITE,
+                        None,
+                        Vec2::new(0.5, 0.5),
+                    )],
+                    |t| t.notif_background,
+                )),
+                NotifInfo::new(Duration::from_secs(1)).or_until(done),
+            )
+        })));
+    }
+    /// Shows a notification until the client is connected to the server again, see `GuiEvent::Reconnected`.
+    fn show_reconnecting(&mut self, text: &'static str) {
+        if self.reconnecting.is_some() {
+            return;
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        self.reconnecting = Some(Arc::clone(&done));
+        self.exec_gui_action(GuiAction::ShowNotification(Box::new(move |_| {
+            (
+                Box::new(Panel::with_theme_background(
+                    GuiElemCfg::default(),
+                    [Label::new(
+                        GuiElemCfg::default(),
+                        text.to_owned(),
+                        Color::WHITE,
+                        None,
+                        Vec2::new(0.5, 0.5),
+                    )],
+                    |t| t.notif_background,
+                )),
+                NotifInfo::until(done).with_highlight(Color::RED),
+            )
+        })));
+    }
+    /// Opens or closes the context menu. An open menu gets the keyboard focus.
+    fn set_context_menu(&mut self, menu: Option<Box<dyn GuiElem>>) {
+        let had_menu = self.gui.c_context_menu.is_some();
+        let opened = menu.is_some();
+        if had_menu && !opened {
+            // the menu is the first child, so the other children's indices change
+            let focus = &mut self.gui.config_mut().keyboard_focus_index;
+            *focus = match *focus {
+                0 | usize::MAX => usize::MAX,
+                i => i - 1,
+            };
+        }
+        self.gui.c_context_menu = menu;
+        if opened {
+            self.context_menus_opened += 1;
+            self.gui._keyboard_reset_focus();
+        }
+    }
     pub fn exec_gui_action(&mut self, action: GuiAction) {
         match action {
             GuiAction::Build(f) => {
@@ -1327,9 +1826,19 @@ impl Gui {
                     self.exec_gui_action(action);
                 }
             }
-            GuiAction::AddKeybind(bind, action, func) => {
+            GuiAction::AddKeybind(binds, action, func) => {
+                let configured = self
+                    .gui_config
+                    .as_ref()
+                    .and_then(|cfg| cfg.keys.get(action.name));
+                let binds = if let Some(configured) = configured {
+                    let priority = binds.first().is_some_and(|(_, priority)| *priority);
+                    configured.iter().map(|bind| (*bind, priority)).collect()
+                } else {
+                    binds
+                };
                 let id = self.key_actions.add(action);
-                if let Some((bind, priority)) = bind {
+                for (bind, priority) in binds {
                     self.keybinds.insert(bind, id.with_priority(priority));
                 }
                 func(id);
@@ -1355,11 +1864,15 @@ impl Gui {
                 }
             }
             GuiAction::SendToServer(action) => {
+                if changes_song(&action) {
+                    self.local_song_change = Some(Instant::now());
+                }
                 let command = self.database.lock().unwrap().seq.pack(action);
                 #[cfg(debug_assertions)]
-                eprintln!("[DEBUG] Sending command to server: {command:?}");
-                if let Err(e) = command.to_bytes(&mut self.connection) {
-                    eprintln!("Error sending command to server: {e}");
+                musicdb_lib::log_debug!("Sending command to server: {command:?}");
+                if let Err(e) = crate::send_command(&self.connection, &command) {
+                    eprintln!("[warn] Couldn't send command to server: {e}");
+                    self.exec_gui_action(text_notif(format!("Action not sent: {e}")));
                 }
             }
             GuiAction::ShowNotification(func) => _ = self.notif_sender.send(func),
@@ -1372,7 +1885,7 @@ impl Gui {
             GuiAction::SetDragging(d) => self.dragging = d,
             GuiAction::SetHighPerformance(d) => self.high_performance = d,
             GuiAction::ContextMenu(elems) => {
-                self.gui.c_context_menu = if let Some(elems) = elems {
+                let menu = if let Some(elems) = elems {
                     let elem_height = 32.0;
                     let w = elem_height * 6.0;
                     let h = elem_height * elems.len() as f32;
@@ -1394,28 +1907,34 @@ impl Gui {
                     if ay < 0.0 {
                         ay = 0.0;
                     }
-                    Some(Box::new(ScrollBox::new(
+                    Some(Box::new(ContextMenu::new(
                         GuiElemCfg::at(Rectangle::from_tuples((ax, ay), (bx, by))),
-                        crate::gui_base::ScrollBoxSizeUnit::Pixels,
                         elems,
-                        vec![],
                         elem_height,
-                    )))
+                    )) as Box<dyn GuiElem>)
                 } else {
                     None
                 };
+                self.set_context_menu(menu);
             }
             GuiAction::SetLineHeight(h) => {
                 self.line_height = h;
+                let slider = &mut self
+                    .gui
+                    .c_settings
+                    .c_scroll_box
+                    .children
+                    .line_height
+                    .children
+                    .1;
+                if slider.val != h as f64 {
+                    slider.val = h as f64;
+                    slider.config_mut().redraw = true;
+                }
                 self.gui
                     ._recursive_all(true, &mut |e| e.config_mut().redraw = true);
             }
-            GuiAction::LoadCover(id) => {
-                self.covers
-                    .as_mut()
-                    .unwrap()
-                    .insert(id, GuiServerImage::new_cover(id, Arc::clone(&self.get_con)));
-            }
+            GuiAction::LoadCover(id) => self.covers.as_mut().unwrap().reload(id),
             GuiAction::Do(f) => f(self),
             GuiAction::Exit => _ = self.event_sender.send_event(GuiEvent::Exit),
             GuiAction::ForceIdle => {
@@ -1440,9 +1959,27 @@ impl Gui {
                     self.gui.settings = (false, Some(Instant::now()));
                 }
             }
+            GuiAction::Edit(target) => {
+                let editor = Editor::new(target, &self.database.lock().unwrap());
+                if editor.is_some() {
+                    self.gui.c_editor = editor;
+                }
+            }
             GuiAction::EditSongs(songs) => {
                 self.gui.c_editing_songs = Some(EditorForSongs::new(songs));
             }
+            GuiAction::JumpToCurrent => {
+                let db = self.database.lock().unwrap();
+                let main = &mut self.gui.c_main_view.children;
+                main.queue_viewer.jump_to_current();
+                if let Some(song) = db
+                    .queue
+                    .get_current_song(&db)
+                    .and_then(|id| db.get_song(id))
+                {
+                    main.library_browser.show_song(&db, song);
+                }
+            }
             GuiAction::OpenAddSongsMenu => {
                 if self.gui.c_song_adder.is_none() {
                     self.gui.c_song_adder = Some(SongAdder::new(
@@ -1459,21 +1996,34 @@ impl Gui {
         }
     }
 }
+impl Drop for Gui {
+    fn drop(&mut self) {
+        if self.line_height != self.line_height_saved {
+            save_config_value(
+                "line_height",
+                toml::Value::Float(self.line_height.round() as f64),
+            );
+        }
+    }
+}
 impl WindowHandler<GuiEvent> for Gui {
     fn on_draw(&mut self, helper: &mut WindowHelper<GuiEvent>, graphics: &mut Graphics2D) {
         let draw_start_time = Instant::now();
+        let mut cfg = self.gui_config.take().unwrap();
         graphics.draw_rectangle(
             Rectangle::new(Vec2::ZERO, self.size.into_f32()),
-            Color::BLACK,
+            cfg.theme.background,
         );
-        let mut cfg = self.gui_config.take().unwrap();
         // before the db is locked!
         #[cfg(feature = "merscfg")]
         MersCfg::run(&mut cfg, self, |m| &m.func_before_draw);
         let dblock = Arc::clone(&self.database);
         let mut dblock = dblock.lock().unwrap();
         let mut covers = self.covers.take().unwrap();
+        let mut thumbnails = self.thumbnails.take().unwrap();
         let mut custom_images = self.custom_images.take().unwrap();
+        let custom_files = Arc::clone(&self.custom_files);
+        let custom_files = custom_files.lock().unwrap();
         let mut info = DrawInfo {
             time: draw_start_time,
             actions: Vec::with_capacity(0),
@@ -1484,7 +2034,9 @@ impl WindowHandler<GuiEvent> for Gui {
             mouse_pos_in_bounds: false,
             get_con: Arc::clone(&self.get_con),
             covers: &mut covers,
+            thumbnails: &mut thumbnails,
             custom_images: &mut custom_images,
+            custom_files: &custom_files,
             helper: Some(helper),
             has_keyboard_focus: false,
             child_has_keyboard_focus: true,
@@ -1492,10 +2044,20 @@ impl WindowHandler<GuiEvent> for Gui {
             high_performance: self.high_performance,
             dragging: self.dragging.take(),
             gui_config: &mut cfg,
+            tooltip: None,
         };
         self.gui._draw(&mut info, graphics);
         let actions = std::mem::replace(&mut info.actions, Vec::with_capacity(0));
         self.dragging = info.dragging.take();
+        if let (Some(tooltip), Some(rest), None) = (&info.tooltip, self.mouse_rest, &self.dragging)
+        {
+            if draw_start_time.saturating_duration_since(rest) >= TOOLTIP_DELAY {
+                draw_tooltip(&info, tooltip, graphics);
+            } else if let Some(helper) = &mut info.helper {
+                // keep drawing until the tooltip appears
+                helper.request_redraw();
+            }
+        }
         if let Some((d, f)) = &mut self.dragging {
             if let Some(f) = f {
                 f(&mut info, graphics);
@@ -1532,12 +2094,34 @@ impl WindowHandler<GuiEvent> for Gui {
         // cleanup
         drop(info);
         self.gui_config = Some(cfg);
+        covers.cleanup(
+            draw_start_time,
+            crate::gui_playback::current_songs_cover(&dblock),
+        );
         self.covers = Some(covers);
+        thumbnails.cleanup();
+        thumbnails.load_queued(&self.get_con);
+        self.thumbnails = Some(thumbnails);
         self.custom_images = Some(custom_images);
         drop(dblock);
         for a in actions {
             self.exec_gui_action(a);
         }
+        let covers = self.covers.as_mut().unwrap();
+        if let Some(check) = &self.cover_version_check {
+            match check.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => self.cover_version_check = None,
+                Ok(versions) => {
+                    covers.reload_changed(versions);
+                    self.cover_version_check = None;
+                }
+            }
+        } else if self.last_cover_version_check.elapsed() >= COVER_VERSION_CHECK_INTERVAL {
+            self.last_cover_version_check = Instant::now();
+            self.cover_version_check = covers.check_versions(&self.get_con);
+        }
+        covers.load_queued(&self.get_con);
         let ft = draw_start_time.elapsed().as_millis() as u32;
         self.average_frame_time_ms = (self.average_frame_time_ms * 7 + ft) / 8;
         if !self.high_performance && self.average_frame_time_ms > 50 {
@@ -1563,15 +2147,17 @@ impl WindowHandler<GuiEvent> for Gui {
                 >= 1
             {
                 self.last_performance_check = draw_start_time;
-                eprintln!(
-                    "[performance] {} fps | {}ms",
-                    self.frames_drawn, self.average_frame_time_ms
+                musicdb_lib::log_debug!(
+                    "{} fps | {}ms",
+                    self.frames_drawn,
+                    self.average_frame_time_ms
                 );
                 self.frames_drawn = 0;
             }
         }
     }
     fn on_mouse_button_down(&mut self, helper: &mut WindowHelper<GuiEvent>, button: MouseButton) {
+        self.mouse_rest = None;
         if let Some(a) =
             self.gui
                 ._mouse_button(&mut EventInfo::new(), button, true, self.mouse_pos.clone())
@@ -1583,6 +2169,9 @@ impl WindowHandler<GuiEvent> for Gui {
         helper.request_redraw();
     }
     fn on_mouse_button_up(&mut self, helper: &mut WindowHelper<GuiEvent>, button: MouseButton) {
+        self.mouse_rest = None;
+        // clicking closes the context menu, unless the click (or dropping something) opened a new one
+        let context_menus_opened = self.context_menus_opened;
         if self.dragging.is_some() {
             let (dr, _) = self.dragging.take().unwrap();
             let mut opt = Some(dr);
@@ -1600,10 +2189,17 @@ impl WindowHandler<GuiEvent> for Gui {
                     | Dragging::Album(_)
                     | Dragging::Song(_)
                     | Dragging::Queue(Ok(_))
-                    | Dragging::Queues(_) => (),
+                    | Dragging::Queues(Ok(_)) => (),
                     Dragging::Queue(Err(path)) => {
                         self.exec_gui_action(GuiAction::SendToServer(Action::QueueRemove(path)))
                     }
+                    Dragging::Queues(Err(mut paths)) => {
+                        // the last one first, so the other paths stay valid
+                        paths.sort();
+                        self.exec_gui_action(GuiAction::SendToServer(Action::Multiple(
+                            paths.into_iter().rev().map(Action::QueueRemove).collect(),
+                        )))
+                    }
                 }
             }
         }
@@ -1615,8 +2211,8 @@ impl WindowHandler<GuiEvent> for Gui {
                 self.exec_gui_action(a)
             }
         }
-        if button != MouseButton::Right {
-            self.gui.c_context_menu = None;
+        if self.context_menus_opened == context_menus_opened {
+            self.set_context_menu(None);
         }
         helper.request_redraw();
     }
@@ -1637,6 +2233,8 @@ impl WindowHandler<GuiEvent> for Gui {
                     * self.last_height
             }
         };
+        // whatever is under the mouse now should get its own delay
+        self.mouse_rest = Some(Instant::now());
         if let Some(a) = self
             .gui
             ._mouse_wheel(&mut EventInfo::new(), dist, self.mouse_pos.clone())
@@ -1732,8 +2330,11 @@ impl WindowHandler<GuiEvent> for Gui {
                 }
             }
         }
+        let context_menus_opened = self.context_menus_opened;
+        // non-priority keybinds only run if no element used the key
+        let mut e_elems = EventInfo::new();
         for a in self.gui._keyboard_event(
-            &mut EventInfo::new(),
+            &mut e_elems,
             true,
             &mut |g, e, a| {
                 if g.config().keyboard_events_focus {
@@ -1760,8 +2361,18 @@ impl WindowHandler<GuiEvent> for Gui {
         ) {
             self.exec_gui_action(a);
         }
+        // activating an entry of the context menu closes it, unless the entry opened a new one
+        if matches!(
+            virtual_key_code,
+            Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter)
+        ) && !e_elems.can_take()
+            && self.context_menus_opened == context_menus_opened
+            && self.gui.c_context_menu.is_some()
+        {
+            self.set_context_menu(None);
+        }
         if let Some(post_action) = post_action.take() {
-            if e.take() {
+            if e_elems.can_take() && e.take() {
                 for a in self.key_actions.get(&post_action).execute() {
                     self.exec_gui_action(a);
                 }
@@ -1794,6 +2405,7 @@ impl WindowHandler<GuiEvent> for Gui {
                     eprintln!("WARN: Skipping call to merscfg's library_updated because gui_config is not available");
                 }
                 self.gui._recursive_all(true, &mut |e| e.updated_library());
+                self.update_window_title(helper);
                 helper.request_redraw();
             }
             GuiEvent::UpdatedQueue => {
@@ -1805,13 +2417,47 @@ impl WindowHandler<GuiEvent> for Gui {
                     eprintln!("WARN: Skipping call to merscfg's queue_updated because gui_config is not available");
                 }
                 self.gui._recursive_all(true, &mut |e| e.updated_queue());
+                self.check_song_changed();
+                self.update_window_title(helper);
+                helper.request_redraw();
+            }
+            GuiEvent::UpdatedPlayback => self.update_window_title(helper),
+            GuiEvent::Disconnected => {
+                self.show_reconnecting("Lost the connection to the server\nreconnecting...");
+                helper.request_redraw();
+            }
+            GuiEvent::Reconnected => {
+                if let Some(done) = self.reconnecting.take() {
+                    done.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                self.exec_gui_action(text_notif("Reconnected to the server".to_owned()));
                 helper.request_redraw();
             }
+            #[cfg(feature = "media-keys")]
+            GuiEvent::MediaKey(key) => {
+                use crate::media_keys::MediaKey;
+                self.exec_gui_action(GuiAction::Build(Box::new(move |db| {
+                    vec![GuiAction::SendToServer(match key {
+                        MediaKey::Play => Action::Resume,
+                        MediaKey::Pause => Action::Pause,
+                        MediaKey::Toggle if db.playing => Action::Pause,
+                        MediaKey::Toggle => Action::Resume,
+                        MediaKey::Next => db.next_song_action(),
+                        // there is no previous song in the queue, so restart the current one
+                        MediaKey::Previous => Action::Seek(0),
+                        MediaKey::Stop => Action::Stop,
+                        MediaKey::SeekBy(by) => Action::SeekBy(by),
+                        MediaKey::SetPosition(pos) => Action::Seek(pos),
+                        MediaKey::SetVolume(volume) => Action::SetVolume(volume),
+                    })]
+                })));
+            }
             GuiEvent::Exit => helper.terminate_loop(),
         }
     }
     fn on_mouse_move(&mut self, helper: &mut WindowHelper<GuiEvent>, position: Vec2) {
         self.mouse_pos = position;
+        self.mouse_rest = Some(Instant::now());
         helper.request_redraw();
     }
     fn on_resize(&mut self, _helper: &mut WindowHelper<GuiEvent>, size_pixels: UVec2) {
@@ -1822,42 +2468,58 @@ impl WindowHandler<GuiEvent> for Gui {
 }
 
 pub enum GuiServerImage {
-    Loading(JoinHandle<Option<Vec<u8>>>),
-    Loaded(ImageHandle),
+    /// will be requested with the other queued covers after this frame, see `Covers::load_queued`
+    Queued,
+    Loading(mpsc::Receiver<Option<Vec<u8>>>),
+    /// the image and the version of its bytes, see `musicdb_lib::data::database::cover_version`
+    Loaded(ImageHandle, u64),
     Error,
 }
 #[allow(unused)]
 impl GuiServerImage {
+    /// if `max_px` is `Some`, a thumbnail is requested instead of the full-size cover, see `get::Client::cover_thumb`
     pub fn new_cover<T: ClientIo + 'static>(
         id: CoverId,
-        get_con: Arc<Mutex<get::Client<T>>>,
+        max_px: Option<u32>,
+        get_con: Arc<get::Pool<T>>,
     ) -> Self {
-        Self::Loading(std::thread::spawn(move || {
+        Self::load_in_thread(move || {
             get_con
-                .lock()
-                .unwrap()
-                .cover_bytes(id)
+                .with_reconnect(|get_con| {
+                    if let Some(max_px) = max_px {
+                        get_con.cover_thumb(id, max_px)
+                    } else {
+                        get_con.cover_bytes(id)
+                    }
+                })
                 .ok()
                 .and_then(|v| v.ok())
-        }))
+        })
+    }
+    /// like `new_cover`, but the cover is requested together with all other covers queued during the same frame
+    pub fn new_cover_queued() -> Self {
+        Self::Queued
     }
     pub fn new_custom_file<T: ClientIo + 'static>(
         file: String,
-        get_con: Arc<Mutex<get::Client<T>>>,
+        get_con: Arc<get::Pool<T>>,
     ) -> Self {
-        Self::Loading(std::thread::spawn(move || {
+        Self::load_in_thread(move || {
             get_con
-                .lock()
-                .unwrap()
-                .custom_file(&file)
+                .with_reconnect(|get_con| get_con.custom_file(&file))
                 .ok()
                 .and_then(|v| v.ok())
-        }))
+        })
+    }
+    fn load_in_thread(load: impl FnOnce() -> Option<Vec<u8>> + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || _ = sender.send(load()));
+        Self::Loading(receiver)
     }
     pub fn get(&self) -> Option<ImageHandle> {
         match self {
-            Self::Loaded(handle) => Some(handle.clone()),
-            Self::Loading(_) | Self::Error => None,
+            Self::Loaded(handle, _) => Some(handle.clone()),
+            Self::Queued | Self::Loading(_) | Self::Error => None,
         }
     }
     pub fn is_err(&self) -> bool {
@@ -1865,40 +2527,340 @@ impl GuiServerImage {
     }
     pub fn get_init(&mut self, g: &mut Graphics2D) -> Option<ImageHandle> {
         match self {
-            Self::Loaded(handle) => Some(handle.clone()),
+            Self::Loaded(handle, _) => Some(handle.clone()),
             Self::Error => None,
-            Self::Loading(t) => {
-                if t.is_finished() {
-                    let s = std::mem::replace(self, Self::Error);
-                    if let Self::Loading(t) = s {
-                        match t.join().unwrap() {
-                            Some(bytes) => match g.create_image_from_file_bytes(
-                                None,
-                                speedy2d::image::ImageSmoothingMode::Linear,
-                                Cursor::new(bytes),
-                            ) {
-                                Ok(handle) => {
-                                    *self = Self::Loaded(handle.clone());
-                                    Some(handle)
-                                }
-                                Err(e) => {
-                                    eprintln!("[info] couldn't load cover from bytes: {e}");
-                                    None
-                                }
-                            },
-                            None => None,
-                        }
-                    } else {
-                        *self = s;
+            Self::Queued => None,
+            Self::Loading(receiver) => match receiver.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => None,
+                Err(mpsc::TryRecvError::Disconnected) | Ok(None) => {
+                    *self = Self::Error;
+                    None
+                }
+                Ok(Some(bytes)) => match g.create_image_from_file_bytes(
+                    None,
+                    speedy2d::image::ImageSmoothingMode::Linear,
+                    Cursor::new(&bytes),
+                ) {
+                    Ok(handle) => {
+                        *self = Self::Loaded(handle.clone(), cover_version(&bytes));
+                        Some(handle)
+                    }
+                    Err(e) => {
+                        eprintln!("[info] couldn't load cover from bytes: {e}");
+                        *self = Self::Error;
                         None
                     }
-                } else {
-                    None
+                },
+            },
+        }
+    }
+}
+
+/// an action sent by this client longer ago than this didn't cause the next song change
+const LOCAL_SONG_CHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+/// true if the action (probably) makes another song the current one
+fn changes_song(action: &Action) -> bool {
+    match action {
+        Action::NextSong | Action::NextSongIfCurrent(..) | Action::QueueGoto(..) => true,
+        Action::Multiple(actions) => actions.iter().any(changes_song),
+        _ => false,
+    }
+}
+
+/// the window's title when no song is playing
+pub const WINDOW_TITLE: &str = "MusicDB Client";
+/// longer song titles and artist names are shortened in the window's title
+const WINDOW_TITLE_MAX_CHARS: usize = 100;
+
+/// "Artist – Title — MusicDB" while a song is playing, `WINDOW_TITLE` otherwise.
+fn window_title(db: &Database) -> String {
+    let song = db
+        .playing
+        .then(|| db.queue.get_current_song(db))
+        .flatten()
+        .and_then(|id| db.get_song(id));
+    let Some(song) = song else {
+        return WINDOW_TITLE.to_owned();
+    };
+    let artist = db.artists().get(&song.artist).map(|a| a.name.as_str());
+    window_title_for(&song.title, artist)
+}
+fn window_title_for(title: &str, artist: Option<&str>) -> String {
+    let song = match artist {
+        Some(artist) if !artist.is_empty() => format!("{artist} – {title}"),
+        _ => title.to_owned(),
+    };
+    let song = if song.chars().count() > WINDOW_TITLE_MAX_CHARS {
+        let mut short = song
+            .chars()
+            .take(WINDOW_TITLE_MAX_CHARS - 1)
+            .collect::<String>();
+        short.push('…');
+        short
+    } else {
+        song
+    };
+    format!("{song} — MusicDB")
+}
+
+/// the default for `line_height` in config_gui.toml, which is a scale of 100%
+pub const LINE_HEIGHT_DEFAULT: f32 = 32.0;
+pub const LINE_HEIGHT_MIN: f32 = 16.0;
+pub const LINE_HEIGHT_MAX: f32 = 80.0;
+/// how much the scale keys change the line height
+pub const LINE_HEIGHT_STEP: f32 = 4.0;
+
+/// the default for `cover_cache_mib` in config_gui.toml
+pub const COVER_CACHE_DEFAULT_MIB: u64 = 512;
+/// The full-size covers. Once the loaded covers use more than `budget` bytes,
+/// the ones which weren't drawn for the longest time are dropped, they are requested again if they are needed again.
+pub struct Covers {
+    images: HashMap<CoverId, (GuiServerImage, Instant)>,
+    /// in bytes, see `cleanup`
+    pub budget: u64,
+}
+impl Covers {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            images: HashMap::new(),
+            budget,
+        }
+    }
+    /// the cover, which is queued if it wasn't requested yet
+    pub fn get(&mut self, id: CoverId, time: Instant) -> &mut GuiServerImage {
+        let (image, last_used) = self
+            .images
+            .entry(id)
+            .or_insert_with(|| (GuiServerImage::new_cover_queued(), time));
+        *last_used = time;
+        image
+    }
+    /// requests the cover again, even if it is loaded already
+    pub fn reload(&mut self, id: CoverId) {
+        let (image, _) = self
+            .images
+            .entry(id)
+            .or_insert_with(|| (GuiServerImage::new_cover_queued(), Instant::now()));
+        *image = GuiServerImage::new_cover_queued();
+    }
+    /// how many covers are loaded, and roughly how many bytes they use
+    pub fn size(&self) -> (usize, u64) {
+        self.images
+            .values()
+            .filter_map(|(image, _)| match image {
+                GuiServerImage::Loaded(handle, _) => Some(image_bytes(handle)),
+                _ => None,
+            })
+            .fold((0, 0), |(count, bytes), b| (count + 1, bytes + b))
+    }
+    /// Drops the least recently used loaded covers until they fit into the `budget`.
+    /// Covers which were used during the frame drawn at `now` and the `keep` cover (the current song's) are never dropped.
+    fn cleanup(&mut self, now: Instant, keep: Option<CoverId>) {
+        let (_, bytes) = self.size();
+        if bytes <= self.budget {
+            return;
+        }
+        let unused = self
+            .images
+            .iter()
+            .filter_map(|(id, (image, last_used))| match image {
+                GuiServerImage::Loaded(handle, _) if *last_used < now && keep != Some(*id) => {
+                    Some((*id, *last_used, image_bytes(handle)))
+                }
+                _ => None,
+            })
+            .collect();
+        for id in least_recently_used(unused, bytes, self.budget) {
+            self.images.remove(&id);
+        }
+    }
+    /// requests all `Queued` covers using one `covers-bytes` request (per `COVERS_BYTES_MAX_IDS` covers)
+    pub fn load_queued<T: ClientIo + 'static>(&mut self, get_con: &Arc<get::Pool<T>>) {
+        let mut queued = self
+            .images
+            .iter_mut()
+            .filter(|(_, (cover, _))| matches!(cover, GuiServerImage::Queued))
+            .map(|(id, (cover, _))| {
+                let (sender, receiver) = mpsc::channel();
+                *cover = GuiServerImage::Loading(receiver);
+                (*id, sender)
+            })
+            .collect::<Vec<_>>();
+        while !queued.is_empty() {
+            let batch = queued
+                .drain(..queued.len().min(get::COVERS_BYTES_MAX_IDS))
+                .collect::<Vec<_>>();
+            let get_con = Arc::clone(get_con);
+            std::thread::spawn(move || {
+                let ids = batch.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+                let mut covers = match get_con.with_reconnect(|get_con| get_con.covers_bytes(&ids))
+                {
+                    Ok(Ok(covers)) => covers.into_iter().collect::<HashMap<_, _>>(),
+                    Ok(Err(e)) => {
+                        musicdb_lib::log_warn!("couldn't load covers: {}", e.trim());
+                        HashMap::new()
+                    }
+                    Err(e) => {
+                        musicdb_lib::log_warn!("couldn't load covers: {e}");
+                        HashMap::new()
+                    }
+                };
+                for (id, sender) in batch {
+                    _ = sender.send(covers.remove(&id).and_then(Result::ok));
+                }
+            });
+        }
+    }
+    /// requests the versions of all loaded covers in the background, see `reload_changed`
+    pub fn check_versions<T: ClientIo + 'static>(
+        &self,
+        get_con: &Arc<get::Pool<T>>,
+    ) -> Option<mpsc::Receiver<Vec<(CoverId, u64)>>> {
+        let ids = self
+            .images
+            .iter()
+            .filter(|(_, (cover, _))| matches!(cover, GuiServerImage::Loaded(..)))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return None;
+        }
+        let get_con = Arc::clone(get_con);
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut versions = vec![];
+            for ids in ids.chunks(get::COVERS_BYTES_MAX_IDS) {
+                match get_con.with_reconnect(|get_con| get_con.cover_versions(ids)) {
+                    Ok(Ok(v)) => versions.extend(v),
+                    Ok(Err(e)) => {
+                        musicdb_lib::log_debug!("couldn't check cover versions: {}", e.trim());
+                        return;
+                    }
+                    Err(e) => {
+                        musicdb_lib::log_warn!("couldn't check cover versions: {e}");
+                        return;
+                    }
+                }
+            }
+            _ = sender.send(versions);
+        });
+        Some(receiver)
+    }
+    /// queues all loaded covers whose version on the server differs from the loaded version
+    pub fn reload_changed(&mut self, versions: Vec<(CoverId, u64)>) {
+        for (id, version) in versions {
+            if let Some((cover @ GuiServerImage::Loaded(..), _)) = self.images.get_mut(&id) {
+                if !matches!(cover, GuiServerImage::Loaded(_, v) if *v == version) {
+                    *cover = GuiServerImage::Queued;
                 }
             }
         }
     }
 }
+/// which of the `(id, last used, bytes)` covers have to be dropped to get from `bytes` to at most `budget` bytes,
+/// starting with the one which wasn't used for the longest time
+fn least_recently_used(
+    mut covers: Vec<(CoverId, Instant, u64)>,
+    mut bytes: u64,
+    budget: u64,
+) -> Vec<CoverId> {
+    covers.sort_unstable_by_key(|(_, last_used, _)| *last_used);
+    let mut drop = vec![];
+    for (id, _, size) in covers {
+        if bytes <= budget {
+            break;
+        }
+        drop.push(id);
+        bytes -= size;
+    }
+    drop
+}
+/// roughly how much memory a loaded image uses
+fn image_bytes(handle: &ImageHandle) -> u64 {
+    let size = handle.size();
+    size.x as u64 * size.y as u64 * 4
+}
+
+/// the size of the covers in the library's album grid
+pub const THUMBNAIL_PX: u32 = 256;
+/// how many thumbnails are kept, the ones which weren't drawn for the longest time are dropped first
+const THUMBNAILS_MAX: usize = 512;
+/// Scaled-down covers (see `get::Client::cover_thumb`), kept separate from `Gui::covers`
+/// so that the full-size covers aren't replaced by thumbnails.
+#[derive(Default)]
+pub struct Thumbnails(HashMap<CoverId, (GuiServerImage, Instant)>);
+impl Thumbnails {
+    /// the thumbnail for this cover, which is queued if it wasn't requested yet
+    pub fn get(&mut self, id: CoverId, time: Instant) -> &mut GuiServerImage {
+        let (image, last_used) = self
+            .0
+            .entry(id)
+            .or_insert_with(|| (GuiServerImage::new_cover_queued(), time));
+        *last_used = time;
+        image
+    }
+    /// requests all queued thumbnails, one after another, in a background thread
+    fn load_queued<T: ClientIo + 'static>(&mut self, get_con: &Arc<get::Pool<T>>) {
+        let queued = self
+            .0
+            .iter_mut()
+            .filter(|(_, (image, _))| matches!(image, GuiServerImage::Queued))
+            .map(|(id, (image, _))| {
+                let (sender, receiver) = mpsc::channel();
+                *image = GuiServerImage::Loading(receiver);
+                (*id, sender)
+            })
+            .collect::<Vec<_>>();
+        if queued.is_empty() {
+            return;
+        }
+        let get_con = Arc::clone(get_con);
+        std::thread::spawn(move || {
+            for (id, sender) in queued {
+                let bytes = get_con
+                    .with_reconnect(|get_con| get_con.cover_thumb(id, THUMBNAIL_PX))
+                    .ok()
+                    .and_then(|v| v.ok());
+                _ = sender.send(bytes);
+            }
+        });
+    }
+    /// drops the least recently used thumbnails if there are more than `THUMBNAILS_MAX`
+    fn cleanup(&mut self) {
+        if self.0.len() > THUMBNAILS_MAX {
+            let mut last_used = self.0.values().map(|(_, t)| *t).collect::<Vec<_>>();
+            last_used.sort_unstable();
+            let min = last_used[self.0.len() - THUMBNAILS_MAX];
+            self.0.retain(|_, (_, t)| *t >= min);
+        }
+    }
+}
+/// draws the cover's thumbnail into the square `area`, keeping its aspect ratio
+pub fn draw_thumbnail(info: &mut DrawInfo, g: &mut Graphics2D, cover: CoverId, area: &Rectangle) {
+    let size = area.width();
+    let thumbnail = info.thumbnails.get(cover, info.time);
+    if let Some(img) = thumbnail.get_init(g) {
+        let img_size = img.size();
+        let scale = size / img_size.x.max(img_size.y).max(1) as f32;
+        let (w, h) = (img_size.x as f32 * scale, img_size.y as f32 * scale);
+        let center = Vec2::new(
+            area.top_left().x + size / 2.0,
+            area.top_left().y + size / 2.0,
+        );
+        g.draw_rectangle_image(
+            Rectangle::from_tuples(
+                (center.x - w / 2.0, center.y - h / 2.0),
+                (center.x + w / 2.0, center.y + h / 2.0),
+            ),
+            &img,
+        );
+    } else if !thumbnail.is_err() {
+        // still loading
+        if let Some(h) = &info.helper {
+            h.request_redraw();
+        }
+    }
+}
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct KeyBinding {
@@ -1919,6 +2881,15 @@ impl KeyBinding {
             key,
         }
     }
+    pub fn key(key: VirtualKeyCode) -> Self {
+        Self { modifiers: 0, key }
+    }
+    pub fn shift(key: VirtualKeyCode) -> Self {
+        Self {
+            modifiers: Self::SHIFT,
+            key,
+        }
+    }
     pub fn ctrl(key: VirtualKeyCode) -> Self {
         Self {
             modifiers: Self::CTRL,
@@ -1944,7 +2915,215 @@ impl KeyBinding {
         self.modifiers & Self::META != 0
     }
 }
+/// like `Ctrl+Shift+Right`
+impl std::fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}{}{:?}",
+            if self.get_ctrl() { "Ctrl+" } else { "" },
+            if self.get_alt() { "Alt+" } else { "" },
+            if self.get_shift() { "Shift+" } else { "" },
+            if self.get_meta() { "Meta+" } else { "" },
+            self.key,
+        )
+    }
+}
+impl std::str::FromStr for KeyBinding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts.pop().unwrap_or_default();
+        let mut modifiers = 0;
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "ctrl" | "control" => Self::CTRL,
+                "alt" => Self::ALT,
+                "shift" => Self::SHIFT,
+                "meta" | "super" | "logo" | "win" => Self::META,
+                _ => {
+                    return Err(format!(
+                        "unknown modifier `{part}` in `{s}`, expected Ctrl, Alt, Shift or Meta"
+                    ))
+                }
+            };
+        }
+        let key = match key.as_bytes() {
+            [c @ b'0'..=b'9'] => KEY_CODES[(c - b'0') as usize + 26],
+            _ => *KEY_CODES
+                .iter()
+                .find(|code| format!("{code:?}").eq_ignore_ascii_case(key))
+                .ok_or_else(|| {
+                    format!("unknown key `{key}` in `{s}`, keys are named like `Space`, `N`, `5`, `F5`, `Left` or `NumpadAdd`")
+                })?,
+        };
+        Ok(Self { modifiers, key })
+    }
+}
+/// the keys which can be used in config_gui.toml. letters and digits first, see `KeyBinding::from_str`.
+const KEY_CODES: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+    &[
+        A,
+        B,
+        C,
+        D,
+        E,
+        F,
+        G,
+        H,
+        I,
+        J,
+        K,
+        L,
+        M,
+        N,
+        O,
+        P,
+        Q,
+        R,
+        S,
+        T,
+        U,
+        V,
+        W,
+        X,
+        Y,
+        Z,
+        Key0,
+        Key1,
+        Key2,
+        Key3,
+        Key4,
+        Key5,
+        Key6,
+        Key7,
+        Key8,
+        Key9,
+        Escape,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+        F13,
+        F14,
+        F15,
+        F16,
+        F17,
+        F18,
+        F19,
+        F20,
+        F21,
+        F22,
+        F23,
+        F24,
+        PrintScreen,
+        ScrollLock,
+        PauseBreak,
+        Insert,
+        Home,
+        Delete,
+        End,
+        PageDown,
+        PageUp,
+        Left,
+        Up,
+        Right,
+        Down,
+        Backspace,
+        Return,
+        Space,
+        Compose,
+        Caret,
+        Numlock,
+        Numpad0,
+        Numpad1,
+        Numpad2,
+        Numpad3,
+        Numpad4,
+        Numpad5,
+        Numpad6,
+        Numpad7,
+        Numpad8,
+        Numpad9,
+        NumpadAdd,
+        NumpadDivide,
+        NumpadDecimal,
+        NumpadComma,
+        NumpadEnter,
+        NumpadEquals,
+        NumpadMultiply,
+        NumpadSubtract,
+        AbntC1,
+        AbntC2,
+        Apostrophe,
+        Apps,
+        Asterisk,
+        At,
+        Ax,
+        Backslash,
+        Calculator,
+        Capital,
+        Colon,
+        Comma,
+        Convert,
+        Equals,
+        Grave,
+        Kana,
+        Kanji,
+        LBracket,
+        Mail,
+        MediaSelect,
+        MediaStop,
+        Minus,
+        Mute,
+        MyComputer,
+        NavigateForward,
+        NavigateBackward,
+        NextTrack,
+        NoConvert,
+        OEM102,
+        Period,
+        PlayPause,
+        Plus,
+        Power,
+        PrevTrack,
+        RBracket,
+        Semicolon,
+        Slash,
+        Sleep,
+        Stop,
+        Sysrq,
+        Tab,
+        Underline,
+        Unlabeled,
+        VolumeDown,
+        VolumeUp,
+        Wake,
+        WebBack,
+        WebFavorites,
+        WebForward,
+        WebHome,
+        WebRefresh,
+        WebSearch,
+        WebStop,
+        Yen,
+        Copy,
+        Paste,
+        Cut,
+    ]
+};
 pub struct KeyAction {
+    /// the name used in the `[keys]` section of config_gui.toml, see `gui_screen::KEY_ACTION_NAMES`
+    pub name: &'static str,
     pub category: String,
     pub title: String,
     pub description: String,
@@ -2030,3 +3209,128 @@ pub fn rect_from_rel(v: &Rectangle, outer: &Rectangle) -> Rectangle {
         ),
     )
 }
+/// `[left, top, right, bottom]`, relative like the `pos` in a `GuiElemCfg`
+fn rect_from_toml(v: &toml::Value) -> Option<Rectangle> {
+    let v = v
+        .as_array()?
+        .iter()
+        .map(|v| Some(v.as_float().or_else(|| Some(v.as_integer()? as f64))? as f32))
+        .collect::<Option<Vec<_>>>()?;
+    match v[..] {
+        [left, top, right, bottom] => Some(Rectangle::from_tuples((left, top), (right, bottom))),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_set_config_value() {
+    let v = toml::Value::String("name".to_owned());
+    assert_eq!(
+        set_config_value(
+            "# comment\nline_height = 32.0\n\n[text]\na = 1\n",
+            "library_sort",
+            &v
+        ),
+        "# comment\nline_height = 32.0\n\nlibrary_sort = \"name\"\n\n[text]\na = 1\n"
+    );
+    assert_eq!(
+        set_config_value(
+            "library_sort = \"year\" # x\n[text]\nlibrary_sort = 1\n",
+            "library_sort",
+            &v
+        ),
+        "library_sort = \"name\"\n[text]\nlibrary_sort = 1\n"
+    );
+    assert_eq!(
+        set_config_value("a = 1", "library_sort", &v),
+        "a = 1\nlibrary_sort = \"name\"\n"
+    );
+}
+
+#[test]
+fn test_changes_song() {
+    assert!(changes_song(&Action::QueueGoto(vec![0, 1])));
+    assert!(changes_song(&Action::Multiple(vec![
+        Action::QueueClear(vec![]),
+        Action::NextSong,
+    ])));
+    assert!(!changes_song(&Action::Pause));
+}
+
+#[test]
+fn test_window_title() {
+    assert_eq!(
+        window_title_for("Song", Some("Artist")),
+        "Artist – Song — MusicDB"
+    );
+    assert_eq!(window_title_for("Song", Some("")), "Song — MusicDB");
+    let long = window_title_for(&"a".repeat(500), None);
+    assert_eq!(
+        long.chars().count(),
+        WINDOW_TITLE_MAX_CHARS + " — MusicDB".chars().count()
+    );
+    assert!(long.ends_with("a… — MusicDB"));
+}
+
+#[test]
+fn test_key_binding_from_str() {
+    let bind = "Ctrl+Shift+Right".parse::<KeyBinding>().unwrap();
+    assert!(bind.get_ctrl() && bind.get_shift() && !bind.get_alt());
+    assert_eq!(bind.key, VirtualKeyCode::Right);
+    assert_eq!(bind.to_string(), "Ctrl+Shift+Right");
+    assert_eq!(
+        "space".parse::<KeyBinding>().unwrap().key,
+        VirtualKeyCode::Space
+    );
+    assert_eq!("n".parse::<KeyBinding>().unwrap().key, VirtualKeyCode::N);
+    assert_eq!("5".parse::<KeyBinding>().unwrap().key, VirtualKeyCode::Key5);
+    assert_eq!(
+        "Key5".parse::<KeyBinding>().unwrap().key,
+        VirtualKeyCode::Key5
+    );
+    assert_eq!(
+        "NumpadAdd".parse::<KeyBinding>().unwrap().to_string(),
+        "NumpadAdd"
+    );
+    assert!("Ctrl+Nope".parse::<KeyBinding>().is_err());
+    assert!("Hyper+N".parse::<KeyBinding>().is_err());
+    assert!("".parse::<KeyBinding>().is_err());
+}
+
+#[test]
+fn test_thumbnails_cleanup() {
+    let start = Instant::now();
+    let mut thumbnails = Thumbnails::default();
+    for id in 0..THUMBNAILS_MAX as CoverId + 10 {
+        thumbnails.get(id, start + Duration::from_millis(id as u64));
+    }
+    thumbnails.cleanup();
+    assert_eq!(thumbnails.0.len(), THUMBNAILS_MAX);
+    // the least recently used ones were removed
+    assert!(!thumbnails.0.contains_key(&9));
+    assert!(thumbnails.0.contains_key(&10));
+}
+
+#[test]
+fn test_covers_least_recently_used() {
+    let start = Instant::now();
+    let covers = (0..5)
+        .map(|id| (id, start + Duration::from_millis(id as u64), 100))
+        .collect::<Vec<_>>();
+    assert!(least_recently_used(covers.clone(), 500, 500).is_empty());
+    assert_eq!(least_recently_used(covers.clone(), 500, 250), vec![0, 1, 2]);
+    // covers which are in use aren't passed in, so the budget may not be reached
+    assert_eq!(least_recently_used(covers, 800, 0).len(), 5);
+}
+
+#[test]
+fn test_rect_from_toml() {
+    let rect = |s: &str| rect_from_toml(&s.parse::<toml::Table>().unwrap()["v"]);
+    assert_eq!(
+        rect("v = [0.5, 0, 1, 0.25]"),
+        Some(Rectangle::from_tuples((0.5, 0.0), (1.0, 0.25)))
+    );
+    assert_eq!(rect("v = [0.5, 0, 1]"), None);
+    assert_eq!(rect("v = [0.5, 0, 1, \"x\"]"), None);
+    assert_eq!(rect("v = 1.0"), None);
+}
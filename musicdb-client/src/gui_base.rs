@@ -1,10 +1,16 @@
 use std::{sync::Arc, time::Instant};
 
-use speedy2d::{color::Color, dimen::Vec2, shape::Rectangle, window::MouseButton};
+use speedy2d::{
+    color::Color,
+    dimen::Vec2,
+    shape::Rectangle,
+    window::{MouseButton, VirtualKeyCode},
+};
 
 use crate::{
-    gui::{DrawInfo, EventInfo, GuiAction, GuiElem, GuiElemCfg, GuiElemChildren},
+    gui::{DrawInfo, EventInfo, GuiAction, GuiElem, GuiElemCfg, GuiElemChildren, GuiElemInternal},
     gui_text::Label,
+    gui_theme::Theme,
 };
 
 /*
@@ -14,11 +20,25 @@ Mostly containers for other GuiElems.
 
 */
 
+/// outlines the element if it has the keyboard focus
+pub fn draw_keyboard_focus(info: &DrawInfo, g: &mut speedy2d::Graphics2D) {
+    if info.has_keyboard_focus {
+        let p = &info.pos;
+        let c = info.gui_config.theme.focus;
+        g.draw_line(p.top_left(), p.top_right(), 2.0, c);
+        g.draw_line(p.bottom_left(), p.bottom_right(), 2.0, c);
+        g.draw_line(p.top_left(), p.bottom_left(), 2.0, c);
+        g.draw_line(p.top_right(), p.bottom_right(), 2.0, c);
+    }
+}
+
 /// A simple container for zero, one, or multiple child GuiElems. Can optionally fill the background with a color.
 pub struct Panel<C: GuiElemChildren> {
     config: GuiElemCfg,
     pub children: C,
     pub background: Option<Color>,
+    /// if set, used instead of `background`
+    pub theme_background: Option<fn(&Theme) -> Color>,
 }
 impl<C: GuiElemChildren> Panel<C> {
     pub fn new(config: GuiElemCfg, children: C) -> Self {
@@ -26,6 +46,7 @@ impl<C: GuiElemChildren> Panel<C> {
             config,
             children,
             background: None,
+            theme_background: None,
         }
     }
     pub fn with_background(config: GuiElemCfg, children: C, background: Color) -> Self {
@@ -33,6 +54,20 @@ impl<C: GuiElemChildren> Panel<C> {
             config,
             children,
             background: Some(background),
+            theme_background: None,
+        }
+    }
+    /// fills the background with one of the theme's colors, like `|t| t.notif_background`
+    pub fn with_theme_background(
+        config: GuiElemCfg,
+        children: C,
+        background: fn(&Theme) -> Color,
+    ) -> Self {
+        Self {
+            config,
+            children,
+            background: None,
+            theme_background: Some(background),
         }
     }
 }
@@ -59,8 +94,11 @@ impl<C: GuiElemChildren + 'static> GuiElem for Panel<C> {
         self
     }
     fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
-        if let Some(c) = self.background {
+        let theme = &info.gui_config.theme;
+        if let Some(c) = self.theme_background.map(|f| f(theme)) {
             g.draw_rectangle(info.pos.clone(), c);
+        } else if let Some(c) = self.background {
+            g.draw_rectangle(info.pos.clone(), theme.shade(c));
         }
     }
 }
@@ -132,9 +170,21 @@ pub struct ScrollBox<C: GuiElemChildren> {
     max_scroll: f32,
     last_height_px: f32,
     mouse_in_scrollbar: bool,
-    mouse_scrolling: bool,
+    /// while the scrollbar's thumb is dragged: where it was grabbed, in pixels from its top edge
+    mouse_scrolling: Option<f32>,
     mouse_scroll_margin_right: f32,
+    /// the mouse's y-position and the thumb's top and bottom edge, in pixels
+    scrollbar_mouse_y: f32,
+    scrollbar_thumb: (f32, f32),
+    /// when the scrollbar was last scrolled or hovered, it fades out after `SCROLLBAR_VISIBLE`
+    scrollbar_active: Option<Instant>,
+    /// the child whose keyboard focus was last scrolled into view
+    focus_scrolled_into_view: usize,
+    /// the child which should be scrolled into view on the next draw, see `scroll_into_view`
+    scroll_into_view: Option<usize>,
 }
+const SCROLLBAR_VISIBLE: f32 = 1.0;
+const SCROLLBAR_FADE: f32 = 0.3;
 #[derive(Clone)]
 #[allow(unused)]
 pub enum ScrollBoxSizeUnit {
@@ -150,7 +200,7 @@ impl<C: GuiElemChildren> ScrollBox<C> {
         default_size: f32,
     ) -> Self {
         Self {
-            config: config.w_scroll().w_mouse(),
+            config: config.w_scroll().w_mouse().w_keyboard_focus(),
             children,
             children_heights,
             default_size,
@@ -161,10 +211,20 @@ impl<C: GuiElemChildren> ScrollBox<C> {
             max_scroll: 0.0,
             last_height_px: 0.0,
             mouse_in_scrollbar: false,
-            mouse_scrolling: false,
+            mouse_scrolling: None,
             mouse_scroll_margin_right: 0.0,
+            scrollbar_mouse_y: 0.0,
+            scrollbar_thumb: (0.0, 0.0),
+            scrollbar_active: None,
+            focus_scrolled_into_view: usize::MAX,
+            scroll_into_view: None,
         }
     }
+    /// scrolls as little as possible so that the child at this index is visible, on the next draw.
+    /// `children_heights` must already contain the child.
+    pub fn scroll_into_view(&mut self, index: usize) {
+        self.scroll_into_view = Some(index);
+    }
 }
 impl<C: GuiElemChildren + 'static> GuiElem for ScrollBox<C> {
     fn config(&self) -> &GuiElemCfg {
@@ -195,6 +255,24 @@ impl<C: GuiElemChildren + 'static> GuiElem for ScrollBox<C> {
         if self.config.pixel_pos.size() != info.pos.size() {
             self.config.redraw = true;
         }
+        // show the focused child
+        let focus = self.config.keyboard_focus_index;
+        if focus != self.focus_scrolled_into_view {
+            self.focus_scrolled_into_view = focus;
+            self.scroll_into_view.get_or_insert(focus);
+        }
+        if let Some(index) = self.scroll_into_view.take() {
+            if index < self.children_heights.len() {
+                let top = self.children_heights[..index].iter().sum::<f32>();
+                let bottom = top + self.children_heights[index];
+                let height = self.size_unit.from_rel(1.0, info.pos.height());
+                if top < self.scroll_target {
+                    self.scroll_target = top;
+                } else if bottom > self.scroll_target + height {
+                    self.scroll_target = bottom - height;
+                }
+            }
+        }
         // smooth scrolling animation
         if self.scroll_target > self.max_scroll {
             self.scroll_target = self.max_scroll;
@@ -258,39 +336,129 @@ impl<C: GuiElemChildren + 'static> GuiElem for ScrollBox<C> {
                 0.0f32.max(self.height_bottom - self.size_unit.from_rel(0.75, info.pos.height()));
         }
         // scroll bar
-        self.mouse_in_scrollbar = info.mouse_pos.y >= info.pos.top_left().y
+        self.last_height_px = info.pos.height();
+        self.mouse_in_scrollbar = self.max_scroll > 0.0
+            && info.mouse_pos.y >= info.pos.top_left().y
             && info.mouse_pos.y <= info.pos.bottom_right().y
             && info.mouse_pos.x <= info.pos.bottom_right().x
             && info.mouse_pos.x >= (info.pos.bottom_right().x - self.mouse_scroll_margin_right);
-        if self.mouse_scrolling {
-            self.scroll_target = (self.max_scroll * (info.mouse_pos.y - info.pos.top_left().y)
-                / info.pos.height())
+        self.scrollbar_mouse_y = info.mouse_pos.y;
+        if self.max_scroll <= 0.0 {
+            return;
+        }
+        // the thumb is to the track what the visible part is to everything that can be scrolled to
+        let track_height = info.pos.height();
+        let total = self.max_scroll + self.size_unit.from_rel(1.0, track_height);
+        let thumb_height = (track_height * (1.0 - self.max_scroll / total))
+            .max(info.line_height * 0.5)
+            .min(track_height);
+        if let Some(grab) = self.mouse_scrolling {
+            let thumb_top = info.mouse_pos.y - grab - info.pos.top_left().y;
+            self.scroll_target = (self.max_scroll * thumb_top
+                / (track_height - thumb_height).max(1.0))
             .max(0.0)
             .min(self.max_scroll);
+            self.scroll_display = self.scroll_target;
         }
         if self.mouse_in_scrollbar
-            || self.mouse_scrolling
-            || (self.scroll_display - self.scroll_target).abs()
-                > self.size_unit.from_abs(1.0, info.pos.height())
+            || self.mouse_scrolling.is_some()
+            || self.scroll_display != self.scroll_target
         {
+            self.scrollbar_active = Some(info.time);
+        }
+        let inactive = self
+            .scrollbar_active
+            .map_or(f32::INFINITY, |t| (info.time - t).as_secs_f32());
+        let alpha = (1.0 - (inactive - SCROLLBAR_VISIBLE) / SCROLLBAR_FADE).clamp(0.0, 1.0);
+        if alpha > 0.0 {
+            if inactive > 0.0 {
+                // fading out
+                if let Some(h) = &info.helper {
+                    h.request_redraw();
+                }
+            }
+            let x1 = info.pos.bottom_right().x - self.mouse_scroll_margin_right;
+            let x2 = info.pos.bottom_right().x;
             let y1 = info.pos.top_left().y
-                + info.pos.height() * self.scroll_display.min(self.scroll_target) / self.max_scroll
-                - 1.0;
-            let y2 = info.pos.top_left().y
-                + info.pos.height() * self.scroll_display.max(self.scroll_target) / self.max_scroll
-                + 1.0;
+                + (track_height - thumb_height) * self.scroll_display / self.max_scroll;
+            self.scrollbar_thumb = (y1, y1 + thumb_height);
             g.draw_rectangle(
                 Rectangle::from_tuples(
-                    (
-                        info.pos.bottom_right().x - self.mouse_scroll_margin_right,
-                        y1,
-                    ),
-                    (info.pos.bottom_right().x, y2),
+                    (x1, info.pos.top_left().y),
+                    (x2, info.pos.bottom_right().y),
+                ),
+                Theme::with_alpha(info.gui_config.theme.scrollbar, 0.1 * alpha),
+            );
+            let highlight = self.mouse_in_scrollbar || self.mouse_scrolling.is_some();
+            g.draw_rectangle(
+                Rectangle::from_tuples((x1, y1), (x2, y1 + thumb_height)),
+                Theme::with_alpha(
+                    info.gui_config.theme.scrollbar,
+                    if highlight { 0.8 } else { 0.5 } * alpha,
                 ),
-                Color::WHITE,
             );
         }
     }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        modifiers: speedy2d::window::ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        if modifiers.ctrl() || modifiers.alt() || modifiers.logo() {
+            return vec![];
+        }
+        let page = self.size_unit.from_rel(1.0, self.last_height_px);
+        let len = self.children.len();
+        let focused = self.config.keyboard_focus_index < len;
+        let used = match key {
+            Some(VirtualKeyCode::PageUp) => {
+                if down {
+                    self.scroll_target -= page;
+                }
+                true
+            }
+            Some(VirtualKeyCode::PageDown) => {
+                if down {
+                    self.scroll_target += page;
+                }
+                true
+            }
+            Some(VirtualKeyCode::Home) => {
+                if down {
+                    self.scroll_target = 0.0;
+                    if focused {
+                        self.focus_first_of(0..len, false);
+                    }
+                }
+                true
+            }
+            Some(VirtualKeyCode::End) => {
+                if down {
+                    self.scroll_target = self.max_scroll;
+                    if focused {
+                        self.focus_first_of((0..len).rev(), true);
+                    }
+                }
+                true
+            }
+            Some(VirtualKeyCode::Up) if focused => {
+                let i = self.config.keyboard_focus_index;
+                !down || self.focus_first_of((0..i).rev(), true)
+            }
+            Some(VirtualKeyCode::Down) if focused => {
+                let i = self.config.keyboard_focus_index;
+                !down || self.focus_first_of(i + 1..len, false)
+            }
+            _ => false,
+        };
+        if used {
+            e.take();
+        }
+        vec![]
+    }
     fn mouse_wheel(&mut self, e: &mut EventInfo, diff: f32) -> Vec<crate::gui::GuiAction> {
         let nst = (self.scroll_target - self.size_unit.from_abs(diff as f32, self.last_height_px))
             .max(0.0);
@@ -302,20 +470,44 @@ impl<C: GuiElemChildren + 'static> GuiElem for ScrollBox<C> {
     }
     fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
         if button == MouseButton::Left && self.mouse_in_scrollbar && e.take() {
-            self.mouse_scrolling = true;
+            let (top, bottom) = self.scrollbar_thumb;
+            let y = self.scrollbar_mouse_y;
+            // drag the thumb from where it was grabbed, or jump so that the thumb is centered on the mouse
+            self.mouse_scrolling = Some(if y >= top && y <= bottom {
+                y - top
+            } else {
+                (bottom - top) / 2.0
+            });
         }
         vec![]
     }
     fn mouse_up(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
         if button == MouseButton::Left {
-            if self.mouse_scrolling {
+            if self.mouse_scrolling.is_some() {
                 e.take();
             }
-            self.mouse_scrolling = false;
+            self.mouse_scrolling = None;
         }
         vec![]
     }
 }
+impl<C: GuiElemChildren> ScrollBox<C> {
+    /// gives the keyboard focus to the first child in `indices` which accepts it
+    fn focus_first_of(&mut self, indices: impl Iterator<Item = usize>, up: bool) -> bool {
+        let mut children = self.children.iter().collect::<Vec<_>>();
+        for i in indices {
+            let child = &mut children[i];
+            // children which are out of view are disabled until they are scrolled into view
+            let enabled = std::mem::replace(&mut child.config_mut().enabled, true);
+            if child._keyboard_move_focus(up, true) {
+                self.config.keyboard_focus_index = i;
+                return true;
+            }
+            child.config_mut().enabled = enabled;
+        }
+        false
+    }
+}
 impl ScrollBoxSizeUnit {
     fn to_rel(&self, val: f32, draw_height: f32) -> f32 {
         match self {
@@ -414,38 +606,86 @@ impl<C: GuiElemChildren + 'static> GuiElem for Button<C> {
         g.draw_rectangle(
             info.pos.clone(),
             if mouse_down && contains {
-                Color::from_rgb(0.25, 0.25, 0.25)
+                info.gui_config.theme.button_pressed
             } else if contains || mouse_down {
-                Color::from_rgb(0.15, 0.15, 0.15)
+                info.gui_config.theme.button_hover
             } else {
-                Color::from_rgb(0.1, 0.1, 0.1)
+                info.gui_config.theme.button
             },
         );
-        if info.has_keyboard_focus {
-            g.draw_line(
-                *info.pos.top_left(),
-                info.pos.top_right(),
-                2.0,
-                Color::WHITE,
-            );
-            g.draw_line(
-                *info.pos.top_left(),
-                info.pos.bottom_left(),
-                2.0,
-                Color::WHITE,
-            );
-            g.draw_line(
-                info.pos.top_right(),
-                *info.pos.bottom_right(),
-                2.0,
-                Color::WHITE,
-            );
-            g.draw_line(
-                info.pos.bottom_left(),
-                *info.pos.bottom_right(),
-                2.0,
-                Color::WHITE,
-            );
+        draw_keyboard_focus(info, g);
+    }
+}
+
+/// The menu opened by `GuiAction::ContextMenu`. The first entry which can have keyboard focus gets it,
+/// Up and Down move the focus, Enter activates the entry (see `Button`) and Escape closes the menu.
+pub struct ContextMenu {
+    config: GuiElemCfg,
+    pub c_entries: ScrollBox<Vec<Box<dyn GuiElem>>>,
+}
+impl ContextMenu {
+    pub fn new(config: GuiElemCfg, mut entries: Vec<Box<dyn GuiElem>>, entry_height: f32) -> Self {
+        if let Some(entry) = entries
+            .iter_mut()
+            .find(|entry| entry.config().keyboard_events_focus)
+        {
+            entry.config_mut().request_keyboard_focus = true;
+        }
+        Self {
+            config: config.w_keyboard_watch(),
+            c_entries: ScrollBox::new(
+                GuiElemCfg::default(),
+                ScrollBoxSizeUnit::Pixels,
+                entries,
+                vec![],
+                entry_height,
+            ),
+        }
+    }
+}
+impl GuiElem for ContextMenu {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new([self.c_entries.elem_mut()].into_iter())
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+    fn key_watch(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: speedy2d::window::ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        match key {
+            Some(VirtualKeyCode::Up | VirtualKeyCode::Down) if down && e.take() => {
+                let up = key == Some(VirtualKeyCode::Up);
+                // wrap around at the first and last entry
+                if !self._keyboard_move_focus(up, false) {
+                    self._keyboard_move_focus(up, true);
+                }
+                vec![]
+            }
+            Some(VirtualKeyCode::Escape) if !down && e.take() => {
+                vec![GuiAction::ContextMenu(None)]
+            }
+            _ => vec![],
         }
     }
 }
@@ -464,6 +704,8 @@ pub struct Slider {
     /// if Some, the display is in a transition period.
     /// you can set this to None to indicate that the transition has finished, but this is not required.
     pub display_since: Option<Instant>,
+    /// if not zero, scrolling while the mouse is over the slider changes its value by this much
+    pub wheel_step: f64,
     pub on_update: Arc<dyn Fn(&mut Self, &mut DrawInfo)>,
 }
 #[allow(unused)]
@@ -504,6 +746,7 @@ impl Slider {
             val_changed_subs: vec![],
             display: false,
             display_since: None,
+            wheel_step: 0.0,
             on_update: Arc::new(on_update),
         }
     }
@@ -631,7 +874,7 @@ impl GuiElem for Slider {
             }
             self.config.redraw = true;
         }
-        let line_color = Color::from_int_rgb(50, 50, 100);
+        let line_color = info.gui_config.theme.slider_line;
         g.draw_circle(
             Vec2::new(line_pos.top_left().x, y_mid_line),
             line_radius,
@@ -650,11 +893,26 @@ impl GuiElem for Slider {
                 y_mid_line,
             ),
             0.5 * dot_size,
-            Color::CYAN,
+            info.gui_config.theme.slider_handle,
         );
         if self.config.redraw {
             self.config.redraw = false;
             (Arc::clone(&self.on_update))(self, info);
         }
     }
+    fn mouse_wheel(&mut self, e: &mut EventInfo, diff: f32) -> Vec<GuiAction> {
+        let val = (self.val + self.wheel_step * diff.signum() as f64)
+            .max(self.min)
+            .min(self.max);
+        // only take the event if this would actually change the value
+        if self.wheel_step != 0.0 && val != self.val && e.take() {
+            self.val = val;
+            self.val_changed = true;
+            for v in &mut self.val_changed_subs {
+                *v = true;
+            }
+            self.config.redraw = true;
+        }
+        vec![]
+    }
 }
@@ -0,0 +1,555 @@
+use std::sync::mpsc;
+
+use musicdb_lib::{
+    data::{database::Database, AlbumId, ArtistId, SongId},
+    server::{Action, Req},
+};
+use speedy2d::{
+    color::Color,
+    dimen::Vec2,
+    shape::Rectangle,
+    window::{ModifiersState, VirtualKeyCode},
+};
+
+use crate::{
+    gui::{DrawInfo, EventInfo, GuiAction, GuiElem, GuiElemCfg, GuiElemChildren},
+    gui_base::{Button, Panel, ScrollBox},
+    gui_library::menu_entry,
+    gui_notif::text_notif,
+    gui_text::{Label, TextField},
+};
+
+/*
+
+An editor for the name, artist and tags of a song, album or artist.
+When saving, the values are compared to the ones from when the editor was opened,
+so changes made by other clients in the meantime aren't overwritten without asking.
+
+*/
+
+const ELEM_HEIGHT: f32 = 32.0;
+
+#[derive(Clone, Copy)]
+pub enum EditTarget {
+    Song(SongId),
+    Album(AlbumId),
+    Artist(ArtistId),
+}
+/// the values which can be changed in the editor
+#[derive(Clone, PartialEq, Debug)]
+struct Fields {
+    name: String,
+    /// `None` for artists
+    artist: Option<ArtistId>,
+    tags: Vec<String>,
+}
+impl EditTarget {
+    fn fields(self, db: &Database) -> Option<Fields> {
+        Some(match self {
+            Self::Song(id) => {
+                let song = db.get_song(&id)?;
+                Fields {
+                    name: song.title.clone(),
+                    artist: Some(song.artist),
+                    tags: song.general.tags.clone(),
+                }
+            }
+            Self::Album(id) => {
+                let album = db.albums().get(&id)?;
+                Fields {
+                    name: album.name.clone(),
+                    artist: Some(album.artist),
+                    tags: album.general.tags.clone(),
+                }
+            }
+            Self::Artist(id) => {
+                let artist = db.artists().get(&id)?;
+                Fields {
+                    name: artist.name.clone(),
+                    artist: None,
+                    tags: artist.general.tags.clone(),
+                }
+            }
+        })
+    }
+    /// the action which changes the target's fields to these values
+    fn modify(self, fields: Fields, db: &Database) -> Option<Action> {
+        Some(match self {
+            Self::Song(id) => {
+                let mut song = db.get_song(&id)?.clone();
+                song.title = fields.name;
+                song.general.tags = fields.tags;
+                if let Some(artist) = fields.artist {
+                    if artist != song.artist {
+                        song.artist = artist;
+                        // the song can't stay in an album of another artist
+                        if song
+                            .album
+                            .and_then(|id| db.albums().get(&id))
+                            .is_some_and(|album| album.artist != artist)
+                        {
+                            song.album = None;
+                        }
+                    }
+                }
+                Action::ModifySong(song, Req::none())
+            }
+            Self::Album(id) => {
+                let mut album = db.albums().get(&id)?.clone();
+                album.name = fields.name;
+                album.general.tags = fields.tags;
+                if let Some(artist) = fields.artist {
+                    album.artist = artist;
+                }
+                Action::ModifyAlbum(album, Req::none())
+            }
+            Self::Artist(id) => {
+                let mut artist = db.artists().get(&id)?.clone();
+                artist.name = fields.name;
+                artist.general.tags = fields.tags;
+                Action::ModifyArtist(artist, Req::none())
+            }
+        })
+    }
+    fn kind(self) -> &'static str {
+        match self {
+            Self::Song(_) => "song",
+            Self::Album(_) => "album",
+            Self::Artist(_) => "artist",
+        }
+    }
+}
+
+/// splits `Key=Value` tags, tags without a value (like `Fav`) have an empty value
+fn split_tag(tag: &str) -> (&str, &str) {
+    tag.split_once('=').unwrap_or((tag, ""))
+}
+/// the opposite of `split_tag`, rows with an empty key are skipped
+fn join_tags<'a>(rows: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<String> {
+    rows.map(|(key, value)| (key.trim(), value.trim()))
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| {
+            if value.is_empty() {
+                key.to_owned()
+            } else {
+                format!("{key}={value}")
+            }
+        })
+        .collect()
+}
+
+enum Event {
+    Close,
+    /// `true` to overwrite changes which were made after the editor was opened
+    Save(bool),
+    AddTag,
+    RemoveTag(usize),
+}
+
+pub struct Editor {
+    config: GuiElemCfg,
+    target: EditTarget,
+    /// the values when the editor was opened
+    original: Fields,
+    c_title: Label,
+    c_scroll_box: ScrollBox<EditorElems>,
+    c_buttons: Panel<[Button<[Label; 1]>; 2]>,
+    c_background: Panel<()>,
+    event_sender: mpsc::Sender<Event>,
+    event_recv: mpsc::Receiver<Event>,
+    /// to give each tag row a unique id
+    tag_rows_created: usize,
+}
+struct EditorElems {
+    c_name: TextField,
+    c_artist: Option<TextField>,
+    c_tags_label: Label,
+    c_tags: Vec<TagRow>,
+    c_add_tag: Button<[Label; 1]>,
+}
+impl GuiElemChildren for EditorElems {
+    fn iter(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new(
+            [self.c_name.elem_mut()]
+                .into_iter()
+                .chain(self.c_artist.as_mut().map(|v| v.elem_mut()))
+                .chain([self.c_tags_label.elem_mut()])
+                .chain(self.c_tags.iter_mut().map(|v| v.elem_mut()))
+                .chain([self.c_add_tag.elem_mut()]),
+        )
+    }
+    fn len(&self) -> usize {
+        3 + self.c_artist.iter().count() + self.c_tags.len()
+    }
+}
+
+impl Editor {
+    /// `None` if the target doesn't exist
+    pub fn new(target: EditTarget, db: &Database) -> Option<Self> {
+        let original = target.fields(db)?;
+        let (sender, recv) = mpsc::channel();
+        let button = |text: &str, event: fn() -> Event, x: f32| {
+            let sender = sender.clone();
+            Button::new(
+                GuiElemCfg::at(Rectangle::from_tuples((x, 0.0), (x + 0.5, 1.0))),
+                move |_| {
+                    _ = sender.send(event());
+                    vec![]
+                },
+                [Label::new(
+                    GuiElemCfg::default(),
+                    text.to_owned(),
+                    Color::WHITE,
+                    None,
+                    Vec2::new(0.5, 0.5),
+                )],
+            )
+        };
+        let c_buttons = Panel::new(
+            GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.95), (1.0, 1.0))),
+            [
+                button("Cancel", || Event::Close, 0.0),
+                button("Save", || Event::Save(false), 0.5),
+            ],
+        );
+        let c_add_tag = {
+            let sender = sender.clone();
+            Button::new(
+                GuiElemCfg::default(),
+                move |_| {
+                    _ = sender.send(Event::AddTag);
+                    vec![]
+                },
+                [Label::new(
+                    GuiElemCfg::default(),
+                    "Add tag".to_owned(),
+                    Color::LIGHT_GRAY,
+                    None,
+                    Vec2::new(0.0, 0.5),
+                )],
+            )
+        };
+        let mut c_name = TextField::new_adv(
+            GuiElemCfg::default(),
+            original.name.clone(),
+            if matches!(target, EditTarget::Song(_)) {
+                "title"
+            } else {
+                "name"
+            }
+            .to_owned(),
+            Color::DARK_GRAY,
+            Color::WHITE,
+        );
+        c_name.config_mut().request_keyboard_focus = true;
+        let c_artist = original.artist.map(|id| {
+            TextField::new_adv(
+                GuiElemCfg::default(),
+                db.artists()
+                    .get(&id)
+                    .map(|artist| artist.name.clone())
+                    .unwrap_or_default(),
+                "artist".to_owned(),
+                Color::DARK_GRAY,
+                Color::WHITE,
+            )
+        });
+        let mut editor = Self {
+            config: GuiElemCfg::default().w_keyboard_watch(),
+            target,
+            c_title: Label::new(
+                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (1.0, 0.05))),
+                format!("Editing {} {}", target.kind(), original.name),
+                Color::LIGHT_GRAY,
+                None,
+                Vec2::new(0.5, 0.5),
+            ),
+            c_scroll_box: ScrollBox::new(
+                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.05), (1.0, 0.95))),
+                crate::gui_base::ScrollBoxSizeUnit::Pixels,
+                EditorElems {
+                    c_name,
+                    c_artist,
+                    c_tags_label: Label::new(
+                        GuiElemCfg::default(),
+                        "Tags (key and value, or only a key)".to_owned(),
+                        Color::GRAY,
+                        None,
+                        Vec2::new(0.0, 0.5),
+                    ),
+                    c_tags: vec![],
+                    c_add_tag,
+                },
+                vec![],
+                ELEM_HEIGHT,
+            ),
+            c_buttons,
+            c_background: Panel::with_background(GuiElemCfg::default(), (), Color::BLACK),
+            event_sender: sender,
+            event_recv: recv,
+            tag_rows_created: 0,
+            original,
+        };
+        for tag in editor.original.tags.clone() {
+            let (key, value) = split_tag(&tag);
+            editor.add_tag_row(key.to_owned(), value.to_owned());
+        }
+        Some(editor)
+    }
+    fn add_tag_row(&mut self, key: String, value: String) {
+        let id = self.tag_rows_created;
+        self.tag_rows_created += 1;
+        let sender = self.event_sender.clone();
+        self.c_scroll_box.children.c_tags.push(TagRow {
+            config: GuiElemCfg::default(),
+            c_key: TextField::new_adv(
+                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.4, 1.0))),
+                key,
+                "key".to_owned(),
+                Color::DARK_GRAY,
+                Color::WHITE,
+            ),
+            c_value: TextField::new_adv(
+                GuiElemCfg::at(Rectangle::from_tuples((0.41, 0.0), (0.9, 1.0))),
+                value,
+                "value".to_owned(),
+                Color::DARK_GRAY,
+                Color::WHITE,
+            ),
+            c_remove: Button::new(
+                GuiElemCfg::at(Rectangle::from_tuples((0.91, 0.0), (1.0, 1.0))),
+                move |_| {
+                    _ = sender.send(Event::RemoveTag(id));
+                    vec![]
+                },
+                [Label::new(
+                    GuiElemCfg::default(),
+                    "remove".to_owned(),
+                    Color::GRAY,
+                    None,
+                    Vec2::new(0.5, 0.5),
+                )],
+            ),
+            id,
+        });
+        self.c_scroll_box.config_mut().redraw = true;
+    }
+    /// the values in the text fields, or an error if the artist doesn't exist
+    fn fields(&self, db: &Database) -> Result<Fields, String> {
+        let elems = &self.c_scroll_box.children;
+        let artist = if let Some(c_artist) = &elems.c_artist {
+            let name = c_artist.c_input.content.get_text().trim();
+            let artists = db.artists().values();
+            // prefer the previous artist and exact matches
+            let artist = self
+                .original
+                .artist
+                .and_then(|id| db.artists().get(&id))
+                .filter(|artist| artist.name == name)
+                .or_else(|| artists.clone().find(|artist| artist.name == name))
+                .or_else(|| {
+                    let name = name.to_lowercase();
+                    artists
+                        .clone()
+                        .find(|artist| artist.name.to_lowercase() == name)
+                });
+            match artist {
+                Some(artist) => Some(artist.id),
+                None => return Err(format!("There is no artist named {name}")),
+            }
+        } else {
+            None
+        };
+        Ok(Fields {
+            name: elems.c_name.c_input.content.get_text().trim().to_owned(),
+            artist,
+            tags: join_tags(elems.c_tags[..].iter().map(|row| {
+                (
+                    row.c_key.c_input.content.get_text().as_str(),
+                    row.c_value.c_input.content.get_text().as_str(),
+                )
+            })),
+        })
+    }
+    fn save(&self, overwrite: bool, db: &Database) -> Vec<GuiAction> {
+        let fields = match self.fields(db) {
+            Ok(fields) => fields,
+            Err(e) => return vec![text_notif(e)],
+        };
+        let Some(current) = self.target.fields(db) else {
+            return vec![
+                text_notif(format!("This {} was removed", self.target.kind())),
+                close(),
+            ];
+        };
+        if !overwrite && current != self.original {
+            let sender = self.event_sender.clone();
+            return vec![GuiAction::ContextMenu(Some(vec![
+                Box::new(Label::new(
+                    GuiElemCfg::default(),
+                    format!(
+                        "This {} was changed since you started editing it",
+                        self.target.kind()
+                    ),
+                    Color::LIGHT_GRAY,
+                    None,
+                    Vec2::new_y(0.5),
+                )),
+                // first, so it gets the keyboard focus
+                menu_entry("Keep editing", Vec::new),
+                menu_entry("Overwrite", move || {
+                    _ = sender.send(Event::Save(true));
+                    vec![]
+                }),
+            ]))];
+        }
+        if fields == current {
+            return vec![close()];
+        }
+        self.target
+            .modify(fields, db)
+            .map(GuiAction::SendToServer)
+            .into_iter()
+            .chain([close()])
+            .collect()
+    }
+}
+fn close() -> GuiAction {
+    GuiAction::Do(Box::new(|gui| {
+        gui.gui.c_editor = None;
+        gui.gui.set_normal_ui_enabled(true);
+    }))
+}
+
+impl GuiElem for Editor {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new(
+            [
+                self.c_title.elem_mut(),
+                self.c_scroll_box.elem_mut(),
+                self.c_buttons.elem_mut(),
+                self.c_background.elem_mut(),
+            ]
+            .into_iter(),
+        )
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+    fn draw(&mut self, info: &mut DrawInfo, _g: &mut speedy2d::Graphics2D) {
+        if self.config.init {
+            info.actions.push(GuiAction::Do(Box::new(|gui| {
+                gui.gui.set_normal_ui_enabled(false);
+            })));
+            info.actions.push(GuiAction::ResetKeyboardFocus);
+        }
+        while let Ok(event) = self.event_recv.try_recv() {
+            match event {
+                Event::Close => info.actions.push(close()),
+                Event::Save(overwrite) => {
+                    let actions = self.save(overwrite, info.database);
+                    info.actions.extend(actions);
+                }
+                Event::AddTag => {
+                    self.add_tag_row(String::new(), String::new());
+                    if let Some(row) = self.c_scroll_box.children.c_tags.last_mut() {
+                        row.c_key.config_mut().request_keyboard_focus = true;
+                        info.actions.push(GuiAction::ResetKeyboardFocus);
+                    }
+                }
+                Event::RemoveTag(id) => {
+                    self.c_scroll_box.children.c_tags.retain(|row| row.id != id);
+                    self.c_scroll_box.config_mut().redraw = true;
+                }
+            }
+        }
+    }
+    fn key_watch(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        // discard the changes
+        if !down && key == Some(VirtualKeyCode::Escape) && e.take() {
+            vec![close()]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// one tag in the editor, with text fields for its key and value
+struct TagRow {
+    config: GuiElemCfg,
+    id: usize,
+    c_key: TextField,
+    c_value: TextField,
+    c_remove: Button<[Label; 1]>,
+}
+impl GuiElem for TagRow {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new(
+            [
+                self.c_key.elem_mut(),
+                self.c_value.elem_mut(),
+                self.c_remove.elem_mut(),
+            ]
+            .into_iter(),
+        )
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+}
+
+#[test]
+fn test_tags() {
+    assert_eq!(split_tag("Year=1995"), ("Year", "1995"));
+    assert_eq!(split_tag("Fav"), ("Fav", ""));
+    assert_eq!(split_tag("A=b=c"), ("A", "b=c"));
+    assert_eq!(
+        join_tags(
+            [
+                ("Year", "1995"),
+                (" Fav ", ""),
+                ("", "ignored"),
+                ("Genre", " Rock ")
+            ]
+            .into_iter()
+        ),
+        ["Year=1995", "Fav", "Genre=Rock"]
+    );
+}
@@ -1,20 +1,39 @@
 use std::{
-    sync::{atomic::AtomicBool, Arc},
+    sync::{atomic::AtomicBool, mpsc, Arc},
     time::Instant,
 };
 
-use musicdb_lib::data::ArtistId;
-use speedy2d::{color::Color, dimen::Vec2, image::ImageHandle, shape::Rectangle};
+use musicdb_lib::{
+    data::{ArtistId, CoverId, SongId},
+    server::thumbnail,
+};
+use speedy2d::{
+    color::Color,
+    dimen::Vec2,
+    image::{ImageDataType, ImageHandle, ImageSmoothingMode},
+    shape::Rectangle,
+};
 
 use crate::{
     gui::{rect_from_rel, DrawInfo, GuiAction, GuiElem, GuiElemCfg, GuiServerImage},
     gui_anim::AnimationController,
     gui_base::Button,
-    gui_playback::{get_right_x, image_display, CurrentInfo},
+    gui_playback::{get_right_x, image_display, song_cover, CurrentInfo},
     gui_playpause::PlayPause,
     gui_text::{AdvancedLabel, Label},
+    gui_theme::Theme,
+    textcfg,
 };
 
+/// how far (relative to the screen's height) the upcoming songs slide up when they change
+const UPCOMING_SLIDE: f32 = 0.05;
+/// the size of the cover thumbnail which is blurred for the background, it is stretched to fill the screen
+const BACKGROUND_PX: u32 = 64;
+/// the blur's sigma, in pixels of the thumbnail
+const BACKGROUND_BLUR: f32 = 4.0;
+/// how much of the theme's background color is drawn over the blurred cover
+const BACKGROUND_DIM: f32 = 0.6;
+
 pub struct IdleDisplay {
     pub config: GuiElemCfg,
     pub idle_mode: f32,
@@ -24,6 +43,8 @@ pub struct IdleDisplay {
     pub c_top_label: AdvancedLabel,
     pub c_side1_label: AdvancedLabel,
     pub c_side2_label: AdvancedLabel,
+    pub c_clock_label: AdvancedLabel,
+    pub c_upcoming_label: AdvancedLabel,
     pub c_buttons: PlayPause,
     pub c_buttons_custom_pos: bool,
 
@@ -44,6 +65,11 @@ pub struct IdleDisplay {
     pub force_reset_texts: bool,
 
     is_fav: (bool, Arc<AtomicBool>),
+    /// `textcfg::unix_minutes` when the clock was last updated
+    clock_minute: Option<u64>,
+    upcoming: Vec<SongId>,
+    upcoming_offset: AnimationController<f32>,
+    background: Background,
 }
 
 impl IdleDisplay {
@@ -73,6 +99,16 @@ impl IdleDisplay {
             ),
             c_side1_label: AdvancedLabel::new(GuiElemCfg::default(), Vec2::new(0.0, 0.5), vec![]),
             c_side2_label: AdvancedLabel::new(GuiElemCfg::default(), Vec2::new(0.0, 0.5), vec![]),
+            c_clock_label: AdvancedLabel::new(
+                GuiElemCfg::default().disabled(),
+                Vec2::new(0.0, 0.5),
+                vec![],
+            ),
+            c_upcoming_label: AdvancedLabel::new(
+                GuiElemCfg::default().disabled(),
+                Vec2::new(0.0, 0.0),
+                vec![],
+            ),
             is_fav: (false, Arc::clone(&is_fav)),
             c_buttons: PlayPause::new(GuiElemCfg::default(), is_fav),
             c_buttons_custom_pos: false,
@@ -102,6 +138,136 @@ impl IdleDisplay {
             artist_image_top: 0.5,
             artist_image_to_cover_margin: 0.01,
             force_reset_texts: false,
+            clock_minute: None,
+            upcoming: vec![],
+            upcoming_offset: AnimationController::new(
+                0.0,
+                0.0,
+                0.01,
+                1.0,
+                0.8,
+                0.6,
+                Instant::now(),
+            ),
+            background: Background::new(),
+        }
+    }
+}
+
+/// width, height and RGBA pixels, see `thumbnail::blurred`
+type Pixels = (u32, u32, Vec<u8>);
+
+/// the current song's cover, blurred and dimmed, behind the idle screen's contents.
+/// songs without a cover only have the theme's background.
+struct Background {
+    /// the cover which is (or is being loaded to be) shown
+    cover: Option<CoverId>,
+    /// the blurred pixels, from a worker thread
+    loading: Option<mpsc::Receiver<Option<Pixels>>>,
+    image: Option<ImageHandle>,
+    /// fades out while `image` fades in, as `fade` goes from 0 to 1
+    previous: Option<ImageHandle>,
+    fade: AnimationController<f32>,
+}
+impl Background {
+    fn new() -> Self {
+        Self {
+            cover: None,
+            loading: None,
+            image: None,
+            previous: None,
+            fade: AnimationController::new(1.0, 1.0, 0.01, 1.0, 0.8, 0.6, Instant::now()),
+        }
+    }
+    fn update(
+        &mut self,
+        cover: Option<CoverId>,
+        info: &mut DrawInfo,
+        g: &mut speedy2d::Graphics2D,
+    ) {
+        if cover != self.cover {
+            self.cover = cover;
+            self.loading = cover.map(|id| {
+                let (sender, receiver) = mpsc::channel();
+                let get_con = Arc::clone(&info.get_con);
+                std::thread::spawn(move || {
+                    let bytes = get_con
+                        .with_reconnect(|get_con| get_con.cover_thumb(id, BACKGROUND_PX))
+                        .ok()
+                        .and_then(|v| v.ok());
+                    _ = sender.send(bytes.and_then(|bytes| {
+                        thumbnail::blurred(&bytes, BACKGROUND_PX, BACKGROUND_BLUR)
+                    }));
+                });
+                receiver
+            });
+            if cover.is_none() {
+                self.show(None);
+            }
+        }
+        if let Some(receiver) = &self.loading {
+            let image = match receiver.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => return,
+                Ok(Some((width, height, pixels))) => match g.create_image_from_raw_pixels(
+                    ImageDataType::RGBA,
+                    ImageSmoothingMode::Linear,
+                    (width, height),
+                    &pixels,
+                ) {
+                    Ok(image) => Some(image),
+                    Err(e) => {
+                        eprintln!("[info] couldn't create the idle screen's background: {e}");
+                        None
+                    }
+                },
+                Ok(None) | Err(mpsc::TryRecvError::Disconnected) => None,
+            };
+            self.loading = None;
+            self.show(image);
+        }
+    }
+    /// crossfades to this image
+    fn show(&mut self, image: Option<ImageHandle>) {
+        self.previous = std::mem::replace(&mut self.image, image);
+        self.fade.value = 0.0;
+        self.fade.target = 1.0;
+    }
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D, alpha: f32) {
+        if self.fade.update(info.time, info.high_performance) {
+            if let Some(h) = &info.helper {
+                h.request_redraw();
+            }
+        } else {
+            self.previous = None;
+        }
+        let mut covered = 0.0;
+        for (image, alpha) in [
+            (&self.previous, alpha * (1.0 - self.fade.value)),
+            (&self.image, alpha * self.fade.value),
+        ] {
+            if let Some(image) = image {
+                // fill the screen, cutting off the sides which don't fit
+                let size = image.size().into_f32();
+                let scale = (info.pos.width() / size.x).max(info.pos.height() / size.y);
+                let w = info.pos.width() / (size.x * scale);
+                let h = info.pos.height() / (size.y * scale);
+                g.draw_rectangle_image_subset_tinted(
+                    info.pos.clone(),
+                    Color::from_rgba(1.0, 1.0, 1.0, alpha),
+                    Rectangle::from_tuples(
+                        ((1.0 - w) / 2.0, (1.0 - h) / 2.0),
+                        ((1.0 + w) / 2.0, (1.0 + h) / 2.0),
+                    ),
+                    image,
+                );
+                covered += alpha;
+            }
+        }
+        if covered > 0.0 {
+            g.draw_rectangle(
+                info.pos.clone(),
+                Theme::with_alpha(info.gui_config.theme.background, BACKGROUND_DIM * covered),
+            );
         }
     }
 }
@@ -114,6 +280,8 @@ impl GuiElem for IdleDisplay {
                 self.c_top_label.elem_mut(),
                 self.c_side1_label.elem_mut(),
                 self.c_side2_label.elem_mut(),
+                self.c_clock_label.elem_mut(),
+                self.c_upcoming_label.elem_mut(),
                 self.c_buttons.elem_mut(),
             ]
             .into_iter(),
@@ -123,10 +291,17 @@ impl GuiElem for IdleDisplay {
         // draw background
         g.draw_rectangle(
             info.pos.clone(),
-            Color::from_rgba(0.0, 0.0, 0.0, 0.5 + 0.5 * self.idle_mode),
+            Theme::with_alpha(info.gui_config.theme.background, 0.5 + 0.5 * self.idle_mode),
         );
         // update current_info
         self.current_info.update(info, g);
+        // the blurred cover
+        let cover = self
+            .current_info
+            .current_song
+            .and_then(|id| song_cover(info.database, &id));
+        self.background.update(cover, info, g);
+        self.background.draw(info, g, self.idle_mode);
         if self.current_info.new_song || self.force_reset_texts {
             self.current_info.new_song = false;
             self.force_reset_texts = false;
@@ -166,6 +341,30 @@ impl GuiElem for IdleDisplay {
                 vec![]
             };
             self.c_side2_label.config_mut().redraw = true;
+            // the next songs in the queue
+            let upcoming = if info.gui_config.idle_upcoming_text.is_some() {
+                info.database
+                    .queue
+                    .upcoming_songs(info.database, info.gui_config.idle_upcoming_count)
+            } else {
+                vec![]
+            };
+            if upcoming != self.upcoming && !self.upcoming.is_empty() {
+                self.upcoming_offset.value = UPCOMING_SLIDE;
+            }
+            self.upcoming = upcoming;
+            self.c_upcoming_label.content = if let Some(text) = &info.gui_config.idle_upcoming_text
+            {
+                self.upcoming
+                    .iter()
+                    .filter_map(|id| info.database.get_song(id))
+                    .flat_map(|song| text.gen(info.database, Some(song)))
+                    .collect()
+            } else {
+                vec![]
+            };
+            self.c_upcoming_label.config_mut().redraw = true;
+            self.clock_minute = None;
             // check artist
             if let Some(artist_id) = self
                 .current_info
@@ -183,21 +382,30 @@ impl GuiElem for IdleDisplay {
                     self.current_artist_image = Some((artist_id, None));
                     self.artist_image_aspect_ratio.target = 0.0;
                     if let Some(artist) = info.database.artists().get(&artist_id) {
-                        for tag in &artist.general.tags {
-                            if tag.starts_with("ImageExt=") {
-                                let filename = format!("{}.{}", artist.name, &tag[9..]);
-                                self.current_artist_image =
-                                    Some((artist_id, Some((filename.clone(), None))));
-                                if !info.custom_images.contains_key(&filename) {
-                                    info.custom_images.insert(
-                                        filename.clone(),
-                                        GuiServerImage::new_custom_file(
-                                            filename,
-                                            Arc::clone(&info.get_con),
-                                        ),
-                                    );
-                                }
-                                break;
+                        // use the ImageExt= tag if there is one, otherwise look for a custom file with the artist's name
+                        let filename = artist
+                            .general
+                            .tags
+                            .iter()
+                            .find_map(|tag| tag.strip_prefix("ImageExt="))
+                            .map(|ext| format!("{}.{ext}", artist.name))
+                            .or_else(|| {
+                                ["png", "jpg", "jpeg"].iter().find_map(|ext| {
+                                    let filename = format!("{}.{ext}", artist.name);
+                                    info.custom_files.contains(&filename).then_some(filename)
+                                })
+                            });
+                        if let Some(filename) = filename {
+                            self.current_artist_image =
+                                Some((artist_id, Some((filename.clone(), None))));
+                            if !info.custom_images.contains_key(&filename) {
+                                info.custom_images.insert(
+                                    filename.clone(),
+                                    GuiServerImage::new_custom_file(
+                                        filename,
+                                        Arc::clone(&info.get_con),
+                                    ),
+                                );
                             }
                         }
                     }
@@ -209,6 +417,37 @@ impl GuiElem for IdleDisplay {
                 }
             }
         }
+        // the clock only changes once a minute, and the gui is redrawn every second anyway
+        if let Some(clock) = &info.gui_config.idle_clock_text {
+            let minute = textcfg::unix_minutes();
+            if self.clock_minute != Some(minute) {
+                self.clock_minute = Some(minute);
+                self.c_clock_label.content = clock.gen(
+                    info.database,
+                    self.current_info
+                        .current_song
+                        .and_then(|id| info.database.get_song(&id)),
+                );
+                self.c_clock_label.config_mut().redraw = true;
+            }
+        }
+        self.c_clock_label.config_mut().enabled = info.gui_config.idle_clock_text.is_some();
+        self.c_clock_label.config_mut().pos = info.gui_config.idle_clock_pos.clone();
+        self.c_upcoming_label.config_mut().enabled = info.gui_config.idle_upcoming_text.is_some();
+        if self
+            .upcoming_offset
+            .update(info.time, info.high_performance)
+        {
+            if let Some(h) = &info.helper {
+                h.request_redraw();
+            }
+        }
+        let pos = &info.gui_config.idle_upcoming_pos;
+        let offset = self.upcoming_offset.value;
+        self.c_upcoming_label.config_mut().pos = Rectangle::from_tuples(
+            (pos.top_left().x, pos.top_left().y + offset),
+            (pos.bottom_right().x, pos.bottom_right().y + offset),
+        );
         if self.current_info.new_cover {
             self.current_info.new_cover = false;
             match self.current_info.current_cover {
@@ -334,5 +573,7 @@ impl GuiElem for IdleDisplay {
     }
     fn updated_queue(&mut self) {
         self.current_info.update = true;
+        // the queue's position and remaining time (`\q`, `\r`) may have changed
+        self.force_reset_texts = true;
     }
 }
@@ -1,60 +1,73 @@
 use std::{
+    cell::RefCell,
     cmp::Ordering,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    rc::Rc,
     sync::Arc,
     sync::{
         atomic::{AtomicBool, AtomicUsize},
         mpsc, Mutex,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use musicdb_lib::data::{
     album::Album,
     artist::Artist,
     database::Database,
-    queue::{Queue, QueueContent},
+    queue::{Queue, QueueContent, QueueFolder},
+    search::SearchResultKind,
     song::Song,
-    AlbumId, ArtistId, GeneralData, SongId,
+    AlbumId, ArtistId, CoverId, GeneralData, SongId,
 };
+use musicdb_lib::server::{Action, Req};
 use regex::{Regex, RegexBuilder};
 use speedy2d::{
     color::Color,
     dimen::Vec2,
     shape::Rectangle,
-    window::{MouseButton, VirtualKeyCode},
+    window::{ModifiersState, MouseButton, VirtualKeyCode},
 };
 
 use crate::{
+    clipboard,
     gui::{
         Dragging, DrawInfo, EventInfo, GuiAction, GuiConfig, GuiElem, GuiElemCfg, GuiElemChildren,
         GuiElemWrapper,
     },
     gui_anim::AnimationController,
-    gui_base::{Button, Panel, ScrollBox},
-    gui_text::{self, AdvancedLabel, Label, TextField},
+    gui_base::{draw_keyboard_focus, Button, Panel, ScrollBox},
+    gui_edit::EditTarget,
+    gui_notif::text_notif,
+    gui_queue::{add_to_queue_album_by_id, add_to_queue_artist_by_id},
+    gui_text::{self, AdvancedLabel, Label, TextField, TextLayoutMode},
 };
 
-use self::selected::Selected;
+use self::selected::{Item, Selected};
 
 /*
 
 This is responsible for showing the library,
-with Regex search and drag-n-drop.
+with a quick search, Regex search and drag-n-drop.
 
 */
 
 pub struct LibraryBrowser {
     config: GuiElemCfg,
+    pub c_search: TextField,
     pub c_search_artist: TextField,
     pub c_search_album: TextField,
     pub c_search_song: TextField,
     pub c_scroll_box: ScrollBox<Vec<ListElement>>,
     pub c_filter_button: Button<[Label; 1]>,
+    pub c_view_button: Button<[Label; 1]>,
     pub c_filter_panel: FilterPanel,
-    pub c_selected_counter_panel: Panel<[Label; 1]>,
+    pub c_selected_counter_panel: Panel<(Label, Button<[Label; 1]>)>,
     // - - -
     library_sorted: Vec<(ArtistId, Vec<SongId>, Vec<(AlbumId, Vec<SongId>)>)>,
+    /// the sort order used for `library_sorted`
+    sort: LibrarySort,
     library_filtered: Vec<(
         ArtistId,
         Vec<(SongId, f32)>,
@@ -62,7 +75,22 @@ pub struct LibraryBrowser {
         f32,
     )>,
     selected: Selected,
+    /// the item which was clicked last, where a range selected using shift starts
+    select_anchor: Option<Item>,
+    /// if the albums are shown as a grid, see `GuiConfig::library_grid`
+    grid: bool,
+    /// the album in the grid whose songs are shown below its row
+    grid_expanded: Option<AlbumId>,
     // - - -
+    /// the query from `c_search` which is currently applied
+    search_query: String,
+    /// what was typed into `c_search` and when, applied after `SEARCH_DEBOUNCE`
+    search_query_typed: (String, Instant),
+    search_matches: Option<SearchMatches>,
+    /// give the keyboard focus to the first element in the list once it was rebuilt
+    focus_list: bool,
+    /// focus this song instead of the first element, see `show_song`
+    focus_song: Option<SongId>,
     search_artist: String,
     search_artist_regex: Option<Regex>,
     search_album: String,
@@ -95,25 +123,37 @@ fn search_regex_new(pat: &str, case_insensitive: bool) -> Result<Option<Regex>,
         ))
     }
 }
-const LP_LIB1: f32 = 0.1;
+const LP_LIB1: f32 = 0.15;
 const LP_LIB2: f32 = 1.0;
-const LP_LIB1S: f32 = 0.4;
+const LP_LIB1S: f32 = 0.45;
+/// how long to wait after the last keypress before searching, so typing stays smooth in large libraries
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(150);
+/// the color of the parts of names which match the search
+const SEARCH_HIGHLIGHT: Color = Color::from_rgb(1.0, 0.8, 0.3);
+/// the minimum width of an album in the grid, in lines
+const GRID_TILE_WIDTH: f32 = 6.0;
 impl LibraryBrowser {
     pub fn new(config: GuiElemCfg) -> Self {
+        let c_search = TextField::new(
+            GuiElemCfg::at(Rectangle::from_tuples((0.01, 0.01), (0.89, 0.045))),
+            "search".to_string(),
+            Color::GRAY,
+            Color::WHITE,
+        );
         let c_search_artist = TextField::new(
-            GuiElemCfg::at(Rectangle::from_tuples((0.01, 0.01), (0.45, 0.05))),
+            GuiElemCfg::at(Rectangle::from_tuples((0.01, 0.055), (0.45, 0.09))),
             "artist".to_string(),
             Color::GRAY,
             Color::WHITE,
         );
         let c_search_album = TextField::new(
-            GuiElemCfg::at(Rectangle::from_tuples((0.55, 0.01), (0.99, 0.05))),
+            GuiElemCfg::at(Rectangle::from_tuples((0.55, 0.055), (0.99, 0.09))),
             "album".to_string(),
             Color::GRAY,
             Color::WHITE,
         );
         let c_search_song = TextField::new(
-            GuiElemCfg::at(Rectangle::from_tuples((0.01, 0.06), (0.99, 0.1))),
+            GuiElemCfg::at(Rectangle::from_tuples((0.01, 0.1), (0.99, 0.135))),
             "song".to_string(),
             Color::GRAY,
             Color::WHITE,
@@ -134,7 +174,7 @@ impl LibraryBrowser {
         let filter_target_state = Arc::new(AtomicBool::new(false));
         let fts = Arc::clone(&filter_target_state);
         let c_filter_button = Button::new(
-            GuiElemCfg::at(Rectangle::from_tuples((0.46, 0.01), (0.54, 0.05))),
+            GuiElemCfg::at(Rectangle::from_tuples((0.46, 0.055), (0.54, 0.09))),
             move |_| {
                 fts.store(
                     !fts.load(std::sync::atomic::Ordering::Relaxed),
@@ -150,6 +190,27 @@ impl LibraryBrowser {
                 Vec2::new(0.5, 0.5),
             )],
         );
+        let c_view_button = Button::new(
+            GuiElemCfg::at(Rectangle::from_tuples((0.9, 0.01), (0.99, 0.045))),
+            |_| {
+                vec![GuiAction::Do(Box::new(|gui| {
+                    if let Some(cfg) = &mut gui.gui_config {
+                        cfg.library_grid = !cfg.library_grid;
+                        crate::gui::save_config_value(
+                            "library_grid",
+                            toml::Value::Boolean(cfg.library_grid),
+                        );
+                    }
+                }))]
+            },
+            [Label::new(
+                GuiElemCfg::default(),
+                "grid".to_owned(),
+                Color::GRAY,
+                None,
+                Vec2::new(0.5, 0.5),
+            )],
+        );
         let filter_songs = Arc::new(Mutex::new(Filter {
             and: true,
             filters: vec![],
@@ -165,11 +226,13 @@ impl LibraryBrowser {
         let selected = Selected::new(Arc::clone(&search_settings_changed));
         Self {
             config: config.w_keyboard_watch(),
+            c_search,
             c_search_artist,
             c_search_album,
             c_search_song,
             c_scroll_box: library_scroll_box,
             c_filter_button,
+            c_view_button,
             c_filter_panel: FilterPanel::new(
                 Arc::clone(&search_settings_changed),
                 Arc::clone(&search_is_case_sensitive),
@@ -182,20 +245,45 @@ impl LibraryBrowser {
             ),
             c_selected_counter_panel: Panel::with_background(
                 GuiElemCfg::default().disabled(),
-                [Label::new(
-                    GuiElemCfg::default(),
-                    String::new(),
-                    Color::LIGHT_GRAY,
-                    None,
-                    Vec2::new(0.5, 0.5),
-                )],
+                (
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.8, 1.0))),
+                        String::new(),
+                        Color::LIGHT_GRAY,
+                        None,
+                        Vec2::new(0.5, 0.5),
+                    ),
+                    Button::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.8, 0.0), (1.0, 1.0))),
+                        {
+                            let selected = selected.clone();
+                            move |_| vec![selection_menu(selected.clone())]
+                        },
+                        [Label::new(
+                            GuiElemCfg::default(),
+                            "Selection...".to_owned(),
+                            Color::WHITE,
+                            None,
+                            Vec2::new(0.5, 0.5),
+                        )],
+                    ),
+                ),
                 Color::from_rgba(0.0, 0.0, 0.0, 0.8),
             ),
             // - - -
             library_sorted: vec![],
+            sort: LibrarySort::default(),
             library_filtered: vec![],
             selected,
+            select_anchor: None,
+            grid: false,
+            grid_expanded: None,
             // - - -
+            search_query: String::new(),
+            search_query_typed: (String::new(), Instant::now()),
+            search_matches: None,
+            focus_list: false,
+            focus_song: None,
             search_artist: String::new(),
             search_artist_regex: None,
             search_album: String::new(),
@@ -260,6 +348,85 @@ impl LibraryBrowser {
         })
     }
 }
+impl LibraryBrowser {
+    /// changes the selection after `item` was clicked:
+    /// ctrl toggles it, shift selects everything from the previously clicked item to this one
+    /// (ctrl+shift keeps the previous selection), and a plain click selects only this item,
+    /// or nothing if it was the only selected item.
+    pub fn click(&mut self, item: Item, modifiers: &ModifiersState) {
+        if modifiers.shift() {
+            if let Some(anchor) = self.select_anchor {
+                let items = self.shown_items();
+                let a = items.iter().position(|v| *v == anchor);
+                let b = items.iter().position(|v| *v == item);
+                if let (Some(a), Some(b)) = (a, b) {
+                    if !modifiers.ctrl() {
+                        self.selected.clear();
+                    }
+                    for item in &items[a.min(b)..=a.max(b)] {
+                        self.selected.insert(*item);
+                    }
+                    return;
+                }
+            }
+        }
+        self.select_anchor = Some(item);
+        if modifiers.ctrl() {
+            if !self.selected.remove(item) {
+                self.selected.insert(item);
+            }
+        } else {
+            let was_only = self.selected.len() == 1 && self.selected.contains(item);
+            self.selected.clear();
+            if !was_only {
+                self.selected.insert(item);
+            }
+        }
+    }
+    /// the artists, albums and songs in the order in which they are shown
+    fn shown_items(&self) -> Vec<Item> {
+        let mut out = vec![];
+        for (artist, singles, albums, _) in &self.library_filtered {
+            if !self.grid {
+                out.push(Item::Artist(*artist));
+                out.extend(singles.iter().map(|(id, _)| Item::Song(*id)));
+            }
+            for (album, songs, _) in albums {
+                out.push(Item::Album(*album));
+                if !self.grid || self.grid_expanded == Some(*album) {
+                    out.extend(songs.iter().map(|(id, _)| Item::Song(*id)));
+                }
+            }
+        }
+        out
+    }
+    /// deselects everything which was filtered out
+    fn deselect_hidden(&mut self) {
+        if self.selected.len() == 0 && self.select_anchor.is_none() {
+            return;
+        }
+        let mut artists = HashSet::new();
+        let mut albums = HashSet::new();
+        let mut songs = HashSet::new();
+        for (artist, singles, albs, _) in &self.library_filtered {
+            artists.insert(*artist);
+            songs.extend(singles.iter().map(|(id, _)| *id));
+            for (album, s, _) in albs {
+                albums.insert(*album);
+                songs.extend(s.iter().map(|(id, _)| *id));
+            }
+        }
+        let shown = |item| match item {
+            Item::Artist(id) => artists.contains(&id),
+            Item::Album(id) => albums.contains(&id),
+            Item::Song(id) => songs.contains(&id),
+        };
+        self.selected.retain(shown);
+        if self.select_anchor.is_some_and(|item| !shown(item)) {
+            self.select_anchor = None;
+        }
+    }
+}
 impl GuiElem for LibraryBrowser {
     fn config(&self) -> &GuiElemCfg {
         &self.config
@@ -270,11 +437,13 @@ impl GuiElem for LibraryBrowser {
     fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
         Box::new(
             [
+                self.c_search.elem_mut(),
                 self.c_search_artist.elem_mut(),
                 self.c_search_album.elem_mut(),
                 self.c_search_song.elem_mut(),
                 self.c_scroll_box.elem_mut(),
                 self.c_filter_button.elem_mut(),
+                self.c_view_button.elem_mut(),
                 self.c_filter_panel.elem_mut(),
                 self.c_selected_counter_panel.elem_mut(),
             ]
@@ -307,6 +476,23 @@ impl GuiElem for LibraryBrowser {
         // search
         let mut search_changed = false;
         let mut rebuild_regex = false;
+        let mut search_query_changed = false;
+        {
+            let typed = self.c_search.c_input.content.get_text();
+            if *typed != self.search_query_typed.0 {
+                self.search_query_typed = (typed.clone(), Instant::now());
+            }
+            if self.search_query_typed.0 != self.search_query {
+                // don't wait if the user wants to see the results now
+                if self.focus_list || self.search_query_typed.1.elapsed() >= SEARCH_DEBOUNCE {
+                    self.search_query = self.search_query_typed.0.clone();
+                    search_query_changed = true;
+                    search_changed = true;
+                } else if let Some(h) = &info.helper {
+                    h.request_redraw();
+                }
+            }
+        }
         if self
             .search_settings_changed
             .load(std::sync::atomic::Ordering::Relaxed)
@@ -396,9 +582,19 @@ impl GuiElem for LibraryBrowser {
         // -
         if self.library_updated {
             self.library_updated = false;
-            self.update_local_library(&info.database, |(_, a), (_, b)| a.name.cmp(&b.name));
+            self.sort = info.gui_config.library_sort;
+            self.update_local_library(&info.database);
+            search_changed = true;
+            search_query_changed = true;
+        } else if self.sort != info.gui_config.library_sort {
+            self.sort = info.gui_config.library_sort;
+            self.update_local_library(&info.database);
             search_changed = true;
         }
+        if search_query_changed {
+            self.search_matches = SearchMatches::new(&info.database, &self.search_query);
+            self.c_scroll_box.scroll_target = 0.0;
+        }
         if search_changed {
             fn filter(
                 s: &LibraryBrowser,
@@ -406,9 +602,10 @@ impl GuiElem for LibraryBrowser {
                 regex: &Option<Regex>,
                 search_text: &String,
                 filter: &Filter,
-                search_gd: &GeneralData,
+                item: FilterItem,
+                db: &Database,
             ) -> f32 {
-                if !filter.passes(search_gd) {
+                if !filter.passes(&item, db) {
                     return 0.0;
                 };
                 if let Some(r) = regex {
@@ -447,8 +644,9 @@ impl GuiElem for LibraryBrowser {
             }
             let allow_singles = self.search_album.is_empty()
                 && self.filter_albums.lock().unwrap().filters.is_empty();
+            let db = &info.database;
             self.filter_local_library(
-                &info.database,
+                db,
                 |s, artist| {
                     filter(
                         s,
@@ -456,8 +654,12 @@ impl GuiElem for LibraryBrowser {
                         &s.search_artist_regex,
                         &s.search_artist,
                         &s.filter_artists.lock().unwrap(),
-                        &artist.general,
-                    )
+                        FilterItem::Artist(artist),
+                        db,
+                    ) * s
+                        .search_matches
+                        .as_ref()
+                        .map_or(1.0, |m| m.artists.get(&artist.id).copied().unwrap_or(1.0))
                 },
                 |s, album| {
                     filter(
@@ -466,8 +668,12 @@ impl GuiElem for LibraryBrowser {
                         &s.search_album_regex,
                         &s.search_album,
                         &s.filter_albums.lock().unwrap(),
-                        &album.general,
-                    )
+                        FilterItem::Album(album),
+                        db,
+                    ) * s
+                        .search_matches
+                        .as_ref()
+                        .map_or(1.0, |m| m.albums.get(&album.id).copied().unwrap_or(1.0))
                 },
                 |s, song| {
                     if song.album.is_some() || allow_singles {
@@ -477,13 +683,18 @@ impl GuiElem for LibraryBrowser {
                             &s.search_song_regex,
                             &s.search_song,
                             &s.filter_songs.lock().unwrap(),
-                            &song.general,
-                        )
+                            FilterItem::Song(song),
+                            db,
+                        ) * s
+                            .search_matches
+                            .as_ref()
+                            .map_or(1.0, |m| m.songs.get(&song.id).copied().unwrap_or(0.0))
                     } else {
                         0.0
                     }
                 },
             );
+            self.deselect_hidden();
             // selected
             {
                 let (artists, albums, songs) = self
@@ -545,7 +756,7 @@ impl GuiElem for LibraryBrowser {
                                 Some(format!("{s} songs, {al} albums and {ar} artists selected"))
                             }
                         } {
-                            *self.c_selected_counter_panel.children[0].content.text() = text;
+                            *self.c_selected_counter_panel.children.0.content.text() = text;
                         }
                     } else {
                     }
@@ -588,9 +799,45 @@ impl GuiElem for LibraryBrowser {
                 }
             }
         }
+        if self.grid != info.gui_config.library_grid {
+            self.grid = info.gui_config.library_grid;
+            self.grid_expanded = None;
+            *self.c_view_button.children[0].content.text() =
+                if self.grid { "list" } else { "grid" }.to_owned();
+            self.c_scroll_box.scroll_target = 0.0;
+            self.config.redraw = true;
+        }
         if self.config.redraw || info.pos.size() != self.config.pixel_pos.size() {
             self.config.redraw = false;
-            self.update_ui(&info.database, info.line_height);
+            if self.grid {
+                self.update_ui_grid(info.database, info.line_height, info.pos.width());
+            } else {
+                self.update_ui(&info.database, info.line_height);
+            }
+        }
+        if self.focus_list {
+            self.focus_list = false;
+            let index = self
+                .focus_song
+                .take()
+                .and_then(|id| {
+                    self.c_scroll_box
+                        .children
+                        .as_slice()
+                        .iter()
+                        .position(|e| matches!(e, ListElement::Song(s) if s.id == id))
+                })
+                .unwrap_or(0);
+            if let Some(elem) = self.c_scroll_box.children.get_mut(index) {
+                match elem {
+                    ListElement::AlbumRow(row) => {
+                        row.children[0].config_mut().request_keyboard_focus = true
+                    }
+                    elem => elem.config_mut().request_keyboard_focus = true,
+                }
+                self.c_scroll_box.scroll_into_view(index);
+                info.actions.push(GuiAction::ResetKeyboardFocus);
+            }
         }
     }
     fn updated_library(&mut self) {
@@ -616,36 +863,80 @@ impl GuiElem for LibraryBrowser {
         if down && crate::gui::hotkey_select_songs(&modifiers, key) && e.take() {
             self.selected_add_songs();
         }
+        // the search field is the first child
+        if down && self.config.keyboard_focus_index == 0 {
+            match key {
+                // clear the search and go back to the list
+                Some(VirtualKeyCode::Escape) if e.take() => {
+                    self.c_search.set_text(String::new());
+                    self.focus_list = true;
+                }
+                // go to the results
+                Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter) if e.take() => {
+                    self.focus_list = true;
+                }
+                _ => {}
+            }
+        }
         vec![]
     }
 }
 impl LibraryBrowser {
+    /// Searches for this artist (and album), so that only they are shown.
+    pub fn show(&mut self, artist: &str, album: Option<&str>) {
+        let exact = |name: &str| format!("^{}$", regex::escape(name));
+        self.c_search.set_text(String::new());
+        self.c_search_artist.set_text(exact(artist));
+        self.c_search_album
+            .set_text(album.map(exact).unwrap_or_default());
+        self.c_search_song.set_text(String::new());
+    }
+    /// Shows the song's artist and album, then focuses the song and scrolls to it.
+    pub fn show_song(&mut self, db: &Database, song: &Song) {
+        let album = song.album.as_ref().and_then(|id| db.albums().get(id));
+        let artist = album.map_or(song.artist, |album| album.artist);
+        if let Some(artist) = db.artists().get(&artist) {
+            self.show(&artist.name, album.map(|album| album.name.as_str()));
+        }
+        self.grid_expanded = song.album;
+        self.focus_list = true;
+        self.focus_song = Some(song.id);
+        self.config.redraw = true;
+    }
     /// Sets `self.library_sorted` based on the contents of the `Database`.
-    fn update_local_library(
-        &mut self,
-        db: &Database,
-        sort_artists: impl FnMut(&(&ArtistId, &Artist), &(&ArtistId, &Artist)) -> Ordering,
-    ) {
-        let mut artists = db.artists().iter().collect::<Vec<_>>();
-        artists.sort_unstable_by(sort_artists);
+    fn update_local_library(&mut self, db: &Database) {
+        let sort = self.sort;
+        let sorted_songs = |songs: &[SongId]| {
+            let mut songs = songs
+                .iter()
+                .filter_map(|id| db.songs().get(id))
+                .collect::<Vec<_>>();
+            songs.sort_by_cached_key(|song| sort.song_key(song));
+            songs.into_iter().map(|song| song.id).collect::<Vec<_>>()
+        };
+        let mut artists = db.artists().values().collect::<Vec<_>>();
+        artists.sort_by_cached_key(|artist| sort.artist_key(artist));
         self.library_sorted = artists
             .into_iter()
-            .map(|(ar_id, artist)| {
-                let singles = artist.singles.iter().map(|id| *id).collect();
-                let albums = artist
+            .map(|artist| {
+                let singles = sorted_songs(&artist.singles);
+                let mut albums = artist
                     .albums
                     .iter()
-                    .map(|id| {
-                        let songs = if let Some(album) = db.albums().get(id) {
-                            album.songs.iter().map(|id| *id).collect()
-                        } else {
+                    .filter_map(|id| {
+                        let album = db.albums().get(id);
+                        if album.is_none() {
                             eprintln!("[warn] No album with id {id} found in db!");
-                            vec![]
-                        };
-                        (*id, songs)
+                        }
+                        album
                     })
+                    .collect::<Vec<_>>();
+                albums.sort_by_cached_key(|album| sort.album_key(album, db));
+                let albums = albums
+                    .into_iter()
+                    .map(|album| (album.id, sorted_songs(&album.songs)))
                     .collect();
-                (*ar_id, singles, albums)
+                (artist.id, singles, albums)
             })
             .collect();
     }
@@ -764,6 +1055,59 @@ impl LibraryBrowser {
         library_scroll_box.children_heights = elemh;
         library_scroll_box.config_mut().redraw = true;
     }
+    /// Like `update_ui`, but shows the albums as rows of covers.
+    /// The songs of `self.grid_expanded` are shown below its row.
+    fn update_ui_grid(&mut self, db: &Database, line_height: f32, width: f32) {
+        let columns = ((width / (line_height * GRID_TILE_WIDTH)) as usize).max(1);
+        let height = width / columns as f32 + line_height * 2.0;
+        let albums = self
+            .library_filtered
+            .iter()
+            .flat_map(|(_, _, albums, _)| albums)
+            .collect::<Vec<_>>();
+        let mut elems = vec![];
+        let mut elemh = vec![];
+        for row in albums.chunks(columns) {
+            let tiles = row
+                .iter()
+                .enumerate()
+                .map(|(i, (id, _, _))| {
+                    GridAlbum::new(
+                        GuiElemCfg::at(Rectangle::from_tuples(
+                            (i as f32 / columns as f32, 0.0),
+                            ((i + 1) as f32 / columns as f32, 1.0),
+                        )),
+                        *id,
+                        db,
+                        line_height / height,
+                        self,
+                    )
+                })
+                .collect();
+            elems.push(ListElement::AlbumRow(GridRow {
+                config: GuiElemCfg::default(),
+                children: tiles,
+            }));
+            elemh.push(height);
+            if let Some((_, songs, _)) = row.iter().find(|(id, ..)| self.grid_expanded == Some(*id))
+            {
+                for (song_id, _song_filterscore) in songs {
+                    let (e, h) = self.build_ui_element_song(*song_id, db, line_height);
+                    elems.push(e);
+                    elemh.push(h);
+                }
+            }
+        }
+        let library_scroll_box = &mut self.c_scroll_box;
+        library_scroll_box.children = elems;
+        library_scroll_box.children_heights = elemh;
+        library_scroll_box.config_mut().redraw = true;
+    }
+    fn search_words(&self) -> &[String] {
+        self.search_matches
+            .as_ref()
+            .map_or(&[], |m| m.words.as_slice())
+    }
     fn build_ui_element_artist(&self, id: ArtistId, db: &Database, h: f32) -> (ListElement, f32) {
         (
             ListElement::Artist(ListArtist::new(
@@ -774,6 +1118,7 @@ impl LibraryBrowser {
                 } else {
                     format!("[ Artist #{id} ]")
                 },
+                self.search_words(),
                 self.selected.clone(),
             )),
             h * 2.5,
@@ -810,6 +1155,7 @@ impl LibraryBrowser {
                 id,
                 name,
                 duration,
+                self.search_words(),
                 self.selected.clone(),
             )),
             h * 1.5,
@@ -831,6 +1177,7 @@ impl LibraryBrowser {
                 id,
                 name,
                 duration,
+                self.search_words(),
                 self.selected.clone(),
             )),
             h,
@@ -842,6 +1189,7 @@ pub enum ListElement {
     Artist(ListArtist),
     Album(ListAlbum),
     Song(ListSong),
+    AlbumRow(GridRow),
 }
 impl GuiElemWrapper for ListElement {
     fn as_elem(&self) -> &dyn GuiElem {
@@ -849,6 +1197,7 @@ impl GuiElemWrapper for ListElement {
             Self::Artist(v) => v,
             Self::Album(v) => v,
             Self::Song(v) => v,
+            Self::AlbumRow(v) => v,
         }
     }
     fn as_elem_mut(&mut self) -> &mut dyn GuiElem {
@@ -856,6 +1205,7 @@ impl GuiElemWrapper for ListElement {
             Self::Artist(v) => v,
             Self::Album(v) => v,
             Self::Song(v) => v,
+            Self::AlbumRow(v) => v,
         }
     }
 }
@@ -870,17 +1220,25 @@ pub struct ListArtist {
     sel: bool,
 }
 impl ListArtist {
-    pub fn new(mut config: GuiElemCfg, id: ArtistId, name: String, selected: Selected) -> Self {
-        let label = Label::new(
+    pub fn new(
+        mut config: GuiElemCfg,
+        id: ArtistId,
+        name: String,
+        search_words: &[String],
+        selected: Selected,
+    ) -> Self {
+        let label = AdvancedLabel::new(
             GuiElemCfg::default(),
-            name,
-            Color::from_int_rgb(81, 24, 125),
-            None,
             Vec2::new(0.0, 0.5),
+            vec![highlighted(
+                name,
+                Color::from_int_rgb(81, 24, 125),
+                search_words,
+            )],
         );
         config.redraw = true;
         Self {
-            config: config.w_mouse(),
+            config: config.w_mouse().w_keyboard_focus(),
             id,
             children: vec![Box::new(label)],
             mouse: false,
@@ -912,7 +1270,8 @@ impl GuiElem for ListArtist {
     fn elem_mut(&mut self) -> &mut dyn GuiElem {
         self
     }
-    fn draw(&mut self, info: &mut DrawInfo, _g: &mut speedy2d::Graphics2D) {
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
+        draw_keyboard_focus(info, g);
         if self.config.redraw {
             self.config.redraw = false;
             let sel = self.selected.contains_artist(&self.id);
@@ -942,7 +1301,7 @@ impl GuiElem for ListArtist {
                             &gui.database.lock().unwrap(),
                         );
                         gui.exec_gui_action(GuiAction::SetDragging(Some((
-                            Dragging::Queues(q),
+                            Dragging::Queues(Ok(q)),
                             None,
                         ))));
                     })));
@@ -954,6 +1313,17 @@ impl GuiElem for ListArtist {
             info.mouse_pos.y - info.pos.top_left().y,
         );
     }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: speedy2d::window::ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        let id = self.id;
+        enter_adds_to_queue(e, down, key, move |db| add_to_queue_artist_by_id(id, db))
+    }
     fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
         if button == MouseButton::Left && e.take() {
             self.mouse = true;
@@ -974,15 +1344,46 @@ impl GuiElem for ListArtist {
             self.mouse = false;
             self.config.redraw = true;
             if e.take() {
-                if !self.sel {
-                    self.selected.insert_artist(self.id);
-                } else {
-                    self.selected.remove_artist(&self.id);
-                }
+                return click_item(Item::Artist(self.id));
             }
         }
         vec![]
     }
+    fn mouse_pressed(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if button == MouseButton::Right && e.take() {
+            let id = self.id;
+            vec![GuiAction::Build(Box::new(move |db| {
+                let Some(artist) = db.artists().get(&id) else {
+                    return vec![];
+                };
+                let name = artist.name.clone();
+                vec![GuiAction::ContextMenu(Some(vec![
+                    menu_entry_queue("Play next", move |db| {
+                        add_to_queue_artist_by_id(id, db).map(Action::QueuePlayNext)
+                    }),
+                    menu_entry_queue("Add to end of queue", move |db| {
+                        add_to_queue_artist_by_id(id, db).map(queue_add)
+                    }),
+                    menu_entry_queue("Add as folder", move |db| {
+                        artist_as_folder(id, db).map(queue_add)
+                    }),
+                    menu_entry("Show artist", {
+                        let name = name.clone();
+                        move || show_in_library(name.clone(), None)
+                    }),
+                    menu_entry("Edit", move || {
+                        vec![GuiAction::Edit(EditTarget::Artist(id))]
+                    }),
+                    menu_entry_remove(
+                        format!("Remove {name} and all their songs?"),
+                        Action::RemoveArtist(id),
+                    ),
+                ]))]
+            }))]
+        } else {
+            vec![]
+        }
+    }
 }
 
 pub struct ListAlbum {
@@ -1000,33 +1401,19 @@ impl ListAlbum {
         id: AlbumId,
         name: String,
         half_sized_info: String,
+        search_words: &[String],
         selected: Selected,
     ) -> Self {
-        let label = AdvancedLabel::new(
-            GuiElemCfg::default(),
-            Vec2::new(0.0, 0.5),
-            vec![vec![
-                (
-                    gui_text::AdvancedContent::Text(gui_text::Content::new(
-                        name,
-                        Color::from_int_rgb(8, 61, 47),
-                    )),
-                    1.0,
-                    1.0,
-                ),
-                (
-                    gui_text::AdvancedContent::Text(gui_text::Content::new(
-                        half_sized_info,
-                        Color::GRAY,
-                    )),
-                    0.5,
-                    1.0,
-                ),
-            ]],
-        );
+        let mut line = highlighted(name, Color::from_int_rgb(8, 61, 47), search_words);
+        line.push((
+            gui_text::AdvancedContent::Text(gui_text::Content::new(half_sized_info, Color::GRAY)),
+            0.5,
+            1.0,
+        ));
+        let label = AdvancedLabel::new(GuiElemCfg::default(), Vec2::new(0.0, 0.5), vec![line]);
         config.redraw = true;
         Self {
-            config: config.w_mouse(),
+            config: config.w_mouse().w_keyboard_focus(),
             id,
             children: vec![Box::new(label)],
             mouse: false,
@@ -1058,7 +1445,8 @@ impl GuiElem for ListAlbum {
     fn elem_mut(&mut self) -> &mut dyn GuiElem {
         self
     }
-    fn draw(&mut self, info: &mut DrawInfo, _g: &mut speedy2d::Graphics2D) {
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
+        draw_keyboard_focus(info, g);
         if self.config.redraw {
             self.config.redraw = false;
             let sel = self.selected.contains_album(&self.id);
@@ -1088,7 +1476,7 @@ impl GuiElem for ListAlbum {
                             &gui.database.lock().unwrap(),
                         );
                         gui.exec_gui_action(GuiAction::SetDragging(Some((
-                            Dragging::Queues(q),
+                            Dragging::Queues(Ok(q)),
                             None,
                         ))));
                     })));
@@ -1100,6 +1488,17 @@ impl GuiElem for ListAlbum {
             info.mouse_pos.y - info.pos.top_left().y,
         );
     }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: speedy2d::window::ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        let id = self.id;
+        enter_adds_to_queue(e, down, key, move |db| add_to_queue_album_by_id(id, db))
+    }
     fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
         if button == MouseButton::Left && e.take() {
             self.mouse = true;
@@ -1120,66 +1519,154 @@ impl GuiElem for ListAlbum {
             self.mouse = false;
             self.config.redraw = true;
             if e.take() {
-                if !self.sel {
-                    self.selected.insert_album(self.id);
-                } else {
-                    self.selected.remove_album(&self.id);
-                }
+                return click_item(Item::Album(self.id));
             }
         }
         vec![]
     }
+    fn mouse_pressed(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if button == MouseButton::Right && e.take() {
+            album_context_menu(self.id)
+        } else {
+            vec![]
+        }
+    }
 }
 
-pub struct ListSong {
+fn album_context_menu(id: AlbumId) -> Vec<GuiAction> {
+    vec![GuiAction::Build(Box::new(move |db| {
+        let Some(album) = db.albums().get(&id) else {
+            return vec![];
+        };
+        let name = album.name.clone();
+        let artist = db
+            .artists()
+            .get(&album.artist)
+            .map(|artist| artist.name.clone());
+        let mut entries = vec![
+            menu_entry_queue("Play next", move |db| {
+                add_to_queue_album_by_id(id, db).map(Action::QueuePlayNext)
+            }),
+            menu_entry_queue("Add to end of queue", move |db| {
+                add_to_queue_album_by_id(id, db).map(queue_add)
+            }),
+            menu_entry_queue("Add as folder", move |db| {
+                album_as_folder(id, db).map(queue_add)
+            }),
+        ];
+        if let Some(artist) = artist {
+            entries.push(menu_entry("Show album", {
+                let (artist, name) = (artist.clone(), name.clone());
+                move || show_in_library(artist.clone(), Some(name.clone()))
+            }));
+            entries.push(menu_entry("Show artist", move || {
+                show_in_library(artist.clone(), None)
+            }));
+        }
+        entries.push(menu_entry("Edit", move || {
+            vec![GuiAction::Edit(EditTarget::Album(id))]
+        }));
+        entries.push(menu_entry_remove(
+            format!("Remove {name} and its songs?"),
+            Action::RemoveAlbum(id),
+        ));
+        vec![GuiAction::ContextMenu(Some(entries))]
+    }))]
+}
+
+/// a row of albums in the library's grid view
+pub struct GridRow {
     config: GuiElemCfg,
-    id: SongId,
-    children: Vec<Box<dyn GuiElem>>,
+    children: Vec<GridAlbum>,
+}
+impl GuiElem for GridRow {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new(self.children.iter_mut().map(|v| v.elem_mut()))
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+}
+
+/// An album's cover with its name and artist below it.
+/// Clicking it shows or hides its songs, dragging it works like `ListAlbum`.
+pub struct GridAlbum {
+    config: GuiElemCfg,
+    id: AlbumId,
+    cover: Option<CoverId>,
+    c_name: AdvancedLabel,
+    c_artist: Label,
     mouse: bool,
-    mouse_pos: Vec2,
     selected: Selected,
     sel: bool,
+    expanded: bool,
 }
-impl ListSong {
+impl GridAlbum {
+    /// `line`: the height of one line relative to the height of the element
     pub fn new(
-        mut config: GuiElemCfg,
-        id: SongId,
-        name: String,
-        duration: String,
-        selected: Selected,
+        config: GuiElemCfg,
+        id: AlbumId,
+        db: &Database,
+        line: f32,
+        lb: &LibraryBrowser,
     ) -> Self {
-        let label = AdvancedLabel::new(
-            GuiElemCfg::default(),
-            Vec2::new(0.0, 0.5),
-            vec![vec![
-                (
-                    gui_text::AdvancedContent::Text(gui_text::Content::new(
-                        name,
-                        Color::from_int_rgb(175, 175, 175),
-                    )),
-                    1.0,
-                    1.0,
-                ),
-                (
-                    gui_text::AdvancedContent::Text(gui_text::Content::new(duration, Color::GRAY)),
-                    0.6,
-                    1.0,
-                ),
-            ]],
-        );
-        config.redraw = true;
+        let album = db.albums().get(&id);
+        let name = album.map_or_else(|| format!("[ Album #{id} ]"), |v| v.name.clone());
+        let artist = album
+            .and_then(|v| db.artists().get(&v.artist))
+            .map_or_else(String::new, |v| v.name.clone());
+        // albums without a cover use their first song's cover
+        let cover = album.and_then(|v| {
+            v.cover.or_else(|| {
+                v.songs
+                    .iter()
+                    .filter_map(|id| db.get_song(id))
+                    .find_map(|song| song.cover)
+            })
+        });
         Self {
-            config: config.w_mouse(),
+            config: config.w_mouse().w_keyboard_focus(),
             id,
-            children: vec![Box::new(label)],
+            cover,
+            c_name: AdvancedLabel::new(
+                GuiElemCfg::at(Rectangle::from_tuples(
+                    (0.05, 1.0 - 2.0 * line),
+                    (0.95, 1.0 - line),
+                )),
+                Vec2::new(0.5, 0.5),
+                vec![highlighted(name, Color::WHITE, lb.search_words())],
+            ),
+            c_artist: Label::new(
+                GuiElemCfg::at(Rectangle::from_tuples((0.05, 1.0 - line), (0.95, 1.0))),
+                artist,
+                Color::GRAY,
+                None,
+                Vec2::new(0.5, 0.5),
+            )
+            .with_layout(TextLayoutMode::SingleLineEllipsis),
             mouse: false,
-            mouse_pos: Vec2::ZERO,
-            selected,
+            selected: lb.selected.clone(),
             sel: false,
+            expanded: lb.grid_expanded == Some(id),
         }
     }
 }
-impl GuiElem for ListSong {
+impl GuiElem for GridAlbum {
     fn config(&self) -> &GuiElemCfg {
         &self.config
     }
@@ -1187,7 +1674,7 @@ impl GuiElem for ListSong {
         &mut self.config
     }
     fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
-        Box::new(self.children.iter_mut().map(|v| v.elem_mut()))
+        Box::new([self.c_name.elem_mut(), self.c_artist.elem_mut()].into_iter())
     }
     fn any(&self) -> &dyn std::any::Any {
         self
@@ -1201,27 +1688,192 @@ impl GuiElem for ListSong {
     fn elem_mut(&mut self) -> &mut dyn GuiElem {
         self
     }
-    fn draw(&mut self, info: &mut DrawInfo, _g: &mut speedy2d::Graphics2D) {
-        if self.config.redraw {
-            self.config.redraw = false;
-            let sel = self.selected.contains_song(&self.id);
-            if sel != self.sel {
-                self.sel = sel;
-                if sel {
-                    self.children.push(Box::new(Panel::with_background(
-                        GuiElemCfg::default(),
-                        (),
-                        Color::from_rgba(1.0, 1.0, 1.0, 0.2),
-                    )));
-                } else {
-                    self.children.pop();
-                }
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
+        draw_keyboard_focus(info, g);
+        self.sel = self.selected.contains_album(&self.id);
+        // the cover, as a square above the labels
+        let pos = &info.pos;
+        let size = pos.width().min(pos.height() - 2.0 * info.line_height) * 0.9;
+        let area = Rectangle::from_tuples(
+            (
+                pos.top_left().x + (pos.width() - size) / 2.0,
+                pos.top_left().y + pos.width() * 0.05,
+            ),
+            (
+                pos.top_left().x + (pos.width() + size) / 2.0,
+                pos.top_left().y + pos.width() * 0.05 + size,
+            ),
+        );
+        g.draw_rectangle(area.clone(), info.gui_config.theme.button);
+        if let Some(cover) = self.cover {
+            crate::gui::draw_thumbnail(info, g, cover, &area);
+        }
+        if self.expanded {
+            let p = &area;
+            let c = info.gui_config.theme.focus;
+            g.draw_line(p.top_left(), p.top_right(), 2.0, c);
+            g.draw_line(p.bottom_left(), p.bottom_right(), 2.0, c);
+            g.draw_line(p.top_left(), p.bottom_left(), 2.0, c);
+            g.draw_line(p.top_right(), p.bottom_right(), 2.0, c);
+        }
+        if self.sel {
+            g.draw_rectangle(info.pos.clone(), info.gui_config.theme.highlight);
+        }
+        if self.mouse && !info.pos.contains(info.mouse_pos) {
+            self.mouse = false;
+            if self.sel {
+                let selected = self.selected.clone();
+                info.actions.push(GuiAction::Do(Box::new(move |gui| {
+                    let q = selected.as_queue(
+                        &gui.gui.c_main_view.children.library_browser,
+                        &gui.database.lock().unwrap(),
+                    );
+                    gui.exec_gui_action(GuiAction::SetDragging(Some((
+                        Dragging::Queues(Ok(q)),
+                        None,
+                    ))));
+                })));
             }
         }
-        if self.mouse {
-            if info.pos.contains(info.mouse_pos) {
-                return;
-            } else {
+    }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: speedy2d::window::ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        let id = self.id;
+        enter_adds_to_queue(e, down, key, move |db| add_to_queue_album_by_id(id, db))
+    }
+    fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if button == MouseButton::Left && e.take() {
+            self.mouse = true;
+            if self.sel {
+                vec![]
+            } else {
+                vec![GuiAction::SetDragging(Some((
+                    Dragging::Album(self.id),
+                    None,
+                )))]
+            }
+        } else {
+            vec![]
+        }
+    }
+    fn mouse_up(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if self.mouse && button == MouseButton::Left {
+            self.mouse = false;
+            if e.take() {
+                let id = self.id;
+                return vec![GuiAction::Do(Box::new(move |gui| {
+                    let lb = &mut gui.gui.c_main_view.children.library_browser;
+                    if gui.modifiers.ctrl() || gui.modifiers.shift() {
+                        lb.click(Item::Album(id), &gui.modifiers);
+                    } else {
+                        lb.grid_expanded = if lb.grid_expanded == Some(id) {
+                            None
+                        } else {
+                            Some(id)
+                        };
+                        lb.config.redraw = true;
+                    }
+                }))];
+            }
+        }
+        vec![]
+    }
+    fn mouse_pressed(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if button == MouseButton::Right && e.take() {
+            album_context_menu(self.id)
+        } else {
+            vec![]
+        }
+    }
+}
+
+pub struct ListSong {
+    config: GuiElemCfg,
+    id: SongId,
+    children: Vec<Box<dyn GuiElem>>,
+    mouse: bool,
+    mouse_pos: Vec2,
+    selected: Selected,
+    sel: bool,
+}
+impl ListSong {
+    pub fn new(
+        mut config: GuiElemCfg,
+        id: SongId,
+        name: String,
+        duration: String,
+        search_words: &[String],
+        selected: Selected,
+    ) -> Self {
+        let mut line = highlighted(name, Color::from_int_rgb(175, 175, 175), search_words);
+        line.push((
+            gui_text::AdvancedContent::Text(gui_text::Content::new(duration, Color::GRAY)),
+            0.6,
+            1.0,
+        ));
+        let label = AdvancedLabel::new(GuiElemCfg::default(), Vec2::new(0.0, 0.5), vec![line]);
+        config.redraw = true;
+        Self {
+            config: config.w_mouse().w_keyboard_focus(),
+            id,
+            children: vec![Box::new(label)],
+            mouse: false,
+            mouse_pos: Vec2::ZERO,
+            selected,
+            sel: false,
+        }
+    }
+}
+impl GuiElem for ListSong {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new(self.children.iter_mut().map(|v| v.elem_mut()))
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
+        draw_keyboard_focus(info, g);
+        if self.config.redraw {
+            self.config.redraw = false;
+            let sel = self.selected.contains_song(&self.id);
+            if sel != self.sel {
+                self.sel = sel;
+                if sel {
+                    self.children.push(Box::new(Panel::with_background(
+                        GuiElemCfg::default(),
+                        (),
+                        Color::from_rgba(1.0, 1.0, 1.0, 0.2),
+                    )));
+                } else {
+                    self.children.pop();
+                }
+            }
+        }
+        if self.mouse {
+            if info.pos.contains(info.mouse_pos) {
+                return;
+            } else {
                 self.mouse = false;
                 if self.sel {
                     let selected = self.selected.clone();
@@ -1231,7 +1883,7 @@ impl GuiElem for ListSong {
                             &gui.database.lock().unwrap(),
                         );
                         gui.exec_gui_action(GuiAction::SetDragging(Some((
-                            Dragging::Queues(q),
+                            Dragging::Queues(Ok(q)),
                             None,
                         ))));
                     })));
@@ -1243,6 +1895,17 @@ impl GuiElem for ListSong {
             info.mouse_pos.y - info.pos.top_left().y,
         );
     }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: speedy2d::window::ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        let id = self.id;
+        enter_adds_to_queue(e, down, key, move |_| Some(QueueContent::Song(id).into()))
+    }
     fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
         if button == MouseButton::Left && e.take() {
             self.mouse = true;
@@ -1263,11 +1926,7 @@ impl GuiElem for ListSong {
             self.mouse = false;
             self.config.redraw = true;
             if e.take() {
-                if !self.sel {
-                    self.selected.insert_song(self.id);
-                } else {
-                    self.selected.remove_song(&self.id);
-                }
+                return click_item(Item::Song(self.id));
             }
         }
         vec![]
@@ -1276,22 +1935,46 @@ impl GuiElem for ListSong {
         if button == MouseButton::Right && e.take() {
             let id = self.id;
             vec![GuiAction::Build(Box::new(move |db| {
-                if let Some(me) = db.songs().get(&id) {
-                    let me = me.clone();
-                    vec![GuiAction::ContextMenu(Some(vec![Box::new(Button::new(
-                        GuiElemCfg::default(),
-                        move |_| vec![GuiAction::EditSongs(vec![me.clone()])],
-                        [Label::new(
-                            GuiElemCfg::default(),
-                            format!("Edit"),
-                            Color::WHITE,
-                            None,
-                            Vec2::new_y(0.5),
-                        )],
-                    ))]))]
-                } else {
-                    vec![]
+                let Some(me) = db.songs().get(&id) else {
+                    return vec![];
+                };
+                let me = me.clone();
+                let album = me
+                    .album
+                    .and_then(|id| db.albums().get(&id))
+                    .map(|album| album.name.clone());
+                let artist = db
+                    .artists()
+                    .get(&me.artist)
+                    .map(|artist| artist.name.clone());
+                let mut entries = vec![
+                    menu_entry_queue("Play next", move |_| {
+                        Some(Action::QueuePlayNext(QueueContent::Song(id).into()))
+                    }),
+                    menu_entry_queue("Add to end of queue", move |_| {
+                        Some(queue_add(QueueContent::Song(id).into()))
+                    }),
+                ];
+                if let Some(artist) = artist {
+                    if let Some(album) = album {
+                        entries.push(menu_entry("Show album", {
+                            let artist = artist.clone();
+                            move || show_in_library(artist.clone(), Some(album.clone()))
+                        }));
+                    }
+                    entries.push(menu_entry("Show artist", move || {
+                        show_in_library(artist.clone(), None)
+                    }));
                 }
+                let title = me.title.clone();
+                entries.push(menu_entry("Edit", move || {
+                    vec![GuiAction::Edit(EditTarget::Song(id))]
+                }));
+                entries.push(menu_entry_remove(
+                    format!("Remove {title}?"),
+                    Action::RemoveSong(id),
+                ));
+                vec![GuiAction::ContextMenu(Some(entries))]
             }))]
         } else {
             vec![]
@@ -1299,6 +1982,348 @@ impl GuiElem for ListSong {
     }
 }
 
+/// a left click on an item in the list, see `LibraryBrowser::click`
+fn click_item(item: Item) -> Vec<GuiAction> {
+    vec![GuiAction::Do(Box::new(move |gui| {
+        gui.gui
+            .c_main_view
+            .children
+            .library_browser
+            .click(item, &gui.modifiers)
+    }))]
+}
+
+/// an entry in a context menu
+pub fn menu_entry(text: &str, action: impl Fn() -> Vec<GuiAction> + 'static) -> Box<dyn GuiElem> {
+    Box::new(Button::new(
+        GuiElemCfg::default(),
+        move |_| action(),
+        [Label::new(
+            GuiElemCfg::default(),
+            text.to_owned(),
+            Color::WHITE,
+            None,
+            Vec2::new_y(0.5),
+        )],
+    ))
+}
+/// an entry in a context menu which sends an action to the server, if the element still exists
+fn menu_entry_queue(
+    text: &str,
+    action: impl Fn(&Database) -> Option<Action> + Clone + 'static,
+) -> Box<dyn GuiElem> {
+    menu_entry(text, move || {
+        let action = action.clone();
+        vec![GuiAction::Build(Box::new(move |db| {
+            action(db)
+                .map(GuiAction::SendToServer)
+                .into_iter()
+                .collect()
+        }))]
+    })
+}
+/// "Remove from library", which asks `question` in a second menu before removing anything
+fn menu_entry_remove(question: String, action: Action) -> Box<dyn GuiElem> {
+    menu_entry("Remove from library", move || {
+        let action = action.clone();
+        vec![GuiAction::ContextMenu(Some(vec![
+            Box::new(Label::new(
+                GuiElemCfg::default(),
+                question.clone(),
+                Color::LIGHT_GRAY,
+                None,
+                Vec2::new_y(0.5),
+            )),
+            // first, so it gets the keyboard focus
+            menu_entry("Cancel", Vec::new),
+            menu_entry("Remove", move || {
+                vec![GuiAction::SendToServer(action.clone())]
+            }),
+        ]))]
+    })
+}
+/// bulk actions for everything that is selected
+fn selection_menu(selected: Selected) -> GuiAction {
+    let (artists, albums, songs) = selected.view(|sel| sel.clone());
+    let count = artists.len() + albums.len() + songs.len();
+    if count == 0 {
+        return GuiAction::ContextMenu(None);
+    }
+    let remove = Action::Multiple(
+        songs
+            .iter()
+            .map(|id| Action::RemoveSong(*id))
+            .chain(albums.iter().map(|id| Action::RemoveAlbum(*id)))
+            .chain(artists.iter().map(|id| Action::RemoveArtist(*id)))
+            .collect(),
+    );
+    GuiAction::ContextMenu(Some(vec![
+        menu_entry("Add to end of queue", {
+            let selected = selected.clone();
+            move || {
+                let selected = selected.clone();
+                vec![GuiAction::Do(Box::new(move |gui| {
+                    let content = selected.as_queue(
+                        &gui.gui.c_main_view.children.library_browser,
+                        &gui.database.lock().unwrap(),
+                    );
+                    gui.exec_gui_action(GuiAction::SendToServer(queue_add(
+                        QueueContent::Folder(QueueFolder {
+                            index: 0,
+                            content,
+                            name: "Selection".to_owned(),
+                            order: None,
+                        })
+                        .into(),
+                    )));
+                }))]
+            }
+        }),
+        menu_entry("Add flag...", {
+            let selected = selected.clone();
+            move || vec![flag_menu(selected.clone())]
+        }),
+        menu_entry("Deselect all", move || {
+            selected.clear();
+            vec![]
+        }),
+        menu_entry_remove(
+            format!(
+                "Remove {count} selected item{} from the library?",
+                if count == 1 { "" } else { "s" }
+            ),
+            remove,
+        ),
+    ]))
+}
+/// asks for a flag, then adds it to everything that is selected
+fn flag_menu(selected: Selected) -> GuiAction {
+    let text = Rc::new(RefCell::new(String::new()));
+    let mut field = TextField::new_adv(
+        GuiElemCfg::default(),
+        String::new(),
+        "Flag".to_owned(),
+        Color::GRAY,
+        Color::WHITE,
+    );
+    field.on_changed = Some(Box::new({
+        let text = Rc::clone(&text);
+        move |new| *text.borrow_mut() = new.to_owned()
+    }));
+    GuiAction::ContextMenu(Some(vec![
+        Box::new(field),
+        menu_entry("Add flag", move || {
+            let flag = text.borrow().trim().to_owned();
+            if flag.is_empty() {
+                return vec![];
+            }
+            let action = selected.view(|(artists, albums, songs)| {
+                Action::Multiple(
+                    songs
+                        .iter()
+                        .map(|id| Action::TagSongFlagSet(*id, flag.clone()))
+                        .chain(
+                            albums
+                                .iter()
+                                .map(|id| Action::TagAlbumFlagSet(*id, flag.clone())),
+                        )
+                        .chain(
+                            artists
+                                .iter()
+                                .map(|id| Action::TagArtistFlagSet(*id, flag.clone())),
+                        )
+                        .collect(),
+                )
+            });
+            vec![GuiAction::SendToServer(action)]
+        }),
+        menu_entry("Cancel", Vec::new),
+    ]))
+}
+fn queue_add(queue: Queue) -> Action {
+    Action::QueueAdd(vec![], vec![queue], Req::none())
+}
+/// like `add_to_queue_album_by_id`, but a folder with the songs instead of a reference to the album
+fn album_as_folder(id: AlbumId, db: &Database) -> Option<Queue> {
+    let album = db.albums().get(&id)?;
+    Some(
+        QueueContent::Folder(QueueFolder {
+            index: 0,
+            content: album
+                .songs
+                .iter()
+                .map(|id| QueueContent::Song(*id).into())
+                .collect(),
+            name: album.name.clone(),
+            order: None,
+        })
+        .into(),
+    )
+}
+/// like `add_to_queue_artist_by_id`, but with folders instead of references to the albums
+fn artist_as_folder(id: ArtistId, db: &Database) -> Option<Queue> {
+    let artist = db.artists().get(&id)?;
+    Some(
+        QueueContent::Folder(QueueFolder {
+            index: 0,
+            content: artist
+                .singles
+                .iter()
+                .map(|id| QueueContent::Song(*id).into())
+                .chain(
+                    artist
+                        .albums
+                        .iter()
+                        .filter_map(|id| album_as_folder(*id, db)),
+                )
+                .collect(),
+            name: artist.name.clone(),
+            order: None,
+        })
+        .into(),
+    )
+}
+/// shows only this artist (and album) in the library
+fn show_in_library(artist: String, album: Option<String>) -> Vec<GuiAction> {
+    vec![GuiAction::Do(Box::new(move |gui| {
+        gui.gui
+            .c_main_view
+            .children
+            .library_browser
+            .show(&artist, album.as_deref())
+    }))]
+}
+
+/// The quick search's results (see `Database::search`) as filter scores:
+/// matches score between 1.0 and 4.0, better matches are shown first.
+struct SearchMatches {
+    /// the lowercase words of the query, to highlight them
+    words: Vec<String>,
+    artists: HashMap<ArtistId, f32>,
+    albums: HashMap<AlbumId, f32>,
+    songs: HashMap<SongId, f32>,
+}
+impl SearchMatches {
+    /// `None` if the query is empty
+    fn new(db: &Database, query: &str) -> Option<Self> {
+        let words = query
+            .to_lowercase()
+            .split_whitespace()
+            .map(|word| word.to_owned())
+            .collect::<Vec<_>>();
+        if words.is_empty() {
+            return None;
+        }
+        let mut matches = Self {
+            words,
+            artists: HashMap::new(),
+            albums: HashMap::new(),
+            songs: HashMap::new(),
+        };
+        for result in db.search(query, usize::MAX) {
+            let score = result.score as f32 / 25.0;
+            match result.kind {
+                SearchResultKind::Artist => matches.artists.insert(result.id, score),
+                SearchResultKind::Album => matches.albums.insert(result.id, score),
+                SearchResultKind::Song => matches.songs.insert(result.id, score),
+            };
+        }
+        Some(matches)
+    }
+}
+/// the byte ranges in `text` which contain one of the (lowercase) `words`, ignoring case, sorted and merged
+fn match_ranges(text: &str, words: &[String]) -> Vec<Range<usize>> {
+    // lowercasing can change the length of a char, so remember where each byte came from
+    let mut lower = String::with_capacity(text.len());
+    let mut origin = Vec::with_capacity(text.len() + 1);
+    for (i, c) in text.char_indices() {
+        for c in c.to_lowercase() {
+            lower.push(c);
+            origin.resize(lower.len(), i);
+        }
+    }
+    let mut ranges = words
+        .iter()
+        .flat_map(|word| {
+            lower.match_indices(word.as_str()).map(|(start, m)| {
+                // the last char which is part of the match
+                let last = origin[start + m.len() - 1];
+                origin[start]..last + text[last..].chars().next().map_or(0, char::len_utf8)
+            })
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<usize>> = vec![];
+    for r in ranges {
+        match merged.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+/// `text` as label contents, with the parts which match the search highlighted
+fn highlighted(
+    text: String,
+    color: Color,
+    search_words: &[String],
+) -> Vec<(gui_text::AdvancedContent, f32, f32)> {
+    let ranges = match_ranges(&text, search_words);
+    if ranges.is_empty() {
+        return vec![(
+            gui_text::AdvancedContent::Text(gui_text::Content::new(text, color)),
+            1.0,
+            1.0,
+        )];
+    }
+    let mut parts = vec![];
+    let mut part = |s: &str, color| {
+        if !s.is_empty() {
+            // spaces at the start or end of a part would otherwise not take up any space
+            parts.push((
+                gui_text::AdvancedContent::Text(gui_text::Content::new(
+                    s.replace(' ', "\u{00A0}"),
+                    color,
+                )),
+                1.0,
+                1.0,
+            ));
+        }
+    };
+    let mut pos = 0;
+    for r in ranges {
+        part(&text[pos..r.start], color);
+        part(&text[r.clone()], SEARCH_HIGHLIGHT);
+        pos = r.end;
+    }
+    part(&text[pos..], color);
+    parts
+}
+/// Enter adds the element with keyboard focus to the end of the queue
+fn enter_adds_to_queue(
+    e: &mut EventInfo,
+    down: bool,
+    key: Option<VirtualKeyCode>,
+    queue: impl FnOnce(&Database) -> Option<Queue> + 'static,
+) -> Vec<GuiAction> {
+    if down
+        && matches!(
+            key,
+            Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter)
+        )
+        && e.take()
+    {
+        vec![GuiAction::Build(Box::new(move |db| {
+            queue(db)
+                .map(|q| GuiAction::SendToServer(Action::QueueAdd(vec![], vec![q], Req::none())))
+                .into_iter()
+                .collect()
+        }))]
+    } else {
+        vec![]
+    }
+}
+
 pub struct FilterPanel {
     config: GuiElemCfg,
     c_tab_main: ScrollBox<(
@@ -1306,6 +2331,7 @@ pub struct FilterPanel {
         Button<[Label; 1]>,
         Button<[Label; 1]>,
         Panel<[Button<[Label; 1]>; 3]>,
+        Button<[Label; 1]>,
     )>,
     c_tab_filters_songs: ScrollBox<FilterTab>,
     c_tab_filters_albums: ScrollBox<FilterTab>,
@@ -1318,6 +2344,8 @@ pub struct FilterPanel {
     filter_songs: Arc<Mutex<Filter>>,
     filter_albums: Arc<Mutex<Filter>>,
     filter_artists: Arc<Mutex<Filter>>,
+    /// the sort order shown on the sort button
+    sort: Option<LibrarySort>,
 }
 #[derive(Default)]
 struct FilterTab {
@@ -1330,6 +2358,9 @@ enum FilterLine {
     TagEq(Panel<(Label, TextField)>),
     TagStartsWith(Panel<(Label, TextField)>),
     TagWithValueInt(Panel<(Label, TextField, TextField, TextField)>),
+    TagRegex(Panel<(Label, TextField)>),
+    Duration(Panel<(Label, TextField, TextField)>),
+    NoCover(Label),
 }
 impl GuiElemWrapper for FilterLine {
     fn as_elem(&self) -> &dyn GuiElem {
@@ -1339,6 +2370,9 @@ impl GuiElemWrapper for FilterLine {
             Self::TagEq(v) => v,
             Self::TagStartsWith(v) => v,
             Self::TagWithValueInt(v) => v,
+            Self::TagRegex(v) => v,
+            Self::Duration(v) => v,
+            Self::NoCover(v) => v,
         }
     }
     fn as_elem_mut(&mut self) -> &mut dyn GuiElem {
@@ -1348,6 +2382,9 @@ impl GuiElemWrapper for FilterLine {
             Self::TagEq(v) => v,
             Self::TagStartsWith(v) => v,
             Self::TagWithValueInt(v) => v,
+            Self::TagRegex(v) => v,
+            Self::Duration(v) => v,
+            Self::NoCover(v) => v,
         }
     }
 }
@@ -1368,6 +2405,37 @@ const FP_CASESENS_N: &'static str = "search is case-insensitive";
 const FP_CASESENS_Y: &'static str = "search is case-sensitive!";
 const FP_PREFSTART_N: &'static str = "simple search";
 const FP_PREFSTART_Y: &'static str = "will prefer matches at the start of a word";
+/// buttons below the presets in the filter panel's tabs
+const FILTER_ACTIONS: [(&str, fn(&mut Filter)); 6] = [
+    ("+ tag", |f| {
+        f.filters.push(FilterType::TagEq(String::new()))
+    }),
+    ("+ tag regex", |f| {
+        f.filters
+            .push(FilterType::TagRegex(Regex::new("").unwrap()))
+    }),
+    ("+ length", |f| {
+        f.filters.push(FilterType::Duration(0, u64::MAX))
+    }),
+    ("+ no cover", |f| f.filters.push(FilterType::NoCover)),
+    ("negate last", |f| {
+        if let Some(last) = f.filters.pop() {
+            f.filters.push(FilterType::Not(Filter {
+                and: true,
+                filters: vec![last],
+            }));
+        }
+    }),
+    ("group last two", |f| {
+        if f.filters.len() >= 2 {
+            let filters = f.filters.split_off(f.filters.len() - 2);
+            f.filters.push(FilterType::Nested(Filter {
+                and: !f.and,
+                filters,
+            }));
+        }
+    }),
+];
 impl FilterPanel {
     pub fn new(
         search_settings_changed: Arc<AtomicBool>,
@@ -1528,6 +2596,27 @@ impl FilterPanel {
                         ),
                     ],
                 ),
+                Button::new(
+                    GuiElemCfg::default(),
+                    |_| {
+                        vec![GuiAction::Do(Box::new(|gui| {
+                            if let Some(cfg) = &mut gui.gui_config {
+                                cfg.library_sort = cfg.library_sort.next();
+                                crate::gui::save_config_value(
+                                    "library_sort",
+                                    toml::Value::String(cfg.library_sort.name().to_owned()),
+                                );
+                            }
+                        }))]
+                    },
+                    [Label::new(
+                        GuiElemCfg::default(),
+                        String::new(),
+                        Color::GRAY,
+                        None,
+                        Vec2::new(0.5, 0.5),
+                    )],
+                ),
             ),
             vec![0.0; 10],
             0.0,
@@ -1619,6 +2708,7 @@ impl FilterPanel {
             filter_songs,
             filter_albums,
             filter_artists,
+            sort: None,
         }
     }
     fn build_filter(
@@ -1686,6 +2776,51 @@ impl FilterPanel {
                     )
                 }),
             )
+            .chain(FILTER_ACTIONS.into_iter().map(|(text, action)| {
+                let f = Arc::clone(&filter);
+                let oc = Arc::clone(&on_change);
+                Button::new(
+                    GuiElemCfg::default(),
+                    move |_| {
+                        action(&mut f.lock().unwrap());
+                        oc(true);
+                        vec![]
+                    },
+                    [Label::new(
+                        GuiElemCfg::default(),
+                        text.to_owned(),
+                        Color::LIGHT_GRAY,
+                        None,
+                        Vec2::new(0.5, 0.5),
+                    )],
+                )
+            }))
+            .chain([{
+                let f = Arc::clone(filter);
+                let section = match tab {
+                    2 => "artist",
+                    1 => "album",
+                    _ => "song",
+                };
+                Button::new(
+                    GuiElemCfg::default(),
+                    move |_| {
+                        let preset = f.lock().unwrap().to_toml().to_string();
+                        vec![text_notif(if clipboard::set(&preset) {
+                            format!("Copied, add it to [filters.{section}] in config_gui.toml:\nname = {preset}")
+                        } else {
+                            format!("Add this to [filters.{section}] in config_gui.toml:\nname = {preset}")
+                        })]
+                    },
+                    [Label::new(
+                        GuiElemCfg::default(),
+                        "copy as preset".to_owned(),
+                        Color::LIGHT_GRAY,
+                        None,
+                        Vec2::new(0.5, 0.5),
+                    )],
+                )
+            }])
             .collect(),
             filters,
         };
@@ -1884,6 +3019,108 @@ impl FilterPanel {
                         ),
                     )));
                 }
+                FilterType::TagRegex(r) => {
+                    let mut tf = TextField::new_adv(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.1, 0.0), (1.0, 1.0))),
+                        r.as_str().to_owned(),
+                        "regex".to_owned(),
+                        Color::GRAY,
+                        Color::WHITE,
+                    );
+                    let mx = Arc::clone(mutex);
+                    let oc = Arc::clone(on_change);
+                    tf.on_changed_mut = Some(Box::new(move |tf, text| {
+                        // keep the last valid regex, but show that this one is invalid
+                        if let Ok(regex) = Regex::new(&text) {
+                            *tf.c_input.content.color() = Color::WHITE;
+                            if let Some(Ok(FilterType::TagRegex(r))) =
+                                mx.lock().unwrap().get_mut(&path)
+                            {
+                                *r = regex;
+                                oc(false);
+                            }
+                        } else {
+                            *tf.c_input.content.color() = Color::RED;
+                        }
+                    }));
+                    children.push(FilterLine::TagRegex(Panel::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((indent, 0.0), (1.0, 1.0))),
+                        (
+                            Label::new(
+                                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.1, 1.0))),
+                                "~".to_owned(),
+                                Color::WHITE,
+                                None,
+                                Vec2::new(0.5, 0.5),
+                            ),
+                            tf,
+                        ),
+                    )));
+                }
+                FilterType::Duration(min, max) => {
+                    let mut tf1 = TextField::new_adv(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.3, 0.0), (0.65, 1.0))),
+                        fmt_duration(*min, 0),
+                        "min (m:ss)".to_owned(),
+                        Color::GRAY,
+                        Color::WHITE,
+                    );
+                    let mut tf2 = TextField::new_adv(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.65, 0.0), (1.0, 1.0))),
+                        fmt_duration(*max, u64::MAX),
+                        "max (m:ss)".to_owned(),
+                        Color::GRAY,
+                        Color::WHITE,
+                    );
+                    let mx = Arc::clone(mutex);
+                    let oc = Arc::clone(on_change);
+                    let p = path.clone();
+                    tf1.on_changed = Some(Box::new(move |text| {
+                        if let Some(n) = parse_duration(text, 0) {
+                            if let Some(Ok(FilterType::Duration(v, _))) =
+                                mx.lock().unwrap().get_mut(&p)
+                            {
+                                *v = n;
+                                oc(false);
+                            }
+                        }
+                    }));
+                    let mx = Arc::clone(mutex);
+                    let oc = Arc::clone(on_change);
+                    tf2.on_changed = Some(Box::new(move |text| {
+                        if let Some(n) = parse_duration(text, u64::MAX) {
+                            if let Some(Ok(FilterType::Duration(_, v))) =
+                                mx.lock().unwrap().get_mut(&path)
+                            {
+                                *v = n;
+                                oc(false);
+                            }
+                        }
+                    }));
+                    children.push(FilterLine::Duration(Panel::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((indent, 0.0), (1.0, 1.0))),
+                        (
+                            Label::new(
+                                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.3, 1.0))),
+                                "length".to_owned(),
+                                Color::WHITE,
+                                None,
+                                Vec2::new(0.5, 0.5),
+                            ),
+                            tf1,
+                            tf2,
+                        ),
+                    )));
+                }
+                FilterType::NoCover => {
+                    children.push(FilterLine::NoCover(Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((indent, 0.0), (1.0, 1.0))),
+                        "no cover".to_owned(),
+                        Color::WHITE,
+                        None,
+                        Vec2::new(0.0, 0.5),
+                    )));
+                }
             }
         }
     }
@@ -1910,6 +3147,11 @@ impl GuiElem for FilterPanel {
             self.c_tab_filters_artists.config_mut().redraw = true;
             self.line_height = info.line_height;
         }
+        if self.sort != Some(info.gui_config.library_sort) {
+            self.sort = Some(info.gui_config.library_sort);
+            *self.c_tab_main.children.4.children[0].content.text() =
+                format!("sort by {}", info.gui_config.library_sort.name());
+        }
         // maybe switch tabs
         let new_tab = self.new_tab.load(std::sync::atomic::Ordering::Relaxed);
         let mut load_tab = false;
@@ -2008,13 +3250,93 @@ impl GuiElem for FilterPanel {
         self
     }
 }
+/// The order of the library, chosen in the filter panel and saved as `library_sort` in config_gui.toml.
+/// Ties are sorted by name and then by id, so the order doesn't change when the library is updated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LibrarySort {
+    /// albums by year, songs in albums by track number
+    #[default]
+    TrackNr,
+    Name,
+    /// albums and songs by year
+    Year,
+    /// newest first. there are no timestamps, but new ids are usually higher.
+    RecentlyAdded,
+    /// shortest albums and songs first
+    Duration,
+}
+impl LibrarySort {
+    pub const ALL: [Self; 5] = [
+        Self::TrackNr,
+        Self::Name,
+        Self::Year,
+        Self::RecentlyAdded,
+        Self::Duration,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TrackNr => "track number",
+            Self::Name => "name",
+            Self::Year => "year",
+            Self::RecentlyAdded => "recently added",
+            Self::Duration => "duration",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.name() == name)
+    }
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|v| *v == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+    fn artist_key(self, artist: &Artist) -> (i64, String, u64) {
+        match self {
+            Self::RecentlyAdded => (-(artist.id as i64), String::new(), artist.id),
+            _ => (0, artist.name.to_lowercase(), artist.id),
+        }
+    }
+    fn album_key(self, album: &Album, db: &Database) -> (i64, String, u64) {
+        let num = match self {
+            Self::Name => 0,
+            Self::TrackNr | Self::Year => tag_number(&album.general, "Year=").unwrap_or(i64::MAX),
+            Self::RecentlyAdded => -(album.id as i64),
+            Self::Duration => album
+                .songs
+                .iter()
+                .filter_map(|id| db.songs().get(id))
+                .map(|song| song.duration_millis as i64)
+                .sum(),
+        };
+        (num, album.name.to_lowercase(), album.id)
+    }
+    fn song_key(self, song: &Song) -> (i64, String, u64) {
+        let num = match self {
+            Self::Name => 0,
+            Self::TrackNr => tag_number(&song.general, "TrackNr=").unwrap_or(i64::MAX),
+            Self::Year => tag_number(&song.general, "Year=").unwrap_or(i64::MAX),
+            Self::RecentlyAdded => -(song.id as i64),
+            Self::Duration => song.duration_millis as i64,
+        };
+        (num, song.title.to_lowercase(), song.id)
+    }
+}
+/// the number at the start of the value of a tag like `Year=2000` or `TrackNr=3/12`
+fn tag_number(general: &GeneralData, key: &str) -> Option<i64> {
+    general.tags.iter().find_map(|tag| {
+        let value = tag.strip_prefix(key)?;
+        let end = value
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || i == 0 && c == '-'))
+            .map_or(value.len(), |(i, _)| i);
+        value[..end].parse().ok()
+    })
+}
 #[derive(Clone)]
 pub struct Filter {
     and: bool,
     filters: Vec<FilterType>,
 }
 #[derive(Clone)]
-#[allow(unused)]
 pub enum FilterType {
     Nested(Filter),
     Not(Filter),
@@ -2023,13 +3345,236 @@ pub enum FilterType {
     /// true if the tag is '<String><Integer>' and Integer is between min and max (both inclusive)
     /// note: <String> usually ends with '='.
     TagWithValueInt(String, i32, i32),
+    /// true if any tag matches the regex
+    TagRegex(Regex),
+    /// true if the duration in seconds is between min and max (both inclusive).
+    /// for albums and artists, this is the duration of all their songs.
+    Duration(u64, u64),
+    /// true if there is no cover. songs without a cover use their album's cover.
+    NoCover,
+}
+impl FilterType {
+    /// Parses a filter preset from the `[filters]` section in config_gui.toml:
+    /// `"Fav"` (tag equals), `"Genre=*"` (tag starts with), `"Year=1990..2000"` (tag with a value in this range),
+    /// `"!..."` (not), and arrays or `{ all = [...] }` / `{ any = [...] }` / `{ not = ... }` for nested filters.
+    /// Tables can also be `{ tag = "..." }`, `{ tag_prefix = "..." }`, `{ tag = "...", min = 0, max = 9 }`,
+    /// `{ regex = "..." }`, `{ duration = "2:00..5:00" }` and `{ cover = false }`.
+    pub fn from_toml(v: &toml::Value) -> Result<Self, String> {
+        match v {
+            toml::Value::String(s) => Self::from_toml_str(s),
+            toml::Value::Array(a) => Ok(Self::Nested(Filter::from_toml_array(true, a)?)),
+            toml::Value::Table(t) => {
+                let mut keys = t.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+                keys.sort_unstable();
+                match (keys.as_slice(), t.values().next()) {
+                    (["all" | "any"], Some(toml::Value::Array(a))) => {
+                        Ok(Self::Nested(Filter::from_toml_array(t.contains_key("all"), a)?))
+                    }
+                    (["not"], Some(v)) => Ok(Self::Not(Filter {
+                        and: true,
+                        filters: vec![Self::from_toml(v)?],
+                    })),
+                    (["tag"], Some(toml::Value::String(s))) => Ok(Self::TagEq(s.clone())),
+                    (["tag_prefix"], Some(toml::Value::String(s))) => {
+                        Ok(Self::TagStartsWith(s.clone()))
+                    }
+                    (["max", "min", "tag"], _) => match (&t["tag"], &t["min"], &t["max"]) {
+                        (
+                            toml::Value::String(s),
+                            toml::Value::Integer(min),
+                            toml::Value::Integer(max),
+                        ) => Ok(Self::TagWithValueInt(
+                            s.clone(),
+                            (*min).try_into().map_err(|_| "min is out of range".to_owned())?,
+                            (*max).try_into().map_err(|_| "max is out of range".to_owned())?,
+                        )),
+                        _ => Err("expected `{ tag = \"...\", min = 0, max = 9 }`".to_owned()),
+                    },
+                    (["regex"], Some(toml::Value::String(s))) => Regex::new(s)
+                        .map(Self::TagRegex)
+                        .map_err(|e| format!("invalid regex: {e}")),
+                    (["duration"], Some(toml::Value::String(s))) => {
+                        let err = || {
+                            format!("`{s}` is not a valid duration range, expected something like `2:00..5:00`, `..90` or `600..`")
+                        };
+                        let (min, max) = s.split_once("..").ok_or_else(err)?;
+                        let min = parse_duration(min, 0).ok_or_else(err)?;
+                        let max = parse_duration(max, u64::MAX).ok_or_else(err)?;
+                        Ok(Self::Duration(min, max))
+                    }
+                    (["cover"], Some(toml::Value::Boolean(has_cover))) => Ok(if *has_cover {
+                        Self::Not(Filter {
+                            and: true,
+                            filters: vec![Self::NoCover],
+                        })
+                    } else {
+                        Self::NoCover
+                    }),
+                    _ => Err(
+                        "expected `{ all = [...] }`, `{ any = [...] }`, `{ not = ... }`, `{ tag = \"...\" }`, `{ tag_prefix = \"...\" }`, `{ regex = \"...\" }`, `{ duration = \"...\" }` or `{ cover = false }`"
+                            .to_owned(),
+                    ),
+                }
+            }
+            _ => Err("expected a string, array or table".to_owned()),
+        }
+    }
+    fn from_toml_str(s: &str) -> Result<Self, String> {
+        if let Some(s) = s.strip_prefix('!') {
+            Ok(Self::Not(Filter {
+                and: true,
+                filters: vec![Self::from_toml_str(s)?],
+            }))
+        } else if let Some(prefix) = s.strip_suffix('*') {
+            Ok(Self::TagStartsWith(prefix.to_owned()))
+        } else if let Some((start, max)) = s.rsplit_once("..") {
+            let err =
+                || format!("`{s}` is not a valid range, expected something like `Year=1990..2000`");
+            let key = start.trim_end_matches(|c: char| c.is_ascii_digit());
+            // a minus after the '=' is part of the number
+            let key = key
+                .strip_suffix('-')
+                .filter(|k| k.ends_with('='))
+                .unwrap_or(key);
+            let min = start[key.len()..].parse().map_err(|_| err())?;
+            let max = max.parse().map_err(|_| err())?;
+            if min > max {
+                return Err(err());
+            }
+            Ok(Self::TagWithValueInt(key.to_owned(), min, max))
+        } else {
+            Ok(Self::TagEq(s.to_owned()))
+        }
+    }
+    /// The inverse of `from_toml`, used to copy filters from the filter panel as presets.
+    /// Uses the short string syntax where it can be parsed back to the same filter.
+    pub fn to_toml(&self) -> toml::Value {
+        fn table(k: &str, v: toml::Value) -> toml::Value {
+            toml::Value::Table([(k.to_owned(), v)].into_iter().collect())
+        }
+        match self {
+            Self::Nested(f) => f.to_toml(),
+            Self::Not(f) => match f.filters.as_slice() {
+                [v] => match v.to_toml() {
+                    toml::Value::String(s) => toml::Value::String(format!("!{s}")),
+                    v => table("not", v),
+                },
+                _ => table("not", f.to_toml()),
+            },
+            Self::TagEq(s) if !s.starts_with('!') && !s.ends_with('*') && !s.contains("..") => {
+                toml::Value::String(s.clone())
+            }
+            Self::TagEq(s) => table("tag", toml::Value::String(s.clone())),
+            Self::TagStartsWith(s) if !s.starts_with('!') => toml::Value::String(format!("{s}*")),
+            Self::TagStartsWith(s) => table("tag_prefix", toml::Value::String(s.clone())),
+            Self::TagWithValueInt(s, min, max)
+                if !s.starts_with('!')
+                    && !s.ends_with(|c: char| c.is_ascii_digit() || c == '-')
+                    && (*min >= 0 || s.ends_with('='))
+                    && min <= max =>
+            {
+                toml::Value::String(format!("{s}{min}..{max}"))
+            }
+            Self::TagWithValueInt(s, min, max) => toml::Value::Table(
+                [
+                    ("tag".to_owned(), toml::Value::String(s.clone())),
+                    ("min".to_owned(), toml::Value::Integer(*min as _)),
+                    ("max".to_owned(), toml::Value::Integer(*max as _)),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+            Self::TagRegex(r) => table("regex", toml::Value::String(r.as_str().to_owned())),
+            Self::Duration(min, max) => table(
+                "duration",
+                toml::Value::String(format!(
+                    "{}..{}",
+                    fmt_duration(*min, 0),
+                    fmt_duration(*max, u64::MAX)
+                )),
+            ),
+            Self::NoCover => table("cover", toml::Value::Boolean(false)),
+        }
+    }
 }
 impl Filter {
-    pub fn passes(&self, gd: &GeneralData) -> bool {
+    fn from_toml_array(and: bool, a: &[toml::Value]) -> Result<Self, String> {
+        Ok(Self {
+            and,
+            filters: a
+                .iter()
+                .map(FilterType::from_toml)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+    pub fn to_toml(&self) -> toml::Value {
+        let filters = toml::Value::Array(self.filters.iter().map(FilterType::to_toml).collect());
+        if self.and {
+            filters
+        } else {
+            toml::Value::Table([("any".to_owned(), filters)].into_iter().collect())
+        }
+    }
+}
+/// `m:ss` or seconds, or `default` if empty
+fn parse_duration(s: &str, default: u64) -> Option<u64> {
+    let s = s.trim();
+    if s.is_empty() {
+        Some(default)
+    } else if let Some((m, s)) = s.split_once(':') {
+        let s: u64 = s.parse().ok()?;
+        if s < 60 {
+            Some(m.parse::<u64>().ok()?.checked_mul(60)?.checked_add(s)?)
+        } else {
+            None
+        }
+    } else {
+        s.parse().ok()
+    }
+}
+/// `m:ss`, or an empty string for `default`
+fn fmt_duration(secs: u64, default: u64) -> String {
+    if secs == default {
+        String::new()
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+/// The presets from a table in the `[filters]` section of config_gui.toml, like `[filters.song]`.
+/// On error, also returns the name of the invalid preset.
+pub fn filter_presets_from_toml(
+    v: &toml::Value,
+) -> Result<Vec<(String, FilterType)>, (Option<String>, String)> {
+    v.as_table()
+        .ok_or_else(|| {
+            (
+                None,
+                "expected a table of `name = filter` presets".to_owned(),
+            )
+        })?
+        .iter()
+        .map(|(name, v)| match FilterType::from_toml(v) {
+            Ok(f) => Ok((name.clone(), f)),
+            Err(e) => Err((Some(name.clone()), e)),
+        })
+        .collect()
+}
+/// used if config_gui.toml has no presets
+pub fn default_filter_presets() -> Vec<(String, FilterType)> {
+    vec![
+        ("Fav".to_owned(), FilterType::TagEq("Fav".to_owned())),
+        (
+            "Year".to_owned(),
+            FilterType::TagWithValueInt("Year=".to_owned(), 1990, 2000),
+        ),
+    ]
+}
+impl Filter {
+    pub fn passes(&self, item: &FilterItem, db: &Database) -> bool {
         if self.filters.is_empty() {
             return true;
         }
-        let mut iter = self.filters.iter().map(|v| v.passes(gd));
+        let mut iter = self.filters.iter().map(|v| v.passes(item, db));
         if self.and {
             iter.all(|v| v)
         } else {
@@ -2050,10 +3595,11 @@ impl Filter {
     }
 }
 impl FilterType {
-    pub fn passes(&self, gd: &GeneralData) -> bool {
+    pub fn passes(&self, item: &FilterItem, db: &Database) -> bool {
+        let gd = item.general();
         match self {
-            Self::Nested(f) => f.passes(gd),
-            Self::Not(f) => !f.passes(gd),
+            Self::Nested(f) => f.passes(item, db),
+            Self::Not(f) => !f.passes(item, db),
             Self::TagEq(v) => gd.tags.iter().any(|t| t == v),
             Self::TagStartsWith(v) => gd.tags.iter().any(|t| t.starts_with(v)),
             Self::TagWithValueInt(v, min, max) => gd.tags.iter().any(|t| {
@@ -2067,6 +3613,12 @@ impl FilterType {
                     false
                 }
             }),
+            Self::TagRegex(r) => gd.tags.iter().any(|t| r.is_match(t)),
+            Self::Duration(min, max) => {
+                let secs = item.duration_millis(db) / 1000;
+                *min <= secs && secs <= *max
+            }
+            Self::NoCover => !item.has_cover(db),
         }
     }
     pub fn get_mut(&mut self, path: &[usize]) -> Option<Result<&mut Self, &mut Filter>> {
@@ -2083,7 +3635,60 @@ impl FilterType {
     pub fn inner_filter(&mut self) -> Option<&mut Filter> {
         match self {
             Self::Nested(f) | Self::Not(f) => Some(f),
-            Self::TagEq(_) | Self::TagStartsWith(_) | Self::TagWithValueInt(..) => None,
+            Self::TagEq(_)
+            | Self::TagStartsWith(_)
+            | Self::TagWithValueInt(..)
+            | Self::TagRegex(_)
+            | Self::Duration(..)
+            | Self::NoCover => None,
+        }
+    }
+}
+/// what a `Filter` is checked against
+pub enum FilterItem<'a> {
+    Song(&'a Song),
+    Album(&'a Album),
+    Artist(&'a Artist),
+}
+impl FilterItem<'_> {
+    fn general(&self) -> &GeneralData {
+        match self {
+            Self::Song(v) => &v.general,
+            Self::Album(v) => &v.general,
+            Self::Artist(v) => &v.general,
+        }
+    }
+    fn duration_millis(&self, db: &Database) -> u64 {
+        let songs = |songs: &[SongId]| {
+            songs
+                .iter()
+                .filter_map(|id| db.songs().get(id))
+                .map(|song| song.duration_millis)
+                .sum::<u64>()
+        };
+        match self {
+            Self::Song(v) => v.duration_millis,
+            Self::Album(v) => songs(&v.songs),
+            Self::Artist(v) => {
+                songs(&v.singles)
+                    + v.albums
+                        .iter()
+                        .filter_map(|id| db.albums().get(id))
+                        .map(|album| songs(&album.songs))
+                        .sum::<u64>()
+            }
+        }
+    }
+    fn has_cover(&self, db: &Database) -> bool {
+        match self {
+            Self::Song(v) => {
+                v.cover.is_some()
+                    || v.album
+                        .and_then(|id| db.albums().get(&id))
+                        .is_some_and(|album| album.cover.is_some())
+            }
+            Self::Album(v) => v.cover.is_some(),
+            Self::Artist(v) => v.cover.is_some(),
         }
     }
 }
@@ -2092,6 +3697,12 @@ mod selected {
     use musicdb_lib::data::queue::QueueFolder;
 
     use super::*;
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum Item {
+        Artist(ArtistId),
+        Album(AlbumId),
+        Song(SongId),
+    }
     #[derive(Clone)]
     pub struct Selected(
         // artist, album, songs
@@ -2145,6 +3756,41 @@ mod selected {
             self.changed();
             self.0.lock().unwrap().2.remove(id)
         }
+        pub fn contains(&self, item: Item) -> bool {
+            match item {
+                Item::Artist(id) => self.contains_artist(&id),
+                Item::Album(id) => self.contains_album(&id),
+                Item::Song(id) => self.contains_song(&id),
+            }
+        }
+        pub fn insert(&self, item: Item) -> bool {
+            match item {
+                Item::Artist(id) => self.insert_artist(id),
+                Item::Album(id) => self.insert_album(id),
+                Item::Song(id) => self.insert_song(id),
+            }
+        }
+        pub fn remove(&self, item: Item) -> bool {
+            match item {
+                Item::Artist(id) => self.remove_artist(&id),
+                Item::Album(id) => self.remove_album(&id),
+                Item::Song(id) => self.remove_song(&id),
+            }
+        }
+        pub fn len(&self) -> usize {
+            self.view(|sel| sel.0.len() + sel.1.len() + sel.2.len())
+        }
+        /// deselects everything for which `keep` returns false
+        pub fn retain(&self, keep: impl Fn(Item) -> bool) {
+            let mut s = self.0.lock().unwrap();
+            let len = s.0.len() + s.1.len() + s.2.len();
+            s.0.retain(|id| keep(Item::Artist(*id)));
+            s.1.retain(|id| keep(Item::Album(*id)));
+            s.2.retain(|id| keep(Item::Song(*id)));
+            if s.0.len() + s.1.len() + s.2.len() != len {
+                self.changed();
+            }
+        }
         pub fn view<T>(
             &self,
             f: impl FnOnce(&(HashSet<ArtistId>, HashSet<AlbumId>, HashSet<SongId>)) -> T,
@@ -2162,9 +3808,29 @@ mod selected {
         fn changed(&self) {
             self.1.store(true, std::sync::atomic::Ordering::Relaxed);
         }
+        /// the selection as a queue. selected albums and artists are added as folders,
+        /// and if none of their songs are selected, all of them are added.
         pub fn as_queue(&self, lb: &LibraryBrowser, db: &Database) -> Vec<Queue> {
             let lock = self.0.lock().unwrap();
             let (sel_artists, sel_albums, sel_songs) = &*lock;
+            let songs_queue = |songs: &[SongId]| {
+                songs
+                    .iter()
+                    .map(|song| QueueContent::Song(*song).into())
+                    .collect::<Vec<Queue>>()
+            };
+            let album_folder = |album: &AlbumId, content: Vec<Queue>| -> Queue {
+                QueueContent::Folder(QueueFolder {
+                    index: 0,
+                    content,
+                    name: match db.albums().get(album) {
+                        Some(v) => v.name.clone(),
+                        None => "< unknown album >".to_owned(),
+                    },
+                    order: None,
+                })
+                .into()
+            };
             let mut out = vec![];
             for (artist, singles, albums) in &lb.library_sorted {
                 let artist_selected = sel_artists.contains(artist);
@@ -2195,21 +3861,21 @@ mod selected {
                         }
                     }
                     if album_selected {
-                        local_artist.push(
-                            QueueContent::Folder(QueueFolder {
-                                index: 0,
-                                content: local_album_owned,
-                                name: match db.albums().get(album) {
-                                    Some(v) => v.name.clone(),
-                                    None => "< unknown album >".to_owned(),
-                                },
-                                order: None,
-                            })
-                            .into(),
-                        );
+                        if local_album_owned.is_empty() {
+                            local_album_owned = songs_queue(songs);
+                        }
+                        local_artist.push(album_folder(album, local_album_owned));
                     }
                 }
                 if artist_selected {
+                    if local_artist_owned.is_empty() {
+                        local_artist_owned = songs_queue(singles);
+                        local_artist_owned.extend(
+                            albums
+                                .iter()
+                                .map(|(album, songs)| album_folder(album, songs_queue(songs))),
+                        );
+                    }
                     out.push(
                         QueueContent::Folder(QueueFolder {
                             index: 0,
@@ -2228,3 +3894,164 @@ mod selected {
         }
     }
 }
+
+#[cfg(test)]
+fn test_db() -> (Database, AlbumId, [SongId; 3]) {
+    let mut db = Database::new_clientside();
+    let artist = db.add_artist_new(Artist {
+        id: 0,
+        name: "artist".to_owned(),
+        cover: None,
+        albums: vec![],
+        singles: vec![],
+        general: GeneralData::default(),
+    });
+    let album = db.add_album_new(Album {
+        id: 0,
+        name: "album".to_owned(),
+        artist,
+        cover: Some(0),
+        songs: vec![],
+        general: GeneralData::default(),
+    });
+    let mut song = |title: &str, album, secs: u64, tags: &[&str]| {
+        db.add_song_new(Song::new(
+            "song".into(),
+            None,
+            title.to_owned(),
+            album,
+            artist,
+            vec![],
+            None,
+            0,
+            secs * 1000,
+            GeneralData {
+                tags: tags.iter().map(|t| (*t).to_owned()).collect(),
+            },
+        ))
+    };
+    let songs = [
+        song("short", Some(album), 90, &["Fav", "Genre=Rock"]),
+        song("long", Some(album), 600, &["Genre=Jazz", "Year=1995"]),
+        song("single", None, 200, &[]),
+    ];
+    (db, album, songs)
+}
+#[cfg(test)]
+fn test_passing(db: &Database, songs: &[SongId], filter: FilterType) -> Vec<String> {
+    let filter = Filter {
+        and: true,
+        filters: vec![filter],
+    };
+    songs
+        .iter()
+        .map(|id| &db.songs()[id])
+        .filter(|song| filter.passes(&FilterItem::Song(song), db))
+        .map(|song| song.title.clone())
+        .collect()
+}
+#[test]
+fn test_filters() {
+    let (db, album, songs) = test_db();
+    let parse = |s: &str| FilterType::from_toml(&toml::from_str::<toml::Value>(s).unwrap()["f"]);
+    let passing = |s: &str| test_passing(&db, &songs, parse(s).unwrap());
+    assert_eq!(passing(r#"f = "Fav""#), ["short"]);
+    assert_eq!(passing(r#"f = "!Fav""#), ["long", "single"]);
+    assert_eq!(passing(r#"f = "Year=1990..1999""#), ["long"]);
+    assert_eq!(passing(r#"f = { regex = "^Genre=(Jazz|Pop)$" }"#), ["long"]);
+    assert_eq!(
+        passing(r#"f = { duration = "1:00..5:00" }"#),
+        ["short", "single"]
+    );
+    assert_eq!(passing(r#"f = { duration = "300.." }"#), ["long"]);
+    // songs in an album use the album's cover
+    assert_eq!(passing(r#"f = { cover = false }"#), ["single"]);
+    assert_eq!(
+        passing(r#"f = { any = ["Fav", { cover = false }] }"#),
+        ["short", "single"]
+    );
+    assert_eq!(passing(r#"f = ["Genre=*", { not = "Fav" }]"#), ["long"]);
+    assert!(parse(r#"f = { regex = "(" }"#).is_err());
+    assert!(parse(r#"f = { duration = "1:60.." }"#).is_err());
+    assert!(parse(r#"f = { cover = "no" }"#).is_err());
+    // albums and artists use the duration of all their songs
+    let long = FilterType::Duration(10 * 60, u64::MAX);
+    assert!(long.passes(&FilterItem::Album(&db.albums()[&album]), &db));
+    assert!(!FilterType::Duration(0, 10 * 60).passes(&FilterItem::Album(&db.albums()[&album]), &db));
+    assert!(FilterType::Duration(890, 890).passes(&FilterItem::Artist(&db.artists()[&0]), &db));
+}
+#[test]
+fn test_filters_to_toml() {
+    let (db, _, songs) = test_db();
+    for s in [
+        r#""Fav""#,
+        r#""!Genre=*""#,
+        r#""Year=-5..1999""#,
+        r#"{ tag = "a..b" }"#,
+        r#"{ tag_prefix = "!" }"#,
+        r#"{ tag = "Track", min = -1, max = 2 }"#,
+        r#"{ regex = "^Genre=(Jazz|Pop)$" }"#,
+        r#"{ duration = "1:30..10:00" }"#,
+        r#"{ duration = "..5:00" }"#,
+        r#"{ not = { cover = false } }"#,
+        r#"{ any = ["Fav", ["Genre=*", { cover = false }]] }"#,
+    ] {
+        let v = toml::from_str::<toml::Value>(&format!("f = {s}")).unwrap();
+        let f = FilterType::from_toml(&v["f"]).unwrap();
+        assert_eq!(f.to_toml(), v["f"], "{s}");
+        assert_eq!(
+            test_passing(&db, &songs, FilterType::from_toml(&f.to_toml()).unwrap()),
+            test_passing(&db, &songs, f),
+        );
+    }
+    // filters from the editor which can't use the short syntax
+    for f in [
+        FilterType::TagEq("Genre=*".to_owned()),
+        FilterType::TagWithValueInt("Year".to_owned(), -5, 5),
+        FilterType::TagWithValueInt("Year=".to_owned(), 5, -5),
+    ] {
+        assert_eq!(
+            FilterType::from_toml(&f.to_toml()).unwrap().to_toml(),
+            f.to_toml()
+        );
+    }
+}
+#[test]
+fn test_library_sort() {
+    let (mut db, album, songs) = test_db();
+    for (id, tags) in [
+        (songs[0], &["TrackNr=1/2", "Year=1990"][..]),
+        (songs[1], &["TrackNr=2/2"]),
+    ] {
+        let general = &mut db.songs_mut().get_mut(&id).unwrap().general;
+        general.tags.extend(tags.iter().map(|t| (*t).to_owned()));
+    }
+    let titles = |sort: LibrarySort| {
+        let mut lb = LibraryBrowser::new(GuiElemCfg::default());
+        lb.sort = sort;
+        lb.update_local_library(&db);
+        let (_, singles, albums) = &lb.library_sorted[0];
+        assert_eq!(albums[0].0, album);
+        albums[0]
+            .1
+            .iter()
+            .chain(singles)
+            .map(|id| db.songs()[id].title.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(titles(LibrarySort::TrackNr), ["short", "long", "single"]);
+    assert_eq!(titles(LibrarySort::Name), ["long", "short", "single"]);
+    assert_eq!(titles(LibrarySort::Year), ["short", "long", "single"]);
+    assert_eq!(
+        titles(LibrarySort::RecentlyAdded),
+        ["long", "short", "single"]
+    );
+    assert_eq!(titles(LibrarySort::Duration), ["short", "long", "single"]);
+    assert_eq!(
+        tag_number(&db.songs()[&songs[0]].general, "TrackNr="),
+        Some(1)
+    );
+    for sort in LibrarySort::ALL {
+        assert_eq!(LibrarySort::from_name(sort.name()), Some(sort));
+    }
+}
@@ -1,11 +1,23 @@
 use std::{
-    sync::mpsc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
     time::{Duration, Instant},
 };
 
-use speedy2d::{color::Color, dimen::Vector2, shape::Rectangle};
+use musicdb_lib::data::CoverId;
+use speedy2d::{
+    color::Color,
+    dimen::{Vec2, Vector2},
+    shape::Rectangle,
+};
 
-use crate::gui::{GuiElem, GuiElemCfg};
+use crate::{
+    gui::{DrawInfo, GuiAction, GuiElem, GuiElemCfg},
+    gui_base::Panel,
+    gui_text::{Label, TextLayoutMode},
+};
 
 /// This should be added on top of overything else and set to fullscreen.
 /// It will respond to notification events.
@@ -60,7 +72,15 @@ impl NotifOverlay {
                     }
                 }
                 NotifInfoTime::Displayed(since) => {
-                    let p = since.elapsed().as_secs_f32() / info.duration.as_secs_f32();
+                    let p = if info
+                        .until
+                        .as_ref()
+                        .is_some_and(|until| until.load(Ordering::Relaxed))
+                    {
+                        1.0
+                    } else {
+                        since.elapsed().as_secs_f32() / info.duration.as_secs_f32()
+                    };
                     if p >= 1.0 {
                         info.time = NotifInfoTime::FadingOut(Instant::now());
                         info.progress = 0.0;
@@ -121,6 +141,8 @@ pub struct NotifInfo {
     /// light up the edges of the screen/window
     /// in this color (usually red for important things)
     color: Option<Color>,
+    /// if set, the notification is hidden once this is `true`, even before `duration` has passed
+    until: Option<Arc<AtomicBool>>,
     /// used for fade-out animation
     progress: f32,
 }
@@ -137,15 +159,123 @@ impl NotifInfo {
             time: NotifInfoTime::Pending,
             duration,
             color: None,
+            until: None,
             progress: 0.0,
         }
     }
+    /// a notification which stays on screen until `done` is set to `true`
+    pub fn until(done: Arc<AtomicBool>) -> Self {
+        Self::new(Duration::MAX).or_until(done)
+    }
+    /// hides the notification early once `done` is set to `true`
+    pub fn or_until(mut self, done: Arc<AtomicBool>) -> Self {
+        self.until = Some(done);
+        self
+    }
     pub fn with_highlight(mut self, color: Color) -> Self {
         self.color = Some(color);
         self
     }
 }
 
+/// a notification which shows one line of text for two seconds
+pub fn text_notif(text: String) -> GuiAction {
+    GuiAction::ShowNotification(Box::new(move |_| {
+        (
+            Box::new(Panel::with_theme_background(
+                GuiElemCfg::default(),
+                [Label::new(
+                    GuiElemCfg::default(),
+                    text,
+                    Color::WHITE,
+                    None,
+                    Vec2::new(0.5, 0.5),
+                )
+                .with_layout(TextLayoutMode::Wrap)],
+                |t| t.notif_background,
+            )),
+            NotifInfo::new(Duration::from_secs(2)),
+        )
+    }))
+}
+
+/// a notification for the song which just started playing, with its cover on the left
+pub fn song_notif(title: String, artist: Option<String>, cover: Option<CoverId>) -> GuiAction {
+    GuiAction::ShowNotification(Box::new(move |_| {
+        let left = if cover.is_some() { 0.22 } else { 0.02 };
+        (
+            Box::new(Panel::with_theme_background(
+                GuiElemCfg::default(),
+                (
+                    NotifCover {
+                        config: GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.2, 1.0))),
+                        cover,
+                    },
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((left, 0.1), (0.98, 0.55))),
+                        title,
+                        Color::WHITE,
+                        None,
+                        Vec2::new(0.0, 0.5),
+                    ),
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((left, 0.55), (0.98, 0.9))),
+                        artist.unwrap_or_default(),
+                        Color::LIGHT_GRAY,
+                        None,
+                        Vec2::new(0.0, 0.5),
+                    ),
+                ),
+                |t| t.notif_background,
+            )),
+            NotifInfo::new(Duration::from_secs(3)),
+        )
+    }))
+}
+
+/// the cover's thumbnail in a `song_notif`, as large as possible
+struct NotifCover {
+    config: GuiElemCfg,
+    cover: Option<CoverId>,
+}
+impl GuiElem for NotifCover {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new([].into_iter())
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
+        if let Some(cover) = self.cover {
+            let size = info.pos.width().min(info.pos.height()) * 0.8;
+            let center = Vec2::new(
+                info.pos.top_left().x + info.pos.width() / 2.0,
+                info.pos.top_left().y + info.pos.height() / 2.0,
+            );
+            let area = Rectangle::from_tuples(
+                (center.x - size / 2.0, center.y - size / 2.0),
+                (center.x + size / 2.0, center.y + size / 2.0),
+            );
+            crate::gui::draw_thumbnail(info, g, cover, &area);
+        }
+    }
+}
+
 impl Clone for NotifOverlay {
     fn clone(&self) -> Self {
         Self::new().0
@@ -1,10 +1,10 @@
-use std::{sync::Arc, time::Duration};
+use std::time::Duration;
 
-use musicdb_lib::data::{CoverId, SongId};
+use musicdb_lib::data::{database::Database, CoverId, SongId};
 use speedy2d::{color::Color, dimen::Vec2, image::ImageHandle, shape::Rectangle};
 
 use crate::{
-    gui::{DrawInfo, GuiAction, GuiElemCfg, GuiServerImage},
+    gui::{DrawInfo, GuiAction, GuiElemCfg},
     gui_anim::AnimationController,
     gui_base::Panel,
     gui_notif::NotifInfo,
@@ -19,6 +19,30 @@ pub struct CurrentInfo {
     pub current_cover: Option<(CoverId, Option<Option<ImageHandle>>)>,
 }
 
+/// the song's cover, or its album's, or its artist's
+pub fn song_cover(db: &Database, song: &SongId) -> Option<CoverId> {
+    let song = db.songs().get(song)?;
+    song.cover
+        .or_else(|| {
+            song.album
+                .and_then(|album| db.albums().get(&album))
+                .and_then(|album| album.cover)
+        })
+        .or_else(|| {
+            db.artists()
+                .get(&song.artist)
+                .and_then(|artist| artist.cover)
+        })
+}
+/// the cover shown for the current song, see `CurrentInfo`
+pub fn current_songs_cover(db: &Database) -> Option<CoverId> {
+    if db.queue_finished {
+        None
+    } else {
+        song_cover(db, db.queue.get_current_song(db)?)
+    }
+}
+
 impl CurrentInfo {
     pub fn new() -> Self {
         Self {
@@ -32,89 +56,60 @@ impl CurrentInfo {
     pub fn update(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
         if self.update {
             self.update = false;
-            let current_song = info.database.queue.get_current_song().cloned();
+            // once the queue has ended, show nothing instead of the first song
+            let current_song = if info.database.queue_finished {
+                None
+            } else {
+                info.database.queue.get_current_song(info.database).cloned()
+            };
             if current_song != self.current_song {
                 self.current_song = current_song;
                 self.new_song = true;
             }
             if let Some(current_song) = current_song {
-                let current_songs_cover =
-                    info.database.songs().get(&current_song).and_then(|song| {
-                        song.cover
-                            .or_else(|| {
-                                song.album
-                                    .and_then(|album| info.database.albums().get(&album))
-                                    .and_then(|album| album.cover)
+                if let Some(current_songs_cover) = song_cover(info.database, &current_song) {
+                    let cover = info.covers.get(current_songs_cover, info.time);
+                    if let Some(cover) = cover.get_init(g) {
+                        // cover loaded
+                        if self.current_cover.is_none()
+                            || self.current_cover.as_ref().is_some_and(|(cc, h)| {
+                                *cc != current_songs_cover || !matches!(h, Some(Some(_)))
                             })
-                            .or_else(|| {
-                                info.database
-                                    .artists()
-                                    .get(&song.artist)
-                                    .and_then(|artist| artist.cover)
+                        {
+                            self.current_cover = Some((current_songs_cover, Some(Some(cover))));
+                            self.new_cover = true;
+                        }
+                    } else if cover.is_err() {
+                        // no cover with that ID
+                        if self.current_cover.is_none()
+                            || self.current_cover.as_ref().is_some_and(|(csc, h)| {
+                                *csc != current_songs_cover || !matches!(h, Some(None))
                             })
-                    });
-                if let Some(current_songs_cover) = current_songs_cover {
-                    if let Some(cover) = info.covers.get_mut(&current_songs_cover) {
-                        if let Some(cover) = cover.get_init(g) {
-                            // cover loaded
-                            if self.current_cover.is_none()
-                                || self.current_cover.as_ref().is_some_and(|(cc, h)| {
-                                    *cc != current_songs_cover || !matches!(h, Some(Some(_)))
-                                })
-                            {
-                                self.current_cover = Some((current_songs_cover, Some(Some(cover))));
-                                self.new_cover = true;
-                            }
-                        } else if cover.is_err() {
-                            // no cover with that ID
-                            if self.current_cover.is_none()
-                                || self.current_cover.as_ref().is_some_and(|(csc, h)| {
-                                    *csc != current_songs_cover || !matches!(h, Some(None))
-                                })
-                            {
-                                // is_err and `current` is old
-                                self.current_cover = Some((current_songs_cover, Some(None)));
-                                self.new_cover = true;
-                                // show notification
-                                info.actions.push(GuiAction::ShowNotification(Box::new(
-                                    move |_| {
-                                        (
-                                            Box::new(Panel::with_background(
+                        {
+                            // is_err and `current` is old
+                            self.current_cover = Some((current_songs_cover, Some(None)));
+                            self.new_cover = true;
+                            // show notification
+                            info.actions
+                                .push(GuiAction::ShowNotification(Box::new(move |_| {
+                                    (
+                                        Box::new(Panel::with_theme_background(
+                                            GuiElemCfg::default(),
+                                            [Label::new(
                                                 GuiElemCfg::default(),
-                                                [Label::new(
-                                                    GuiElemCfg::default(),
-                                                    format!("Couldn't load cover"),
-                                                    Color::WHITE,
-                                                    None,
-                                                    Vec2::new(0.5, 0.5),
-                                                )],
-                                                Color::from_rgba(0.0, 0.0, 0.0, 0.8),
-                                            )),
-                                            NotifInfo::new(Duration::from_secs(1)),
-                                        )
-                                    },
-                                )));
-                            }
-                        } else {
-                            // Cover loading, check again later
-                            if self.current_cover.is_none()
-                                || self.current_cover.as_ref().is_some_and(|(cc, h)| {
-                                    *cc != current_songs_cover || h.is_some()
-                                })
-                            {
-                                self.current_cover = Some((current_songs_cover, None));
-                                self.new_cover = true;
-                            }
-                            self.update = true;
+                                                format!("Couldn't load cover"),
+                                                Color::WHITE,
+                                                None,
+                                                Vec2::new(0.5, 0.5),
+                                            )],
+                                            |t| t.notif_background,
+                                        )),
+                                        NotifInfo::new(Duration::from_secs(1)),
+                                    )
+                                })));
                         }
                     } else {
-                        info.covers.insert(
-                            current_songs_cover,
-                            GuiServerImage::new_cover(
-                                current_songs_cover,
-                                Arc::clone(&info.get_con),
-                            ),
-                        );
+                        // Cover loading, check again later
                         if self.current_cover.is_none()
                             || self
                                 .current_cover
@@ -0,0 +1,191 @@
+use std::{cell::RefCell, rc::Rc};
+
+use musicdb_lib::{data::playlist, server::Action};
+use speedy2d::{color::Color, dimen::Vec2};
+
+use crate::{
+    gui::{GuiAction, GuiElemCfg},
+    gui_library::menu_entry,
+    gui_notif::text_notif,
+    gui_text::{Label, TextField},
+};
+
+/*
+
+Context menus for saving the queue (or a part of it) as a playlist
+and for loading, renaming and deleting saved playlists.
+The playlists are stored on the server (see `musicdb_lib::data::playlist`),
+so they are shared with other clients and the web UI.
+The server reports the results of the `Playlist*` actions as notifications.
+
+*/
+
+/// the list of saved playlists, opened from the main view
+pub fn playlists_menu() -> GuiAction {
+    with_playlists(|names| {
+        let mut entries = vec![menu_entry("Save queue as...", || {
+            vec![save_menu(vec![], String::new())]
+        })];
+        if names.is_empty() {
+            entries.push(Box::new(Label::new(
+                GuiElemCfg::default(),
+                "No saved playlists".to_owned(),
+                Color::LIGHT_GRAY,
+                None,
+                Vec2::new_y(0.5),
+            )));
+        }
+        for name in names {
+            entries.push(menu_entry(&name.clone(), move || {
+                vec![playlist_menu(name.clone())]
+            }));
+        }
+        vec![GuiAction::ContextMenu(Some(entries))]
+    })
+}
+
+/// asks for a name, then saves the queue element at `path` as a playlist
+pub fn save_menu(path: Vec<usize>, name: String) -> GuiAction {
+    name_menu("Playlist name", name, "Save", move |name| {
+        let path = path.clone();
+        vec![with_playlists(move |names| {
+            let save = Action::PlaylistSave(path, name.clone());
+            if names.contains(&name) {
+                vec![confirm_menu(
+                    format!("Replace the playlist {name}?"),
+                    "Replace",
+                    move || vec![GuiAction::SendToServer(save.clone())],
+                )]
+            } else {
+                vec![GuiAction::SendToServer(save)]
+            }
+        })]
+    })
+}
+
+fn playlist_menu(name: String) -> GuiAction {
+    GuiAction::ContextMenu(Some(vec![
+        Box::new(Label::new(
+            GuiElemCfg::default(),
+            name.clone(),
+            Color::LIGHT_GRAY,
+            None,
+            Vec2::new_y(0.5),
+        )),
+        menu_entry("Add to end of queue", {
+            let name = name.clone();
+            move || {
+                vec![GuiAction::SendToServer(Action::PlaylistLoad(
+                    name.clone(),
+                    true,
+                ))]
+            }
+        }),
+        menu_entry("Replace queue", {
+            let name = name.clone();
+            move || {
+                let name = name.clone();
+                vec![confirm_menu(
+                    format!("Replace the queue with {name}?"),
+                    "Replace",
+                    move || {
+                        vec![GuiAction::SendToServer(Action::PlaylistLoad(
+                            name.clone(),
+                            false,
+                        ))]
+                    },
+                )]
+            }
+        }),
+        menu_entry("Rename...", {
+            let name = name.clone();
+            move || {
+                let old = name.clone();
+                vec![name_menu("New name", name.clone(), "Rename", move |new| {
+                    vec![GuiAction::SendToServer(Action::PlaylistRename(
+                        old.clone(),
+                        new,
+                    ))]
+                })]
+            }
+        }),
+        menu_entry("Delete", move || {
+            let name = name.clone();
+            vec![confirm_menu(
+                format!("Delete the playlist {name}?"),
+                "Delete",
+                move || {
+                    vec![GuiAction::SendToServer(Action::PlaylistDelete(
+                        name.clone(),
+                    ))]
+                },
+            )]
+        }),
+    ]))
+}
+
+/// requests the names of the saved playlists from the server, then runs the actions built by `f`
+fn with_playlists(f: impl FnOnce(Vec<String>) -> Vec<GuiAction> + 'static) -> GuiAction {
+    GuiAction::Do(Box::new(move |gui| {
+        let actions = match gui.get_con.with_reconnect(|get_con| get_con.playlists()) {
+            Ok(Ok(names)) => f(names),
+            Ok(Err(e)) => vec![text_notif(e)],
+            Err(e) => vec![text_notif(format!("Couldn't get the playlists: {e}"))],
+        };
+        for action in actions {
+            gui.exec_gui_action(action);
+        }
+    }))
+}
+
+/// a text field and a button which calls `action` with the name, see `playlist::sanitize_name`
+fn name_menu(
+    hint: &str,
+    name: String,
+    button: &'static str,
+    action: impl Fn(String) -> Vec<GuiAction> + 'static,
+) -> GuiAction {
+    let text = Rc::new(RefCell::new(name.clone()));
+    let mut field = TextField::new_adv(
+        GuiElemCfg::default(),
+        name,
+        hint.to_owned(),
+        Color::GRAY,
+        Color::WHITE,
+    );
+    field.on_changed = Some(Box::new({
+        let text = Rc::clone(&text);
+        move |new| *text.borrow_mut() = new.to_owned()
+    }));
+    GuiAction::ContextMenu(Some(vec![
+        Box::new(field),
+        menu_entry(button, move || {
+            match playlist::sanitize_name(&text.borrow()) {
+                Some(name) => action(name.to_owned()),
+                None => vec![text_notif(
+                    "Playlist names can't be empty, start with a '.', contain any of / \\ : * ? \" < > | or be very long".to_owned(),
+                )],
+            }
+        }),
+        menu_entry("Cancel", Vec::new),
+    ]))
+}
+
+fn confirm_menu(
+    question: String,
+    button: &str,
+    action: impl Fn() -> Vec<GuiAction> + 'static,
+) -> GuiAction {
+    GuiAction::ContextMenu(Some(vec![
+        Box::new(Label::new(
+            GuiElemCfg::default(),
+            question,
+            Color::LIGHT_GRAY,
+            None,
+            Vec2::new_y(0.5),
+        )),
+        // first, so it gets the keyboard focus
+        menu_entry("Cancel", Vec::new),
+        menu_entry(button, action),
+    ]))
+}
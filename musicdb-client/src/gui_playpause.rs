@@ -21,10 +21,11 @@ impl PlayPause {
         Self {
             config,
             set_fav: Button::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.01, 0.01), (0.24, 0.99))),
+                GuiElemCfg::at(Rectangle::from_tuples((0.01, 0.01), (0.24, 0.99)))
+                    .w_tooltip("Add to / remove from favorites".to_owned()),
                 |_| {
                     vec![GuiAction::Build(Box::new(|db| {
-                        if let Some(song_id) = db.queue.get_current_song() {
+                        if let Some(song_id) = db.queue.get_current_song(db) {
                             if let Some(song) = db.get_song(song_id) {
                                 vec![GuiAction::SendToServer(
                                     if song.general.tags.iter().any(|v| v == "Fav") {
@@ -47,7 +48,8 @@ impl PlayPause {
                 )],
             ),
             to_zero: Button::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.26, 0.01), (0.49, 0.99))),
+                GuiElemCfg::at(Rectangle::from_tuples((0.26, 0.01), (0.49, 0.99)))
+                    .w_tooltip("Stop".to_owned()),
                 |_| vec![GuiAction::SendToServer(Action::Stop)],
                 [Panel::with_background(
                     GuiElemCfg::at(Rectangle::from_tuples((0.2, 0.2), (0.8, 0.8))),
@@ -56,7 +58,8 @@ impl PlayPause {
                 )],
             ),
             play_pause: Button::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.51, 0.01), (0.74, 0.99))),
+                GuiElemCfg::at(Rectangle::from_tuples((0.51, 0.01), (0.74, 0.99)))
+                    .w_tooltip("Play / Pause".to_owned()),
                 |btn| {
                     vec![GuiAction::SendToServer(if btn.children[0].is_playing {
                         Action::Pause
@@ -69,8 +72,13 @@ impl PlayPause {
                 ))],
             ),
             to_end: Button::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.76, 0.01), (0.99, 0.99))),
-                |_| vec![GuiAction::SendToServer(Action::NextSong)],
+                GuiElemCfg::at(Rectangle::from_tuples((0.76, 0.01), (0.99, 0.99)))
+                    .w_tooltip("Next song".to_owned()),
+                |_| {
+                    vec![GuiAction::Build(Box::new(|db| {
+                        vec![GuiAction::SendToServer(db.next_song_action())]
+                    }))]
+                },
                 [NextSongShape::new(GuiElemCfg::at(Rectangle::from_tuples(
                     (0.2, 0.2),
                     (0.8, 0.8),
@@ -1,12 +1,18 @@
 use musicdb_lib::{
     data::{
         database::Database,
-        queue::{Queue, QueueContent, QueueDuration},
+        queue::{Queue, QueueContent, QueueDuration, RandomFilter, RandomWeighting},
         song::Song,
         AlbumId, ArtistId,
     },
     server::{Action, Req},
 };
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
 use speedy2d::{
     color::Color,
     dimen::Vec2,
@@ -16,8 +22,11 @@ use speedy2d::{
 
 use crate::{
     gui::{Dragging, DrawInfo, EventInfo, GuiAction, GuiElem, GuiElemCfg},
-    gui_base::{Panel, ScrollBox},
-    gui_text::{self, AdvancedLabel, Label, TextField},
+    gui_base::{draw_keyboard_focus, Button, Panel, ScrollBox},
+    gui_library::{menu_entry, FilterType},
+    gui_playlists,
+    gui_text::{self, AdvancedLabel, Label, TextField, TextLayoutMode},
+    gui_theme::Theme,
 };
 
 /*
@@ -33,24 +42,34 @@ pub struct QueueViewer {
     config: GuiElemCfg,
     c_scroll_box: ScrollBox<Vec<Box<dyn GuiElem>>>,
     c_empty_space_drag_handler: QueueEmptySpaceDragHandler,
-    c_control_flow_elements: Panel<(QueueLoop, QueueLoop, QueueFolder, TextField)>,
+    c_control_flow_elements: Panel<(QueueLoop, QueueLoop, QueueFolder, TextField, QueueRandom)>,
     c_duration: AdvancedLabel,
     recv: std::sync::mpsc::Receiver<QVMsg>,
     queue_updated: bool,
+    selection: QueueSelection,
+    /// the number of selected elements shown in `c_duration`
+    selected_count: usize,
+    /// scroll to and flash the current song on the next draw
+    jump_to_current: bool,
 }
+#[derive(Clone)]
 pub enum QVMsg {
     ControlFlowElementsSetFolderName(String),
+    ControlFlowElementsSetRandomFilter(String, RandomFilter),
+    ControlFlowElementsSetRandomWeighting(RandomWeighting),
 }
 const QP_QUEUE1: f32 = 0.0;
 const QP_QUEUE2: f32 = 0.95;
 const QP_INV1: f32 = QP_QUEUE2;
 const QP_INV2: f32 = 1.0;
+/// how long the current song is highlighted after `jump_to_current`
+const FLASH_DURATION: Duration = Duration::from_millis(1000);
 impl QueueViewer {
     pub fn new(config: GuiElemCfg) -> Self {
         let (sender, recv) = std::sync::mpsc::channel();
         let control_flow_elements = (
             QueueLoop::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.5, 0.5))).w_mouse(),
+                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.3, 0.5))).w_mouse(),
                 vec![],
                 QueueContent::Loop(
                     0,
@@ -70,7 +89,7 @@ impl QueueViewer {
             )
             .alwayscopy(),
             QueueLoop::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.5), (0.5, 1.0))).w_mouse(),
+                GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.5), (0.3, 1.0))).w_mouse(),
                 vec![],
                 QueueContent::Loop(
                     2,
@@ -90,7 +109,7 @@ impl QueueViewer {
             )
             .alwayscopy(),
             QueueFolder::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.5, 0.0), (1.0, 0.5))).w_mouse(),
+                GuiElemCfg::at(Rectangle::from_tuples((0.3, 0.0), (0.7, 0.5))).w_mouse(),
                 vec![],
                 musicdb_lib::data::queue::QueueFolder {
                     index: 0,
@@ -103,11 +122,12 @@ impl QueueViewer {
             .alwayscopy(),
             {
                 let mut tf = TextField::new(
-                    GuiElemCfg::at(Rectangle::from_tuples((0.5, 0.5), (1.0, 1.0))),
+                    GuiElemCfg::at(Rectangle::from_tuples((0.3, 0.5), (0.7, 1.0))),
                     format!("folder name"),
                     Color::from_rgb(0.0, 0.33, 0.0),
                     Color::from_rgb(0.0, 0.67, 0.0),
                 );
+                let sender = sender.clone();
                 tf.on_changed = Some(Box::new(move |folder_name| {
                     _ = sender.send(QVMsg::ControlFlowElementsSetFolderName(
                         folder_name.to_owned(),
@@ -115,6 +135,19 @@ impl QueueViewer {
                 }));
                 tf
             },
+            QueueRandom::new(
+                GuiElemCfg::at(Rectangle::from_tuples((0.7, 0.0), (1.0, 0.5))).w_mouse(),
+                vec![],
+                QueueContent::Random(
+                    RandomFilter::All,
+                    RandomWeighting::Uniform,
+                    Default::default(),
+                )
+                .into(),
+                false,
+                None,
+            )
+            .alwayscopy(sender),
         );
         Self {
             config,
@@ -139,6 +172,9 @@ impl QueueViewer {
             ),
             queue_updated: false,
             recv,
+            selection: QueueSelection::default(),
+            selected_count: 0,
+            jump_to_current: false,
         }
     }
 }
@@ -185,10 +221,49 @@ impl GuiElem for QueueViewer {
                         .text() = name.clone();
                     self.c_control_flow_elements.children.2.queue.name = name;
                 }
+                QVMsg::ControlFlowElementsSetRandomFilter(text, new_filter) => {
+                    let random = &mut self.c_control_flow_elements.children.4;
+                    if let QueueContent::Random(filter, _, _) = random.queue.content_mut() {
+                        *filter = new_filter;
+                    }
+                    random.filter_text = text;
+                    random.update_label();
+                }
+                QVMsg::ControlFlowElementsSetRandomWeighting(new_weighting) => {
+                    let random = &mut self.c_control_flow_elements.children.4;
+                    if let QueueContent::Random(_, weighting, _) = random.queue.content_mut() {
+                        *weighting = new_weighting;
+                    }
+                    random.update_label();
+                }
             }
         }
-        if self.queue_updated {
+        // scroll while something is dragged near the top or bottom edge of the queue
+        if info.dragging.is_some() {
+            let pos = &self.c_scroll_box.config().pixel_pos;
+            if pos.contains(info.mouse_pos) {
+                let edge = info.line_height * 1.5;
+                let dist_top = info.mouse_pos.y - pos.top_left().y;
+                let dist_bottom = pos.bottom_right().y - info.mouse_pos.y;
+                let speed = if dist_top < edge {
+                    dist_top - edge
+                } else if dist_bottom < edge {
+                    edge - dist_bottom
+                } else {
+                    0.0
+                };
+                if speed != 0.0 {
+                    self.c_scroll_box.scroll_target += speed * 0.2;
+                    if let Some(h) = &info.helper {
+                        h.request_redraw();
+                    }
+                }
+            }
+        }
+        let selected_count = self.selection.len();
+        if self.queue_updated || selected_count != self.selected_count {
             self.queue_updated = false;
+            self.selected_count = selected_count;
             let label = &mut self.c_duration;
             fn fmt_dur(dur: QueueDuration) -> String {
                 if dur.infinite {
@@ -220,31 +295,47 @@ impl GuiElem for QueueViewer {
                 }
             }
             let dt = fmt_dur(info.database.queue.duration_total(&info.database));
-            let dr = fmt_dur(info.database.queue.duration_remaining(&info.database));
-            label.content = vec![
-                vec![(
+            let position = [
+                queue_position_text(info.database),
+                queue_remaining_text(info.database),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" · ");
+            let mut total = vec![(
+                gui_text::AdvancedContent::Text(gui_text::Content::new(
+                    format!("Total: {dt}"),
+                    Color::GRAY,
+                )),
+                1.0,
+                1.0,
+            )];
+            if selected_count > 0 {
+                total.push((
                     gui_text::AdvancedContent::Text(gui_text::Content::new(
-                        format!("Total: {dt}"),
-                        Color::GRAY,
+                        format!("  ({selected_count} selected)"),
+                        Color::LIGHT_GRAY,
                     )),
                     1.0,
                     1.0,
-                )],
-                vec![(
-                    gui_text::AdvancedContent::Text(gui_text::Content::new(
-                        format!("Remaining: {dr}"),
-                        Color::GRAY,
-                    )),
+                ));
+            }
+            label.content = vec![total];
+            if !position.is_empty() {
+                label.content.push(vec![(
+                    gui_text::AdvancedContent::Text(gui_text::Content::new(position, Color::GRAY)),
                     1.0,
                     1.0,
-                )],
-            ];
+                )]);
+            }
             label.config_mut().redraw = true;
         }
         if self.config.redraw || info.pos.size() != self.config.pixel_pos.size() {
             self.config.redraw = false;
             let mut c = vec![];
             let mut h = vec![];
+            self.selection.clear_shown();
             queue_gui(
                 &info.database.queue,
                 &info.database,
@@ -256,16 +347,73 @@ impl GuiElem for QueueViewer {
                 vec![],
                 true,
                 true,
+                &self.selection,
             );
             let scroll_box = &mut self.c_scroll_box;
             scroll_box.children = c;
             scroll_box.children_heights = h;
             scroll_box.config_mut().redraw = true;
         }
+        if self.jump_to_current {
+            self.jump_to_current = false;
+            let children = &mut self.c_scroll_box.children;
+            // folders containing the current song are also `current`, the song is the last one
+            if let Some(i) = children.iter_mut().rposition(|c| {
+                c.any_mut()
+                    .downcast_mut::<QueueSong>()
+                    .is_some_and(|s| s.current)
+            }) {
+                if let Some(song) = children[i].any_mut().downcast_mut::<QueueSong>() {
+                    song.flash = Some(Instant::now());
+                }
+                self.c_scroll_box.scroll_into_view(i);
+            }
+        }
     }
     fn updated_queue(&mut self) {
         self.queue_updated = true;
         self.config.redraw = true;
+        self.selection.clear();
+    }
+}
+
+impl QueueViewer {
+    /// scrolls the current song into view and briefly highlights it
+    pub fn jump_to_current(&mut self) {
+        self.jump_to_current = true;
+    }
+}
+
+/// `song 12 of 87`, or `None` if nothing is playing.
+/// the total is `∞` if the queue never ends (infinite loops or random elements).
+pub fn queue_position_text(db: &Database) -> Option<String> {
+    db.queue.get_current_song(db)?;
+    let len = db.queue.len(db);
+    let pos = len.saturating_sub(db.queue.len_remaining(db)) + 1;
+    let total = db.queue.duration_total(db);
+    Some(if total.infinite || total.random_counter > 0 {
+        format!("song {pos} of ∞")
+    } else {
+        format!("song {pos} of {len}")
+    })
+}
+/// `42 min remaining`, including the current song, or `None` if nothing is playing.
+pub fn queue_remaining_text(db: &Database) -> Option<String> {
+    db.queue.get_current_song(db)?;
+    let dur = db.queue.duration_remaining(db);
+    Some(if dur.infinite || dur.random_counter > 0 {
+        "∞ remaining".to_owned()
+    } else {
+        format!("{} remaining", fmt_minutes(dur.millis))
+    })
+}
+/// rounded to minutes, `1 h 5 min` if it's an hour or more
+fn fmt_minutes(millis: u64) -> String {
+    let minutes = (millis + 30_000) / 60_000;
+    if minutes >= 60 {
+        format!("{} h {} min", minutes / 60, minutes % 60)
+    } else {
+        format!("{minutes} min")
     }
 }
 
@@ -280,19 +428,29 @@ fn queue_gui(
     path: Vec<usize>,
     current: bool,
     skip_folder: bool,
+    selection: &QueueSelection,
 ) {
     let cfg = GuiElemCfg::at(Rectangle::from_tuples((depth, 0.0), (1.0, 1.0)));
+    if let Some(label) = queue.label() {
+        target.push(Box::new(
+            Label::new(
+                cfg.clone(),
+                label.to_owned(),
+                Color::LIGHT_GRAY,
+                None,
+                Vec2::new_y(0.5),
+            )
+            .with_layout(TextLayoutMode::SingleLineEllipsis),
+        ));
+        target_h.push(line_height * 0.6);
+    }
     match queue.content() {
         QueueContent::Song(id) => {
             if let Some(s) = db.songs().get(id) {
-                target.push(Box::new(QueueSong::new(
-                    cfg,
-                    path,
-                    s.clone(),
-                    current,
-                    db,
-                    depth_inc_by * 0.33,
-                )));
+                target.push(Box::new(
+                    QueueSong::new(cfg, path, s.clone(), current, db, depth_inc_by * 0.33)
+                        .selectable(selection),
+                ));
                 target_h.push(line_height * 1.75);
             }
         }
@@ -304,12 +462,10 @@ fn queue_gui(
                 order: _,
             } = qf;
             if !skip_folder {
-                target.push(Box::new(QueueFolder::new(
-                    cfg.clone(),
-                    path.clone(),
-                    qf.clone(),
-                    current,
-                )));
+                target.push(Box::new(
+                    QueueFolder::new(cfg.clone(), path.clone(), qf.clone(), current)
+                        .selectable(selection),
+                ));
                 target_h.push(line_height * 0.8);
             }
             for (i, q) in qf.iter().enumerate() {
@@ -326,6 +482,7 @@ fn queue_gui(
                     p,
                     current && *ia == i,
                     false,
+                    selection,
                 );
             }
             if !skip_folder {
@@ -340,12 +497,9 @@ fn queue_gui(
             p.push(0);
             let mut p1 = path.clone();
             let p2 = p1.pop().unwrap_or(0) + 1;
-            target.push(Box::new(QueueLoop::new(
-                cfg.clone(),
-                path,
-                queue.clone(),
-                current,
-            )));
+            target.push(Box::new(
+                QueueLoop::new(cfg.clone(), path, queue.clone(), current).selectable(selection),
+            ));
             target_h.push(line_height * 0.8);
             queue_gui(
                 &inner,
@@ -358,10 +512,73 @@ fn queue_gui(
                 p,
                 current,
                 true,
+                selection,
             );
             target.push(Box::new(QueueIndentEnd::new(cfg, (p1, p2))));
             target_h.push(line_height * 0.4);
         }
+        QueueContent::Random(_, _, q) => {
+            target.push(Box::new(
+                QueueRandom::new(cfg.clone(), path.clone(), queue.clone(), current, Some(db))
+                    .selectable(selection),
+            ));
+            target_h.push(line_height * 0.8);
+            for (i, inner) in q.iter().enumerate() {
+                let mut p = path.clone();
+                p.push(i);
+                queue_gui(
+                    inner,
+                    db,
+                    depth + depth_inc_by,
+                    depth_inc_by,
+                    line_height,
+                    target,
+                    target_h,
+                    p,
+                    current && i == 0,
+                    false,
+                    selection,
+                );
+            }
+            let mut p1 = path;
+            let p2 = p1.pop().unwrap_or(0) + 1;
+            target.push(Box::new(QueueIndentEnd::new(cfg, (p1, p2))));
+            target_h.push(line_height * 0.4);
+        }
+        QueueContent::AlbumRef(id, index) => {
+            let songs = db
+                .albums()
+                .get(id)
+                .map(|a| a.songs.as_slice())
+                .unwrap_or(&[]);
+            target.push(Box::new(
+                QueueAlbumRef::new(cfg.clone(), path.clone(), queue.clone(), db, current)
+                    .selectable(selection),
+            ));
+            target_h.push(line_height * 0.8);
+            for (i, song) in songs.iter().enumerate() {
+                if let Some(s) = db.songs().get(song) {
+                    let mut p = path.clone();
+                    p.push(i);
+                    target.push(Box::new(QueueSong::new(
+                        GuiElemCfg::at(Rectangle::from_tuples(
+                            (depth + depth_inc_by, 0.0),
+                            (1.0, 1.0),
+                        )),
+                        p,
+                        s.clone(),
+                        current && *index == i,
+                        db,
+                        depth_inc_by * 0.33,
+                    )));
+                    target_h.push(line_height * 1.75);
+                }
+            }
+            let mut p1 = path;
+            let p2 = p1.pop().unwrap_or(0) + 1;
+            target.push(Box::new(QueueIndentEnd::new(cfg, (p1, p2))));
+            target_h.push(line_height * 0.4);
+        }
     }
 }
 
@@ -412,24 +629,190 @@ impl GuiElem for QueueEmptySpaceDragHandler {
 
 fn generic_queue_draw(
     info: &mut DrawInfo,
+    g: &mut speedy2d::Graphics2D,
     path: &Vec<usize>,
     queue: impl FnOnce() -> Queue,
     mouse: &mut bool,
     copy_on_mouse_down: bool,
+    selection: &Option<QueueSelection>,
 ) {
+    draw_keyboard_focus(info, g);
+    let selected = selection.as_ref().is_some_and(|s| s.contains(path));
+    if selected {
+        g.draw_rectangle(info.pos.clone(), info.gui_config.theme.highlight);
+    }
     if *mouse && !info.pos.contains(info.mouse_pos) {
         // mouse left our element
         *mouse = false;
-        info.actions.push(GuiAction::SetDragging(Some((
-            Dragging::Queue(if copy_on_mouse_down {
+        let dragging = match selection {
+            // drag the entire selection
+            Some(selection) if selected => {
+                let paths = selection.paths();
+                Dragging::Queues(if copy_on_mouse_down {
+                    Ok(paths
+                        .iter()
+                        .filter_map(|p| info.database.queue.get_item_at_index(p, 0))
+                        .cloned()
+                        .collect())
+                } else {
+                    Err(paths)
+                })
+            }
+            _ => Dragging::Queue(if copy_on_mouse_down {
                 Ok(queue())
             } else {
                 Err(path.clone())
             }),
-            None,
-        ))));
+        };
+        info.actions
+            .push(GuiAction::SetDragging(Some((dragging, None))));
     }
 }
+/// Enter goes to the focused queue element
+fn enter_goes_to(
+    e: &mut EventInfo,
+    down: bool,
+    key: Option<VirtualKeyCode>,
+    path: &[usize],
+) -> Vec<GuiAction> {
+    if down
+        && matches!(
+            key,
+            Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter)
+        )
+        && e.take()
+    {
+        vec![GuiAction::SendToServer(Action::QueueGoto(path.to_vec()))]
+    } else {
+        vec![]
+    }
+}
+/// a left click on a queue element goes to that element,
+/// unless ctrl or shift are held, which changes the selection instead.
+fn queue_click(selection: &Option<QueueSelection>, path: &[usize]) -> Vec<GuiAction> {
+    let selection = selection.clone();
+    let path = path.to_vec();
+    vec![GuiAction::Do(Box::new(move |gui| {
+        if !selection.is_some_and(|s| s.click(&path, &gui.modifiers)) {
+            gui.exec_gui_action(GuiAction::SendToServer(Action::QueueGoto(path)));
+        }
+    }))]
+}
+
+/// The selected elements of the queue, shared by the `QueueViewer` and its elements.
+/// Because the paths change with the queue, the selection is cleared whenever the queue changes.
+#[derive(Clone, Default)]
+struct QueueSelection(Arc<Mutex<QueueSelectionState>>);
+#[derive(Default)]
+struct QueueSelectionState {
+    selected: BTreeSet<Vec<usize>>,
+    /// the element which was clicked last, where a range selected using shift starts
+    anchor: Option<Vec<usize>>,
+    /// the elements which can be selected, in the order in which they are shown
+    shown: Vec<Vec<usize>>,
+}
+impl QueueSelection {
+    fn add_shown(&self, path: Vec<usize>) {
+        self.0.lock().unwrap().shown.push(path);
+    }
+    fn clear_shown(&self) {
+        self.0.lock().unwrap().shown.clear();
+    }
+    fn clear(&self) {
+        let mut s = self.0.lock().unwrap();
+        s.selected.clear();
+        s.anchor = None;
+    }
+    fn contains(&self, path: &[usize]) -> bool {
+        self.0.lock().unwrap().selected.contains(path)
+    }
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().selected.len()
+    }
+    /// the selected paths, except for those inside other selected elements
+    fn paths(&self) -> Vec<Vec<usize>> {
+        let mut out: Vec<Vec<usize>> = vec![];
+        // sorted, so every element comes directly before its children
+        for path in &self.0.lock().unwrap().selected {
+            if !out.last().is_some_and(|p| path.starts_with(p)) {
+                out.push(path.clone());
+            }
+        }
+        out
+    }
+    /// changes the selection after the element at `path` was clicked:
+    /// ctrl toggles it and shift selects everything from the previously clicked element to this one
+    /// (ctrl+shift keeps the previous selection).
+    /// returns false if neither was held, in which case everything is deselected.
+    fn click(&self, path: &[usize], modifiers: &ModifiersState) -> bool {
+        let mut s = self.0.lock().unwrap();
+        if modifiers.shift() {
+            if let Some(anchor) = s.anchor.as_deref() {
+                // paths are sorted in the same order as the elements are shown
+                let (start, end) = if anchor <= path {
+                    (anchor, path)
+                } else {
+                    (path, anchor)
+                };
+                let range = s
+                    .shown
+                    .iter()
+                    .filter(|p| start <= p.as_slice() && p.as_slice() <= end)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if !modifiers.ctrl() {
+                    s.selected.clear();
+                }
+                s.selected.extend(range);
+                return true;
+            }
+        }
+        s.anchor = Some(path.to_vec());
+        if modifiers.ctrl() || modifiers.shift() {
+            if !s.selected.remove(path) {
+                s.selected.insert(path.to_vec());
+            }
+            true
+        } else {
+            s.selected.clear();
+            false
+        }
+    }
+}
+
+/// true if `QueueMove(from, to)` would move an element onto itself or into its own children,
+/// or insert it directly below itself.
+fn queue_move_is_noop(from: &[usize], to: &[usize]) -> bool {
+    if to.starts_with(from) {
+        return true;
+    }
+    match (from.split_last(), to.split_last()) {
+        (Some((i1, p1)), Some((i2, p2))) => p1 == p2 && *i2 == *i1 + 1,
+        _ => false,
+    }
+}
+/// the path of the queue element which is being moved (not copied) by dragging, if any
+fn dragged_queue_path<'a>(info: &'a DrawInfo) -> Option<&'a [usize]> {
+    match &info.dragging {
+        Some((Dragging::Queue(Err(path)), _)) => Some(path),
+        _ => None,
+    }
+}
+/// draws a line at the top or bottom edge of the element to show where something would be inserted
+fn draw_insert_indicator(info: &DrawInfo, g: &mut speedy2d::Graphics2D, below: bool) {
+    let h = (info.line_height * 0.08).max(2.0);
+    let (y1, y2) = if below {
+        (info.pos.bottom_right().y - h, info.pos.bottom_right().y)
+    } else {
+        (info.pos.top_left().y, info.pos.top_left().y + h)
+    };
+    g.draw_rectangle(
+        Rectangle::from_tuples((info.pos.top_left().x, y1), (info.pos.bottom_right().x, y2)),
+        info.gui_config
+            .theme
+            .shade(Color::from_rgba(1.0, 1.0, 1.0, 0.75)),
+    );
+}
 
 struct QueueSong {
     config: GuiElemCfg,
@@ -443,6 +826,9 @@ struct QueueSong {
     copy: bool,
     always_copy: bool,
     copy_on_mouse_down: bool,
+    selection: Option<QueueSelection>,
+    /// when `QueueViewer::jump_to_current` started highlighting this song
+    flash: Option<Instant>,
 }
 impl QueueSong {
     pub fn new(
@@ -454,7 +840,11 @@ impl QueueSong {
         sub_offset: f32,
     ) -> Self {
         Self {
-            config: config.w_mouse().w_keyboard_watch().w_drag_target(),
+            config: config
+                .w_mouse()
+                .w_keyboard_watch()
+                .w_keyboard_focus()
+                .w_drag_target(),
             children: vec![
                 Box::new(AdvancedLabel::new(
                     GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (1.0, 0.57))),
@@ -489,33 +879,36 @@ impl QueueSong {
                         ),
                     ]],
                 )),
-                Box::new(Label::new(
-                    GuiElemCfg::at(Rectangle::from_tuples((sub_offset, 0.57), (1.0, 1.0))),
-                    match (
-                        db.artists().get(&song.artist),
-                        song.album.as_ref().and_then(|id| db.albums().get(id)),
-                    ) {
-                        (None, None) => String::new(),
-                        (Some(artist), None) => format!("by {}", artist.name),
-                        (None, Some(album)) => {
-                            if let Some(artist) = db.artists().get(&album.artist) {
-                                format!("on {} by {}", album.name, artist.name)
-                            } else {
-                                format!("on {}", album.name)
+                Box::new(
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((sub_offset, 0.57), (1.0, 1.0))),
+                        match (
+                            db.artists().get(&song.artist),
+                            song.album.as_ref().and_then(|id| db.albums().get(id)),
+                        ) {
+                            (None, None) => String::new(),
+                            (Some(artist), None) => format!("by {}", artist.name),
+                            (None, Some(album)) => {
+                                if let Some(artist) = db.artists().get(&album.artist) {
+                                    format!("on {} by {}", album.name, artist.name)
+                                } else {
+                                    format!("on {}", album.name)
+                                }
                             }
-                        }
-                        (Some(artist), Some(album)) => {
-                            format!("by {} on {}", artist.name, album.name)
-                        }
-                    },
-                    if current {
-                        Color::from_int_rgb(97, 38, 89)
-                    } else {
-                        Color::from_int_rgb(60, 38, 97)
-                    },
-                    None,
-                    Vec2::new(0.0, 0.5),
-                )),
+                            (Some(artist), Some(album)) => {
+                                format!("by {} on {}", artist.name, album.name)
+                            }
+                        },
+                        if current {
+                            Color::from_int_rgb(97, 38, 89)
+                        } else {
+                            Color::from_int_rgb(60, 38, 97)
+                        },
+                        None,
+                        Vec2::new(0.0, 0.5),
+                    )
+                    .with_layout(TextLayoutMode::SingleLineEllipsis),
+                ),
             ],
             path,
             song,
@@ -526,8 +919,15 @@ impl QueueSong {
             copy: false,
             always_copy: false,
             copy_on_mouse_down: false,
+            selection: None,
+            flash: None,
         }
     }
+    fn selectable(mut self, selection: &QueueSelection) -> Self {
+        selection.add_shown(self.path.clone());
+        self.selection = Some(selection.clone());
+        self
+    }
 }
 
 impl GuiElem for QueueSong {
@@ -563,9 +963,7 @@ impl GuiElem for QueueSong {
         if self.mouse && button == MouseButton::Left {
             self.mouse = false;
             if e.take() && !self.always_copy {
-                vec![GuiAction::SendToServer(Action::QueueGoto(
-                    self.path.clone(),
-                ))]
+                queue_click(&self.selection, &self.path)
             } else {
                 vec![]
             }
@@ -576,26 +974,15 @@ impl GuiElem for QueueSong {
     fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
         self.insert_below = info.mouse_pos.y > info.pos.top_left().y + info.pos.height() * 0.5;
         if !self.always_copy && info.dragging.is_some() && info.pos.contains(info.mouse_pos) {
-            g.draw_rectangle(
-                if self.insert_below {
-                    Rectangle::new(
-                        Vec2::new(
-                            info.pos.top_left().x,
-                            info.pos.top_left().y + info.pos.height() * 0.75,
-                        ),
-                        *info.pos.bottom_right(),
-                    )
-                } else {
-                    Rectangle::new(
-                        *info.pos.top_left(),
-                        Vec2::new(
-                            info.pos.bottom_right().x,
-                            info.pos.top_left().y + info.pos.height() * 0.25,
-                        ),
-                    )
-                },
-                Color::from_rgba(1.0, 1.0, 1.0, 0.25),
-            );
+            let mut to = self.path.clone();
+            if self.insert_below {
+                if let Some(l) = to.last_mut() {
+                    *l += 1;
+                }
+            }
+            if !dragged_queue_path(info).is_some_and(|from| queue_move_is_noop(from, &to)) {
+                draw_insert_indicator(info, g, self.insert_below);
+            }
         }
         if !self.mouse {
             self.mouse_pos = Vec2::new(
@@ -605,11 +992,37 @@ impl GuiElem for QueueSong {
         }
         generic_queue_draw(
             info,
+            g,
             &self.path,
             || QueueContent::Song(self.song.id).into(),
             &mut self.mouse,
             self.copy_on_mouse_down,
+            &self.selection,
         );
+        if let Some(start) = self.flash {
+            let t = start.elapsed().as_secs_f32() / FLASH_DURATION.as_secs_f32();
+            if t < 1.0 {
+                g.draw_rectangle(
+                    info.pos.clone(),
+                    Theme::with_alpha(info.gui_config.theme.focus, 0.5 * (1.0 - t)),
+                );
+                if let Some(h) = &info.helper {
+                    h.request_redraw();
+                }
+            } else {
+                self.flash = None;
+            }
+        }
+    }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        enter_goes_to(e, down, key, &self.path)
     }
     fn key_watch(
         &mut self,
@@ -663,6 +1076,7 @@ struct QueueFolder {
     copy: bool,
     always_copy: bool,
     copy_on_mouse_down: bool,
+    selection: Option<QueueSelection>,
 }
 impl QueueFolder {
     pub fn new(
@@ -681,7 +1095,7 @@ impl QueueFolder {
             config: if path.is_empty() {
                 config
             } else {
-                config.w_mouse().w_keyboard_watch()
+                config.w_mouse().w_keyboard_watch().w_keyboard_focus()
             }
             .w_drag_target(),
             c_name: Label::new(
@@ -699,7 +1113,8 @@ impl QueueFolder {
                 Color::from_int_rgb(52, 132, 50),
                 None,
                 Vec2::new(0.0, 0.5),
-            ),
+            )
+            .with_layout(TextLayoutMode::SingleLineEllipsis),
             path,
             queue,
             current,
@@ -709,8 +1124,14 @@ impl QueueFolder {
             copy: false,
             always_copy: false,
             copy_on_mouse_down: false,
+            selection: None,
         }
     }
+    fn selectable(mut self, selection: &QueueSelection) -> Self {
+        selection.add_shown(self.path.clone());
+        self.selection = Some(selection.clone());
+        self
+    }
     fn alwayscopy(mut self) -> Self {
         self.always_copy = true;
         self.copy = true;
@@ -743,26 +1164,25 @@ impl GuiElem for QueueFolder {
     fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
         self.insert_into = info.mouse_pos.y > info.pos.top_left().y + info.pos.height() * 0.5;
         if !self.always_copy && info.dragging.is_some() && info.pos.contains(info.mouse_pos) {
-            g.draw_rectangle(
-                if self.insert_into {
-                    Rectangle::new(
-                        Vec2::new(
-                            info.pos.top_left().x,
-                            info.pos.top_left().y + info.pos.height() * 0.5,
-                        ),
-                        *info.pos.bottom_right(),
-                    )
-                } else {
-                    Rectangle::new(
-                        *info.pos.top_left(),
-                        Vec2::new(
-                            info.pos.bottom_right().x,
-                            info.pos.top_left().y + info.pos.height() * 0.25,
+            let from = dragged_queue_path(info);
+            if self.insert_into {
+                if !from.is_some_and(|from| self.path.starts_with(from)) {
+                    g.draw_rectangle(
+                        Rectangle::new(
+                            Vec2::new(
+                                info.pos.top_left().x,
+                                info.pos.top_left().y + info.pos.height() * 0.5,
+                            ),
+                            *info.pos.bottom_right(),
                         ),
-                    )
-                },
-                Color::from_rgba(1.0, 1.0, 1.0, 0.25),
-            );
+                        info.gui_config
+                            .theme
+                            .shade(Color::from_rgba(1.0, 1.0, 1.0, 0.25)),
+                    );
+                }
+            } else if !from.is_some_and(|from| queue_move_is_noop(from, &self.path)) {
+                draw_insert_indicator(info, g, false);
+            }
         }
         if !self.mouse {
             self.mouse_pos = Vec2::new(
@@ -772,10 +1192,12 @@ impl GuiElem for QueueFolder {
         }
         generic_queue_draw(
             info,
+            g,
             &self.path,
             || QueueContent::Folder(self.queue.clone()).into(),
             &mut self.mouse,
             self.copy_on_mouse_down,
+            &self.selection,
         );
     }
     fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
@@ -783,14 +1205,41 @@ impl GuiElem for QueueFolder {
             self.mouse = true;
             self.copy_on_mouse_down = self.copy;
         } else if button == MouseButton::Right && e.take() {
-            // return vec![GuiAction::ContextMenu(Some(vec![Box::new(
-            //     Panel::with_background(GuiElemCfg::default(), (), Color::DARK_GRAY),
-            // )]))];
-            return vec![GuiAction::SendToServer(if self.queue.order.is_some() {
-                Action::QueueUnshuffle(self.path.clone())
-            } else {
-                Action::QueueShuffle(self.path.clone())
-            })];
+            let button = |action: Action, text: &str| {
+                Box::new(Button::new(
+                    GuiElemCfg::default(),
+                    move |_| vec![GuiAction::SendToServer(action.clone())],
+                    [Label::new(
+                        GuiElemCfg::default(),
+                        text.to_owned(),
+                        Color::WHITE,
+                        None,
+                        Vec2::new_y(0.5),
+                    )],
+                )) as Box<dyn GuiElem>
+            };
+            return vec![GuiAction::ContextMenu(Some(vec![
+                if self.queue.order.is_some() {
+                    button(Action::QueueUnshuffle(self.path.clone()), "Unshuffle")
+                } else {
+                    button(Action::QueueShuffle(self.path.clone()), "Shuffle")
+                },
+                button(
+                    Action::QueueShuffleInPlace(self.path.clone()),
+                    "Shuffle permanently",
+                ),
+                button(Action::QueueClear(self.path.clone()), "Clear"),
+                button(Action::QueueDuplicate(self.path.clone()), "Duplicate"),
+                button(
+                    Action::QueueDedupSongs(self.path.clone()),
+                    "Remove duplicate songs",
+                ),
+                menu_entry("Save as playlist...", {
+                    let path = self.path.clone();
+                    let name = self.queue.name.clone();
+                    move || vec![gui_playlists::save_menu(path.clone(), name.clone())]
+                }),
+            ]))];
         }
         vec![]
     }
@@ -798,9 +1247,7 @@ impl GuiElem for QueueFolder {
         if self.mouse && button == MouseButton::Left {
             self.mouse = false;
             if e.take() && !self.always_copy {
-                vec![GuiAction::SendToServer(Action::QueueGoto(
-                    self.path.clone(),
-                ))]
+                queue_click(&self.selection, &self.path)
             } else {
                 vec![]
             }
@@ -808,6 +1255,16 @@ impl GuiElem for QueueFolder {
             vec![]
         }
     }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        enter_goes_to(e, down, key, &self.path)
+    }
     fn key_watch(
         &mut self,
         _e: &mut EventInfo,
@@ -885,7 +1342,7 @@ impl GuiElem for QueueIndentEnd {
         if info.dragging.is_some() {
             g.draw_rectangle(
                 info.pos.clone(),
-                Color::from_rgba(
+                info.gui_config.theme.shade(Color::from_rgba(
                     1.0,
                     1.0,
                     1.0,
@@ -894,7 +1351,7 @@ impl GuiElem for QueueIndentEnd {
                     } else {
                         0.2
                     },
-                ),
+                )),
             );
         }
     }
@@ -923,6 +1380,7 @@ struct QueueLoop {
     copy: bool,
     always_copy: bool,
     copy_on_mouse_down: bool,
+    selection: Option<QueueSelection>,
 }
 impl QueueLoop {
     pub fn new(config: GuiElemCfg, path: Vec<usize>, queue: Queue, current: bool) -> Self {
@@ -930,16 +1388,19 @@ impl QueueLoop {
             config: if path.is_empty() {
                 config
             } else {
-                config.w_mouse().w_keyboard_watch()
+                config.w_mouse().w_keyboard_watch().w_keyboard_focus()
             }
             .w_drag_target(),
-            children: vec![Box::new(Label::new(
-                GuiElemCfg::default(),
-                Self::get_label_text(&queue),
-                Color::from_int_rgb(217, 197, 65),
-                None,
-                Vec2::new(0.0, 0.5),
-            ))],
+            children: vec![Box::new(
+                Label::new(
+                    GuiElemCfg::default(),
+                    Self::get_label_text(&queue, !path.is_empty()),
+                    Color::from_int_rgb(217, 197, 65),
+                    None,
+                    Vec2::new(0.0, 0.5),
+                )
+                .with_layout(TextLayoutMode::SingleLineEllipsis),
+            )],
             path,
             queue,
             current,
@@ -948,23 +1409,35 @@ impl QueueLoop {
             copy: false,
             always_copy: false,
             copy_on_mouse_down: false,
+            selection: None,
         }
     }
+    fn selectable(mut self, selection: &QueueSelection) -> Self {
+        selection.add_shown(self.path.clone());
+        self.selection = Some(selection.clone());
+        self
+    }
     fn alwayscopy(mut self) -> Self {
         self.always_copy = true;
         self.copy = true;
         self.config.scroll_events = true;
         self
     }
-    fn get_label_text(queue: &Queue) -> String {
-        match queue.content() {
-            QueueContent::Loop(total, _current, _) => {
-                if *total == 0 {
-                    format!("repeat forever")
-                } else if *total == 1 {
-                    format!("repeat 1 time")
+    fn get_label_text(queue: &Queue, show_progress: bool) -> String {
+        let content = queue.content();
+        match (content.loop_total(), content.loop_iterations_done()) {
+            (Some(total), Some(done)) => {
+                let text = match total {
+                    None => format!("repeat forever"),
+                    Some(1) => format!("repeat 1 time"),
+                    Some(total) => format!("repeat {total} times"),
+                };
+                if !show_progress {
+                    text
+                } else if let Some(total) = total {
+                    format!("{text} ({}/{total})", done + 1)
                 } else {
-                    format!("repeat {total} times")
+                    format!("{text} ({})", done + 1)
                 }
             }
             _ => "[???]".to_string(),
@@ -1007,11 +1480,11 @@ impl GuiElem for QueueLoop {
                 .downcast_mut::<Label>()
                 .unwrap()
                 .content
-                .text() = Self::get_label_text(&self.queue);
+                .text() = Self::get_label_text(&self.queue, false);
         }
         vec![]
     }
-    fn draw(&mut self, info: &mut DrawInfo, _g: &mut speedy2d::Graphics2D) {
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
         if !self.mouse {
             self.mouse_pos = Vec2::new(
                 info.mouse_pos.x - self.config.pixel_pos.top_left().x,
@@ -1020,16 +1493,31 @@ impl GuiElem for QueueLoop {
         }
         generic_queue_draw(
             info,
+            g,
             &self.path,
             || self.queue.clone(),
             &mut self.mouse,
             self.copy_on_mouse_down,
+            &self.selection,
         );
     }
     fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
         if button == MouseButton::Left && e.take() {
             self.mouse = true;
             self.copy_on_mouse_down = self.copy;
+        } else if button == MouseButton::Right && !self.always_copy && e.take() {
+            let action = Action::QueueLoopBreak(self.path.clone());
+            return vec![GuiAction::ContextMenu(Some(vec![Box::new(Button::new(
+                GuiElemCfg::default(),
+                move |_| vec![GuiAction::SendToServer(action.clone())],
+                [Label::new(
+                    GuiElemCfg::default(),
+                    "Stop after this pass".to_owned(),
+                    Color::WHITE,
+                    None,
+                    Vec2::new_y(0.5),
+                )],
+            ))]))];
         }
         vec![]
     }
@@ -1037,9 +1525,7 @@ impl GuiElem for QueueLoop {
         if self.mouse && button == MouseButton::Left {
             self.mouse = false;
             if e.take() && !self.always_copy {
-                vec![GuiAction::SendToServer(Action::QueueGoto(
-                    self.path.clone(),
-                ))]
+                queue_click(&self.selection, &self.path)
             } else {
                 vec![]
             }
@@ -1047,6 +1533,16 @@ impl GuiElem for QueueLoop {
             vec![]
         }
     }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        enter_goes_to(e, down, key, &self.path)
+    }
     fn key_watch(
         &mut self,
         _e: &mut EventInfo,
@@ -1075,17 +1571,444 @@ impl GuiElem for QueueLoop {
     }
 }
 
-fn dragged_add_to_queue<T: 'static>(
+struct QueueRandom {
+    config: GuiElemCfg,
+    children: Vec<Box<dyn GuiElem>>,
+    path: Vec<usize>,
+    queue: Queue,
+    mouse: bool,
+    copy: bool,
+    always_copy: bool,
+    copy_on_mouse_down: bool,
+    /// only for the control flow element: the filters which can be chosen with a right click
+    presets: Vec<(String, RandomFilter)>,
+    sender: Option<std::sync::mpsc::Sender<QVMsg>>,
+    /// describes the filter, part of the label
+    filter_text: String,
+    selection: Option<QueueSelection>,
+}
+impl QueueRandom {
+    pub fn new(
+        config: GuiElemCfg,
+        path: Vec<usize>,
+        queue: Queue,
+        current: bool,
+        db: Option<&Database>,
+    ) -> Self {
+        let filter_text = match (queue.content(), db) {
+            (QueueContent::Random(filter, _, _), Some(db)) => filter.describe(db),
+            _ => String::new(),
+        };
+        Self {
+            config: if path.is_empty() {
+                config
+            } else {
+                config.w_mouse().w_keyboard_watch().w_keyboard_focus()
+            }
+            .w_drag_target(),
+            children: vec![Box::new(Label::new(
+                GuiElemCfg::default(),
+                Self::label_text(&queue, &filter_text),
+                if current {
+                    Color::from_int_rgb(112, 196, 214)
+                } else {
+                    Color::from_int_rgb(65, 163, 217)
+                },
+                None,
+                Vec2::new(0.0, 0.5),
+            ))],
+            path,
+            queue,
+            mouse: false,
+            copy: false,
+            always_copy: false,
+            copy_on_mouse_down: false,
+            presets: vec![],
+            sender: None,
+            filter_text,
+            selection: None,
+        }
+    }
+    fn selectable(mut self, selection: &QueueSelection) -> Self {
+        selection.add_shown(self.path.clone());
+        self.selection = Some(selection.clone());
+        self
+    }
+    fn alwayscopy(mut self, sender: std::sync::mpsc::Sender<QVMsg>) -> Self {
+        self.always_copy = true;
+        self.copy = true;
+        self.sender = Some(sender);
+        self
+    }
+    fn label_text(queue: &Queue, filter_text: &str) -> String {
+        let mut text = "random songs".to_owned();
+        if let QueueContent::Random(_, weighting, _) = queue.content() {
+            for part in [filter_text, weighting.describe()] {
+                if !part.is_empty() {
+                    text.push(' ');
+                    text.push_str(part);
+                }
+            }
+        }
+        text
+    }
+    fn update_label(&mut self) {
+        *self.children[0]
+            .any_mut()
+            .downcast_mut::<Label>()
+            .unwrap()
+            .content
+            .text() = Self::label_text(&self.queue, &self.filter_text);
+    }
+}
+impl GuiElem for QueueRandom {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new(self.children.iter_mut().map(|v| v.elem_mut()))
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
+        if self.always_copy && self.presets.is_empty() {
+            self.presets = info
+                .gui_config
+                .filter_presets_song
+                .iter()
+                .filter_map(|(name, filter)| {
+                    Some((
+                        name.clone(),
+                        match filter {
+                            FilterType::TagEq(tag) => RandomFilter::TagEq(tag.clone()),
+                            FilterType::TagWithValueInt(key, min, max) => {
+                                RandomFilter::TagWithValueInt(key.clone(), *min, *max)
+                            }
+                            FilterType::Nested(_)
+                            | FilterType::Not(_)
+                            | FilterType::TagStartsWith(_)
+                            | FilterType::TagRegex(_)
+                            | FilterType::Duration(..)
+                            | FilterType::NoCover => return None,
+                        },
+                    ))
+                })
+                .collect();
+        }
+        generic_queue_draw(
+            info,
+            g,
+            &self.path,
+            || self.queue.clone(),
+            &mut self.mouse,
+            self.copy_on_mouse_down,
+            &self.selection,
+        );
+    }
+    fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if button == MouseButton::Left && e.take() {
+            self.mouse = true;
+            self.copy_on_mouse_down = self.copy;
+        } else if button == MouseButton::Right && e.take() {
+            if let Some(sender) = &self.sender {
+                let button = |msg: QVMsg, label: String| {
+                    let sender = sender.clone();
+                    Box::new(Button::new(
+                        GuiElemCfg::default(),
+                        move |_| {
+                            _ = sender.send(msg.clone());
+                            vec![]
+                        },
+                        [Label::new(
+                            GuiElemCfg::default(),
+                            label,
+                            Color::WHITE,
+                            None,
+                            Vec2::new_y(0.5),
+                        )],
+                    )) as Box<dyn GuiElem>
+                };
+                // choose which songs the new element should pick from, and how
+                return vec![GuiAction::ContextMenu(Some(
+                    [(String::new(), RandomFilter::All)]
+                        .into_iter()
+                        .chain(self.presets.iter().cloned())
+                        .map(|(name, filter)| {
+                            let label = if name.is_empty() {
+                                "all songs".to_owned()
+                            } else {
+                                name.clone()
+                            };
+                            button(
+                                QVMsg::ControlFlowElementsSetRandomFilter(name, filter),
+                                label,
+                            )
+                        })
+                        .chain(
+                            [
+                                (RandomWeighting::Uniform, "any song"),
+                                (RandomWeighting::PreferUnplayed, "prefer unplayed"),
+                                (RandomWeighting::PreferFavorites, "prefer favorites"),
+                            ]
+                            .into_iter()
+                            .map(|(weighting, label)| {
+                                button(
+                                    QVMsg::ControlFlowElementsSetRandomWeighting(weighting),
+                                    label.to_owned(),
+                                )
+                            }),
+                        )
+                        .collect(),
+                ))];
+            }
+        }
+        vec![]
+    }
+    fn mouse_up(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if self.mouse && button == MouseButton::Left {
+            self.mouse = false;
+            if e.take() && !self.always_copy {
+                queue_click(&self.selection, &self.path)
+            } else {
+                vec![]
+            }
+        } else {
+            vec![]
+        }
+    }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        enter_goes_to(e, down, key, &self.path)
+    }
+    fn key_watch(
+        &mut self,
+        _e: &mut EventInfo,
+        modifiers: ModifiersState,
+        _down: bool,
+        _key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        self.copy = self.always_copy || modifiers.ctrl();
+        vec![]
+    }
+    fn dragged(&mut self, e: &mut EventInfo, dragged: Dragging) -> Vec<GuiAction> {
+        if !self.always_copy {
+            e.take();
+            dragged_add_to_queue(
+                dragged,
+                self.path.clone(),
+                |p, q| Action::QueueAdd(p, q, Req::none()),
+                |p, q| Action::QueueMoveInto(q, p),
+            )
+        } else {
+            vec![]
+        }
+    }
+}
+
+struct QueueAlbumRef {
+    config: GuiElemCfg,
+    c_name: Label,
+    path: Vec<usize>,
+    queue: Queue,
+    insert_below: bool,
+    mouse: bool,
+    copy: bool,
+    copy_on_mouse_down: bool,
+    selection: Option<QueueSelection>,
+}
+impl QueueAlbumRef {
+    pub fn new(
+        config: GuiElemCfg,
+        path: Vec<usize>,
+        queue: Queue,
+        db: &Database,
+        current: bool,
+    ) -> Self {
+        let name = match queue.content() {
+            QueueContent::AlbumRef(id, _) => match db.albums().get(id) {
+                Some(album) => format!("{}  ({})", album.name, album.songs.len()),
+                None => "< unknown album >".to_owned(),
+            },
+            _ => "[???]".to_owned(),
+        };
+        Self {
+            config: config
+                .w_mouse()
+                .w_keyboard_watch()
+                .w_keyboard_focus()
+                .w_drag_target(),
+            c_name: Label::new(
+                GuiElemCfg::default(),
+                name,
+                if current {
+                    Color::from_int_rgb(64, 170, 62)
+                } else {
+                    Color::from_int_rgb(52, 132, 50)
+                },
+                None,
+                Vec2::new(0.0, 0.5),
+            ),
+            path,
+            queue,
+            insert_below: false,
+            mouse: false,
+            copy: false,
+            copy_on_mouse_down: false,
+            selection: None,
+        }
+    }
+    fn selectable(mut self, selection: &QueueSelection) -> Self {
+        selection.add_shown(self.path.clone());
+        self.selection = Some(selection.clone());
+        self
+    }
+}
+impl GuiElem for QueueAlbumRef {
+    fn config(&self) -> &GuiElemCfg {
+        &self.config
+    }
+    fn config_mut(&mut self) -> &mut GuiElemCfg {
+        &mut self.config
+    }
+    fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
+        Box::new([self.c_name.elem_mut()].into_iter())
+    }
+    fn any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn elem(&self) -> &dyn GuiElem {
+        self
+    }
+    fn elem_mut(&mut self) -> &mut dyn GuiElem {
+        self
+    }
+    fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
+        self.insert_below = info.mouse_pos.y > info.pos.top_left().y + info.pos.height() * 0.5;
+        if info.dragging.is_some() && info.pos.contains(info.mouse_pos) {
+            let mut to = self.path.clone();
+            if self.insert_below {
+                if let Some(l) = to.last_mut() {
+                    *l += 1;
+                }
+            }
+            if !dragged_queue_path(info).is_some_and(|from| queue_move_is_noop(from, &to)) {
+                draw_insert_indicator(info, g, self.insert_below);
+            }
+        }
+        generic_queue_draw(
+            info,
+            g,
+            &self.path,
+            || self.queue.clone(),
+            &mut self.mouse,
+            self.copy_on_mouse_down,
+            &self.selection,
+        );
+    }
+    fn mouse_down(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if button == MouseButton::Left && e.take() {
+            self.mouse = true;
+            self.copy_on_mouse_down = self.copy;
+        }
+        vec![]
+    }
+    fn mouse_up(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
+        if self.mouse && button == MouseButton::Left {
+            self.mouse = false;
+            if e.take() {
+                queue_click(&self.selection, &self.path)
+            } else {
+                vec![]
+            }
+        } else {
+            vec![]
+        }
+    }
+    fn key_focus(
+        &mut self,
+        e: &mut EventInfo,
+        _modifiers: ModifiersState,
+        down: bool,
+        key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        enter_goes_to(e, down, key, &self.path)
+    }
+    fn key_watch(
+        &mut self,
+        _e: &mut EventInfo,
+        modifiers: ModifiersState,
+        _down: bool,
+        _key: Option<VirtualKeyCode>,
+        _scan: speedy2d::window::KeyScancode,
+    ) -> Vec<GuiAction> {
+        self.copy = modifiers.ctrl();
+        vec![]
+    }
+    fn dragged(&mut self, e: &mut EventInfo, dragged: Dragging) -> Vec<GuiAction> {
+        e.take();
+        let insert_below = self.insert_below;
+        dragged_add_to_queue(
+            dragged,
+            self.path.clone(),
+            move |mut p: Vec<usize>, q| {
+                if let Some(j) = p.pop() {
+                    Action::QueueInsert(p, if insert_below { j + 1 } else { j }, q, Req::none())
+                } else {
+                    Action::QueueAdd(p, q, Req::none())
+                }
+            },
+            move |mut p, q| {
+                if insert_below {
+                    if let Some(l) = p.last_mut() {
+                        *l += 1;
+                    }
+                }
+                Action::QueueMove(q, p)
+            },
+        )
+    }
+}
+
+fn dragged_add_to_queue<T: Clone + 'static>(
     dragged: Dragging,
     data: T,
-    f_queues: impl FnOnce(T, Vec<Queue>) -> Action + 'static,
+    f_queues: impl FnOnce(T, Vec<Queue>) -> Action + Clone + 'static,
     f_queue_by_path: impl FnOnce(T, Vec<usize>) -> Action + 'static,
 ) -> Vec<GuiAction> {
     match dragged {
         Dragging::Artist(id) => {
             vec![GuiAction::Build(Box::new(move |db| {
                 if let Some(q) = add_to_queue_artist_by_id(id, db) {
-                    vec![GuiAction::SendToServer(f_queues(data, vec![q]))]
+                    let name = db.artists().get(&id).map(|a| a.name.as_str());
+                    add_or_random_menu(
+                        f_queues.clone()(data.clone(), vec![q]),
+                        f_queues(data, vec![random_queue(RandomFilter::Artist(id))]),
+                        name.unwrap_or_default(),
+                    )
                 } else {
                     vec![]
                 }
@@ -1094,7 +2017,12 @@ fn dragged_add_to_queue<T: 'static>(
         Dragging::Album(id) => {
             vec![GuiAction::Build(Box::new(move |db| {
                 if let Some(q) = add_to_queue_album_by_id(id, db) {
-                    vec![GuiAction::SendToServer(f_queues(data, vec![q]))]
+                    let name = db.albums().get(&id).map(|a| a.name.as_str());
+                    add_or_random_menu(
+                        f_queues.clone()(data.clone(), vec![q]),
+                        f_queues(data, vec![random_queue(RandomFilter::Album(id))]),
+                        name.unwrap_or_default(),
+                    )
                 } else {
                     vec![]
                 }
@@ -1104,34 +2032,123 @@ fn dragged_add_to_queue<T: 'static>(
             let q = QueueContent::Song(id).into();
             vec![GuiAction::SendToServer(f_queues(data, vec![q]))]
         }
-        Dragging::Queue(q) => vec![GuiAction::SendToServer(match q {
-            Ok(q) => f_queues(data, vec![q]),
-            Err(p) => f_queue_by_path(data, p),
-        })],
-        Dragging::Queues(q) => vec![GuiAction::SendToServer(f_queues(data, q))],
+        Dragging::Queue(Ok(q)) => vec![GuiAction::SendToServer(f_queues(data, vec![q]))],
+        Dragging::Queue(Err(p)) => {
+            // dropping an element onto itself or into its own children does nothing
+            let action = f_queue_by_path(data, p);
+            match &action {
+                Action::QueueMove(from, to) if queue_move_is_noop(from, to) => vec![],
+                Action::QueueMoveInto(from, parent) if parent.starts_with(from) => vec![],
+                _ => vec![GuiAction::SendToServer(action)],
+            }
+        }
+        Dragging::Queues(Ok(q)) => vec![GuiAction::SendToServer(f_queues(data, q))],
+        Dragging::Queues(Err(paths)) => {
+            // the action for the first element says where the elements should be moved to
+            let Some(first) = paths.first() else {
+                return vec![];
+            };
+            let actions = match f_queue_by_path(data, first.clone()) {
+                Action::QueueMove(_, to) => queue_move_many(paths, to, false),
+                Action::QueueMoveInto(_, parent) => queue_move_many(paths, parent, true),
+                _ => vec![],
+            };
+            if actions.is_empty() {
+                vec![]
+            } else {
+                vec![GuiAction::SendToServer(Action::Multiple(actions))]
+            }
+        }
     }
 }
 
-fn add_to_queue_album_by_id(id: AlbumId, db: &Database) -> Option<Queue> {
-    if let Some(album) = db.albums().get(&id) {
-        Some(
-            QueueContent::Folder(musicdb_lib::data::queue::QueueFolder {
-                index: 0,
-                content: album
-                    .songs
-                    .iter()
-                    .map(|id| QueueContent::Song(*id).into())
-                    .collect(),
-                name: album.name.clone(),
-                order: None,
-            })
-            .into(),
-        )
+/// the `QueueMove`s (or `QueueMoveInto`s, if `into` is true) which move the elements at `paths` to `to`
+/// without changing their order. `paths` must be sorted.
+fn queue_move_many(mut paths: Vec<Vec<usize>>, mut to: Vec<usize>, into: bool) -> Vec<Action> {
+    // nothing can be moved into itself
+    if paths
+        .iter()
+        .any(|p| to.starts_with(p) && (into || to.len() > p.len()))
+    {
+        return vec![];
+    }
+    let mut actions = vec![];
+    for i in 0..paths.len() {
+        let from = paths[i].clone();
+        if into {
+            actions.push(Action::QueueMoveInto(from.clone(), to.clone()));
+            path_after_remove(&mut to, &from);
+            for p in &mut paths[i + 1..] {
+                path_after_remove(p, &from);
+            }
+        } else {
+            // where the element will be after it was moved
+            let mut moved = to.clone();
+            path_after_remove(&mut moved, &from);
+            if moved != from {
+                actions.push(Action::QueueMove(from.clone(), to));
+            }
+            for p in &mut paths[i + 1..] {
+                path_after_remove(p, &from);
+                path_after_insert(p, &moved);
+            }
+            // the next element goes below this one
+            to = moved;
+            if let Some(l) = to.last_mut() {
+                *l += 1;
+            }
+        }
+    }
+    actions
+}
+/// adjusts `path` after the element at `removed` was removed
+fn path_after_remove(path: &mut [usize], removed: &[usize]) {
+    if let Some((i, parent)) = removed.split_last() {
+        if path.len() > parent.len() && path.starts_with(parent) && path[parent.len()] > *i {
+            path[parent.len()] -= 1;
+        }
+    }
+}
+/// adjusts `path` after an element was inserted at `inserted`
+fn path_after_insert(path: &mut [usize], inserted: &[usize]) {
+    if let Some((i, parent)) = inserted.split_last() {
+        if path.len() > parent.len() && path.starts_with(parent) && path[parent.len()] >= *i {
+            path[parent.len()] += 1;
+        }
+    }
+}
+
+fn random_queue(filter: RandomFilter) -> Queue {
+    QueueContent::Random(filter, RandomWeighting::Uniform, Default::default()).into()
+}
+/// lets the user choose between adding an artist/album as-is or adding random songs from it
+fn add_or_random_menu(add: Action, random: Action, name: &str) -> Vec<GuiAction> {
+    let button = |action: Action, text: String| {
+        Box::new(Button::new(
+            GuiElemCfg::default(),
+            move |_| vec![GuiAction::SendToServer(action.clone())],
+            [Label::new(
+                GuiElemCfg::default(),
+                text,
+                Color::WHITE,
+                None,
+                Vec2::new_y(0.5),
+            )],
+        )) as Box<dyn GuiElem>
+    };
+    vec![GuiAction::ContextMenu(Some(vec![
+        button(add, format!("Add {name}")),
+        button(random, format!("Random songs from {name}")),
+    ]))]
+}
+pub fn add_to_queue_album_by_id(id: AlbumId, db: &Database) -> Option<Queue> {
+    if db.albums().contains_key(&id) {
+        Some(QueueContent::AlbumRef(id, 0).into())
     } else {
         None
     }
 }
-fn add_to_queue_artist_by_id(id: ArtistId, db: &Database) -> Option<Queue> {
+pub fn add_to_queue_artist_by_id(id: ArtistId, db: &Database) -> Option<Queue> {
     if let Some(artist) = db.artists().get(&id) {
         Some(
             QueueContent::Folder(musicdb_lib::data::queue::QueueFolder {
@@ -1156,3 +2173,64 @@ fn add_to_queue_artist_by_id(id: ArtistId, db: &Database) -> Option<Queue> {
         None
     }
 }
+
+#[test]
+fn test_queue_move_is_noop() {
+    assert!(queue_move_is_noop(&[1], &[1]));
+    assert!(queue_move_is_noop(&[1], &[2]));
+    assert!(queue_move_is_noop(&[1], &[1, 0]));
+    assert!(queue_move_is_noop(&[0, 3], &[0, 3, 2, 1]));
+    assert!(!queue_move_is_noop(&[2], &[1]));
+    assert!(!queue_move_is_noop(&[1], &[3]));
+    assert!(!queue_move_is_noop(&[1], &[2, 0]));
+    assert!(!queue_move_is_noop(&[0, 1], &[1, 2]));
+}
+
+#[test]
+fn test_queue_move_many() {
+    fn show(queue: &Queue) -> String {
+        match queue.content() {
+            QueueContent::Song(id) => id.to_string(),
+            QueueContent::Folder(folder) => folder.iter().map(show).collect::<Vec<_>>().join(" "),
+            _ => unreachable!(),
+        }
+    }
+    let folder = |ids: &[u64]| -> Queue {
+        QueueContent::Folder(musicdb_lib::data::queue::QueueFolder {
+            index: 0,
+            content: ids
+                .iter()
+                .map(|id| QueueContent::Song(*id).into())
+                .collect(),
+            name: String::new(),
+            order: None,
+        })
+        .into()
+    };
+    let moved = |paths: &[&[usize]], to: &[usize], into: bool| {
+        let mut db = Database::new_clientside();
+        db.queue = folder(&[0, 1, 2, 3, 4, 5]);
+        db.queue.add_to_end(vec![folder(&[10, 11])], false);
+        let paths = paths.iter().map(|p| p.to_vec()).collect();
+        for action in queue_move_many(paths, to.to_vec(), into) {
+            db.apply_action_unchecked_seq(action, None);
+        }
+        show(&db.queue)
+    };
+    assert_eq!(moved(&[&[1], &[3]], &[5], false), "0 2 4 1 3 5 10 11");
+    assert_eq!(moved(&[&[4], &[5]], &[0], false), "4 5 0 1 2 3 10 11");
+    assert_eq!(moved(&[&[2], &[3]], &[2], false), "0 1 2 3 4 5 10 11");
+    assert_eq!(moved(&[&[0], &[6, 1]], &[3], false), "1 2 0 11 3 4 5 10");
+    assert_eq!(moved(&[&[1], &[6, 0]], &[6], true), "0 2 3 4 5 11 1 10");
+    assert!(queue_move_many(vec![vec![6]], vec![6, 1], false).is_empty());
+    assert!(queue_move_many(vec![vec![6]], vec![6], true).is_empty());
+}
+
+#[test]
+fn test_fmt_minutes() {
+    assert_eq!(fmt_minutes(0), "0 min");
+    assert_eq!(fmt_minutes(29_999), "0 min");
+    assert_eq!(fmt_minutes(90_000), "2 min");
+    assert_eq!(fmt_minutes(59 * 60_000), "59 min");
+    assert_eq!(fmt_minutes(3_700_000), "1 h 2 min");
+}
@@ -2,7 +2,7 @@ use std::time::Instant;
 
 use musicdb_lib::{
     data::queue::{QueueContent, QueueFolder},
-    server::{Action, Req},
+    server::Action,
 };
 use speedy2d::{color::Color, dimen::Vec2, shape::Rectangle, window::VirtualKeyCode, Graphics2D};
 use uianimator::{default_animator_f64_quadratic::DefaultAnimatorF64Quadratic, Animator};
@@ -10,13 +10,15 @@ use uianimator::{default_animator_f64_quadratic::DefaultAnimatorF64Quadratic, An
 use crate::{
     gui::{
         DrawInfo, EventInfo, GuiAction, GuiElem, GuiElemCfg, GuiElemChildren, KeyAction,
-        KeyBinding, SpecificGuiElem,
+        KeyBinding, SpecificGuiElem, LINE_HEIGHT_DEFAULT, LINE_HEIGHT_STEP,
     },
     gui_base::{Button, Panel},
+    gui_edit::Editor,
     gui_edit_song::EditorForSongs,
     gui_idle_display::IdleDisplay,
     gui_library::LibraryBrowser,
     gui_notif::NotifOverlay,
+    gui_playlists,
     gui_queue::QueueViewer,
     gui_settings::Settings,
     gui_song_adder::SongAdder,
@@ -43,11 +45,52 @@ pub fn transition(p: f32) -> f32 {
     3.0 * p * p - 2.0 * p * p * p
 }
 
+/// the key actions which can be rebound in the `[keys]` section of config_gui.toml
+pub const KEY_ACTION_NAMES: &[&str] = &[
+    "quit",
+    "idle",
+    "play_pause",
+    "next_song",
+    "previous",
+    "seek_forward",
+    "seek_backward",
+    "volume_up",
+    "volume_down",
+    "search",
+    "jump_to_current",
+    "scale_up",
+    "scale_down",
+    "scale_reset",
+];
+
+fn keybind(
+    name: &'static str,
+    binds: Vec<(KeyBinding, bool)>,
+    category: &str,
+    title: &str,
+    description: &str,
+    action: impl FnMut() -> Vec<GuiAction> + 'static,
+) -> GuiAction {
+    GuiAction::AddKeybind(
+        binds,
+        KeyAction {
+            name,
+            category: category.to_owned(),
+            title: title.to_owned(),
+            description: description.to_owned(),
+            action: Box::new(action),
+            enabled: true,
+        },
+        Box::new(|_| {}),
+    )
+}
+
 pub struct GuiScreen {
     config: GuiElemCfg,
     pub c_notif_overlay: NotifOverlay,
     pub c_idle_display: IdleDisplay,
     pub c_editing_songs: Option<EditorForSongs>,
+    pub c_editor: Option<Editor>,
     pub c_status_bar: StatusBar,
     pub c_settings: Settings,
     pub c_song_adder: Option<SongAdder>,
@@ -64,6 +107,8 @@ pub struct GuiScreen {
 }
 pub struct MainView {
     pub button_clear_queue: Button<[Label; 1]>,
+    pub button_autoplay: Button<[Label; 1]>,
+    pub button_playlists: Button<[Label; 1]>,
     pub button_settings: Button<[Label; 1]>,
     pub button_exit: Button<[Label; 1]>,
     pub library_browser: LibraryBrowser,
@@ -74,6 +119,8 @@ impl GuiElemChildren for MainView {
         Box::new(
             [
                 self.button_clear_queue.elem_mut(),
+                self.button_autoplay.elem_mut(),
+                self.button_playlists.elem_mut(),
                 self.button_settings.elem_mut(),
                 self.button_exit.elem_mut(),
                 self.library_browser.elem_mut(),
@@ -83,7 +130,7 @@ impl GuiElemChildren for MainView {
         )
     }
     fn len(&self) -> usize {
-        5
+        7
     }
 }
 impl GuiScreen {
@@ -104,6 +151,7 @@ impl GuiScreen {
                 (1.0, 1.0),
             ))),
             c_editing_songs: None,
+            c_editor: None,
             c_idle_display: IdleDisplay::new(GuiElemCfg::default().disabled()),
             c_settings: Settings::new(
                 GuiElemCfg::default().disabled(),
@@ -118,27 +166,48 @@ impl GuiScreen {
                 GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (1.0, 0.9))),
                 MainView {
                     button_clear_queue: Button::new(
-                        GuiElemCfg::at(Rectangle::from_tuples((0.5, 0.0), (0.75, 0.03))),
+                        GuiElemCfg::at(Rectangle::from_tuples((0.5, 0.0), (0.6, 0.03))),
+                        |_| vec![GuiAction::SendToServer(Action::QueueClear(vec![]))],
+                        [Label::new(
+                            GuiElemCfg::default(),
+                            "Clear Queue".to_string(),
+                            Color::WHITE,
+                            None,
+                            Vec2::new(0.5, 0.5),
+                        )],
+                    ),
+                    button_autoplay: Button::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.6, 0.0), (0.7, 0.03))),
                         |_| {
-                            vec![GuiAction::SendToServer(Action::QueueUpdate(
-                                vec![],
-                                musicdb_lib::data::queue::QueueContent::Folder(
-                                    musicdb_lib::data::queue::QueueFolder::default(),
-                                )
-                                .into(),
-                                Req::none(),
-                            ))]
+                            vec![GuiAction::Do(Box::new(|gui| {
+                                let autoplay = !gui.database.lock().unwrap().autoplay_on_empty;
+                                gui.exec_gui_action(GuiAction::SendToServer(
+                                    Action::SetAutoplayOnEmpty(autoplay),
+                                ));
+                            }))]
                         },
                         [Label::new(
                             GuiElemCfg::default(),
-                            "Clear Queue".to_string(),
+                            String::new(),
+                            Color::WHITE,
+                            None,
+                            Vec2::new(0.5, 0.5),
+                        )],
+                    ),
+                    button_playlists: Button::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.7, 0.0), (0.8, 0.03))),
+                        |_| vec![gui_playlists::playlists_menu()],
+                        [Label::new(
+                            GuiElemCfg::default(),
+                            "Playlists".to_string(),
                             Color::WHITE,
                             None,
                             Vec2::new(0.5, 0.5),
                         )],
                     ),
                     button_settings: Button::new(
-                        GuiElemCfg::at(Rectangle::from_tuples((0.75, 0.0), (0.875, 0.03))),
+                        GuiElemCfg::at(Rectangle::from_tuples((0.8, 0.0), (0.9, 0.03)))
+                            .w_tooltip("Open the settings".to_owned()),
                         |_| vec![GuiAction::OpenSettings(true)],
                         [Label::new(
                             GuiElemCfg::default(),
@@ -149,7 +218,7 @@ impl GuiScreen {
                         )],
                     ),
                     button_exit: Button::new(
-                        GuiElemCfg::at(Rectangle::from_tuples((0.875, 0.0), (1.0, 0.03))),
+                        GuiElemCfg::at(Rectangle::from_tuples((0.9, 0.0), (1.0, 0.03))),
                         |_| vec![GuiAction::Exit],
                         [Label::new(
                             GuiElemCfg::default(),
@@ -253,6 +322,7 @@ impl GuiElem for GuiScreen {
                 ]
                 .into_iter()
                 .chain(self.c_editing_songs.as_mut().map(|v| v.elem_mut()))
+                .chain(self.c_editor.as_mut().map(|v| v.elem_mut()))
                 .chain(self.c_song_adder.as_mut().map(|v| v.elem_mut()).into_iter())
                 .chain([
                     self.c_status_bar.elem_mut(),
@@ -289,26 +359,6 @@ impl GuiElem for GuiScreen {
         if self.hotkey.triggered(modifiers, down, key) && e.take() {
             self.config.request_keyboard_focus = true;
             vec![GuiAction::ResetKeyboardFocus]
-        // } else if down && matches!(key, Some(VirtualKeyCode::Space)) && e.take() {
-        // MOVED TO CHAR_WATCH !!!
-        } else {
-            vec![]
-        }
-    }
-    fn char_watch(
-        &mut self,
-        e: &mut EventInfo,
-        modifiers: speedy2d::window::ModifiersState,
-        key: char,
-    ) -> Vec<GuiAction> {
-        if key == ' ' && !(modifiers.ctrl() || modifiers.alt() || modifiers.logo()) && e.take() {
-            vec![GuiAction::Build(Box::new(|db| {
-                vec![GuiAction::SendToServer(if db.playing {
-                    Action::Pause
-                } else {
-                    Action::Resume
-                })]
-            }))]
         } else {
             vec![]
         }
@@ -323,44 +373,187 @@ impl GuiElem for GuiScreen {
     }
     fn draw(&mut self, info: &mut DrawInfo, _g: &mut Graphics2D) {
         if self.config.init {
+            let volume_by = |by: f32| {
+                move || {
+                    vec![GuiAction::Build(Box::new(move |db| {
+                        vec![GuiAction::SendToServer(Action::SetVolume(
+                            (db.volume + by).clamp(0.0, 1.0),
+                        ))]
+                    }))]
+                }
+            };
             info.actions.extend([
-                GuiAction::AddKeybind(
-                    Some((KeyBinding::ctrl(VirtualKeyCode::Q), true)),
-                    KeyAction {
-                        category: "General".to_owned(),
-                        title: "Quit".to_owned(),
-                        description: "Closes the application".to_owned(),
-                        action: Box::new(|| vec![GuiAction::Exit]),
-                        enabled: true,
+                keybind(
+                    "quit",
+                    vec![(KeyBinding::ctrl(VirtualKeyCode::Q), true)],
+                    "General",
+                    "Quit",
+                    "Closes the application",
+                    || vec![GuiAction::Exit],
+                ),
+                keybind(
+                    "idle",
+                    vec![(KeyBinding::ctrl(VirtualKeyCode::I), true)],
+                    "General",
+                    "Idle",
+                    "Opens the idle display",
+                    || vec![GuiAction::ForceIdle],
+                ),
+                keybind(
+                    "play_pause",
+                    vec![(KeyBinding::key(VirtualKeyCode::Space), false)],
+                    "Playback",
+                    "Play/Pause",
+                    "pauses or resumes playback",
+                    || {
+                        vec![GuiAction::Build(Box::new(|db| {
+                            vec![GuiAction::SendToServer(if db.playing {
+                                Action::Pause
+                            } else {
+                                Action::Resume
+                            })]
+                        }))]
                     },
-                    Box::new(|_| {}),
                 ),
-                GuiAction::AddKeybind(
-                    Some((KeyBinding::ctrl(VirtualKeyCode::I), true)),
-                    KeyAction {
-                        category: "General".to_owned(),
-                        title: "Idle".to_owned(),
-                        description: "Opens the idle display".to_owned(),
-                        action: Box::new(|| vec![GuiAction::ForceIdle]),
-                        enabled: true,
+                keybind(
+                    "next_song",
+                    vec![
+                        (KeyBinding::ctrl(VirtualKeyCode::Right), false),
+                        (KeyBinding::key(VirtualKeyCode::N), false),
+                    ],
+                    "Playback",
+                    "Next song",
+                    "skips to the next song",
+                    || {
+                        vec![GuiAction::Build(Box::new(|db| {
+                            vec![GuiAction::SendToServer(db.next_song_action())]
+                        }))]
                     },
-                    Box::new(|_| {}),
                 ),
-                GuiAction::AddKeybind(
-                    Some((KeyBinding::ctrl(VirtualKeyCode::F), true)),
-                    KeyAction {
-                        category: "Library".to_owned(),
-                        title: "Search songs".to_owned(),
-                        description: "moves keyboard focus to the song search".to_owned(),
-                        action: Box::new(|| {
-                            vec![GuiAction::SetFocused(SpecificGuiElem::SearchSong)]
-                        }),
-                        enabled: true,
+                keybind(
+                    "previous",
+                    vec![
+                        (KeyBinding::ctrl(VirtualKeyCode::Left), false),
+                        (KeyBinding::key(VirtualKeyCode::P), false),
+                    ],
+                    "Playback",
+                    "Previous",
+                    "goes back to the start of the current song",
+                    || vec![GuiAction::SendToServer(Action::Seek(0))],
+                ),
+                keybind(
+                    "seek_forward",
+                    vec![(KeyBinding::ctrl_shift(VirtualKeyCode::Right), false)],
+                    "Playback",
+                    "Seek forward",
+                    "skips 10 seconds ahead in the current song",
+                    || vec![GuiAction::SendToServer(Action::SeekBy(10_000))],
+                ),
+                keybind(
+                    "seek_backward",
+                    vec![(KeyBinding::ctrl_shift(VirtualKeyCode::Left), false)],
+                    "Playback",
+                    "Seek backward",
+                    "goes back 10 seconds in the current song",
+                    || vec![GuiAction::SendToServer(Action::SeekBy(-10_000))],
+                ),
+                keybind(
+                    "volume_up",
+                    vec![
+                        (KeyBinding::key(VirtualKeyCode::Plus), false),
+                        (KeyBinding::shift(VirtualKeyCode::Equals), false),
+                        (KeyBinding::key(VirtualKeyCode::NumpadAdd), false),
+                    ],
+                    "Playback",
+                    "Volume up",
+                    "increases the volume by 5%",
+                    volume_by(0.05),
+                ),
+                keybind(
+                    "volume_down",
+                    vec![
+                        (KeyBinding::key(VirtualKeyCode::Minus), false),
+                        (KeyBinding::key(VirtualKeyCode::NumpadSubtract), false),
+                    ],
+                    "Playback",
+                    "Volume down",
+                    "decreases the volume by 5%",
+                    volume_by(-0.05),
+                ),
+                keybind(
+                    "search",
+                    vec![(KeyBinding::ctrl(VirtualKeyCode::F), true)],
+                    "Library",
+                    "Search",
+                    "moves keyboard focus to the library search",
+                    || vec![GuiAction::SetFocused(SpecificGuiElem::Search)],
+                ),
+                keybind(
+                    "jump_to_current",
+                    vec![(KeyBinding::ctrl(VirtualKeyCode::J), true)],
+                    "Library",
+                    "Jump to current song",
+                    "scrolls the queue and the library to the song which is playing",
+                    || vec![GuiAction::JumpToCurrent],
+                ),
+                keybind(
+                    "scale_up",
+                    vec![
+                        (KeyBinding::ctrl(VirtualKeyCode::Plus), true),
+                        (KeyBinding::ctrl_shift(VirtualKeyCode::Equals), true),
+                        (KeyBinding::ctrl(VirtualKeyCode::NumpadAdd), true),
+                    ],
+                    "General",
+                    "Scale up",
+                    "makes text and the layout larger",
+                    || {
+                        vec![GuiAction::Do(Box::new(|gui| {
+                            gui.scale_to(gui.line_height + LINE_HEIGHT_STEP)
+                        }))]
+                    },
+                ),
+                keybind(
+                    "scale_down",
+                    vec![
+                        (KeyBinding::ctrl(VirtualKeyCode::Minus), true),
+                        (KeyBinding::ctrl(VirtualKeyCode::NumpadSubtract), true),
+                    ],
+                    "General",
+                    "Scale down",
+                    "makes text and the layout smaller",
+                    || {
+                        vec![GuiAction::Do(Box::new(|gui| {
+                            gui.scale_to(gui.line_height - LINE_HEIGHT_STEP)
+                        }))]
+                    },
+                ),
+                keybind(
+                    "scale_reset",
+                    vec![
+                        (KeyBinding::ctrl(VirtualKeyCode::Key0), true),
+                        (KeyBinding::ctrl(VirtualKeyCode::Numpad0), true),
+                    ],
+                    "General",
+                    "Reset scale",
+                    "resets text and the layout to their default size",
+                    || {
+                        vec![GuiAction::Do(Box::new(|gui| {
+                            gui.scale_to(LINE_HEIGHT_DEFAULT)
+                        }))]
                     },
-                    Box::new(|_| {}),
                 ),
             ]);
         }
+        // autoplay button
+        let autoplay_text = if info.database.autoplay_on_empty {
+            "Autoplay: on"
+        } else {
+            "Autoplay: off"
+        };
+        let label = &mut self.c_main_view.children.button_autoplay.children[0];
+        if label.content.get_text() != autoplay_text {
+            *label.content.text() = autoplay_text.to_owned();
+        }
         // idle stuff
         if self.prev_mouse_pos != info.mouse_pos {
             self.prev_mouse_pos = info.mouse_pos;
@@ -64,11 +64,15 @@ pub struct SettingsContent {
     pub back_button: Button<[Label; 1]>,
     pub opacity: Panel<(Label, Slider)>,
     pub performance_toggle: Panel<(Label, Button<[Label; 1]>)>,
+    /// how many covers are loaded and how much memory they use, see `gui::Covers`
+    pub cover_cache: Panel<(Label, Label)>,
     pub line_height: Panel<(Label, Slider)>,
     pub scroll_sensitivity: Panel<(Label, Slider)>,
     pub idle_time: Panel<(Label, Slider)>,
     pub save_button: Button<[Label; 1]>,
     pub add_new_songs_button: Button<[Label; 1]>,
+    /// the filter presets loaded from config_gui.toml, filled in on the first draw
+    pub filter_presets: Panel<(Label, Label)>,
     pub keybinds: Vec<Panel<(AdvancedLabel, KeybindInput)>>,
     pub keybinds_should_be_updated: Arc<AtomicBool>,
     pub keybinds_updated: bool,
@@ -81,18 +85,20 @@ impl GuiElemChildren for SettingsContent {
                 self.back_button.elem_mut(),
                 self.opacity.elem_mut(),
                 self.performance_toggle.elem_mut(),
+                self.cover_cache.elem_mut(),
                 self.line_height.elem_mut(),
                 self.scroll_sensitivity.elem_mut(),
                 self.idle_time.elem_mut(),
                 self.save_button.elem_mut(),
                 self.add_new_songs_button.elem_mut(),
+                self.filter_presets.elem_mut(),
             ]
             .into_iter()
             .chain(self.keybinds.iter_mut().map(|v| v.elem_mut())),
         )
     }
     fn len(&self) -> usize {
-        8 + self.keybinds.len()
+        10 + self.keybinds.len()
     }
 }
 pub struct KeybindInput {
@@ -116,14 +122,7 @@ impl KeybindInput {
             c_label: Label::new(
                 GuiElemCfg::default(),
                 if let Some(b) = &binding {
-                    format!(
-                        "{}{}{}{}{:?}",
-                        if b.get_ctrl() { "Ctrl+" } else { "" },
-                        if b.get_alt() { "Alt+" } else { "" },
-                        if b.get_shift() { "Shift+" } else { "" },
-                        if b.get_meta() { "Meta+" } else { "" },
-                        b.key,
-                    )
+                    b.to_string()
                 } else {
                     format!("")
                 },
@@ -273,7 +272,8 @@ impl SettingsContent {
     ) -> Self {
         Self {
             back_button: Button::new(
-                GuiElemCfg::at(Rectangle::from_tuples((0.75, 0.0), (1.0, 1.0))),
+                GuiElemCfg::at(Rectangle::from_tuples((0.75, 0.0), (1.0, 1.0)))
+                    .w_tooltip("Close the settings".to_owned()),
                 |_| vec![GuiAction::OpenSettings(false)],
                 [Label::new(
                     GuiElemCfg::default(),
@@ -343,6 +343,25 @@ impl SettingsContent {
                     ),
                 ),
             ),
+            cover_cache: Panel::new(
+                GuiElemCfg::default(),
+                (
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.33, 1.0))),
+                        "Cover cache".to_string(),
+                        Color::WHITE,
+                        None,
+                        Vec2::new(0.9, 0.5),
+                    ),
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.33, 0.0), (1.0, 1.0))),
+                        String::new(),
+                        Color::LIGHT_GRAY,
+                        None,
+                        Vec2::new(0.0, 0.5),
+                    ),
+                ),
+            ),
             line_height: Panel::new(
                 GuiElemCfg::default(),
                 (
@@ -355,8 +374,8 @@ impl SettingsContent {
                     ),
                     Slider::new_labeled(
                         GuiElemCfg::at(Rectangle::from_tuples((0.33, 0.0), (1.0, 1.0))),
-                        16.0,
-                        80.0,
+                        crate::gui::LINE_HEIGHT_MIN as _,
+                        crate::gui::LINE_HEIGHT_MAX as _,
                         line_height as _,
                         |slider, label, info| {
                             if slider.val_changed() {
@@ -449,7 +468,7 @@ impl SettingsContent {
                 ),
             ),
             save_button: Button::new(
-                GuiElemCfg::default(),
+                GuiElemCfg::default().w_tooltip("Write the server's database to disk".to_owned()),
                 |_| vec![GuiAction::SendToServer(Action::Save)],
                 [Label::new(
                     GuiElemCfg::default(),
@@ -460,7 +479,9 @@ impl SettingsContent {
                 )],
             ),
             add_new_songs_button: Button::new(
-                GuiElemCfg::default(),
+                GuiElemCfg::default().w_tooltip(
+                    "Look for files in the library which aren't in the database yet".to_owned(),
+                ),
                 |_| vec![GuiAction::OpenAddSongsMenu],
                 [Label::new(
                     GuiElemCfg::default(),
@@ -470,6 +491,25 @@ impl SettingsContent {
                     Vec2::new(0.5, 0.5),
                 )],
             ),
+            filter_presets: Panel::new(
+                GuiElemCfg::default(),
+                (
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.0, 0.0), (0.33, 1.0))),
+                        "Filter presets".to_string(),
+                        Color::WHITE,
+                        None,
+                        Vec2::new(0.9, 0.5),
+                    ),
+                    Label::new(
+                        GuiElemCfg::at(Rectangle::from_tuples((0.33, 0.0), (1.0, 1.0))),
+                        String::new(),
+                        Color::LIGHT_GRAY,
+                        None,
+                        Vec2::new(0.0, 0.5),
+                    ),
+                ),
+            ),
             keybinds: vec![],
             keybinds_should_be_updated: Arc::new(AtomicBool::new(true)),
             keybinds_updated: false,
@@ -477,6 +517,33 @@ impl SettingsContent {
         }
     }
     pub fn draw(&mut self, info: &mut DrawInfo) -> bool {
+        let (count, bytes) = info.covers.size();
+        let cover_cache = format!(
+            "{count} covers, {} of {} MiB",
+            bytes / (1024 * 1024),
+            info.covers.budget / (1024 * 1024)
+        );
+        let cover_cache_label = &mut self.cover_cache.children.1;
+        if *cover_cache_label.content.get_text() != cover_cache {
+            *cover_cache_label.content.text() = cover_cache;
+        }
+        let presets_label = &mut self.filter_presets.children.1;
+        if presets_label.content.get_text().is_empty() {
+            let names = |presets: &Vec<(String, crate::gui_library::FilterType)>| {
+                presets
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let cfg = &info.gui_config;
+            *presets_label.content.text() = format!(
+                "songs: {} | albums: {} | artists: {}",
+                names(&cfg.filter_presets_song),
+                names(&cfg.filter_presets_album),
+                names(&cfg.filter_presets_artist),
+            );
+        }
         if !self.keybinds_updated
             && self
                 .keybinds_should_be_updated
@@ -552,7 +619,7 @@ impl GuiElem for Settings {
             scrollbox.config_mut().redraw = true;
             if scrollbox.children_heights.len() == scrollbox.children.len() {
                 for (i, h) in scrollbox.children_heights.iter_mut().enumerate() {
-                    *h = if i == 0 || i >= 8 {
+                    *h = if i == 0 || i >= 9 {
                         info.line_height * 2.0
                     } else {
                         info.line_height
@@ -89,10 +89,12 @@ impl GuiElem for SongAdder {
             self.state += 1;
             if self.state == 2 {
                 self.c_loading = None;
-                eprintln!("Locking GetCon...");
-                let mut get_con = info.get_con.lock().unwrap();
                 eprintln!("Requesting list of unused songs...");
-                match get_con.find_unused_song_files(None).unwrap() {
+                match info
+                    .get_con
+                    .with_reconnect(|get_con| get_con.find_unused_song_files(None))
+                    .unwrap()
+                {
                     Ok(data) => {
                         eprintln!("Got list of songs.");
                         self.c_scroll_box.children = data
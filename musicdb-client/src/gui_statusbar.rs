@@ -1,18 +1,26 @@
 use std::{
-    sync::{atomic::AtomicBool, Arc},
-    time::Instant,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use speedy2d::{dimen::Vec2, shape::Rectangle};
+use musicdb_lib::{data::SongId, server::Action};
+use speedy2d::{color::Color, dimen::Vec2, shape::Rectangle};
 
 use crate::{
-    gui::{DrawInfo, GuiElem, GuiElemCfg},
+    gui::{DrawInfo, GuiAction, GuiElem, GuiElemCfg},
     gui_anim::AnimationController,
+    gui_base::{Button, Slider},
     gui_playback::{image_display, CurrentInfo},
     gui_playpause::PlayPause,
-    gui_text::AdvancedLabel,
+    gui_text::{AdvancedLabel, Label},
 };
 
+/// while the seek bar is being dragged, `Seek`s are sent at most this often
+const SEEK_INTERVAL: Duration = Duration::from_millis(200);
+
 pub struct StatusBar {
     config: GuiElemCfg,
     pub idle_mode: f32,
@@ -21,12 +29,28 @@ pub struct StatusBar {
     c_song_label: AdvancedLabel,
     pub force_reset_texts: bool,
     c_buttons: PlayPause,
+    c_volume: Slider,
+    c_mute: Button<[Label; 1]>,
+    c_jump: Button<[Label; 1]>,
+    /// the volume we last sent to the server while the slider was being dragged
+    volume_sent: f32,
+    /// the database's volume when we last updated the slider
+    volume_db: f32,
+    /// the last volume other than zero (as `f32` bits), restored when unmuting
+    volume_unmuted: Arc<AtomicU32>,
+    c_seek: Slider,
+    c_elapsed: Label,
+    c_total: Label,
+    /// the position we last sent to the server while the seek bar was being dragged, and when
+    seek_sent: Option<(u64, Instant)>,
+    position_estimate: PositionEstimate,
     is_fav: (bool, Arc<AtomicBool>),
 }
 
 impl StatusBar {
     pub fn new(config: GuiElemCfg) -> Self {
         let is_fav = Arc::new(AtomicBool::new(false));
+        let volume_unmuted = Arc::new(AtomicU32::new(1.0f32.to_bits()));
         Self {
             config,
             idle_mode: 0.0,
@@ -44,13 +68,203 @@ impl StatusBar {
             force_reset_texts: false,
             is_fav: (false, Arc::clone(&is_fav)),
             c_buttons: PlayPause::new(GuiElemCfg::default(), is_fav),
+            c_volume: {
+                let mut slider = Slider::new_labeled(
+                    GuiElemCfg::default(),
+                    0.0,
+                    1.0,
+                    1.0,
+                    |slider, label, _info| {
+                        *label.content.text() = format!("Volume {:.0}%", slider.val * 100.0);
+                    },
+                );
+                slider.wheel_step = 0.05;
+                slider.val_changed_subs.push(false);
+                slider
+            },
+            c_mute: {
+                let volume_unmuted = Arc::clone(&volume_unmuted);
+                Button::new(
+                    GuiElemCfg::default(),
+                    move |_| {
+                        let volume_unmuted = Arc::clone(&volume_unmuted);
+                        vec![GuiAction::Build(Box::new(move |db| {
+                            vec![GuiAction::SendToServer(Action::SetVolume(
+                                if db.volume > 0.0 {
+                                    0.0
+                                } else {
+                                    f32::from_bits(volume_unmuted.load(Ordering::Relaxed))
+                                },
+                            ))]
+                        }))]
+                    },
+                    [Label::new(
+                        GuiElemCfg::default(),
+                        "mute".to_owned(),
+                        Color::GRAY,
+                        None,
+                        Vec2::new(0.5, 0.5),
+                    )],
+                )
+            },
+            c_jump: Button::new(
+                GuiElemCfg::default(),
+                |_| vec![GuiAction::JumpToCurrent],
+                [Label::new(
+                    GuiElemCfg::default(),
+                    "show current song".to_owned(),
+                    Color::GRAY,
+                    None,
+                    Vec2::new(0.5, 0.5),
+                )],
+            ),
+            volume_sent: f32::NAN,
+            volume_db: f32::NAN,
+            volume_unmuted,
+            c_seek: Slider::new(
+                GuiElemCfg::default(),
+                Rectangle::new(Vec2::ZERO, Vec2::new(1.0, 1.0)),
+                0.0,
+                1.0,
+                0.0,
+                vec![],
+                |_, _| {},
+            ),
+            c_elapsed: Label::new(
+                GuiElemCfg::default(),
+                String::new(),
+                Color::GRAY,
+                None,
+                Vec2::new(1.0, 0.5),
+            ),
+            c_total: Label::new(
+                GuiElemCfg::default(),
+                String::new(),
+                Color::GRAY,
+                None,
+                Vec2::new(0.0, 0.5),
+            ),
+            seek_sent: None,
+            position_estimate: PositionEstimate::default(),
+        }
+    }
+}
+
+impl StatusBar {
+    fn update_seek_bar(&mut self, info: &mut DrawInfo) {
+        let db = &info.database;
+        let song = db.queue.get_current_song(db).copied();
+        self.position_estimate.update(song, db.playing);
+        let duration = song
+            .and_then(|id| db.get_song(&id))
+            .map(|song| song.duration_millis)
+            .unwrap_or(0);
+        let position = db
+            .playback_position_now()
+            .unwrap_or_else(|| self.position_estimate.get())
+            .min(duration);
+        self.c_seek.config.enabled = song.is_some() && duration > 0;
+        self.c_seek.max = duration.max(1) as f64;
+        if self.c_seek.config.mouse_pressed.0 {
+            // send the position while dragging, but not too often
+            let pos = self.c_seek.val as u64;
+            match self.seek_sent {
+                // the slider only updates its value after this, so there's nothing to send yet
+                None => self.seek_sent = Some((pos, Instant::now())),
+                Some((sent, at)) if sent != pos && at.elapsed() >= SEEK_INTERVAL => {
+                    self.seek_sent = Some((pos, Instant::now()));
+                    info.actions
+                        .push(GuiAction::SendToServer(Action::Seek(pos)));
+                }
+                Some(_) => {}
+            }
+        } else if self.seek_sent.is_some() {
+            // released, send the final position
+            let pos = self.c_seek.val as u64;
+            if self.seek_sent.take().is_some_and(|(sent, _)| sent != pos) {
+                info.actions
+                    .push(GuiAction::SendToServer(Action::Seek(pos)));
+            }
+            self.position_estimate.set(pos);
+        } else {
+            self.c_seek.val = position as f64;
+        }
+        let elapsed = if self.seek_sent.is_some() {
+            self.c_seek.val as u64
+        } else {
+            position
+        };
+        let (elapsed, total) = if self.c_seek.config.enabled {
+            (fmt_time(elapsed), fmt_time(duration))
+        } else {
+            (String::new(), String::new())
+        };
+        if *self.c_elapsed.content.get_text() != elapsed {
+            *self.c_elapsed.content.text() = elapsed;
+        }
+        if *self.c_total.content.get_text() != total {
+            *self.c_total.content.text() = total;
+        }
+    }
+}
+
+/// m:ss
+fn fmt_time(millis: u64) -> String {
+    let seconds = millis / 1000;
+    format!("{}:{:0>2}", seconds / 60, seconds % 60)
+}
+
+/// the position in the current song, estimated from when it started or was resumed.
+/// only used if the database doesn't know the position.
+#[derive(Default)]
+struct PositionEstimate {
+    song: Option<SongId>,
+    pos: u64,
+    since: Option<Instant>,
+}
+impl PositionEstimate {
+    fn update(&mut self, song: Option<SongId>, playing: bool) {
+        if song != self.song {
+            self.song = song;
+            self.pos = 0;
+            self.since = playing.then(Instant::now);
+        } else if playing != self.since.is_some() {
+            if let Some(since) = self.since.take() {
+                self.pos += since.elapsed().as_millis() as u64;
+            } else {
+                self.since = Some(Instant::now());
+            }
+        }
+    }
+    fn get(&self) -> u64 {
+        self.pos
+            + self
+                .since
+                .map_or(0, |since| since.elapsed().as_millis() as u64)
+    }
+    fn set(&mut self, pos: u64) {
+        self.pos = pos;
+        if self.since.is_some() {
+            self.since = Some(Instant::now());
         }
     }
 }
 
 impl GuiElem for StatusBar {
     fn children(&mut self) -> Box<dyn Iterator<Item = &mut dyn GuiElem> + '_> {
-        Box::new([self.c_song_label.elem_mut(), self.c_buttons.elem_mut()].into_iter())
+        Box::new(
+            [
+                self.c_song_label.elem_mut(),
+                self.c_buttons.elem_mut(),
+                self.c_volume.elem_mut(),
+                self.c_mute.elem_mut(),
+                self.c_jump.elem_mut(),
+                self.c_seek.elem_mut(),
+                self.c_elapsed.elem_mut(),
+                self.c_total.elem_mut(),
+            ]
+            .into_iter(),
+        )
     }
     fn draw(&mut self, info: &mut DrawInfo, g: &mut speedy2d::Graphics2D) {
         self.current_info.update(info, g);
@@ -65,9 +279,7 @@ impl GuiElem for StatusBar {
                 .unwrap_or(false);
             if self.is_fav.0 != is_fav {
                 self.is_fav.0 = is_fav;
-                self.is_fav
-                    .1
-                    .store(is_fav, std::sync::atomic::Ordering::Relaxed);
+                self.is_fav.1.store(is_fav, Ordering::Relaxed);
             }
             self.c_song_label.content = if let Some(song) = self.current_info.current_song {
                 info.gui_config
@@ -78,6 +290,35 @@ impl GuiElem for StatusBar {
             };
             self.c_song_label.config_mut().redraw = true;
         }
+        // while showing the cached library (or reconnecting), there is nothing to control
+        self.c_buttons.config_mut().enabled = info.database.is_client_init();
+        // hide the volume control if the server never told us its volume
+        self.c_volume.config.enabled = info.database.volume_received;
+        self.c_mute.config_mut().enabled = info.database.volume_received;
+        self.c_jump.config_mut().enabled = self.current_info.current_song.is_some();
+        let scrolled = std::mem::replace(&mut self.c_volume.val_changed_subs[0], false);
+        if self.c_volume.config.mouse_pressed.0 || scrolled {
+            let volume = self.c_volume.val as f32;
+            if volume != self.volume_sent {
+                self.volume_sent = volume;
+                info.actions
+                    .push(GuiAction::SendToServer(Action::SetVolume(volume)));
+            }
+        } else if info.database.volume != self.volume_db {
+            // changed by us, by another client, or by the server
+            let volume = info.database.volume;
+            self.volume_db = volume;
+            self.volume_sent = volume;
+            self.c_volume.val = volume as _;
+            self.c_volume.config.redraw = true;
+            if volume > 0.0 {
+                self.volume_unmuted
+                    .store(volume.to_bits(), Ordering::Relaxed);
+            }
+            *self.c_mute.children[0].content.text() =
+                if volume > 0.0 { "mute" } else { "unmute" }.to_owned();
+        }
+        self.update_seek_bar(info);
         if self.current_info.new_cover {
             self.current_info.new_cover = false;
             match self.current_info.current_cover {
@@ -103,13 +344,32 @@ impl GuiElem for StatusBar {
                 (buttons_right_pos - buttons_width, 0.15),
                 (buttons_right_pos, 0.85),
             );
-            self.c_song_label.config_mut().pos = Rectangle::from_tuples(
-                (
-                    self.cover_aspect_ratio.value * info.pos.height() / info.pos.width(),
-                    0.0,
-                ),
-                (buttons_right_pos - buttons_width, 1.0),
+            self.c_jump.config_mut().pos = Rectangle::from_tuples(
+                (buttons_right_pos - buttons_width, 0.0),
+                (buttons_right_pos, 0.15),
+            );
+            let mute_width = buttons_width * 0.25;
+            self.c_mute.config_mut().pos = Rectangle::from_tuples(
+                (buttons_right_pos - buttons_width, 0.85),
+                (buttons_right_pos - buttons_width + mute_width, 1.0),
+            );
+            self.c_volume.config_mut().pos = Rectangle::from_tuples(
+                (buttons_right_pos - buttons_width + mute_width, 0.85),
+                (buttons_right_pos, 1.0),
             );
+            let text_left = self.cover_aspect_ratio.value * info.pos.height() / info.pos.width();
+            let text_right = buttons_right_pos - buttons_width;
+            let time_width = info.line_height * 2.5 / info.pos.width();
+            self.c_song_label.config_mut().pos =
+                Rectangle::from_tuples((text_left, 0.0), (text_right, 0.75));
+            self.c_elapsed.config_mut().pos =
+                Rectangle::from_tuples((text_left, 0.75), (text_left + time_width, 1.0));
+            self.c_seek.config_mut().pos = Rectangle::from_tuples(
+                (text_left + time_width, 0.75),
+                (text_right - time_width, 1.0),
+            );
+            self.c_total.config_mut().pos =
+                Rectangle::from_tuples((text_right - time_width, 0.75), (text_right, 1.0));
         }
         // draw cover
         if let Some(Some(cover)) = self
@@ -153,5 +413,7 @@ impl GuiElem for StatusBar {
     }
     fn updated_queue(&mut self) {
         self.current_info.update = true;
+        // the queue's position and remaining time (`\q`, `\r`) may have changed
+        self.force_reset_texts = true;
     }
 }
@@ -1,16 +1,19 @@
-use std::{fmt::Display, rc::Rc, sync::Arc};
+use std::{fmt::Display, ops::Range, rc::Rc, sync::Arc};
 
 use musicdb_lib::data::CoverId;
 use speedy2d::{
     color::Color,
     dimen::Vec2,
-    font::{FormattedTextBlock, TextLayout, TextOptions},
+    font::{FormattedTextBlock, TextAlignment, TextLayout, TextOptions},
     image::ImageHandle,
     shape::Rectangle,
     window::{ModifiersState, MouseButton},
 };
 
-use crate::gui::{EventInfo, GuiAction, GuiElem, GuiElemCfg, GuiServerImage};
+use crate::{
+    clipboard,
+    gui::{DrawInfo, EventInfo, GuiAction, GuiElem, GuiElemCfg, GuiServerImage},
+};
 
 /*
 
@@ -30,8 +33,24 @@ pub struct Content {
     text: String,
     color: Color,
     background: Option<Color>,
+    /// how a `Label` fits the text into its area
+    layout: TextLayoutMode,
     formatted: Option<Rc<FormattedTextBlock>>,
 }
+/// how a `Label` fits its text into the available space
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextLayoutMode {
+    /// one line, scaled to use as much of the space as possible
+    #[default]
+    ScaleToFit,
+    /// multiple lines with a text size based on `line_height`, shrunk only if the text doesn't fit
+    Wrap,
+    /// one line as high as the space, cut off with "…" if it is too long.
+    /// the full text is then shown as the label's tooltip.
+    SingleLineEllipsis,
+}
+/// the height of one line of text in `TextLayoutMode::Wrap`, relative to `line_height`
+const WRAP_LINE_HEIGHT: f32 = 0.6;
 
 #[allow(unused)]
 impl Content {
@@ -44,6 +63,7 @@ impl Content {
             },
             color,
             background: None,
+            layout: TextLayoutMode::default(),
             formatted: None,
         }
     }
@@ -61,6 +81,14 @@ impl Content {
     pub fn color(&mut self) -> &mut Color {
         &mut self.color
     }
+    pub fn get_layout(&self) -> TextLayoutMode {
+        self.layout
+    }
+    /// causes text layout reset
+    pub fn set_layout(&mut self, layout: TextLayoutMode) {
+        self.formatted = None;
+        self.layout = layout;
+    }
     /// returns true if the text needs to be redrawn, probably because it was changed.
     pub fn will_redraw(&self) -> bool {
         self.formatted.is_none()
@@ -80,11 +108,101 @@ impl Label {
                 text,
                 color,
                 background,
+                layout: TextLayoutMode::default(),
                 formatted: None,
             },
             pos,
         }
     }
+    pub fn with_layout(mut self, layout: TextLayoutMode) -> Self {
+        self.content.set_layout(layout);
+        self
+    }
+    /// the text, fit into `info.pos` as described by the content's `TextLayoutMode`.
+    /// cached until the text changes or the label is resized.
+    pub fn layout(&mut self, info: &DrawInfo) -> Rc<FormattedTextBlock> {
+        if self.config.pixel_pos.size() != info.pos.size() {
+            // resize
+            self.content.formatted = None;
+        }
+        if let Some(text) = &self.content.formatted {
+            Rc::clone(text)
+        } else {
+            let (w, h) = (info.pos.width(), info.pos.height());
+            let text = &self.content.text;
+            let l = info.font.layout_text(text, 1.0, TextOptions::new());
+            let l = match self.content.layout {
+                TextLayoutMode::ScaleToFit => info.font.layout_text(
+                    text,
+                    (w / l.width()).min(h / l.height()),
+                    TextOptions::new(),
+                ),
+                TextLayoutMode::Wrap | TextLayoutMode::SingleLineEllipsis if text.is_empty() => l,
+                TextLayoutMode::Wrap => {
+                    let wrap = |scale: f32, width: f32, alignment: TextAlignment| {
+                        info.font.layout_text(
+                            text,
+                            scale,
+                            TextOptions::new().with_wrap_to_width(width, alignment),
+                        )
+                    };
+                    let mut scale = WRAP_LINE_HEIGHT * info.line_height / l.height();
+                    let mut l = wrap(scale, w, TextAlignment::Left);
+                    if l.height() > h {
+                        // smaller text fits more words per line, so this is always enough
+                        scale *= h / l.height();
+                        l = wrap(scale, w, TextAlignment::Left);
+                    }
+                    let alignment = if self.pos.x < 0.25 {
+                        TextAlignment::Left
+                    } else if self.pos.x > 0.75 {
+                        TextAlignment::Right
+                    } else {
+                        TextAlignment::Center
+                    };
+                    if alignment != TextAlignment::Left && l.iter_lines().len() > 1 {
+                        // align lines within the block, not within the whole width
+                        l = wrap(scale, l.width() + 1.0, alignment);
+                    }
+                    l
+                }
+                TextLayoutMode::SingleLineEllipsis => {
+                    let scale = h / l.height();
+                    let layout =
+                        |text: &str| info.font.layout_text(text, scale, TextOptions::new());
+                    let full = layout(text);
+                    if full.width() <= w {
+                        self.config.tooltip = None;
+                        full
+                    } else {
+                        self.config.tooltip = Some(text.clone());
+                        // longest prefix which still fits with the "…" appended
+                        let ends = text
+                            .char_indices()
+                            .map(|(i, _)| i)
+                            .skip(1)
+                            .collect::<Vec<_>>();
+                        let fits = |end: usize| {
+                            layout(&format!("{}…", text[..end].trim_end())).width() <= w
+                        };
+                        let n = ends.partition_point(|end| fits(*end));
+                        match n.checked_sub(1).map(|i| ends[i]) {
+                            Some(end) => layout(&format!("{}…", text[..end].trim_end())),
+                            None => layout("…"),
+                        }
+                    }
+                }
+            };
+            self.content.formatted = Some(Rc::clone(&l));
+            l
+        }
+    }
+    fn text_top_left(&self, info: &DrawInfo, text: &FormattedTextBlock) -> Vec2 {
+        Vec2::new(
+            info.pos.top_left().x + self.pos.x * (info.pos.width() - text.width()),
+            info.pos.top_left().y + self.pos.y * (info.pos.height() - text.height()),
+        )
+    }
 }
 impl GuiElem for Label {
     fn config(&self) -> &GuiElemCfg {
@@ -109,38 +227,19 @@ impl GuiElem for Label {
         self
     }
     fn draw(&mut self, info: &mut crate::gui::DrawInfo, g: &mut speedy2d::Graphics2D) {
-        if self.config.pixel_pos.size() != info.pos.size() {
-            // resize
-            self.content.formatted = None;
-        }
-        let text = if let Some(text) = &self.content.formatted {
-            text
-        } else {
-            let l = info
-                .font
-                .layout_text(&self.content.text, 1.0, TextOptions::new());
-            let l = info.font.layout_text(
-                &self.content.text,
-                (info.pos.width() / l.width()).min(info.pos.height() / l.height()),
-                TextOptions::new(),
-            );
-            self.content.formatted = Some(l);
-            self.content.formatted.as_ref().unwrap()
-        };
-        let top_left = Vec2::new(
-            info.pos.top_left().x + self.pos.x * (info.pos.width() - text.width()),
-            info.pos.top_left().y + self.pos.y * (info.pos.height() - text.height()),
-        );
+        let text = self.layout(info);
+        let top_left = self.text_top_left(info, &text);
+        let theme = &info.gui_config.theme;
         if let Some(bg) = self.content.background {
             g.draw_rectangle(
                 Rectangle::new(
                     top_left,
                     Vec2::new(top_left.x + text.width(), top_left.y + text.height()),
                 ),
-                bg,
+                theme.shade(bg),
             );
         }
-        g.draw_text(top_left, self.content.color, text);
+        g.draw_text(top_left, theme.shade(self.content.color), &text);
     }
 }
 
@@ -153,6 +252,14 @@ pub struct TextField {
     pub c_hint: Label,
     pub on_changed: Option<Box<dyn FnMut(&str)>>,
     pub on_changed_mut: Option<Box<dyn FnMut(&mut Self, String)>>,
+    /// byte index of the caret in `c_input`'s text
+    cursor: usize,
+    /// length of the text when `cursor` was last set, to notice when `c_input` was changed from outside
+    cursor_text_len: usize,
+    /// mouse position relative to the start of the text, to place the caret on click
+    mouse_x: f32,
+    /// true if a character was typed since the last key was released
+    typed: bool,
 }
 impl TextField {
     pub fn new(config: GuiElemCfg, hint: String, color_hint: Color, color_input: Color) -> Self {
@@ -166,6 +273,7 @@ impl TextField {
         color_input: Color,
     ) -> Self {
         let text_is_empty = text.is_empty();
+        let cursor = text.len();
         Self {
             config: config.w_mouse().w_keyboard_focus(),
             c_input: Label::new(
@@ -188,6 +296,55 @@ impl TextField {
             ),
             on_changed: None,
             on_changed_mut: None,
+            cursor,
+            cursor_text_len: cursor,
+            mouse_x: 0.0,
+            typed: false,
+        }
+    }
+    /// the caret's position. if the text was replaced from outside, the caret is moved to the end.
+    pub fn cursor(&mut self) -> usize {
+        let text = self.c_input.content.get_text();
+        if text.len() != self.cursor_text_len || !text.is_char_boundary(self.cursor) {
+            self.cursor = text.len();
+            self.cursor_text_len = text.len();
+        }
+        self.cursor
+    }
+    /// moves the caret, `cursor` must be a char boundary in `c_input`'s text.
+    pub fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.cursor_text_len = self.c_input.content.get_text().len();
+    }
+    /// replaces the text without calling `on_changed` and puts the caret at the end.
+    pub fn set_text(&mut self, text: String) {
+        self.c_hint.config_mut().enabled = text.is_empty();
+        *self.c_input.content.text() = text;
+        self.set_cursor(self.c_input.content.get_text().len());
+    }
+    /// replaces `range` with `new`, puts the caret after it and calls `on_changed`.
+    fn edit(&mut self, range: Range<usize>, new: &str) {
+        if range.is_empty() && new.is_empty() {
+            return;
+        }
+        let cursor = range.start + new.len();
+        self.c_input.content.text().replace_range(range, new);
+        self.set_cursor(cursor);
+        if let Some(f) = &mut self.on_changed {
+            f(self.c_input.content.get_text());
+        }
+        if let Some(mut f) = self.on_changed_mut.take() {
+            let text = self.c_input.content.get_text().clone();
+            f(self, text);
+            self.on_changed_mut = Some(f);
+        }
+        self.c_hint.config_mut().enabled = self.c_input.content.get_text().is_empty();
+    }
+    /// inserts the clipboard's text at the caret
+    fn paste(&mut self) {
+        if let Some(text) = clipboard::get() {
+            let cursor = self.cursor();
+            self.edit(cursor..cursor, &clipboard::single_line(&text));
         }
     }
 }
@@ -215,17 +372,59 @@ impl GuiElem for TextField {
     }
     fn draw(&mut self, info: &mut crate::gui::DrawInfo, g: &mut speedy2d::Graphics2D) {
         let (t, c) = if info.has_keyboard_focus {
-            (3.0, Color::WHITE)
+            (3.0, info.gui_config.theme.focus)
         } else {
-            (1.0, Color::GRAY)
+            (1.0, info.gui_config.theme.shade(Color::GRAY))
         };
         g.draw_line(info.pos.top_left(), info.pos.top_right(), t, c);
         g.draw_line(info.pos.bottom_left(), info.pos.bottom_right(), t, c);
         g.draw_line(info.pos.top_left(), info.pos.bottom_left(), t, c);
         g.draw_line(info.pos.top_right(), info.pos.bottom_right(), t, c);
+        // the label caches this layout and draws it after us
+        let cursor = self.cursor();
+        let empty = self.c_input.content.get_text().is_empty();
+        let text = self.c_input.layout(info);
+        let top_left = if empty {
+            *info.pos.top_left()
+        } else {
+            self.c_input.text_top_left(info, &text)
+        };
+        self.mouse_x = info.mouse_pos.x - top_left.x;
+        if info.has_keyboard_focus {
+            let x = top_left.x
+                + caret_x(
+                    &text,
+                    self.c_input.content.get_text()[..cursor].chars().count(),
+                );
+            let (y1, y2) = if empty {
+                (
+                    info.pos.top_left().y + 0.2 * info.pos.height(),
+                    info.pos.bottom_left().y - 0.2 * info.pos.height(),
+                )
+            } else {
+                (top_left.y, top_left.y + text.height())
+            };
+            g.draw_line(
+                Vec2::new(x, y1),
+                Vec2::new(x, y2),
+                2.0,
+                info.gui_config.theme.text,
+            );
+        }
     }
-    fn mouse_pressed(&mut self, e: &mut EventInfo, _button: MouseButton) -> Vec<GuiAction> {
+    fn mouse_pressed(&mut self, e: &mut EventInfo, button: MouseButton) -> Vec<GuiAction> {
         if e.take() {
+            if button == MouseButton::Left {
+                if let Some(text) = self.c_input.content.formatted.clone() {
+                    let index = char_index_at(&text, self.mouse_x);
+                    let content = self.c_input.content.get_text();
+                    let cursor = content
+                        .char_indices()
+                        .nth(index)
+                        .map_or(content.len(), |(i, _)| i);
+                    self.set_cursor(cursor);
+                }
+            }
             self.config.request_keyboard_focus = true;
             vec![GuiAction::ResetKeyboardFocus]
         } else {
@@ -242,20 +441,9 @@ impl GuiElem for TextField {
             && !key.is_control()
             && e.take()
         {
-            let content = &mut self.c_input.content;
-            let was_empty = content.get_text().is_empty();
-            content.text().push(key);
-            if let Some(f) = &mut self.on_changed {
-                f(content.get_text());
-            }
-            if let Some(mut f) = self.on_changed_mut.take() {
-                let text = content.get_text().clone();
-                f(self, text);
-                self.on_changed_mut = Some(f);
-            }
-            if was_empty {
-                self.c_hint.config_mut().enabled = false;
-            }
+            let cursor = self.cursor();
+            self.edit(cursor..cursor, key.encode_utf8(&mut [0; 4]));
+            self.typed = true;
         }
         vec![]
     }
@@ -267,41 +455,116 @@ impl GuiElem for TextField {
         key: Option<speedy2d::window::VirtualKeyCode>,
         _scan: speedy2d::window::KeyScancode,
     ) -> Vec<GuiAction> {
-        if down
-            && !(modifiers.alt() || modifiers.logo())
-            && key == Some(speedy2d::window::VirtualKeyCode::Backspace)
-            && e.take()
-        {
-            let content = &mut self.c_input.content;
-            if !content.get_text().is_empty() {
-                if modifiers.ctrl() {
-                    for s in [true, false, true] {
-                        while !content.get_text().is_empty()
-                            && content.get_text().ends_with(' ') == s
-                        {
-                            content.text().pop();
-                        }
-                    }
-                } else {
-                    content.text().pop();
-                }
-                let is_now_empty = content.get_text().is_empty();
-                if let Some(f) = &mut self.on_changed {
-                    f(content.get_text());
-                }
-                if let Some(mut f) = self.on_changed_mut.take() {
-                    let text = content.get_text().clone();
-                    f(self, text);
-                    self.on_changed_mut = Some(f);
-                }
-                if is_now_empty {
-                    self.c_hint.config_mut().enabled = true;
-                }
+        use speedy2d::window::VirtualKeyCode;
+        if modifiers.alt() || modifiers.logo() {
+            return vec![];
+        }
+        if !down {
+            // keybinds run when the key is released, take it so that ctrl+left doesn't also seek
+            // and typing doesn't trigger keybinds without modifiers.
+            if std::mem::take(&mut self.typed)
+                || matches!(
+                    key,
+                    Some(
+                        VirtualKeyCode::Backspace
+                            | VirtualKeyCode::Delete
+                            | VirtualKeyCode::Left
+                            | VirtualKeyCode::Right
+                            | VirtualKeyCode::Home
+                            | VirtualKeyCode::End
+                    )
+                )
+                || (modifiers.ctrl() && key == Some(VirtualKeyCode::V))
+            {
+                e.take();
             }
+            return vec![];
+        }
+        let cursor = self.cursor();
+        let text = self.c_input.content.get_text();
+        // with ctrl, move by words instead of chars
+        let prev = if modifiers.ctrl() {
+            word_start(text, cursor)
+        } else {
+            text[..cursor]
+                .chars()
+                .next_back()
+                .map_or(cursor, |c| cursor - c.len_utf8())
+        };
+        let next = if modifiers.ctrl() {
+            word_end(text, cursor)
+        } else {
+            text[cursor..]
+                .chars()
+                .next()
+                .map_or(cursor, |c| cursor + c.len_utf8())
+        };
+        let end = text.len();
+        match key {
+            Some(VirtualKeyCode::Backspace) if e.take() => self.edit(prev..cursor, ""),
+            Some(VirtualKeyCode::Delete) if e.take() => self.edit(cursor..next, ""),
+            Some(VirtualKeyCode::Left) if e.take() => self.set_cursor(prev),
+            Some(VirtualKeyCode::Right) if e.take() => self.set_cursor(next),
+            Some(VirtualKeyCode::Home) if e.take() => self.set_cursor(0),
+            Some(VirtualKeyCode::End) if e.take() => self.set_cursor(end),
+            Some(VirtualKeyCode::V) if modifiers.ctrl() && e.take() => self.paste(),
+            Some(VirtualKeyCode::Insert) if modifiers.shift() && e.take() => self.paste(),
+            _ => {}
         }
         vec![]
     }
 }
+/// where ctrl+backspace deletes to and ctrl+left moves to:
+/// skips spaces, then a word, then the spaces before it.
+fn word_start(text: &str, mut cursor: usize) -> usize {
+    for s in [true, false, true] {
+        while let Some(c) = text[..cursor].chars().next_back() {
+            if (c == ' ') != s {
+                break;
+            }
+            cursor -= c.len_utf8();
+        }
+    }
+    cursor
+}
+/// like `word_start`, but forwards, for ctrl+delete and ctrl+right.
+fn word_end(text: &str, mut cursor: usize) -> usize {
+    for s in [true, false, true] {
+        while let Some(c) = text[cursor..].chars().next() {
+            if (c == ' ') != s {
+                break;
+            }
+            cursor += c.len_utf8();
+        }
+    }
+    cursor
+}
+/// the x position of the caret before the char at `index`, relative to the start of the text
+fn caret_x(text: &FormattedTextBlock, index: usize) -> f32 {
+    let mut x = 0.0;
+    if let Some(line) = text.iter_lines().next() {
+        for glyph in line.iter_glyphs() {
+            if glyph.user_index() as usize >= index {
+                return glyph.position_x();
+            }
+            x = glyph.position_x() + glyph.advance_width();
+        }
+    }
+    x
+}
+/// the index of the char before which the caret should be placed when clicking at `x`
+fn char_index_at(text: &FormattedTextBlock, x: f32) -> usize {
+    let mut index = 0;
+    if let Some(line) = text.iter_lines().next() {
+        for glyph in line.iter_glyphs() {
+            if x < glyph.position_x() + glyph.advance_width() / 2.0 {
+                return glyph.user_index() as usize;
+            }
+            index = glyph.user_index() as usize + 1;
+        }
+    }
+    index
+}
 
 #[derive(Clone)]
 pub enum AdvancedContent {
@@ -461,26 +724,11 @@ impl GuiElem for AdvancedLabel {
                                 if handle.is_none() {
                                     match source {
                                         ImageSource::Cover(id) => {
-                                            if let Some(img) = info.covers.get_mut(&id) {
-                                                if let Some(img) = img.get_init(g) {
-                                                    *handle = Some(Some(img));
-                                                } else {
-                                                    match img {
-                                                        GuiServerImage::Loading(_) => {}
-                                                        GuiServerImage::Loaded(_) => {}
-                                                        GuiServerImage::Error => {
-                                                            *handle = Some(None)
-                                                        }
-                                                    }
-                                                }
-                                            } else {
-                                                info.covers.insert(
-                                                    *id,
-                                                    GuiServerImage::new_cover(
-                                                        *id,
-                                                        Arc::clone(&info.get_con),
-                                                    ),
-                                                );
+                                            let img = info.covers.get(*id, info.time);
+                                            if let Some(img) = img.get_init(g) {
+                                                *handle = Some(Some(img));
+                                            } else if img.is_err() {
+                                                *handle = Some(None)
                                             }
                                         }
                                         ImageSource::CustomFile(path) => {
@@ -489,8 +737,9 @@ impl GuiElem for AdvancedLabel {
                                                     *handle = Some(Some(img));
                                                 } else {
                                                     match img {
-                                                        GuiServerImage::Loading(_) => {}
-                                                        GuiServerImage::Loaded(_) => {}
+                                                        GuiServerImage::Queued
+                                                        | GuiServerImage::Loading(_) => {}
+                                                        GuiServerImage::Loaded(..) => {}
                                                         GuiServerImage::Error => {
                                                             *handle = Some(None)
                                                         }
@@ -543,7 +792,11 @@ impl GuiElem for AdvancedLabel {
                     AdvancedContent::Text(c) => {
                         if let Some(f) = &c.formatted {
                             let y = pos_y + (line_height - f.height()) * placement_height;
-                            g.draw_text(Vec2::new(pos_x, y), c.color, f);
+                            g.draw_text(
+                                Vec2::new(pos_x, y),
+                                info.gui_config.theme.shade(c.color),
+                                f,
+                            );
                             pos_x += f.width();
                         }
                     }
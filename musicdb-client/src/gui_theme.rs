@@ -0,0 +1,180 @@
+use speedy2d::color::Color;
+
+/// the colors of the gui, from the `[theme]` table in config_gui.toml.
+/// the defaults are the dark look the client always had.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// the window and the backgrounds of full-screen panels
+    pub background: Color,
+    /// the default text color
+    pub text: Color,
+    /// outline of the element with the keyboard focus
+    pub focus: Color,
+    pub button: Color,
+    pub button_hover: Color,
+    pub button_pressed: Color,
+    pub slider_line: Color,
+    pub slider_handle: Color,
+    /// the scrollbar's thumb, its track uses the same color but more transparent
+    pub scrollbar: Color,
+    /// drawn over selected elements
+    pub highlight: Color,
+    pub notif_background: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: Color::BLACK,
+            text: Color::WHITE,
+            focus: Color::WHITE,
+            button: Color::from_rgb(0.1, 0.1, 0.1),
+            button_hover: Color::from_rgb(0.15, 0.15, 0.15),
+            button_pressed: Color::from_rgb(0.25, 0.25, 0.25),
+            slider_line: Color::from_int_rgb(50, 50, 100),
+            slider_handle: Color::CYAN,
+            scrollbar: Color::WHITE,
+            highlight: Color::from_rgba(1.0, 1.0, 1.0, 0.2),
+            notif_background: Color::from_rgba(0.0, 0.0, 0.0, 0.8),
+        }
+    }
+}
+
+impl Theme {
+    pub const NAMES: [&'static str; 11] = [
+        "background",
+        "text",
+        "focus",
+        "button",
+        "button_hover",
+        "button_pressed",
+        "slider_line",
+        "slider_handle",
+        "scrollbar",
+        "highlight",
+        "notif_background",
+    ];
+
+    /// the color named `name` (see `NAMES`)
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Color> {
+        Some(match name {
+            "background" => &mut self.background,
+            "text" => &mut self.text,
+            "focus" => &mut self.focus,
+            "button" => &mut self.button,
+            "button_hover" => &mut self.button_hover,
+            "button_pressed" => &mut self.button_pressed,
+            "slider_line" => &mut self.slider_line,
+            "slider_handle" => &mut self.slider_handle,
+            "scrollbar" => &mut self.scrollbar,
+            "highlight" => &mut self.highlight,
+            "notif_background" => &mut self.notif_background,
+            _ => return None,
+        })
+    }
+
+    /// Elements which don't use a named color are built with shades of gray,
+    /// from black (the background) to white (the text).
+    /// This maps such a gray to the same position between the theme's `background` and `text`,
+    /// so that white text stays readable on a light background. Other colors are returned unchanged.
+    pub fn shade(&self, color: Color) -> Color {
+        if color.r() == color.g() && color.g() == color.b() {
+            let v = color.r();
+            let (b, t) = (&self.background, &self.text);
+            Color::from_rgba(
+                b.r() + (t.r() - b.r()) * v,
+                b.g() + (t.g() - b.g()) * v,
+                b.b() + (t.b() - b.b()) * v,
+                color.a(),
+            )
+        } else {
+            color
+        }
+    }
+
+    /// `color` with its alpha multiplied by `alpha`
+    pub fn with_alpha(color: Color, alpha: f32) -> Color {
+        Color::from_rgba(color.r(), color.g(), color.b(), color.a() * alpha)
+    }
+}
+
+/// parses a hex color like `"#RRGGBB"` or `"#RRGGBBAA"`, the `#` is optional.
+pub fn parse_color(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Color::from_int_rgba(
+        byte(0)?,
+        byte(2)?,
+        byte(4)?,
+        if hex.len() == 8 { byte(6)? } else { 255 },
+    ))
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(
+        parse_color("#ff8000"),
+        Some(Color::from_int_rgb(255, 128, 0))
+    );
+    assert_eq!(
+        parse_color("000000CC"),
+        Some(Color::from_int_rgba(0, 0, 0, 204))
+    );
+    assert_eq!(parse_color("#fff"), None);
+    assert_eq!(parse_color("#gg0000"), None);
+    assert_eq!(parse_color("#ff00ff0"), None);
+}
+
+#[test]
+fn test_theme_shade() {
+    let dark = Theme::default();
+    let gray = Color::from_rgba(0.25, 0.25, 0.25, 0.5);
+    assert_eq!(dark.shade(gray), gray);
+    assert_eq!(dark.shade(Color::RED), Color::RED);
+    let light = Theme {
+        background: Color::WHITE,
+        text: Color::BLACK,
+        ..Theme::default()
+    };
+    assert_eq!(light.shade(gray), Color::from_rgba(0.75, 0.75, 0.75, 0.5));
+    assert_eq!(light.shade(Color::WHITE), Color::BLACK);
+}
+
+#[test]
+fn test_light_theme_example() {
+    // the commented `[theme]` example in the default config_gui.toml
+    let example = include_str!("config_gui.toml")
+        .lines()
+        .skip_while(|line| *line != "# [theme]")
+        .map_while(|line| line.strip_prefix("# "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let table = example.parse::<toml::Table>().unwrap();
+    let mut theme = Theme::default();
+    for (name, color) in table["theme"].as_table().unwrap() {
+        *theme.get_mut(name).unwrap() = parse_color(color.as_str().unwrap()).unwrap();
+    }
+    // WCAG contrast ratio
+    let luminance = |c: Color| {
+        let f = |v: f32| {
+            if v <= 0.03928 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * f(c.r()) + 0.7152 * f(c.g()) + 0.0722 * f(c.b())
+    };
+    let contrast = |a: Color, b: Color| {
+        let (a, b) = (luminance(a), luminance(b));
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    };
+    for bg in [theme.background, theme.button, theme.button_pressed] {
+        assert!(contrast(theme.shade(Color::WHITE), bg) >= 4.5);
+        assert!(contrast(theme.shade(Color::LIGHT_GRAY), bg) >= 3.0);
+    }
+    assert!(contrast(theme.shade(Color::GRAY), theme.background) >= 3.0);
+}
@@ -1,9 +1,8 @@
 // #![allow(unused)]
 
 use std::{
-    io::{BufReader, Write},
-    net::{SocketAddr, TcpStream},
-    path::PathBuf,
+    io::Write,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     thread,
 };
@@ -14,24 +13,33 @@ use gui::GuiEvent;
 #[cfg(feature = "playback")]
 use musicdb_lib::data::cache_manager::CacheManager;
 #[cfg(feature = "playback")]
-use musicdb_lib::player::{Player, PlayerBackendFeat};
+use musicdb_lib::player::{Player, PlayerBackend, PlayerBackendFeat, SongCustomData};
+#[cfg(feature = "playback")]
+use musicdb_lib::server::relay::OutputReporter;
 use musicdb_lib::{
     data::{
         database::{ClientIo, Database},
         CoverId, SongId,
     },
     load::ToFromBytes,
-    server::Command,
+    server::{
+        connection::{start_main, Connection, ServerAddr},
+        Action, Command,
+    },
 };
 #[cfg(feature = "speedy2d")]
 use speedy2d::color::Color;
 #[cfg(feature = "speedy2d")]
+mod clipboard;
+#[cfg(feature = "speedy2d")]
 mod gui;
 #[cfg(feature = "speedy2d")]
 mod gui_anim;
 #[cfg(feature = "speedy2d")]
 mod gui_base;
 #[cfg(feature = "speedy2d")]
+mod gui_edit;
+#[cfg(feature = "speedy2d")]
 mod gui_edit_song;
 #[cfg(feature = "speedy2d")]
 mod gui_idle_display;
@@ -42,6 +50,8 @@ mod gui_notif;
 #[cfg(feature = "speedy2d")]
 mod gui_playback;
 #[cfg(feature = "speedy2d")]
+mod gui_playlists;
+#[cfg(feature = "speedy2d")]
 mod gui_playpause;
 #[cfg(feature = "speedy2d")]
 mod gui_queue;
@@ -56,7 +66,11 @@ mod gui_statusbar;
 #[cfg(feature = "speedy2d")]
 mod gui_text;
 #[cfg(feature = "speedy2d")]
+mod gui_theme;
+#[cfg(feature = "speedy2d")]
 mod gui_wrappers;
+#[cfg(feature = "media-keys")]
+mod media_keys;
 #[cfg(feature = "merscfg")]
 mod merscfg;
 #[cfg(feature = "speedy2d")]
@@ -64,8 +78,24 @@ mod textcfg;
 
 #[derive(Parser, Debug)]
 struct Args {
-    /// the address to be used for the tcp connection to the server
-    addr: SocketAddr,
+    /// the address of the server: <ip>:<port> for tcp, or unix:<path> for a unix domain socket
+    addr: ServerAddr,
+    /// the token, if the server requires one. if not set, it is read from the `auth_token` file in the config directory (if that exists).
+    #[arg(
+        long,
+        value_name = "token",
+        env = "MUSICDB_AUTH_TOKEN",
+        hide_env_values = true
+    )]
+    auth_token: Option<String>,
+    /// don't ask the server to compress the initial sync. required for servers which don't support compression.
+    #[arg(long)]
+    no_compression: bool,
+    /// in syncplayer modes, be the audio output of a server started with `--no-playback`:
+    /// the server then waits for this client to finish playing a song before moving on to the next one.
+    #[cfg(feature = "playback")]
+    #[arg(long)]
+    audio_output: bool,
     /// what to do
     #[command(subcommand)]
     mode: Mode,
@@ -101,6 +131,42 @@ fn get_config_file_path() -> PathBuf {
         .to_path_buf()
 }
 
+/// where the library received from the server at `addr` is cached, see `save_library_cache`
+fn get_cache_file_path(addr: &ServerAddr) -> PathBuf {
+    let name = addr
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    directories::ProjectDirs::from("", "", "musicdb-client")
+        .unwrap()
+        .cache_dir()
+        .join(format!("library_{name}"))
+}
+
+/// Saves the library, so it can be shown if the server can't be reached the next time the gui is started.
+fn save_library_cache(db: &mut Database, path: &Path) {
+    if let Some(dir) = path.parent() {
+        _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(e) = db.save_database(Some(path.to_path_buf())) {
+        eprintln!("[warn] Couldn't save the library cache to {path:?}: {e}");
+    }
+}
+
+/// the gui can start without a connection to the server, it shows the cached library until it can connect
+fn can_start_offline(mode: &Mode) -> bool {
+    match mode {
+        #[cfg(feature = "speedy2d")]
+        Mode::Gui => true,
+        #[cfg(feature = "speedy2d")]
+        #[cfg(feature = "playback")]
+        Mode::GuiSyncplayerLocal { .. } | Mode::GuiSyncplayerNetwork => true,
+        #[allow(unreachable_patterns)]
+        _ => false,
+    }
+}
+
 fn main() {
     #[cfg(not(feature = "speedy2d"))]
     #[cfg(not(feature = "mers"))]
@@ -110,10 +176,40 @@ fn main() {
     let args = Args::parse();
     // start
     let addr = args.addr;
-    let mut con = TcpStream::connect(addr).unwrap();
+    let auth_token = args.auth_token.or_else(|| {
+        std::fs::read_to_string(get_config_file_path().join("auth_token"))
+            .ok()
+            .map(|token| token.trim().to_owned())
+    });
     let mode = args.mode;
-    writeln!(con, "main").unwrap();
-    let database = Arc::new(Mutex::new(Database::new_clientside()));
+    #[cfg(feature = "playback")]
+    let audio_output = args.audio_output;
+    let compression = !args.no_compression;
+    let cache_file = can_start_offline(&mode).then(|| get_cache_file_path(&addr));
+    let mut database = Database::new_clientside();
+    let (con, writer) = match connect_main(&addr, auth_token.as_deref(), compression) {
+        Ok((con, writer)) => (Some(con), Some(writer)),
+        Err(e) => {
+            // show the cached library, the connection is retried in the background
+            match cache_file.as_deref().map(Database::load_clientside_cache) {
+                Some(Ok(cache)) => {
+                    eprintln!(
+                        "[warn] Couldn't connect to the server ({e}), showing the cached library"
+                    );
+                    database = cache;
+                    (None, None)
+                }
+                _ => {
+                    eprintln!("[error] Couldn't connect to the server: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+    #[cfg(feature = "speedy2d")]
+    let offline = con.is_none();
+    let command_con: CommandConnection = Arc::new(Mutex::new(writer));
+    let database = Arc::new(Mutex::new(database));
     #[cfg(feature = "speedy2d")]
     let update_gui_sender: Arc<Mutex<Option<speedy2d::window::UserEventSender<GuiEvent>>>> =
         Arc::new(Mutex::new(None));
@@ -128,7 +224,9 @@ fn main() {
         let mers_after_db_updated_action = Arc::clone(&mers_after_db_updated_action);
         let mode = mode.clone();
         let database = Arc::clone(&database);
-        let mut con = con.try_clone().unwrap();
+        let addr = addr.clone();
+        let auth_token = auth_token.clone();
+        let command_con = Arc::clone(&command_con);
         // this is all you need to keep the db in sync
         thread::spawn(move || {
             #[cfg(feature = "playback")]
@@ -148,17 +246,26 @@ fn main() {
             #[allow(unused)]
             let mut cache_manager = None;
             #[cfg(feature = "playback")]
-            let mut player = if is_syncplayer {
+            let player = if is_syncplayer {
                 let cm = CacheManager::new(Arc::clone(&database));
                 cm.set_memory_mib(1024, 2048);
                 cm.set_cache_songs_count(20);
                 cache_manager = Some(cm);
-                Some(Player::new_client(
+                Some(Arc::new(Mutex::new(Player::new_client(
                     PlayerBackendFeat::new_without_command_sending().unwrap(),
-                ))
+                ))))
             } else {
                 None
             };
+            #[cfg(feature = "playback")]
+            if let (Some(player), true) = (&player, audio_output) {
+                start_audio_output(
+                    &addr,
+                    auth_token.as_deref(),
+                    Arc::clone(&database),
+                    Arc::clone(player),
+                );
+            }
             #[allow(unused_labels)]
             'ifstatementworkaround: {
                 // use if+break instead of if-else because we can't #[cfg(feature)] the if statement,
@@ -182,18 +289,54 @@ fn main() {
                     break 'ifstatementworkaround;
                 }
                 let mut db = database.lock().unwrap();
-                let client_con: Box<dyn ClientIo> = Box::new(TcpStream::connect(addr).unwrap());
-                db.remote_server_as_song_file_source = Some(Arc::new(Mutex::new(
-                    musicdb_lib::server::get::Client::new(BufReader::new(client_con)).unwrap(),
-                )));
+                db.remote_server_as_song_file_source = Some(Arc::new(
+                    musicdb_lib::server::get::Pool::connect_to(
+                        addr.clone(),
+                        auth_token.clone(),
+                        None,
+                        None,
+                    )
+                    .unwrap(),
+                ));
             }
+            // after reconnecting, commands are only sent once the new server's init is done
+            let mut reconnected_con = None;
+            let mut con = match con {
+                Some(con) => con,
+                // started offline
+                None => {
+                    let (con, writer) = reconnect(&addr, auth_token.as_deref(), compression);
+                    reconnected_con = Some(writer);
+                    database.lock().unwrap().client_reset_for_init();
+                    con
+                }
+            };
             loop {
-                let command = Command::from_bytes(&mut con).unwrap();
+                let command = match Command::from_bytes(&mut con) {
+                    Ok(command) => command,
+                    Err(e) => {
+                        eprintln!("[warn] Lost the connection to the server: {e}");
+                        if let Some(con) = command_con.lock().unwrap().take() {
+                            _ = con.shutdown_connection();
+                        }
+                        #[cfg(feature = "speedy2d")]
+                        if let Some(v) = &*update_gui_sender.lock().unwrap() {
+                            _ = v.send_event(GuiEvent::Disconnected);
+                        }
+                        let (new_con, writer) =
+                            reconnect(&addr, auth_token.as_deref(), compression);
+                        con = new_con;
+                        reconnected_con = Some(writer);
+                        database.lock().unwrap().client_reset_for_init();
+                        continue;
+                    }
+                };
                 let mut db = database.lock().unwrap();
                 let action = db.seq.recv(command);
+                let init_complete = matches!(action, Action::InitComplete);
                 #[cfg(feature = "playback")]
-                if let Some(player) = &mut player {
-                    player.handle_action(&action);
+                if let Some(player) = &player {
+                    player.lock().unwrap().handle_action(&action);
                 }
                 #[allow(unused_labels)]
                 'feature_if: {
@@ -206,8 +349,19 @@ fn main() {
                     db.apply_action_unchecked_seq(action, None);
                 }
                 #[cfg(feature = "playback")]
-                if let Some(player) = &mut player {
-                    player.update_dont_uncache(&mut *db);
+                if let Some(player) = &player {
+                    player.lock().unwrap().update_dont_uncache(&mut *db);
+                }
+                if let (true, Some(cache_file)) = (init_complete, &cache_file) {
+                    save_library_cache(&mut db, cache_file);
+                }
+                if reconnected_con.is_some() && db.is_client_init() {
+                    *command_con.lock().unwrap() = reconnected_con.take();
+                    eprintln!("[info] Reconnected to the server");
+                    #[cfg(feature = "speedy2d")]
+                    if let Some(v) = &*update_gui_sender.lock().unwrap() {
+                        _ = v.send_event(GuiEvent::Reconnected);
+                    }
                 }
                 drop(db);
                 #[cfg(feature = "speedy2d")]
@@ -219,13 +373,23 @@ fn main() {
     };
     macro_rules! gui_modes {
         () => {{
-            let get_con: Arc<Mutex<musicdb_lib::server::get::Client<Box<dyn ClientIo + 'static>>>> =
-                Arc::new(Mutex::new(
-                    musicdb_lib::server::get::Client::new(BufReader::new(Box::new(
-                        TcpStream::connect(addr).expect("opening get client connection"),
-                    ) as _))
-                    .expect("initializing get client connection"),
-                ));
+            let get_con: Arc<musicdb_lib::server::get::Pool<Box<dyn ClientIo + 'static>>> =
+                Arc::new(if offline {
+                    musicdb_lib::server::get::Pool::connect_to_lazy(
+                        addr.clone(),
+                        auth_token.clone(),
+                        Some(GET_TIMEOUT),
+                        Some(GET_TIMEOUT),
+                    )
+                } else {
+                    musicdb_lib::server::get::Pool::connect_to(
+                        addr.clone(),
+                        auth_token.clone(),
+                        Some(GET_TIMEOUT),
+                        Some(GET_TIMEOUT),
+                    )
+                    .expect("opening get client connection")
+                });
             #[allow(unused_labels)]
             'anotherifstatement: {
                 #[cfg(feature = "playback")]
@@ -250,7 +414,8 @@ fn main() {
             });
             gui::main(
                 database,
-                con,
+                command_con,
+                offline,
                 get_con,
                 sender,
                 #[cfg(feature = "merscfg")]
@@ -274,11 +439,14 @@ fn main() {
             let mut src =
                 musicdb_mers::mers_lib::prelude_compile::Source::new_from_file(path).unwrap();
             let srca = Arc::new(src.clone());
-            let con = Mutex::new(con);
             let (mut i1, mut i2, mut i3) = musicdb_mers::add(
                 musicdb_mers::mers_lib::prelude_compile::Config::new().bundle_std(),
                 &database,
-                &Arc::new(move |cmd: Command| cmd.to_bytes(&mut *con.lock().unwrap()).unwrap()),
+                &Arc::new(move |cmd: Command| {
+                    if let Err(e) = send_command(&command_con, &cmd) {
+                        eprintln!("[warn] Couldn't send command to the server: {e}");
+                    }
+                }),
                 &mers_after_db_updated_action,
             )
             .infos();
@@ -330,6 +498,110 @@ pub fn accumulate<F: FnMut() -> Option<T>, T>(mut f: F) -> Vec<T> {
     o
 }
 
+/// the write half of the `main` connection, used to send commands to the server.
+/// `None` while the connection is lost and the client is reconnecting.
+pub type CommandConnection = Arc<Mutex<Option<Box<dyn Connection>>>>;
+
+/// Sends `command` to the server. If that fails, the connection is shut down,
+/// so the thread reading from it notices and reconnects.
+pub fn send_command(connection: &CommandConnection, command: &Command) -> std::io::Result<()> {
+    let mut connection = connection.lock().unwrap();
+    let Some(con) = &mut *connection else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotConnected,
+            "not connected to the server",
+        ));
+    };
+    let result = command.to_bytes(con);
+    if result.is_err() {
+        _ = con.shutdown_connection();
+        *connection = None;
+    }
+    result
+}
+
+/// the first reconnect attempt happens after this delay, which doubles after every failed attempt
+const RECONNECT_MIN_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Connects to the server and starts a new `main` connection.
+/// Returns the connection to read from and a clone of it to write to.
+fn connect_main(
+    addr: &ServerAddr,
+    auth_token: Option<&str>,
+    compression: bool,
+) -> std::io::Result<(Box<dyn Connection>, Box<dyn Connection>)> {
+    let mut con = addr.connect_with_auth(auth_token)?;
+    start_main(&mut con, compression)?;
+    let writer = con.try_clone_connection()?;
+    Ok((con, writer))
+}
+
+/// `connect_main`, retrying until it works
+fn reconnect(
+    addr: &ServerAddr,
+    auth_token: Option<&str>,
+    compression: bool,
+) -> (Box<dyn Connection>, Box<dyn Connection>) {
+    let mut delay = RECONNECT_MIN_DELAY;
+    loop {
+        thread::sleep(delay);
+        match connect_main(addr, auth_token, compression) {
+            Ok(con) => return con,
+            Err(e) => {
+                delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                eprintln!(
+                    "[info] Couldn't reconnect to the server ({e}), retrying in {}s",
+                    delay.as_secs()
+                );
+            }
+        }
+    }
+}
+
+/// requests on the gui's get connection fail if the server doesn't respond for this long,
+/// so that a server restart doesn't block cover loading forever
+#[cfg(feature = "speedy2d")]
+const GET_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// how often the audio output checks if the current song ended
+#[cfg(feature = "playback")]
+const AUDIO_OUTPUT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Opens an `output` connection, which tells the server that this client plays the audio,
+/// and sends `NextSongIfCurrent` and `PlaybackPosition` through it, see `musicdb_lib::server::relay`.
+#[cfg(feature = "playback")]
+fn start_audio_output<T: PlayerBackend<SongCustomData> + Send + 'static>(
+    addr: &ServerAddr,
+    auth_token: Option<&str>,
+    database: Arc<Mutex<Database>>,
+    player: Arc<Mutex<Player<T>>>,
+) {
+    let mut con = addr.connect_with_auth(auth_token).unwrap();
+    writeln!(con, "output").unwrap();
+    thread::spawn(move || {
+        let mut reporter = OutputReporter::new();
+        loop {
+            thread::sleep(AUDIO_OUTPUT_INTERVAL);
+            let mut db = database.lock().unwrap();
+            let actions = reporter.update(&player.lock().unwrap(), &db);
+            // so the gui can show the exact position
+            for action in &actions {
+                if let musicdb_lib::server::Action::PlaybackPosition(id, pos) = action {
+                    db.playback_position = Some((*id, *pos, std::time::Instant::now()));
+                }
+            }
+            drop(db);
+            for action in actions {
+                if let Err(e) = action.cmd(0xFFu8).to_bytes(&mut con) {
+                    eprintln!("[WARN] Lost the connection used as the audio output: {e}");
+                    return;
+                }
+            }
+        }
+    });
+}
+
 fn get_cover(song: SongId, database: &Database) -> Option<CoverId> {
     let song = database.get_song(&song)?;
     if let Some(v) = song.cover {
@@ -0,0 +1,117 @@
+use std::sync::{mpsc, Arc, Mutex};
+
+use musicdb_lib::data::database::{Database, UpdateEndpoint};
+use souvlaki::{
+    MediaControlEvent, MediaControls, MediaPlayback, MediaPosition, PlatformConfig, SeekDirection,
+};
+use speedy2d::window::UserEventSender;
+
+use crate::gui::GuiEvent;
+
+/// a media key or desktop widget was used, sent to the gui as a `GuiEvent::MediaKey`
+pub enum MediaKey {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Stop,
+    /// milliseconds, negative to seek backward
+    SeekBy(i64),
+    /// milliseconds
+    SetPosition(u64),
+    SetVolume(f32),
+}
+
+/// Listens for media keys on its own thread, using the system's media controls (MPRIS on linux).
+/// Uses a different name than the server's MPRIS integration, so both can run on the same machine.
+pub fn start(database: Arc<Mutex<Database>>, sender: UserEventSender<GuiEvent>) {
+    std::thread::spawn(move || {
+        if let Err(e) = run(database, sender) {
+            eprintln!("[warn] media keys: {e}");
+        }
+    });
+}
+
+fn run(database: Arc<Mutex<Database>>, sender: UserEventSender<GuiEvent>) -> Result<(), String> {
+    let mut controls = MediaControls::new(PlatformConfig {
+        display_name: "MusicDb Client",
+        dbus_name: "musicdb_client",
+        hwnd: None,
+    })
+    .map_err(|e| format!("Couldn't create media controls: {e:?}"))?;
+    controls
+        .attach(move |event| {
+            let key = match event {
+                MediaControlEvent::Play => MediaKey::Play,
+                MediaControlEvent::Pause => MediaKey::Pause,
+                MediaControlEvent::Toggle => MediaKey::Toggle,
+                MediaControlEvent::Next => MediaKey::Next,
+                MediaControlEvent::Previous => MediaKey::Previous,
+                MediaControlEvent::Stop => MediaKey::Stop,
+                MediaControlEvent::Seek(direction) => {
+                    MediaKey::SeekBy(seek_offset(direction, 10_000))
+                }
+                MediaControlEvent::SeekBy(direction, by) => {
+                    MediaKey::SeekBy(seek_offset(direction, by.as_millis() as _))
+                }
+                MediaControlEvent::SetPosition(MediaPosition(pos)) => {
+                    MediaKey::SetPosition(pos.as_millis() as _)
+                }
+                MediaControlEvent::SetVolume(volume) => MediaKey::SetVolume(volume as _),
+                MediaControlEvent::OpenUri(_)
+                | MediaControlEvent::Raise
+                | MediaControlEvent::Quit => return,
+            };
+            _ = sender.send_event(GuiEvent::MediaKey(key));
+        })
+        .map_err(|e| format!("Couldn't attach media controls: {e:?}"))?;
+    // desktops only send media keys to players which report their playback status
+    let (cmd_sender, receiver) = mpsc::channel();
+    let mut playing = None;
+    {
+        let mut db = database.lock().unwrap();
+        update_playback(&db, &mut playing, &mut controls);
+        let udepid = db.update_endpoints_id;
+        db.update_endpoints_id += 1;
+        db.update_endpoints
+            .push((udepid, UpdateEndpoint::CmdChannel(cmd_sender)));
+    }
+    while receiver.recv().is_ok() {
+        // handle all commands which arrived in the meantime at once
+        while receiver.try_recv().is_ok() {}
+        update_playback(&database.lock().unwrap(), &mut playing, &mut controls);
+    }
+    Ok(())
+}
+
+fn seek_offset(direction: SeekDirection, millis: i64) -> i64 {
+    match direction {
+        SeekDirection::Forward => millis,
+        SeekDirection::Backward => -millis,
+    }
+}
+
+/// `playing` is what was last sent to the media controls, `Some(None)` meaning stopped
+fn update_playback(
+    db: &Database,
+    playing: &mut Option<Option<bool>>,
+    controls: &mut MediaControls,
+) {
+    let now = db
+        .queue
+        .get_current_song(db)
+        .is_some()
+        .then_some(db.playing);
+    if *playing != Some(now) {
+        *playing = Some(now);
+        let playback = match now {
+            None => MediaPlayback::Stopped,
+            Some(true) => MediaPlayback::Playing { progress: None },
+            Some(false) => MediaPlayback::Paused { progress: None },
+        };
+        if let Err(e) = controls.set_playback(playback) {
+            eprintln!("[warn] media keys: couldn't set playback status: {e:?}");
+        }
+    }
+}
@@ -305,7 +305,7 @@ impl MersCfg {
                     notif_sender
                         .send(Box::new(move |_| {
                             (
-                                Box::new(Panel::with_background(
+                                Box::new(Panel::with_theme_background(
                                     GuiElemCfg::default(),
                                     (
                                         Label::new(
@@ -329,7 +329,7 @@ impl MersCfg {
                                             Vec2::new(0.5, 1.0),
                                         ),
                                     ),
-                                    Color::from_rgba(0.0, 0.0, 0.0, 0.8),
+                                    |t| t.notif_background,
                                 )),
                                 NotifInfo::new(duration),
                             )
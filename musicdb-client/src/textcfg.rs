@@ -1,12 +1,16 @@
 use std::{
     fmt::Display,
     str::{Chars, FromStr},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use musicdb_lib::data::{database::Database, song::Song, CoverId, GeneralData};
 use speedy2d::color::Color;
 
-use crate::gui_text::{AdvancedContent, Content, ImageSource};
+use crate::{
+    gui_queue::{queue_position_text, queue_remaining_text},
+    gui_text::{AdvancedContent, Content, ImageSource},
+};
 
 #[derive(Debug)]
 pub struct TextBuilder(pub Vec<TextPart>);
@@ -22,6 +26,20 @@ pub enum TextPart {
     AlbumName,
     ArtistName,
     SongDuration(bool),
+    /// the `Year=` tag of the song, or of its album
+    SongYear,
+    /// the `Year=` tag of the song's album
+    AlbumYear,
+    /// the `Genre=` tag of the song, its album or its artist
+    Genre,
+    /// the title of the song after the current one in the queue
+    NextSongTitle,
+    /// `song 12 of 87`, see `gui_queue::queue_position_text`
+    QueuePosition,
+    /// `42 min remaining`, see `gui_queue::queue_remaining_text`
+    QueueRemaining,
+    /// the current time, `hh:mm`
+    Clock,
     /// Searches for a tag with exactly the provided value.
     /// Returns nothing or one of the following characters:
     /// `s` for Song, `a` for Album, and `A` for Artist.
@@ -150,6 +168,42 @@ impl TextBuilder {
                         });
                     }
                 }
+                TextPart::SongYear => {
+                    let [song, album, _] = all_general(db, &current_song);
+                    if let Some(year) = tag_value(song, "Year=").or(tag_value(album, "Year=")) {
+                        push!(year.to_owned());
+                    }
+                }
+                TextPart::AlbumYear => {
+                    let [_, album, _] = all_general(db, &current_song);
+                    if let Some(year) = tag_value(album, "Year=") {
+                        push!(year.to_owned());
+                    }
+                }
+                TextPart::Genre => {
+                    if let Some(genre) = all_general(db, &current_song)
+                        .into_iter()
+                        .find_map(|gen| tag_value(gen, "Genre="))
+                    {
+                        push!(genre.to_owned());
+                    }
+                }
+                TextPart::NextSongTitle => {
+                    if let Some(song) = db.queue.get_next_song(db).and_then(|id| db.get_song(id)) {
+                        push!(song.title.to_owned());
+                    }
+                }
+                TextPart::QueuePosition => {
+                    if let Some(s) = queue_position_text(db) {
+                        push!(s);
+                    }
+                }
+                TextPart::QueueRemaining => {
+                    if let Some(s) = queue_remaining_text(db) {
+                        push!(s);
+                    }
+                }
+                TextPart::Clock => push!(clock_text()),
                 TextPart::TagEq(p) => {
                     for (i, gen) in all_general(db, &current_song).into_iter().enumerate() {
                         if let Some(_) = gen.and_then(|gen| gen.tags.iter().find(|t| *t == p)) {
@@ -205,6 +259,35 @@ impl TextBuilder {
         }
     }
 }
+/// the rest of the first tag which starts with `prefix`
+fn tag_value<'a>(general: Option<&'a GeneralData>, prefix: &str) -> Option<&'a str> {
+    general?
+        .tags
+        .iter()
+        .find_map(|tag| tag.strip_prefix(prefix))
+}
+/// minutes since the unix epoch, `\T` only changes when this does
+pub fn unix_minutes() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 60)
+}
+/// the local time as `hh:mm`, from the `date` command.
+/// if that isn't available (e.g. on windows), the time is in UTC.
+fn clock_text() -> String {
+    std::process::Command::new("date")
+        .arg("+%H:%M")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|time| time.trim().to_owned())
+        .filter(|time| !time.is_empty())
+        .unwrap_or_else(|| {
+            let minutes = unix_minutes();
+            format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+        })
+}
 impl FromStr for TextBuilder {
     type Err = TextBuilderParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -260,6 +343,34 @@ impl TextBuilder {
                             done!();
                             vec.push(TextPart::SongDuration(true));
                         }
+                        Some('y') => {
+                            done!();
+                            vec.push(TextPart::SongYear);
+                        }
+                        Some('Y') => {
+                            done!();
+                            vec.push(TextPart::AlbumYear);
+                        }
+                        Some('g') => {
+                            done!();
+                            vec.push(TextPart::Genre);
+                        }
+                        Some('N') => {
+                            done!();
+                            vec.push(TextPart::NextSongTitle);
+                        }
+                        Some('q') => {
+                            done!();
+                            vec.push(TextPart::QueuePosition);
+                        }
+                        Some('r') => {
+                            done!();
+                            vec.push(TextPart::QueueRemaining);
+                        }
+                        Some('T') => {
+                            done!();
+                            vec.push(TextPart::Clock);
+                        }
                         Some('s') => {
                             done!();
                             vec.push(TextPart::SetScale({
@@ -353,6 +464,10 @@ impl TextBuilder {
                                 }
                             });
                         }
+                        // letters are reserved for placeholders, so typos aren't silently shown as text
+                        Some(ch) if ch.is_ascii_alphanumeric() => {
+                            return Err(TextBuilderParseError::UnknownEscape(ch))
+                        }
                         Some(ch) => current.push(ch),
                     },
                     '%' => {
@@ -407,6 +522,7 @@ pub enum TextBuilderParseError {
     CouldntParse(String, String),
     InvalidImageSourceName(String),
     InvalidImageCoverId(String),
+    UnknownEscape(char),
 }
 impl Display for TextBuilderParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -423,6 +539,30 @@ impl Display for TextBuilderParseError {
             Self::CouldntParse(v, t) => write!(f, "Couldn't parse value '{v}' to type '{t}'."),
             Self::InvalidImageSourceName(name) => write!(f, "Invalid image source name: '{name}'."),
             Self::InvalidImageCoverId(id) => write!(f, "Invalid image cover id: '{id}'."),
+            Self::UnknownEscape(ch) => write!(f, "Unknown placeholder: '\\{ch}'. The placeholders are listed in the [text] section of the default config_gui.toml."),
+        }
+    }
+}
+
+#[test]
+fn test_parse_placeholders() {
+    let parts = |s: &str| s.parse::<TextBuilder>().map(|t| format!("{:?}", t.0));
+    assert_eq!(
+        parts("\\y\\Y\\g\\N").unwrap(),
+        "[SongYear, AlbumYear, Genre, NextSongTitle]"
+    );
+    assert_eq!(parts("\\\\\\#\\%").unwrap(), "[Literal(\"\\\\#%\")]");
+    assert!(matches!(
+        "\\x".parse::<TextBuilder>(),
+        Err(TextBuilderParseError::UnknownEscape('x'))
+    ));
+    // the texts in the default config
+    let config = include_str!("config_gui.toml")
+        .parse::<toml::Table>()
+        .unwrap();
+    for (key, text) in config["text"].as_table().unwrap() {
+        if let Some(text) = text.as_str() {
+            assert!(text.parse::<TextBuilder>().is_ok(), "{key}");
         }
     }
 }
@@ -1,17 +1,16 @@
 use std::{
-    cmp::Ordering,
     collections::HashMap,
     fs,
     io::Write,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex}, time::SystemTime,
+    sync::{Arc, Mutex},
 };
 
-use id3::TagLike;
 use musicdb_lib::data::{
     album::Album,
     artist::Artist,
     database::{Cover, Database},
+    scan::{all_files_in_dir, find_cover, is_song_file, read_song_file, sort_songs},
     song::Song,
     CoverId, DatabaseLocation, GeneralData,
 };
@@ -66,27 +65,22 @@ fn main() {
     std::io::stdin().read_line(&mut String::new()).unwrap();
     // start
     eprintln!("finding files...");
-    let files = get_all_files_in_dir(&lib_dir);
+    let files = all_files_in_dir(&lib_dir);
     let files_count = files.len();
-    eprintln!("found {files_count} files, reading metadata...");
+    eprintln!("found {files_count} files, reading metadata... (this will be much faster with --skip-duration because it avoids loading and decoding all the mp3 files)");
     let mut songs = Vec::new();
     for (i, file) in files.into_iter().enumerate() {
         let mut newline = OnceNewline::new();
         eprint!("\r{}/{}", i + 1, files_count);
-        if let Ok(metadata) = file.metadata() {
-            _ = std::io::stderr().flush();
-            if let Some("mp3") = file.extension().and_then(|ext_os| ext_os.to_str()) {
-                match id3::Tag::read_from_path(&file) {
-                    Err(e) => {
-                        newline.now();
-                        eprintln!("[{file:?}] error reading id3 tag: {e}");
-                    }
-                    Ok(tag) => songs.push((file, metadata, tag)),
+        _ = std::io::stderr().flush();
+        if is_song_file(&file) {
+            match read_song_file(lib_dir.as_ref(), file, skip_duration) {
+                Err(e) => {
+                    newline.now();
+                    eprintln!("[err] {e}, skipping");
                 }
+                Ok(song) => songs.push(song),
             }
-        } else {
-            newline.now();
-            eprintln!("[err] couldn't get metadata of file {:?}, skipping", file);
         }
     }
     eprintln!("\nloaded metadata of {} files.", songs.len());
@@ -99,61 +93,19 @@ fn main() {
         singles: vec![],
         general: GeneralData::default(),
     });
-    eprintln!(
-        "searching for artists and adding songs... (this will be much faster with --skip-duration because it avoids loading and decoding all the mp3 files)"
-    );
+    eprintln!("searching for artists and adding songs...");
     let mut artists = HashMap::new();
     let len = songs.len();
     let mut prev_perc = 999;
-    songs.sort_by(|(path1, _, tags1), (path2, _, tags2)| {
-        // Sort by Disc->Track->Path
-        match (tags1.disc(), tags2.disc()) {
-            (Some(d1), Some(d2)) => d1.cmp(&d2),
-            (Some(_), None) => Ordering::Greater,
-            (None, Some(_)) => Ordering::Less,
-            (None, None) => Ordering::Equal,
-        }
-        .then_with(|| {
-            match (tags1.track(), tags2.track()) {
-                (Some(t1), Some(t2)) => t1.cmp(&t2),
-                (Some(_), None) => Ordering::Greater,
-                (None, Some(_)) => Ordering::Less,
-                (None, None) => Ordering::Equal,
-            }
-            .then_with(|| path1.cmp(&path2))
-        })
-    });
-    for (i, (song_path, song_file_metadata, song_tags)) in songs.into_iter().enumerate() {
+    sort_songs(&mut songs);
+    for (i, song) in songs.into_iter().enumerate() {
         let perc = i * 100 / len;
         if perc != prev_perc {
             eprint!("{perc: >2}%\r");
             _ = std::io::stderr().lock().flush();
             prev_perc = perc;
         }
-        let mut general = GeneralData::default();
-        match (song_tags.track(), song_tags.total_tracks()) {
-            (None, None) => {}
-            (Some(n), Some(t)) => general.tags.push(format!("TrackNr={n}/{t}")),
-            (Some(n), None) => general.tags.push(format!("TrackNr={n}")),
-            (None, Some(t)) => general.tags.push(format!("TrackNr=?/{t}")),
-        }
-        match (song_tags.disc(), song_tags.total_discs()) {
-            (None, None) => {}
-            (Some(n), Some(t)) => general.tags.push(format!("DiscNr={n}/{t}")),
-            (Some(n), None) => general.tags.push(format!("DiscNr={n}")),
-            (None, Some(t)) => general.tags.push(format!("DiscNr=?/{t}")),
-        }
-        if let Some(year) = song_tags.year() {
-            general.tags.push(format!("Year={year}"));
-        }
-        if let Some(genre) = song_tags.genre_parsed() {
-            general.tags.push(format!("Genre={genre}"));
-        }
-        let (artist_id, album_id) = if let Some(artist) = song_tags
-            .album_artist()
-            .filter(|v| !v.trim().is_empty())
-            .or_else(|| song_tags.artist().filter(|v| !v.trim().is_empty()))
-        {
+        let (artist_id, album_id) = if let Some(artist) = &song.artist {
             let artist_id = if !artists.contains_key(artist) {
                 let artist_id = database.add_artist_new(Artist {
                     id: 0,
@@ -168,7 +120,7 @@ fn main() {
             } else {
                 artists.get(artist).unwrap().0
             };
-            if let Some(album) = song_tags.album().filter(|a| !a.trim().is_empty()) {
+            if let Some(album) = &song.album {
                 let (_, albums) = artists.get_mut(artist).unwrap();
                 let album_id = if !albums.contains_key(album) {
                     let album_id = database.add_album_new(Album {
@@ -181,7 +133,7 @@ fn main() {
                     });
                     albums.insert(
                         album.to_string(),
-                        (album_id, song_path.parent().map(|dir| dir.to_path_buf())),
+                        (album_id, song.path.parent().map(|dir| dir.to_path_buf())),
                     );
                     album_id
                 } else {
@@ -189,7 +141,7 @@ fn main() {
                     if album
                         .1
                         .as_ref()
-                        .is_some_and(|dir| Some(dir.as_path()) != song_path.parent())
+                        .is_some_and(|dir| Some(dir.as_path()) != song.path.parent())
                     {
                         // album directory is inconsistent
                         album.1 = None;
@@ -203,81 +155,19 @@ fn main() {
         } else {
             (unknown_artist, None)
         };
-        let path = song_path.strip_prefix(&lib_dir).unwrap();
-        let title = song_tags
-            .title()
-            .map_or(None, |title| {
-                if title.trim().is_empty() {
-                    None
-                } else {
-                    Some(title.to_string())
-                }
-            })
-            .unwrap_or_else(|| {
-                song_path
-                    .file_stem()
-                    .unwrap()
-                    .to_string_lossy()
-                    .into_owned()
-            });
         database.add_song_new(Song::new(
             DatabaseLocation {
-                rel_path: path.to_path_buf(),
-            },
-            match song_path.metadata() {
-                Ok(v) => match v.modified() {
-                    Ok(v) => if let Ok(time) = v.duration_since(SystemTime::UNIX_EPOCH) {
-                        Some(time.as_secs())
-                    } else {
-                        eprintln!(
-                            "LastModified time of song {:?} is before the UNIX-EPOCH, setting `None`.",
-                            song_path
-                        );
-                        None
-                    },
-                    Err(e) => {
-                        eprintln!(
-                            "LastModified time of song {:?} not available: {e}.",
-                            song_path
-                        );
-                        None
-                    }
-                }
-                Err(e) => {
-                    eprintln!(
-                        "LastModified time of song {:?} could not be read: {e}.",
-                        song_path
-                    );
-                    None
-                    
-                }
+                rel_path: song.rel_path,
             },
-            title.clone(),
+            song.last_modified,
+            song.title,
             album_id,
             artist_id,
             vec![],
             None,
-            song_file_metadata.len(),
-            if let Some(dur) = song_tags.duration() {
-                dur as u64
-            } else {
-                if skip_duration {
-                    eprintln!(
-                        "Duration of song {:?} not found in tags, using 0 instead!",
-                        song_path
-                    );
-                    0
-                } else {
-                    match mp3_duration::from_path(&song_path) {
-                        Ok(dur) => dur.as_millis().min(u64::MAX as _) as u64,
-                        Err(e) => {
-                            eprintln!("Duration of song {song_path:?} not found in tags and can't be determined from the file contents either ({e}). Using duration 0 instead.");
-                            0
-                        }
-                    }
-                }
-            },
-            general,
+            song.file_size,
+            song.duration_millis,
+            song.general,
         ));
     }
     eprintln!("searching for covers...");
@@ -394,23 +284,6 @@ fn main() {
     eprintln!("done!");
 }
 
-fn get_all_files_in_dir(dir: impl AsRef<Path>) -> Vec<PathBuf> {
-    let mut files = Vec::new();
-    _ = all_files_in_dir(&dir, &mut files);
-    files
-}
-fn all_files_in_dir(dir: impl AsRef<Path>, vec: &mut Vec<PathBuf>) -> Result<(), std::io::Error> {
-    for path in fs::read_dir(dir)?
-        .filter_map(|possible_entry| possible_entry.ok())
-        .map(|entry| entry.path())
-    {
-        if all_files_in_dir(&path, vec).is_err() {
-            vec.push(path);
-        }
-    }
-    Ok(())
-}
-
 struct OnceNewline(bool);
 impl OnceNewline {
     pub fn new() -> Self {
@@ -429,37 +302,11 @@ fn get_cover(
     abs_dir: impl AsRef<Path>,
     multiple_options_list: &mut Vec<PathBuf>,
 ) -> Option<CoverId> {
-    let mut multiple = false;
-    let mut cover = None;
-    if let Ok(files) = fs::read_dir(&abs_dir) {
-        for file in files {
-            if let Ok(file) = file {
-                        let path = file.path();
-                if let Ok(metadata) = path.metadata() {
-                    if metadata.is_file() {
-                        if path.extension().and_then(|v| v.to_str()).is_some_and(|v| {
-                            matches!(v.to_lowercase().as_str(), "png" | "jpg" | "jpeg")
-                        }) {
-                            if cover.is_none()
-                                || cover
-                                    .as_ref()
-                                    .is_some_and(|(_, size)| *size < metadata.len())
-                            {
-                                if cover.is_some() {
-                                    multiple = true;
-                                }
-                                cover = Some((path, metadata.len()));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+    let (cover, multiple) = find_cover(&abs_dir);
     if multiple {
         multiple_options_list.push(abs_dir.as_ref().to_path_buf());
     }
-    if let Some((path, _)) = cover {
+    if let Some(path) = cover {
         let rel_path = path.strip_prefix(&lib_dir).unwrap().to_path_buf();
         Some(database.add_cover_new(Cover {
             location: DatabaseLocation {
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     sync::{
         atomic::{AtomicU32, AtomicU64},
         Arc, Mutex,
@@ -7,13 +8,14 @@ use std::{
     time::Duration,
 };
 
-use colorize::AnsiColor;
-
 use crate::data::queue::{Queue, QueueContent};
 
-use super::database::Database;
+use super::{database::Database, CoverId, SongId};
 
-// CacheManage will never uncache the currently playing song or the song that will be played next.
+// CacheManager will never uncache the currently playing song or the song that will be played next, or their covers.
+// Other songs and covers are uncached when their total size exceeds the budget,
+// starting with the ones which haven't been needed for the longest time, then the ones furthest from the current position in the queue.
+// Covers are cheaper to reload than songs, so they are uncached first.
 
 pub struct CacheManager {
     /// Amount of bytes. If free system memory drops below this number, initiate cleanup.
@@ -21,6 +23,8 @@ pub struct CacheManager {
     /// Amount of bytes. If free system memory is greater than this number, consider caching more songs.
     pub max_avail_mem: Arc<AtomicU64>,
     pub songs_to_cache: Arc<AtomicU32>,
+    /// Amount of bytes. The cached songs' total size won't exceed this, even if there is enough free system memory.
+    pub max_bytes: Arc<AtomicU64>,
     #[allow(unused)]
     thread: Arc<JoinHandle<()>>,
 }
@@ -31,10 +35,12 @@ impl CacheManager {
         let max_avail_mem = Arc::new(AtomicU64::new(1024 * 1024 * 2048));
         // if < 2, does the same as 2.
         let songs_to_cache = Arc::new(AtomicU32::new(10));
+        let max_bytes = Arc::new(AtomicU64::new(u64::MAX));
         Self {
             min_avail_mem: Arc::clone(&min_avail_mem),
             max_avail_mem: Arc::clone(&max_avail_mem),
             songs_to_cache: Arc::clone(&songs_to_cache),
+            max_bytes: Arc::clone(&max_bytes),
             thread: Arc::new(thread::spawn(move || {
                 let sleep_dur_long = Duration::from_secs(20);
                 let sleep_dur_short = Duration::from_secs(1);
@@ -42,10 +48,11 @@ impl CacheManager {
                     sysinfo::RefreshKind::new()
                         .with_memory(sysinfo::MemoryRefreshKind::new().with_ram()),
                 );
-                eprintln!("[{}] Starting CacheManager", "INFO".cyan());
+                log_info!("Starting CacheManager");
                 let mut sleep_short = true;
                 let cleanup_max = 100;
                 let mut cleanup_countdown = cleanup_max;
+                let mut planner = CachePlanner::default();
                 loop {
                     thread::sleep(if sleep_short {
                         sleep_dur_short
@@ -57,7 +64,6 @@ impl CacheManager {
                     si.refresh_memory_specifics(sysinfo::MemoryRefreshKind::new().with_ram());
                     let available_memory = si.available_memory();
                     let min_avail_mem = min_avail_mem.load(std::sync::atomic::Ordering::Relaxed);
-                    let low_memory = available_memory < min_avail_mem;
                     let max_avail_mem = max_avail_mem.load(std::sync::atomic::Ordering::Relaxed);
                     let songs_to_cache = songs_to_cache.load(std::sync::atomic::Ordering::Relaxed);
 
@@ -65,13 +71,11 @@ impl CacheManager {
 
                     let db = database.lock().unwrap();
 
-                    let (queue_current_song, queue_next_song, ids_to_cache) = if songs_to_cache <= 2
-                    {
-                        let queue_current_song = db.queue.get_current_song().copied();
-                        let queue_next_song = db.queue.get_next_song().copied();
+                    let (queue_next_song, ids_to_cache) = if songs_to_cache <= 2 {
+                        let queue_current_song = db.queue.get_current_song(&db).copied();
+                        let queue_next_song = db.queue.get_next_song(&db).copied();
 
                         (
-                            queue_current_song,
                             queue_next_song,
                             match (queue_current_song, queue_next_song) {
                                 (None, None) => vec![],
@@ -88,9 +92,9 @@ impl CacheManager {
                     } else {
                         let mut queue = db.queue.clone();
 
-                        let queue_current_song = queue.get_current_song().copied();
-                        queue.advance_index_inner();
-                        let queue_next_song = queue.get_current_song().copied();
+                        let queue_current_song = queue.get_current_song(&db).copied();
+                        queue.advance_index_inner(&db);
+                        let queue_next_song = queue.get_current_song(&db).copied();
 
                         let mut ids_to_cache = queue_current_song
                             .into_iter()
@@ -98,8 +102,8 @@ impl CacheManager {
                             .collect::<Vec<_>>();
 
                         for _ in 2..songs_to_cache {
-                            queue.advance_index_inner();
-                            if let Some(id) = queue.get_current_song() {
+                            queue.advance_index_inner(&db);
+                            if let Some(id) = queue.get_current_song(&db) {
                                 if !ids_to_cache.contains(id) {
                                     ids_to_cache.push(*id);
                                 }
@@ -108,82 +112,70 @@ impl CacheManager {
                             }
                         }
 
-                        (queue_current_song, queue_next_song, ids_to_cache)
+                        (queue_next_song, ids_to_cache)
                     };
 
-                    if low_memory {
-                        let mut found = false;
-                        for (id, song) in db.songs().iter() {
-                            if !ids_to_cache.contains(id) {
-                                if let Ok(true) = song.cached_data().uncache_data() {
-                                    eprintln!(
-                                        "[{}] CacheManager :: Uncached bytes for song '{}' (memory limit).",
-                                        "INFO".cyan(),
-                                        song.title
-                                    );
-                                    found = true;
-                                    break;
-                                }
-                            }
-                        }
-                        if !found {
-                            // also uncache songs that should be cached, but not current/next song
-                            for id in ids_to_cache.iter().rev() {
-                                if !(queue_current_song.is_some_and(|i| i == *id)
-                                    || queue_next_song.is_some_and(|i| i == *id))
-                                {
-                                    if let Some(song) = db.get_song(id) {
-                                        if let Ok(true) = song.cached_data().uncache_data() {
-                                            eprintln!(
-                                                "[{}] CacheManager :: Uncached bytes for song '{}' (memory limit).",
-                                                "INFO".cyan(),
-                                                song.title
-                                            );
-                                            found = true;
-                                            break;
-                                        }
-                                    }
+                    // each song is followed by its cover (or its album's cover)
+                    let mut wanted = vec![];
+                    let mut protected = vec![];
+                    for (i, id) in ids_to_cache.iter().enumerate() {
+                        let song = CacheItem::Song(*id);
+                        let cover = db.get_song(id).and_then(|song| {
+                            song.cover.or_else(|| {
+                                song.album
+                                    .and_then(|album| db.albums().get(&album))
+                                    .and_then(|album| album.cover)
+                            })
+                        });
+                        let items = std::iter::once(song).chain(cover.map(CacheItem::Cover));
+                        for item in items {
+                            if !wanted.contains(&item) {
+                                wanted.push(item);
+                                if i < 2 {
+                                    protected.push(item);
                                 }
                             }
                         }
-                        if found {
-                            // uncache more songs
-                            sleep_short = true;
-                        }
-                    } else if available_memory > max_avail_mem {
-                        // we have some memory left, maybe cache a song (or cache multiple if we know their byte-sizes)
-                        for song in &ids_to_cache {
-                            if let Some(song) = db.get_song(song) {
-                                match song
-                                    .cached_data()
-                                    .cache_data_start_thread_or_say_already_running(&db, song)
-                                {
-                                    Err(false) => (),
-                                    // thread already running, don't start a second one (otherwise we may load too many songs, using too much memory, causing a cache-uncache-cycle)
-                                    Err(true) => {
-                                        break;
-                                    }
-                                    Ok(true) => {
-                                        eprintln!(
-                                            "[{}] CacheManager :: Start caching bytes for song '{}'.",
-                                            "INFO".cyan(),
-                                            song.title
-                                        );
-                                        sleep_short = true;
-                                        break;
-                                    }
-                                    Ok(false) => (),
-                                }
-                            }
+                    }
+
+                    let max_bytes = max_bytes.load(std::sync::atomic::Ordering::Relaxed);
+                    let changes = planner.step(&*db, &wanted, &protected, |usage| {
+                        memory_budget(
+                            usage,
+                            available_memory,
+                            min_avail_mem,
+                            max_avail_mem,
+                            max_bytes,
+                        )
+                    });
+                    for item in &changes.uncached {
+                        if let Some(song) = match item {
+                            CacheItem::Song(id) => db.get_song(id),
+                            CacheItem::Cover(_) => None,
+                        } {
+                            log_info!(
+                                "CacheManager :: Uncached bytes for song '{}' (over budget).",
+                                song.title
+                            );
                         }
                     }
+                    for song in changes.started.iter().filter_map(|item| match item {
+                        CacheItem::Song(id) => db.get_song(id),
+                        CacheItem::Cover(_) => None,
+                    }) {
+                        log_info!(
+                            "CacheManager :: Start caching bytes for song '{}'.",
+                            song.title
+                        );
+                        // cache more songs
+                        sleep_short = true;
+                    }
 
                     if let Some(song_id) = queue_next_song {
                         if let Some(song) = db.get_song(&song_id) {
                             if song.cached_data().cache_data_start_thread(&db, song) {
-                                eprintln!(
-                                    "[{}] CacheManager :: Start caching bytes for next song, '{}'.",
-                                    "INFO".cyan(),
+                                log_info!(
+                                    "CacheManager :: Start caching bytes for next song, '{}'.",
                                     song.title
                                 );
                             }
@@ -197,22 +189,30 @@ impl CacheManager {
                         cleanup_countdown = cleanup_max;
                         for (id, song) in db.songs() {
                             if let Some(_size) = song.cached_data().has_cached_data() {
-                                if !is_in_queue(*id, &db.queue) {
+                                if !is_in_queue(*id, &db.queue, &db) {
                                     if let Ok(true) = song.cached_data().uncache_data() {
-                                        eprintln!(
-                                            "[{}] CacheManager :: Uncached bytes for song '{}' (not in queue).",
-                                            "INFO".cyan(),
+                                        log_info!(
+                                            "CacheManager :: Uncached bytes for song '{}' (not in queue).",
                                             song.title
                                         );
                                     }
                                 }
-                                fn is_in_queue(id: u64, queue: &Queue) -> bool {
+                                fn is_in_queue(id: u64, queue: &Queue, db: &Database) -> bool {
                                     match queue.content() {
                                         QueueContent::Song(v) => return *v == id,
                                         QueueContent::Folder(folder) => {
-                                            folder.content.iter().any(|q| is_in_queue(id, q))
+                                            folder.content.iter().any(|q| is_in_queue(id, q, db))
+                                        }
+                                        QueueContent::Loop(_, _, inner) => {
+                                            is_in_queue(id, inner, db)
                                         }
-                                        QueueContent::Loop(_, _, inner) => is_in_queue(id, inner),
+                                        QueueContent::Random(_, _, q) => {
+                                            q.iter().any(|q| is_in_queue(id, q, db))
+                                        }
+                                        QueueContent::AlbumRef(album, _) => db
+                                            .albums()
+                                            .get(album)
+                                            .is_some_and(|a| a.songs.contains(&id)),
                                     }
                                 }
                             }
@@ -222,8 +222,8 @@ impl CacheManager {
             })),
         }
     }
-    /// Songs will be removed from cache if `available_memory < min_avail_mem`.
-    /// New songs will only be cached if `available_memory > max_avail_mem`.
+    /// Songs will be removed from cache if `available_memory < min_avail_mem`, until enough memory is available again.
+    /// New songs will only be cached if they fit into `available_memory - max_avail_mem` (and `max_bytes`).
     /// `min` and `max` in MiB (1024*1024 Bytes)
     pub fn set_memory_mib(&self, min: u64, mut max: u64) {
         if max < min + 16 {
@@ -236,10 +236,401 @@ impl CacheManager {
     }
 
     /// How many songs to load ahead of time. `< 2` behaves like `2`.
-    /// Songs will be cached slowly over time.
-    /// New songs will only be cached if `available_memory > max_avail_mem`.
+    /// Songs will be cached slowly over time, as long as they fit into the budget (see `set_memory_mib` and `set_max_mib`).
     pub fn set_cache_songs_count(&self, count: u32) {
         self.songs_to_cache
             .store(count, std::sync::atomic::Ordering::Relaxed);
     }
+    /// The cached songs' total size won't exceed this, even if there is enough free system memory.
+    /// `max` in MiB (1024*1024 Bytes)
+    pub fn set_max_mib(&self, max: u64) {
+        self.max_bytes.store(
+            max.saturating_mul(1024 * 1024),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+}
+
+/// How many bytes may be used for cached songs, given the current usage and the available system memory:
+/// If less than `min_avail` is available, shrink until it is.
+/// If more than `max_avail` is available, the rest may be used.
+/// Never more than `max_bytes`.
+fn memory_budget(
+    usage: u64,
+    available: u64,
+    min_avail: u64,
+    max_avail: u64,
+    max_bytes: u64,
+) -> u64 {
+    let budget = if available < min_avail {
+        usage.saturating_sub(min_avail - available)
+    } else if available < max_avail {
+        usage
+    } else {
+        usage.saturating_add(available - max_avail)
+    };
+    budget.min(max_bytes)
+}
+
+/// Something the `CacheManager` can load into memory ahead of time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum CacheItem {
+    Song(SongId),
+    /// covers are small and cheap to reload, so they are uncached before songs
+    Cover(CoverId),
+}
+
+/// What the `CachePlanner` needs to know about songs and covers, and how it (un)caches them.
+trait CacheLoader {
+    /// All items which are cached or currently being loaded, their size in bytes, and if they are still loading.
+    /// For items which are still loading, this is the expected size.
+    fn cached(&self) -> Vec<(CacheItem, u64, bool)>;
+    /// The size the item will have once it is cached
+    fn expected_size(&self, item: CacheItem) -> u64;
+    /// `true` if the data was removed
+    fn uncache(&self, item: CacheItem) -> bool;
+    /// see `CachedData::cache_data_start_thread_or_say_already_running`
+    fn start_caching(&self, item: CacheItem) -> Result<bool, bool>;
+}
+
+impl CacheLoader for Database {
+    fn cached(&self) -> Vec<(CacheItem, u64, bool)> {
+        let songs = self.songs().iter().filter_map(|(id, song)| {
+            if let Some(size) = song.cached_data().has_cached_data() {
+                Some((CacheItem::Song(*id), size as u64, false))
+            } else if song.cached_data().is_loading() {
+                Some((CacheItem::Song(*id), song.file_size, true))
+            } else {
+                None
+            }
+        });
+        let covers = self.covers().iter().filter_map(|(id, cover)| {
+            let data = cover.data.lock().unwrap();
+            if let Some((_, bytes)) = &data.1 {
+                Some((CacheItem::Cover(*id), bytes.len() as u64, false))
+            } else if data.0 {
+                drop(data);
+                Some((
+                    CacheItem::Cover(*id),
+                    self.expected_size(CacheItem::Cover(*id)),
+                    true,
+                ))
+            } else {
+                None
+            }
+        });
+        songs.chain(covers).collect()
+    }
+    fn expected_size(&self, item: CacheItem) -> u64 {
+        match item {
+            CacheItem::Song(id) => self.get_song(&id).map(|s| s.file_size).unwrap_or(0),
+            CacheItem::Cover(id) => self
+                .covers()
+                .get(&id)
+                .and_then(|c| std::fs::metadata(self.get_path(&c.location)).ok())
+                .map(|m| m.len())
+                .unwrap_or(0),
+        }
+    }
+    fn uncache(&self, item: CacheItem) -> bool {
+        match item {
+            CacheItem::Song(id) => self
+                .get_song(&id)
+                .is_some_and(|s| s.cached_data().uncache_data() == Ok(true)),
+            CacheItem::Cover(id) => self.covers().get(&id).is_some_and(|c| c.uncache()),
+        }
+    }
+    fn start_caching(&self, item: CacheItem) -> Result<bool, bool> {
+        match item {
+            CacheItem::Song(id) => {
+                if let Some(song) = self.get_song(&id) {
+                    song.cached_data()
+                        .cache_data_start_thread_or_say_already_running(self, song)
+                } else {
+                    Err(false)
+                }
+            }
+            // if the server gets its files from another server, covers aren't cached locally
+            CacheItem::Cover(_) if self.remote_server_as_song_file_source.is_some() => Err(false),
+            CacheItem::Cover(id) => {
+                let path = self
+                    .covers()
+                    .get(&id)
+                    .map(|c| (c, self.get_path(&c.location)));
+                // don't retry every second if the file is missing
+                if let Some((cover, path)) = path.filter(|(_, path)| path.is_file()) {
+                    cover.prefetch(path)
+                } else {
+                    Err(false)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct CachePlanner {
+    /// incremented on every step
+    step: u64,
+    /// the last step in which an item was needed, used to uncache the least recently needed items first
+    last_needed: HashMap<CacheItem, u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CacheChanges {
+    uncached: Vec<CacheItem>,
+    started: Vec<CacheItem>,
+}
+
+impl CachePlanner {
+    /// `wanted` are the items which should be cached, in the order they will be needed (current song first).
+    /// `protected` items are never uncached. `budget` is called with the number of bytes currently used by cached items.
+    /// Uncaches items until the budget isn't exceeded anymore, then starts caching the next wanted items, if they fit.
+    /// Only one song is loaded at a time, covers are loaded while songs are loading.
+    fn step(
+        &mut self,
+        loader: &impl CacheLoader,
+        wanted: &[CacheItem],
+        protected: &[CacheItem],
+        budget: impl FnOnce(u64) -> u64,
+    ) -> CacheChanges {
+        self.step += 1;
+        for item in wanted {
+            self.last_needed.insert(*item, self.step);
+        }
+        let mut changes = CacheChanges::default();
+        let cached = loader.cached();
+        self.last_needed
+            .retain(|item, _| cached.iter().any(|(c, _, _)| c == item));
+        let mut usage = cached.iter().map(|(_, size, _)| size).sum::<u64>();
+        let budget = budget(usage);
+        if usage > budget {
+            // items which are still loading can't be uncached
+            let mut candidates = cached
+                .iter()
+                .filter(|(item, _, loading)| !loading && !protected.contains(item))
+                .collect::<Vec<_>>();
+            candidates.sort_by_key(|(item, _, _)| {
+                let song = matches!(item, CacheItem::Song(_));
+                match wanted.iter().position(|w| w == item) {
+                    // not wanted: least recently needed first
+                    None => (0, song, self.last_needed.get(item).copied().unwrap_or(0)),
+                    // wanted: furthest from the current position first
+                    Some(pos) => (1, song, u64::MAX - pos as u64),
+                }
+            });
+            for (item, size, _) in candidates {
+                if usage <= budget {
+                    break;
+                }
+                if loader.uncache(*item) {
+                    usage -= size;
+                    changes.uncached.push(*item);
+                }
+            }
+        }
+        // only one song is loaded at a time, but covers can be loaded while a song is loading
+        let mut song_loading = false;
+        for item in wanted {
+            let song = matches!(item, CacheItem::Song(_));
+            if song && song_loading {
+                continue;
+            }
+            match cached.iter().find(|(c, _, _)| c == item) {
+                Some((_, _, false)) => continue,
+                Some((_, _, true)) => {
+                    song_loading |= song;
+                    continue;
+                }
+                None => {}
+            }
+            let size = loader.expected_size(*item);
+            if !protected.contains(item) && usage + size > budget {
+                break;
+            }
+            match loader.start_caching(*item) {
+                Ok(true) => {
+                    usage += size;
+                    changes.started.push(*item);
+                    song_loading |= song;
+                }
+                // already cached or loading failed recently
+                Ok(false) | Err(false) => {}
+                Err(true) => song_loading |= song,
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+struct FakeLoader {
+    sizes: HashMap<CacheItem, u64>,
+    cached: std::cell::RefCell<HashMap<CacheItem, bool>>,
+}
+#[cfg(test)]
+impl FakeLoader {
+    fn new(sizes: &[(CacheItem, u64)], cached: &[CacheItem]) -> Self {
+        Self {
+            sizes: sizes.iter().copied().collect(),
+            cached: std::cell::RefCell::new(cached.iter().map(|id| (*id, false)).collect()),
+        }
+    }
+    /// finish loading everything
+    fn finish(&self) {
+        for loading in self.cached.borrow_mut().values_mut() {
+            *loading = false;
+        }
+    }
+}
+#[cfg(test)]
+impl CacheLoader for FakeLoader {
+    fn cached(&self) -> Vec<(CacheItem, u64, bool)> {
+        let mut cached = self
+            .cached
+            .borrow()
+            .iter()
+            .map(|(item, loading)| (*item, self.sizes[item], *loading))
+            .collect::<Vec<_>>();
+        cached.sort();
+        cached
+    }
+    fn expected_size(&self, item: CacheItem) -> u64 {
+        self.sizes[&item]
+    }
+    fn uncache(&self, item: CacheItem) -> bool {
+        self.cached.borrow_mut().remove(&item).is_some()
+    }
+    fn start_caching(&self, item: CacheItem) -> Result<bool, bool> {
+        match self.cached.borrow_mut().entry(item) {
+            std::collections::hash_map::Entry::Occupied(e) => Err(*e.get()),
+            std::collections::hash_map::Entry::Vacant(e) => {
+                e.insert(true);
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cache_eviction_order() {
+    use CacheItem::Song;
+    let loader = FakeLoader::new(
+        &[
+            (Song(1), 100),
+            (Song(2), 100),
+            (Song(3), 100),
+            (Song(4), 100),
+            (Song(5), 100),
+        ],
+        &[Song(1), Song(2), Song(3), Song(4), Song(5)],
+    );
+    let mut planner = CachePlanner::default();
+    // 2 was needed before 5
+    planner.step(&loader, &[Song(2)], &[], |_| u64::MAX);
+    planner.step(&loader, &[Song(5)], &[], |_| u64::MAX);
+    // 3 and 4 are current and next, 1 comes after them.
+    let wanted = [Song(3), Song(4), Song(1)];
+    let changes = planner.step(&loader, &wanted, &wanted[..2], |_| 200);
+    assert_eq!(changes.uncached, vec![Song(2), Song(5), Song(1)]);
+    assert_eq!(changes.started, vec![]);
+    // current and next song stay, even if over budget
+    let changes = planner.step(&loader, &wanted, &wanted[..2], |_| 0);
+    assert_eq!(changes.uncached, vec![]);
+    assert_eq!(loader.cached().len(), 2);
+}
+
+#[test]
+fn test_cache_covers_evicted_first() {
+    use CacheItem::{Cover, Song};
+    let loader = FakeLoader::new(
+        &[
+            (Song(1), 100),
+            (Song(2), 100),
+            (Song(3), 100),
+            (Cover(1), 10),
+            (Cover(3), 10),
+        ],
+        &[Song(1), Song(2), Song(3), Cover(1), Cover(3)],
+    );
+    let mut planner = CachePlanner::default();
+    let wanted = [Song(1), Cover(1), Song(2), Song(3), Cover(3)];
+    let protected = &wanted[..3];
+    // the cover is uncached before the song, even though it comes later
+    let changes = planner.step(&loader, &wanted, protected, |_| 315);
+    assert_eq!(changes.uncached, vec![Cover(3)]);
+    let changes = planner.step(&loader, &wanted, protected, |_| 300);
+    assert_eq!(changes.uncached, vec![Song(3)]);
+    // the current song's cover stays
+    let changes = planner.step(&loader, &wanted, protected, |_| 0);
+    assert_eq!(changes.uncached, vec![]);
+}
+
+#[test]
+fn test_cache_respects_budget() {
+    use CacheItem::{Cover, Song};
+    let loader = FakeLoader::new(
+        &[
+            (Song(1), 100),
+            (Song(2), 300),
+            (Song(3), 100),
+            (Song(4), 100),
+            (Cover(1), 10),
+        ],
+        &[],
+    );
+    let mut planner = CachePlanner::default();
+    let wanted = [Song(1), Cover(1), Song(2), Song(3), Song(4)];
+    let protected = &wanted[..3];
+    // one song at a time, in queue order, but covers don't have to wait for songs
+    let changes = planner.step(&loader, &wanted, protected, |_| 560);
+    assert_eq!(changes.started, vec![Song(1), Cover(1)]);
+    let changes = planner.step(&loader, &wanted, protected, |_| 560);
+    assert_eq!(changes.started, vec![]);
+    loader.finish();
+    let changes = planner.step(&loader, &wanted, protected, |_| 560);
+    assert_eq!(changes.started, vec![Song(2)]);
+    loader.finish();
+    let changes = planner.step(&loader, &wanted, protected, |_| 560);
+    assert_eq!(changes.started, vec![Song(3)]);
+    loader.finish();
+    // 4 doesn't fit
+    let changes = planner.step(&loader, &wanted, protected, |_| 560);
+    assert_eq!(changes.started, vec![]);
+    // the current and next song are always cached
+    let loader = FakeLoader::new(&[(Song(1), 100), (Song(2), 300), (Cover(1), 10)], &[]);
+    let wanted = [Song(1), Cover(1), Song(2)];
+    let changes = planner.step(&loader, &wanted, &wanted, |_| 0);
+    assert_eq!(changes.started, vec![Song(1), Cover(1)]);
+    loader.finish();
+    let changes = planner.step(&loader, &wanted, &wanted, |_| 0);
+    assert_eq!(changes.started, vec![Song(2)]);
+}
+
+#[test]
+fn test_memory_budget() {
+    let mib = 1024 * 1024;
+    // enough memory: use what's available above max
+    assert_eq!(
+        memory_budget(100 * mib, 3000 * mib, 1024 * mib, 2048 * mib, u64::MAX),
+        1052 * mib
+    );
+    assert_eq!(
+        memory_budget(100 * mib, 3000 * mib, 1024 * mib, 2048 * mib, 500 * mib),
+        500 * mib
+    );
+    // between min and max: don't cache more
+    assert_eq!(
+        memory_budget(100 * mib, 1500 * mib, 1024 * mib, 2048 * mib, u64::MAX),
+        100 * mib
+    );
+    // below min: free memory
+    assert_eq!(
+        memory_budget(100 * mib, 1000 * mib, 1024 * mib, 2048 * mib, u64::MAX),
+        76 * mib
+    );
+    assert_eq!(
+        memory_budget(100 * mib, 0, 1024 * mib, 2048 * mib, u64::MAX),
+        0
+    );
 }
@@ -1,29 +1,47 @@
 use rand::prelude::SliceRandom;
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeSet, HashMap, VecDeque},
     fs::{self, File},
     io::{BufReader, Read, Write},
     path::{Path, PathBuf},
-    sync::{mpsc, Arc, Mutex},
+    sync::{atomic::AtomicBool, mpsc, Arc, Mutex},
     time::{Duration, Instant},
 };
 
-use colorize::AnsiColor;
 use rand::thread_rng;
 
 use crate::{
     load::ToFromBytes,
-    server::{Action, Command, Commander, Req},
+    server::{
+        throttle::TransferLimit, thumbnail::ThumbnailCache, Action, Command, Commander,
+        ConnectionCounts, Req,
+    },
 };
 
 use super::{
     album::Album,
     artist::Artist,
-    queue::{Queue, QueueContent, QueueFolder},
+    playlist,
+    queue::{pick_random_song, Queue, QueueContent, QueueFolder, RandomFilter, RandomWeighting},
+    replay_gain::ReplayGainMode,
     song::Song,
     AlbumId, ArtistId, CoverId, DatabaseLocation, SongId,
 };
 
+/// the longest allowed crossfade, in milliseconds
+pub const MAX_CROSSFADE_MILLIS: u32 = 10_000;
+/// if only the play counts changed, `autosave` waits this long before saving them
+const PLAY_COUNTS_SAVE_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// the ReplayGain pre-amp is limited to +/- 15 dB
+fn clamp_preamp(preamp_db: f32) -> f32 {
+    if preamp_db.is_nan() {
+        0.0
+    } else {
+        preamp_db.clamp(-15.0, 15.0)
+    }
+}
+
 pub struct Database {
     pub seq: Commander,
     /// the directory that contains the dbfile, backups, statistics, ...
@@ -43,21 +61,108 @@ pub struct Database {
     /// Some(Some(path)) -> access to path
     pub custom_files: Option<Option<PathBuf>>,
     pub queue: Queue,
+    /// random elements in the queue try not to repeat any of the last this many songs.
+    /// only relevant for the server.
+    pub random_no_repeat: usize,
+    /// the most recently played songs, newest last. at most `random_no_repeat` entries.
+    recently_played: VecDeque<SongId>,
+    /// how often each song was played, used by `RandomWeighting::PreferUnplayed`. saved in the dbfile.
+    play_counts: HashMap<SongId, u64>,
+    /// when `play_counts` were first changed since the database was last saved, see `autosave`.
+    /// not part of `times_data_modified`, so playing songs doesn't invalidate caches which depend on `data_version`.
+    play_counts_modified: Option<Instant>,
     /// if the database receives an update, it will inform all of its clients so they can stay in sync.
     /// this is a list containing all the clients.
     pub update_endpoints: Vec<(u64, UpdateEndpoint)>,
     pub update_endpoints_id: u64,
     /// true if a song is/should be playing
     pub playing: bool,
+    /// true if playback stopped because the end of the queue was reached.
+    /// reset once playback is resumed or the queue's index changes.
+    pub queue_finished: bool,
+    /// if the end of the queue is reached while playing, add a `Random` element instead of stopping.
+    /// saved in the dbfile.
+    pub autoplay_on_empty: bool,
+    /// output volume, 0.0 to 1.0. saved in the dbfile.
+    pub volume: f32,
+    /// only relevant for clients. true once the server sent its volume, which old servers don't do.
+    pub volume_received: bool,
+    /// how long the current and next song overlap, in milliseconds. 0 disables crossfading.
+    /// at most `MAX_CROSSFADE_MILLIS`.
+    pub crossfade_millis: u32,
+    /// if true, only crossfade when a song ends, not when the user skips to the next song.
+    /// only relevant for the server.
+    pub crossfade_natural_only: bool,
+    /// which ReplayGain tags to use. saved in the dbfile.
+    pub replay_gain: ReplayGainMode,
+    /// added to the ReplayGain of each song, in dB. saved in the dbfile.
+    pub replay_gain_preamp_db: f32,
+    /// when to pause, and if the song which is playing at that time should end first. see `Action::SetSleepTimer`.
+    pub sleep_timer: Option<(Instant, bool)>,
+    /// the current song and the position in it (in milliseconds) at the given time.
+    /// set by the server's player or `Relay`, see `playback_position_now`.
+    pub playback_position: Option<(SongId, u64, Instant)>,
     pub command_sender: Option<mpsc::Sender<(Command, Option<u64>)>>,
-    pub remote_server_as_song_file_source:
-        Option<Arc<Mutex<crate::server::get::Client<Box<dyn ClientIo>>>>>,
+    /// see `get::Pool`
+    pub remote_server_as_song_file_source: Option<Arc<crate::server::get::Pool<Box<dyn ClientIo>>>>,
     /// only relevant for clients. true if init is done
     client_is_init: bool,
 
     /// If `Some`, contains the first time and the last time data was modified.
     /// When the DB is saved, this is reset to `None` to represent that nothing was modified.
     pub times_data_modified: Option<(Instant, Instant)>,
+    /// incremented whenever library data is modified, see `data_version()`
+    data_version: u64,
+    /// the `data_version` when the database was last saved or loaded
+    saved_data_version: u64,
+    /// when the database was last saved or loaded
+    last_save: Instant,
+    /// if `Some`, `autosave` saves the database this often, but only if it was modified
+    pub save_every: Option<Duration>,
+    /// when the database was created or loaded, see `uptime()`
+    started: Instant,
+    /// the number of currently open connections of each type
+    pub open_connections: ConnectionCounts,
+    /// while `Some`, broadcasts are collected here instead of being sent immediately, see `batch_broadcasts()`.
+    /// also contains the `update_endpoints_id` at the time of the broadcast, so endpoints added later don't receive it.
+    broadcast_batch: Option<Vec<(Command, Option<u64>, u64)>>,
+    /// `true` while `scan::start_rescan` is scanning the library
+    pub rescan_running: bool,
+    /// `Some` while `scan::set_duration_backfill` is running. Set the flag to `true` to abort it.
+    pub duration_backfill: Option<Arc<AtomicBool>>,
+    /// `Some` while `scan::set_library_verification` is running. Set the flag to `true` to abort it.
+    pub library_verification: Option<Arc<AtomicBool>>,
+    /// if `Some`, song files and covers are sent to get connections at most this fast
+    pub transfer_limit: Option<TransferLimit>,
+    /// scaled down covers which were sent to get connections
+    pub cover_thumbnails: ThumbnailCache,
+    /// if `true`, a server which doesn't play audio still advances the queue, see `server::relay::Relay`
+    pub relay: bool,
+}
+/// `true` if broadcasting `old` is unnecessary when `new` is broadcast right after it
+fn supersedes(new: &Action, old: &Action) -> bool {
+    match (new, old) {
+        // replaces the element at `old_path`, or one of its parents
+        (Action::QueueUpdate(path, _, req), Action::QueueUpdate(old_path, _, old_req)) => {
+            req.is_none() && old_req.is_none() && old_path.starts_with(path)
+        }
+        (Action::SetVolume(_), Action::SetVolume(_)) => true,
+        _ => false,
+    }
+}
+/// see `Database::statistics()`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Statistics {
+    pub songs: usize,
+    pub albums: usize,
+    pub artists: usize,
+    pub covers: usize,
+    pub cached_songs: usize,
+    pub cached_song_bytes: u64,
+    pub cached_covers: usize,
+    pub cached_cover_bytes: u64,
+    /// see `Database::data_version()`
+    pub data_version: u64,
 }
 pub trait ClientIo: Read + Write + Send {}
 impl<T: Read + Write + Send> ClientIo for T {}
@@ -65,6 +170,9 @@ impl<T: Read + Write + Send> ClientIo for T {}
 pub enum UpdateEndpoint {
     Bytes(Box<dyn Write + Sync + Send>),
     CmdChannel(mpsc::Sender<Arc<Command>>),
+    /// like `CmdChannel`, but for async receivers. removed once the receiver is dropped.
+    #[cfg(feature = "tokio")]
+    CmdChannelTokio(tokio::sync::mpsc::UnboundedSender<Arc<Command>>),
     Custom(Box<dyn FnMut(&Command) + Send>),
     CustomArc(Box<dyn FnMut(Arc<Command>) + Send>),
     CustomBytes(Box<dyn FnMut(&[u8]) + Send>),
@@ -77,6 +185,19 @@ impl Database {
     pub fn is_client_init(&self) -> bool {
         self.client_is_init
     }
+    /// only relevant for clients. call this before reading a new `init_connection` (after reconnecting to the server):
+    /// resets everything `init_connection` only sends if it isn't the default, so the old server's state doesn't stick around.
+    pub fn client_reset_for_init(&mut self) {
+        self.client_is_init = false;
+        self.playing = false;
+        self.queue_finished = false;
+        self.autoplay_on_empty = false;
+        self.replay_gain = ReplayGainMode::Off;
+        self.replay_gain_preamp_db = 0.0;
+        self.crossfade_millis = 0;
+        self.sleep_timer = None;
+        self.playback_position = None;
+    }
     pub fn get_path(&self, location: &DatabaseLocation) -> PathBuf {
         Self::get_path_nodb(&self.lib_directory, location)
     }
@@ -84,6 +205,7 @@ impl Database {
         lib_directory.as_ref().join(&location.rel_path)
     }
     fn modified_data(&mut self) {
+        self.data_version = self.data_version.wrapping_add(1);
         let now = Instant::now();
         if let Some((_first, last)) = &mut self.times_data_modified {
             *last = now;
@@ -91,6 +213,76 @@ impl Database {
             self.times_data_modified = Some((now, now));
         }
     }
+    /// changes whenever songs, albums, artists or covers are modified.
+    /// can be used to find out if cached values which depend on library data are outdated.
+    pub fn data_version(&self) -> u64 {
+        self.data_version
+    }
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+    /// `NextSongIfCurrent` for the current song, or `NextSong` if there is no current song.
+    /// use this instead of `NextSong` when the user wants to skip a song.
+    pub fn next_song_action(&self) -> Action {
+        match (
+            self.queue.get_current_path(),
+            self.queue.get_current_song(self),
+        ) {
+            (Some(path), Some(id)) => Action::NextSongIfCurrent(path, *id),
+            _ => Action::NextSong,
+        }
+    }
+    /// the time until the sleep timer expires (zero if it expired, but waits for the current song to end)
+    pub fn sleep_timer_remaining(&self) -> Option<(Duration, bool)> {
+        self.sleep_timer
+            .map(|(at, after_song)| (at.saturating_duration_since(Instant::now()), after_song))
+    }
+    /// the position in the current song, in milliseconds, if it is known (see `playback_position`)
+    pub fn playback_position_now(&self) -> Option<u64> {
+        let (id, pos, at) = self.playback_position?;
+        if self.queue.get_current_song(self) != Some(&id) {
+            return None;
+        }
+        let pos = if self.playing {
+            pos + at.elapsed().as_millis() as u64
+        } else {
+            pos
+        };
+        Some(match self.get_song(&id).map(|song| song.duration_millis) {
+            Some(duration) if duration > 0 => pos.min(duration),
+            _ => pos,
+        })
+    }
+    /// sets the position in the current song, in milliseconds
+    fn set_playback_position(&mut self, pos: u64) {
+        if let Some(id) = self.queue.get_current_song(self).copied() {
+            self.playback_position = Some((id, pos, Instant::now()));
+        }
+    }
+    /// counts the library's songs, albums, artists and covers, and the songs and covers which are currently in memory.
+    pub fn statistics(&self) -> Statistics {
+        let (cached_songs, cached_song_bytes) = self
+            .songs
+            .values()
+            .filter_map(|song| song.cached_data().has_cached_data())
+            .fold((0, 0), |(n, bytes), len| (n + 1, bytes + len as u64));
+        let (cached_covers, cached_cover_bytes) = self
+            .covers
+            .values()
+            .filter_map(|cover| cover.data.lock().unwrap().1.as_ref().map(|d| d.1.len()))
+            .fold((0, 0), |(n, bytes), len| (n + 1, bytes + len as u64));
+        Statistics {
+            songs: self.songs.len(),
+            albums: self.albums.len(),
+            artists: self.artists.len(),
+            covers: self.covers.len(),
+            cached_songs,
+            cached_song_bytes,
+            cached_covers,
+            cached_cover_bytes,
+            data_version: self.data_version,
+        }
+    }
     // NOTE: just use `songs` directly? not sure yet...
     pub fn get_song(&self, song: &SongId) -> Option<&Song> {
         self.songs.get(song)
@@ -199,17 +391,15 @@ impl Database {
                         if let Some(i) = a.songs.iter().position(|s| *s == song.id) {
                             a.songs.remove(i);
                         } else {
-                            eprintln!(
-                                "[{}] Couldn't remove Song {} from previous album, because the album with the ID {} didn't contain that song.",
-                                "WARN".yellow(),
+                            log_warn!(
+                                "Couldn't remove Song {} from previous album, because the album with the ID {} didn't contain that song.",
                                 song.id,
                                 a.id
                             );
                         }
                     } else {
-                        eprintln!(
-                            "[{}] Couldn't remove Song {} from previous album, because no album with the ID {} was found.",
-                            "ERR!".red(),
+                        log_error!(
+                            "Couldn't remove Song {} from previous album, because no album with the ID {} was found.",
                             song.id,
                                         a
                         );
@@ -219,26 +409,25 @@ impl Database {
                         if let Some(i) = a.singles.iter().position(|s| *s == song.id) {
                             a.singles.remove(i);
                         } else {
-                            eprintln!("[{}] Couldn't remove Song {} from Artist {} singles, because that song wasn't found in that artist.", "WARN".yellow(), song.id, prev_song.artist);
+                            log_warn!("Couldn't remove Song {} from Artist {} singles, because that song wasn't found in that artist.", song.id, prev_song.artist);
                         }
                     } else {
-                        eprintln!("[{}] Couldn't remove Song {} from Artist {} singles, because that artist wasn't found.", "ERR!".red(), song.id, prev_song.artist);
+                        log_error!("Couldn't remove Song {} from Artist {} singles, because that artist wasn't found.", song.id, prev_song.artist);
                     }
                 }
                 // add new song to album/artist
                 if let Some(a) = song.album {
                     if let Some(a) = self.albums.get_mut(&a) {
                         if song.artist != a.artist {
-                            eprintln!("[{}] Changing song's artist because it doesn't match the specified album's artist.", "WARN".yellow());
+                            log_warn!("Changing song's artist because it doesn't match the specified album's artist.");
                             song.artist = a.artist;
                         }
                         if !a.songs.contains(&song.id) {
                             a.songs.push(song.id);
                         }
                     } else {
-                        eprintln!(
-                            "[{}] Couldn't add Song {} to new album, because no album with the ID {} was found.",
-                            "ERR!".red(),
+                        log_error!(
+                            "Couldn't add Song {} to new album, because no album with the ID {} was found.",
                             song.id, a
                         );
                     }
@@ -248,7 +437,7 @@ impl Database {
                             a.singles.push(song.id);
                         }
                     } else {
-                        eprintln!("[{}] Couldn't add Song {} to Artist {} singles, because that artist wasn't found.", "ERR!".red(), song.id, song.artist);
+                        log_error!("Couldn't add Song {} to Artist {} singles, because that artist wasn't found.", song.id, song.artist);
                     }
                 }
             }
@@ -256,10 +445,9 @@ impl Database {
             self.songs.insert(song.id, song);
             Ok(prev_song)
         } else {
-            eprintln!(
-                "[{}] Couldn't update Song {}, because no song with that ID exists.",
-                "WARN".yellow(),
-                song.id,
+            log_warn!(
+                "Couldn't update Song {}, because no song with that ID exists.",
+                song.id
             );
             Err(())
         }
@@ -268,10 +456,11 @@ impl Database {
         if let Some(prev_album) = self.albums.remove(&album.id) {
             // some checks
             let new_songs = album.songs.iter().copied().collect::<BTreeSet<_>>();
-            let prev_songs = album.songs.iter().copied().collect::<BTreeSet<_>>();
+            let prev_songs = prev_album.songs.iter().copied().collect::<BTreeSet<_>>();
             // check if we would end up with songs that aren't referenced anywhere, and, if yes, don't do anything.
             if prev_songs.difference(&new_songs).next().is_some() {
-                eprintln!("[{}] Can't update Album {} because some songs that used to be in this album are not included in the new data.", "ERR!".red(), album.id);
+                log_error!("Can't update Album {} because some songs that used to be in this album are not included in the new data.", album.id);
+                self.albums.insert(prev_album.id, prev_album);
                 return Err(());
             }
 
@@ -279,20 +468,18 @@ impl Database {
             if prev_album.artist != album.artist {
                 // remove album from previous artist
                 if let Some(prev_artist) = self.artists.get_mut(&prev_album.artist) {
-                    if let Some(i) = prev_artist.albums.iter().position(|a| *a != prev_album.id) {
+                    if let Some(i) = prev_artist.albums.iter().position(|a| *a == prev_album.id) {
                         prev_artist.albums.remove(i);
                     } else {
-                        eprintln!(
-                            "[{}] Couldn't remove Album {} from Artist {}, because it was not listed as an album in that artist.",
-                            "ERR!".red(),
+                        log_error!(
+                            "Couldn't remove Album {} from Artist {}, because it was not listed as an album in that artist.",
                             prev_album.id,
                             prev_album.artist
                         );
                     }
                 } else {
-                    eprintln!(
-                            "[{}] Couldn't remove Album {} from Artist {}, because no artist with that ID exists.",
-                            "ERR!".red(),
+                    log_error!(
+                            "Couldn't remove Album {} from Artist {}, because no artist with that ID exists.",
                             prev_album.id,
                             prev_album.artist
                         );
@@ -302,17 +489,15 @@ impl Database {
                     if !artist.albums.contains(&album.id) {
                         artist.albums.push(album.id);
                     } else {
-                        eprintln!(
-                            "[{}] Couldn't add Album {} to Artist {}, because the album was already added (this should never happen...).",
-                            "WARN".yellow(),
+                        log_warn!(
+                            "Couldn't add Album {} to Artist {}, because the album was already added (this should never happen...).",
                             album.id,
                             album.artist
                         );
                     }
                 } else {
-                    eprintln!(
-                            "[{}] Couldn't add Album {} to Artist {}, because no artist with that ID exists.",
-                            "ERR!".red(),
+                    log_error!(
+                            "Couldn't add Album {} to Artist {}, because no artist with that ID exists.",
                             album.id,
                             album.artist
                         );
@@ -322,9 +507,8 @@ impl Database {
                     if let Some(song) = self.songs.get_mut(song) {
                         song.artist = album.artist;
                     } else {
-                        eprintln!(
-                            "[{}] Couldn't change Song {} artist to Artist {}, because no song with that ID exists (changing because album artist was changed).",
-                            "ERR!".red(),
+                        log_error!(
+                            "Couldn't change Song {} artist to Artist {}, because no song with that ID exists (changing because album artist was changed).",
                             song,
                             album.artist
                         );
@@ -347,17 +531,15 @@ impl Database {
                                 {
                                     prev_album.songs.remove(i);
                                 } else {
-                                    eprintln!(
-                                        "[{}] Couldn't remove Song {} from its previous album, Album {}, because no song with that ID exists in that album.",
-                                        "WARN".yellow(),
+                                    log_warn!(
+                                        "Couldn't remove Song {} from its previous album, Album {}, because no song with that ID exists in that album.",
                                         song.id,
                                         prev_album.id
                                     );
                                 }
                             } else {
-                                eprintln!(
-                                        "[{}] Couldn't remove Song {} from its previous album, Album {}, because no album with that ID exists.",
-                                        "WARN".yellow(),
+                                log_warn!(
+                                        "Couldn't remove Song {} from its previous album, Album {}, because no album with that ID exists.",
                                         song.id,
                                         prev_album
                                     );
@@ -365,10 +547,9 @@ impl Database {
                         }
                     }
                 } else {
-                    eprintln!(
-                        "[{}] Couldn't remove Song {} from its previous album because no song with that ID exists.",
-                        "ERR!".red(),
-                        *song,
+                    log_error!(
+                        "Couldn't remove Song {} from its previous album because no song with that ID exists.",
+                        *song
                     );
                 }
             }
@@ -377,10 +558,9 @@ impl Database {
             self.modified_data();
             Ok(prev_album)
         } else {
-            eprintln!(
-                "[{}] Couldn't update Album {}, because no album with that ID exists.",
-                "WARN".yellow(),
-                album.id,
+            log_warn!(
+                "Couldn't update Album {}, because no album with that ID exists.",
+                album.id
             );
             Err(())
         }
@@ -392,14 +572,14 @@ impl Database {
             let prev_albums = prev_artist.albums.iter().copied().collect::<BTreeSet<_>>();
             let new_albums = artist.albums.iter().copied().collect::<BTreeSet<_>>();
             if prev_albums.difference(&new_albums).next().is_some() {
-                eprintln!("[{}] Can't update Artist {} because some albums that used to be in this artist are not included in the new data.", "ERR!".red(), artist.id);
+                log_error!("Can't update Artist {} because some albums that used to be in this artist are not included in the new data.", artist.id);
                 return Err(());
             }
 
             let prev_singles = prev_artist.singles.iter().copied().collect::<BTreeSet<_>>();
             let new_singles = artist.singles.iter().copied().collect::<BTreeSet<_>>();
             if prev_singles.difference(&new_singles).next().is_some() {
-                eprintln!("[{}] Can't update Artist {} because some singles that used to be in this artist are not included in the new data.", "ERR!".red(), artist.id);
+                log_error!("Can't update Artist {} because some singles that used to be in this artist are not included in the new data.", artist.id);
                 return Err(());
             }
 
@@ -410,21 +590,25 @@ impl Database {
                         if let Some(i) = a.albums.iter().position(|a| *a == album.id) {
                             a.albums.remove(i);
                         } else {
-                            eprintln!("[{}] Couldn't remove Album {} from Artist {} because that artist doesn't contain that album.", "ERR!".red(), album.id, album.artist);
+                            log_error!("Couldn't remove Album {} from Artist {} because that artist doesn't contain that album.", album.id, album.artist);
                         }
                     } else {
-                        eprintln!("[{}] Couldn't remove Album {} from Artist {} because that artist doesn't exist.", "ERR!".red(), album.id, album.artist);
+                        log_error!("Couldn't remove Album {} from Artist {} because that artist doesn't exist.", album.id, album.artist);
                     }
                     album.artist = artist.id;
                     for song in &album.songs {
                         if let Some(song) = self.songs.get_mut(song) {
                             song.artist = artist.id;
                         } else {
-                            eprintln!("[{}] Couldn't change Song {} artist to Artist {} because no song with that ID exists (should change because song is newly added to Album {}).", "ERR!".red(), song, artist.id, album.id);
+                            log_error!("Couldn't change Song {} artist to Artist {} because no song with that ID exists (should change because song is newly added to Album {}).", song, artist.id, album.id);
                         }
                     }
                 } else {
-                    eprintln!("[{}] Couldn't move Album {} to Artist {} because no album with that ID exists.", "ERR!".red(), album, artist.id);
+                    log_error!(
+                        "Couldn't move Album {} to Artist {} because no album with that ID exists.",
+                        album,
+                        artist.id
+                    );
                 }
             }
 
@@ -437,25 +621,25 @@ impl Database {
                             if let Some(i) = a.songs.iter().position(|s| *s == song.id) {
                                 a.songs.remove(i);
                             } else {
-                                eprintln!("[{}] Couldn't remove Song {} from Album {} because the album doesn't contain that song.", "ERR!".red(), song.id, a.id);
+                                log_error!("Couldn't remove Song {} from Album {} because the album doesn't contain that song.", song.id, a.id);
                             }
                         } else {
-                            eprintln!("[{}] Couldn't remove Song {} from Album {} because no album with that ID exists.", "ERR!".red(), song.id, a);
+                            log_error!("Couldn't remove Song {} from Album {} because no album with that ID exists.", song.id, a);
                         }
                     } else {
                         if let Some(a) = self.artists.get_mut(&song.artist) {
                             if let Some(i) = a.singles.iter().position(|s| *s == song.id) {
                                 a.singles.remove(i);
                             } else {
-                                eprintln!("[{}] Couldn't remove Song {} from Artist {} because the artist doesn't contain that song.", "ERR!".red(), song.id, a.id);
+                                log_error!("Couldn't remove Song {} from Artist {} because the artist doesn't contain that song.", song.id, a.id);
                             }
                         } else {
-                            eprintln!("[{}] Couldn't remove Song {} from Artist {} because no artist with that ID exists.", "ERR!".red(), song.id, song.artist);
+                            log_error!("Couldn't remove Song {} from Artist {} because no artist with that ID exists.", song.id, song.artist);
                         }
                     }
                     song.artist = artist.id;
                 } else {
-                    eprintln!("[{}] Couldn't move Song {} to Artist {} singles because no song with that ID exists.", "ERR!".red(), song, artist.id);
+                    log_error!("Couldn't move Song {} to Artist {} singles because no song with that ID exists.", song, artist.id);
                 }
             }
 
@@ -473,46 +657,106 @@ impl Database {
         self.songs.insert(song.id, song)
     }
 
+    /// removes the song and its reference in its album (or in its artist's singles)
     pub fn remove_song(&mut self, song: SongId) -> Option<Song> {
-        if let Some(removed) = self.songs.remove(&song) {
-            self.modified_data();
-            Some(removed)
-        } else {
-            None
+        let removed = self.songs.remove(&song)?;
+        if let Some(album) = removed.album.and_then(|id| self.albums.get_mut(&id)) {
+            album.songs.retain(|id| *id != song);
+        } else if let Some(artist) = self.artists.get_mut(&removed.artist) {
+            artist.singles.retain(|id| *id != song);
         }
+        self.modified_data();
+        Some(removed)
     }
-    pub fn remove_album(&mut self, song: SongId) -> Option<Song> {
-        if let Some(removed) = self.songs.remove(&song) {
-            self.modified_data();
-            Some(removed)
-        } else {
-            None
+    /// removes the album, its songs and its reference in its artist
+    pub fn remove_album(&mut self, album: AlbumId) -> Option<Album> {
+        let removed = self.albums.remove(&album)?;
+        for song in &removed.songs {
+            self.songs.remove(song);
+        }
+        if let Some(artist) = self.artists.get_mut(&removed.artist) {
+            artist.albums.retain(|id| *id != album);
         }
+        self.modified_data();
+        Some(removed)
     }
-    pub fn remove_artist(&mut self, song: SongId) -> Option<Song> {
-        if let Some(removed) = self.songs.remove(&song) {
-            self.modified_data();
-            Some(removed)
-        } else {
-            None
+    /// removes the artist and all of its albums and songs
+    pub fn remove_artist(&mut self, artist: ArtistId) -> Option<Artist> {
+        let removed = self.artists.remove(&artist)?;
+        for album in &removed.albums {
+            if let Some(album) = self.albums.remove(album) {
+                for song in &album.songs {
+                    self.songs.remove(song);
+                }
+            }
         }
+        for song in &removed.singles {
+            self.songs.remove(song);
+        }
+        self.modified_data();
+        Some(removed)
     }
 
     pub fn init_connection<T: Write>(&self, con: &mut T) -> Result<(), std::io::Error> {
-        // TODO! this is slow because it clones everything - there has to be a better way...
-        self.seq
-            .pack(Action::SyncDatabase(
-                self.artists().iter().map(|v| v.1.clone()).collect(),
-                self.albums().iter().map(|v| v.1.clone()).collect(),
-                self.songs().iter().map(|v| v.1.clone()).collect(),
-            ))
-            .to_bytes(con)?;
+        self.init_connection_opt(con, false)
+    }
+    /// like `init_connection`, but if `compression` is true, the (potentially very large) `SyncDatabase` is compressed.
+    /// only use compression if the client sent `compression::OPTION_DEFLATE`.
+    pub fn init_connection_opt<T: Write>(
+        &self,
+        con: &mut T,
+        compression: bool,
+    ) -> Result<(), std::io::Error> {
+        // the `Command`'s seq, then the action
+        con.write_all(&[self.seq.seq()])?;
+        let sync_database = |s: &mut dyn Write| {
+            Action::sync_database_to_bytes(
+                self.artists.values(),
+                self.albums.values(),
+                self.songs.values(),
+                &mut &mut *s,
+            )
+        };
+        if compression {
+            Action::compressed_to_bytes(con, |s| sync_database(s))?;
+        } else {
+            sync_database(con)?;
+        }
         self.seq
             .pack(Action::QueueUpdate(vec![], self.queue.clone(), Req::none()))
             .to_bytes(con)?;
         if self.playing {
             self.seq.pack(Action::Resume).to_bytes(con)?;
         }
+        if self.queue_finished {
+            self.seq.pack(Action::QueueFinished).to_bytes(con)?;
+        }
+        if self.autoplay_on_empty {
+            self.seq
+                .pack(Action::SetAutoplayOnEmpty(true))
+                .to_bytes(con)?;
+        }
+        self.seq
+            .pack(Action::SetVolume(self.volume))
+            .to_bytes(con)?;
+        if self.replay_gain != ReplayGainMode::Off || self.replay_gain_preamp_db != 0.0 {
+            self.seq
+                .pack(Action::SetReplayGain(
+                    self.replay_gain,
+                    self.replay_gain_preamp_db,
+                ))
+                .to_bytes(con)?;
+        }
+        if self.crossfade_millis != 0 {
+            self.seq
+                .pack(Action::SetCrossfade(self.crossfade_millis))
+                .to_bytes(con)?;
+        }
+        if let Some((remaining, after_song)) = self.sleep_timer_remaining() {
+            self.seq
+                .pack(Action::SetSleepTimer(Some(remaining.as_secs()), after_song))
+                .to_bytes(con)?;
+        }
         // this allows clients to find out when init_connection is done.
         self.seq.pack(Action::InitComplete).to_bytes(con)?;
         // is initialized now - client can receive updates after this point.
@@ -525,31 +769,11 @@ impl Database {
     pub fn apply_command(&mut self, mut command: Command, client: Option<u64>) {
         if command.seq != self.seq.seq() && command.seq != 0xFF {
             if let Some(client) = client {
-                for (udepid, udep) in &mut self.update_endpoints {
-                    if client == *udepid {
-                        let mut reqs = command.action.get_req_if_some();
-                        if reqs.is_empty() {
-                            reqs.push(Req::none());
-                        }
-                        for req in reqs {
-                            let denied = Action::Denied(req).cmd(0xFFu8);
-                            match udep {
-                                UpdateEndpoint::Bytes(w) => {
-                                    let _ = w.write(&denied.to_bytes_vec());
-                                }
-                                UpdateEndpoint::CmdChannel(w) => {
-                                    let _ = w.send(Arc::new(denied));
-                                }
-                                UpdateEndpoint::Custom(w) => w(&denied),
-                                UpdateEndpoint::CustomArc(w) => w(Arc::new(denied)),
-                                UpdateEndpoint::CustomBytes(w) => w(&denied.to_bytes_vec()),
-                            }
-                        }
-                        return;
-                    }
+                if self.deny(&mut command.action, client) {
+                    return;
                 }
             }
-            eprintln!(
+            log_warn!(
                 "Invalid sequence number: got {} but expected {}.",
                 command.seq,
                 self.seq.seq()
@@ -558,7 +782,92 @@ impl Database {
         }
         self.apply_action_unchecked_seq(command.action, client)
     }
-    pub fn apply_action_unchecked_seq(&mut self, mut action: Action, client: Option<u64>) {
+    /// sends `Denied` for each of the action's requests (or `Denied(Req::none())`) to the client, and nothing to anyone else.
+    /// returns `false` if the client doesn't exist (anymore).
+    pub fn deny(&mut self, action: &mut Action, client: u64) -> bool {
+        let mut reqs = action.get_req_if_some();
+        if reqs.is_empty() {
+            reqs.push(Req::none());
+        }
+        let mut sent = false;
+        for req in reqs {
+            sent |= self.send_to_client(client, Action::Denied(req).cmd(0xFFu8));
+        }
+        sent
+    }
+    /// sends the command to the client, and nothing to anyone else.
+    /// returns `false` if the client doesn't exist (anymore).
+    pub fn send_to_client(&mut self, client: u64, command: Command) -> bool {
+        for (udepid, udep) in &mut self.update_endpoints {
+            if client == *udepid {
+                match udep {
+                    UpdateEndpoint::Bytes(w) => {
+                        let _ = w
+                            .write_all(&command.to_bytes_vec())
+                            .and_then(|()| w.flush());
+                    }
+                    UpdateEndpoint::CmdChannel(w) => {
+                        let _ = w.send(Arc::new(command));
+                    }
+                    #[cfg(feature = "tokio")]
+                    UpdateEndpoint::CmdChannelTokio(w) => {
+                        let _ = w.send(Arc::new(command));
+                    }
+                    UpdateEndpoint::Custom(w) => w(&command),
+                    UpdateEndpoint::CustomArc(w) => w(Arc::new(command)),
+                    UpdateEndpoint::CustomBytes(w) => w(&command.to_bytes_vec()),
+                }
+                return true;
+            }
+        }
+        false
+    }
+    /// tells all clients the result of a `Playlist*` action.
+    /// errors have a title, so clients can show them as errors.
+    fn broadcast_playlist_result(&mut self, result: Result<String, String>, client: Option<u64>) {
+        // broadcast directly, `apply_action_unchecked_seq` would remove the title
+        self.broadcast_update(
+            match result {
+                Ok(message) => Action::ErrorInfo(String::new(), message),
+                Err(message) => Action::ErrorInfo("Playlist".to_owned(), message),
+            },
+            client,
+        );
+    }
+    /// Runs `f`, which may add, remove or reorder the songs of albums, and then moves the index of every `AlbumRef`
+    /// in the queue to the song it was at before, so that the current song doesn't change.
+    /// If that song is no longer in the album, the index stays the same and points at the song after it.
+    fn keep_album_ref_songs(&mut self, f: impl FnOnce(&mut Self)) {
+        let mut songs = vec![];
+        let albums = &self.albums;
+        self.queue.for_each_album_ref_mut(&mut |album, index| {
+            songs.push(
+                albums
+                    .get(&album)
+                    .and_then(|a| a.songs.get(*index).copied()),
+            )
+        });
+        f(self);
+        let mut songs = songs.into_iter();
+        let albums = &self.albums;
+        self.queue.for_each_album_ref_mut(&mut |album, index| {
+            if let (Some(Some(song)), Some(album)) = (songs.next(), albums.get(&album)) {
+                if let Some(i) = album.songs.iter().position(|id| *id == song) {
+                    *index = i;
+                }
+            }
+        });
+    }
+    pub fn apply_action_unchecked_seq(&mut self, action: Action, client: Option<u64>) {
+        let changes_queue = action.changes_queue();
+        self.apply_action_unchecked_seq_inner(action, client);
+        if changes_queue {
+            self.fill_random_elements();
+        }
+    }
+    /// like `apply_action_unchecked_seq`, but doesn't refill `Random` elements.
+    /// used for actions which are part of another one, which refills them once it is done.
+    fn apply_action_unchecked_seq_inner(&mut self, mut action: Action, client: Option<u64>) {
         if !self.is_client() {
             if let Action::ErrorInfo(t, _) = &mut action {
                 // clients can send ErrorInfo to the server and it will show up on other clients,
@@ -570,31 +879,163 @@ impl Database {
         match &action {
             // Will broadcast `QueueSetShuffle`
             Action::QueueShuffle(_) => (),
-            Action::NextSong if self.queue.is_almost_empty() => (),
+            // Will broadcast `QueueUpdate`
+            Action::QueueShuffleInPlace(_) => (),
+            // Will broadcast `QueueAdd`
+            Action::QueueDuplicate(_) => (),
+            // Will broadcast `QueueRemove`s
+            Action::QueueDedupSongs(_) => (),
+            // Will broadcast an `ErrorInfo` with the result (and `PlaylistLoad` a `QueueAdd` or `QueueUpdate`)
+            Action::PlaylistSave(..)
+            | Action::PlaylistLoad(..)
+            | Action::PlaylistDelete(_)
+            | Action::PlaylistRename(..) => (),
+            Action::NextSong if self.queue.is_almost_empty(self) => (),
+            // will broadcast `NextSong` if the song is still the current one
+            Action::NextSongIfCurrent(..) => (),
+            Action::QueueGoto(path) if !self.queue.is_enabled_at(path) => (),
             Action::Pause if !self.playing => (),
             Action::Resume if self.playing => (),
             // will be broadcast individually
             Action::Multiple(_) => (),
+            // only sent to each client directly, see `ping_clients`
+            Action::Ping => (),
+            // handled by the server, which reports progress using `ErrorInfo`s
+            Action::RescanLibrary => (),
+            // handled by the server, which broadcasts `SetSongDuration`s
+            Action::SetDurationBackfill(_) => (),
+            // handled by the server, which broadcasts the tag changes and reports the result using `ErrorInfo`s
+            Action::VerifyLibrary(_) => (),
+            // handled by the server's `Relay`
+            Action::PlaybackPosition(..) => (),
             // since db.update_endpoints is empty for clients, this won't cause unwanted back and forth
             _ => action = self.broadcast_update(action, client),
         }
         match action {
-            Action::Resume => self.playing = true,
-            Action::Pause => self.playing = false,
-            Action::Stop => self.playing = false,
+            Action::Resume => {
+                if !self.playing {
+                    // the position didn't advance while paused
+                    if let Some((_, _, at)) = &mut self.playback_position {
+                        *at = Instant::now();
+                    }
+                }
+                self.playing = true;
+                self.queue_finished = false;
+            }
+            Action::Pause | Action::Stop => {
+                if let Some(pos) = self.playback_position_now() {
+                    self.set_playback_position(pos);
+                }
+                self.playing = false;
+            }
+            // handled by the player, but the position is updated so clients can show it
+            Action::Seek(pos) => self.set_playback_position(pos),
+            Action::SeekBy(by) => {
+                if let Some(pos) = self.playback_position_now() {
+                    self.set_playback_position(pos.saturating_add_signed(by));
+                }
+            }
+            Action::NextSongIfCurrent(path, id) => {
+                if self.queue.get_current_path().as_ref() == Some(&path)
+                    && self.queue.get_current_song(self) == Some(&id)
+                {
+                    self.apply_action_unchecked_seq_inner(Action::NextSong, client);
+                }
+            }
             Action::NextSong => {
-                if !Queue::advance_index_db(self) {
+                let finished_song = self.queue.get_current_song(self).copied();
+                if self.command_sender.is_some() {
+                    if let Some(id) = finished_song {
+                        *self.play_counts.entry(id).or_default() += 1;
+                        self.play_counts_modified.get_or_insert_with(Instant::now);
+                        if self.random_no_repeat > 0 {
+                            self.recently_played.push_back(id);
+                            while self.recently_played.len() > self.random_no_repeat {
+                                self.recently_played.pop_front();
+                            }
+                        }
+                    }
+                }
+                if Queue::advance_index_db(self) {
+                    self.queue_finished = false;
+                    if self.command_sender.is_some() {
+                        // shuffle every pass of a loop differently
+                        for (path, ord) in self
+                            .queue
+                            .reshuffle_restarted_loops(self, &mut thread_rng())
+                        {
+                            self.apply_action_unchecked_seq_inner(
+                                Action::QueueSetShuffle(path, ord),
+                                client,
+                            );
+                        }
+                    }
+                } else if !self.autoplay(finished_song, client) {
                     // end of queue
-                    self.apply_action_unchecked_seq(Action::Pause, client);
-                    self.queue.init();
+                    self.apply_action_unchecked_seq_inner(Action::QueueFinished, client);
+                }
+                // a sleep timer which waits for the song to end expires now (the server handles other sleep timers)
+                if self.command_sender.is_some()
+                    && self
+                        .sleep_timer
+                        .is_some_and(|(at, after_song)| after_song && at <= Instant::now())
+                {
+                    self.apply_action_unchecked_seq_inner(
+                        Action::SetSleepTimer(None, false),
+                        client,
+                    );
+                    self.apply_action_unchecked_seq_inner(Action::Pause, client);
+                }
+            }
+            Action::SetVolume(volume) => {
+                self.volume_received = true;
+                let volume = if volume.is_nan() {
+                    1.0
+                } else {
+                    volume.clamp(0.0, 1.0)
+                };
+                if self.volume != volume {
+                    self.volume = volume;
+                    // so the setting is saved
+                    self.modified_data();
+                }
+            }
+            Action::SetReplayGain(mode, preamp_db) => {
+                let preamp_db = clamp_preamp(preamp_db);
+                if self.replay_gain != mode || self.replay_gain_preamp_db != preamp_db {
+                    self.replay_gain = mode;
+                    self.replay_gain_preamp_db = preamp_db;
+                    // so the setting is saved
+                    self.modified_data();
                 }
             }
+            Action::SetCrossfade(millis) => {
+                self.crossfade_millis = millis.min(MAX_CROSSFADE_MILLIS);
+            }
+            Action::SetSleepTimer(secs, after_song) => {
+                self.sleep_timer =
+                    secs.map(|secs| (Instant::now() + Duration::from_secs(secs), after_song));
+            }
+            Action::SetAutoplayOnEmpty(autoplay) => {
+                if self.autoplay_on_empty != autoplay {
+                    self.autoplay_on_empty = autoplay;
+                    // so the setting is saved
+                    self.modified_data();
+                }
+            }
+            Action::Ping
+            | Action::RescanLibrary
+            | Action::SetDurationBackfill(_)
+            | Action::VerifyLibrary(_) => {}
+            Action::PlaybackPosition(id, pos) => {
+                self.playback_position = Some((id, pos, Instant::now()));
+            }
             Action::Save => {
                 if let Err(e) = self.save_database(None) {
-                    eprintln!("[{}] Couldn't save: {e}", "ERR!".red());
+                    log_error!("Couldn't save: {e}");
                 }
             }
-            Action::SyncDatabase(a, b, c) => self.sync(a, b, c),
+            Action::SyncDatabase(a, b, c) => self.keep_album_ref_songs(|db| db.sync(a, b, c)),
             Action::QueueUpdate(index, new_data, _) => {
                 if let Some(v) = self.queue.get_item_at_index_mut(&index, 0) {
                     *v = new_data;
@@ -610,6 +1051,18 @@ impl Database {
                     v.insert(new_data, pos, false);
                 }
             }
+            Action::QueueSetLabel(path, label) => {
+                if let Some(elem) = self.queue.get_item_at_index_mut(&path, 0) {
+                    elem.set_label(label);
+                } else {
+                    log_warn!("(QueueSetLabel) No QueueElement at {path:?}");
+                }
+            }
+            Action::QueuePlayNext(elem) => {
+                if !self.queue.insert_after_current(elem) {
+                    log_warn!("can't QueuePlayNext");
+                }
+            }
             Action::QueueRemove(index) => {
                 self.queue.remove_by_index(&index, 0);
             }
@@ -678,7 +1131,19 @@ impl Database {
                     }
                 }
             }
-            Action::QueueGoto(index) => Queue::set_index_db(self, &index),
+            Action::QueueGoto(index) => {
+                if self.queue.is_enabled_at(&index) {
+                    self.queue_finished = false;
+                    Queue::set_index_db(self, &index);
+                } else {
+                    log_warn!("can't QueueGoto - element at {index:?} is disabled");
+                }
+            }
+            Action::QueueFinished => {
+                self.playing = false;
+                self.queue_finished = true;
+                self.queue.init();
+            }
             Action::QueueShuffle(path) => {
                 if let Some(elem) = self.queue.get_item_at_index_mut(&path, 0) {
                     if let QueueContent::Folder(QueueFolder {
@@ -690,12 +1155,15 @@ impl Database {
                     {
                         let mut ord: Vec<usize> = (0..content.len()).collect();
                         ord.shuffle(&mut thread_rng());
-                        self.apply_action_unchecked_seq(Action::QueueSetShuffle(path, ord), client);
+                        self.apply_action_unchecked_seq_inner(
+                            Action::QueueSetShuffle(path, ord),
+                            client,
+                        );
                     } else {
-                        eprintln!("(QueueShuffle) QueueElement at {path:?} not a folder!");
+                        log_warn!("(QueueShuffle) QueueElement at {path:?} not a folder!");
                     }
                 } else {
-                    eprintln!("(QueueShuffle) No QueueElement at {path:?}");
+                    log_warn!("(QueueShuffle) No QueueElement at {path:?}");
                 }
             }
             Action::QueueSetShuffle(path, ord) => {
@@ -708,27 +1176,28 @@ impl Database {
                     }) = elem.content_mut()
                     {
                         if ord.len() == content.len() {
-                            if let Some(ni) = ord.iter().position(|v| *v == *index) {
+                            // keep the current element, even if the folder was already shuffled
+                            let current = order
+                                .as_ref()
+                                .map_or(Some(*index), |order| order.get(*index).copied());
+                            if let Some(ni) = ord.iter().position(|v| Some(*v) == current) {
                                 *index = ni;
                             }
                             *order = Some(ord);
                         } else {
-                            eprintln!(
-                                "[warn] can't QueueSetShuffle - length of new ord ({}) is not the same as length of content ({})!",
+                            log_warn!(
+                                "can't QueueSetShuffle - length of new ord ({}) is not the same as length of content ({})!",
                                 ord.len(),
                                 content.len()
                             );
                         }
                     } else {
-                        eprintln!(
-                            "[warn] can't QueueSetShuffle - element at path {path:?} isn't a folder"
+                        log_warn!(
+                            "can't QueueSetShuffle - element at path {path:?} isn't a folder"
                         );
                     }
                 } else {
-                    eprintln!(
-                        "[{}] can't QueueSetShuffle - no element at path {path:?}",
-                        "WARN".yellow()
-                    );
+                    log_warn!("can't QueueSetShuffle - no element at path {path:?}");
                 }
             }
             Action::QueueUnshuffle(path) => {
@@ -747,8 +1216,109 @@ impl Database {
                     }
                 }
             }
+            Action::QueueClear(path) => {
+                if let Some(QueueContent::Folder(folder)) = self
+                    .queue
+                    .get_item_at_index_mut(&path, 0)
+                    .map(|elem| elem.content_mut())
+                {
+                    folder.clear();
+                } else {
+                    log_warn!("can't QueueClear - no folder at path {path:?}");
+                }
+            }
+            Action::QueueShuffleInPlace(path) => {
+                if let Some(elem) = self.queue.get_item_at_index_mut(&path, 0) {
+                    if let QueueContent::Folder(folder) = elem.content_mut() {
+                        let mut folder = folder.clone();
+                        folder.shuffle_in_place(&mut thread_rng());
+                        let mut elem = elem.clone();
+                        *elem.content_mut() = QueueContent::Folder(folder);
+                        self.apply_action_unchecked_seq_inner(
+                            Action::QueueUpdate(path, elem, Req::none()),
+                            client,
+                        );
+                    } else {
+                        log_warn!("(QueueShuffleInPlace) QueueElement at {path:?} not a folder!");
+                    }
+                } else {
+                    log_warn!("(QueueShuffleInPlace) No QueueElement at {path:?}");
+                }
+            }
+            Action::QueueDuplicate(path) => {
+                if path.is_empty() {
+                    log_warn!("can't QueueDuplicate - can't duplicate the queue root");
+                } else if let Some(elem) = self.queue.get_item_at_index(&path, 0) {
+                    let mut elem = elem.clone();
+                    elem.reset_index();
+                    elem.reshuffle(&mut thread_rng());
+                    // loops and `Random`s can't hold a copy next to the original,
+                    // so it is added to the end of the nearest folder around it.
+                    let folder = (0..path.len()).rev().map(|len| &path[..len]).find(|p| {
+                        matches!(
+                            self.queue.get_item_at_index(p, 0).map(|v| v.content()),
+                            Some(QueueContent::Folder(_))
+                        )
+                    });
+                    if let Some(folder) = folder {
+                        self.apply_action_unchecked_seq_inner(
+                            Action::QueueAdd(folder.to_vec(), vec![elem], Req::none()),
+                            client,
+                        );
+                    } else {
+                        log_warn!("can't QueueDuplicate - there is no folder around {path:?}");
+                    }
+                } else {
+                    log_warn!("(QueueDuplicate) No QueueElement at {path:?}");
+                }
+            }
+            Action::QueueLoopBreak(path) => {
+                if !self
+                    .queue
+                    .get_item_at_index_mut(&path, 0)
+                    .is_some_and(|elem| elem.content_mut().loop_break())
+                {
+                    log_warn!("can't QueueLoopBreak - no loop at path {path:?}");
+                }
+            }
+            Action::QueueDedupSongs(path) => {
+                if let Some(elem) = self.queue.get_item_at_index(&path, 0) {
+                    let removals = elem
+                        .duplicate_song_paths(self)
+                        .into_iter()
+                        .map(|p| Action::QueueRemove(path.iter().copied().chain(p).collect()))
+                        .collect::<Vec<_>>();
+                    let count = removals.len();
+                    self.apply_action_unchecked_seq_inner(Action::Multiple(removals), client);
+                    // broadcast directly, `apply_action_unchecked_seq` would remove the title
+                    self.broadcast_update(
+                        Action::ErrorInfo(
+                            "Removed duplicates".to_owned(),
+                            format!(
+                                "removed {count} duplicate song{}",
+                                if count == 1 { "" } else { "s" }
+                            ),
+                        ),
+                        client,
+                    );
+                } else {
+                    log_warn!("(QueueDedupSongs) No QueueElement at {path:?}");
+                }
+            }
+            action @ (Action::PlaylistSave(..)
+            | Action::PlaylistLoad(..)
+            | Action::PlaylistDelete(_)
+            | Action::PlaylistRename(..)) => match playlist::apply_action(self, &action) {
+                Ok((message, queue_action)) => {
+                    if let Some(queue_action) = queue_action {
+                        self.apply_action_unchecked_seq_inner(queue_action, client);
+                    }
+                    self.broadcast_playlist_result(Ok(message), client);
+                }
+                Err(e) => self.broadcast_playlist_result(Err(e.to_string()), client),
+            },
             Action::AddSong(song, _) => {
-                self.add_song_new(song);
+                self.keep_album_ref_songs(|db| _ = db.add_song_new(song));
             }
             Action::AddAlbum(album, _) => {
                 self.add_album_new(album);
@@ -758,16 +1328,16 @@ impl Database {
             }
             Action::AddCover(cover, _) => _ = self.add_cover_new(cover),
             Action::ModifySong(song, _) => {
-                _ = self.update_song(song);
+                self.keep_album_ref_songs(|db| _ = db.update_song(song));
             }
             Action::ModifyAlbum(album, _) => {
-                _ = self.update_album(album);
+                self.keep_album_ref_songs(|db| _ = db.update_album(album));
             }
             Action::ModifyArtist(artist, _) => {
                 _ = self.update_artist(artist);
             }
             Action::RemoveSong(song) => {
-                _ = self.remove_song(song);
+                self.keep_album_ref_songs(|db| _ = db.remove_song(song));
             }
             Action::RemoveAlbum(album) => {
                 _ = self.remove_album(album);
@@ -872,7 +1442,7 @@ impl Database {
             }
             Action::Multiple(actions) => {
                 for action in actions {
-                    self.apply_action_unchecked_seq(action, client);
+                    self.apply_action_unchecked_seq_inner(action, client);
                 }
             }
             Action::InitComplete => {
@@ -882,6 +1452,115 @@ impl Database {
             Action::Denied(..) => {}
         }
     }
+    /// Called on the server when the end of the queue was reached.
+    /// If `autoplay_on_empty` is enabled and we are playing (the user didn't press stop),
+    /// adds a `Random` element with songs by the artist of the song which just finished to the queue and goes to it.
+    /// Returns false if playback should stop instead.
+    fn autoplay(&mut self, finished_song: Option<SongId>, client: Option<u64>) -> bool {
+        if !(self.autoplay_on_empty && self.playing && self.command_sender.is_some())
+            || self.songs.is_empty()
+        {
+            return false;
+        }
+        let index = if let QueueContent::Folder(folder) = self.queue.content() {
+            folder.content.len()
+        } else {
+            return false;
+        };
+        let filter = finished_song
+            .and_then(|id| self.get_song(&id))
+            .map(|song| RandomFilter::Artist(song.artist))
+            .filter(|filter| self.songs.values().any(|song| filter.matches(song)))
+            .unwrap_or(RandomFilter::All);
+        // not `_inner`, so that the new element gets its songs before we go to it
+        self.apply_action_unchecked_seq(
+            Action::QueueAdd(
+                vec![],
+                vec![
+                    QueueContent::Random(filter, RandomWeighting::Uniform, VecDeque::new()).into(),
+                ],
+                Req::none(),
+            ),
+            client,
+        );
+        self.apply_action_unchecked_seq_inner(Action::QueueGoto(vec![index]), client);
+        true
+    }
+    /// Makes sure that every `Random` element in the queue contains at least two songs (the current and the next one).
+    /// Only does something on the server (if `command_sender` is set), because the songs are chosen randomly and then broadcast to clients.
+    fn fill_random_elements(&mut self) {
+        if self.command_sender.is_none() {
+            return;
+        }
+        fn find(
+            queue: &Queue,
+            path: &mut Vec<usize>,
+            out: &mut Vec<(Vec<usize>, usize, RandomFilter, RandomWeighting)>,
+        ) {
+            match queue.content() {
+                QueueContent::Song(_) | QueueContent::AlbumRef(..) => {}
+                QueueContent::Folder(folder) => {
+                    for (i, v) in folder.iter().enumerate() {
+                        path.push(i);
+                        find(v, path, out);
+                        path.pop();
+                    }
+                }
+                QueueContent::Loop(_, _, inner) => {
+                    path.push(0);
+                    find(inner, path, out);
+                    path.pop();
+                }
+                QueueContent::Random(filter, weighting, q) => {
+                    if q.len() < 2 {
+                        out.push((path.clone(), 2 - q.len(), filter.clone(), *weighting));
+                    }
+                }
+            }
+        }
+        let mut to_fill = vec![];
+        find(&self.queue, &mut vec![], &mut to_fill);
+        if to_fill.is_empty() {
+            return;
+        }
+        let recently_played = self.recently_played.iter().copied().collect::<Vec<_>>();
+        let mut rng = thread_rng();
+        for (path, count, filter, weighting) in to_fill {
+            // if no songs match the filter, nothing is added and the element will be skipped
+            let songs = self
+                .songs
+                .values()
+                .filter(|song| filter.matches(song))
+                .map(|song| song.id)
+                .collect::<Vec<_>>();
+            let mut recent = recently_played.clone();
+            if let Some(QueueContent::Random(_, _, q)) =
+                self.queue.get_item_at_index(&path, 0).map(|v| v.content())
+            {
+                // also avoid songs which are already coming up
+                recent.extend(q.iter().filter_map(|v| v.get_current_song(self)));
+            }
+            let mut new = vec![];
+            for _ in 0..count {
+                let weight = |id| match self.songs.get(&id) {
+                    Some(song) => {
+                        weighting.weight(song, self.play_counts.get(&id).copied().unwrap_or(0))
+                    }
+                    None => 0.0,
+                };
+                if let Some(id) = pick_random_song(&songs, &recent, weight, &mut rng) {
+                    recent.push(id);
+                    new.push(QueueContent::Song(id).into());
+                }
+            }
+            if !new.is_empty() {
+                self.apply_action_unchecked_seq_inner(
+                    Action::QueueAdd(path, new, Req::none()),
+                    None,
+                );
+            }
+        }
+    }
 }
 
 // file saving/loading
@@ -908,13 +1587,40 @@ impl Database {
             covers: HashMap::new(),
             custom_files: None,
             queue: QueueContent::Folder(QueueFolder::default()).into(),
+            random_no_repeat: 0,
+            recently_played: VecDeque::new(),
+            play_counts: HashMap::new(),
+            play_counts_modified: None,
             update_endpoints: vec![],
             update_endpoints_id: 0,
             playing: false,
+            queue_finished: false,
+            autoplay_on_empty: false,
+            volume: 1.0,
+            crossfade_millis: 0,
+            crossfade_natural_only: false,
+            replay_gain: ReplayGainMode::Off,
+            replay_gain_preamp_db: 0.0,
+            sleep_timer: None,
             command_sender: None,
             remote_server_as_song_file_source: None,
             client_is_init: false,
             times_data_modified: None,
+            saved_data_version: 0,
+            last_save: Instant::now(),
+            save_every: None,
+            data_version: 0,
+            started: Instant::now(),
+            open_connections: ConnectionCounts::default(),
+            broadcast_batch: None,
+            rescan_running: false,
+            duration_backfill: None,
+            library_verification: None,
+            transfer_limit: None,
+            cover_thumbnails: Default::default(),
+            relay: false,
+            playback_position: None,
+            volume_received: false,
         }
     }
     pub fn new_empty_in_dir(dir: PathBuf, lib_dir: PathBuf) -> Self {
@@ -930,13 +1636,40 @@ impl Database {
             covers: HashMap::new(),
             custom_files: None,
             queue: QueueContent::Folder(QueueFolder::default()).into(),
+            random_no_repeat: 0,
+            recently_played: VecDeque::new(),
+            play_counts: HashMap::new(),
+            play_counts_modified: None,
             update_endpoints: vec![],
             update_endpoints_id: 0,
             playing: false,
+            queue_finished: false,
+            autoplay_on_empty: false,
+            volume: 1.0,
+            crossfade_millis: 0,
+            crossfade_natural_only: false,
+            replay_gain: ReplayGainMode::Off,
+            replay_gain_preamp_db: 0.0,
+            sleep_timer: None,
             command_sender: None,
             remote_server_as_song_file_source: None,
             client_is_init: false,
             times_data_modified: None,
+            saved_data_version: 0,
+            last_save: Instant::now(),
+            save_every: None,
+            data_version: 0,
+            started: Instant::now(),
+            open_connections: ConnectionCounts::default(),
+            broadcast_batch: None,
+            rescan_running: false,
+            duration_backfill: None,
+            library_verification: None,
+            transfer_limit: None,
+            cover_thumbnails: Default::default(),
+            relay: false,
+            playback_position: None,
+            volume_received: false,
         }
     }
     pub fn load_database_from_dir(
@@ -945,8 +1678,8 @@ impl Database {
     ) -> Result<Self, std::io::Error> {
         let path = dir.join("dbfile");
         let mut file = BufReader::new(File::open(&path)?);
-        eprintln!("[{}] loading library from {file:?}", "INFO".cyan());
-        let s = Self {
+        log_info!("loading library from {file:?}");
+        let mut s = Self {
             seq: Commander::new(false),
             db_dir: dir,
             db_file: path,
@@ -957,15 +1690,60 @@ impl Database {
             covers: ToFromBytes::from_bytes(&mut file)?,
             custom_files: None,
             queue: QueueContent::Folder(QueueFolder::default()).into(),
+            random_no_repeat: 0,
+            recently_played: VecDeque::new(),
+            play_counts: HashMap::new(),
+            play_counts_modified: None,
             update_endpoints: vec![],
             update_endpoints_id: 0,
             playing: false,
+            queue_finished: false,
+            // added later, so older dbfiles don't contain this
+            autoplay_on_empty: u8::from_bytes(&mut file).is_ok_and(|v| v != 0),
+            volume: u32::from_bytes(&mut file)
+                .map(|v| f32::from_bits(v).clamp(0.0, 1.0))
+                .unwrap_or(1.0),
+            crossfade_millis: 0,
+            crossfade_natural_only: false,
+            replay_gain: ToFromBytes::from_bytes(&mut file).unwrap_or_default(),
+            replay_gain_preamp_db: u32::from_bytes(&mut file)
+                .map(|v| clamp_preamp(f32::from_bits(v)))
+                .unwrap_or(0.0),
+            sleep_timer: None,
             command_sender: None,
             remote_server_as_song_file_source: None,
             client_is_init: false,
             times_data_modified: None,
+            saved_data_version: 0,
+            last_save: Instant::now(),
+            save_every: None,
+            data_version: 0,
+            started: Instant::now(),
+            open_connections: ConnectionCounts::default(),
+            broadcast_batch: None,
+            rescan_running: false,
+            duration_backfill: None,
+            library_verification: None,
+            transfer_limit: None,
+            cover_thumbnails: Default::default(),
+            relay: false,
+            playback_position: None,
+            volume_received: false,
         };
-        eprintln!("[{}] loaded library", "INFO".green());
+        // added later, after the fields above
+        s.play_counts = ToFromBytes::from_bytes(&mut file).unwrap_or_default();
+        log_info!("loaded library");
+        Ok(s)
+    }
+    /// only relevant for clients: loads the library from a file saved using `save_database(Some(path))`,
+    /// so it can be browsed while the server can't be reached. the database is not initialized, see `is_client_init`.
+    pub fn load_clientside_cache(path: &Path) -> Result<Self, std::io::Error> {
+        let mut file = BufReader::new(File::open(path)?);
+        log_info!("loading cached library from {path:?}");
+        let mut s = Self::new_clientside();
+        s.artists = ToFromBytes::from_bytes(&mut file)?;
+        s.albums = ToFromBytes::from_bytes(&mut file)?;
+        s.songs = ToFromBytes::from_bytes(&mut file)?;
         Ok(s)
     }
     /// saves the database's contents. save path can be overridden
@@ -979,8 +1757,10 @@ impl Database {
         if path.as_os_str().is_empty() {
             return Ok(path);
         }
-        eprintln!("[{}] saving db to {path:?}", "INFO".cyan());
-        if path.try_exists()? {
+        log_info!("saving db to {path:?}");
+        let start = Instant::now();
+        // clients only save a cache (see `load_clientside_cache`), which doesn't need backups
+        if !self.is_client() && path.try_exists()? {
             let backup_name = format!(
                 "dbfile-{}",
                 std::time::SystemTime::now()
@@ -989,10 +1769,7 @@ impl Database {
                     .unwrap_or(0),
             );
             if let Err(e) = fs::rename(&path, self.db_dir.join(&backup_name)) {
-                eprintln!(
-                    "[{}] Couldn't move previous dbfile to {backup_name}!",
-                    "ERR!".red()
-                );
+                log_error!("Couldn't move previous dbfile to {backup_name}!");
                 return Err(e);
             }
         }
@@ -1005,11 +1782,118 @@ impl Database {
         self.albums.to_bytes(&mut file)?;
         self.songs.to_bytes(&mut file)?;
         self.covers.to_bytes(&mut file)?;
-        eprintln!("[{}] saved db", "INFO".green());
+        (self.autoplay_on_empty as u8).to_bytes(&mut file)?;
+        self.volume.to_bits().to_bytes(&mut file)?;
+        self.replay_gain.to_bytes(&mut file)?;
+        self.replay_gain_preamp_db.to_bits().to_bytes(&mut file)?;
+        self.play_counts.to_bytes(&mut file)?;
+        log_info!("saved db in {}ms", start.elapsed().as_millis());
         // all changes saved, data no longer modified
         self.times_data_modified = None;
+        self.play_counts_modified = None;
+        self.saved_data_version = self.data_version;
+        self.last_save = Instant::now();
         Ok(path)
     }
+    /// Called regularly by the server. Saves the database if it was modified since the last save:
+    /// with `save_every`, once that much time has passed since the last save,
+    /// otherwise once it was first modified a minute ago and hasn't been modified for 5 seconds.
+    /// If only the play counts changed, they are saved `PLAY_COUNTS_SAVE_DELAY` after the first change.
+    /// Unlike `Action::Save`, this isn't broadcast to clients. Returns `true` if the database was saved.
+    pub fn autosave(&mut self) -> Result<bool, std::io::Error> {
+        let save = if self
+            .play_counts_modified
+            .is_some_and(|first| first.elapsed() >= PLAY_COUNTS_SAVE_DELAY)
+        {
+            true
+        } else if let Some(save_every) = self.save_every {
+            self.data_version != self.saved_data_version && self.last_save.elapsed() >= save_every
+        } else if let Some((first, last)) = self.times_data_modified {
+            first.elapsed().as_secs_f32() > 60.0 && last.elapsed().as_secs_f32() > 5.0
+        } else {
+            false
+        };
+        if save {
+            self.save_database(None)?;
+        }
+        Ok(save)
+    }
+    /// Saves the database if it was modified, then tells all clients that the server is stopping.
+    /// The caller should exit without unlocking the database, so nothing can change after the final save.
+    pub fn shutdown(&mut self) -> Result<(), std::io::Error> {
+        // save first, in case sending to one of the clients blocks
+        let saved = if self.times_data_modified.is_some() || self.play_counts_modified.is_some() {
+            self.save_database(None).map(|_| ())
+        } else {
+            Ok(())
+        };
+        self.apply_action_unchecked_seq(Action::Stop, None);
+        // broadcast directly, `apply_action_unchecked_seq` would remove the title
+        self.broadcast_update(
+            Action::ErrorInfo(
+                "Server stopped".to_owned(),
+                "The server was shut down.".to_owned(),
+            ),
+            None,
+        );
+        self.flush_broadcasts();
+        saved
+    }
+    /// Sends `Ping` to all clients without changing the sequence number,
+    /// and removes the ones which can't be reached anymore.
+    pub fn ping_clients(&mut self) {
+        let ping = self.seq.pack(Action::Ping);
+        let bytes = ping.to_bytes_vec();
+        self.update_endpoints.retain_mut(|(_, udep)| match udep {
+            UpdateEndpoint::Bytes(writer) => writer
+                .write_all(&bytes)
+                .and_then(|()| writer.flush())
+                .is_ok(),
+            UpdateEndpoint::CmdChannel(sender) => sender.send(Arc::new(ping.clone())).is_ok(),
+            #[cfg(feature = "tokio")]
+            UpdateEndpoint::CmdChannelTokio(sender) => sender.send(Arc::new(ping.clone())).is_ok(),
+            // these aren't connections
+            UpdateEndpoint::Custom(_)
+            | UpdateEndpoint::CustomArc(_)
+            | UpdateEndpoint::CustomBytes(_) => true,
+        });
+    }
+    /// Until `flush_broadcasts()` is called, broadcasts are collected instead of being sent immediately.
+    /// They are then sent to each endpoint back-to-back, with only one flush per endpoint,
+    /// and updates which are made redundant by the next update (see `supersedes`) are skipped.
+    pub fn batch_broadcasts(&mut self) {
+        if self.broadcast_batch.is_none() {
+            self.broadcast_batch = Some(vec![]);
+        }
+    }
+    /// sends all broadcasts collected since `batch_broadcasts()` was called
+    pub fn flush_broadcasts(&mut self) {
+        if let Some(batch) = self.broadcast_batch.take() {
+            if batch.is_empty() {
+                return;
+            }
+            for (update, client, endpoints_id) in batch {
+                self.send_update(update, client, endpoints_id, false);
+            }
+            let mut removed = 0;
+            self.update_endpoints.retain_mut(|(_, udep)| match udep {
+                UpdateEndpoint::Bytes(writer) => {
+                    let ok = writer.flush().is_ok();
+                    if !ok {
+                        removed += 1;
+                    }
+                    ok
+                }
+                _ => true,
+            });
+            if removed > 0 {
+                log_info!(
+                    "closing {removed} connections, {} are still active",
+                    self.update_endpoints.len()
+                );
+            }
+        }
+    }
     pub fn broadcast_update(&mut self, update: Action, client: Option<u64>) -> Action {
         match update {
             Action::InitComplete => return update,
@@ -1018,17 +1902,48 @@ impl Database {
         if !self.is_client() {
             self.seq.inc();
         }
-        let mut update = self.seq.pack(update);
+        let update = self.seq.pack(update);
+        if let Some(batch) = &mut self.broadcast_batch {
+            let action = update.action.clone();
+            if batch
+                .last()
+                .is_some_and(|(prev, _, _)| supersedes(&update.action, &prev.action))
+            {
+                batch.pop();
+            }
+            batch.push((update, client, self.update_endpoints_id));
+            action
+        } else {
+            self.send_update(update, client, self.update_endpoints_id, true)
+        }
+    }
+    /// sends the update to all endpoints with an id below `endpoints_id`. the client which sent the update also gets the `Req`s.
+    fn send_update(
+        &mut self,
+        mut update: Command,
+        client: Option<u64>,
+        endpoints_id: u64,
+        flush: bool,
+    ) -> Action {
         let reqs = update.action.take_req_all();
         let mut remove = vec![];
         let mut bytes = None;
         let mut arc = None;
+        let write = |writer: &mut Box<dyn Write + Sync + Send>, bytes: &[u8]| {
+            writer
+                .write_all(bytes)
+                .and_then(|()| if flush { writer.flush() } else { Ok(()) })
+        };
         for (i, (udepid, udep)) in self.update_endpoints.iter_mut().enumerate() {
+            if *udepid >= endpoints_id {
+                // was added after the update was broadcast, so it already knows about it
+                continue;
+            }
             if reqs.iter().any(|r| r.is_some()) && client.is_some_and(|v| *udepid == v) {
                 update.action.put_req_all(reqs.clone());
                 match udep {
                     UpdateEndpoint::Bytes(writer) => {
-                        if writer.write_all(&update.to_bytes_vec()).is_err() {
+                        if write(writer, &update.to_bytes_vec()).is_err() {
                             remove.push(i);
                         }
                     }
@@ -1037,6 +1952,12 @@ impl Database {
                             remove.push(i);
                         }
                     }
+                    #[cfg(feature = "tokio")]
+                    UpdateEndpoint::CmdChannelTokio(sender) => {
+                        if sender.send(Arc::new(update.clone())).is_err() {
+                            remove.push(i);
+                        }
+                    }
                     UpdateEndpoint::Custom(func) => func(&update),
                     UpdateEndpoint::CustomArc(func) => func(Arc::new(update.clone())),
                     UpdateEndpoint::CustomBytes(func) => {
@@ -1053,7 +1974,7 @@ impl Database {
                     if bytes.is_none() {
                         bytes = Some(update.to_bytes_vec());
                     }
-                    if writer.write_all(bytes.as_ref().unwrap()).is_err() {
+                    if write(writer, bytes.as_ref().unwrap()).is_err() {
                         remove.push(i);
                     }
                 }
@@ -1065,6 +1986,15 @@ impl Database {
                         remove.push(i);
                     }
                 }
+                #[cfg(feature = "tokio")]
+                UpdateEndpoint::CmdChannelTokio(sender) => {
+                    if arc.is_none() {
+                        arc = Some(Arc::new(update.clone()));
+                    }
+                    if sender.send(arc.clone().unwrap()).is_err() {
+                        remove.push(i);
+                    }
+                }
                 UpdateEndpoint::Custom(func) => func(&update),
                 UpdateEndpoint::CustomArc(func) => {
                     if arc.is_none() {
@@ -1081,8 +2011,8 @@ impl Database {
             }
         }
         if !remove.is_empty() {
-            eprintln!(
-                "[info] closing {} connections, {} are still active",
+            log_info!(
+                "closing {} connections, {} are still active",
                 remove.len(),
                 self.update_endpoints.len() - remove.len()
             );
@@ -1147,6 +2077,38 @@ impl PartialEq for Cover {
     }
 }
 impl Cover {
+    /// Starts a thread which loads the cover's bytes, unless they are already loaded or being loaded.
+    /// Returns `Ok(true)` if a thread was started, `Ok(false)` if the bytes are loaded, and `Err(true)` if they are being loaded.
+    pub fn prefetch(&self, path: PathBuf) -> Result<bool, bool> {
+        let mut data = self.data.lock().unwrap();
+        if data.0 {
+            return Err(true);
+        }
+        if data.1.is_some() {
+            return Ok(false);
+        }
+        data.0 = true;
+        let arc = Arc::clone(&self.data);
+        std::thread::spawn(move || {
+            let bytes = std::fs::read(&path);
+            let mut data = arc.lock().unwrap();
+            data.0 = false;
+            if let Ok(bytes) = bytes {
+                data.1 = Some((Instant::now(), bytes));
+            }
+        });
+        Ok(true)
+    }
+    /// Removes the cover's bytes from memory. Returns `true` if bytes were removed.
+    pub fn uncache(&self) -> bool {
+        let mut data = self.data.lock().unwrap();
+        !data.0 && data.1.take().is_some()
+    }
+    /// A hash of the cover's bytes, which changes when the cover's file is replaced
+    /// (once the old bytes are no longer cached). Clients can include it in their cache keys, see `cover_version`.
+    pub fn version(&self, path: impl FnOnce(&DatabaseLocation) -> PathBuf) -> Option<u64> {
+        self.get_bytes_from_file(path, |bytes| cover_version(bytes))
+    }
     pub fn get_bytes_from_file<O>(
         &self,
         path: impl FnOnce(&DatabaseLocation) -> PathBuf,
@@ -1175,6 +2137,13 @@ impl Cover {
         }
     }
 }
+/// the version of a cover with these bytes, see `Cover::version`
+pub fn cover_version(bytes: &[u8]) -> u64 {
+    let mut crc = flate2::Crc::new();
+    crc.update(bytes);
+    (bytes.len() as u64) << 32 | crc.sum() as u64
+}
+
 impl ToFromBytes for Cover {
     fn to_bytes<T>(&self, s: &mut T) -> Result<(), std::io::Error>
     where
@@ -1192,3 +2161,407 @@ impl ToFromBytes for Cover {
         })
     }
 }
+
+#[test]
+fn test_batched_broadcasts() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    /// counts the writes which reach the connection
+    struct CountWrites(Arc<AtomicUsize>);
+    impl Write for CountWrites {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let writes = |batch: bool, actions: &mut dyn Iterator<Item = Action>| {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let mut db = Database::new_clientside();
+        db.update_endpoints.push((
+            0,
+            UpdateEndpoint::Bytes(Box::new(std::io::BufWriter::new(CountWrites(Arc::clone(
+                &writes,
+            ))))),
+        ));
+        db.update_endpoints_id = 1;
+        if batch {
+            db.batch_broadcasts();
+        }
+        for action in actions {
+            db.apply_action_unchecked_seq(action, None);
+        }
+        db.flush_broadcasts();
+        writes.load(Ordering::Relaxed)
+    };
+    let add = |id| Action::QueueAdd(vec![], vec![QueueContent::Song(id).into()], Req::none());
+    // a client adding 200 songs to the queue
+    assert_eq!(writes(false, &mut (0..200).map(add)), 200);
+    assert_eq!(writes(true, &mut (0..200).map(add)), 1);
+    // only the last update is sent
+    let update = || Action::QueueUpdate(vec![], QueueContent::Song(0).into(), Req::none());
+    let mut db = Database::new_clientside();
+    let (sender, receiver) = mpsc::channel();
+    db.update_endpoints
+        .push((0, UpdateEndpoint::CmdChannel(sender)));
+    db.update_endpoints_id = 1;
+    db.batch_broadcasts();
+    db.apply_action_unchecked_seq(add(0), None);
+    db.apply_action_unchecked_seq(update(), None);
+    db.apply_action_unchecked_seq(update(), None);
+    // an endpoint added during the batch doesn't receive updates from before it was added
+    let (late_sender, late_receiver) = mpsc::channel();
+    db.update_endpoints
+        .push((1, UpdateEndpoint::CmdChannel(late_sender)));
+    db.update_endpoints_id = 2;
+    db.apply_action_unchecked_seq(update(), None);
+    assert!(receiver.try_recv().is_err());
+    db.flush_broadcasts();
+    // the QueueAdd and the last QueueUpdate
+    assert_eq!(receiver.try_iter().count(), 2);
+    assert_eq!(late_receiver.try_iter().count(), 1);
+}
+
+#[test]
+fn test_sleep_timer_after_song() {
+    use crate::test_util::test_song;
+    let mut db = Database::new_clientside();
+    // only the server pauses when the song ends
+    db.command_sender = Some(mpsc::channel().0);
+    for id in 0..3 {
+        let mut song = test_song(&format!("{id}"), None, 0, 0);
+        song.id = id;
+        db.songs_mut().insert(id, song);
+    }
+    let songs = (0..3).map(|id| QueueContent::Song(id).into()).collect();
+    db.apply_action_unchecked_seq(Action::QueueAdd(vec![], songs, Req::none()), None);
+    db.apply_action_unchecked_seq(Action::Resume, None);
+    db.apply_action_unchecked_seq(Action::SetSleepTimer(Some(3600), true), None);
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    assert!(db.playing);
+    let (remaining, after_song) = db.sleep_timer_remaining().unwrap();
+    assert!(after_song && remaining > Duration::from_secs(3590));
+    // a new timer replaces the old one
+    db.apply_action_unchecked_seq(Action::SetSleepTimer(Some(0), true), None);
+    assert!(db.playing);
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    assert!(!db.playing);
+    assert!(db.sleep_timer.is_none());
+}
+
+#[test]
+fn test_next_song_race() {
+    use crate::test_util::test_song;
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    db.command_sender = Some(mpsc::channel().0);
+    let (sender, receiver) = mpsc::channel();
+    db.update_endpoints
+        .push((0, UpdateEndpoint::CmdChannel(sender)));
+    db.update_endpoints_id = 1;
+    let songs = (0..3)
+        .map(|_| QueueContent::Song(db.add_song_new(test_song("", None, 0, 1000))).into())
+        .collect();
+    db.apply_action_unchecked_seq(Action::QueueAdd(vec![], songs, Req::none()), None);
+    db.apply_action_unchecked_seq(Action::Resume, None);
+    _ = receiver.try_iter().count();
+    // two clients skip the same song at the same time
+    let (first, second) = (db.next_song_action(), db.next_song_action());
+    assert_eq!(first, Action::NextSongIfCurrent(vec![0], 0));
+    db.apply_action_unchecked_seq(first, None);
+    db.apply_action_unchecked_seq(second, None);
+    assert_eq!(db.queue.get_current_path(), Some(vec![1]));
+    // other clients only see a `NextSong`
+    let broadcast = receiver
+        .try_iter()
+        .map(|command| command.action.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(broadcast, vec![Action::NextSong]);
+    // without the current song, both skips are applied
+    db.apply_action_unchecked_seq(Action::QueueGoto(vec![0]), None);
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    assert_eq!(db.queue.get_current_path(), Some(vec![2]));
+}
+
+#[test]
+fn test_autosave_every() {
+    let dir = crate::test_util::test_dir("autosave");
+    let mut db = Database::new_empty_in_dir(dir.clone(), dir.clone());
+    db.save_every = Some(Duration::ZERO);
+    // nothing changed
+    assert!(!db.autosave().unwrap());
+    db.apply_action_unchecked_seq(Action::SetAutoplayOnEmpty(true), None);
+    assert!(db.autosave().unwrap());
+    assert!(dir.join("dbfile").exists());
+    assert!(!db.autosave().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_play_counts_saved() {
+    use crate::test_util::{test_dir, test_song};
+    let dir = test_dir("play-counts");
+    let mut db = Database::new_empty_in_dir(dir.clone(), dir.clone());
+    db.command_sender = Some(mpsc::channel().0);
+    let songs = (0..2)
+        .map(|_| QueueContent::Song(db.add_song_new(test_song("", None, 0, 1000))).into())
+        .collect();
+    db.apply_action_unchecked_seq(Action::QueueAdd(vec![], songs, Req::none()), None);
+    db.save_database(None).unwrap();
+    let data_version = db.data_version();
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    // playing a song has to be saved, but doesn't change the library
+    assert!(db.play_counts_modified.is_some());
+    assert_eq!(db.data_version(), data_version);
+    assert!(!db.autosave().unwrap());
+    db.shutdown().unwrap();
+    let db = Database::load_database_from_dir(dir.clone(), dir.clone()).unwrap();
+    assert_eq!(db.play_counts, HashMap::from([(0, 1)]));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_clientside_cache() {
+    use crate::test_util::{test_dir, test_song};
+    let dir = test_dir("client-cache");
+    let path = dir.join("library");
+    let mut db = Database::new_clientside();
+    db.sync(vec![], vec![], vec![test_song("song", None, 0, 0)]);
+    db.save_database(Some(path.clone())).unwrap();
+    // saving again replaces the cache without keeping a backup
+    db.save_database(Some(path.clone())).unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    let cache = Database::load_clientside_cache(&path).unwrap();
+    assert!(cache.is_client());
+    assert!(!cache.is_client_init());
+    assert_eq!(cache.songs().len(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_remove() {
+    use super::GeneralData;
+    use crate::test_util::test_song;
+    let mut db = Database::new_clientside();
+    let artist = db.add_artist_new(Artist {
+        id: 0,
+        name: "artist".to_owned(),
+        cover: None,
+        albums: vec![],
+        singles: vec![],
+        general: GeneralData::default(),
+    });
+    let album = db.add_album_new(Album {
+        id: 0,
+        name: "album".to_owned(),
+        artist,
+        cover: None,
+        songs: vec![],
+        general: GeneralData::default(),
+    });
+    let mut song = |album| db.add_song_new(test_song("song", album, artist, 0));
+    let (a, b, single) = (song(Some(album)), song(Some(album)), song(None));
+    // ids of other kinds are unrelated
+    assert!(db.remove_album(single).is_none());
+    db.remove_song(a).unwrap();
+    assert_eq!(db.albums()[&album].songs, vec![b]);
+    db.remove_album(album).unwrap();
+    assert!(db.songs().get(&b).is_none());
+    assert!(db.artists()[&artist].albums.is_empty());
+    assert!(db.songs().get(&single).is_some());
+    db.remove_artist(artist).unwrap();
+    assert!(db.songs().is_empty() && db.artists().is_empty());
+}
+
+#[test]
+fn test_update_album() {
+    use super::GeneralData;
+    use crate::test_util::test_song;
+    let mut db = Database::new_clientside();
+    let mut artist = |name: &str| {
+        db.add_artist_new(Artist {
+            id: 0,
+            name: name.to_owned(),
+            cover: None,
+            albums: vec![],
+            singles: vec![],
+            general: GeneralData::default(),
+        })
+    };
+    let (a, b) = (artist("a"), artist("b"));
+    let mut album = |name: &str| {
+        db.add_album_new(Album {
+            id: 0,
+            name: name.to_owned(),
+            artist: a,
+            cover: None,
+            songs: vec![],
+            general: GeneralData::default(),
+        })
+    };
+    let (x, y) = (album("x"), album("y"));
+    let song = db.add_song_new(test_song("song", Some(y), a, 0));
+    // move y to another artist
+    let mut new = db.albums()[&y].clone();
+    new.name = "z".to_owned();
+    new.artist = b;
+    db.update_album(new).unwrap();
+    assert_eq!(db.artists()[&a].albums, vec![x]);
+    assert_eq!(db.artists()[&b].albums, vec![y]);
+    assert_eq!(db.songs()[&song].artist, b);
+    // removing the song this way isn't allowed
+    let mut new = db.albums()[&y].clone();
+    new.songs.clear();
+    assert!(db.update_album(new).is_err());
+    assert_eq!(db.albums()[&y].songs, vec![song]);
+}
+
+#[test]
+fn test_album_ref_keeps_song() {
+    use super::{queue::QueueContent, GeneralData};
+    use crate::{server::Req, test_util::test_song};
+    let mut db = Database::new_clientside();
+    let artist = db.add_artist_new(Artist {
+        id: 0,
+        name: "artist".to_owned(),
+        cover: None,
+        albums: vec![],
+        singles: vec![],
+        general: GeneralData::default(),
+    });
+    let album = db.add_album_new(Album {
+        id: 0,
+        name: "album".to_owned(),
+        artist,
+        cover: None,
+        songs: vec![],
+        general: GeneralData::default(),
+    });
+    let song = test_song("song", Some(album), artist, 0);
+    let [a, b, c, d] = [(); 4].map(|_| db.add_song_new(song.clone()));
+    db.apply_action_unchecked_seq(
+        Action::QueueUpdate(vec![], QueueContent::AlbumRef(album, 1).into(), Req::none()),
+        None,
+    );
+    let current = |db: &Database| db.queue.get_current_song(db).copied();
+    assert_eq!(current(&db), Some(b));
+    // reordering the album doesn't change the current song
+    let mut new = db.albums()[&album].clone();
+    new.songs = vec![d, c, b, a];
+    db.apply_action_unchecked_seq(Action::ModifyAlbum(new, Req::none()), None);
+    assert_eq!(current(&db), Some(b));
+    // neither does removing a song before it
+    db.apply_action_unchecked_seq(Action::RemoveSong(c), None);
+    assert_eq!(db.albums()[&album].songs, vec![d, b, a]);
+    assert_eq!(current(&db), Some(b));
+    // removing the current song continues with the next one
+    db.apply_action_unchecked_seq(Action::RemoveSong(b), None);
+    assert_eq!(current(&db), Some(a));
+    db.apply_action_unchecked_seq(Action::AddSong(song, Req::none()), None);
+    assert_eq!(current(&db), Some(a));
+}
+
+#[test]
+fn test_playback_position() {
+    use crate::test_util::test_song;
+    let mut db = Database::new_clientside();
+    let song = db.add_song_new(test_song("song", None, 0, 180_000));
+    db.apply_action_unchecked_seq(
+        Action::QueueAdd(vec![], vec![QueueContent::Song(song).into()], Req::none()),
+        None,
+    );
+    assert_eq!(db.playback_position_now(), None);
+    db.apply_action_unchecked_seq(Action::Seek(60_000), None);
+    assert_eq!(db.playback_position_now(), Some(60_000));
+    db.apply_action_unchecked_seq(Action::SeekBy(-10_000), None);
+    assert_eq!(db.playback_position_now(), Some(50_000));
+    db.apply_action_unchecked_seq(Action::PlaybackPosition(song, 200_000), None);
+    assert_eq!(db.playback_position_now(), Some(180_000));
+    // only the current song's position is known
+    db.apply_action_unchecked_seq(Action::PlaybackPosition(song + 1, 1_000), None);
+    assert_eq!(db.playback_position_now(), None);
+}
+
+#[test]
+fn test_queue_duplicate() {
+    use super::queue::{folder_at, song};
+    let mut db = Database::new_clientside();
+    // nested path: the copy goes to the end of the parent folder, with its index reset
+    db.queue = folder_at(
+        0,
+        vec![
+            song(0),
+            folder_at(1, vec![folder_at(1, vec![song(1), song(2)], None)], None),
+        ],
+        None,
+    );
+    db.apply_action_unchecked_seq(Action::QueueDuplicate(vec![1, 0]), None);
+    assert_eq!(
+        db.queue,
+        folder_at(
+            0,
+            vec![
+                song(0),
+                folder_at(
+                    1,
+                    vec![
+                        folder_at(1, vec![song(1), song(2)], None),
+                        folder_at(0, vec![song(1), song(2)], None),
+                    ],
+                    None,
+                ),
+            ],
+            None,
+        )
+    );
+    // a loop's content: the copy goes into the folder around the loop
+    let inner = folder_at(0, vec![song(3)], None);
+    db.queue = folder_at(
+        0,
+        vec![
+            song(0),
+            QueueContent::Loop(2, 1, Box::new(inner.clone())).into(),
+        ],
+        None,
+    );
+    db.apply_action_unchecked_seq(Action::QueueDuplicate(vec![1, 0]), None);
+    assert_eq!(
+        db.queue,
+        folder_at(
+            0,
+            vec![
+                song(0),
+                QueueContent::Loop(2, 1, Box::new(inner.clone())).into(),
+                inner,
+            ],
+            None,
+        )
+    );
+    // the queue root can't be duplicated
+    let before = db.queue.clone();
+    db.apply_action_unchecked_seq(Action::QueueDuplicate(vec![]), None);
+    assert_eq!(db.queue, before);
+    // shuffled folder: the copy gets a new order and starts from the beginning
+    let order = (0..20).collect::<Vec<usize>>();
+    db.queue = folder_at(
+        0,
+        vec![folder_at(
+            5,
+            (0..20).map(song).collect(),
+            Some(order.clone()),
+        )],
+        None,
+    );
+    db.apply_action_unchecked_seq(Action::QueueDuplicate(vec![0]), None);
+    let copy = db.queue.get_item_at_index(&[1], 0).unwrap();
+    let QueueContent::Folder(copy) = copy.content() else {
+        panic!("the copy should be a folder");
+    };
+    assert_eq!(copy.index, 0);
+    assert_eq!(copy.content, (0..20).map(song).collect::<Vec<_>>());
+    let mut new_order = copy.order.clone().unwrap();
+    assert_ne!(new_order, order);
+    new_order.sort();
+    assert_eq!(new_order, order);
+}
@@ -9,7 +9,11 @@ pub mod album;
 pub mod artist;
 pub mod cache_manager;
 pub mod database;
+pub mod playlist;
 pub mod queue;
+pub mod replay_gain;
+pub mod scan;
+pub mod search;
 pub mod song;
 
 pub type SongId = u64;
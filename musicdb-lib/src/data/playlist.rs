@@ -0,0 +1,219 @@
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    load::ToFromBytes,
+    server::{Action, Req},
+};
+
+use super::{
+    database::Database,
+    queue::{Queue, QueueContent},
+};
+
+/// the file extension of saved playlists
+const EXTENSION: &str = "queue";
+/// names longer than this are rejected by `sanitize_name`
+const MAX_NAME_LEN: usize = 100;
+
+/// Trims the name and checks that it can be used as a file name, so that
+/// names like `../dbfile` can't be used to access files outside of the playlist directory.
+pub fn sanitize_name(name: &str) -> Option<&str> {
+    let name = name.trim();
+    if name.is_empty()
+        || name.chars().count() > MAX_NAME_LEN
+        || name.starts_with('.')
+        || name.chars().any(|c| {
+            c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+        })
+    {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Saved queues are stored in the `playlists` directory next to the dbfile, one file per playlist.
+/// Not available on clients, which have no database directory.
+fn dir(db: &Database) -> io::Result<PathBuf> {
+    if db.db_dir.as_os_str().is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "playlists can only be stored on the server",
+        ))
+    } else {
+        Ok(db.db_dir.join("playlists"))
+    }
+}
+fn path(db: &Database, name: &str) -> io::Result<PathBuf> {
+    let name = sanitize_name(name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid playlist name"))?;
+    Ok(dir(db)?.join(format!("{name}.{EXTENSION}")))
+}
+
+/// the names of all saved playlists, sorted
+pub fn list(db: &Database) -> io::Result<Vec<String>> {
+    let dir = dir(db)?;
+    if !dir.try_exists()? {
+        return Ok(vec![]);
+    }
+    let mut names = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names.sort_by_cached_key(|name| name.to_lowercase());
+    Ok(names)
+}
+/// saves the queue under this name, replacing the playlist if it already exists
+pub fn save(db: &Database, name: &str, queue: &Queue) -> io::Result<()> {
+    let path = path(db, name)?;
+    fs::create_dir_all(dir(db)?)?;
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    queue.to_bytes(&mut file)?;
+    file.flush()
+}
+/// Loads the playlist, without the songs which were removed from the database since it was saved.
+/// If it is a folder, the folder gets the playlist's name. Also returns the number of songs which were skipped.
+pub fn load(db: &Database, name: &str) -> io::Result<(Queue, usize)> {
+    let mut queue = Queue::from_bytes(&mut BufReader::new(fs::File::open(path(db, name)?)?))?;
+    let skipped = queue.remove_missing_songs(db);
+    if let (QueueContent::Folder(folder), Some(name)) = (queue.content_mut(), sanitize_name(name)) {
+        folder.name = name.to_owned();
+    }
+    queue.init();
+    Ok((queue, skipped))
+}
+pub fn delete(db: &Database, name: &str) -> io::Result<()> {
+    fs::remove_file(path(db, name)?)
+}
+/// renames the playlist, but doesn't replace another playlist which already has the new name
+pub fn rename(db: &Database, name: &str, new_name: &str) -> io::Result<()> {
+    let (path, new_path) = (path(db, name)?, path(db, new_name)?);
+    if new_path.try_exists()? {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "a playlist with this name already exists",
+        ));
+    }
+    fs::rename(path, new_path)
+}
+
+/// Saves, loads, deletes or renames a playlist for one of the `Playlist*` actions, see `Action::PlaylistSave`.
+/// Returns a message for the user and, for `PlaylistLoad`, the `QueueAdd` or `QueueUpdate` which puts the playlist into the queue.
+/// The error's message is also meant for the user.
+pub fn apply_action(db: &Database, action: &Action) -> io::Result<(String, Option<Action>)> {
+    let (verb, name, result) = match action {
+        Action::PlaylistSave(path, name) => (
+            "save",
+            name,
+            match db.queue.get_item_at_index(path, 0) {
+                Some(elem) => save(db, name, elem)
+                    .map(|()| (format!("saved playlist {name} ({} songs)", elem.len(db)), None)),
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no queue element at {path:?}"),
+                )),
+            },
+        ),
+        Action::PlaylistLoad(name, append) => (
+            "load",
+            name,
+            load(db, name).map(|(queue, skipped)| {
+                let message = if skipped == 0 {
+                    format!("loaded playlist {name}")
+                } else {
+                    format!(
+                        "loaded playlist {name}, skipped {skipped} song{} which {} no longer in the library",
+                        if skipped == 1 { "" } else { "s" },
+                        if skipped == 1 { "is" } else { "are" },
+                    )
+                };
+                let action = if *append {
+                    Action::QueueAdd(vec![], vec![queue], Req::none())
+                } else {
+                    Action::QueueUpdate(vec![], queue, Req::none())
+                };
+                (message, Some(action))
+            }),
+        ),
+        Action::PlaylistDelete(name) => (
+            "delete",
+            name,
+            delete(db, name).map(|()| (format!("deleted playlist {name}"), None)),
+        ),
+        Action::PlaylistRename(name, new_name) => (
+            "rename",
+            name,
+            rename(db, name, new_name)
+                .map(|()| (format!("renamed playlist {name} to {new_name}"), None)),
+        ),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a playlist action",
+            ))
+        }
+    };
+    result.map_err(|e| io::Error::new(e.kind(), format!("couldn't {verb} playlist {name}: {e}")))
+}
+
+#[test]
+fn test_sanitize_name() {
+    assert_eq!(sanitize_name("  party mix "), Some("party mix"));
+    assert_eq!(sanitize_name(""), None);
+    assert_eq!(sanitize_name("../dbfile"), None);
+    assert_eq!(sanitize_name("a/b"), None);
+    assert_eq!(sanitize_name(".hidden"), None);
+    assert_eq!(sanitize_name("line\nbreak"), None);
+    assert_eq!(sanitize_name(&"x".repeat(MAX_NAME_LEN + 1)), None);
+}
+#[test]
+fn test_save_load() {
+    use super::queue::QueueContent;
+    use crate::test_util::{test_dir, test_song};
+    let dir = test_dir("playlists");
+    let mut db = Database::new_empty_in_dir(dir.clone(), dir.clone());
+    let songs = ["a", "b"]
+        .into_iter()
+        .map(|title| db.add_song_new(test_song(title, None, 0, 0)))
+        .collect::<Vec<_>>();
+    let queue: Queue = QueueContent::Folder(super::queue::QueueFolder {
+        index: 0,
+        content: songs
+            .iter()
+            .map(|id| QueueContent::Song(*id).into())
+            .collect(),
+        name: "mix".to_owned(),
+        order: None,
+    })
+    .into();
+    assert!(list(&db).unwrap().is_empty());
+    save(&db, "mix", &queue).unwrap();
+    assert!(save(&db, "../mix", &queue).is_err());
+    assert_eq!(list(&db).unwrap(), vec!["mix".to_owned()]);
+    let (loaded, skipped) = load(&db, "mix").unwrap();
+    assert_eq!((loaded.len(&db), skipped), (2, 0));
+    db.remove_song(songs[0]).unwrap();
+    let (loaded, skipped) = load(&db, "mix").unwrap();
+    assert_eq!((loaded.len(&db), skipped), (1, 1));
+    save(&db, "other", &queue).unwrap();
+    assert!(rename(&db, "mix", "other").is_err());
+    assert!(rename(&db, "mix", "../mix").is_err());
+    rename(&db, "other", "renamed").unwrap();
+    assert_eq!(
+        list(&db).unwrap(),
+        vec!["mix".to_owned(), "renamed".to_owned()]
+    );
+    delete(&db, "renamed").unwrap();
+    delete(&db, "mix").unwrap();
+    assert!(list(&db).unwrap().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,19 +1,100 @@
-use std::ops::AddAssign;
+use std::{
+    collections::{HashSet, VecDeque},
+    ops::AddAssign,
+    sync::Mutex,
+};
+
+use rand::{seq::SliceRandom, Rng};
 
 use crate::load::ToFromBytes;
 
-use super::{database::Database, SongId};
+use super::{database::Database, song::Song, AlbumId, ArtistId, SongId};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Queue {
     enabled: bool,
+    /// shown in the queue view, to tell elements apart. folders also have a name, which is separate from this.
+    label: Option<String>,
     content: QueueContent,
+    cache: QueueCache,
+}
+/// Values which are expensive to compute for large queues.
+/// They are reset whenever the element is borrowed mutably,
+/// and ignored if the database's library changed since they were computed.
+/// Not serialized and ignored by `PartialEq`.
+#[derive(Debug, Default)]
+struct QueueCache {
+    len: Mutex<Option<(u64, usize)>>,
+    duration_total: Mutex<Option<(u64, QueueDuration)>>,
+}
+impl QueueCache {
+    fn get<T: Copy>(cache: &Mutex<Option<(u64, T)>>, db: &Database, f: impl FnOnce() -> T) -> T {
+        if let Some((version, v)) = *cache.lock().unwrap() {
+            if version == db.data_version() {
+                return v;
+            }
+        }
+        // don't hold the lock while computing the value
+        let v = f();
+        *cache.lock().unwrap() = Some((db.data_version(), v));
+        v
+    }
+    fn invalidate(&mut self) {
+        *self.len.get_mut().unwrap() = None;
+        *self.duration_total.get_mut().unwrap() = None;
+    }
+}
+impl Clone for QueueCache {
+    fn clone(&self) -> Self {
+        Self {
+            len: Mutex::new(*self.len.lock().unwrap()),
+            duration_total: Mutex::new(*self.duration_total.lock().unwrap()),
+        }
+    }
+}
+impl PartialEq for QueueCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 #[derive(Clone, Debug, PartialEq)]
 pub enum QueueContent {
     Song(SongId),
     Folder(QueueFolder),
     Loop(usize, usize, Box<Queue>),
+    /// An album and the index of the current song in it.
+    /// The album's songs are looked up in the database whenever they are needed,
+    /// so changes to the album are reflected in the queue.
+    /// When songs are added to, removed from or moved in the album, the index is moved so that the current song stays the same.
+    AlbumRef(AlbumId, usize),
+    /// Random songs from the library which match the filter. The first element is the current one, the rest are coming up next.
+    /// The server adds new songs to the end when the element runs low, see `Database::fill_random_elements`.
+    Random(RandomFilter, RandomWeighting, VecDeque<Queue>),
+}
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RandomFilter {
+    /// any song in the library
+    #[default]
+    All,
+    /// songs which have this tag
+    TagEq(String),
+    /// songs with a tag '<String><Integer>' where Integer is between min and max (both inclusive)
+    TagWithValueInt(String, i32, i32),
+    /// songs by this artist (including features)
+    Artist(ArtistId),
+    /// songs on this album
+    Album(AlbumId),
+}
+/// How likely each song is to be chosen by a `Random` element
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RandomWeighting {
+    /// every song is equally likely
+    #[default]
+    Uniform,
+    /// songs which have been played less often are more likely
+    PreferUnplayed,
+    /// songs tagged "Fav" are more likely
+    PreferFavorites,
 }
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct QueueFolder {
@@ -27,18 +108,37 @@ impl Queue {
     pub fn enabled(&self) -> bool {
         self.enabled
     }
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+    pub fn set_label(&mut self, label: Option<String>) {
+        self.label = label;
+    }
     pub fn content(&self) -> &QueueContent {
         &self.content
     }
     pub fn content_mut(&mut self) -> &mut QueueContent {
+        self.cache.invalidate();
         &mut self.content
     }
 
     pub fn add_to_end(&mut self, v: Vec<Self>, skip_init: bool) -> Option<usize> {
+        self.cache.invalidate();
         match &mut self.content {
             QueueContent::Song(_) => None,
             QueueContent::Folder(folder) => folder.add_to_end(v, skip_init),
             QueueContent::Loop(..) => None,
+            QueueContent::AlbumRef(..) => None,
+            QueueContent::Random(_, _, q) => {
+                let len = q.len();
+                for mut v in v {
+                    if !skip_init {
+                        v.init();
+                    }
+                    q.push_back(v);
+                }
+                Some(len)
+            }
         }
     }
     pub fn is_current(&self, index: &[usize]) -> bool {
@@ -54,31 +154,117 @@ impl Queue {
                         .is_some_and(|c| c.is_current(&index[1..]))
             }
             QueueContent::Loop(_, _, inner) => index[0] == 0 && inner.is_current(&index[1..]),
+            QueueContent::AlbumRef(_, i) => index[0] == *i,
+            QueueContent::Random(_, _, q) => {
+                index[0] == 0 && q.front().is_some_and(|c| c.is_current(&index[1..]))
+            }
         }
     }
     pub fn insert(&mut self, v: Vec<Self>, index: usize, skip_init: bool) -> bool {
+        self.cache.invalidate();
         match &mut self.content {
             QueueContent::Song(_) => false,
             QueueContent::Folder(folder) => folder.insert(v, index, skip_init),
             QueueContent::Loop(..) => false,
+            QueueContent::AlbumRef(..) => false,
+            QueueContent::Random(_, _, q) => {
+                // the first element is the current one, so we can't insert before it
+                if index > 0 && index <= q.len() {
+                    for (i, mut v) in v.into_iter().enumerate() {
+                        if !skip_init {
+                            v.init();
+                        }
+                        q.insert(index + i, v);
+                    }
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+    /// Inserts `elem` directly after the current element, in the innermost folder which contains the current song.
+    /// Random elements aren't entered, so if the current song is inside a random element, `elem` is inserted after that.
+    /// If there is no such folder, `elem` is added to the end of this element instead.
+    /// Returns `false` if `elem` couldn't be added at all.
+    pub fn insert_after_current(&mut self, elem: Self) -> bool {
+        fn insert_inner(queue: &mut Queue, elem: &mut Option<Queue>) -> bool {
+            match queue.content_mut() {
+                QueueContent::Folder(folder) => {
+                    let index = folder.index;
+                    if folder
+                        .get_current_mut()
+                        .is_some_and(|current| insert_inner(current, elem))
+                    {
+                        return true;
+                    }
+                    !folder.content.is_empty()
+                        && folder.insert(vec![elem.take().unwrap()], index + 1, false)
+                }
+                QueueContent::Loop(_, _, inner) => insert_inner(inner, elem),
+                QueueContent::Song(_) | QueueContent::AlbumRef(..) | QueueContent::Random(..) => {
+                    false
+                }
+            }
+        }
+        let mut elem = Some(elem);
+        insert_inner(self, &mut elem)
+            || elem
+                .take()
+                .is_some_and(|elem| self.add_to_end(vec![elem], false).is_some())
+    }
+
+    /// true if this element is disabled or only contains disabled elements.
+    /// unlike `is_empty`, this doesn't depend on the database.
+    fn is_disabled(&self) -> bool {
+        !self.enabled
+            || match &self.content {
+                QueueContent::Song(_) | QueueContent::AlbumRef(..) | QueueContent::Random(..) => {
+                    false
+                }
+                QueueContent::Folder(folder) => {
+                    !folder.content.is_empty() && folder.content.iter().all(|v| v.is_disabled())
+                }
+                QueueContent::Loop(_, _, inner) => inner.is_disabled(),
+            }
+    }
+    /// false if the element at the path or one of its parents is disabled.
+    pub fn is_enabled_at(&self, index: &[usize]) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let i = if let Some(i) = index.first() {
+            *i
+        } else {
+            return true;
+        };
+        match &self.content {
+            QueueContent::Song(_) | QueueContent::AlbumRef(..) => true,
+            QueueContent::Folder(folder) => folder
+                .get_at(i)
+                .is_none_or(|v| v.is_enabled_at(&index[1..])),
+            QueueContent::Loop(_, _, inner) => inner.is_enabled_at(&index[1..]),
+            QueueContent::Random(_, _, q) => q.get(i).is_none_or(|v| v.is_enabled_at(&index[1..])),
         }
     }
 
-    pub fn is_empty(&self) -> bool {
+    pub fn is_empty(&self, db: &Database) -> bool {
         if !self.enabled {
             return true;
         }
         match &self.content {
             QueueContent::Song(_) => false,
-            QueueContent::Folder(folder) => folder.content.iter().all(|v| v.is_empty()),
-            QueueContent::Loop(_total, _done, inner) => inner.is_empty(),
+            QueueContent::Folder(folder) => folder.content.iter().all(|v| v.is_empty(db)),
+            QueueContent::Loop(_total, _done, inner) => inner.is_empty(db),
+            QueueContent::AlbumRef(album, _) => album_songs(db, album).is_empty(),
+            QueueContent::Random(_, _, q) => q.iter().all(|v| v.is_empty(db)),
         }
     }
     /// returns true if there is at most one song in the queue
-    pub fn is_almost_empty(&self) -> bool {
-        self.is_almost_empty_int() < 2
+    pub fn is_almost_empty(&self, db: &Database) -> bool {
+        self.is_almost_empty_int(db) < 2
     }
-    fn is_almost_empty_int(&self) -> u8 {
+    fn is_almost_empty_int(&self, db: &Database) -> u8 {
         if !self.enabled {
             return 0;
         }
@@ -87,36 +273,83 @@ impl Queue {
             QueueContent::Folder(folder) => {
                 let mut o = 0;
                 for v in folder.content.iter() {
-                    o += v.is_almost_empty_int();
+                    o += v.is_almost_empty_int(db);
+                    if o >= 2 {
+                        return 2;
+                    }
+                }
+                o
+            }
+            QueueContent::Loop(_total, _done, inner) => inner.is_almost_empty_int(db),
+            QueueContent::AlbumRef(album, _) => album_songs(db, album).len().min(2) as u8,
+            QueueContent::Random(_, _, q) => {
+                let mut o = 0;
+                for v in q.iter() {
+                    o += v.is_almost_empty_int(db);
                     if o >= 2 {
                         return 2;
                     }
                 }
                 o
             }
-            QueueContent::Loop(_total, _done, inner) => inner.is_almost_empty_int(),
         }
     }
-    pub fn len(&self) -> usize {
+    pub fn len(&self, db: &Database) -> usize {
         if !self.enabled {
             return 0;
         }
+        QueueCache::get(&self.cache.len, db, || self.len_uncached(db))
+    }
+    fn len_uncached(&self, db: &Database) -> usize {
         match &self.content {
             QueueContent::Song(_) => 1,
-            QueueContent::Folder(folder) => folder.len(),
+            QueueContent::Folder(folder) => folder.len(db),
             QueueContent::Loop(total, _done, inner) => {
                 if *total == 0 {
-                    inner.len()
+                    inner.len(db)
+                } else {
+                    total.saturating_mul(inner.len(db))
+                }
+            }
+            QueueContent::AlbumRef(album, _) => album_songs(db, album).len(),
+            QueueContent::Random(_, _, q) => q.iter().map(|v| v.len(db)).sum(),
+        }
+    }
+    /// the number of songs from the current one (including it) to the end, like `duration_remaining`.
+    /// `len - len_remaining` is the number of songs before the current one.
+    pub fn len_remaining(&self, db: &Database) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+        match &self.content {
+            QueueContent::Song(_) => 1,
+            QueueContent::Folder(folder) => folder
+                .iter()
+                .skip(folder.index)
+                .map(|v| v.len_remaining(db))
+                .sum(),
+            QueueContent::Loop(total, done, inner) => {
+                if *total == 0 {
+                    inner.len_remaining(db)
                 } else {
-                    total.saturating_mul(inner.len())
+                    inner.len_remaining(db)
+                        + total
+                            .saturating_sub(*done + 1)
+                            .saturating_mul(inner.len(db))
                 }
             }
+            QueueContent::AlbumRef(album, index) => {
+                album_songs(db, album).len().saturating_sub(*index)
+            }
+            QueueContent::Random(_, _, q) => q.iter().map(|v| v.len_remaining(db)).sum(),
         }
     }
     pub fn duration_total(&self, db: &Database) -> QueueDuration {
-        let mut dur = QueueDuration::new_total();
-        self.add_duration(&mut dur, db);
-        dur
+        QueueCache::get(&self.cache.duration_total, db, || {
+            let mut dur = QueueDuration::new_total();
+            self.add_duration(&mut dur, db);
+            dur
+        })
     }
     // remaining time, including current song
     pub fn duration_remaining(&self, db: &Database) -> QueueDuration {
@@ -130,14 +363,10 @@ impl Queue {
                 QueueContent::Song(v) => {
                     dur.millis += db.get_song(v).map(|s| s.duration_millis).unwrap_or(0)
                 }
-                QueueContent::Folder(QueueFolder {
-                    index,
-                    content,
-                    name: _,
-                    order: _,
-                }) => {
-                    for (i, inner) in content.iter().enumerate() {
-                        if dur.include_past || i >= *index {
+                QueueContent::Folder(folder) => {
+                    // `index` is a position in the folder's (maybe shuffled) order
+                    for (i, inner) in folder.iter().enumerate() {
+                        if dur.include_past || i >= folder.index {
                             inner.add_duration(dur, db);
                         }
                     }
@@ -160,82 +389,466 @@ impl Queue {
                         }
                     }
                 }
+                QueueContent::AlbumRef(album, index) => {
+                    for (i, song) in album_songs(db, album).iter().enumerate() {
+                        if dur.include_past || i >= *index {
+                            dur.millis += db.get_song(song).map(|s| s.duration_millis).unwrap_or(0);
+                        }
+                    }
+                }
+                QueueContent::Random(_, _, q) => {
+                    let mut known = QueueDuration::new(dur.include_past);
+                    for inner in q.iter() {
+                        inner.add_duration(&mut known, db);
+                    }
+                    dur.random_known_millis += known.millis;
+                    dur.random_counter += 1;
+                }
             }
         }
     }
 
     /// recursively descends the queue until the current active element is found, then returns it.
+    /// this is either a `Song` or an `AlbumRef`, use `get_current_song` to get the actual song.
+    /// disabled elements have no current element.
     pub fn get_current(&self) -> Option<&Self> {
+        if !self.enabled {
+            return None;
+        }
         match &self.content {
-            QueueContent::Song(_) => Some(self),
+            QueueContent::Song(_) | QueueContent::AlbumRef(..) => Some(self),
             QueueContent::Folder(folder) => folder.get_current_immut()?.get_current(),
             QueueContent::Loop(_, _, inner) => inner.get_current(),
+            QueueContent::Random(_, _, q) => q.front()?.get_current(),
         }
     }
-    pub fn get_current_song(&self) -> Option<&SongId> {
-        if let QueueContent::Song(id) = self.get_current()?.content() {
-            Some(id)
-        } else {
-            None
+    pub fn get_current_song<'a>(&'a self, db: &'a Database) -> Option<&'a SongId> {
+        match self.get_current()?.content() {
+            QueueContent::Song(id) => Some(id),
+            QueueContent::AlbumRef(album, index) => album_songs(db, album).get(*index),
+            QueueContent::Folder(..) | QueueContent::Loop(..) | QueueContent::Random(..) => None,
         }
     }
-    pub fn get_next_song(&self) -> Option<&SongId> {
-        if let QueueContent::Song(id) = self.get_next()?.content() {
-            Some(id)
-        } else {
-            None
+    pub fn get_next_song<'a>(&'a self, db: &'a Database) -> Option<&'a SongId> {
+        if !self.enabled {
+            return None;
         }
-    }
-    pub fn get_next(&self) -> Option<&Self> {
         match &self.content {
             QueueContent::Song(_) => None,
-            QueueContent::Folder(folder) => folder.get_next(),
+            QueueContent::Folder(folder) => folder.get_next_song(db),
             QueueContent::Loop(total, current, inner) => {
-                if let Some(v) = inner.get_next() {
+                if let Some(v) = inner.get_next_song(db) {
+                    Some(v)
+                } else if *total == 0 || *current + 1 < *total {
+                    inner.get_first_song(db)
+                } else {
+                    None
+                }
+            }
+            QueueContent::AlbumRef(album, index) => album_songs(db, album).get(*index + 1),
+            QueueContent::Random(_, _, q) => {
+                if let Some(v) = q.front()?.get_next_song(db) {
                     Some(v)
-                } else if *total == 0 || current < total {
-                    inner.get_first()
                 } else {
+                    q.get(1)?.get_first_song(db)
+                }
+            }
+        }
+    }
+    /// the songs which will be played after the current song, in order, but at most `limit`.
+    /// for `Random` elements, only the songs which were already picked are known.
+    pub fn upcoming_songs(&self, db: &Database, limit: usize) -> Vec<SongId> {
+        let mut out = vec![];
+        self.add_songs(db, &mut out, limit, false);
+        out
+    }
+    /// adds the songs in this element (if `all`) or the ones after the current song to `out`, until it contains `limit` songs
+    fn add_songs(&self, db: &Database, out: &mut Vec<SongId>, limit: usize, all: bool) {
+        if !self.enabled || out.len() >= limit {
+            return;
+        }
+        match &self.content {
+            QueueContent::Song(id) => {
+                if all {
+                    out.push(*id);
+                }
+            }
+            QueueContent::Folder(folder) => {
+                let start = if all { 0 } else { folder.index };
+                for i in start..folder.content.len() {
+                    if let Some(inner) = folder.get_at(i) {
+                        inner.add_songs(db, out, limit, all || i > folder.index);
+                    }
+                }
+            }
+            QueueContent::Loop(total, done, inner) => {
+                let mut remaining = if all {
+                    *total
+                } else {
+                    inner.add_songs(db, out, limit, false);
+                    total.saturating_sub(*done + 1)
+                };
+                // `total == 0` repeats forever
+                while (*total == 0 || remaining > 0) && out.len() < limit {
+                    let len = out.len();
+                    inner.add_songs(db, out, limit, true);
+                    if out.len() == len {
+                        break;
+                    }
+                    remaining = remaining.saturating_sub(1);
+                }
+            }
+            QueueContent::AlbumRef(album, index) => {
+                let start = if all { 0 } else { *index + 1 };
+                let songs = album_songs(db, album).iter().skip(start);
+                out.extend(songs.take(limit - out.len()));
+            }
+            QueueContent::Random(_, _, q) => {
+                for (i, inner) in q.iter().enumerate() {
+                    inner.add_songs(db, out, limit, all || i > 0);
+                }
+            }
+        }
+    }
+    /// the path of the current element, as used by `set_index_inner`, `get_item_at_index`, ...
+    /// for shuffled folders, this contains the position in the shuffled order, not the index in `content`.
+    pub fn get_current_path(&self) -> Option<Vec<usize>> {
+        if !self.enabled {
+            return None;
+        }
+        let (i, inner) = match &self.content {
+            QueueContent::Song(_) => return Some(vec![]),
+            QueueContent::Folder(folder) => (folder.index, folder.get_current_immut()?),
+            QueueContent::Loop(_, _, inner) => (0, inner.as_ref()),
+            QueueContent::AlbumRef(_, index) => return Some(vec![*index]),
+            QueueContent::Random(_, _, q) => (0, q.front()?),
+        };
+        let mut path = inner.get_current_path()?;
+        path.insert(0, i);
+        Some(path)
+    }
+    /// the path of the element which will be current after the next `advance_index_inner`.
+    /// note: since `Random` elements discard their current element when advancing,
+    /// the path is only valid until then.
+    pub fn get_next_path(&self, db: &Database) -> Option<Vec<usize>> {
+        if !self.enabled {
+            return None;
+        }
+        let (i, mut path) = match &self.content {
+            QueueContent::Song(_) => return None,
+            QueueContent::Folder(folder) => {
+                if let Some(path) = folder.get_current_immut()?.get_next_path(db) {
+                    (folder.index, path)
+                } else {
+                    let i = (folder.index + 1..folder.content.len())
+                        .find(|i| folder.get_at(*i).is_some_and(|v| !v.is_empty(db)))?;
+                    (i, folder.get_at(i)?.get_first_path(db)?)
+                }
+            }
+            QueueContent::Loop(total, current, inner) => {
+                if let Some(path) = inner.get_next_path(db) {
+                    (0, path)
+                } else if *total == 0 || *current + 1 < *total {
+                    (0, inner.get_first_path(db)?)
+                } else {
+                    return None;
+                }
+            }
+            QueueContent::AlbumRef(album, index) => {
+                return if *index + 1 < album_songs(db, album).len() {
+                    Some(vec![*index + 1])
+                } else {
+                    None
+                };
+            }
+            QueueContent::Random(_, _, q) => {
+                if let Some(path) = q.front()?.get_next_path(db) {
+                    (0, path)
+                } else {
+                    (1, q.get(1)?.get_first_path(db)?)
+                }
+            }
+        };
+        path.insert(0, i);
+        Some(path)
+    }
+    /// the path of the first song in this element, relative to this element
+    pub fn get_first_path(&self, db: &Database) -> Option<Vec<usize>> {
+        if !self.enabled {
+            return None;
+        }
+        let (i, inner) = match &self.content {
+            QueueContent::Song(_) => return Some(vec![]),
+            QueueContent::Folder(folder) => {
+                let i = (0..folder.content.len())
+                    .find(|i| folder.get_at(*i).is_some_and(|v| !v.is_empty(db)))?;
+                (i, folder.get_at(i)?)
+            }
+            QueueContent::Loop(_, _, inner) => (0, inner.as_ref()),
+            QueueContent::AlbumRef(album, _) => {
+                return if album_songs(db, album).is_empty() {
                     None
+                } else {
+                    Some(vec![0])
+                };
+            }
+            QueueContent::Random(_, _, q) => (0, q.front()?),
+        };
+        let mut path = inner.get_first_path(db)?;
+        path.insert(0, i);
+        Some(path)
+    }
+    /// Finds loops which just started a new pass and returns new orders for the shuffled folders inside of them,
+    /// so that each pass is shuffled differently. Used by the server after `NextSong`,
+    /// the results should be applied (and broadcast) using `QueueSetShuffle`.
+    /// The current element of each folder stays the first one, so the song which was already chosen as the next song doesn't change.
+    pub fn reshuffle_restarted_loops(
+        &self,
+        db: &Database,
+        rng: &mut impl Rng,
+    ) -> Vec<(Vec<usize>, Vec<usize>)> {
+        fn shuffled_folders(
+            queue: &Queue,
+            path: &mut Vec<usize>,
+            rng: &mut impl Rng,
+            out: &mut Vec<(Vec<usize>, Vec<usize>)>,
+        ) {
+            match queue.content() {
+                QueueContent::Song(_) | QueueContent::AlbumRef(..) => {}
+                QueueContent::Folder(folder) => {
+                    if folder.order.is_some() {
+                        out.push((path.clone(), folder.new_order_keep_current(rng)));
+                    }
+                    for (i, v) in folder.iter().enumerate() {
+                        path.push(i);
+                        shuffled_folders(v, path, rng, out);
+                        path.pop();
+                    }
+                }
+                QueueContent::Loop(_, _, inner) => {
+                    path.push(0);
+                    shuffled_folders(inner, path, rng, out);
+                    path.pop();
+                }
+                QueueContent::Random(_, _, q) => {
+                    for (i, v) in q.iter().enumerate() {
+                        path.push(i);
+                        shuffled_folders(v, path, rng, out);
+                        path.pop();
+                    }
+                }
+            }
+        }
+        let mut out = vec![];
+        let Some(current) = self.get_current_path() else {
+            return out;
+        };
+        for depth in 0..current.len() {
+            if let Some(QueueContent::Loop(_, done, inner)) = self
+                .get_item_at_index(&current[..depth], 0)
+                .map(|v| v.content())
+            {
+                // a new pass has started if we are at the start of the loop, but not in the first pass
+                if *done > 0 && inner.get_current_path() == inner.get_first_path(db) {
+                    let mut path = current[..=depth].to_vec();
+                    shuffled_folders(inner, &mut path, rng, &mut out);
+                    // inner loops are handled as part of this one
+                    break;
+                }
+            }
+        }
+        out
+    }
+    /// Paths (relative to this element) of songs which already appeared earlier in this element.
+    /// The current element is never included. Songs in `AlbumRef`s can't be removed, but they count as appearances.
+    /// The paths are in reverse order, so they can be removed one after the other.
+    pub fn duplicate_song_paths(&self, db: &Database) -> Vec<Vec<usize>> {
+        fn find(
+            queue: &Queue,
+            db: &Database,
+            path: &mut Vec<usize>,
+            current: Option<&[usize]>,
+            seen: &mut HashSet<SongId>,
+            out: &mut Vec<Vec<usize>>,
+        ) {
+            match queue.content() {
+                QueueContent::Song(id) => {
+                    if !seen.insert(*id) && current != Some(path.as_slice()) {
+                        out.push(path.clone());
+                    }
+                }
+                QueueContent::Folder(folder) => {
+                    for (i, queue) in folder.iter().enumerate() {
+                        path.push(i);
+                        find(queue, db, path, current, seen, out);
+                        path.pop();
+                    }
+                }
+                QueueContent::Loop(_, _, inner) => {
+                    path.push(0);
+                    find(inner, db, path, current, seen, out);
+                    path.pop();
+                }
+                QueueContent::Random(_, _, q) => {
+                    for (i, queue) in q.iter().enumerate() {
+                        path.push(i);
+                        find(queue, db, path, current, seen, out);
+                        path.pop();
+                    }
+                }
+                QueueContent::AlbumRef(album, _) => {
+                    seen.extend(album_songs(db, album).iter().copied());
+                }
+            }
+        }
+        let current = self.get_current_path();
+        let mut out = vec![];
+        find(
+            self,
+            db,
+            &mut vec![],
+            current.as_deref(),
+            &mut HashSet::new(),
+            &mut out,
+        );
+        out.reverse();
+        out
+    }
+    /// Calls `f` with the album and the index of every `AlbumRef` in this element,
+    /// see `Database::keep_album_ref_songs`.
+    pub fn for_each_album_ref_mut(&mut self, f: &mut impl FnMut(AlbumId, &mut usize)) {
+        match &mut self.content {
+            QueueContent::Song(_) => {}
+            QueueContent::Folder(folder) => {
+                for queue in folder.content.iter_mut() {
+                    queue.for_each_album_ref_mut(f);
+                }
+            }
+            QueueContent::Loop(_, _, inner) => inner.for_each_album_ref_mut(f),
+            QueueContent::Random(_, _, q) => {
+                for queue in q.iter_mut() {
+                    queue.for_each_album_ref_mut(f);
+                }
+            }
+            QueueContent::AlbumRef(album, index) => f(*album, index),
+        }
+    }
+    /// Removes songs and album references which aren't in the database (anymore), for example from a saved playlist.
+    /// Returns the number of removed elements.
+    pub fn remove_missing_songs(&mut self, db: &Database) -> usize {
+        fn find(queue: &Queue, db: &Database, path: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+            match queue.content() {
+                QueueContent::Song(id) => {
+                    if db.get_song(id).is_none() {
+                        out.push(path.clone());
+                    }
+                }
+                QueueContent::Folder(folder) => {
+                    for (i, queue) in folder.iter().enumerate() {
+                        path.push(i);
+                        find(queue, db, path, out);
+                        path.pop();
+                    }
+                }
+                QueueContent::Loop(_, _, inner) => {
+                    path.push(0);
+                    find(inner, db, path, out);
+                    path.pop();
+                }
+                QueueContent::Random(_, _, q) => {
+                    for (i, queue) in q.iter().enumerate() {
+                        path.push(i);
+                        find(queue, db, path, out);
+                        path.pop();
+                    }
+                }
+                QueueContent::AlbumRef(album, _) => {
+                    if !db.albums().contains_key(album) {
+                        out.push(path.clone());
+                    }
                 }
             }
         }
+        let mut paths = vec![];
+        find(self, db, &mut vec![], &mut paths);
+        // in reverse order, so removing one doesn't change the others' paths
+        paths
+            .iter()
+            .rev()
+            .filter(|path| self.remove_by_index(path, 0).is_some())
+            .count()
     }
-    pub fn get_first(&self) -> Option<&Self> {
+    pub fn get_first_song<'a>(&'a self, db: &'a Database) -> Option<&'a SongId> {
+        if !self.enabled {
+            return None;
+        }
         match &self.content {
-            QueueContent::Song(..) => Some(self),
-            QueueContent::Folder(folder) => folder.get_first(),
-            QueueContent::Loop(_, _, q) => q.get_first(),
+            QueueContent::Song(id) => Some(id),
+            QueueContent::Folder(folder) => (0..folder.content.len())
+                .filter_map(|i| folder.get_at(i))
+                .find(|v| !v.is_empty(db))?
+                .get_first_song(db),
+            QueueContent::Loop(_, _, q) => q.get_first_song(db),
+            QueueContent::AlbumRef(album, _) => album_songs(db, album).first(),
+            QueueContent::Random(_, _, q) => q.front()?.get_first_song(db),
         }
     }
 
     pub fn advance_index_db(db: &mut Database) -> bool {
-        let o = db.queue.advance_index_inner();
+        // take the queue out of the database so we can borrow both at the same time
+        let mut queue = std::mem::replace(
+            &mut db.queue,
+            QueueContent::Folder(QueueFolder::default()).into(),
+        );
+        let o = queue.advance_index_inner(db);
+        db.queue = queue;
         o
     }
     pub fn init(&mut self) {
         match &mut self.content {
             QueueContent::Song(..) => {}
             QueueContent::Folder(folder) => {
-                folder.index = 0;
+                // start at the first element which isn't disabled
+                folder.index = (0..folder.content.len())
+                    .find(|i| folder.get_at(*i).is_some_and(|v| !v.is_disabled()))
+                    .unwrap_or(0);
                 for v in &mut folder.content {
                     v.init();
                 }
             }
             QueueContent::Loop(_, _, inner) => inner.init(),
+            QueueContent::AlbumRef(_, index) => *index = 0,
+            QueueContent::Random(_, _, q) => {
+                if let Some(v) = q.front_mut() {
+                    v.init();
+                }
+            }
+        }
+    }
+    /// after `init`, moves past empty elements at the start, which `init` can't detect without the database
+    fn skip_empty_start(&mut self, db: &Database) {
+        if self.get_current_song(db).is_none() && !self.is_empty(db) {
+            self.advance_index_inner(db);
         }
     }
-    pub fn advance_index_inner(&mut self) -> bool {
+    pub fn advance_index_inner(&mut self, db: &Database) -> bool {
+        // `Random` discards its current element
+        self.cache.invalidate();
         match &mut self.content {
             QueueContent::Song(_) => false,
-            QueueContent::Folder(folder) => folder.advance_index_inner(),
+            QueueContent::Folder(folder) => folder.advance_index_inner(db),
             QueueContent::Loop(total, current, inner) => {
-                if inner.advance_index_inner() {
+                if inner.enabled && inner.advance_index_inner(db) {
                     true
+                } else if inner.is_empty(db) {
+                    // don't loop forever if there is nothing to play
+                    *current = 0;
+                    false
                 } else {
                     *current += 1;
                     if *total == 0 || *current < *total {
                         inner.init();
+                        inner.skip_empty_start(db);
                         true
                     } else {
                         *current = 0;
@@ -243,6 +856,31 @@ impl Queue {
                     }
                 }
             }
+            QueueContent::AlbumRef(album, index) => {
+                if *index + 1 < album_songs(db, album).len() {
+                    *index += 1;
+                    true
+                } else {
+                    *index = 0;
+                    false
+                }
+            }
+            QueueContent::Random(_, _, q) => {
+                if q.front_mut()
+                    .is_some_and(|v| v.enabled && v.advance_index_inner(db))
+                {
+                    true
+                } else if q.len() > 1 {
+                    // the current element is done, discard it
+                    q.pop_front();
+                    if let Some(v) = q.front_mut() {
+                        v.init();
+                    }
+                    true
+                } else {
+                    false
+                }
+            }
         }
     }
 
@@ -257,6 +895,7 @@ impl Queue {
         mut build_index: Vec<usize>,
         keep_child_indices: bool,
     ) {
+        self.cache.invalidate();
         let i = if let Some(i) = index.get(depth) {
             *i
         } else {
@@ -280,6 +919,19 @@ impl Queue {
                 }
                 inner.set_index_inner(index, depth + 1, build_index, keep_child_indices)
             }
+            QueueContent::AlbumRef(_, index) => *index = i,
+            QueueContent::Random(_, _, q) => {
+                // elements before the new current one have been skipped, so they are discarded
+                for _ in 0..i.min(q.len().saturating_sub(1)) {
+                    q.pop_front();
+                }
+                if let Some(c) = q.front_mut() {
+                    if !keep_child_indices {
+                        c.init();
+                    }
+                    c.set_index_inner(index, depth + 1, build_index, keep_child_indices);
+                }
+            }
         }
     }
     pub fn reset_index(&mut self) {
@@ -295,13 +947,39 @@ impl Queue {
                 *done = 0;
                 i.reset_index();
             }
+            QueueContent::AlbumRef(_, index) => *index = 0,
+            QueueContent::Random(_, _, q) => {
+                for v in q {
+                    v.reset_index();
+                }
+            }
+        }
+    }
+    /// gives every shuffled folder a new random order
+    pub fn reshuffle(&mut self, rng: &mut impl Rng) {
+        match self.content_mut() {
+            QueueContent::Song(_) | QueueContent::AlbumRef(..) => {}
+            QueueContent::Folder(folder) => {
+                if let Some(order) = &mut folder.order {
+                    order.shuffle(rng);
+                }
+                for v in &mut folder.content {
+                    v.reshuffle(rng);
+                }
+            }
+            QueueContent::Loop(_, _, i) => i.reshuffle(rng),
+            QueueContent::Random(_, _, q) => {
+                for v in q {
+                    v.reshuffle(rng);
+                }
+            }
         }
     }
 
     pub fn get_item_at_index(&self, index: &[usize], depth: usize) -> Option<&Self> {
         if let Some(i) = index.get(depth) {
             match &self.content {
-                QueueContent::Song(_) => None,
+                QueueContent::Song(_) | QueueContent::AlbumRef(..) => None,
                 QueueContent::Folder(folder) => {
                     if let Some(v) = folder.get_at(*i) {
                         v.get_item_at_index(index, depth + 1)
@@ -310,15 +988,18 @@ impl Queue {
                     }
                 }
                 QueueContent::Loop(_, _, inner) => inner.get_item_at_index(index, depth + 1),
+                QueueContent::Random(_, _, q) => q.get(*i)?.get_item_at_index(index, depth + 1),
             }
         } else {
             Some(self)
         }
     }
     pub fn get_item_at_index_mut(&mut self, index: &[usize], depth: usize) -> Option<&mut Self> {
+        // the returned element (or one of its parents) might be modified
+        self.cache.invalidate();
         if let Some(i) = index.get(depth) {
             match &mut self.content {
-                QueueContent::Song(_) => None,
+                QueueContent::Song(_) | QueueContent::AlbumRef(..) => None,
                 QueueContent::Folder(folder) => {
                     if let Some(v) = folder.get_mut_at(*i) {
                         v.get_item_at_index_mut(index, depth + 1)
@@ -327,6 +1008,9 @@ impl Queue {
                     }
                 }
                 QueueContent::Loop(_, _, inner) => inner.get_item_at_index_mut(index, depth + 1),
+                QueueContent::Random(_, _, q) => {
+                    q.get_mut(*i)?.get_item_at_index_mut(index, depth + 1)
+                }
             }
         } else {
             Some(self)
@@ -334,9 +1018,10 @@ impl Queue {
     }
 
     pub fn remove_by_index(&mut self, index: &[usize], depth: usize) -> Option<Self> {
+        self.cache.invalidate();
         if let Some(i) = index.get(depth) {
             match &mut self.content {
-                QueueContent::Song(_) => None,
+                QueueContent::Song(_) | QueueContent::AlbumRef(..) => None,
                 QueueContent::Folder(folder) => {
                     if depth + 1 < index.len() {
                         if let Some(v) = folder.get_mut_at(*i) {
@@ -376,6 +1061,13 @@ impl Queue {
                         None
                     }
                 }
+                QueueContent::Random(_, _, q) => {
+                    if depth + 1 < index.len() {
+                        q.get_mut(*i)?.remove_by_index(index, depth + 1)
+                    } else {
+                        q.remove(*i)
+                    }
+                }
             }
         } else {
             None
@@ -408,7 +1100,8 @@ impl QueueFolder {
     }
     pub fn insert(&mut self, v: Vec<Queue>, index: usize, skip_init: bool) -> bool {
         if index <= self.content.len() {
-            if self.index >= index {
+            // keep the current element the same. in an empty folder, there is no current element.
+            if self.index >= index && !self.content.is_empty() {
                 self.index += v.len();
             }
             fn insert_multiple<T>(index: usize, vec: &mut Vec<T>, v: impl IntoIterator<Item = T>) {
@@ -438,8 +1131,8 @@ impl QueueFolder {
             false
         }
     }
-    pub fn len(&self) -> usize {
-        self.content.iter().map(|v| v.len()).sum()
+    pub fn len(&self, db: &Database) -> usize {
+        self.content.iter().map(|v| v.len(db)).sum()
     }
     pub fn get_at(&self, mut i: usize) -> Option<&Queue> {
         if let Some(order) = &self.order {
@@ -459,19 +1152,15 @@ impl QueueFolder {
     pub fn get_current_mut(&mut self) -> Option<&mut Queue> {
         self.get_mut_at(self.index)
     }
-    pub fn get_next(&self) -> Option<&Queue> {
-        if let Some(v) = self.get_current_immut() {
-            if let Some(v) = v.get_next() {
-                Some(v)
-            } else {
-                if let Some(v) = self.get_at(self.index + 1) {
-                    v.get_current()
-                } else {
-                    None
-                }
-            }
+    pub fn get_next_song<'a>(&'a self, db: &'a Database) -> Option<&'a SongId> {
+        if let Some(v) = self.get_current_immut()?.get_next_song(db) {
+            Some(v)
         } else {
-            None
+            // skips disabled and empty elements, like `advance_index_inner`
+            (self.index + 1..self.content.len())
+                .filter_map(|i| self.get_at(i))
+                .find(|v| !v.is_empty(db))?
+                .get_first_song(db)
         }
     }
     pub fn get_first(&self) -> Option<&Queue> {
@@ -481,9 +1170,10 @@ impl QueueFolder {
             self.content.first()
         }
     }
-    pub fn advance_index_inner(&mut self) -> bool {
+    pub fn advance_index_inner(&mut self, db: &Database) -> bool {
         if let Some(c) = self.get_current_mut() {
-            if c.advance_index_inner() {
+            // don't advance inside of disabled elements
+            if c.enabled && c.advance_index_inner(db) {
                 // inner value could advance index, do nothing.
                 true
             } else {
@@ -491,9 +1181,13 @@ impl QueueFolder {
                     if self.index + 1 < self.content.len() {
                         // can advance
                         self.index += 1;
-                        if self.content[self.index].enabled {
-                            self.content[self.index].init();
-                            break true;
+                        // skips disabled elements, and elements with no songs, like a `Random` with no matching songs
+                        if let Some(c) = self.get_mut_at(self.index) {
+                            if !c.is_empty(db) {
+                                c.init();
+                                c.skip_empty_start(db);
+                                break true;
+                            }
                         }
                     } else {
                         // can't advance: index would be out of bounds
@@ -534,6 +1228,39 @@ impl QueueFolder {
             vec_move(&mut self.content, index_from, index_to)
         }
     }
+    /// removes all elements and resets the index
+    pub fn clear(&mut self) {
+        self.content.clear();
+        self.index = 0;
+        self.order = None;
+    }
+    /// permutes the elements themselves (unlike `order`, this can't be undone).
+    /// the current element stays at the current position, only the other elements are moved.
+    /// if the folder was shuffled using `order`, the elements are first put into that order.
+    pub fn shuffle_in_place(&mut self, rng: &mut impl Rng) {
+        let ord = self.new_order_keep_current(rng);
+        self.order = None;
+        let mut content = std::mem::take(&mut self.content)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        self.content = ord.into_iter().filter_map(|i| content[i].take()).collect();
+    }
+    /// a random order (see `order`) in which the current element stays at the current position
+    pub fn new_order_keep_current(&self, rng: &mut impl Rng) -> Vec<usize> {
+        let mut ord = self
+            .order
+            .clone()
+            .unwrap_or_else(|| (0..self.content.len()).collect());
+        if self.index < ord.len() {
+            let current = ord.remove(self.index);
+            ord.shuffle(rng);
+            ord.insert(self.index, current);
+        } else {
+            ord.shuffle(rng);
+        }
+        ord
+    }
 }
 pub struct QueueFolderIter<'a> {
     folder: &'a QueueFolder,
@@ -551,11 +1278,53 @@ impl<'a> Iterator for QueueFolderIter<'a> {
     }
 }
 
+impl QueueContent {
+    /// for loops: the number of passes which have been completed
+    pub fn loop_iterations_done(&self) -> Option<usize> {
+        match self {
+            Self::Loop(_, done, _) => Some(*done),
+            _ => None,
+        }
+    }
+    /// for loops: the total number of passes, `Some(None)` if the loop repeats forever
+    pub fn loop_total(&self) -> Option<Option<usize>> {
+        match self {
+            Self::Loop(total, _, _) => Some(if *total == 0 { None } else { Some(*total) }),
+            _ => None,
+        }
+    }
+    /// for loops: the number of passes left, including the current one.
+    /// `Some(None)` if the loop repeats forever.
+    pub fn loop_remaining(&self) -> Option<Option<usize>> {
+        match self {
+            Self::Loop(total, done, _) => Some(if *total == 0 {
+                None
+            } else {
+                Some(total.saturating_sub(*done))
+            }),
+            _ => None,
+        }
+    }
+    /// for loops: makes the current pass the last one (also works for infinite loops).
+    /// returns false if this isn't a loop.
+    pub fn loop_break(&mut self) -> bool {
+        match self {
+            Self::Loop(total, done, _) => {
+                *total = *done + 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 impl From<QueueContent> for Queue {
     fn from(value: QueueContent) -> Self {
         Self {
             enabled: true,
+            label: None,
             content: value,
+            cache: Default::default(),
         }
     }
 }
@@ -565,7 +1334,14 @@ impl ToFromBytes for Queue {
     where
         T: std::io::Write,
     {
-        s.write_all(&[if self.enabled { 0b11111111 } else { 0b00000000 }])?;
+        // if there is a label, the lowest bit is flipped, so data without labels is encoded the same as before
+        let flags = if self.enabled { 0b11111111 } else { 0b00000000 };
+        if let Some(label) = &self.label {
+            s.write_all(&[flags ^ 0b00000001])?;
+            label.to_bytes(s)?;
+        } else {
+            s.write_all(&[flags])?;
+        }
         self.content.to_bytes(s)?;
         Ok(())
     }
@@ -573,11 +1349,19 @@ impl ToFromBytes for Queue {
     where
         T: std::io::Read,
     {
-        let mut enabled = [0];
-        s.read_exact(&mut enabled)?;
+        let mut flags = [0];
+        s.read_exact(&mut flags)?;
+        let [flags] = flags;
+        let label = if (flags & 0b00000001) != (flags >> 7) {
+            Some(ToFromBytes::from_bytes(s)?)
+        } else {
+            None
+        };
         Ok(Self {
-            enabled: enabled[0].count_ones() >= 4,
+            enabled: flags.count_ones() >= 4,
+            label,
             content: ToFromBytes::from_bytes(s)?,
+            cache: Default::default(),
         })
     }
 }
@@ -602,6 +1386,17 @@ impl ToFromBytes for QueueContent {
                 current.to_bytes(s)?;
                 inner.to_bytes(s)?;
             }
+            Self::AlbumRef(album, index) => {
+                s.write_all(&[0b00110000])?;
+                album.to_bytes(s)?;
+                index.to_bytes(s)?;
+            }
+            Self::Random(filter, weighting, q) => {
+                s.write_all(&[0b00001100])?;
+                filter.to_bytes(s)?;
+                weighting.to_bytes(s)?;
+                q.to_bytes(s)?;
+            }
         }
         Ok(())
     }
@@ -619,6 +1414,12 @@ impl ToFromBytes for QueueContent {
                 ToFromBytes::from_bytes(s)?,
                 Box::new(ToFromBytes::from_bytes(s)?),
             ),
+            0b00110000 => Self::AlbumRef(ToFromBytes::from_bytes(s)?, ToFromBytes::from_bytes(s)?),
+            0b00001100 => Self::Random(
+                ToFromBytes::from_bytes(s)?,
+                ToFromBytes::from_bytes(s)?,
+                ToFromBytes::from_bytes(s)?,
+            ),
             _ => Self::Folder(QueueFolder {
                 index: 0,
                 content: vec![],
@@ -653,7 +1454,183 @@ impl ToFromBytes for QueueFolder {
     }
 }
 
-#[derive(Clone, Copy)]
+/// the songs of an album, or an empty slice if the album doesn't exist (anymore)
+fn album_songs<'a>(db: &'a Database, album: &AlbumId) -> &'a [SongId] {
+    db.albums()
+        .get(album)
+        .map(|a| a.songs.as_slice())
+        .unwrap_or(&[])
+}
+
+impl RandomFilter {
+    pub fn matches(&self, song: &Song) -> bool {
+        match self {
+            Self::All => true,
+            Self::TagEq(v) => song.general.tags.iter().any(|t| t == v),
+            Self::TagWithValueInt(v, min, max) => song.general.tags.iter().any(|t| {
+                t.strip_prefix(v.as_str())
+                    .and_then(|val| val.parse().ok())
+                    .is_some_and(|val: i32| *min <= val && val <= *max)
+            }),
+            Self::Artist(id) => song.artist == *id || song.more_artists.contains(id),
+            Self::Album(id) => song.album == Some(*id),
+        }
+    }
+    /// a short description like "tagged Fav" or "by <artist>", empty for `All`.
+    pub fn describe(&self, db: &Database) -> String {
+        match self {
+            Self::All => String::new(),
+            Self::TagEq(tag) => format!("tagged {tag}"),
+            Self::TagWithValueInt(key, min, max) => format!("with {key}{min}..{max}"),
+            Self::Artist(id) => match db.artists().get(id) {
+                Some(artist) => format!("by {}", artist.name),
+                None => "by < unknown artist >".to_owned(),
+            },
+            Self::Album(id) => match db.albums().get(id) {
+                Some(album) => format!("from {}", album.name),
+                None => "from < unknown album >".to_owned(),
+            },
+        }
+    }
+}
+impl RandomWeighting {
+    /// `play_count` is the number of times the song was played
+    pub fn weight(&self, song: &Song, play_count: u64) -> f64 {
+        match self {
+            Self::Uniform => 1.0,
+            Self::PreferUnplayed => 1.0 / (1 + play_count) as f64,
+            Self::PreferFavorites => {
+                if song.general.tags.iter().any(|t| t == "Fav") {
+                    4.0
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Uniform => "",
+            Self::PreferUnplayed => "(prefer unplayed)",
+            Self::PreferFavorites => "(prefer favorites)",
+        }
+    }
+}
+impl ToFromBytes for RandomWeighting {
+    fn to_bytes<T>(&self, s: &mut T) -> Result<(), std::io::Error>
+    where
+        T: std::io::Write,
+    {
+        s.write_all(&[match self {
+            Self::Uniform => 0b00000000,
+            Self::PreferUnplayed => 0b00000001,
+            Self::PreferFavorites => 0b00000010,
+        }])
+    }
+    fn from_bytes<T>(s: &mut T) -> Result<Self, std::io::Error>
+    where
+        T: std::io::Read,
+    {
+        let mut b = [0];
+        s.read_exact(&mut b)?;
+        Ok(match b[0] {
+            0b00000001 => Self::PreferUnplayed,
+            0b00000010 => Self::PreferFavorites,
+            _ => Self::Uniform,
+        })
+    }
+}
+impl ToFromBytes for RandomFilter {
+    fn to_bytes<T>(&self, s: &mut T) -> Result<(), std::io::Error>
+    where
+        T: std::io::Write,
+    {
+        match self {
+            Self::All => s.write_all(&[0b00000000])?,
+            Self::TagEq(tag) => {
+                s.write_all(&[0b00000001])?;
+                tag.to_bytes(s)?;
+            }
+            Self::TagWithValueInt(key, min, max) => {
+                s.write_all(&[0b00000010])?;
+                key.to_bytes(s)?;
+                min.to_bytes(s)?;
+                max.to_bytes(s)?;
+            }
+            Self::Artist(id) => {
+                s.write_all(&[0b00000100])?;
+                id.to_bytes(s)?;
+            }
+            Self::Album(id) => {
+                s.write_all(&[0b00001000])?;
+                id.to_bytes(s)?;
+            }
+        }
+        Ok(())
+    }
+    fn from_bytes<T>(s: &mut T) -> Result<Self, std::io::Error>
+    where
+        T: std::io::Read,
+    {
+        let mut switch_on = [0];
+        s.read_exact(&mut switch_on)?;
+        Ok(match switch_on[0] {
+            0b00000001 => Self::TagEq(ToFromBytes::from_bytes(s)?),
+            0b00000010 => Self::TagWithValueInt(
+                ToFromBytes::from_bytes(s)?,
+                ToFromBytes::from_bytes(s)?,
+                ToFromBytes::from_bytes(s)?,
+            ),
+            0b00000100 => Self::Artist(ToFromBytes::from_bytes(s)?),
+            0b00001000 => Self::Album(ToFromBytes::from_bytes(s)?),
+            _ => Self::All,
+        })
+    }
+}
+
+/// Chooses a random song, trying not to pick one of the songs in `recent`.
+/// `recent` should be ordered from oldest to newest.
+/// If the library is too small to avoid all of them, only the newest `songs.len() / 2` entries are avoided.
+/// The probability of a song being chosen is proportional to its `weight`.
+pub fn pick_random_song(
+    songs: &[SongId],
+    recent: &[SongId],
+    weight: impl Fn(SongId) -> f64,
+    rng: &mut impl Rng,
+) -> Option<SongId> {
+    let window = recent.len().min(songs.len() / 2);
+    let recent = &recent[recent.len() - window..];
+    let mut candidates = songs
+        .iter()
+        .filter(|id| !recent.contains(id))
+        .copied()
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        // can only happen if `recent` contains duplicates
+        candidates = songs.to_vec();
+    }
+    let weights = candidates
+        .iter()
+        .map(|id| weight(*id).max(0.0))
+        .collect::<Vec<_>>();
+    let total = weights.iter().sum::<f64>();
+    if total <= 0.0 || !total.is_finite() {
+        return candidates
+            .get(rng.gen_range(0..candidates.len().max(1)))
+            .copied();
+    }
+    let mut r = rng.gen_range(0.0..total);
+    for (id, w) in candidates.iter().zip(weights) {
+        if r < w {
+            return Some(*id);
+        }
+        r -= w;
+    }
+    // rounding errors
+    candidates.last().copied()
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct QueueDuration {
     pub include_past: bool,
     pub infinite: bool,
@@ -691,3 +1668,726 @@ impl AddAssign<QueueDuration> for QueueDuration {
         self.random_counter += rhs.random_counter;
     }
 }
+
+/// a queue element for the song, for tests
+#[cfg(test)]
+pub fn song(id: SongId) -> Queue {
+    QueueContent::Song(id).into()
+}
+/// a folder for tests, which plays `content` in order, starting at the first element
+#[cfg(test)]
+pub fn folder(content: Vec<Queue>) -> Queue {
+    folder_at(0, content, None)
+}
+/// a folder for tests, which is at `index` and plays `content` in the given `order`
+#[cfg(test)]
+pub fn folder_at(index: usize, content: Vec<Queue>, order: Option<Vec<usize>>) -> Queue {
+    QueueContent::Folder(QueueFolder {
+        index,
+        content,
+        name: String::new(),
+        order,
+    })
+    .into()
+}
+
+#[test]
+fn test_pick_random_song() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(1833);
+    let songs = (0..10).collect::<Vec<SongId>>();
+    let mut recent = vec![];
+    for _ in 0..200 {
+        let id = pick_random_song(&songs, &recent, |_| 1.0, &mut rng).unwrap();
+        assert!(!recent.contains(&id), "{id} was picked again too soon");
+        recent.push(id);
+        if recent.len() > 4 {
+            recent.remove(0);
+        }
+    }
+    // window larger than half the library: only the newest 5 songs are excluded
+    let recent = (0..10).collect::<Vec<SongId>>();
+    for _ in 0..100 {
+        assert!(pick_random_song(&songs, &recent, |_| 1.0, &mut rng).unwrap() < 5);
+    }
+    // a single song has to repeat
+    assert_eq!(pick_random_song(&[7], &[7], |_| 1.0, &mut rng), Some(7));
+    assert_eq!(pick_random_song(&[], &[1, 2], |_| 1.0, &mut rng), None);
+}
+
+#[test]
+fn test_pick_random_song_weighted() {
+    use crate::test_util::test_song;
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(1845);
+    // songs 0..10 are favorites, songs 0..50 have been played 3 times
+    let songs = (0..100)
+        .map(|id| {
+            let mut song = test_song("", None, 0, 0);
+            song.id = id;
+            if id < 10 {
+                song.general.tags.push("Fav".to_owned());
+            }
+            song
+        })
+        .collect::<Vec<_>>();
+    let ids = songs.iter().map(|s| s.id).collect::<Vec<_>>();
+    let play_count = |id: SongId| if id < 50 { 3 } else { 0 };
+    let count = |weighting: RandomWeighting, rng: &mut StdRng, range: std::ops::Range<SongId>| {
+        (0..10000)
+            .filter(|_| {
+                let id = pick_random_song(
+                    &ids,
+                    &[],
+                    |id| weighting.weight(&songs[id as usize], play_count(id)),
+                    rng,
+                )
+                .unwrap();
+                range.contains(&id)
+            })
+            .count()
+    };
+    // uniform: 10% favorites, 50% played
+    let n = count(RandomWeighting::Uniform, &mut rng, 0..10);
+    assert!((800..1200).contains(&n), "{n}");
+    let n = count(RandomWeighting::Uniform, &mut rng, 0..50);
+    assert!((4600..5400).contains(&n), "{n}");
+    // favorites have 4x the weight: 40 / (40 + 90) = ~31%
+    let n = count(RandomWeighting::PreferFavorites, &mut rng, 0..10);
+    assert!((2700..3500).contains(&n), "{n}");
+    // played songs have 1/4 the weight: 12.5 / (12.5 + 50) = 20%
+    let n = count(RandomWeighting::PreferUnplayed, &mut rng, 0..50);
+    assert!((1600..2400).contains(&n), "{n}");
+}
+
+#[test]
+fn test_queue_clear_and_shuffle_in_place() {
+    use crate::server::Action;
+    fn songs(q: &Queue) -> Vec<SongId> {
+        match q.content() {
+            QueueContent::Folder(f) => f
+                .content
+                .iter()
+                .map(|v| match v.content() {
+                    QueueContent::Song(id) => *id,
+                    _ => panic!("expected a song"),
+                })
+                .collect(),
+            _ => panic!("expected a folder"),
+        }
+    }
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    db.queue = folder_at(
+        1,
+        vec![
+            song(0),
+            folder(vec![
+                folder_at(2, (10..14).map(song).collect(), None),
+                folder((20..40).map(song).collect()),
+            ]),
+        ],
+        None,
+    );
+    // shuffle [1, 1] - current element (20, at index 0) must stay where it is
+    for _ in 0..10 {
+        db.apply_action_unchecked_seq(Action::QueueShuffleInPlace(vec![1, 1]), None);
+        let f = db.queue.get_item_at_index(&[1, 1], 0).unwrap();
+        let s = songs(f);
+        assert_eq!(s[0], 20);
+        let mut sorted = s.clone();
+        sorted.sort();
+        assert_eq!(sorted, (20..40).collect::<Vec<_>>());
+    }
+    assert!(!db.queue.is_current(&[1, 1, 0]));
+    // shuffle [1, 0] - current element (12) stays at index 2
+    db.apply_action_unchecked_seq(Action::QueueShuffleInPlace(vec![1, 0]), None);
+    let f = db.queue.get_item_at_index(&[1, 0], 0).unwrap();
+    assert_eq!(songs(f)[2], 12);
+    assert!(db.queue.is_current(&[1, 0, 2]));
+    // clear [1, 0] - only that folder is emptied
+    db.apply_action_unchecked_seq(Action::QueueClear(vec![1, 0]), None);
+    let f = db.queue.get_item_at_index(&[1, 0], 0).unwrap();
+    assert!(songs(f).is_empty());
+    match f.content() {
+        QueueContent::Folder(f) => assert_eq!(f.index, 0),
+        _ => unreachable!(),
+    }
+    assert_eq!(
+        songs(db.queue.get_item_at_index(&[1, 1], 0).unwrap()).len(),
+        20
+    );
+    // clearing a song does nothing
+    db.apply_action_unchecked_seq(Action::QueueClear(vec![0]), None);
+    assert_eq!(db.queue.len(&db), 21);
+}
+
+#[test]
+fn test_loop_accessors_and_break() {
+    let mut q = QueueContent::Loop(5, 1, Box::new(QueueContent::Song(0).into()));
+    assert_eq!(q.loop_iterations_done(), Some(1));
+    assert_eq!(q.loop_total(), Some(Some(5)));
+    assert_eq!(q.loop_remaining(), Some(Some(4)));
+    assert!(q.loop_break());
+    assert_eq!(q.loop_total(), Some(Some(2)));
+    assert_eq!(q.loop_remaining(), Some(Some(1)));
+    let mut q = QueueContent::Loop(0, 7, Box::new(QueueContent::Song(0).into()));
+    assert_eq!(q.loop_total(), Some(None));
+    assert_eq!(q.loop_remaining(), Some(None));
+    assert!(q.loop_break());
+    assert_eq!(q.loop_remaining(), Some(Some(1)));
+    let mut q = QueueContent::Song(0);
+    assert_eq!(q.loop_remaining(), None);
+    assert!(!q.loop_break());
+}
+
+#[test]
+fn test_folder_insert_multiple() {
+    let songs = |ids: std::ops::Range<SongId>| {
+        ids.map(|id| QueueContent::Song(id).into())
+            .collect::<Vec<Queue>>()
+    };
+    let mut folder = QueueFolder::default();
+    assert!(folder.insert(songs(0..3), 0, true));
+    assert_eq!(folder.index, 0);
+    folder.index = 1;
+    // before the current element
+    assert!(folder.insert(songs(10..12), 1, true));
+    assert_eq!(folder.index, 3);
+    // after the current element
+    assert!(folder.insert(songs(20..22), 4, true));
+    assert_eq!(folder.index, 3);
+    assert!(!folder.insert(songs(30..31), 100, true));
+    let ids = folder
+        .iter()
+        .map(|q| match q.content() {
+            QueueContent::Song(id) => *id,
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![0, 10, 11, 1, 20, 21, 2]);
+    assert!(matches!(
+        folder.get_current_immut().map(|q| q.content()),
+        Some(QueueContent::Song(1))
+    ));
+}
+
+#[test]
+fn test_current_and_next_path() {
+    fn songs(ids: std::ops::Range<SongId>) -> Vec<Queue> {
+        ids.map(song).collect()
+    }
+    fn loop_progress(q: &Queue) -> bool {
+        match q.content() {
+            QueueContent::Song(_) | QueueContent::AlbumRef(..) => false,
+            QueueContent::Folder(f) => f.content.iter().any(loop_progress),
+            QueueContent::Loop(_, done, inner) => *done > 0 || loop_progress(inner),
+            QueueContent::Random(_, _, q) => q.iter().any(loop_progress),
+        }
+    }
+    let mut disabled = song(99);
+    disabled.enabled = false;
+    let queues = vec![
+        folder(songs(0..4)),
+        folder_at(0, songs(0..4), Some(vec![2, 0, 3, 1])),
+        folder(vec![
+            folder(songs(0..2)),
+            disabled,
+            QueueContent::Loop(
+                2,
+                0,
+                Box::new(folder_at(0, songs(10..13), Some(vec![1, 2, 0]))),
+            )
+            .into(),
+            folder(vec![]),
+            folder_at(
+                0,
+                vec![
+                    folder(songs(20..22)),
+                    folder_at(0, songs(30..33), Some(vec![2, 1, 0])),
+                ],
+                Some(vec![1, 0]),
+            ),
+        ]),
+    ];
+    for queue in queues {
+        let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+        db.queue = queue;
+        db.queue.init();
+        let mut expected_next = db.queue.get_first_path(&db);
+        let upcoming = db.queue.upcoming_songs(&db, usize::MAX);
+        assert_eq!(db.queue.upcoming_songs(&db, 2), upcoming[..2]);
+        let mut played = vec![];
+        let mut steps = 0;
+        loop {
+            let path = db.queue.get_current_path().unwrap();
+            assert_eq!(Some(&path), expected_next.as_ref());
+            assert_eq!(db.queue.len(&db) - db.queue.len_remaining(&db), steps);
+            assert!(db.queue.is_current(&path));
+            let song = db.queue.get_current_song(&db).copied();
+            assert_eq!(
+                db.queue.get_item_at_index(&path, 0).map(|v| v.content()),
+                song.map(QueueContent::Song).as_ref()
+            );
+            // going to the current path should not change anything,
+            // except for loop counters, which are reset by `set_index_db`.
+            let before = db.queue.clone();
+            Queue::set_index_db(&mut db, &path);
+            assert_eq!(db.queue.get_current_path(), Some(path));
+            assert_eq!(db.queue.get_current_song(&db).copied(), song);
+            if !loop_progress(&before) {
+                assert_eq!(db.queue, before);
+            }
+            db.queue = before;
+            if steps > 0 {
+                played.extend(song);
+            }
+            assert_eq!(
+                db.queue.upcoming_songs(&db, 1).first(),
+                db.queue.get_next_song(&db)
+            );
+            expected_next = db.queue.get_next_path(&db);
+            if !Queue::advance_index_db(&mut db) {
+                assert_eq!(expected_next, None);
+                break;
+            }
+            steps += 1;
+        }
+        assert_eq!(played, upcoming);
+        assert!(steps >= 3);
+    }
+}
+
+#[test]
+fn test_cached_len_and_duration() {
+    use super::album::Album;
+    use crate::{
+        server::{Action, Req},
+        test_util::test_song,
+    };
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    let album = db.add_album_new(Album {
+        id: 0,
+        name: String::new(),
+        artist: 0,
+        cover: None,
+        songs: vec![],
+        general: Default::default(),
+    });
+    let add_song = |db: &mut Database, album| db.add_song_new(test_song("", album, 0, 1000));
+    let songs = (0..5).map(|_| add_song(&mut db, None)).collect::<Vec<_>>();
+    add_song(&mut db, Some(album));
+    // compares the cached values to those of a freshly deserialized queue
+    fn check(db: &Database) {
+        let fresh = Queue::from_bytes(&mut &db.queue.to_bytes_vec()[..]).unwrap();
+        assert_eq!(db.queue.len(db), fresh.len(db));
+        assert_eq!(
+            db.queue.duration_total(db).millis,
+            fresh.duration_total(db).millis
+        );
+        assert_eq!(
+            db.queue.duration_total(db).random_known_millis,
+            fresh.duration_total(db).random_known_millis
+        );
+    }
+    let folder = |songs: &[SongId]| -> Queue {
+        QueueContent::Folder(QueueFolder {
+            index: 0,
+            content: songs
+                .iter()
+                .map(|id| QueueContent::Song(*id).into())
+                .collect(),
+            name: String::new(),
+            order: None,
+        })
+        .into()
+    };
+    db.queue = folder(&songs[0..2]);
+    check(&db);
+    assert_eq!(db.queue.len(&db), 2);
+    let actions = vec![
+        Action::QueueAdd(vec![], vec![folder(&songs[2..5])], Req::none()),
+        Action::QueueInsert(vec![2], 1, vec![folder(&songs[0..1])], Req::none()),
+        Action::QueueAdd(
+            vec![],
+            vec![
+                QueueContent::AlbumRef(album, 0).into(),
+                QueueContent::Random(
+                    RandomFilter::All,
+                    RandomWeighting::Uniform,
+                    Default::default(),
+                )
+                .into(),
+            ],
+            Req::none(),
+        ),
+        Action::QueueAdd(vec![4], vec![folder(&songs[0..3])], Req::none()),
+        Action::QueueAdd(vec![4], vec![folder(&songs[3..5])], Req::none()),
+        Action::QueueRemove(vec![2, 0]),
+        Action::QueueMoveInto(vec![0], vec![2]),
+        Action::QueueLoopBreak(vec![0]),
+        Action::QueueGoto(vec![3, 1]),
+        Action::NextSong,
+        Action::NextSong,
+        Action::QueueDuplicate(vec![1]),
+        Action::QueueClear(vec![1]),
+    ];
+    for action in actions {
+        db.apply_action_unchecked_seq(action, None);
+        check(&db);
+    }
+    // library changes are detected too
+    let len = db.queue.len(&db);
+    add_song(&mut db, Some(album));
+    assert_eq!(db.queue.len(&db), len + 1);
+    let duration = db.queue.duration_total(&db).millis;
+    db.apply_action_unchecked_seq(Action::SetSongDuration(songs[0], 3000), None);
+    assert!(db.queue.duration_total(&db).millis > duration);
+    check(&db);
+}
+
+#[test]
+fn test_reshuffle_restarted_loops() {
+    use crate::server::Action;
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(1843);
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    let order = (0..8).rev().collect::<Vec<usize>>();
+    db.queue = QueueContent::Loop(
+        3,
+        0,
+        Box::new(
+            QueueContent::Folder(QueueFolder {
+                index: 0,
+                content: (0..8).map(|id| QueueContent::Song(id).into()).collect(),
+                name: String::new(),
+                order: Some(order.clone()),
+            })
+            .into(),
+        ),
+    )
+    .into();
+    let get_order = |db: &Database| match db.queue.get_item_at_index(&[0], 0).unwrap().content() {
+        QueueContent::Folder(folder) => folder.order.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    let mut orders = vec![order];
+    for pass in 0..3 {
+        for i in 0..8 {
+            assert_eq!(db.queue.get_current_path(), Some(vec![0, i]));
+            // nothing to do in the middle of a pass, or in the first pass
+            let new = db.queue.reshuffle_restarted_loops(&db, &mut rng);
+            if pass == 0 || i > 0 {
+                assert!(new.is_empty());
+            } else {
+                assert_eq!(new.len(), 1);
+                let (path, ord) = new.into_iter().next().unwrap();
+                assert_eq!(path, vec![0]);
+                let song = db.queue.get_current_song(&db).copied();
+                db.apply_action_unchecked_seq(Action::QueueSetShuffle(path, ord), None);
+                // the song which was already chosen stays the current one
+                assert_eq!(db.queue.get_current_song(&db).copied(), song);
+                assert_eq!(db.queue.get_current_path(), Some(vec![0, 0]));
+                orders.push(get_order(&db));
+            }
+            assert_eq!(Queue::advance_index_db(&mut db), pass < 2 || i < 7);
+        }
+    }
+    assert_eq!(orders.len(), 3);
+    for ord in &orders {
+        let mut sorted = ord.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+        assert_eq!(ord[0], orders[0][0]);
+    }
+    assert_ne!(orders[0], orders[1]);
+    assert_ne!(orders[1], orders[2]);
+}
+
+#[test]
+fn test_dedup_songs() {
+    use crate::server::{Action, Req};
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    db.queue = folder(vec![
+        folder(vec![song(1), song(2)]),
+        // [1, 1] is the current element and must not be removed
+        folder_at(1, vec![song(3), song(1), song(2), song(3)], None),
+        QueueContent::Loop(2, 0, Box::new(folder(vec![song(2), song(4)]))).into(),
+    ]);
+    Queue::set_index_db(&mut db, &[1, 1]);
+    assert_eq!(
+        db.queue.duplicate_song_paths(&db),
+        vec![vec![2, 0, 0], vec![1, 3], vec![1, 2]]
+    );
+    db.apply_action_unchecked_seq(Action::QueueDedupSongs(vec![]), None);
+    assert_eq!(
+        db.queue,
+        folder_at(
+            1,
+            vec![
+                folder(vec![song(1), song(2)]),
+                folder_at(1, vec![song(3), song(1)], None),
+                QueueContent::Loop(2, 0, Box::new(folder(vec![song(4)]))).into(),
+            ],
+            None,
+        )
+    );
+    assert_eq!(db.queue.get_current_path(), Some(vec![1, 1]));
+    assert!(db.queue.duplicate_song_paths(&db).is_empty());
+    // only the element at the path is deduplicated
+    db.apply_action_unchecked_seq(
+        Action::QueueAdd(vec![0], vec![song(1), song(1), song(4)], Req::none()),
+        None,
+    );
+    db.apply_action_unchecked_seq(Action::QueueDedupSongs(vec![0]), None);
+    assert_eq!(db.queue.len(&db), 7);
+}
+
+#[test]
+fn test_insert_after_current() {
+    let db = Database::new_clientside();
+    // nested, in a loop, in a shuffled folder
+    let mut queue = folder_at(
+        1,
+        vec![
+            song(0),
+            QueueContent::Loop(
+                2,
+                0,
+                Box::new(folder_at(
+                    0,
+                    vec![song(1), song(2), song(3)],
+                    Some(vec![2, 0, 1]),
+                )),
+            )
+            .into(),
+            song(4),
+        ],
+        None,
+    );
+    assert!(queue.insert_after_current(song(10)));
+    assert_eq!(queue.get_current_path(), Some(vec![1, 0, 0]));
+    assert_eq!(queue.get_next_song(&db), Some(&10));
+    // inside a random element, insert after the random element
+    let mut queue = folder_at(
+        0,
+        vec![
+            QueueContent::Random(
+                RandomFilter::All,
+                RandomWeighting::Uniform,
+                [song(1), song(2)].into_iter().collect(),
+            )
+            .into(),
+            song(3),
+        ],
+        None,
+    );
+    assert!(queue.insert_after_current(song(10)));
+    assert_eq!(queue.get_item_at_index(&[1], 0), Some(&song(10)));
+    // empty root, append
+    let mut queue = folder_at(0, vec![], None);
+    assert!(queue.insert_after_current(song(10)));
+    assert_eq!(queue, folder_at(0, vec![song(10)], None));
+    assert!(!song(0).insert_after_current(song(10)));
+}
+
+#[test]
+fn test_disabled_elements() {
+    use crate::server::Action;
+    fn off(mut q: Queue) -> Queue {
+        q.enabled = false;
+        q
+    }
+    // plays the whole queue, checking that `get_next_song` and `get_next_path` always match what's played next
+    fn play(mut queue: Queue, db: &Database) -> Vec<SongId> {
+        queue.init();
+        let mut played = vec![];
+        loop {
+            played.extend(queue.get_current_song(db).copied());
+            let next_song = queue.get_next_song(db).copied();
+            let next_path = queue.get_next_path(db);
+            if !queue.advance_index_inner(db) {
+                assert_eq!(next_song, None);
+                assert_eq!(next_path, None);
+                break;
+            }
+            assert_eq!(queue.get_current_song(db).copied(), next_song);
+            assert_eq!(queue.get_current_path(), next_path);
+            assert!(queue.is_enabled_at(&queue.get_current_path().unwrap()));
+        }
+        assert_eq!(played.len(), queue.len(db));
+        played
+    }
+    let db = Database::new_clientside();
+    for (queue, expected) in [
+        (folder(vec![off(song(0)), song(1), song(2)]), vec![1, 2]),
+        (
+            folder(vec![song(0), off(song(1)), off(song(2)), song(3)]),
+            vec![0, 3],
+        ),
+        (folder(vec![song(0), song(1), off(song(2))]), vec![0, 1]),
+        (
+            folder(vec![
+                folder(vec![off(song(0)), song(1)]),
+                off(folder(vec![song(2)])),
+                folder(vec![folder(vec![off(song(3))]), song(4)]),
+                off(song(5)),
+            ]),
+            vec![1, 4],
+        ),
+        (
+            QueueContent::Loop(2, 0, Box::new(folder(vec![song(0), off(song(1))]))).into(),
+            vec![0, 0],
+        ),
+        (
+            folder(vec![
+                off(QueueContent::Loop(2, 0, Box::new(folder(vec![song(0)]))).into()),
+                song(1),
+            ]),
+            vec![1],
+        ),
+        (
+            folder(vec![folder(vec![folder(vec![off(song(0))])]), song(1)]),
+            vec![1],
+        ),
+        (
+            folder_at(0, vec![song(0), off(song(1)), song(2)], Some(vec![2, 0, 1])),
+            vec![2, 0],
+        ),
+        (
+            folder_at(0, vec![song(0), song(1), off(song(2))], Some(vec![2, 0, 1])),
+            vec![0, 1],
+        ),
+        (folder(vec![off(song(0)), off(song(1))]), vec![]),
+    ] {
+        assert_eq!(play(queue, &db), expected);
+    }
+    // can't go to a disabled element
+    let mut db = Database::new_clientside();
+    db.queue = folder(vec![song(0), off(folder(vec![song(1)])), song(2)]);
+    db.apply_action_unchecked_seq(Action::QueueGoto(vec![1, 0]), None);
+    assert_eq!(db.queue.get_current_path(), Some(vec![0]));
+    db.apply_action_unchecked_seq(Action::QueueGoto(vec![2]), None);
+    assert_eq!(db.queue.get_current_path(), Some(vec![2]));
+}
+
+#[test]
+fn test_autoplay_on_empty() {
+    use crate::{
+        server::{Action, Req},
+        test_util::test_song,
+    };
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    // makes `db` act like a server
+    let (sender, _receiver) = std::sync::mpsc::channel();
+    db.command_sender = Some(sender);
+    let songs = [0, 0, 1]
+        .into_iter()
+        .map(|artist| db.add_song_new(test_song("", None, artist, 1000)))
+        .collect::<Vec<_>>();
+    let add_song = |db: &mut Database| {
+        db.apply_action_unchecked_seq(
+            Action::QueueAdd(
+                vec![],
+                vec![QueueContent::Song(songs[0]).into()],
+                Req::none(),
+            ),
+            None,
+        );
+    };
+    // disabled: stop at the end
+    add_song(&mut db);
+    db.apply_action_unchecked_seq(Action::Resume, None);
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    assert!(db.queue_finished);
+    assert!(!db.playing);
+    // stopped: don't start playing again
+    db.apply_action_unchecked_seq(Action::SetAutoplayOnEmpty(true), None);
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    assert!(db.queue_finished);
+    assert_eq!(db.queue.len(&db), 1);
+    // playing: continue with songs by the same artist
+    db.apply_action_unchecked_seq(Action::Resume, None);
+    db.apply_action_unchecked_seq(Action::NextSong, None);
+    assert!(db.playing);
+    assert!(!db.queue_finished);
+    assert_eq!(db.queue.get_current_path(), Some(vec![1, 0]));
+    assert!(matches!(
+        db.queue.get_item_at_index(&[1], 0).map(|v| v.content()),
+        Some(QueueContent::Random(RandomFilter::Artist(0), _, q)) if q.len() == 2
+    ));
+    assert_eq!(
+        db.get_song(db.queue.get_current_song(&db).unwrap())
+            .unwrap()
+            .artist,
+        0
+    );
+}
+
+#[test]
+fn test_skip_empty_elements() {
+    fn repeat(total: usize, inner: Queue) -> Queue {
+        QueueContent::Loop(total, 0, Box::new(inner)).into()
+    }
+    // plays the whole queue, returns `None` if it doesn't end
+    fn play(mut queue: Queue, db: &Database) -> Option<Vec<SongId>> {
+        queue.init();
+        let mut played = vec![];
+        for _ in 0..100 {
+            played.extend(queue.get_current_song(db).copied());
+            let next_song = queue.get_next_song(db).copied();
+            if !queue.advance_index_inner(db) {
+                assert_eq!(next_song, None);
+                return Some(played);
+            }
+            assert_eq!(queue.get_current_song(db).copied(), next_song);
+        }
+        None
+    }
+    let db = Database::new_clientside();
+    for (queue, expected) in [
+        // empty folders and loops with nothing to play are skipped, and loops don't repeat forever
+        (folder(vec![song(0), folder(vec![]), song(1)]), vec![0, 1]),
+        (
+            folder(vec![
+                song(0),
+                folder(vec![folder(vec![]), folder(vec![])]),
+                song(1),
+            ]),
+            vec![0, 1],
+        ),
+        (
+            folder(vec![song(0), repeat(0, folder(vec![])), song(1)]),
+            vec![0, 1],
+        ),
+        (
+            folder(vec![
+                song(0),
+                repeat(3, folder(vec![folder(vec![])])),
+                song(1),
+            ]),
+            vec![0, 1],
+        ),
+        // when a loop restarts, it also skips empty elements at the start
+        (repeat(2, folder(vec![song(0), folder(vec![])])), vec![0, 0]),
+        (
+            folder(vec![
+                song(0),
+                repeat(2, folder(vec![folder(vec![]), song(1), folder(vec![])])),
+            ]),
+            vec![0, 1, 1],
+        ),
+        (
+            folder(vec![song(0), folder(vec![folder(vec![]), song(1)])]),
+            vec![0, 1],
+        ),
+        // only empty elements: nothing is played
+        (folder(vec![]), vec![]),
+        (
+            folder(vec![folder(vec![]), repeat(0, folder(vec![]))]),
+            vec![],
+        ),
+        (repeat(0, folder(vec![folder(vec![])])), vec![]),
+    ] {
+        assert_eq!(play(queue, &db), Some(expected));
+    }
+}
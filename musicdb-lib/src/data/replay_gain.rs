@@ -0,0 +1,116 @@
+use std::io::{Read, Write};
+
+use crate::load::ToFromBytes;
+
+use super::song::Song;
+
+/// which ReplayGain tags (`ReplayGainTrack=-6.5 dB`, `ReplayGainAlbum=...`) are used to adjust the volume of songs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    /// uses the track gain if a song has no album gain
+    Album,
+}
+
+impl ReplayGainMode {
+    /// the linear factor by which the song's samples should be multiplied.
+    /// songs without ReplayGain tags play at unity gain.
+    pub fn gain(&self, song: &Song, preamp_db: f32) -> f32 {
+        let track = || {
+            Some((
+                tag_value(song, "ReplayGainTrack=")?,
+                tag_value(song, "ReplayGainTrackPeak="),
+            ))
+        };
+        let gain = match self {
+            Self::Off => None,
+            Self::Track => track(),
+            Self::Album => tag_value(song, "ReplayGainAlbum=")
+                .map(|gain| (gain, tag_value(song, "ReplayGainAlbumPeak=")))
+                .or_else(track),
+        };
+        if let Some((gain_db, peak)) = gain {
+            gain_factor(gain_db, preamp_db, peak)
+        } else {
+            1.0
+        }
+    }
+}
+
+fn tag_value(song: &Song, prefix: &str) -> Option<f32> {
+    let value = song
+        .general
+        .tags
+        .iter()
+        .find_map(|tag| tag.strip_prefix(prefix))?
+        .trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value.trim().parse().ok().filter(|v: &f32| v.is_finite())
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// converts the gain and pre-amp (in dB) to a linear factor.
+/// to prevent clipping, the factor is limited so that the song's peak (1.0 = full scale) stays at or below full scale.
+/// if the peak isn't known, songs are never amplified.
+pub fn gain_factor(gain_db: f32, preamp_db: f32, peak: Option<f32>) -> f32 {
+    let max = match peak {
+        Some(peak) if peak > 0.0 => 1.0 / peak,
+        _ => 1.0,
+    };
+    db_to_linear(gain_db + preamp_db).min(max)
+}
+
+impl ToFromBytes for ReplayGainMode {
+    fn to_bytes<T>(&self, s: &mut T) -> Result<(), std::io::Error>
+    where
+        T: Write,
+    {
+        s.write_all(&[match self {
+            Self::Off => 0b00000000,
+            Self::Track => 0b00000001,
+            Self::Album => 0b00000010,
+        }])
+    }
+    fn from_bytes<T>(s: &mut T) -> Result<Self, std::io::Error>
+    where
+        T: Read,
+    {
+        let mut b = [0];
+        s.read_exact(&mut b)?;
+        Ok(match b[0] {
+            0b00000001 => Self::Track,
+            0b00000010 => Self::Album,
+            _ => Self::Off,
+        })
+    }
+}
+
+#[test]
+fn test_db_to_linear() {
+    assert_eq!(db_to_linear(0.0), 1.0);
+    assert!((db_to_linear(-6.0) - 0.501).abs() < 0.001);
+    assert!((db_to_linear(6.0) - 1.995).abs() < 0.001);
+    assert!((db_to_linear(-20.0) - 0.1).abs() < 0.0001);
+}
+
+#[test]
+fn test_gain_factor_clipping() {
+    // attenuation is never limited
+    assert!((gain_factor(-6.0, 0.0, Some(1.0)) - 0.501).abs() < 0.001);
+    assert!((gain_factor(-10.0, 4.0, None) - 0.501).abs() < 0.001);
+    // without a peak, songs aren't amplified
+    assert_eq!(gain_factor(3.0, 0.0, None), 1.0);
+    assert_eq!(gain_factor(-3.0, 6.0, None), 1.0);
+    // with a peak, amplification stops at full scale
+    assert!((gain_factor(6.0, 0.0, Some(0.25)) - 1.995).abs() < 0.001);
+    assert_eq!(gain_factor(6.0, 0.0, Some(0.8)), 1.25);
+    assert_eq!(gain_factor(0.0, 0.0, Some(0.0)), 1.0);
+}
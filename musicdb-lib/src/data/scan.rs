@@ -0,0 +1,1022 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use id3::TagLike;
+
+use crate::server::{Action, Req};
+
+use super::{
+    album::Album,
+    artist::Artist,
+    database::{Cover, Database},
+    song::Song,
+    AlbumId, ArtistId, CoverId, DatabaseLocation, GeneralData, SongId,
+};
+
+/// the title of the `ErrorInfo`s sent to clients during a rescan
+const RESCAN_TITLE: &str = "Library rescan";
+/// the title of the `ErrorInfo`s sent to clients during a library check
+const VERIFY_TITLE: &str = "Library check";
+/// how many songs the library check looks up at once, so the database isn't locked for long
+const VERIFY_BATCH_SIZE: usize = 256;
+/// how often progress is reported to clients while reading tags
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+/// the tag set on songs whose file was removed, see `apply_library_changes`
+pub const FILE_MISSING_TAG: &str = "FileMissing";
+/// the duration backfill waits this long after each song, so it doesn't slow down anything else
+const BACKFILL_INTERVAL: Duration = Duration::from_millis(200);
+/// how long the duration backfill waits if the current or next song is being loaded
+const BACKFILL_LOADING_WAIT: Duration = Duration::from_secs(1);
+/// tags which are generated from the file's metadata, and replaced when the file changes
+const GENERATED_TAGS: [&str; 8] = [
+    "TrackNr=",
+    "DiscNr=",
+    "Year=",
+    "Genre=",
+    "ReplayGainTrack=",
+    "ReplayGainTrackPeak=",
+    "ReplayGainAlbum=",
+    "ReplayGainAlbumPeak=",
+];
+
+/// What was read from a song file, see `read_song_file`
+#[derive(Clone, Debug)]
+pub struct ScannedSong {
+    pub path: PathBuf,
+    /// the path relative to the library directory
+    pub rel_path: PathBuf,
+    pub file_size: u64,
+    pub last_modified: Option<u64>,
+    pub title: String,
+    /// the album artist, or the artist if there is no album artist
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_millis: u64,
+    pub general: GeneralData,
+    disc: Option<u32>,
+    track: Option<u32>,
+}
+
+/// all files in the directory and its subdirectories
+pub fn all_files_in_dir(dir: impl AsRef<Path>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    _ = all_files_in_dir_internal(&dir, &mut files);
+    files
+}
+fn all_files_in_dir_internal(
+    dir: impl AsRef<Path>,
+    vec: &mut Vec<PathBuf>,
+) -> Result<(), std::io::Error> {
+    for path in fs::read_dir(dir)?
+        .filter_map(|possible_entry| possible_entry.ok())
+        .map(|entry| entry.path())
+    {
+        if all_files_in_dir_internal(&path, vec).is_err() {
+            vec.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// if the file can be added to the library (only mp3 files are supported)
+pub fn is_song_file(path: &Path) -> bool {
+    matches!(path.extension().and_then(|ext| ext.to_str()), Some("mp3"))
+}
+
+/// the file's last-modified time as a unix timestamp
+pub fn last_modified(path: &Path) -> Option<u64> {
+    match path.metadata() {
+        Ok(v) => match v.modified() {
+            Ok(v) => {
+                if let Ok(time) = v.duration_since(SystemTime::UNIX_EPOCH) {
+                    Some(time.as_secs())
+                } else {
+                    log_warn!(
+                        "LastModified time of song {:?} is before the UNIX-EPOCH, setting `None`.",
+                        path
+                    );
+                    None
+                }
+            }
+            Err(e) => {
+                log_warn!("LastModified time of song {:?} not available: {e}.", path);
+                None
+            }
+        },
+        Err(e) => {
+            log_warn!(
+                "LastModified time of song {:?} could not be read: {e}.",
+                path
+            );
+            None
+        }
+    }
+}
+
+/// Reads the id3 tag of a song file. If `skip_duration` is true and the tag doesn't contain the duration,
+/// the duration is 0, otherwise it is determined from the file contents, which is much slower.
+pub fn read_song_file(
+    lib_dir: &Path,
+    path: PathBuf,
+    skip_duration: bool,
+) -> Result<ScannedSong, String> {
+    let metadata = path
+        .metadata()
+        .map_err(|e| format!("couldn't get metadata of file {path:?}: {e}"))?;
+    let tags = id3::Tag::read_from_path(&path)
+        .map_err(|e| format!("[{path:?}] error reading id3 tag: {e}"))?;
+    let rel_path = path
+        .strip_prefix(lib_dir)
+        .map_err(|_| format!("{path:?} is not in the library directory"))?
+        .to_path_buf();
+    let mut general = GeneralData::default();
+    match (tags.track(), tags.total_tracks()) {
+        (None, None) => {}
+        (Some(n), Some(t)) => general.tags.push(format!("TrackNr={n}/{t}")),
+        (Some(n), None) => general.tags.push(format!("TrackNr={n}")),
+        (None, Some(t)) => general.tags.push(format!("TrackNr=?/{t}")),
+    }
+    match (tags.disc(), tags.total_discs()) {
+        (None, None) => {}
+        (Some(n), Some(t)) => general.tags.push(format!("DiscNr={n}/{t}")),
+        (Some(n), None) => general.tags.push(format!("DiscNr={n}")),
+        (None, Some(t)) => general.tags.push(format!("DiscNr=?/{t}")),
+    }
+    if let Some(year) = tags.year() {
+        general.tags.push(format!("Year={year}"));
+    }
+    if let Some(genre) = tags.genre_parsed() {
+        general.tags.push(format!("Genre={genre}"));
+    }
+    for text in tags.extended_texts() {
+        let tag = match text.description.to_uppercase().as_str() {
+            "REPLAYGAIN_TRACK_GAIN" => "ReplayGainTrack",
+            "REPLAYGAIN_TRACK_PEAK" => "ReplayGainTrackPeak",
+            "REPLAYGAIN_ALBUM_GAIN" => "ReplayGainAlbum",
+            "REPLAYGAIN_ALBUM_PEAK" => "ReplayGainAlbumPeak",
+            _ => continue,
+        };
+        general.tags.push(format!("{tag}={}", text.value.trim()));
+    }
+    let title = tags
+        .title()
+        .filter(|title| !title.trim().is_empty())
+        .map(|title| title.to_owned())
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        });
+    let duration_millis = if let Some(dur) = tags.duration() {
+        dur as u64
+    } else if skip_duration {
+        log_warn!("Duration of song {path:?} not found in tags, using 0 instead!");
+        0
+    } else {
+        match mp3_duration::from_path(&path) {
+            Ok(dur) => dur.as_millis().min(u64::MAX as _) as u64,
+            Err(e) => {
+                log_warn!("Duration of song {path:?} not found in tags and can't be determined from the file contents either ({e}). Using duration 0 instead.");
+                0
+            }
+        }
+    };
+    Ok(ScannedSong {
+        last_modified: last_modified(&path),
+        rel_path,
+        file_size: metadata.len(),
+        title,
+        artist: tags
+            .album_artist()
+            .filter(|v| !v.trim().is_empty())
+            .or_else(|| tags.artist().filter(|v| !v.trim().is_empty()))
+            .map(|v| v.to_owned()),
+        album: tags
+            .album()
+            .filter(|a| !a.trim().is_empty())
+            .map(|a| a.to_owned()),
+        duration_millis,
+        general,
+        disc: tags.disc(),
+        track: tags.track(),
+        path,
+    })
+}
+
+/// Sort by Disc->Track->Path, so songs are added to their albums in the right order
+pub fn sort_songs(songs: &mut [ScannedSong]) {
+    fn cmp_opt(a: Option<u32>, b: Option<u32>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        }
+    }
+    songs.sort_by(|a, b| {
+        cmp_opt(a.disc, b.disc)
+            .then_with(|| cmp_opt(a.track, b.track))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+/// Finds the largest image file in the directory.
+/// The `bool` is `true` if there was more than one image.
+pub fn find_cover(abs_dir: impl AsRef<Path>) -> (Option<PathBuf>, bool) {
+    let mut multiple = false;
+    let mut cover = None;
+    if let Ok(files) = fs::read_dir(&abs_dir) {
+        for file in files.flatten() {
+            let path = file.path();
+            if let Ok(metadata) = path.metadata() {
+                if metadata.is_file()
+                    && path.extension().and_then(|v| v.to_str()).is_some_and(|v| {
+                        matches!(v.to_lowercase().as_str(), "png" | "jpg" | "jpeg")
+                    })
+                {
+                    if let Some((_, size)) = &cover {
+                        multiple = true;
+                        if *size >= metadata.len() {
+                            continue;
+                        }
+                    }
+                    cover = Some((path, metadata.len()));
+                }
+            }
+        }
+    }
+    (cover.map(|(path, _)| path), multiple)
+}
+
+/// Starts a thread which scans the library directory for new and changed song files (see `Action::RescanLibrary`).
+/// New songs are added (with new artists, albums and covers, if necessary), changed songs are updated, then the database is saved.
+/// Songs whose files were removed are not removed from the database.
+/// Progress is reported to all clients using `ErrorInfo`s. If a rescan is already running, `client` gets an error instead.
+pub fn start_rescan(database: &Arc<Mutex<Database>>, client: Option<u64>) {
+    let mut db = database.lock().unwrap();
+    let error = if db.rescan_running {
+        Some("A rescan is already running.")
+    } else if db.remote_server_as_song_file_source.is_some() {
+        Some("The library is on another server, it can't be scanned here.")
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        let error = Action::ErrorInfo(RESCAN_TITLE.to_owned(), error.to_owned());
+        if let Some(client) = client {
+            let command = db.seq.pack(error);
+            db.send_to_client(client, command);
+        } else {
+            log_warn!("{RESCAN_TITLE}: {error:?}");
+        }
+        return;
+    }
+    db.rescan_running = true;
+    let lib_dir = db.lib_directory.clone();
+    let known = db
+        .songs()
+        .values()
+        .map(|song| {
+            (
+                song.location.rel_path.clone(),
+                (song.id, song.file_last_modified_unix_timestamp),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    drop(db);
+    let database = Arc::clone(database);
+    std::thread::spawn(move || {
+        let summary = rescan(&database, &lib_dir, known);
+        let mut db = database.lock().unwrap();
+        db.rescan_running = false;
+        notify(&mut db, RESCAN_TITLE, summary);
+        db.apply_action_unchecked_seq(Action::Save, None);
+    });
+}
+
+fn notify(db: &mut Database, title: &str, text: String) {
+    log_info!("{title}: {text}");
+    // broadcast directly, `apply_action_unchecked_seq` would remove the title
+    db.broadcast_update(Action::ErrorInfo(title.to_owned(), text), None);
+}
+
+/// the lowest unused id, which is the id the database will assign to the next new element
+fn next_id<T>(map: &HashMap<u64, T>) -> u64 {
+    (0..).find(|id| !map.contains_key(id)).unwrap()
+}
+
+/// returns the summary
+fn rescan(
+    database: &Mutex<Database>,
+    lib_dir: &Path,
+    known: HashMap<PathBuf, (SongId, Option<u64>)>,
+) -> String {
+    notify(
+        &mut database.lock().unwrap(),
+        RESCAN_TITLE,
+        format!("Searching for new and changed files in {lib_dir:?}..."),
+    );
+    let mut new = vec![];
+    let mut changed = vec![];
+    for path in all_files_in_dir(lib_dir) {
+        if !is_song_file(&path) {
+            continue;
+        }
+        let Ok(rel_path) = path.strip_prefix(lib_dir) else {
+            continue;
+        };
+        match known.get(rel_path) {
+            None => new.push(path),
+            Some((id, last_modified)) => {
+                if last_modified.is_none() || *last_modified != self::last_modified(&path) {
+                    changed.push((*id, path));
+                }
+            }
+        }
+    }
+    notify(
+        &mut database.lock().unwrap(),
+        RESCAN_TITLE,
+        format!(
+            "Found {} new and {} changed files, reading tags...",
+            new.len(),
+            changed.len()
+        ),
+    );
+    update_songs(database, lib_dir, new, changed)
+}
+
+/// Reads the tags of the new and changed files, then adds the new songs and updates the changed ones.
+/// Returns the summary.
+fn update_songs(
+    database: &Mutex<Database>,
+    lib_dir: &Path,
+    new: Vec<PathBuf>,
+    changed: Vec<(SongId, PathBuf)>,
+) -> String {
+    let total = new.len() + changed.len();
+    let mut done = 0;
+    let mut last_progress = Instant::now();
+    let mut failed = 0;
+    let mut read = |path: PathBuf| {
+        done += 1;
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            notify(
+                &mut database.lock().unwrap(),
+                RESCAN_TITLE,
+                format!("Reading tags: {done}/{total}"),
+            );
+        }
+        match read_song_file(lib_dir, path, false) {
+            Ok(song) => Some(song),
+            Err(e) => {
+                log_warn!("{RESCAN_TITLE}: {e}");
+                failed += 1;
+                None
+            }
+        }
+    };
+    let changed = changed
+        .into_iter()
+        .filter_map(|(id, path)| Some((id, read(path)?)))
+        .collect::<Vec<_>>();
+    let mut new = new.into_iter().filter_map(&mut read).collect::<Vec<_>>();
+    sort_songs(&mut new);
+
+    // update changed songs, but keep their artist, album, cover and custom tags
+    let mut updated = 0;
+    for (id, scanned) in changed {
+        let mut db = database.lock().unwrap();
+        if let Some(song) = db.get_song(&id) {
+            let mut song = song.clone();
+            song.title = scanned.title;
+            song.file_size = scanned.file_size;
+            song.file_last_modified_unix_timestamp = scanned.last_modified;
+            song.duration_millis = scanned.duration_millis;
+            song.general.tags.retain(|tag| {
+                tag != FILE_MISSING_TAG
+                    && !GENERATED_TAGS.iter().any(|prefix| tag.starts_with(prefix))
+            });
+            song.general.tags.extend(scanned.general.tags);
+            _ = song.cached_data().uncache_data();
+            db.apply_action_unchecked_seq(Action::ModifySong(song, Req::none()), None);
+            updated += 1;
+        }
+    }
+
+    // add new songs, and their artists, albums and covers if they don't exist yet
+    let (mut artists, mut albums, mut covers) = (0, 0, 0);
+    let mut dir_covers: HashMap<PathBuf, Option<CoverId>> = HashMap::new();
+    for scanned in &new {
+        let mut db = database.lock().unwrap();
+        let mut cover_for_dir = |db: &mut Database| {
+            let dir = scanned.path.parent()?;
+            if let Some(cover) = dir_covers.get(dir) {
+                return *cover;
+            }
+            let cover = find_cover(dir).0.and_then(|path| {
+                let rel_path = path.strip_prefix(lib_dir).ok()?.to_path_buf();
+                if let Some((id, _)) = db
+                    .covers()
+                    .iter()
+                    .find(|(_, cover)| cover.location.rel_path == rel_path)
+                {
+                    return Some(*id);
+                }
+                let id = next_id(db.covers());
+                db.apply_action_unchecked_seq(
+                    Action::AddCover(
+                        Cover {
+                            location: DatabaseLocation { rel_path },
+                            data: Arc::new(Mutex::new((false, None))),
+                        },
+                        Req::none(),
+                    ),
+                    None,
+                );
+                covers += 1;
+                Some(id)
+            });
+            dir_covers.insert(dir.to_path_buf(), cover);
+            cover
+        };
+        let artist_name = scanned.artist.as_deref().unwrap_or("<unknown>");
+        let artist_id: ArtistId = if let Some(artist) = db
+            .artists()
+            .values()
+            .find(|artist| artist.name == artist_name)
+        {
+            artist.id
+        } else {
+            let id = next_id(db.artists());
+            db.apply_action_unchecked_seq(
+                Action::AddArtist(
+                    Artist {
+                        id: 0,
+                        name: artist_name.to_owned(),
+                        cover: None,
+                        albums: vec![],
+                        singles: vec![],
+                        general: GeneralData::default(),
+                    },
+                    Req::none(),
+                ),
+                None,
+            );
+            artists += 1;
+            id
+        };
+        let album_id: Option<AlbumId> = if let Some(album_name) = scanned
+            .album
+            .as_deref()
+            .filter(|_| scanned.artist.is_some())
+        {
+            if let Some(album) = db
+                .albums()
+                .values()
+                .find(|album| album.artist == artist_id && album.name == album_name)
+            {
+                Some(album.id)
+            } else {
+                let cover = cover_for_dir(&mut db);
+                let id = next_id(db.albums());
+                db.apply_action_unchecked_seq(
+                    Action::AddAlbum(
+                        Album {
+                            id: 0,
+                            artist: artist_id,
+                            name: album_name.to_owned(),
+                            cover,
+                            songs: vec![],
+                            general: GeneralData::default(),
+                        },
+                        Req::none(),
+                    ),
+                    None,
+                );
+                albums += 1;
+                Some(id)
+            }
+        } else {
+            None
+        };
+        // songs in albums use the album's cover
+        let cover = if album_id.is_none() {
+            cover_for_dir(&mut db)
+        } else {
+            None
+        };
+        db.apply_action_unchecked_seq(
+            Action::AddSong(
+                Song::new(
+                    DatabaseLocation {
+                        rel_path: scanned.rel_path.clone(),
+                    },
+                    scanned.last_modified,
+                    scanned.title.clone(),
+                    album_id,
+                    artist_id,
+                    vec![],
+                    cover,
+                    scanned.file_size,
+                    scanned.duration_millis,
+                    scanned.general.clone(),
+                ),
+                Req::none(),
+            ),
+            None,
+        );
+    }
+    let mut summary = format!(
+        "Done. Added {} songs, {albums} albums, {artists} artists and {covers} covers, updated {updated} songs.",
+        new.len()
+    );
+    if failed > 0 {
+        summary.push_str(&format!(" {failed} files couldn't be read."));
+    }
+    summary
+}
+
+/// Starts (`enable`) or aborts (`!enable`) a background thread which determines the duration of all songs
+/// whose duration is 0 (for example because the library was created with `--skip-duration`), see `Action::SetDurationBackfill`.
+/// Songs are read one at a time, using the cached data if available, and `SetSongDuration` is applied for each.
+/// Does nothing if it is already running, if no duration is missing, or if songs are loaded from another server.
+pub fn set_duration_backfill(database: &Arc<Mutex<Database>>, enable: bool) {
+    let mut db = database.lock().unwrap();
+    if !enable {
+        if let Some(abort) = db.duration_backfill.take() {
+            abort.store(true, AtomicOrdering::Relaxed);
+            log_info!("Duration backfill: aborted.");
+        }
+        return;
+    }
+    if db.duration_backfill.is_some() || db.remote_server_as_song_file_source.is_some() {
+        return;
+    }
+    let mut songs = db
+        .songs()
+        .values()
+        .filter(|song| song.duration_millis == 0)
+        .map(|song| song.id)
+        .collect::<Vec<_>>();
+    if songs.is_empty() {
+        return;
+    }
+    songs.sort_unstable();
+    log_info!(
+        "Duration backfill: {} songs have no duration, determining it in the background.",
+        songs.len()
+    );
+    let abort = Arc::new(AtomicBool::new(false));
+    db.duration_backfill = Some(Arc::clone(&abort));
+    drop(db);
+    let database = Arc::clone(database);
+    std::thread::spawn(move || {
+        let count = backfill_durations(&database, songs, &abort);
+        if !abort.load(AtomicOrdering::Relaxed) {
+            database.lock().unwrap().duration_backfill = None;
+            log_info!("Duration backfill: done, determined the duration of {count} songs.");
+        }
+    });
+}
+
+/// returns the number of songs whose duration was set
+fn backfill_durations(database: &Mutex<Database>, songs: Vec<SongId>, abort: &AtomicBool) -> usize {
+    let mut count = 0;
+    for id in songs {
+        // the player shouldn't have to wait for the disk
+        let Some((data, path)) = (loop {
+            if abort.load(AtomicOrdering::Relaxed) {
+                return count;
+            }
+            let db = database.lock().unwrap();
+            if [db.queue.get_current_song(&db), db.queue.get_next_song(&db)]
+                .into_iter()
+                .flatten()
+                .filter_map(|id| db.get_song(id))
+                .any(|song| song.cached_data().is_loading())
+            {
+                drop(db);
+                std::thread::sleep(BACKFILL_LOADING_WAIT);
+                continue;
+            }
+            break db
+                .get_song(&id)
+                .filter(|song| song.duration_millis == 0)
+                .map(|song| {
+                    (
+                        song.cached_data().cached_data(),
+                        db.get_path(&song.location),
+                    )
+                });
+        }) else {
+            continue;
+        };
+        let duration = if let Some(data) = data {
+            mp3_duration::from_read(&mut Cursor::new(&data[..]))
+        } else {
+            mp3_duration::from_path(&path)
+        };
+        match duration {
+            Ok(duration) if !duration.is_zero() => {
+                let millis = duration.as_millis().min(u64::MAX as _) as u64;
+                database
+                    .lock()
+                    .unwrap()
+                    .apply_action_unchecked_seq(Action::SetSongDuration(id, millis), None);
+                count += 1;
+            }
+            Ok(_) => {}
+            Err(e) => log_warn!("Duration backfill: can't determine the duration of {path:?}: {e}"),
+        }
+        std::thread::sleep(BACKFILL_INTERVAL);
+    }
+    count
+}
+
+/// Starts (`enable`) or aborts (`!enable`) a background thread which checks that the file of every song exists and is readable,
+/// see `Action::VerifyLibrary`. Songs whose file is missing get the `FILE_MISSING_TAG`, songs whose file was found again lose it.
+/// The database is only locked to look up a few songs at a time, not while their files are checked.
+/// Progress and the number of missing files are reported to all clients using `ErrorInfo`s.
+/// If a check is already running or songs are loaded from another server, `client` gets an error instead.
+pub fn set_library_verification(
+    database: &Arc<Mutex<Database>>,
+    enable: bool,
+    client: Option<u64>,
+) {
+    let mut db = database.lock().unwrap();
+    if !enable {
+        if let Some(abort) = db.library_verification.take() {
+            abort.store(true, AtomicOrdering::Relaxed);
+        }
+        return;
+    }
+    let error = if db.library_verification.is_some() {
+        Some("A library check is already running.")
+    } else if db.remote_server_as_song_file_source.is_some() {
+        Some("The library is on another server, it can't be checked here.")
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        let error = Action::ErrorInfo(VERIFY_TITLE.to_owned(), error.to_owned());
+        if let Some(client) = client {
+            let command = db.seq.pack(error);
+            db.send_to_client(client, command);
+        } else {
+            log_warn!("{VERIFY_TITLE}: {error:?}");
+        }
+        return;
+    }
+    let mut songs = db.songs().keys().copied().collect::<Vec<_>>();
+    songs.sort_unstable();
+    let abort = Arc::new(AtomicBool::new(false));
+    db.library_verification = Some(Arc::clone(&abort));
+    notify(
+        &mut db,
+        VERIFY_TITLE,
+        format!("Checking the files of {} songs...", songs.len()),
+    );
+    drop(db);
+    let database = Arc::clone(database);
+    std::thread::spawn(move || {
+        let summary = verify_library(&database, songs, &abort);
+        let mut db = database.lock().unwrap();
+        if !abort.load(AtomicOrdering::Relaxed) {
+            db.library_verification = None;
+        }
+        notify(&mut db, VERIFY_TITLE, summary);
+    });
+}
+
+/// returns the summary
+fn verify_library(database: &Mutex<Database>, songs: Vec<SongId>, abort: &AtomicBool) -> String {
+    let total = songs.len();
+    let (mut checked, mut missing, mut newly_missing, mut found) = (0, 0, 0, 0);
+    let mut last_progress = Instant::now();
+    for batch in songs.chunks(VERIFY_BATCH_SIZE) {
+        if abort.load(AtomicOrdering::Relaxed) {
+            return format!(
+                "Aborted after checking {checked} of {total} songs, {missing} files were missing."
+            );
+        }
+        let batch = {
+            let db = database.lock().unwrap();
+            batch
+                .iter()
+                .filter_map(|id| db.get_song(id))
+                .map(|song| {
+                    (
+                        song.id,
+                        db.get_path(&song.location),
+                        song.general.tags.iter().any(|tag| tag == FILE_MISSING_TAG),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let changes = batch
+            .into_iter()
+            .filter_map(|(id, path, was_missing)| {
+                let readable = fs::File::open(&path)
+                    .and_then(|file| file.metadata())
+                    .is_ok_and(|metadata| metadata.is_file());
+                if !readable {
+                    log_warn!(
+                        "{VERIFY_TITLE}: the file of song {id} is missing or unreadable: {path:?}"
+                    );
+                    missing += 1;
+                }
+                checked += 1;
+                match (readable, was_missing) {
+                    (false, false) => {
+                        newly_missing += 1;
+                        Some(Action::TagSongFlagSet(id, FILE_MISSING_TAG.to_owned()))
+                    }
+                    (true, true) => {
+                        found += 1;
+                        Some(Action::TagSongFlagUnset(id, FILE_MISSING_TAG.to_owned()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut db = database.lock().unwrap();
+        for action in changes {
+            db.apply_action_unchecked_seq(action, None);
+        }
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            notify(
+                &mut db,
+                VERIFY_TITLE,
+                format!("Checked {checked}/{total} songs, {missing} files are missing."),
+            );
+        }
+    }
+    let mut summary = format!("Done. {missing} of {total} song files are missing or unreadable.");
+    if newly_missing > 0 {
+        summary.push_str(&format!(
+            " {newly_missing} songs were tagged {FILE_MISSING_TAG}."
+        ));
+    }
+    if found > 0 {
+        summary.push_str(&format!(" {found} files were found again."));
+    }
+    summary
+}
+
+/// Changes to files or directories in the library directory, for example reported by a filesystem watcher.
+/// All paths are absolute.
+#[derive(Debug, Default)]
+pub struct LibraryChanges {
+    /// created or modified
+    pub changed: HashSet<PathBuf>,
+    pub removed: HashSet<PathBuf>,
+    /// renamed or moved, (from, to)
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+}
+impl LibraryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
+/// Updates the database after files in the library directory changed. Runs on the current thread.
+/// Renamed and moved songs keep their `SongId`. If the rename itself wasn't reported, a removed song and a new file
+/// are assumed to be the same song if their size and last-modified time are the same.
+/// Songs whose files were removed aren't removed from the database, they get the `FILE_MISSING_TAG` instead.
+/// Only song files are considered, so changes to covers or the dbfile are ignored.
+/// If a rescan is running, nothing is done and the changes are returned, so they can be applied later.
+pub fn apply_library_changes(
+    database: &Mutex<Database>,
+    mut changes: LibraryChanges,
+) -> Result<(), LibraryChanges> {
+    let mut db = database.lock().unwrap();
+    if db.rescan_running {
+        return Err(changes);
+    }
+    if db.remote_server_as_song_file_source.is_some() {
+        return Ok(());
+    }
+    let lib_dir = db.lib_directory.clone();
+    let rel = |path: &Path| path.strip_prefix(&lib_dir).ok().map(|p| p.to_path_buf());
+    let songs_in = |db: &Database, rel_path: &Path| {
+        db.songs()
+            .values()
+            .filter(|song| song.location.rel_path.starts_with(rel_path))
+            .map(|song| song.id)
+            .collect::<Vec<_>>()
+    };
+    let mut moved = 0;
+    let mut move_song = |db: &mut Database, id: SongId, rel_path: PathBuf| {
+        if let Some(song) = db.get_song(&id) {
+            let mut song = song.clone();
+            song.location.rel_path = rel_path;
+            song.general.tags.retain(|tag| tag != FILE_MISSING_TAG);
+            db.apply_action_unchecked_seq(Action::ModifySong(song, Req::none()), None);
+            moved += 1;
+        }
+    };
+
+    // renames: also handles directories, which don't cause events for the files inside them
+    for (from, to) in std::mem::take(&mut changes.renamed) {
+        match (rel(&from), rel(&to)) {
+            (Some(rel_from), Some(rel_to)) => {
+                let ids = songs_in(&db, &rel_from);
+                if ids.is_empty() {
+                    changes.changed.insert(to);
+                    continue;
+                }
+                for id in ids {
+                    let old_path = db.get_song(&id).unwrap().location.rel_path.clone();
+                    let rel_path = rel_to.join(old_path.strip_prefix(&rel_from).unwrap());
+                    if is_song_file(&rel_path) {
+                        move_song(&mut db, id, rel_path);
+                    } else {
+                        changes.removed.insert(lib_dir.join(old_path));
+                    }
+                }
+                // covers are only ever loaded by the server, so they can be moved without notifying clients
+                for cover in db.covers_mut().values_mut() {
+                    if let Ok(rest) = cover.location.rel_path.strip_prefix(&rel_from) {
+                        cover.location.rel_path = rel_to.join(rest);
+                    }
+                }
+            }
+            (Some(_), None) => {
+                changes.removed.insert(from);
+            }
+            (None, Some(_)) => {
+                changes.changed.insert(to);
+            }
+            (None, None) => {}
+        }
+    }
+
+    // songs whose files may have been removed
+    let mut missing = changes
+        .removed
+        .iter()
+        .filter_map(|path| rel(path))
+        .flat_map(|rel_path| songs_in(&db, &rel_path))
+        .filter(|id| {
+            db.get_song(id).is_some_and(|song| {
+                !lib_dir.join(&song.location.rel_path).exists()
+                    && !song.general.tags.iter().any(|tag| tag == FILE_MISSING_TAG)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // new and changed files
+    let known = db
+        .songs()
+        .values()
+        .map(|song| (song.location.rel_path.clone(), song.id))
+        .collect::<HashMap<_, _>>();
+    let mut new = vec![];
+    let mut changed = vec![];
+    let mut found = 0;
+    for path in changes
+        .changed
+        .iter()
+        .flat_map(|path| {
+            if path.is_dir() {
+                all_files_in_dir(path)
+            } else {
+                vec![path.clone()]
+            }
+        })
+        .collect::<HashSet<_>>()
+    {
+        if !is_song_file(&path) || !path.is_file() {
+            continue;
+        }
+        let Some(rel_path) = rel(&path) else {
+            continue;
+        };
+        if let Some(id) = known.get(&rel_path) {
+            let song = db.get_song(id).unwrap();
+            if song.file_last_modified_unix_timestamp.is_none()
+                || song.file_last_modified_unix_timestamp != last_modified(&path)
+            {
+                changed.push((*id, path));
+            } else if song.general.tags.iter().any(|tag| tag == FILE_MISSING_TAG) {
+                db.apply_action_unchecked_seq(
+                    Action::TagSongFlagUnset(*id, FILE_MISSING_TAG.to_owned()),
+                    None,
+                );
+                found += 1;
+            }
+        } else if let Some(i) = missing.iter().position(|id| {
+            let song = db.get_song(id).unwrap();
+            song.file_last_modified_unix_timestamp.is_some()
+                && song.file_last_modified_unix_timestamp == last_modified(&path)
+                && path.metadata().is_ok_and(|m| m.len() == song.file_size)
+        }) {
+            // probably the same file, moved without a rename event
+            move_song(&mut db, missing.remove(i), rel_path);
+        } else {
+            new.push(path);
+        }
+    }
+    for id in &missing {
+        db.apply_action_unchecked_seq(
+            Action::TagSongFlagSet(*id, FILE_MISSING_TAG.to_owned()),
+            None,
+        );
+    }
+    if new.is_empty() && changed.is_empty() && moved == 0 && found == 0 && missing.is_empty() {
+        return Ok(());
+    }
+    db.rescan_running = true;
+    drop(db);
+    let mut summary = if new.is_empty() && changed.is_empty() {
+        "Done.".to_owned()
+    } else {
+        update_songs(database, &lib_dir, new, changed)
+    };
+    if moved > 0 {
+        summary.push_str(&format!(" {moved} songs were moved."));
+    }
+    if found > 0 {
+        summary.push_str(&format!(" {found} missing files were found again."));
+    }
+    if !missing.is_empty() {
+        summary.push_str(&format!(
+            " {} files are missing and were tagged {FILE_MISSING_TAG}.",
+            missing.len()
+        ));
+    }
+    let mut db = database.lock().unwrap();
+    db.rescan_running = false;
+    notify(&mut db, RESCAN_TITLE, summary);
+    db.apply_action_unchecked_seq(Action::Save, None);
+    Ok(())
+}
+
+#[test]
+fn test_apply_library_changes() {
+    use crate::test_util::{test_dir, test_song};
+    let dir = test_dir("scan");
+    let lib_dir = dir.join("lib");
+    fs::create_dir_all(lib_dir.join("a")).unwrap();
+    fs::write(lib_dir.join("a/1.mp3"), b"one").unwrap();
+    fs::write(lib_dir.join("a/2.mp3"), b"two").unwrap();
+    let mut db = Database::new_empty_in_dir(dir.clone(), lib_dir.clone());
+    for name in ["1", "2"] {
+        let path = lib_dir.join(format!("a/{name}.mp3"));
+        let mut song = test_song(name, None, 0, 0);
+        song.location = format!("a/{name}.mp3").into();
+        song.file_last_modified_unix_timestamp = last_modified(&path);
+        song.file_size = 3;
+        db.add_song_new(song);
+    }
+    let database = Mutex::new(db);
+    let location = |id: SongId| {
+        let db = database.lock().unwrap();
+        let song = db.get_song(&id).unwrap();
+        (
+            song.location.rel_path.clone(),
+            song.general.tags.iter().any(|tag| tag == FILE_MISSING_TAG),
+        )
+    };
+    // renaming a directory moves the songs in it
+    fs::rename(lib_dir.join("a"), lib_dir.join("b")).unwrap();
+    let mut changes = LibraryChanges::default();
+    changes.renamed.push((lib_dir.join("a"), lib_dir.join("b")));
+    apply_library_changes(&database, changes).unwrap();
+    assert_eq!(location(0), (PathBuf::from("b/1.mp3"), false));
+    assert_eq!(location(1), (PathBuf::from("b/2.mp3"), false));
+    // a move reported as remove+create keeps the SongId
+    fs::rename(lib_dir.join("b/1.mp3"), lib_dir.join("1.mp3")).unwrap();
+    let mut changes = LibraryChanges::default();
+    changes.removed.insert(lib_dir.join("b/1.mp3"));
+    changes.changed.insert(lib_dir.join("1.mp3"));
+    apply_library_changes(&database, changes).unwrap();
+    assert_eq!(location(0), (PathBuf::from("1.mp3"), false));
+    // removed files are tagged, and untagged when they come back
+    fs::rename(lib_dir.join("b/2.mp3"), dir.join("2.mp3")).unwrap();
+    let mut changes = LibraryChanges::default();
+    changes.removed.insert(lib_dir.join("b"));
+    apply_library_changes(&database, changes).unwrap();
+    assert_eq!(location(1), (PathBuf::from("b/2.mp3"), true));
+    fs::rename(dir.join("2.mp3"), lib_dir.join("b/2.mp3")).unwrap();
+    let mut changes = LibraryChanges::default();
+    changes.changed.insert(lib_dir.join("b/2.mp3"));
+    apply_library_changes(&database, changes).unwrap();
+    assert_eq!(location(1), (PathBuf::from("b/2.mp3"), false));
+    assert_eq!(database.lock().unwrap().songs().len(), 2);
+    _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,171 @@
+use std::{fmt::Display, str::FromStr};
+
+use super::database::Database;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchResultKind {
+    Song,
+    Album,
+    Artist,
+}
+
+/// A song, album or artist which matches a search query, see `Database::search`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    pub kind: SearchResultKind,
+    /// a `SongId`, `AlbumId` or `ArtistId`, depending on `kind`
+    pub id: u64,
+    /// `Title - Artist` for songs, `Album - Artist` for albums, and the name for artists
+    pub name: String,
+    /// higher is better, see `Database::search`
+    pub score: u32,
+}
+
+impl Database {
+    /// Finds songs, albums and artists whose title or name (or album or artist name) contain all words of the query,
+    /// ignoring case. Returns at most `limit` results, best first:
+    /// exact matches get a score of 100, names starting with the query 75, names containing all words 50, and others 25.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let query = query.trim().to_lowercase();
+        let words = query.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            return vec![];
+        }
+        let score = |name: &str, others: &[&str]| -> Option<u32> {
+            let name = name.to_lowercase();
+            if name == query {
+                Some(100)
+            } else if name.starts_with(&query) {
+                Some(75)
+            } else if words.iter().all(|word| name.contains(word)) {
+                Some(50)
+            } else {
+                let others = others
+                    .iter()
+                    .map(|other| other.to_lowercase())
+                    .collect::<Vec<_>>();
+                words
+                    .iter()
+                    .all(|word| {
+                        name.contains(word) || others.iter().any(|other| other.contains(word))
+                    })
+                    .then_some(25)
+            }
+        };
+        let artist_name = |id| {
+            self.artists()
+                .get(id)
+                .map_or("", |artist| artist.name.as_str())
+        };
+        let mut results = vec![];
+        for song in self.songs().values() {
+            let artist = artist_name(&song.artist);
+            let album = song
+                .album
+                .and_then(|id| self.albums().get(&id))
+                .map_or("", |album| album.name.as_str());
+            if let Some(score) = score(&song.title, &[artist, album]) {
+                results.push(SearchResult {
+                    kind: SearchResultKind::Song,
+                    id: song.id,
+                    name: format!("{} - {artist}", song.title),
+                    score,
+                });
+            }
+        }
+        for album in self.albums().values() {
+            let artist = artist_name(&album.artist);
+            if let Some(score) = score(&album.name, &[artist]) {
+                results.push(SearchResult {
+                    kind: SearchResultKind::Album,
+                    id: album.id,
+                    name: format!("{} - {artist}", album.name),
+                    score,
+                });
+            }
+        }
+        for artist in self.artists().values() {
+            if let Some(score) = score(&artist.name, &[]) {
+                results.push(SearchResult {
+                    kind: SearchResultKind::Artist,
+                    id: artist.id,
+                    name: artist.name.clone(),
+                    score,
+                });
+            }
+        }
+        results.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        results.truncate(limit);
+        results
+    }
+}
+
+impl Display for SearchResultKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Song => write!(f, "song"),
+            Self::Album => write!(f, "album"),
+            Self::Artist => write!(f, "artist"),
+        }
+    }
+}
+impl FromStr for SearchResultKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "song" => Self::Song,
+            "album" => Self::Album,
+            "artist" => Self::Artist,
+            _ => return Err(format!("expected song, album or artist, not {s:?}")),
+        })
+    }
+}
+
+#[test]
+fn test_search() {
+    use super::{album::Album, artist::Artist};
+    use crate::test_util::test_song;
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    let artist = db.add_artist_new(Artist {
+        id: 0,
+        name: "Some Band".to_owned(),
+        cover: None,
+        albums: vec![],
+        singles: vec![],
+        general: Default::default(),
+    });
+    let album = db.add_album_new(Album {
+        id: 0,
+        name: "Blue".to_owned(),
+        artist,
+        cover: None,
+        songs: vec![],
+        general: Default::default(),
+    });
+    let song =
+        |db: &mut Database, title: &str| db.add_song_new(test_song(title, Some(album), artist, 0));
+    let blue_song = song(&mut db, "Blue Song");
+    let sky = song(&mut db, "The Sky is Blue");
+    let other = song(&mut db, "Other");
+    let results = db.search("blue", 10);
+    let ids = results.iter().map(|r| (r.kind, r.id)).collect::<Vec<_>>();
+    assert_eq!(
+        ids,
+        [
+            (SearchResultKind::Album, album),
+            (SearchResultKind::Song, blue_song),
+            (SearchResultKind::Song, sky),
+            (SearchResultKind::Song, other),
+        ]
+    );
+    assert_eq!(results[1].name, "Blue Song - Some Band");
+    assert_eq!(results[3].score, 25);
+    assert_eq!(db.search("band other", 10).len(), 1);
+    assert_eq!(db.search("blue", 2).len(), 2);
+    assert!(db.search(" ", 10).is_empty());
+}
@@ -8,8 +8,6 @@ use std::{
     time::Instant,
 };
 
-use colorize::AnsiColor;
-
 use crate::load::ToFromBytes;
 
 use super::{
@@ -174,6 +172,10 @@ impl CachedData {
             None
         }
     }
+    /// If a thread was started to load the song's data, and the data hasn't been taken from it yet.
+    pub fn is_loading(&self) -> bool {
+        matches!(self.0.lock().unwrap().0, Ok(Err(_)))
+    }
     /// Gets the cached data, if available.
     /// If a thread is running to load the data, it is not awaited.
     /// This function doesn't block.
@@ -204,39 +206,33 @@ impl CachedData {
         out
     }
     fn load_data(
-        src: Result<
-            PathBuf,
-            (
-                SongId,
-                Arc<Mutex<crate::server::get::Client<Box<dyn ClientIo>>>>,
-            ),
-        >,
+        src: Result<PathBuf, (SongId, Arc<crate::server::get::Pool<Box<dyn ClientIo>>>)>,
     ) -> Option<Vec<u8>> {
         match src {
             Ok(path) => {
-                eprintln!("[{}] loading song from {:?}", "INFO".cyan(), path);
+                log_info!("loading song from {:?}", path);
                 match std::fs::read(&path) {
                     Ok(v) => {
-                        eprintln!("[{}] loaded song from {:?}", "INFO".green(), path);
+                        log_info!("loaded song from {:?}", path);
                         Some(v)
                     }
                     Err(e) => {
-                        eprintln!("[{}] error loading {:?}: {e:?}", "ERR!".red(), path);
+                        log_error!("error loading {:?}: {e:?}", path);
                         None
                     }
                 }
             }
             Err((id, dlcon)) => {
-                eprintln!("[{}] loading song {id}", "INFO".cyan());
-                match dlcon
-                    .lock()
-                    .unwrap()
-                    .song_file(id)
-                    .expect("problem with downloader connection...")
-                {
-                    Ok(data) => Some(data),
+                log_info!("loading song {id}");
+                match dlcon.with_reconnect(|con| con.song_file(id)) {
+                    Ok(Ok(data)) => Some(data),
+                    Ok(Err(e)) => {
+                        log_error!("error loading song {id}: {e}");
+                        None
+                    }
+                    // the data received so far is kept by the connection, the next attempt continues from there
                     Err(e) => {
-                        eprintln!("[{}] error loading song {id}: {e}", "ERR!".red());
+                        log_error!("connection error while loading song {id}: {e}");
                         None
                     }
                 }
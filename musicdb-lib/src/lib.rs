@@ -1,5 +1,9 @@
+#[macro_use]
+pub mod logging;
+
 pub mod data;
 pub mod load;
 #[cfg(feature = "playback")]
 pub mod player;
 pub mod server;
+mod test_util;
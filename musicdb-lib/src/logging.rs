@@ -0,0 +1,236 @@
+//! Logging with levels, timestamps and the module which logged the message.
+//! Use the `log_debug!`, `log_info!`, `log_warn!` and `log_error!` macros.
+//! Messages are written to stderr, or, if `set_log_file` was used, to a log file which is rotated once it gets too big.
+//! With a log file, only warnings and errors are also written to stderr.
+
+use std::{
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use colorize::AnsiColor;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn tag(self) -> &'static str {
+        match self {
+            Self::Debug => "DEBG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERR!",
+        }
+    }
+    fn colored_tag(self) -> String {
+        match self {
+            Self::Debug => self.tag().blue(),
+            Self::Info => self.tag().cyan(),
+            Self::Warn => self.tag().yellow(),
+            Self::Error => self.tag().red(),
+        }
+    }
+}
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Debug => write!(f, "debug"),
+            Self::Info => write!(f, "info"),
+            Self::Warn => write!(f, "warn"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+impl FromStr for Level {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "debug" => Self::Debug,
+            "info" => Self::Info,
+            "warn" => Self::Warn,
+            "error" => Self::Error,
+            _ => return Err(format!("expected debug, info, warn or error, not {s:?}")),
+        })
+    }
+}
+
+struct Logger {
+    level: Level,
+    file: Option<LogFile>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    /// how many rotated files (`<path>.1`, `<path>.2`, ...) are kept
+    keep: usize,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    level: Level::Info,
+    file: None,
+});
+
+/// messages below this level are ignored. the default is `Info`.
+pub fn set_level(level: Level) {
+    LOGGER.lock().unwrap().level = level;
+}
+
+/// Appends all messages to this file. Once it is bigger than `max_size` bytes,
+/// it is renamed to `<path>.1` (and `<path>.1` to `<path>.2`, ...) and a new file is started.
+/// At most `keep` old files are kept.
+pub fn set_log_file(path: impl AsRef<Path>, max_size: u64, keep: usize) -> io::Result<()> {
+    let path = path.as_ref().to_path_buf();
+    let file = File::options().create(true).append(true).open(&path)?;
+    let size = file.metadata()?.len();
+    LOGGER.lock().unwrap().file = Some(LogFile {
+        path,
+        file,
+        size,
+        max_size,
+        keep,
+    });
+    Ok(())
+}
+
+/// used by the `log_*!` macros
+pub fn log(level: Level, target: &str, args: fmt::Arguments) {
+    let mut logger = LOGGER.lock().unwrap();
+    if level < logger.level {
+        return;
+    }
+    let time = timestamp(SystemTime::now());
+    if let Some(file) = &mut logger.file {
+        let line = format!("{time} [{}] {target}: {args}\n", level.tag());
+        if let Err(e) = file.write(line.as_bytes()) {
+            eprintln!("[{}] Couldn't write to the log file: {e}", "WARN".yellow());
+        }
+        if level < Level::Warn {
+            return;
+        }
+    }
+    eprintln!("{time} [{}] {target}: {args}", level.colored_tag());
+}
+
+impl LogFile {
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |i: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{i}"));
+            PathBuf::from(path)
+        };
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            _ = fs::remove_file(rotated(self.keep));
+            for i in (1..self.keep).rev() {
+                _ = fs::rename(rotated(i), rotated(i + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = File::options().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// `YYYY-MM-DD hh:mm:ss.mmm` in UTC
+fn timestamp(time: SystemTime) -> String {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let (days, ms) = (millis / 86_400_000, millis % 86_400_000);
+    // days since 1970-01-01 to a date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Level::Debug, module_path!(), format_args!($($arg)*))
+    };
+}
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Level::Info, module_path!(), format_args!($($arg)*))
+    };
+}
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Level::Warn, module_path!(), format_args!($($arg)*))
+    };
+}
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Level::Error, module_path!(), format_args!($($arg)*))
+    };
+}
+
+#[test]
+fn test_timestamp() {
+    use std::time::Duration;
+    assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01 00:00:00.000");
+    assert_eq!(
+        timestamp(UNIX_EPOCH + Duration::from_millis(1_709_210_096_789)),
+        "2024-02-29 12:34:56.789"
+    );
+}
+
+#[test]
+fn test_log_rotation() {
+    let dir = crate::test_util::test_dir("log");
+    let path = dir.join("log");
+    let mut file = LogFile {
+        file: File::create(&path).unwrap(),
+        path: path.clone(),
+        size: 0,
+        max_size: 10,
+        keep: 2,
+    };
+    for line in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+        file.write(line.as_bytes()).unwrap();
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), "dddddd\n");
+    assert_eq!(fs::read_to_string(dir.join("log.1")).unwrap(), "cccccc\n");
+    assert_eq!(fs::read_to_string(dir.join("log.2")).unwrap(), "bbbbbb\n");
+    assert!(!dir.join("log.3").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
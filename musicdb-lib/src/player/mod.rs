@@ -9,8 +9,10 @@ pub type PlayerBackendFeat<T> = rodio::PlayerBackendRodio<T>;
 
 use std::{collections::HashMap, ffi::OsStr, sync::Arc};
 
+use colorize::AnsiColor;
+
 use crate::{
-    data::{database::Database, song::CachedData, SongId},
+    data::{database::Database, replay_gain::ReplayGainMode, song::CachedData, SongId},
     server::Action,
 };
 
@@ -18,18 +20,28 @@ pub struct Player<T: PlayerBackend<SongCustomData>> {
     cached: HashMap<SongId, CachedData>,
     pub backend: T,
     allow_sending_commands: bool,
+    /// set if a seek went past the end of the current song, handled like `NextSong` in `update`
+    seek_past_end: bool,
+    /// the volume which was last given to the backend
+    volume: Option<f32>,
+    /// the ReplayGain mode and pre-amp which were used to load the next song
+    replay_gain: (ReplayGainMode, f32),
 }
 
 pub struct SongCustomData {
     load_duration: bool,
 }
 pub trait PlayerBackend<T> {
-    /// load the next song from its bytes
+    /// load the next song from its bytes.
+    /// if possible, the backend should prepare the song so that it starts right after the current one ends (gapless playback).
+    /// in that case, `song_finished` should become true when the current song ends, and `next` should then not interrupt the already playing next song.
+    /// `gain` is a linear factor (from ReplayGain) which should be applied to this song in addition to the volume.
     fn load_next_song(
         &mut self,
         id: SongId,
         filename: &OsStr,
         bytes: Arc<Vec<u8>>,
+        gain: f32,
         load_duration: bool,
         custom_data: T,
     );
@@ -44,7 +56,8 @@ pub trait PlayerBackend<T> {
 
     /// stop and discard the currently playing song, then set the next song as the current one.
     /// `play` decides whether the next song should start playing or not.
-    fn next(&mut self, play: bool, load_duration: bool);
+    /// if `crossfade_millis` isn't 0, the current song should fade out while the next one fades in.
+    fn next(&mut self, play: bool, load_duration: bool, crossfade_millis: u64);
     /// stop and discard the currently playing and next song.
     /// calling `resume` after this was called but before a new song was loaded does nothing.
     fn clear(&mut self);
@@ -81,6 +94,16 @@ pub trait PlayerBackend<T> {
 
     /// If known, get the current playback position in the song, in milliseconds.
     fn current_song_playback_position(&self) -> Option<u64>;
+    /// Jump to this position (in milliseconds) in the current song.
+    /// Will not be called with positions after the end of the song, if `current_song_duration` is known.
+    /// Returns `false` if seeking isn't possible.
+    fn seek(&mut self, pos_millis: u64) -> bool;
+    /// Set the output volume (0.0 to 1.0), also for songs which will be played later.
+    /// If possible, the change should be ramped over a few milliseconds to avoid clicks.
+    fn set_volume(&mut self, volume: f32);
+    /// If `false`, `next` will always be called with a `crossfade_millis` of 0
+    /// and songs won't be skipped before they end.
+    fn can_crossfade(&self) -> bool;
 }
 
 impl<T: PlayerBackend<SongCustomData>> Player<T> {
@@ -89,6 +112,9 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
             cached: HashMap::new(),
             backend,
             allow_sending_commands: true,
+            seek_past_end: false,
+            volume: None,
+            replay_gain: (ReplayGainMode::Off, 0.0),
         }
     }
     pub fn new_client(backend: T) -> Self {
@@ -96,6 +122,9 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
             cached: HashMap::new(),
             backend,
             allow_sending_commands: false,
+            seek_past_end: false,
+            volume: None,
+            replay_gain: (ReplayGainMode::Off, 0.0),
         }
     }
     pub fn handle_action(&mut self, action: &Action) {
@@ -103,9 +132,32 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
             Action::Resume => self.resume(),
             Action::Pause => self.pause(),
             Action::Stop => self.stop(),
+            Action::Seek(pos) => self.seek(*pos),
+            Action::SeekBy(by) => {
+                if let Some(pos) = self.backend.current_song_playback_position() {
+                    self.seek(pos.saturating_add_signed(*by));
+                }
+            }
             _ => {}
         }
     }
+    /// the current song and the position in it, see `Database::playback_position`
+    pub fn song_position(&self) -> Option<(SongId, u64)> {
+        let (id, _, _) = self.backend.current_song()?;
+        Some((id, self.backend.current_song_playback_position()?))
+    }
+    /// seeking past the end of the song skips to the next song (if this player can send commands)
+    pub fn seek(&mut self, pos_millis: u64) {
+        if self
+            .backend
+            .current_song_duration()
+            .is_some_and(|duration| pos_millis >= duration)
+        {
+            self.seek_past_end = true;
+        } else if !self.backend.seek(pos_millis) {
+            log_warn!("Couldn't seek to {pos_millis}ms");
+        }
+    }
     pub fn pause(&mut self) {
         self.backend.pause();
     }
@@ -116,6 +168,33 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
         self.backend.stop();
     }
 
+    /// true if the current song is about to end and the crossfade to the next one should start now.
+    /// the overlap is at most half of the song's duration.
+    fn crossfade_should_start(&self, db: &Database) -> bool {
+        if db.crossfade_millis == 0
+            || !db.playing
+            || !self.backend.can_crossfade()
+            // don't cut off the last song in the queue
+            || db.queue.get_next_song(db).is_none()
+        {
+            return false;
+        }
+        let Some((id, _, _)) = self.backend.current_song() else {
+            return false;
+        };
+        let Some(pos) = self.backend.current_song_playback_position() else {
+            return false;
+        };
+        let duration = match self.backend.current_song_duration() {
+            Some(duration) => duration,
+            None => match db.get_song(&id) {
+                Some(song) if song.duration_millis > 0 => song.duration_millis,
+                _ => return false,
+            },
+        };
+        pos + (db.crossfade_millis as u64).min(duration / 2) >= duration
+    }
+
     pub fn update(&mut self, db: &mut Database) {
         self.update_uncache_opt(db, true)
     }
@@ -124,14 +203,28 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
         self.update_uncache_opt(db, false)
     }
     pub fn update_uncache_opt(&mut self, db: &mut Database, allow_uncaching: bool) {
+        let seek_past_end = std::mem::take(&mut self.seek_past_end);
+        // true if we go to the next song because the current one (almost) ended
+        let mut natural_end = false;
         if self.allow_sending_commands {
-            if self.allow_sending_commands && self.backend.song_finished() {
+            if self.backend.song_finished() || self.crossfade_should_start(db) {
+                natural_end = true;
+                db.apply_action_unchecked_seq(Action::NextSong, None);
+            } else if seek_past_end {
                 db.apply_action_unchecked_seq(Action::NextSong, None);
             }
         }
+        let crossfade_millis = if self.backend.can_crossfade()
+            && db.playing
+            && (natural_end || !db.crossfade_natural_only)
+        {
+            db.crossfade_millis as u64
+        } else {
+            0
+        };
 
-        let queue_current_song = db.queue.get_current_song().copied();
-        let queue_next_song = db.queue.get_next_song().copied();
+        let queue_current_song = db.queue.get_current_song(db).copied();
+        let queue_next_song = db.queue.get_next_song(db).copied();
 
         match (self.backend.current_song().map(|v| v.0), queue_current_song) {
             (None, None) => (),
@@ -142,7 +235,8 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
                         .backend
                         .next_song()
                         .is_some_and(|(_, _, t)| t.load_duration);
-                    self.backend.next(db.playing, load_duration);
+                    self.backend
+                        .next(db.playing, load_duration, crossfade_millis);
                     if self.allow_sending_commands && load_duration {
                         if let Some(dur) = self.backend.current_song_duration() {
                             db.apply_action_unchecked_seq(Action::SetSongDuration(id, dur), None)
@@ -163,10 +257,12 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
                                 .file_name()
                                 .unwrap_or_else(|| OsStr::new("")),
                             bytes,
+                            db.replay_gain.gain(song, db.replay_gain_preamp_db),
                             load_duration,
                             SongCustomData { load_duration },
                         );
-                        self.backend.next(db.playing, load_duration);
+                        self.backend
+                            .next(db.playing, load_duration, crossfade_millis);
                         if self.allow_sending_commands && load_duration {
                             if let Some(dur) = self.backend.current_song_duration() {
                                 db.apply_action_unchecked_seq(
@@ -199,9 +295,13 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
             }
             (Some(_), None) => self.backend.clear(),
         }
+        // if the ReplayGain settings changed, load the next song again so it uses the new gain
+        let replay_gain = (db.replay_gain, db.replay_gain_preamp_db);
+        let replay_gain_changed = self.replay_gain != replay_gain;
+        self.replay_gain = replay_gain;
         match (self.backend.next_song().map(|v| v.0), queue_next_song) {
             (None, None) => (),
-            (Some(a), Some(b)) if a == b => (),
+            (Some(a), Some(b)) if a == b && !replay_gain_changed => (),
             (_, Some(id)) => {
                 if let Some(song) = db.get_song(&id) {
                     self.cached.insert(id, song.cached_data().clone());
@@ -216,6 +316,7 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
                                 .file_name()
                                 .unwrap_or_else(|| OsStr::new("")),
                             bytes,
+                            db.replay_gain.gain(song, db.replay_gain_preamp_db),
                             load_duration,
                             SongCustomData { load_duration },
                         );
@@ -224,6 +325,10 @@ impl<T: PlayerBackend<SongCustomData>> Player<T> {
             }
             (Some(_), None) => (),
         }
+        if self.volume != Some(db.volume) {
+            self.volume = Some(db.volume);
+            self.backend.set_volume(db.volume);
+        }
         if db.playing != self.backend.playing() {
             if db.playing {
                 self.backend.resume();
@@ -13,7 +13,13 @@ pub struct PlayerBackendPlaybackRs<T> {
     player: playback_rs::Player,
     current: Option<(SongId, Option<playback_rs::Song>, T)>,
     next: Option<(SongId, Option<playback_rs::Song>, T)>,
+    /// true if `next` was given to the player so it starts right after the current song (gapless)
+    next_queued: bool,
     command_sender: Option<std::sync::mpsc::Sender<(Command, Option<u64>)>>,
+    volume: f32,
+    /// ReplayGain factors of the current and next song
+    current_gain: f32,
+    next_gain: f32,
 }
 
 impl<T> PlayerBackendPlaybackRs<T> {
@@ -32,7 +38,11 @@ impl<T> PlayerBackendPlaybackRs<T> {
             player: playback_rs::Player::new(None)?,
             current: None,
             next: None,
+            next_queued: false,
             command_sender,
+            volume: 1.0,
+            current_gain: 1.0,
+            next_gain: 1.0,
         })
     }
 }
@@ -43,6 +53,7 @@ impl<T> PlayerBackend<T> for PlayerBackendPlaybackRs<T> {
         id: SongId,
         filename: &OsStr,
         bytes: Arc<Vec<u8>>,
+        gain: f32,
         _load_duration: bool,
         custom_data: T,
     ) {
@@ -68,20 +79,22 @@ impl<T> PlayerBackend<T> for PlayerBackendPlaybackRs<T> {
                 None
             }
         };
-        // if let Some(song) = &loaded_song {
-        //     if self.player.has_current_song() {
-        //         if let Err(e) = self.player.play_song_next(song, None) {
-        //             if let Some(s) = &self.command_sender {
-        //                 s.send(Command::ErrorInfo(
-        //                     format!("Couldn't preload song #{id}!"),
-        //                     format!("Error: {e}"),
-        //                 ))
-        //                 .unwrap();
-        //             }
-        //         }
-        //     }
-        // }
+        // queue the song in the player, so that it starts playing
+        // as soon as the current one ends, without a gap.
+        // if there is no current song, `next` will start it instead.
+        let was_queued = std::mem::replace(&mut self.next_queued, false);
+        if self.current.is_some() && self.player.has_current_song() {
+            if let Some(song) = &loaded_song {
+                self.next_queued = self
+                    .player
+                    .play_song_next(&song.with_volume_adjustment(self.volume * gain), None)
+                    .is_ok();
+            } else if was_queued && self.player.has_next_song() {
+                let _ = self.player.force_remove_next_song();
+            }
+        }
         self.next = Some((id, loaded_song, custom_data));
+        self.next_gain = gain;
     }
     fn pause(&mut self) {
         self.player.set_playing(false);
@@ -93,14 +106,29 @@ impl<T> PlayerBackend<T> for PlayerBackendPlaybackRs<T> {
     fn resume(&mut self) {
         self.player.set_playing(true);
     }
-    fn next(&mut self, play: bool, _load_duration: bool) {
+    fn next(&mut self, play: bool, _load_duration: bool, _crossfade_millis: u64) {
+        if std::mem::replace(&mut self.next_queued, false) {
+            // the next song is already in the player. if the current song hasn't
+            // finished yet, skip it, otherwise the next song is already playing.
+            if self.player.has_next_song() {
+                self.player.skip();
+            }
+            self.current = self.next.take();
+            self.current_gain = self.next_gain;
+            self.player.set_playing(play);
+            return;
+        }
         self.pause();
         self.player.stop();
         self.player.skip();
         self.current = self.next.take();
+        self.current_gain = self.next_gain;
         if let Some((id, song, _)) = &self.current {
             if let Some(song) = song {
-                if let Err(e) = self.player.play_song_now(song, None) {
+                if let Err(e) = self.player.play_song_now(
+                    &song.with_volume_adjustment(self.volume * self.current_gain),
+                    None,
+                ) {
                     if let Some(s) = &self.command_sender {
                         s.send((
                             Action::ErrorInfo(
@@ -128,6 +156,7 @@ impl<T> PlayerBackend<T> for PlayerBackendPlaybackRs<T> {
         let _ = self.player.force_remove_next_song();
         self.current = None;
         self.next = None;
+        self.next_queued = false;
     }
     fn playing(&self) -> bool {
         self.player.is_playing()
@@ -148,7 +177,9 @@ impl<T> PlayerBackend<T> for PlayerBackendPlaybackRs<T> {
         true
     }
     fn song_finished(&self) -> bool {
-        self.current.is_some() && !self.player.has_current_song()
+        self.current.is_some()
+            && (!self.player.has_current_song()
+                || (self.next_queued && !self.player.has_next_song()))
     }
     fn current_song_duration(&self) -> Option<u64> {
         self.player
@@ -160,6 +191,49 @@ impl<T> PlayerBackend<T> for PlayerBackendPlaybackRs<T> {
             .get_playback_position()
             .map(|v| v.0.as_millis() as _)
     }
+    fn seek(&mut self, pos_millis: u64) -> bool {
+        self.player.seek(Duration::from_millis(pos_millis))
+    }
+    fn can_crossfade(&self) -> bool {
+        // playback-rs can only play one song at a time
+        false
+    }
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        // playback-rs can't change the volume of a song while it is playing,
+        // so the current song is restarted at its current position (no ramp).
+        if self.song_finished() {
+            return;
+        }
+        if let Some((_, Some(song), _)) = &self.current {
+            if let Some((pos, _)) = self.player.get_playback_position() {
+                let playing = self.player.is_playing();
+                if self
+                    .player
+                    .play_song_now(
+                        &song.with_volume_adjustment(volume * self.current_gain),
+                        Some(pos),
+                    )
+                    .is_ok()
+                {
+                    self.player.set_playing(playing);
+                }
+                // `play_song_now` also removed the queued song
+                if self.next_queued {
+                    self.next_queued = false;
+                    if let Some((_, Some(next), _)) = &self.next {
+                        self.next_queued = self
+                            .player
+                            .play_song_next(
+                                &next.with_volume_adjustment(volume * self.next_gain),
+                                None,
+                            )
+                            .is_ok();
+                    }
+                }
+            }
+        }
+    }
 }
 
 pub struct ArcVec(pub Arc<Vec<u8>>);
@@ -1,7 +1,18 @@
-use std::{ffi::OsStr, sync::Arc};
+use std::{
+    ffi::OsStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
+use colorize::AnsiColor;
 use rc_u8_reader::ArcU8Reader;
-use rodio::{decoder::DecoderError, Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use rodio::{
+    decoder::DecoderError, source::SeekError, Decoder, OutputStream, OutputStreamHandle, Sample,
+    Sink, Source,
+};
 
 use crate::{
     data::SongId,
@@ -15,10 +26,22 @@ pub struct PlayerBackendRodio<T> {
     output_stream: OutputStream,
     #[allow(unused)]
     output_stream_handle: OutputStreamHandle,
-    sink: Sink,
+    sink: Arc<Sink>,
+    /// while crossfading, the sink which is playing the previous song
+    fading_sink: Option<Arc<Sink>>,
+    volume: f32,
+    /// ReplayGain factors of the current and next song
+    current_gain: f32,
+    next_gain: f32,
+    /// incremented whenever a new volume ramp starts, so older ramps stop
+    volume_ramp: Arc<AtomicUsize>,
     stopped: bool,
     current: Option<(SongId, Arc<Vec<u8>>, Option<u128>, T)>,
+    /// state of the current song's source in the sink
+    current_source: Option<SourceState>,
     next: Option<(SongId, Arc<Vec<u8>>, Option<MyDecoder>, T)>,
+    /// if `Some`, `next` was already appended to the sink so it plays right after the current song (gapless)
+    next_source: Option<SourceState>,
     command_sender: Option<std::sync::mpsc::Sender<(Command, Option<u64>)>>,
 }
 
@@ -39,10 +62,17 @@ impl<T> PlayerBackendRodio<T> {
         Ok(Self {
             output_stream,
             output_stream_handle,
-            sink,
+            sink: Arc::new(sink),
+            fading_sink: None,
+            volume: 1.0,
+            current_gain: 1.0,
+            next_gain: 1.0,
+            volume_ramp: Arc::new(AtomicUsize::new(0)),
             stopped: true,
             current: None,
+            current_source: None,
             next: None,
+            next_source: None,
             command_sender,
         })
     }
@@ -54,6 +84,7 @@ impl<T> PlayerBackend<T> for PlayerBackendRodio<T> {
         id: SongId,
         _filename: &OsStr,
         bytes: Arc<Vec<u8>>,
+        gain: f32,
         _load_duration: bool,
         custom_data: T,
     ) {
@@ -71,17 +102,45 @@ impl<T> PlayerBackend<T> for PlayerBackendRodio<T> {
                 .unwrap();
             }
         }
-        self.next = Some((id, bytes, decoder.ok(), custom_data));
+        // a previously appended next song must not be played anymore
+        if let Some(old) = self.next_source.take() {
+            old.skip.store(true, Ordering::Relaxed);
+        }
+        let mut decoder = decoder.ok();
+        // append the song to the sink now, so that it starts playing
+        // as soon as the current one ends, without a gap.
+        // if there is no current song, `next` will start it instead.
+        if self
+            .current_source
+            .as_ref()
+            .is_some_and(|s| !s.done.load(Ordering::Relaxed))
+        {
+            if let Some(decoder) = decoder.take() {
+                self.next_source = Some(append_to_sink(&self.sink, decoder, gain));
+            }
+        }
+        self.next = Some((id, bytes, decoder, custom_data));
+        self.next_gain = gain;
     }
     fn pause(&mut self) {
+        self.stop_fading();
         self.sink.pause();
     }
     fn stop(&mut self) {
+        self.stop_fading();
         if !self.stopped {
             self.sink.clear();
+            self.current_source = None;
             if let Some((_, bytes, _, _)) = &self.current {
                 if let Ok(decoder) = decoder_from_bytes(Arc::clone(bytes)) {
-                    self.sink.append(decoder);
+                    self.current_source =
+                        Some(append_to_sink(&self.sink, decoder, self.current_gain));
+                }
+            }
+            // the appended next song was cleared too, so it has to be decoded again
+            if self.next_source.take().is_some() {
+                if let Some((_, bytes, decoder, _)) = &mut self.next {
+                    *decoder = decoder_from_bytes(Arc::clone(bytes)).ok();
                 }
             }
         }
@@ -90,9 +149,68 @@ impl<T> PlayerBackend<T> for PlayerBackendRodio<T> {
         self.stopped = false;
         self.sink.play();
     }
-    fn next(&mut self, play: bool, load_duration: bool) {
+    fn next(&mut self, play: bool, load_duration: bool, crossfade_millis: u64) {
         self.stopped = false;
+        self.stop_fading();
+        if crossfade_millis > 0 && self.playing() {
+            // keep the current song playing in its own sink while it fades out,
+            // and start the next song in a new sink (the output stream mixes them).
+            match Sink::try_new(&self.output_stream_handle) {
+                Ok(sink) => {
+                    sink.set_volume(0.0);
+                    let old_sink = std::mem::replace(&mut self.sink, Arc::new(sink));
+                    // if the next song was appended to the old sink, it has to be decoded again
+                    if let Some(next_source) = self.next_source.take() {
+                        next_source.skip.store(true, Ordering::Relaxed);
+                        if let Some((_, bytes, decoder, _)) = &mut self.next {
+                            *decoder = decoder_from_bytes(Arc::clone(bytes)).ok();
+                        }
+                    }
+                    self.current_source = None;
+                    self.fading_sink = Some(Arc::clone(&old_sink));
+                    std::thread::spawn(move || {
+                        let start = old_sink.volume();
+                        let steps = (crossfade_millis / VOLUME_RAMP_STEP_MILLIS).max(1);
+                        for i in 1..=steps {
+                            if old_sink.empty() {
+                                break;
+                            }
+                            old_sink.set_volume(start * (1.0 - i as f32 / steps as f32));
+                            std::thread::sleep(Duration::from_millis(VOLUME_RAMP_STEP_MILLIS));
+                        }
+                        old_sink.stop();
+                    });
+                    self.ramp_volume(self.volume, crossfade_millis);
+                }
+                Err(e) => log_warn!("Couldn't create sink for crossfade: {e}"),
+            }
+        }
+        if let Some(next_source) = self.next_source.take() {
+            // the next song is already in the sink. if the current song
+            // hasn't finished yet, skip it, otherwise it is already playing.
+            if let Some(current) = self.current_source.take() {
+                current.skip.store(true, Ordering::Relaxed);
+            }
+            self.current_source = Some(next_source);
+            self.current_gain = self.next_gain;
+            self.current = self.next.take().map(|(id, bytes, _, custom_data)| {
+                let duration = if load_duration {
+                    decoder_from_bytes(Arc::clone(&bytes))
+                        .ok()
+                        .and_then(|d| d.total_duration())
+                        .map(|v| v.as_millis())
+                } else {
+                    None
+                };
+                (id, bytes, duration, custom_data)
+            });
+            if play {
+                self.sink.play();
+            }
+            return;
+        }
         self.sink.clear();
+        self.current_source = None;
         self.current = self
             .next
             .take()
@@ -103,7 +221,7 @@ impl<T> PlayerBackend<T> for PlayerBackendRodio<T> {
                     } else {
                         None
                     };
-                    self.sink.append(decoder);
+                    self.current_source = Some(append_to_sink(&self.sink, decoder, self.next_gain));
                     if play {
                         self.sink.play();
                     }
@@ -113,9 +231,13 @@ impl<T> PlayerBackend<T> for PlayerBackendRodio<T> {
                 };
                 (id, bytes, duration, custom_data)
             });
+        self.current_gain = self.next_gain;
     }
     fn clear(&mut self) {
+        self.stop_fading();
         self.sink.clear();
+        self.current_source = None;
+        self.next_source = None;
     }
     fn playing(&self) -> bool {
         !(self.sink.is_paused() || self.sink.empty())
@@ -136,7 +258,11 @@ impl<T> PlayerBackend<T> for PlayerBackendRodio<T> {
         true
     }
     fn song_finished(&self) -> bool {
-        self.current.is_some() && self.sink.empty()
+        self.current.is_some()
+            && self
+                .current_source
+                .as_ref()
+                .is_none_or(|s| s.done.load(Ordering::Relaxed))
     }
     fn current_song_duration(&self) -> Option<u64> {
         self.current
@@ -144,7 +270,126 @@ impl<T> PlayerBackend<T> for PlayerBackendRodio<T> {
             .and_then(|(_, _, dur, _)| dur.map(|v| v as _))
     }
     fn current_song_playback_position(&self) -> Option<u64> {
-        None
+        if self.current.is_some() {
+            Some(self.sink.get_pos().as_millis() as _)
+        } else {
+            None
+        }
+    }
+    fn seek(&mut self, pos_millis: u64) -> bool {
+        // decoders which can't seek directly are re-decoded from the start by rodio
+        self.sink
+            .try_seek(Duration::from_millis(pos_millis))
+            .is_ok()
+    }
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        // ramp to the new volume over 50ms to avoid clicks
+        self.ramp_volume(volume, 50);
+    }
+    fn can_crossfade(&self) -> bool {
+        true
+    }
+}
+
+const VOLUME_RAMP_STEP_MILLIS: u64 = 5;
+
+impl<T> PlayerBackendRodio<T> {
+    /// change the sink's volume to `target` over `millis` milliseconds.
+    /// a newer ramp cancels this one.
+    fn ramp_volume(&self, target: f32, millis: u64) {
+        let sink = Arc::clone(&self.sink);
+        let volume_ramp = Arc::clone(&self.volume_ramp);
+        let ramp = volume_ramp.fetch_add(1, Ordering::SeqCst) + 1;
+        std::thread::spawn(move || {
+            let start = sink.volume();
+            let steps = (millis / VOLUME_RAMP_STEP_MILLIS).max(1);
+            for i in 1..=steps {
+                if volume_ramp.load(Ordering::SeqCst) != ramp {
+                    break;
+                }
+                sink.set_volume(start + (target - start) * i as f32 / steps as f32);
+                std::thread::sleep(Duration::from_millis(VOLUME_RAMP_STEP_MILLIS));
+            }
+        });
+    }
+    /// stop the previous song if it is still fading out
+    fn stop_fading(&mut self) {
+        if let Some(sink) = self.fading_sink.take() {
+            sink.stop();
+        }
+    }
+}
+
+fn append_to_sink(sink: &Sink, decoder: MyDecoder, gain: f32) -> SourceState {
+    let state = SourceState {
+        skip: Arc::new(AtomicBool::new(false)),
+        done: Arc::new(AtomicBool::new(false)),
+    };
+    sink.append(SkippableSource {
+        inner: decoder,
+        gain,
+        skip: Arc::clone(&state.skip),
+        done: Arc::clone(&state.done),
+    });
+    state
+}
+
+struct SourceState {
+    /// set this to make the source end immediately
+    skip: Arc<AtomicBool>,
+    /// set by the source once it has ended
+    done: Arc<AtomicBool>,
+}
+
+/// a source which can be ended from outside the sink
+/// and which reports when it has ended.
+struct SkippableSource<S> {
+    inner: S,
+    /// ReplayGain factor
+    gain: f32,
+    skip: Arc<AtomicBool>,
+    done: Arc<AtomicBool>,
+}
+impl<S: Source> Iterator for SkippableSource<S>
+where
+    S::Item: Sample,
+{
+    type Item = S::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = if self.skip.load(Ordering::Relaxed) {
+            None
+        } else {
+            self.inner.next()
+        };
+        if sample.is_none() {
+            self.done.store(true, Ordering::Relaxed);
+        }
+        if self.gain != 1.0 {
+            sample.map(|s| s.amplify(self.gain))
+        } else {
+            sample
+        }
+    }
+}
+impl<S: Source> Source for SkippableSource<S>
+where
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
     }
 }
 
@@ -0,0 +1,145 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{connection::Connection, permission::Permission};
+
+/// how long a client has to send its token before the connection is dropped
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// after this many failed attempts, connections from the same peer are rejected ...
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+/// ... until this long after the last failed attempt.
+const FAILED_ATTEMPTS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Optional shared secrets. If the server has any, every connection has to start with `auth <token>`
+/// (before `main`, `control` or `get`), and the server responds with `auth ok` or `auth failed`.
+/// Each token grants a permission level.
+pub struct Auth {
+    tokens: Vec<(String, Permission)>,
+    /// number of failed attempts and the time of the last one, per peer
+    failed_attempts: Mutex<HashMap<String, (u32, Instant)>>,
+}
+
+impl Auth {
+    pub fn new(tokens: Vec<(String, Permission)>) -> Self {
+        Self {
+            tokens,
+            failed_attempts: Mutex::new(HashMap::new()),
+        }
+    }
+    /// reads the `auth <token>` line and responds to it.
+    /// returns the token's permission if the client may continue, `None` if the connection should be dropped.
+    pub fn authenticate(
+        &self,
+        connection: &mut BufReader<Box<dyn Connection>>,
+    ) -> Option<Permission> {
+        let peer = connection.get_ref().peer_name();
+        let limit_key = rate_limit_key(&**connection.get_ref());
+        if limit_key.as_ref().is_some_and(|key| self.is_blocked(key)) {
+            return None;
+        }
+        _ = connection
+            .get_ref()
+            .set_read_timeout_connection(Some(AUTH_TIMEOUT));
+        let mut line = String::new();
+        // limit the length so clients can't make us buffer arbitrary amounts of data
+        let read = connection.by_ref().take(1024).read_line(&mut line);
+        _ = connection.get_ref().set_read_timeout_connection(None);
+        if read.is_err() {
+            log_warn!("Connection from {peer} didn't authenticate in time");
+            return None;
+        }
+        let permission = line
+            .trim_end_matches(['\r', '\n'])
+            .strip_prefix("auth ")
+            .and_then(|token| {
+                self.tokens
+                    .iter()
+                    .find(|(t, _)| tokens_eq(token.as_bytes(), t.as_bytes()))
+                    .map(|(_, permission)| *permission)
+            });
+        if let Some(permission) = permission {
+            if let Some(key) = &limit_key {
+                self.failed_attempts.lock().unwrap().remove(key);
+            }
+            writeln!(connection.get_mut(), "auth ok").ok()?;
+            Some(permission)
+        } else {
+            if let Some(key) = &limit_key {
+                let attempts = self.failed(key);
+                log_warn!("Failed authentication attempt from {peer} ({attempts} in a row)");
+                if attempts == MAX_FAILED_ATTEMPTS {
+                    log_warn!(
+                        "Rejecting connections from {peer} for {}s",
+                        FAILED_ATTEMPTS_WINDOW.as_secs()
+                    );
+                }
+            } else {
+                log_warn!("Failed authentication attempt from {peer}");
+            }
+            // slow down guessing
+            std::thread::sleep(Duration::from_secs(1));
+            _ = writeln!(connection.get_mut(), "auth failed");
+            None
+        }
+    }
+    fn is_blocked(&self, peer: &str) -> bool {
+        self.failed_attempts
+            .lock()
+            .unwrap()
+            .get(peer)
+            .is_some_and(|(count, last)| {
+                *count >= MAX_FAILED_ATTEMPTS && last.elapsed() < FAILED_ATTEMPTS_WINDOW
+            })
+    }
+    /// records a failed attempt and returns the number of recent failed attempts from this peer
+    fn failed(&self, peer: &str) -> u32 {
+        let mut failed_attempts = self.failed_attempts.lock().unwrap();
+        failed_attempts.retain(|_, (_, last)| last.elapsed() < FAILED_ATTEMPTS_WINDOW);
+        let entry = failed_attempts
+            .entry(peer.to_owned())
+            .or_insert((0, Instant::now()));
+        entry.0 += 1;
+        entry.1 = Instant::now();
+        entry.0
+    }
+}
+
+/// the key under which failed attempts are counted, `None` if the connection isn't rate-limited.
+/// all unix socket clients have the same `peer_name`, so one misconfigured local client would lock out all the others.
+/// they still have to wait after a failed attempt.
+fn rate_limit_key(connection: &dyn Connection) -> Option<String> {
+    if connection.is_unix_socket() {
+        None
+    } else {
+        Some(connection.peer_name())
+    }
+}
+
+/// compares the tokens without returning early, so the time taken doesn't reveal how much of the token was correct
+pub fn tokens_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[test]
+fn test_tokens_eq() {
+    assert!(tokens_eq(b"secret", b"secret"));
+    assert!(!tokens_eq(b"secret", b"secreT"));
+    assert!(!tokens_eq(b"secret", b"secret2"));
+    assert!(!tokens_eq(b"", b"secret"));
+}
+
+#[test]
+fn test_rate_limit_key() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let tcp = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    assert_eq!(rate_limit_key(&tcp).as_deref(), Some("127.0.0.1"));
+    #[cfg(unix)]
+    {
+        let (unix, _) = std::os::unix::net::UnixStream::pair().unwrap();
+        assert_eq!(rate_limit_key(&unix), None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::load::ToFromBytes;
+
+/// what a client sends after `main` to advertise that it can decode compressed actions.
+/// the server confirms by responding with the same line, then sends `SyncDatabase` compressed.
+/// other actions are small, so they are never compressed.
+pub const OPTION_DEFLATE: &str = "compression=deflate";
+
+/// compressed data is split into chunks of at most this many bytes
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Writes a deflate-compressed stream as length-prefixed chunks, ending with an empty chunk,
+/// so that the reader knows where the compressed data ends without reading too far.
+/// Nothing has to be buffered except for the current chunk.
+pub struct CompressedWriter<W: Write>(DeflateEncoder<ChunkWriter<W>>);
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self(DeflateEncoder::new(
+            ChunkWriter(writer, Vec::with_capacity(CHUNK_SIZE)),
+            Compression::default(),
+        ))
+    }
+    /// writes the remaining compressed data and the final empty chunk
+    pub fn finish(self) -> std::io::Result<W> {
+        let mut chunks = self.0.finish()?;
+        chunks.write_chunk()?;
+        0u32.to_bytes(&mut chunks.0)?;
+        Ok(chunks.0)
+    }
+}
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Reads what a `CompressedWriter` wrote. Reads exactly up to the end of the compressed data, and no further.
+pub struct CompressedReader<R: Read>(DeflateDecoder<ChunkReader<R>>);
+impl<R: Read> CompressedReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self(DeflateDecoder::new(ChunkReader(reader, 0, false)))
+    }
+    /// skips any remaining data, so the underlying reader is positioned right after the compressed data
+    pub fn finish(mut self) -> std::io::Result<R> {
+        std::io::copy(&mut self.0, &mut std::io::sink())?;
+        let mut chunks = self.0.into_inner();
+        std::io::copy(&mut chunks, &mut std::io::sink())?;
+        Ok(chunks.0)
+    }
+}
+impl<R: Read> Read for CompressedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+struct ChunkWriter<W: Write>(W, Vec<u8>);
+impl<W: Write> ChunkWriter<W> {
+    fn write_chunk(&mut self) -> std::io::Result<()> {
+        if !self.1.is_empty() {
+            (self.1.len() as u32).to_bytes(&mut self.0)?;
+            self.0.write_all(&self.1)?;
+            self.1.clear();
+        }
+        Ok(())
+    }
+}
+impl<W: Write> Write for ChunkWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(CHUNK_SIZE - self.1.len());
+        self.1.extend_from_slice(&buf[..len]);
+        if self.1.len() >= CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(len)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_chunk()?;
+        self.0.flush()
+    }
+}
+
+/// the reader, the number of bytes left in the current chunk, and if the final chunk was reached
+struct ChunkReader<R: Read>(R, usize, bool);
+impl<R: Read> Read for ChunkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.1 == 0 && !self.2 {
+            self.1 = u32::from_bytes(&mut self.0)? as usize;
+            self.2 = self.1 == 0;
+        }
+        if self.2 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.1);
+        let len = self.0.read(&mut buf[..len])?;
+        if len == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        self.1 -= len;
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_compressed_roundtrip() {
+    // more than one chunk, even after compression
+    let data = (0..400_000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 7) as u8)
+        .collect::<Vec<_>>();
+    let mut writer = CompressedWriter::new(vec![]);
+    writer.write_all(&data).unwrap();
+    let mut bytes = writer.finish().unwrap();
+    // data after the compressed data must not be consumed
+    bytes.extend_from_slice(b"after");
+    let mut reader = CompressedReader::new(&bytes[..]);
+    let mut decompressed = vec![];
+    reader.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, data);
+    let rest = reader.finish().unwrap();
+    assert_eq!(rest, b"after");
+}
@@ -0,0 +1,224 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+/// A connection to or from a server, currently either a tcp stream or a unix domain socket.
+pub trait Connection: Read + Write + Send + Sync + 'static {
+    fn try_clone_connection(&self) -> std::io::Result<Box<dyn Connection>>;
+    fn shutdown_connection(&self) -> std::io::Result<()>;
+    fn set_read_timeout_connection(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn set_write_timeout_connection(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    /// who is on the other end, used for logging and rate-limiting
+    fn peer_name(&self) -> String;
+    /// `true` for unix sockets and loopback addresses
+    fn is_local(&self) -> bool;
+    /// `true` for unix sockets, where all clients have the same `peer_name`
+    fn is_unix_socket(&self) -> bool;
+}
+
+impl Connection for TcpStream {
+    fn try_clone_connection(&self) -> std::io::Result<Box<dyn Connection>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+    fn shutdown_connection(&self) -> std::io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+    fn set_read_timeout_connection(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.set_read_timeout(timeout)
+    }
+    fn set_write_timeout_connection(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.set_write_timeout(timeout)
+    }
+    fn peer_name(&self) -> String {
+        match self.peer_addr() {
+            Ok(addr) => addr.ip().to_string(),
+            Err(_) => "<unknown>".to_owned(),
+        }
+    }
+    fn is_local(&self) -> bool {
+        self.peer_addr().is_ok_and(|addr| addr.ip().is_loopback())
+    }
+    fn is_unix_socket(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn try_clone_connection(&self) -> std::io::Result<Box<dyn Connection>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+    fn shutdown_connection(&self) -> std::io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+    fn set_read_timeout_connection(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.set_read_timeout(timeout)
+    }
+    fn set_write_timeout_connection(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.set_write_timeout(timeout)
+    }
+    fn peer_name(&self) -> String {
+        "unix socket".to_owned()
+    }
+    fn is_local(&self) -> bool {
+        true
+    }
+    fn is_unix_socket(&self) -> bool {
+        true
+    }
+}
+
+/// Writes to the connection and shuts it down when dropped,
+/// so the other side notices when the server stops sending updates to it.
+pub struct ShutdownOnDrop(pub Box<dyn Connection>);
+impl Write for ShutdownOnDrop {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+impl Drop for ShutdownOnDrop {
+    fn drop(&mut self) {
+        _ = self.0.shutdown_connection();
+    }
+}
+
+/// The address of a server.
+/// Parsed from `unix:<path>` for unix domain sockets, or from `<ip>:<port>` for tcp.
+#[derive(Clone, Debug)]
+pub enum ServerAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ServerAddr {
+    pub fn connect(&self) -> std::io::Result<Box<dyn Connection>> {
+        match self {
+            Self::Tcp(addr) => Ok(Box::new(TcpStream::connect(addr)?)),
+            #[cfg(unix)]
+            Self::Unix(path) => Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?)),
+            #[cfg(not(unix))]
+            Self::Unix(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "unix domain sockets are not supported on this platform",
+            )),
+        }
+    }
+    /// connects to the server and, if a token is given, authenticates (see `authenticate`).
+    pub fn connect_with_auth(&self, token: Option<&str>) -> std::io::Result<Box<dyn Connection>> {
+        let mut connection = self.connect()?;
+        if let Some(token) = token {
+            authenticate(&mut connection, token)?;
+        }
+        Ok(connection)
+    }
+}
+
+/// Sends `auth <token>` and waits for the server's response.
+/// Has to happen before the first line (`main`, `control`, `get`) is sent.
+/// Servers which don't require a token accept any token.
+pub fn authenticate(connection: &mut (impl Write + Read), token: &str) -> std::io::Result<()> {
+    writeln!(connection, "auth {token}")?;
+    connection.flush()?;
+    if read_line_unbuffered(connection)? == b"auth ok" {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "authentication failed: the server rejected the token",
+        ))
+    }
+}
+
+/// Starts a `main` connection. If `compression` is true, asks the server to compress large updates,
+/// which `Command::from_bytes` decompresses transparently. Servers which don't support compression close the connection.
+pub fn start_main(connection: &mut (impl Write + Read), compression: bool) -> std::io::Result<()> {
+    if compression {
+        writeln!(connection, "main {}", super::compression::OPTION_DEFLATE)?;
+        connection.flush()?;
+        // the server confirms that it will use compression
+        read_line_unbuffered(connection)?;
+    } else {
+        writeln!(connection, "main")?;
+        connection.flush()?;
+    }
+    Ok(())
+}
+
+/// reads byte by byte, so nothing after the line is consumed. doesn't include the `\n`.
+fn read_line_unbuffered(connection: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut line = vec![];
+    let mut byte = [0];
+    loop {
+        connection.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break Ok(line);
+        }
+        line.push(byte[0]);
+    }
+}
+
+impl FromStr for ServerAddr {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            Ok(Self::Unix(path.into()))
+        } else {
+            s.parse()
+                .map(Self::Tcp)
+                .map_err(|e| format!("expected <ip>:<port> or unix:<path>: {e}"))
+        }
+    }
+}
+
+impl Display for ServerAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Something which accepts connections, see `ServerAddr`.
+pub trait Listener: Send + 'static {
+    fn accept_connection(&self) -> std::io::Result<Box<dyn Connection>>;
+}
+
+impl Listener for TcpListener {
+    fn accept_connection(&self) -> std::io::Result<Box<dyn Connection>> {
+        Ok(Box::new(self.accept()?.0))
+    }
+}
+
+#[cfg(unix)]
+impl Listener for std::os::unix::net::UnixListener {
+    fn accept_connection(&self) -> std::io::Result<Box<dyn Connection>> {
+        Ok(Box::new(self.accept()?.0))
+    }
+}
+
+/// Listen on a unix domain socket at `path`.
+/// If the socket file already exists but no server is listening on it, it is removed first.
+#[cfg(unix)]
+pub fn bind_unix(path: &std::path::Path) -> std::io::Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("another server is already listening on {}", path.display()),
+            ));
+        }
+        // stale socket file from a server which didn't shut down properly
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
@@ -0,0 +1,92 @@
+use std::io::{Read, Write};
+
+use crate::load::ToFromBytes;
+
+/// what a client sends after `get` to use binary framing instead of escaped lines.
+/// the server confirms by responding with the same line, then reads requests and sends responses as frames.
+/// requests which don't have a binary response yet are answered with their text response as the payload.
+pub const OPTION_FRAMED: &str = "protocol=2";
+
+/// a request is a u32 length followed by the request (`name\narg\n...`, not escaped).
+/// a response is a status byte, then a u32 length and the payload.
+/// for errors, the payload is a message.
+pub const STATUS_OK: u8 = 0;
+pub const STATUS_ERR: u8 = 1;
+
+/// longer requests are rejected and the connection is closed
+const MAX_REQUEST_LEN: u32 = 1024 * 1024;
+
+pub fn write_request(con: &mut impl Write, request: &str) -> std::io::Result<()> {
+    (request.len() as u32).to_bytes(con)?;
+    con.write_all(request.as_bytes())?;
+    con.flush()
+}
+
+/// `None` if the connection was closed before the next request
+pub fn read_request(con: &mut impl Read) -> std::io::Result<Option<String>> {
+    let mut len = [0; 4];
+    match con.read(&mut len[..1])? {
+        0 => return Ok(None),
+        _ => con.read_exact(&mut len[1..])?,
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_REQUEST_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("request too long ({len} bytes)"),
+        ));
+    }
+    let mut request = vec![0; len as usize];
+    con.read_exact(&mut request)?;
+    String::from_utf8(request)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// the payload has to be written afterwards
+pub fn write_response_header(con: &mut impl Write, status: u8, len: u64) -> std::io::Result<()> {
+    let Ok(len) = u32::try_from(len) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("response too long ({len} bytes)"),
+        ));
+    };
+    status.to_bytes(con)?;
+    len.to_bytes(con)
+}
+pub fn write_response(con: &mut impl Write, status: u8, payload: &[u8]) -> std::io::Result<()> {
+    write_response_header(con, status, payload.len() as u64)?;
+    con.write_all(payload)
+}
+
+/// the status and the length of the payload, which has to be read afterwards
+pub fn read_response_header(con: &mut impl Read) -> std::io::Result<(u8, usize)> {
+    let status = u8::from_bytes(con)?;
+    let len = u32::from_bytes(con)?;
+    Ok((status, len as usize))
+}
+pub fn read_response(con: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+    let (status, len) = read_response_header(con)?;
+    let mut payload = vec![0; len];
+    con.read_exact(&mut payload)?;
+    Ok((status, payload))
+}
+
+#[test]
+fn test_framing() {
+    let mut bytes = vec![];
+    write_request(&mut bytes, "song-file\n5").unwrap();
+    write_response(&mut bytes, STATUS_ERR, b"no data").unwrap();
+    assert_eq!(&bytes[..4], [0, 0, 0, 11]);
+    let mut con = &bytes[..];
+    assert_eq!(
+        read_request(&mut con).unwrap().as_deref(),
+        Some("song-file\n5")
+    );
+    assert_eq!(
+        read_response(&mut con).unwrap(),
+        (STATUS_ERR, b"no data".to_vec())
+    );
+    assert_eq!(read_request(&mut con).unwrap(), None);
+    assert!(read_request(&mut &[255, 0, 0, 0][..]).is_err());
+}
@@ -1,33 +1,141 @@
 use std::{
+    fmt::Display,
     fs,
-    io::{BufRead, BufReader, Read, Write},
-    path::{Path, PathBuf},
-    sync::{Arc, Mutex},
-    time::{Instant, SystemTime},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::data::{database::Database, CoverId, SongId};
+use crate::{
+    data::{
+        album::Album,
+        artist::Artist,
+        database::{ClientIo, Cover, Database, Statistics},
+        playlist,
+        search::SearchResult,
+        song::Song,
+        AlbumId, ArtistId, CoverId, SongId,
+    },
+    load::ToFromBytes,
+};
+
+use super::{connection::ServerAddr, framing, thumbnail, ConnectionCounts};
+
+/// a cover's bytes, or why they couldn't be loaded, see `Client::covers_bytes`
+pub type CoverBytes = (CoverId, Result<Vec<u8>, String>);
+
+/// opens a new connection to the server, see `Client::connect`
+pub type Connect<T> = Box<dyn FnMut() -> std::io::Result<T> + Send>;
 
-pub struct Client<T: Write + Read>(BufReader<T>);
+pub struct Client<T: Write + Read> {
+    con: FailFast<T>,
+    /// if the connection failed while a song file was being downloaded, the song and the data received so far.
+    /// the next `song_file` request for that song continues from there.
+    partial_song_file: Option<(SongId, Vec<u8>)>,
+    /// if the connection uses binary framing, see `new_framed`
+    framed: bool,
+    /// used by `reconnect`
+    connect: Option<Connect<T>>,
+}
 impl<T: Write + Read> Client<T> {
-    pub fn new(mut con: BufReader<T>) -> std::io::Result<Self> {
-        writeln!(con.get_mut(), "get")?;
-        con.get_mut().flush()?;
-        Ok(Self(con))
+    pub fn new(con: BufReader<T>) -> std::io::Result<Self> {
+        Self::start(con, false)
+    }
+    /// Like `new`, but requests and responses are sent as frames (see `framing`) instead of escaped lines.
+    /// Older servers don't confirm this, so this only works with servers which support it.
+    pub fn new_framed(con: BufReader<T>) -> std::io::Result<Self> {
+        Self::start(con, true)
+    }
+    /// Opens a connection using `connect`. If a request fails, for example because the server was restarted,
+    /// `reconnect` uses `connect` again to replace the connection.
+    pub fn connect(mut connect: Connect<T>, framed: bool) -> std::io::Result<Self> {
+        let mut client = Self::start(BufReader::new(connect()?), framed)?;
+        client.connect = Some(connect);
+        Ok(client)
+    }
+    fn start(mut con: BufReader<T>, framed: bool) -> std::io::Result<Self> {
+        if framed {
+            writeln!(con.get_mut(), "get {}", framing::OPTION_FRAMED)?;
+            con.get_mut().flush()?;
+            let mut response = String::new();
+            con.read_line(&mut response)?;
+            if response.trim_end() != framing::OPTION_FRAMED {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("server didn't confirm framing, sent {response:?}"),
+                ));
+            }
+        } else {
+            writeln!(con.get_mut(), "get")?;
+            con.get_mut().flush()?;
+        }
+        Ok(Self {
+            con: FailFast(con, false),
+            partial_song_file: None,
+            framed,
+            connect: None,
+        })
+    }
+    /// Replaces the connection with a new one, see `connect`.
+    /// If a song file was being downloaded, the data received so far is kept.
+    pub fn reconnect(&mut self) -> std::io::Result<()> {
+        let Some(connect) = &mut self.connect else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "can't reconnect, the client wasn't created using `Client::connect`",
+            ));
+        };
+        let con = BufReader::new(connect()?);
+        self.con = Self::start(con, self.framed)?.con;
+        Ok(())
+    }
+    /// Calls `request`. If it fails and the client can reconnect (see `connect`), reconnects and calls it once more.
+    pub fn with_reconnect<R>(
+        &mut self,
+        mut request: impl FnMut(&mut Self) -> std::io::Result<R>,
+    ) -> std::io::Result<R> {
+        match request(self) {
+            Err(e) if self.connect.is_some() => {
+                log_info!("get connection failed ({e}), reconnecting");
+                self.reconnect()?;
+                request(self)
+            }
+            result => result,
+        }
+    }
+    /// sends a request and returns the connection to read the text response from.
+    /// on framed connections, the response is read into memory first.
+    fn request(&mut self, request: &str) -> std::io::Result<Box<dyn BufRead + '_>> {
+        if self.framed {
+            framing::write_request(&mut self.con, request)?;
+            let (_, payload) = framing::read_response(&mut self.con)?;
+            Ok(Box::new(std::io::Cursor::new(payload)))
+        } else {
+            writeln!(self.con, "{}", con_get_encode_string(request))?;
+            self.con.flush()?;
+            Ok(Box::new(&mut self.con))
+        }
+    }
+    /// on framed connections, sends a request which gets a binary response and returns the payload, or the error message
+    fn request_framed(&mut self, request: &str) -> Result<Result<Vec<u8>, String>, std::io::Error> {
+        framing::write_request(&mut self.con, request)?;
+        Ok(match framing::read_response(&mut self.con)? {
+            (framing::STATUS_OK, payload) => Ok(payload),
+            (_, message) => Err(String::from_utf8_lossy(&message).into_owned()),
+        })
     }
     pub fn cover_bytes(&mut self, id: CoverId) -> Result<Result<Vec<u8>, String>, std::io::Error> {
-        writeln!(
-            self.0.get_mut(),
-            "{}",
-            con_get_encode_string(&format!("cover-bytes\n{id}"))
-        )?;
-        self.0.get_mut().flush()?;
+        if self.framed {
+            return self.request_framed(&format!("cover-bytes\n{id}"));
+        }
+        let mut con = self.request(&format!("cover-bytes\n{id}"))?;
         let mut response = String::new();
-        self.0.read_line(&mut response)?;
-        if response.starts_with("len: ") {
-            if let Ok(len) = response[4..].trim().parse() {
+        con.read_line(&mut response)?;
+        if let Some(len) = response.strip_prefix("len: ") {
+            if let Ok(len) = len.trim().parse() {
                 let mut bytes = vec![0; len];
-                self.0.read_exact(&mut bytes)?;
+                con.read_exact(&mut bytes)?;
                 Ok(Ok(bytes))
             } else {
                 Ok(Err(response))
@@ -36,19 +144,99 @@ impl<T: Write + Read> Client<T> {
             Ok(Err(response))
         }
     }
-    pub fn song_file(&mut self, id: SongId) -> Result<Result<Vec<u8>, String>, std::io::Error> {
-        writeln!(
-            self.0.get_mut(),
-            "{}",
-            con_get_encode_string(&format!("song-file\n{id}",))
-        )?;
-        self.0.get_mut().flush()?;
+    /// Like `cover_bytes`, but gets multiple covers in one request.
+    /// The results are in the same order as `ids`, at most `COVERS_BYTES_MAX_IDS` ids can be requested at once.
+    pub fn covers_bytes(
+        &mut self,
+        ids: &[CoverId],
+    ) -> Result<Result<Vec<CoverBytes>, String>, std::io::Error> {
+        let ids = ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut con = self.request(&format!("covers-bytes\n{ids}"))?;
         let mut response = String::new();
-        self.0.read_line(&mut response)?;
-        if response.starts_with("len: ") {
-            if let Ok(len) = response[4..].trim().parse() {
+        con.read_line(&mut response)?;
+        let Some(len) = response
+            .strip_prefix("len: ")
+            .and_then(|len| len.trim().parse().ok())
+        else {
+            return Ok(Err(response));
+        };
+        let mut out = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut line = String::new();
+            con.read_line(&mut line)?;
+            let Some((id, info)) = line
+                .trim_end_matches(['\n', '\r'])
+                .split_once('\t')
+                .and_then(|(id, info)| Some((id.parse().ok()?, info)))
+            else {
+                return Ok(Err(format!("bad line-format: {line}")));
+            };
+            if let Some(e) = info.strip_prefix("err: ") {
+                out.push((id, Err(e.to_owned())));
+            } else if let Some(len) = info.strip_prefix("len: ").and_then(|len| len.parse().ok()) {
                 let mut bytes = vec![0; len];
-                self.0.read_exact(&mut bytes)?;
+                con.read_exact(&mut bytes)?;
+                out.push((id, Ok(bytes)));
+            } else {
+                return Ok(Err(format!("bad line-format: {line}")));
+            }
+        }
+        Ok(Ok(out))
+    }
+    /// The versions of the covers (see `Cover::version`), which change when a cover's file is replaced.
+    /// Covers which don't exist or can't be loaded are missing from the result.
+    /// At most `COVERS_BYTES_MAX_IDS` ids can be requested at once.
+    pub fn cover_versions(
+        &mut self,
+        ids: &[CoverId],
+    ) -> Result<Result<Vec<(CoverId, u64)>, String>, std::io::Error> {
+        let ids = ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut con = self.request(&format!("cover-version\n{ids}"))?;
+        let mut response = String::new();
+        con.read_line(&mut response)?;
+        let Some(len) = response
+            .strip_prefix("len: ")
+            .and_then(|len| len.trim().parse().ok())
+        else {
+            return Ok(Err(response));
+        };
+        let mut out = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut line = String::new();
+            con.read_line(&mut line)?;
+            match line
+                .trim_end_matches(['\n', '\r'])
+                .split_once('\t')
+                .and_then(|(id, version)| Some((id.parse().ok()?, version.parse().ok()?)))
+            {
+                Some(version) => out.push(version),
+                None => return Ok(Err(format!("bad line-format: {line}"))),
+            }
+        }
+        Ok(Ok(out))
+    }
+    /// The cover, scaled down so that neither its width nor its height are bigger than `max_px`.
+    /// If the cover is smaller, or the server can't decode it, it is sent unchanged.
+    pub fn cover_thumb(
+        &mut self,
+        id: CoverId,
+        max_px: u32,
+    ) -> Result<Result<Vec<u8>, String>, std::io::Error> {
+        let mut con = self.request(&format!("cover-thumb\n{id}\n{max_px}"))?;
+        let mut response = String::new();
+        con.read_line(&mut response)?;
+        if let Some(len) = response.strip_prefix("len: ") {
+            if let Ok(len) = len.trim().parse() {
+                let mut bytes = vec![0; len];
+                con.read_exact(&mut bytes)?;
                 Ok(Ok(bytes))
             } else {
                 Ok(Err(response))
@@ -57,19 +245,230 @@ impl<T: Write + Read> Client<T> {
             Ok(Err(response))
         }
     }
+    /// The paths (separated by `/`) and sizes of the files which can be requested using `custom_file`.
+    pub fn custom_files_list(
+        &mut self,
+    ) -> Result<Result<Vec<(String, u64)>, String>, std::io::Error> {
+        let mut con = self.request("custom-files-list")?;
+        let mut response = String::new();
+        con.read_line(&mut response)?;
+        let Some(len) = response
+            .strip_prefix("len: ")
+            .and_then(|len| len.trim().parse().ok())
+        else {
+            return Ok(Err(response));
+        };
+        let mut out = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut line = String::new();
+            con.read_line(&mut line)?;
+            let line = con_get_decode_line(line.trim_end_matches(['\n', '\r']));
+            match line
+                .split_once('\t')
+                .and_then(|(size, path)| Some((path.to_owned(), size.parse().ok()?)))
+            {
+                Some(file) => out.push(file),
+                None => return Ok(Err(format!("bad line-format: {line}"))),
+            }
+        }
+        Ok(Ok(out))
+    }
+    /// The names of the playlists saved on the server, see `playlist::list`.
+    /// To save, load, rename or delete playlists, send the `Playlist*` actions.
+    pub fn playlists(&mut self) -> Result<Result<Vec<String>, String>, std::io::Error> {
+        let mut con = self.request("playlists")?;
+        let mut response = String::new();
+        con.read_line(&mut response)?;
+        let Some(len) = response
+            .strip_prefix("len: ")
+            .and_then(|len| len.trim().parse().ok())
+        else {
+            return Ok(Err(response.trim_end().to_owned()));
+        };
+        let mut out = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut line = String::new();
+            con.read_line(&mut line)?;
+            out.push(con_get_decode_line(line.trim_end_matches(['\n', '\r'])));
+        }
+        Ok(Ok(out))
+    }
+    /// Searches the server's library, see `Database::search`. The server returns at most `limit` results,
+    /// but it may also limit the number of results itself.
+    pub fn search(
+        &mut self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Result<Vec<SearchResult>, String>, std::io::Error> {
+        let mut con = self.request(&format!(
+            "search\n{}\n{limit}",
+            query.replace(['\n', '\r'], " ")
+        ))?;
+        let mut response = String::new();
+        con.read_line(&mut response)?;
+        let Some(len) = response
+            .strip_prefix("len: ")
+            .and_then(|len| len.trim().parse().ok())
+        else {
+            return Ok(Err(response));
+        };
+        let mut out = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut line = String::new();
+            con.read_line(&mut line)?;
+            let line = con_get_decode_line(line.trim_end_matches(['\n', '\r']));
+            let mut parts = line.splitn(4, '\t');
+            let (Some(kind), Some(id), Some(score), Some(name)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                return Ok(Err(format!("bad line-format: {line}")));
+            };
+            match (kind.parse(), id.parse(), score.parse()) {
+                (Ok(kind), Ok(id), Ok(score)) => out.push(SearchResult {
+                    kind,
+                    id,
+                    name: name.to_owned(),
+                    score,
+                }),
+                _ => return Ok(Err(format!("bad line-format: {line}"))),
+            }
+        }
+        Ok(Ok(out))
+    }
+    /// Asks the server to send a CRC32 checksum after the file, and requests the file once more if it doesn't match.
+    /// If the connection fails during the download, the data received so far is kept,
+    /// and the next request for the same song only requests the rest of the file.
+    /// Servers which don't send a checksum or don't support resuming are also supported.
+    pub fn song_file(&mut self, id: SongId) -> Result<Result<Vec<u8>, String>, std::io::Error> {
+        let mut data = match self.partial_song_file.take() {
+            Some((partial_id, data)) if partial_id == id => data,
+            _ => vec![],
+        };
+        match self.song_file_into(id, &mut data) {
+            Ok(Ok(())) => Ok(Ok(data)),
+            Ok(Err(e)) => Ok(Err(e)),
+            Err(e) => {
+                if !data.is_empty() {
+                    self.partial_song_file = Some((id, data));
+                }
+                Err(e)
+            }
+        }
+    }
+    /// requests the part of the file which isn't in `data` yet and appends it to `data`
+    fn song_file_into(
+        &mut self,
+        id: SongId,
+        data: &mut Vec<u8>,
+    ) -> Result<Result<(), String>, std::io::Error> {
+        let mut retried = false;
+        loop {
+            let offset = data.len();
+            let request = if offset > 0 {
+                format!("song-file\n{id}\n{offset}\ncrc32")
+            } else {
+                format!("song-file\n{id}\ncrc32")
+            };
+            let (len, has_crc32) = if self.framed {
+                // framed: the server always supports offsets, and the checksum is sent as a u32
+                framing::write_request(&mut self.con, &request)?;
+                match framing::read_response_header(&mut self.con)? {
+                    (framing::STATUS_OK, len) => (len, true),
+                    (_, len) => {
+                        let mut message = vec![0; len];
+                        self.con.read_exact(&mut message)?;
+                        return Ok(Err(String::from_utf8_lossy(&message).into_owned()));
+                    }
+                }
+            } else {
+                writeln!(self.con, "{}", con_get_encode_string(&request))?;
+                self.con.flush()?;
+                let mut response = String::new();
+                self.con.read_line(&mut response)?;
+                let Some(header) = response.strip_prefix("len: ").map(str::trim) else {
+                    return Ok(Err(response));
+                };
+                let (header, has_crc32) = match header.strip_suffix(" +crc32") {
+                    Some(header) => (header, true),
+                    None => (header, false),
+                };
+                let (len, sent_offset) = match header.split_once(" offset: ") {
+                    Some((len, offset)) => (len, offset.parse().ok()),
+                    None => (header, None),
+                };
+                let Ok(len) = len.parse() else {
+                    return Ok(Err(response));
+                };
+                if sent_offset != Some(offset) {
+                    // the server sends the whole file
+                    data.clear();
+                }
+                (len, has_crc32)
+            };
+            let start = data.len();
+            self.read_appending(len, data)?;
+            if !has_crc32 {
+                return Ok(Ok(()));
+            }
+            let checksum = if self.framed {
+                let mut checksum = [0; 4];
+                self.con.read_exact(&mut checksum)?;
+                Some(u32::from_be_bytes(checksum))
+            } else {
+                let mut checksum = String::new();
+                self.con.read_line(&mut checksum)?;
+                u32::from_str_radix(checksum.trim(), 16).ok()
+            };
+            let mut crc = flate2::Crc::new();
+            crc.update(&data[start..]);
+            if checksum == Some(crc.sum()) {
+                return Ok(Ok(()));
+            }
+            if retried {
+                return Ok(Err(format!(
+                    "the checksum of song {id}'s file didn't match, even after downloading it again"
+                )));
+            }
+            log_warn!("The checksum of song {id}'s file didn't match, downloading it again.");
+            data.clear();
+            retried = true;
+        }
+    }
+    /// reads `len` bytes and appends them to `data`.
+    /// if reading fails, `data` contains the bytes which were read until then.
+    fn read_appending(&mut self, len: usize, data: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        let end = data.len() + len;
+        data.reserve(len);
+        while data.len() < end {
+            let start = data.len();
+            data.resize(end.min(start + FILE_CHUNK_SIZE), 0);
+            match self.con.read(&mut data[start..]) {
+                Ok(0) => {
+                    data.truncate(start);
+                    return Err(std::io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(read) => data.truncate(start + read),
+                Err(e) => {
+                    data.truncate(start);
+                    if e.kind() != std::io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
     pub fn custom_file(&mut self, path: &str) -> Result<Result<Vec<u8>, String>, std::io::Error> {
-        writeln!(
-            self.0.get_mut(),
-            "{}",
-            con_get_encode_string(&format!("custom-file\n{path}",))
-        )?;
-        self.0.get_mut().flush()?;
+        if self.framed {
+            return self.request_framed(&format!("custom-file\n{path}"));
+        }
+        let mut con = self.request(&format!("custom-file\n{path}",))?;
         let mut response = String::new();
-        self.0.read_line(&mut response)?;
-        if response.starts_with("len: ") {
-            if let Ok(len) = response[4..].trim().parse() {
+        con.read_line(&mut response)?;
+        if let Some(len) = response.strip_prefix("len: ") {
+            if let Ok(len) = len.trim().parse() {
                 let mut bytes = vec![0; len];
-                self.0.read_exact(&mut bytes)?;
+                con.read_exact(&mut bytes)?;
                 Ok(Ok(bytes))
             } else {
                 Ok(Err(response))
@@ -82,18 +481,13 @@ impl<T: Write + Read> Client<T> {
         &mut self,
         path: &str,
     ) -> Result<Result<Vec<u8>, String>, std::io::Error> {
-        writeln!(
-            self.0.get_mut(),
-            "{}",
-            con_get_encode_string(&format!("song-file-by-path\n{path}",))
-        )?;
-        self.0.get_mut().flush()?;
+        let mut con = self.request(&format!("song-file-by-path\n{path}",))?;
         let mut response = String::new();
-        self.0.read_line(&mut response)?;
-        if response.starts_with("len: ") {
-            if let Ok(len) = response[4..].trim().parse() {
+        con.read_line(&mut response)?;
+        if let Some(len) = response.strip_prefix("len: ") {
+            if let Ok(len) = len.trim().parse() {
                 let mut bytes = vec![0; len];
-                self.0.read_exact(&mut bytes)?;
+                con.read_exact(&mut bytes)?;
                 Ok(Ok(bytes))
             } else {
                 Ok(Err(response))
@@ -117,21 +511,13 @@ impl<T: Write + Read> Client<T> {
         >,
         std::io::Error,
     > {
-        writeln!(
-            self.0.get_mut(),
-            "{}",
-            con_get_encode_string("find-songs-with-changed-files")
-        )?;
-        self.0.get_mut().flush()?;
+        let mut con = self.request("find-songs-with-changed-files")?;
         loop {
             let mut response = String::new();
-            self.0.read_line(&mut response)?;
+            con.read_line(&mut response)?;
             let len_line = response.trim();
             if len_line.starts_with('%') {
-                eprintln!(
-                    "[Find Songs With Changed Files] Status: {}",
-                    len_line[1..].trim()
-                );
+                log_info!("Find Songs With Changed Files: {}", len_line[1..].trim());
             } else {
                 let len_line = len_line.to_owned();
                 let mut read_list =
@@ -140,7 +526,7 @@ impl<T: Write + Read> Client<T> {
                             l.as_str()
                         } else {
                             response.clear();
-                            self.0.read_line(&mut response)?;
+                            con.read_line(&mut response)?;
                             response.trim()
                         };
                         if len_line.starts_with("len: ") {
@@ -148,7 +534,7 @@ impl<T: Write + Read> Client<T> {
                                 let mut out = Vec::with_capacity(len);
                                 for _ in 0..len {
                                     let mut line = String::new();
-                                    self.0.read_line(&mut line)?;
+                                    con.read_line(&mut line)?;
                                     let line = line.trim_end_matches(['\n', '\r']);
                                     out.push(line.trim().to_owned());
                                 }
@@ -239,6 +625,71 @@ impl<T: Write + Read> Client<T> {
             };
         }
     }
+    /// The song's metadata, without any other data from the database.
+    /// If there is no song with this id, returns `Ok(Err("not found"))`.
+    pub fn song_info(&mut self, id: SongId) -> Result<Result<Song, String>, std::io::Error> {
+        self.info("song-info", id)
+    }
+    /// like `song_info`, but for albums
+    pub fn album_info(&mut self, id: AlbumId) -> Result<Result<Album, String>, std::io::Error> {
+        self.info("album-info", id)
+    }
+    /// like `song_info`, but for artists
+    pub fn artist_info(&mut self, id: ArtistId) -> Result<Result<Artist, String>, std::io::Error> {
+        self.info("artist-info", id)
+    }
+    fn info<D: ToFromBytes>(
+        &mut self,
+        request: &str,
+        id: u64,
+    ) -> Result<Result<D, String>, std::io::Error> {
+        let mut con = self.request(&format!("{request}\n{id}"))?;
+        let mut response = String::new();
+        con.read_line(&mut response)?;
+        let Some(len) = response
+            .strip_prefix("len: ")
+            .and_then(|len| len.trim().parse().ok())
+        else {
+            return Ok(Err(response.trim_end().to_owned()));
+        };
+        let mut bytes = vec![0; len];
+        con.read_exact(&mut bytes)?;
+        Ok(D::from_bytes(&mut &bytes[..]).map_err(|e| format!("couldn't decode {request}: {e}")))
+    }
+    /// the server's `status` as `key: value` pairs, see `Status`
+    pub fn status(&mut self) -> Result<Result<Vec<(String, String)>, String>, std::io::Error> {
+        self.key_value_lines("status")
+    }
+    /// the current song, playback position and upcoming songs as `key: value` pairs, see `NowPlaying`
+    pub fn now_playing(&mut self) -> Result<Result<Vec<(String, String)>, String>, std::io::Error> {
+        self.key_value_lines("now-playing")
+    }
+    fn key_value_lines(
+        &mut self,
+        request: &str,
+    ) -> Result<Result<Vec<(String, String)>, String>, std::io::Error> {
+        let mut con = self.request(request)?;
+        let mut response = String::new();
+        con.read_line(&mut response)?;
+        if let Some(len) = response
+            .strip_prefix("len: ")
+            .and_then(|len| len.trim().parse().ok())
+        {
+            let mut out = Vec::with_capacity(len);
+            for _ in 0..len {
+                let mut line = String::new();
+                con.read_line(&mut line)?;
+                if let Some((key, value)) = line.trim_end_matches(['\n', '\r']).split_once(": ") {
+                    out.push((key.to_owned(), value.to_owned()));
+                } else {
+                    return Ok(Err(format!("bad line-format: {line}")));
+                }
+            }
+            Ok(Ok(out))
+        } else {
+            Ok(Err(response))
+        }
+    }
     /// tell the server to search for files that are not in its song database.
     ///
     /// ## `extensions`:
@@ -266,21 +717,20 @@ impl<T: Write + Read> Client<T> {
                 }
             }
         }
-        writeln!(self.0.get_mut(), "{}", con_get_encode_string(&str))?;
-        self.0.get_mut().flush()?;
+        let mut con = self.request(&str)?;
         loop {
             let mut response = String::new();
-            self.0.read_line(&mut response)?;
+            con.read_line(&mut response)?;
             let len_line = response.trim();
             if len_line.starts_with('%') {
-                eprintln!("[Find Unused Song Files] Status: {}", len_line[1..].trim());
+                log_info!("Find Unused Song Files: {}", len_line[1..].trim());
             } else {
                 break if len_line.starts_with("len: ") {
                     if let Ok(len) = len_line[4..].trim().parse() {
                         let mut out = Vec::with_capacity(len);
                         for _ in 0..len {
                             let mut line = String::new();
-                            self.0.read_line(&mut line)?;
+                            con.read_line(&mut line)?;
                             let line = line.trim_end_matches(['\n', '\r']);
                             if line.starts_with('#') {
                                 out.push((line[1..].to_owned(), false))
@@ -302,303 +752,1013 @@ impl<T: Write + Read> Client<T> {
     }
 }
 
+/// The connection of a `Client`. Once reading or writing fails, for example because of a timeout,
+/// the connection is in an unknown state, so all later reads and writes fail immediately
+/// instead of waiting for another timeout. A new connection is needed, see `Client::reconnect`.
+struct FailFast<T>(BufReader<T>, bool);
+impl<T: Read + Write> FailFast<T> {
+    fn run<R>(
+        &mut self,
+        f: impl FnOnce(&mut BufReader<T>) -> std::io::Result<R>,
+    ) -> std::io::Result<R> {
+        if self.1 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "the connection failed earlier",
+            ));
+        }
+        let result = f(&mut self.0);
+        if result
+            .as_ref()
+            .is_err_and(|e| e.kind() != std::io::ErrorKind::Interrupted)
+        {
+            self.1 = true;
+        }
+        result
+    }
+}
+impl<T: Read + Write> Read for FailFast<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.run(|con| con.read(buf))
+    }
+}
+impl<T: Read + Write> BufRead for FailFast<T> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.1 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "the connection failed earlier",
+            ));
+        }
+        match self.0.fill_buf() {
+            Ok(buf) => Ok(buf),
+            Err(e) => {
+                self.1 = e.kind() != std::io::ErrorKind::Interrupted;
+                Err(e)
+            }
+        }
+    }
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt)
+    }
+}
+impl<T: Read + Write> Write for FailFast<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.run(|con| con.get_mut().write(buf))
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.run(|con| con.get_mut().flush())
+    }
+}
+
+impl Client<Box<dyn ClientIo + 'static>> {
+    /// Connects to the server at `addr` (see `ServerAddr::connect_with_auth`) and uses the same address to reconnect.
+    /// Reads and writes which take longer than the timeouts fail instead of blocking forever.
+    pub fn connect_to(
+        addr: ServerAddr,
+        auth_token: Option<String>,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+    ) -> std::io::Result<Self> {
+        Self::connect(
+            connect_to(addr, auth_token, read_timeout, write_timeout),
+            false,
+        )
+    }
+}
+fn connect_to(
+    addr: ServerAddr,
+    auth_token: Option<String>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+) -> Connect<Box<dyn ClientIo + 'static>> {
+    Box::new(move || {
+        let con = addr.connect_with_auth(auth_token.as_deref())?;
+        con.set_read_timeout_connection(read_timeout)?;
+        con.set_write_timeout_connection(write_timeout)?;
+        Ok(Box::new(con) as _)
+    })
+}
+
+/// how many connections a `Pool` opens at most
+pub const POOL_SIZE: usize = 4;
+
+/// Up to `POOL_SIZE` connections to the same server, so that a slow request
+/// (like downloading a big song file) doesn't block other requests.
+/// The pool's lock is only held while taking a connection from the pool or putting it back.
+pub struct Pool<T: Write + Read> {
+    state: Mutex<PoolState<T>>,
+    available: Condvar,
+    connect: Arc<Mutex<Connect<T>>>,
+    framed: bool,
+}
+struct PoolState<T: Write + Read> {
+    idle: Vec<Client<T>>,
+    open: usize,
+}
+impl<T: Write + Read + 'static> Pool<T> {
+    /// Opens the first connection using `connect`, more connections are opened when they are needed.
+    /// All connections can reconnect, see `Client::connect`.
+    pub fn connect(connect: Connect<T>, framed: bool) -> std::io::Result<Self> {
+        let pool = Self::new_lazy(connect, framed);
+        let client = pool.open()?;
+        let mut state = pool.state.lock().unwrap();
+        state.open = 1;
+        state.idle.push(client);
+        drop(state);
+        Ok(pool)
+    }
+    /// Like `connect`, but doesn't open a connection until the first request,
+    /// so this works even if the server can't be reached yet.
+    pub fn new_lazy(connect: Connect<T>, framed: bool) -> Self {
+        Self {
+            state: Mutex::new(PoolState {
+                idle: vec![],
+                open: 0,
+            }),
+            available: Condvar::new(),
+            connect: Arc::new(Mutex::new(connect)),
+            framed,
+        }
+    }
+    fn open(&self) -> std::io::Result<Client<T>> {
+        let connect = Arc::clone(&self.connect);
+        Client::connect(Box::new(move || (connect.lock().unwrap())()), self.framed)
+    }
+    /// Takes an idle connection (or opens a new one, or waits for one if there are `POOL_SIZE` connections already),
+    /// then calls `Client::with_reconnect` and puts the connection back into the pool.
+    pub fn with_reconnect<R>(
+        &self,
+        request: impl FnMut(&mut Client<T>) -> std::io::Result<R>,
+    ) -> std::io::Result<R> {
+        let mut client = self.take()?;
+        let result = client.with_reconnect(request);
+        // failed connections are put back too, they reconnect when they are used next
+        self.state.lock().unwrap().idle.push(client);
+        self.available.notify_one();
+        result
+    }
+    fn take(&self) -> std::io::Result<Client<T>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(client) = state.idle.pop() {
+                return Ok(client);
+            }
+            if state.open < POOL_SIZE {
+                state.open += 1;
+                drop(state);
+                return self.open().inspect_err(|_| {
+                    self.state.lock().unwrap().open -= 1;
+                    self.available.notify_one();
+                });
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+}
+impl Pool<Box<dyn ClientIo + 'static>> {
+    /// Like `Client::connect_to`, but opens up to `POOL_SIZE` connections
+    pub fn connect_to(
+        addr: ServerAddr,
+        auth_token: Option<String>,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+    ) -> std::io::Result<Self> {
+        Self::connect(
+            connect_to(addr, auth_token, read_timeout, write_timeout),
+            false,
+        )
+    }
+    /// Like `connect_to`, but doesn't connect until the first request, see `new_lazy`
+    pub fn connect_to_lazy(
+        addr: ServerAddr,
+        auth_token: Option<String>,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+    ) -> Self {
+        Self::new_lazy(
+            connect_to(addr, auth_token, read_timeout, write_timeout),
+            false,
+        )
+    }
+}
+
+/// How the server is doing. Collected while holding the database lock, formatted afterwards.
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub uptime: Duration,
+    pub connections: ConnectionCounts,
+    /// all clients which receive updates, including the website and other non-tcp clients
+    pub update_endpoints: usize,
+    pub statistics: Statistics,
+    pub queue_length: usize,
+    pub current_song: Option<(SongId, String)>,
+    pub playing: bool,
+    /// see `Database::sleep_timer_remaining`
+    pub sleep_timer: Option<(Duration, bool)>,
+}
+impl Status {
+    pub fn get(db: &Mutex<Database>) -> Self {
+        let db = db.lock().unwrap();
+        Self {
+            uptime: db.uptime(),
+            connections: db.open_connections.clone(),
+            update_endpoints: db.update_endpoints.len(),
+            statistics: db.statistics(),
+            queue_length: db.queue.len(&db),
+            current_song: db
+                .queue
+                .get_current_song(&db)
+                .and_then(|id| db.get_song(id).map(|song| (*id, song.title.clone()))),
+            playing: db.playing,
+            sleep_timer: db.sleep_timer_remaining(),
+        }
+    }
+    /// the lines of the `status` response, without the `len: ` line
+    pub fn lines(&self) -> Vec<(&'static str, String)> {
+        let s = &self.statistics;
+        vec![
+            ("uptime", format!("{}s", self.uptime.as_secs())),
+            ("clients-main", self.connections.main.to_string()),
+            ("clients-control", self.connections.control.to_string()),
+            ("clients-get", self.connections.get.to_string()),
+            ("clients-output", self.connections.output.to_string()),
+            ("update-endpoints", self.update_endpoints.to_string()),
+            ("songs", s.songs.to_string()),
+            ("albums", s.albums.to_string()),
+            ("artists", s.artists.to_string()),
+            ("covers", s.covers.to_string()),
+            ("cached-songs", s.cached_songs.to_string()),
+            ("cached-song-bytes", s.cached_song_bytes.to_string()),
+            ("cached-covers", s.cached_covers.to_string()),
+            ("cached-cover-bytes", s.cached_cover_bytes.to_string()),
+            ("queue-length", self.queue_length.to_string()),
+            (
+                "current-song",
+                match &self.current_song {
+                    Some((id, title)) => format!("#{id} {}", title.replace(['\n', '\r'], " ")),
+                    None => "none".to_owned(),
+                },
+            ),
+            ("playing", self.playing.to_string()),
+            (
+                "sleep-timer",
+                match self.sleep_timer {
+                    Some((remaining, false)) => format!("{}s", remaining.as_secs()),
+                    Some((remaining, true)) => {
+                        format!("{}s, then end of song", remaining.as_secs())
+                    }
+                    None => "none".to_owned(),
+                },
+            ),
+            ("library-revision", s.data_version.to_string()),
+        ]
+    }
+}
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in self.lines() {
+            writeln!(f, "{key}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What is playing, for `now-playing` requests. Like `Status`, this is collected while holding the database lock
+/// and formatted afterwards, so it can be requested often (for example once per second by a status bar).
+///
+/// The response is `len: <n>`, then `<n>` lines of `key: value`:
+/// - `state`: `playing`, `paused` or `stopped` (if there is no current song)
+/// - `song`: `#<id>`, `title`, `artist`, `album`: the current song, `album` is empty for singles
+/// - `elapsed`, `duration`: in seconds (`<n>s`) or `unknown`
+/// - `next`: once for each of the next `NOW_PLAYING_UPCOMING` songs, as `<title> - <artist>`
+///
+/// The lines for the current song are missing if the state is `stopped`, the `next` lines if nothing is queued.
+#[derive(Clone, Debug)]
+pub struct NowPlaying {
+    pub playing: bool,
+    pub current: Option<NowPlayingSong>,
+    /// the position in the current song, see `Database::playback_position_now`
+    pub elapsed: Option<Duration>,
+    pub upcoming: Vec<NowPlayingSong>,
+}
+#[derive(Clone, Debug)]
+pub struct NowPlayingSong {
+    pub id: SongId,
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+}
+/// how many upcoming songs are included in `now-playing` responses
+pub const NOW_PLAYING_UPCOMING: usize = 5;
+impl NowPlaying {
+    pub fn get(db: &Mutex<Database>) -> Self {
+        let db = db.lock().unwrap();
+        let song = |id: &SongId| {
+            let song = db.get_song(id)?;
+            Some(NowPlayingSong {
+                id: *id,
+                title: song.title.clone(),
+                artist: db
+                    .artists()
+                    .get(&song.artist)
+                    .map(|artist| artist.name.clone())
+                    .unwrap_or_default(),
+                album: song
+                    .album
+                    .and_then(|id| db.albums().get(&id))
+                    .map(|album| album.name.clone()),
+                duration: (song.duration_millis > 0)
+                    .then(|| Duration::from_millis(song.duration_millis)),
+            })
+        };
+        Self {
+            playing: db.playing,
+            current: db.queue.get_current_song(&db).and_then(song),
+            elapsed: db.playback_position_now().map(Duration::from_millis),
+            upcoming: db
+                .queue
+                .upcoming_songs(&db, NOW_PLAYING_UPCOMING)
+                .iter()
+                .filter_map(song)
+                .collect(),
+        }
+    }
+    /// the lines of the `now-playing` response, without the `len: ` line
+    pub fn lines(&self) -> Vec<(&'static str, String)> {
+        let one_line = |s: &str| s.replace(['\n', '\r'], " ");
+        let secs = |d: Option<Duration>| match d {
+            Some(d) => format!("{}s", d.as_secs()),
+            None => "unknown".to_owned(),
+        };
+        let mut lines = vec![];
+        if let Some(song) = &self.current {
+            let state = if self.playing { "playing" } else { "paused" };
+            lines.push(("state", state.to_owned()));
+            lines.push(("song", format!("#{}", song.id)));
+            lines.push(("title", one_line(&song.title)));
+            lines.push(("artist", one_line(&song.artist)));
+            lines.push(("album", one_line(song.album.as_deref().unwrap_or(""))));
+            lines.push(("elapsed", secs(self.elapsed)));
+            lines.push(("duration", secs(song.duration)));
+        } else {
+            lines.push(("state", "stopped".to_owned()));
+        }
+        for song in &self.upcoming {
+            lines.push((
+                "next",
+                format!("{} - {}", one_line(&song.title), one_line(&song.artist)),
+            ));
+        }
+        lines
+    }
+}
+
+/// a `covers-bytes` or `cover-version` request can ask for at most this many covers
+pub const COVERS_BYTES_MAX_IDS: usize = 256;
+/// a `search` request returns at most this many results
+const SEARCH_MAX_RESULTS: usize = 100;
+/// how many bytes of a file are read and sent at once, see `write_file_response`
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Writes `len: <len>`, then reads `len` bytes from `data` and sends them in chunks.
+/// If `offset` isn't 0 (`data` starts at that offset in the file), the first line is `len: <len> offset: <offset>`.
+/// If `crc32`, ` +crc32` is added to the first line, and the CRC32 checksum of the sent data
+/// is sent as 8 hex digits in its own line after the data.
+/// If `data` ends early, an error is returned and the connection should be closed.
+fn write_file_response(
+    con: &mut impl Write,
+    data: impl Read,
+    len: u64,
+    offset: u64,
+    crc32: bool,
+) -> Result<(), std::io::Error> {
+    write!(con, "len: {len}")?;
+    if offset > 0 {
+        write!(con, " offset: {offset}")?;
+    }
+    if crc32 {
+        writeln!(con, " +crc32")?;
+    } else {
+        writeln!(con)?;
+    }
+    let crc = write_chunks(con, data, len)?;
+    if crc32 {
+        writeln!(con, "{crc:08x}")?;
+    }
+    Ok(())
+}
+
+/// reads `len` bytes from `data` and sends them in chunks, then returns their CRC32 checksum.
+/// if `data` ends early, an error is returned.
+fn write_chunks(
+    con: &mut impl Write,
+    mut data: impl Read,
+    len: u64,
+) -> Result<u32, std::io::Error> {
+    let mut crc = flate2::Crc::new();
+    let mut buf = vec![0; FILE_CHUNK_SIZE];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = &mut buf[..remaining.min(FILE_CHUNK_SIZE as u64) as usize];
+        let read = data.read(chunk)?;
+        if read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        crc.update(&chunk[..read]);
+        con.write_all(&chunk[..read])?;
+        remaining -= read as u64;
+    }
+    Ok(crc.sum())
+}
+
+/// the covers for a `covers-bytes` request, also used by the website. files are read without holding the database lock.
+pub fn covers_bytes(db: &Mutex<Database>, ids: &[CoverId]) -> Vec<CoverBytes> {
+    let dbl = db.lock().unwrap();
+    if let Some(get_con) = dbl.remote_server_as_song_file_source.clone() {
+        // the remote server may be slow, don't block everything else
+        drop(dbl);
+        return match get_con.with_reconnect(|c| c.covers_bytes(ids)) {
+            Ok(Ok(covers)) => covers,
+            Ok(Err(e)) => ids.iter().map(|id| (*id, Err(e.clone()))).collect(),
+            Err(e) => ids.iter().map(|id| (*id, Err(e.to_string()))).collect(),
+        };
+    }
+    let covers = local_covers(&dbl, ids);
+    drop(dbl);
+    covers
+        .into_iter()
+        .map(|(id, cover)| {
+            let bytes = match cover {
+                Some((cover, path)) => cover
+                    .get_bytes_from_file(|_| path, |bytes| bytes.clone())
+                    .ok_or_else(|| "no data".to_owned()),
+                None => Err("no cover".to_owned()),
+            };
+            (id, bytes)
+        })
+        .collect()
+}
+
+/// the versions for a `cover-version` request, see `Cover::version`. covers without a version are skipped.
+fn cover_versions(db: &Mutex<Database>, ids: &[CoverId]) -> Vec<(CoverId, u64)> {
+    let dbl = db.lock().unwrap();
+    if let Some(get_con) = dbl.remote_server_as_song_file_source.clone() {
+        drop(dbl);
+        return get_con
+            .with_reconnect(|c| c.cover_versions(ids))
+            .ok()
+            .and_then(Result::ok)
+            .unwrap_or_default();
+    }
+    let covers = local_covers(&dbl, ids);
+    drop(dbl);
+    covers
+        .into_iter()
+        .filter_map(|(id, cover)| {
+            let (cover, path) = cover?;
+            Some((id, cover.version(|_| path)?))
+        })
+        .collect()
+}
+
+/// the covers and their paths, so that they can be loaded without holding the database lock
+fn local_covers(db: &Database, ids: &[CoverId]) -> Vec<(CoverId, Option<(Cover, PathBuf)>)> {
+    ids.iter()
+        .map(|id| {
+            (
+                *id,
+                db.covers()
+                    .get(id)
+                    .map(|cover| (cover.clone(), db.get_path(&cover.location))),
+            )
+        })
+        .collect()
+}
+
+/// parses a comma-separated list of cover ids for `covers-bytes` and `cover-version` requests
+fn parse_cover_ids(ids: Option<&str>) -> Option<Vec<CoverId>> {
+    ids.unwrap_or("")
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().ok())
+        .collect()
+}
+
 pub fn handle_one_connection_as_get(
     db: Arc<Mutex<Database>>,
     connection: &mut BufReader<impl Read + Write>,
+    framed: bool,
 ) -> Result<(), std::io::Error> {
+    if framed {
+        writeln!(connection.get_mut(), "{}", framing::OPTION_FRAMED)?;
+    }
     loop {
-        let mut line = String::new();
-        if connection.read_line(&mut line).is_ok() {
-            if line.is_empty() {
+        let request = if framed {
+            match framing::read_request(connection) {
+                Ok(Some(request)) => request,
+                Ok(None) | Err(_) => return Ok(()),
+            }
+        } else {
+            let mut line = String::new();
+            if connection.read_line(&mut line).is_err() || line.is_empty() {
                 return Ok(());
             }
-            let request = con_get_decode_line(&line);
-            let mut request = request.lines();
-            if let Some(req) = request.next() {
-                match req {
-                    "cover-bytes" => {
-                        if let Some(cover_id) = request.next().and_then(|id| id.parse().ok()) {
-                            let dbl = db.lock().unwrap();
-                            if let Some(get_con) = &dbl.remote_server_as_song_file_source {
-                                if let Some(bytes) = get_con
-                                    .lock()
-                                    .unwrap()
-                                    .cover_bytes(cover_id)
-                                    .ok()
-                                    .and_then(Result::ok)
-                                {
-                                    writeln!(connection.get_mut(), "len: {}", bytes.len())?;
-                                    connection.get_mut().write_all(&bytes)?;
-                                } else {
-                                    writeln!(connection.get_mut(), "no")?;
-                                }
-                            } else if let Some(cover) = dbl.covers().get(&cover_id) {
-                                if let Some(v) = cover.get_bytes_from_file(
-                                    |p| dbl.get_path(p),
-                                    |bytes| {
-                                        writeln!(connection.get_mut(), "len: {}", bytes.len())?;
-                                        connection.get_mut().write_all(bytes)?;
-                                        Ok::<(), std::io::Error>(())
-                                    },
-                                ) {
-                                    v?;
-                                } else {
-                                    writeln!(connection.get_mut(), "no data")?;
-                                }
-                            } else {
-                                writeln!(connection.get_mut(), "no cover")?;
-                            }
-                        } else {
-                            writeln!(connection.get_mut(), "bad id")?;
-                        }
-                    }
-                    "song-file" => {
-                        if let Some(bytes) =
-                            request
-                                .next()
-                                .and_then(|id| id.parse().ok())
-                                .and_then(|id| {
-                                    let db = db.lock().unwrap();
-                                    if let Some(song) = db.get_song(&id) {
-                                        let cd = song.cached_data();
-                                        if let Some(data) =
-                                            cd.get_data_or_maybe_start_thread(&db, song)
-                                        {
-                                            Some(data)
-                                        } else {
-                                            let cd = cd.clone();
-                                            drop(db);
-                                            cd.cached_data_await()
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                })
+            con_get_decode_line(&line)
+        };
+        if !framed {
+            handle_request(&db, &request, connection.get_mut())?;
+        } else if !handle_request_framed(&db, &request, connection.get_mut())? {
+            // no binary response, send the text response in one frame
+            let mut out = vec![];
+            handle_request(&db, &request, &mut out)?;
+            framing::write_response(connection.get_mut(), framing::STATUS_OK, &out)?;
+        }
+    }
+}
+
+/// handles a request which was sent as a line (see `con_get_decode_line`) or in a frame.
+/// if there is no response, nothing is written.
+fn handle_request(
+    db: &Arc<Mutex<Database>>,
+    request: &str,
+    out: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    let mut request = request.lines();
+    if let Some(req) = request.next() {
+        match req {
+            "cover-bytes" => {
+                if let Some(cover_id) = request.next().and_then(|id| id.parse().ok()) {
+                    let dbl = db.lock().unwrap();
+                    if let Some(get_con) = dbl.remote_server_as_song_file_source.clone() {
+                        // the remote server may be slow, don't block everything else
+                        drop(dbl);
+                        if let Some(bytes) = get_con
+                            .with_reconnect(|c| c.cover_bytes(cover_id))
+                            .ok()
+                            .and_then(Result::ok)
                         {
-                            writeln!(connection.get_mut(), "len: {}", bytes.len())?;
-                            connection.get_mut().write_all(&bytes)?;
+                            writeln!(out, "len: {}", bytes.len())?;
+                            out.write_all(&bytes)?;
                         } else {
-                            writeln!(connection.get_mut(), "no data")?;
+                            writeln!(out, "no")?;
                         }
-                    }
-                    "custom-file" => {
-                        if let Some(bytes) =
-                            request.next().and_then(|path| 'load_custom_file_data: {
-                                let db = db.lock().unwrap();
-                                let mut parent = match &db.custom_files {
-                                    None => {
-                                        if let Some(con) = &db.remote_server_as_song_file_source {
-                                            if let Ok(Ok(data)) =
-                                                con.lock().unwrap().custom_file(path)
-                                            {
-                                                break 'load_custom_file_data Some(data);
-                                            } else {
-                                                None
-                                            }
-                                        } else {
-                                            None
-                                        }
-                                    }
-                                    // if a remote source is present, this means we should ignore it. if no remote source is present, use the lib_dir.
-                                    Some(None) => {
-                                        if db.remote_server_as_song_file_source.is_none() {
-                                            Some(db.lib_directory.clone())
-                                        } else {
-                                            None
-                                        }
-                                    }
-                                    Some(Some(p)) => Some(p.clone()),
-                                };
-                                // check for malicious paths [TODO: Improve]
-                                if Path::new(path).is_absolute() {
-                                    parent = None;
-                                }
-                                if let Some(parent) = parent {
-                                    let path = parent.join(path);
-                                    if path.starts_with(parent) {
-                                        fs::read(path).ok()
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            })
-                        {
-                            writeln!(connection.get_mut(), "len: {}", bytes.len())?;
-                            connection.get_mut().write_all(&bytes)?;
+                    } else if let Some(cover) = dbl.covers().get(&cover_id) {
+                        if let Some(v) = cover.get_bytes_from_file(
+                            |p| dbl.get_path(p),
+                            |bytes| {
+                                writeln!(out, "len: {}", bytes.len())?;
+                                out.write_all(bytes)?;
+                                Ok::<(), std::io::Error>(())
+                            },
+                        ) {
+                            v?;
                         } else {
-                            writeln!(connection.get_mut(), "no data")?;
+                            writeln!(out, "no data")?;
                         }
+                    } else {
+                        writeln!(out, "no cover")?;
                     }
-                    "song-file-by-path" => {
-                        if let Some(bytes) = request.next().and_then(|path| {
-                            let db = db.lock().unwrap();
-                            let mut parent = Some(db.lib_directory.clone());
-                            // check for malicious paths [TODO: Improve]
-                            if Path::new(path).is_absolute() {
-                                parent = None;
-                            }
-                            if let Some(parent) = parent {
-                                let path = parent.join(path);
-                                if path.starts_with(parent) {
-                                    fs::read(path).ok()
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
+                } else {
+                    writeln!(out, "bad id")?;
+                }
+            }
+            "covers-bytes" => match parse_cover_ids(request.next()) {
+                Some(ids) if ids.len() <= COVERS_BYTES_MAX_IDS => {
+                    let covers = covers_bytes(db, &ids);
+                    writeln!(out, "len: {}", covers.len())?;
+                    for (id, bytes) in covers {
+                        match bytes {
+                            Ok(bytes) => {
+                                writeln!(out, "{id}\tlen: {}", bytes.len())?;
+                                out.write_all(&bytes)?;
                             }
-                        }) {
-                            writeln!(connection.get_mut(), "len: {}", bytes.len())?;
-                            connection.get_mut().write_all(&bytes)?;
-                        } else {
-                            writeln!(connection.get_mut(), "no data")?;
+                            Err(e) => writeln!(out, "{id}\terr: {}", e.trim().replace('\n', " "))?,
                         }
                     }
-                    "find-songs-with-changed-files" => {
-                        let db_lock = db.lock().unwrap();
-                        let lib_directory = db_lock.lib_directory.clone();
-                        let all_songs = db_lock
-                            .songs()
-                            .iter()
-                            .map(|(id, song)| {
-                                (
-                                    *id,
-                                    song.location.clone(),
-                                    song.file_last_modified_unix_timestamp.clone(),
-                                )
-                            })
-                            .collect::<Vec<_>>();
-                        drop(db_lock);
-                        let (
-                            mut songs_no_time,
-                            mut songs_new_time,
-                            mut songs_removed,
-                            mut songs_err,
-                        ) = (vec![], vec![], vec![], vec![]);
-                        for (id, location, last_modified) in all_songs {
-                            let path = Database::get_path_nodb(&lib_directory, &location);
-                            match path.try_exists() {
-                                Ok(true) => match path.metadata() {
-                                    Ok(metadata) => {
-                                        let time = metadata.modified().ok().and_then(|time| {
-                                            time.duration_since(SystemTime::UNIX_EPOCH)
-                                                .ok()
-                                                .map(|v| v.as_secs())
-                                        });
-                                        if last_modified.is_none() || time != last_modified {
-                                            if let Some(time) = time {
-                                                songs_new_time.push((id, time));
-                                            } else {
-                                                songs_no_time.push(id);
-                                            }
-                                        }
+                }
+                Some(_) => writeln!(out, "too many ids (max {COVERS_BYTES_MAX_IDS})")?,
+                None => writeln!(out, "bad id")?,
+            },
+            "cover-version" => match parse_cover_ids(request.next()) {
+                Some(ids) if ids.len() <= COVERS_BYTES_MAX_IDS => {
+                    let versions = cover_versions(db, &ids);
+                    writeln!(out, "len: {}", versions.len())?;
+                    for (id, version) in versions {
+                        writeln!(out, "{id}\t{version}")?;
+                    }
+                }
+                Some(_) => writeln!(out, "too many ids (max {COVERS_BYTES_MAX_IDS})")?,
+                None => writeln!(out, "bad id")?,
+            },
+            "search" => {
+                let query = request.next().unwrap_or("");
+                let limit = request
+                    .next()
+                    .and_then(|limit| limit.parse().ok())
+                    .unwrap_or(SEARCH_MAX_RESULTS)
+                    .min(SEARCH_MAX_RESULTS);
+                let results = db.lock().unwrap().search(query, limit);
+                writeln!(out, "len: {}", results.len())?;
+                for result in results {
+                    writeln!(
+                        out,
+                        "{}",
+                        con_get_encode_string(&format!(
+                            "{}\t{}\t{}\t{}",
+                            result.kind, result.id, result.score, result.name
+                        ))
+                    )?;
+                }
+            }
+            "cover-thumb" => {
+                let cover_id = request.next().and_then(|id| id.parse().ok());
+                let max_px = request.next().and_then(|px| px.parse().ok());
+                if let (Some(cover_id), Some(max_px)) = (cover_id, max_px) {
+                    let max_px = thumbnail::clamp_size(max_px);
+                    let dbl = db.lock().unwrap();
+                    let bytes = if let Some(get_con) = dbl.remote_server_as_song_file_source.clone()
+                    {
+                        drop(dbl);
+                        get_con
+                            .with_reconnect(|c| c.cover_thumb(cover_id, max_px))
+                            .ok()
+                            .and_then(Result::ok)
+                            .map(Arc::new)
+                    } else if let Some(cover) = dbl.covers().get(&cover_id) {
+                        let cover = cover.clone();
+                        let path = dbl.get_path(&cover.location);
+                        let cache = dbl.cover_thumbnails.clone();
+                        // scaling the cover down can take a while
+                        drop(dbl);
+                        cache.get_or_create(cover_id, max_px, || {
+                            cover.get_bytes_from_file(
+                                |_| path,
+                                |bytes| thumbnail::create(bytes, max_px),
+                            )
+                        })
+                    } else {
+                        None
+                    };
+                    if let Some(bytes) = bytes {
+                        writeln!(out, "len: {}", bytes.len())?;
+                        out.write_all(&bytes)?;
+                    } else {
+                        writeln!(out, "no data")?;
+                    }
+                } else {
+                    writeln!(out, "bad id or size")?;
+                }
+            }
+            "song-file" => {
+                let (id, offset, crc32) = song_file_request(request);
+                if let Err(e) = with_song_file(db, id, offset, |data, len| {
+                    write_file_response(out, data, len, offset, crc32)
+                })? {
+                    writeln!(out, "{e}")?;
+                }
+            }
+            "custom-file" => {
+                if let Some(bytes) = request.next().and_then(|path| custom_file_bytes(db, path)) {
+                    writeln!(out, "len: {}", bytes.len())?;
+                    out.write_all(&bytes)?;
+                } else {
+                    writeln!(out, "no data")?;
+                }
+            }
+            "custom-files-list" => {
+                let db = db.lock().unwrap();
+                let files = if db.custom_files.is_none() {
+                    let remote = db.remote_server_as_song_file_source.clone();
+                    drop(db);
+                    remote
+                        .and_then(|con| con.with_reconnect(|c| c.custom_files_list()).ok())
+                        .and_then(Result::ok)
+                } else {
+                    let dir = custom_files_dir(&db);
+                    // listing the files can take a while
+                    drop(db);
+                    dir.map(|dir| list_custom_files(&dir))
+                };
+                if let Some(files) = files {
+                    writeln!(out, "len: {}", files.len())?;
+                    for (path, size) in files {
+                        writeln!(out, "{}", con_get_encode_string(&format!("{size}\t{path}")))?;
+                    }
+                } else {
+                    writeln!(out, "no custom files")?;
+                }
+            }
+            "playlists" => match playlist::list(&db.lock().unwrap()) {
+                Ok(names) => {
+                    writeln!(out, "len: {}", names.len())?;
+                    for name in names {
+                        writeln!(out, "{}", con_get_encode_string(&name))?;
+                    }
+                }
+                Err(e) => writeln!(out, "couldn't list playlists: {e}")?,
+            },
+            "song-file-by-path" => {
+                if let Some(bytes) = request.next().and_then(|path| {
+                    let path = join_relative(&db.lock().unwrap().lib_directory, path)?;
+                    fs::read(path).ok()
+                }) {
+                    writeln!(out, "len: {}", bytes.len())?;
+                    out.write_all(&bytes)?;
+                } else {
+                    writeln!(out, "no data")?;
+                }
+            }
+            "find-songs-with-changed-files" => {
+                let db_lock = db.lock().unwrap();
+                let lib_directory = db_lock.lib_directory.clone();
+                let all_songs = db_lock
+                    .songs()
+                    .iter()
+                    .map(|(id, song)| {
+                        (
+                            *id,
+                            song.location.clone(),
+                            song.file_last_modified_unix_timestamp.clone(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                drop(db_lock);
+                let (mut songs_no_time, mut songs_new_time, mut songs_removed, mut songs_err) =
+                    (vec![], vec![], vec![], vec![]);
+                for (id, location, last_modified) in all_songs {
+                    let path = Database::get_path_nodb(&lib_directory, &location);
+                    match path.try_exists() {
+                        Ok(true) => match path.metadata() {
+                            Ok(metadata) => {
+                                let time = metadata.modified().ok().and_then(|time| {
+                                    time.duration_since(SystemTime::UNIX_EPOCH)
+                                        .ok()
+                                        .map(|v| v.as_secs())
+                                });
+                                if last_modified.is_none() || time != last_modified {
+                                    if let Some(time) = time {
+                                        songs_new_time.push((id, time));
+                                    } else {
+                                        songs_no_time.push(id);
                                     }
-                                    Err(e) => songs_err.push((id, e.to_string())),
-                                },
-                                Ok(false) => songs_removed.push(id),
-                                Err(e) => songs_err.push((id, e.to_string())),
-                            }
-                        }
-                        write_list(
-                            connection.get_mut(),
-                            songs_no_time.len(),
-                            songs_no_time.into_iter().map(|id| (id, None)),
-                        )?;
-                        write_list(
-                            connection.get_mut(),
-                            songs_new_time.len(),
-                            songs_new_time
-                                .into_iter()
-                                .map(|(id, t)| (id, Some(format!("{t}")))),
-                        )?;
-                        write_list(
-                            connection.get_mut(),
-                            songs_removed.len(),
-                            songs_removed.into_iter().map(|id| (id, None)),
-                        )?;
-                        write_list(
-                            connection.get_mut(),
-                            songs_err.len(),
-                            songs_err
-                                .into_iter()
-                                .map(|(id, e)| (id, Some(format!("{e}")))),
-                        )?;
-                        fn write_list(
-                            connection: &mut impl Write,
-                            len: usize,
-                            list: impl IntoIterator<Item = (u64, Option<String>)>,
-                        ) -> std::io::Result<()> {
-                            writeln!(connection, "len: {}", len)?;
-                            for (song, data) in list {
-                                if let Some(data) = data {
-                                    writeln!(connection, "{song}:{data}")?;
-                                } else {
-                                    writeln!(connection, "{song}")?;
                                 }
                             }
-                            Ok(())
+                            Err(e) => songs_err.push((id, e.to_string())),
+                        },
+                        Ok(false) => songs_removed.push(id),
+                        Err(e) => songs_err.push((id, e.to_string())),
+                    }
+                }
+                write_list(
+                    out,
+                    songs_no_time.len(),
+                    songs_no_time.into_iter().map(|id| (id, None)),
+                )?;
+                write_list(
+                    out,
+                    songs_new_time.len(),
+                    songs_new_time
+                        .into_iter()
+                        .map(|(id, t)| (id, Some(format!("{t}")))),
+                )?;
+                write_list(
+                    out,
+                    songs_removed.len(),
+                    songs_removed.into_iter().map(|id| (id, None)),
+                )?;
+                write_list(
+                    out,
+                    songs_err.len(),
+                    songs_err
+                        .into_iter()
+                        .map(|(id, e)| (id, Some(format!("{e}")))),
+                )?;
+                fn write_list(
+                    connection: &mut impl Write,
+                    len: usize,
+                    list: impl IntoIterator<Item = (u64, Option<String>)>,
+                ) -> std::io::Result<()> {
+                    writeln!(connection, "len: {}", len)?;
+                    for (song, data) in list {
+                        if let Some(data) = data {
+                            writeln!(connection, "{song}:{data}")?;
+                        } else {
+                            writeln!(connection, "{song}")?;
                         }
                     }
-                    "find-unused-song-files" => {
-                        // configure search
-                        let mut extensions = None;
-                        loop {
-                            if let Some(line) = request.next() {
-                                if let Some((key, value)) = line.split_once("=") {
-                                    match key.trim() {
-                                        "extensions" => {
-                                            extensions = Some(Some(
-                                                value
-                                                    .split(':')
-                                                    .map(|v| v.trim().to_owned())
-                                                    .collect::<Vec<_>>(),
-                                            ))
-                                        }
-                                        _ => (),
-                                    }
-                                } else {
-                                    match line.trim() {
-                                        "extensions" => extensions = Some(None),
-                                        _ => (),
-                                    }
+                    Ok(())
+                }
+            }
+            "song-info" | "album-info" | "artist-info" => {
+                if let Some(id) = request.next().and_then(|id| id.parse().ok()) {
+                    let db = db.lock().unwrap();
+                    let bytes = match req {
+                        "song-info" => db.songs().get(&id).map(Song::to_bytes_vec),
+                        "album-info" => db.albums().get(&id).map(Album::to_bytes_vec),
+                        _ => db.artists().get(&id).map(Artist::to_bytes_vec),
+                    };
+                    drop(db);
+                    if let Some(bytes) = bytes {
+                        writeln!(out, "len: {}", bytes.len())?;
+                        out.write_all(&bytes)?;
+                    } else {
+                        writeln!(out, "not found")?;
+                    }
+                } else {
+                    writeln!(out, "bad id")?;
+                }
+            }
+            "status" => {
+                let status = Status::get(db).lines();
+                writeln!(out, "len: {}", status.len())?;
+                for (key, value) in status {
+                    writeln!(out, "{key}: {value}")?;
+                }
+            }
+            "now-playing" => {
+                let now_playing = NowPlaying::get(db).lines();
+                writeln!(out, "len: {}", now_playing.len())?;
+                for (key, value) in now_playing {
+                    writeln!(out, "{key}: {value}")?;
+                }
+            }
+            "find-unused-song-files" => {
+                // configure search
+                let mut extensions = None;
+                loop {
+                    if let Some(line) = request.next() {
+                        if let Some((key, value)) = line.split_once("=") {
+                            match key.trim() {
+                                "extensions" => {
+                                    extensions = Some(Some(
+                                        value
+                                            .split(':')
+                                            .map(|v| v.trim().to_owned())
+                                            .collect::<Vec<_>>(),
+                                    ))
                                 }
-                            } else {
-                                break;
+                                _ => (),
                             }
-                        }
-                        // search
-                        let lib_dir = db.lock().unwrap().lib_directory.clone();
-                        let unused = find_unused_song_files(
-                            &db,
-                            &lib_dir,
-                            &mut FindUnusedSongFilesConfig {
-                                extensions: extensions
-                                    .unwrap_or_else(|| Some(vec![".mp3".to_owned()])),
-                                w: connection.get_mut(),
-                                last_write: Instant::now(),
-                                new: 0,
-                                songs: 0,
-                            },
-                        );
-                        writeln!(connection.get_mut(), "len: {}", unused.len())?;
-                        for path in unused {
-                            if let Some(path) = path.to_str().filter(|v| !v.contains('\n')) {
-                                writeln!(connection.get_mut(), "#{path}")?;
-                            } else {
-                                let path = path.to_string_lossy().replace('\n', "");
-                                writeln!(connection.get_mut(), "!{path}")?;
+                        } else {
+                            match line.trim() {
+                                "extensions" => extensions = Some(None),
+                                _ => (),
                             }
                         }
+                    } else {
+                        break;
+                    }
+                }
+                // search
+                let lib_dir = db.lock().unwrap().lib_directory.clone();
+                let unused = find_unused_song_files(
+                    db,
+                    &lib_dir,
+                    &mut FindUnusedSongFilesConfig {
+                        extensions: extensions.unwrap_or_else(|| Some(vec![".mp3".to_owned()])),
+                        w: &mut *out,
+                        last_write: Instant::now(),
+                        new: 0,
+                        songs: 0,
+                    },
+                );
+                writeln!(out, "len: {}", unused.len())?;
+                for path in unused {
+                    if let Some(path) = path.to_str().filter(|v| !v.contains('\n')) {
+                        writeln!(out, "#{path}")?;
+                    } else {
+                        let path = path.to_string_lossy().replace('\n', "");
+                        writeln!(out, "!{path}")?;
                     }
-                    _ => {}
                 }
             }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// sends a binary response to `cover-bytes`, `song-file` and `custom-file` requests on a framed connection.
+/// returns `false` for other requests, which get their text response in a frame instead.
+fn handle_request_framed(
+    db: &Arc<Mutex<Database>>,
+    request: &str,
+    out: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let mut request = request.lines();
+    let bytes = match request.next() {
+        Some("cover-bytes") => match request.next().and_then(|id| id.parse().ok()) {
+            Some(id) => covers_bytes(db, &[id])
+                .pop()
+                .map_or_else(|| Err("no cover".to_owned()), |(_, bytes)| bytes),
+            None => Err("bad id".to_owned()),
+        },
+        Some("custom-file") => request
+            .next()
+            .and_then(|path| custom_file_bytes(db, path))
+            .ok_or_else(|| "no data".to_owned()),
+        Some("song-file") => {
+            let (id, offset, crc32) = song_file_request(request);
+            // the checksum is sent as a u32 after the payload
+            let sent = with_song_file(db, id, offset, |data, len| {
+                framing::write_response_header(out, framing::STATUS_OK, len)?;
+                let crc = write_chunks(out, data, len)?;
+                if crc32 {
+                    out.write_all(&crc.to_be_bytes())?;
+                }
+                Ok(())
+            })?;
+            match sent {
+                Ok(()) => return Ok(true),
+                Err(e) => Err(e.to_owned()),
+            }
+        }
+        _ => return Ok(false),
+    };
+    match bytes {
+        Ok(bytes) => framing::write_response(out, framing::STATUS_OK, &bytes)?,
+        Err(e) => framing::write_response(out, framing::STATUS_ERR, e.as_bytes())?,
+    }
+    Ok(true)
+}
+
+/// the song, offset and `crc32` option of a `song-file` request
+fn song_file_request<'a>(
+    mut request: impl Iterator<Item = &'a str>,
+) -> (Option<SongId>, u64, bool) {
+    let id = request.next().and_then(|id| id.parse().ok());
+    // optional: the offset to start at, and `crc32`
+    let (mut offset, mut crc32) = (0, false);
+    for option in request {
+        if option == "crc32" {
+            crc32 = true;
+        } else if let Ok(o) = option.parse() {
+            offset = o;
+        }
+    }
+    (id, offset, crc32)
+}
+
+/// calls `send` with the song's data, starting at `offset`, and the number of bytes left,
+/// or returns why the song's data can't be sent
+fn with_song_file(
+    db: &Mutex<Database>,
+    id: Option<SongId>,
+    offset: u64,
+    send: impl FnOnce(&mut dyn Read, u64) -> Result<(), std::io::Error>,
+) -> Result<Result<(), &'static str>, std::io::Error> {
+    // the cached data, or the path of the file if it isn't cached
+    let source = id.and_then(|id| {
+        let db = db.lock().unwrap();
+        let song = db.get_song(&id)?;
+        let cd = song.cached_data();
+        if let Some(data) = cd.cached_data() {
+            Some(Ok(data))
+        } else if db.remote_server_as_song_file_source.is_some() {
+            if let Some(data) = cd.get_data_or_maybe_start_thread(&db, song) {
+                Some(Ok(data))
+            } else {
+                let cd = cd.clone();
+                drop(db);
+                cd.cached_data_await().map(Ok)
+            }
         } else {
-            return Ok(());
+            Some(Err(db.get_path(&song.location)))
+        }
+    });
+    match source {
+        Some(Ok(bytes)) if offset > bytes.len() as u64 => Ok(Err("bad offset")),
+        Some(Ok(bytes)) => {
+            send(&mut &bytes[offset as usize..], bytes.len() as u64 - offset).map(Ok)
+        }
+        Some(Err(path)) => {
+            match fs::File::open(path).and_then(|file| Ok((file.metadata()?.len(), file))) {
+                Ok((len, _)) if offset > len => Ok(Err("bad offset")),
+                Ok((len, mut file)) => {
+                    file.seek(SeekFrom::Start(offset))?;
+                    send(&mut file, len - offset).map(Ok)
+                }
+                Err(_) => Ok(Err("no data")),
+            }
         }
+        None => Ok(Err("no data")),
+    }
+}
+
+/// the custom file from the custom files directory or the remote server, see `custom_files_dir`
+fn custom_file_bytes(db: &Mutex<Database>, path: &str) -> Option<Vec<u8>> {
+    let dbl = db.lock().unwrap();
+    if dbl.custom_files.is_none() {
+        // without a custom files directory, they come from the remote server if there is one
+        let con = dbl.remote_server_as_song_file_source.clone()?;
+        drop(dbl);
+        return con.with_reconnect(|c| c.custom_file(path)).ok()?.ok();
     }
+    custom_files_dir(&dbl)
+        .and_then(|parent| join_relative(&parent, path))
+        .and_then(|path| fs::read(path).ok())
 }
 
 pub fn con_get_decode_line(line: &str) -> String {
@@ -632,6 +1792,63 @@ pub fn con_get_encode_string(line: &str) -> String {
     o
 }
 
+/// the directory from which custom files are loaded, see `Database::custom_files`.
+/// `None` if custom files are disabled or are loaded from the remote server.
+fn custom_files_dir(db: &Database) -> Option<PathBuf> {
+    match &db.custom_files {
+        None => None,
+        // if a remote source is present, this means we should ignore it. if no remote source is present, use the lib_dir.
+        Some(None) => db
+            .remote_server_as_song_file_source
+            .is_none()
+            .then(|| db.lib_directory.clone()),
+        Some(Some(p)) => Some(p.clone()),
+    }
+}
+
+/// `parent.join(path)`, or `None` if `path` is absolute or contains `..`, so it can't point outside of `parent`
+fn join_relative(parent: &Path, path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        .then(|| parent.join(path))
+}
+
+/// a `custom-files-list` request lists at most this many files, and doesn't look into deeper directories
+const CUSTOM_FILES_LIST_MAX_COUNT: usize = 10_000;
+const CUSTOM_FILES_LIST_MAX_DEPTH: usize = 8;
+
+/// The paths (relative to `dir`, separated by `/`) and sizes of the files in `dir` and its subdirectories, sorted by path.
+/// Symlinks and paths which aren't valid UTF-8 are ignored.
+fn list_custom_files(dir: &Path) -> Vec<(String, u64)> {
+    fn list(dir: &Path, prefix: &str, depth: usize, out: &mut Vec<(String, u64)>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if out.len() >= CUSTOM_FILES_LIST_MAX_COUNT {
+                return;
+            }
+            let (Ok(file_type), Ok(name)) = (entry.file_type(), entry.file_name().into_string())
+            else {
+                continue;
+            };
+            let path = format!("{prefix}{name}");
+            if file_type.is_dir() {
+                if depth < CUSTOM_FILES_LIST_MAX_DEPTH {
+                    list(&entry.path(), &format!("{path}/"), depth + 1, out);
+                }
+            } else if file_type.is_file() {
+                out.push((path, entry.metadata().map_or(0, |m| m.len())));
+            }
+        }
+    }
+    let mut files = vec![];
+    list(dir, "", 0, &mut files);
+    files.sort_unstable();
+    files
+}
+
 fn find_unused_song_files(
     db: &Arc<Mutex<Database>>,
     path: &impl AsRef<Path>,
@@ -712,3 +1929,436 @@ fn find_unused_song_files_internal(
         }
     }
 }
+
+#[test]
+fn test_file_response() {
+    let mut out = vec![];
+    write_file_response(&mut out, &b"123456789"[..], 9, 0, true).unwrap();
+    assert_eq!(out, b"len: 9 +crc32\n123456789cbf43926\n");
+    out.clear();
+    write_file_response(&mut out, &b"6789"[..], 4, 5, false).unwrap();
+    assert_eq!(out, b"len: 4 offset: 5\n6789");
+    // the file got shorter
+    assert!(write_file_response(&mut vec![], &b"1234"[..], 9, 0, false).is_err());
+}
+
+/// a connection which fails once all of `input` was read
+#[cfg(test)]
+struct Mock {
+    input: std::io::Cursor<Vec<u8>>,
+    written: Vec<u8>,
+}
+#[cfg(test)]
+impl Read for Mock {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.input.read(buf)? {
+            0 => Err(std::io::ErrorKind::ConnectionReset.into()),
+            read => Ok(read),
+        }
+    }
+}
+#[cfg(test)]
+impl Write for Mock {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.written.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+#[cfg(test)]
+fn mock(input: &[u8]) -> BufReader<Mock> {
+    BufReader::new(Mock {
+        input: std::io::Cursor::new(input.to_vec()),
+        written: vec![],
+    })
+}
+
+#[test]
+fn test_resume_song_file() {
+    // the connection drops after 4 of 9 bytes
+    let mut client = Client::new(mock(b"len: 9 +crc32\n1234")).unwrap();
+    assert!(client.song_file(7).is_err());
+    // on a new connection, only the rest is requested
+    client.con = FailFast(mock(b"len: 5 offset: 4 +crc32\n56789131da070\n"), false);
+    assert_eq!(client.song_file(7).unwrap().unwrap(), b"123456789");
+    assert_eq!(
+        client.con.0.get_ref().written,
+        b"song-file\\n7\\n4\\ncrc32\n"
+    );
+    // a server which ignores the offset sends the whole file
+    client.partial_song_file = Some((7, b"12".to_vec()));
+    client.con = FailFast(mock(b"len: 9\n123456789"), false);
+    assert_eq!(client.song_file(7).unwrap().unwrap(), b"123456789");
+    // partial data of another song isn't used
+    client.partial_song_file = Some((8, b"12".to_vec()));
+    client.con = FailFast(mock(b"len: 9 +crc32\n123456789cbf43926\n"), false);
+    assert_eq!(client.song_file(7).unwrap().unwrap(), b"123456789");
+    assert_eq!(client.con.0.get_ref().written, b"song-file\\n7\\ncrc32\n");
+}
+
+#[test]
+fn test_custom_files() {
+    let dir = crate::test_util::test_dir("custom-files");
+    fs::create_dir(dir.join("sub")).unwrap();
+    fs::write(dir.join("b.png"), [0; 3]).unwrap();
+    fs::write(dir.join("sub/a.png"), [0; 5]).unwrap();
+    assert_eq!(
+        list_custom_files(&dir),
+        [("b.png".to_owned(), 3), ("sub/a.png".to_owned(), 5)]
+    );
+    assert_eq!(
+        join_relative(&dir, "sub/a.png"),
+        Some(dir.join("sub/a.png"))
+    );
+    assert_eq!(join_relative(&dir, "sub/../../x"), None);
+    assert_eq!(join_relative(&dir, "/etc/passwd"), None);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_covers_bytes() {
+    use crate::{
+        data::{database::Cover, DatabaseLocation},
+        test_util::test_dir,
+    };
+    let dir = test_dir("covers");
+    fs::write(dir.join("a.jpg"), b"abc").unwrap();
+    let mut db = Database::new_empty_in_dir(dir.clone(), dir.clone());
+    let cover = |path: &str| Cover {
+        location: DatabaseLocation {
+            rel_path: path.into(),
+        },
+        data: Default::default(),
+    };
+    let a = db.add_cover_new(cover("a.jpg"));
+    let missing = db.add_cover_new(cover("missing.jpg"));
+    let db = Mutex::new(db);
+    assert_eq!(
+        covers_bytes(&db, &[a, missing, 99]),
+        [
+            (a, Ok(b"abc".to_vec())),
+            (missing, Err("no data".to_owned())),
+            (99, Err("no cover".to_owned()))
+        ]
+    );
+    let mut client = Client::new(mock(b"len: 2\n3\tlen: 2\nab4\terr: no cover\n")).unwrap();
+    assert_eq!(
+        client.covers_bytes(&[3, 4]).unwrap().unwrap(),
+        [(3, Ok(b"ab".to_vec())), (4, Err("no cover".to_owned()))]
+    );
+    assert_eq!(client.con.0.get_ref().written, b"get\ncovers-bytes\\n3,4\n");
+    let version = crate::data::database::cover_version(b"abc");
+    assert_ne!(version, crate::data::database::cover_version(b"abd"));
+    assert_eq!(cover_versions(&db, &[a, missing, 99]), [(a, version)]);
+    let mut out = vec![];
+    handle_request(&Arc::new(db), &format!("cover-version\n{a},x"), &mut out).unwrap();
+    assert_eq!(out, b"bad id\n");
+    let mut client = Client::new(mock(b"len: 1\n3\t12\n")).unwrap();
+    assert_eq!(client.cover_versions(&[3, 4]).unwrap().unwrap(), [(3, 12)]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// one end of an in-memory connection, see `duplex`
+#[cfg(test)]
+struct DuplexEnd {
+    send: std::sync::mpsc::Sender<Vec<u8>>,
+    recv: std::sync::mpsc::Receiver<Vec<u8>>,
+    buf: std::io::Cursor<Vec<u8>>,
+}
+#[cfg(test)]
+impl Read for DuplexEnd {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.buf.position() as usize >= self.buf.get_ref().len() {
+            match self.recv.recv() {
+                Ok(data) => self.buf = std::io::Cursor::new(data),
+                // the other end was dropped
+                Err(_) => return Ok(0),
+            }
+        }
+        self.buf.read(buf)
+    }
+}
+#[cfg(test)]
+impl Write for DuplexEnd {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.send
+            .send(buf.to_vec())
+            .map_err(|_| std::io::ErrorKind::BrokenPipe)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+#[cfg(test)]
+fn duplex() -> (DuplexEnd, DuplexEnd) {
+    let (send_a, recv_a) = std::sync::mpsc::channel();
+    let (send_b, recv_b) = std::sync::mpsc::channel();
+    let end = |send, recv| DuplexEnd {
+        send,
+        recv,
+        buf: Default::default(),
+    };
+    (end(send_a, recv_b), end(send_b, recv_a))
+}
+
+#[test]
+fn test_framed() {
+    use crate::{
+        data::{artist::Artist, database::Cover},
+        test_util::{test_dir, test_song},
+    };
+    let dir = test_dir("framed");
+    fs::create_dir(dir.join("custom")).unwrap();
+    fs::write(dir.join("a.mp3"), b"123456789").unwrap();
+    fs::write(dir.join("cover.jpg"), b"cover").unwrap();
+    fs::write(dir.join("custom/c.txt"), b"custom\n").unwrap();
+    let mut db = Database::new_empty_in_dir(dir.clone(), dir.clone());
+    db.custom_files = Some(Some(dir.join("custom")));
+    let cover = db.add_cover_new(Cover {
+        location: "cover.jpg".into(),
+        data: Default::default(),
+    });
+    let artist = db.add_artist_new(Artist {
+        id: 0,
+        name: "artist".to_owned(),
+        cover: None,
+        albums: vec![],
+        singles: vec![],
+        general: Default::default(),
+    });
+    let mut song = test_song("song", None, artist, 0);
+    song.location = "a.mp3".into();
+    song.file_size = 9;
+    let song = db.add_song_new(song);
+    let db = Arc::new(Mutex::new(db));
+    let (client_end, server_end) = duplex();
+    let server = std::thread::spawn(move || {
+        let mut con = BufReader::new(server_end);
+        // the first line is handled by `handle_connection`
+        let mut line = String::new();
+        con.read_line(&mut line).unwrap();
+        assert_eq!(line, "get protocol=2\n");
+        handle_one_connection_as_get(db, &mut con, true).unwrap();
+    });
+    let mut client = Client::new_framed(BufReader::new(client_end)).unwrap();
+    assert_eq!(client.cover_bytes(cover).unwrap().unwrap(), b"cover");
+    assert_eq!(client.cover_bytes(99).unwrap(), Err("no cover".to_owned()));
+    assert_eq!(client.song_file(song).unwrap().unwrap(), b"123456789");
+    // resuming only requests the rest of the file
+    client.partial_song_file = Some((song, b"1234".to_vec()));
+    assert_eq!(client.song_file(song).unwrap().unwrap(), b"123456789");
+    assert_eq!(client.song_file(99).unwrap(), Err("no data".to_owned()));
+    assert_eq!(client.custom_file("c.txt").unwrap().unwrap(), b"custom\n");
+    assert_eq!(
+        client.custom_file("../a.mp3").unwrap(),
+        Err("no data".to_owned())
+    );
+    // requests without a binary response get their text response in a frame
+    assert_eq!(
+        client.custom_files_list().unwrap().unwrap(),
+        [("c.txt".to_owned(), 7)]
+    );
+    drop(client);
+    server.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_reconnect() {
+    // the first connection fails after 2 of 3 bytes
+    let mut connections = vec![&b"len: 3\nab"[..], b"len: 3\nabc"].into_iter();
+    let mut client = Client::connect(
+        Box::new(move || Ok(mock(connections.next().unwrap()).into_inner())),
+        false,
+    )
+    .unwrap();
+    assert!(client.cover_bytes(1).is_err());
+    // doesn't wait for a response on the failed connection
+    assert_eq!(
+        client.cover_bytes(1).unwrap_err().kind(),
+        std::io::ErrorKind::NotConnected
+    );
+    assert_eq!(
+        client
+            .with_reconnect(|c| c.cover_bytes(1))
+            .unwrap()
+            .unwrap(),
+        b"abc"
+    );
+    assert_eq!(client.con.0.get_ref().written, b"get\ncover-bytes\\n1\n");
+}
+
+#[test]
+fn test_pool() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    let opened = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let opened = Arc::clone(&opened);
+        Pool::connect(
+            Box::new(move || {
+                opened.fetch_add(1, Ordering::Relaxed);
+                Ok(mock(b"len: 1\nalen: 1\nb").into_inner())
+            }),
+            false,
+        )
+        .unwrap()
+    };
+    assert_eq!(opened.load(Ordering::Relaxed), 1);
+    // a second request while the first connection is in use opens another connection
+    let (outer, inner) = pool
+        .with_reconnect(|a| {
+            let inner = pool.with_reconnect(|b| b.cover_bytes(2))?;
+            Ok((a.cover_bytes(1)?, inner))
+        })
+        .unwrap();
+    assert_eq!(
+        (outer.unwrap(), inner.unwrap()),
+        (b"a".to_vec(), b"a".to_vec())
+    );
+    assert_eq!(opened.load(Ordering::Relaxed), 2);
+    // idle connections are reused
+    assert_eq!(
+        pool.with_reconnect(|c| c.cover_bytes(3)).unwrap().unwrap(),
+        b"b"
+    );
+    assert_eq!(opened.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn test_info() {
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    let artist = db.add_artist_new(Artist {
+        id: 0,
+        name: "artist".to_owned(),
+        cover: None,
+        albums: vec![],
+        singles: vec![],
+        general: Default::default(),
+    });
+    let db = Arc::new(Mutex::new(db));
+    let mut out = vec![];
+    handle_request(&db, &format!("artist-info\n{artist}"), &mut out).unwrap();
+    handle_request(&db, "artist-info\n99", &mut out).unwrap();
+    let mut client = Client::new(mock(&out)).unwrap();
+    assert_eq!(client.artist_info(artist).unwrap().unwrap().name, "artist");
+    assert_eq!(client.artist_info(99).unwrap(), Err("not found".to_owned()));
+}
+
+#[test]
+fn test_now_playing() {
+    use crate::{
+        data::queue::QueueContent,
+        server::{Action, Req},
+        test_util::test_song,
+    };
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    let artist = db.add_artist_new(Artist {
+        id: 0,
+        name: "artist".to_owned(),
+        cover: None,
+        albums: vec![],
+        singles: vec![],
+        general: Default::default(),
+    });
+    let songs = ["a", "b\nc"]
+        .into_iter()
+        .map(|title| db.add_song_new(test_song(title, None, artist, 90_000)))
+        .collect::<Vec<_>>();
+    let db = Arc::new(Mutex::new(db));
+    let now_playing = |db: &Arc<Mutex<Database>>| {
+        let mut out = vec![];
+        handle_request(db, "now-playing", &mut out).unwrap();
+        Client::new(mock(&out))
+            .unwrap()
+            .now_playing()
+            .unwrap()
+            .unwrap()
+    };
+    assert_eq!(
+        now_playing(&db),
+        [("state".to_owned(), "stopped".to_owned())]
+    );
+    {
+        let mut db = db.lock().unwrap();
+        let queue = songs
+            .iter()
+            .map(|id| QueueContent::Song(*id).into())
+            .collect();
+        db.apply_action_unchecked_seq(Action::QueueAdd(vec![], queue, Req::none()), None);
+        db.playback_position = Some((songs[0], 65_000, Instant::now()));
+    }
+    let lines = now_playing(&db);
+    let lines = lines
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            ("state", "paused"),
+            ("song", &*format!("#{}", songs[0])),
+            ("title", "a"),
+            ("artist", "artist"),
+            ("album", ""),
+            ("elapsed", "65s"),
+            ("duration", "90s"),
+            ("next", "b c - artist"),
+        ]
+    );
+}
+
+#[test]
+fn test_playlists() {
+    use crate::{
+        data::queue::{Queue, QueueContent},
+        server::{Action, Req},
+        test_util::{test_dir, test_song},
+    };
+    let dir = test_dir("get-playlists");
+    let mut db = Database::new_empty_in_dir(dir.clone(), dir.clone());
+    let song = db.add_song_new(test_song("a", None, 0, 0));
+    db.apply_action_unchecked_seq(
+        Action::QueueAdd(vec![], vec![QueueContent::Song(song).into()], Req::none()),
+        None,
+    );
+    let db = Arc::new(Mutex::new(db));
+    let playlists = |db: &Arc<Mutex<Database>>| {
+        let mut out = vec![];
+        handle_request(db, "playlists", &mut out).unwrap();
+        Client::new(mock(&out))
+            .unwrap()
+            .playlists()
+            .unwrap()
+            .unwrap()
+    };
+    assert!(playlists(&db).is_empty());
+    let apply = |action| db.lock().unwrap().apply_action_unchecked_seq(action, None);
+    apply(Action::PlaylistSave(vec![], " mix ".to_owned()));
+    apply(Action::PlaylistSave(vec![], "../mix".to_owned()));
+    apply(Action::PlaylistSave(vec![9], "missing".to_owned()));
+    assert_eq!(playlists(&db), ["mix"]);
+    apply(Action::PlaylistRename(
+        "mix".to_owned(),
+        "road trip".to_owned(),
+    ));
+    assert_eq!(playlists(&db), ["road trip"]);
+    apply(Action::PlaylistLoad("road trip".to_owned(), true));
+    {
+        let db = db.lock().unwrap();
+        assert_eq!(db.queue.len(&db), 2);
+        match db.queue.get_item_at_index(&[1], 0).map(Queue::content) {
+            Some(QueueContent::Folder(folder)) => assert_eq!(folder.name, "road trip"),
+            _ => panic!("expected the playlist's folder"),
+        }
+    }
+    apply(Action::PlaylistLoad("road trip".to_owned(), false));
+    {
+        let db = db.lock().unwrap();
+        assert_eq!(db.queue.len(&db), 1);
+    }
+    apply(Action::PlaylistDelete("road trip".to_owned()));
+    assert!(playlists(&db).is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
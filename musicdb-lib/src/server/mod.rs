@@ -1,24 +1,37 @@
+pub mod auth;
+pub mod compression;
+pub mod connection;
+pub mod framing;
 pub mod get;
+pub mod permission;
+pub mod relay;
+pub mod throttle;
+pub mod thumbnail;
 
 use std::{
-    io::{BufRead as _, BufReader, Read, Write},
+    io::{BufRead as _, BufReader, BufWriter, Read, Write},
     net::{SocketAddr, TcpListener},
+    path::PathBuf,
     sync::{mpsc, Arc, Mutex},
     thread,
     time::Duration,
 };
 
-use colorize::AnsiColor;
-
 #[cfg(feature = "playback")]
 use crate::player::Player;
-use crate::server::get::handle_one_connection_as_get;
+use crate::server::{
+    auth::Auth,
+    connection::{Connection, Listener},
+    get::handle_one_connection_as_get,
+    permission::Permission,
+};
 use crate::{
     data::{
         album::Album,
         artist::Artist,
         database::{Cover, Database, UpdateEndpoint},
         queue::Queue,
+        replay_gain::ReplayGainMode,
         song::Song,
         AlbumId, ArtistId, SongId,
     },
@@ -64,6 +77,39 @@ impl Action {
             *o = n;
         }
     }
+    /// true if the action can change the queue or the current position in it
+    pub fn changes_queue(&self) -> bool {
+        match self {
+            Self::Multiple(actions) => actions.iter().any(Self::changes_queue),
+            _ => matches!(
+                self,
+                Self::SyncDatabase(..)
+                    | Self::QueueUpdate(..)
+                    | Self::QueueAdd(..)
+                    | Self::QueueInsert(..)
+                    | Self::QueueRemove(_)
+                    | Self::QueueMove(..)
+                    | Self::QueueMoveInto(..)
+                    | Self::QueueGoto(_)
+                    | Self::QueueShuffle(_)
+                    | Self::QueueSetShuffle(..)
+                    | Self::QueueUnshuffle(_)
+                    | Self::QueueClear(_)
+                    | Self::QueueShuffleInPlace(_)
+                    | Self::QueueDuplicate(_)
+                    | Self::QueueLoopBreak(_)
+                    | Self::QueueDedupSongs(_)
+                    | Self::QueuePlayNext(_)
+                    | Self::PlaylistLoad(..)
+                    | Self::NextSong
+                    | Self::NextSongIfCurrent(..)
+                    | Self::QueueFinished
+                    | Self::RemoveSong(_)
+                    | Self::RemoveAlbum(_)
+                    | Self::RemoveArtist(_)
+            ),
+        }
+    }
     fn req_mut(&mut self) -> Vec<&mut Req> {
         match self {
             Self::QueueUpdate(_, _, req)
@@ -81,6 +127,8 @@ impl Action {
             | Self::Pause
             | Self::Stop
             | Self::NextSong
+            | Self::NextSongIfCurrent(_, _)
+            | Self::QueueFinished
             | Self::SyncDatabase(_, _, _)
             | Self::QueueRemove(_)
             | Self::QueueMove(_, _)
@@ -89,6 +137,24 @@ impl Action {
             | Self::QueueShuffle(_)
             | Self::QueueSetShuffle(_, _)
             | Self::QueueUnshuffle(_)
+            | Self::QueueClear(_)
+            | Self::QueueShuffleInPlace(_)
+            | Self::QueueDuplicate(_)
+            | Self::QueueLoopBreak(_)
+            | Self::QueueDedupSongs(_)
+            | Self::QueuePlayNext(_)
+            | Self::QueueSetLabel(_, _)
+            | Self::SetAutoplayOnEmpty(_)
+            | Self::SetVolume(_)
+            | Self::SetCrossfade(_)
+            | Self::SetReplayGain(..)
+            | Self::SetSleepTimer(..)
+            | Self::PlaylistSave(..)
+            | Self::PlaylistLoad(..)
+            | Self::PlaylistDelete(_)
+            | Self::PlaylistRename(..)
+            | Self::Seek(_)
+            | Self::SeekBy(_)
             | Self::RemoveSong(_)
             | Self::RemoveAlbum(_)
             | Self::RemoveArtist(_)
@@ -107,6 +173,11 @@ impl Action {
             | Self::TagArtistPropertyUnset(_, _)
             | Self::InitComplete
             | Self::Save
+            | Self::RescanLibrary
+            | Self::SetDurationBackfill(_)
+            | Self::VerifyLibrary(_)
+            | Self::PlaybackPosition(_, _)
+            | Self::Ping
             | Self::ErrorInfo(_, _) => vec![],
             Self::Multiple(actions) => actions.iter_mut().flat_map(|v| v.req_mut()).collect(),
         }
@@ -176,6 +247,17 @@ pub enum Action {
     Pause,
     Stop,
     NextSong,
+    /// Like `NextSong`, but only if the current song is the one with this id at this path in the queue.
+    /// Sent by clients, so that if multiple clients skip a song at the same time, only one song is skipped.
+    /// Never broadcast, the server broadcasts `NextSong` instead.
+    NextSongIfCurrent(Vec<usize>, SongId),
+    /// Jump to this position (in milliseconds) in the current song. Seeking past the end skips to the next song.
+    Seek(u64),
+    /// Like `Seek`, but relative to the current position
+    SeekBy(i64),
+    /// sent by the server when the end of the queue was reached.
+    /// stops playback and goes back to the start of the queue.
+    QueueFinished,
     SyncDatabase(Vec<Artist>, Vec<Album>, Vec<Song>),
     QueueUpdate(Vec<usize>, Queue, Req),
     QueueAdd(Vec<usize>, Vec<Queue>, Req),
@@ -191,6 +273,45 @@ pub enum Action {
     // sent by the server when the folder was shuffled
     QueueSetShuffle(Vec<usize>, Vec<usize>),
     QueueUnshuffle(Vec<usize>),
+    /// Remove all elements from the folder at the path
+    QueueClear(Vec<usize>),
+    // sent by clients when they want to permanently shuffle a folder's contents.
+    // the server then sends a `QueueUpdate` with the shuffled folder.
+    QueueShuffleInPlace(Vec<usize>),
+    // sent by clients to add a copy of an element to the end of its parent.
+    // the server then sends a `QueueAdd` with the copy.
+    QueueDuplicate(Vec<usize>),
+    /// Make the current pass of the loop at the path its last one
+    QueueLoopBreak(Vec<usize>),
+    // sent by clients to remove songs which appear more than once in the element at the path.
+    // the server then sends the `QueueRemove`s and an `ErrorInfo` with the number of removed songs.
+    QueueDedupSongs(Vec<usize>),
+    /// Insert the element right after the current one, see `Queue::insert_after_current`
+    QueuePlayNext(Queue),
+    /// Set or remove the label of the element at the path
+    QueueSetLabel(Vec<usize>, Option<String>),
+    /// If enabled, the server adds a `Random` element instead of stopping when the end of the queue is reached
+    SetAutoplayOnEmpty(bool),
+    /// Output volume, 0.0 to 1.0
+    SetVolume(f32),
+    /// How long songs overlap when fading from one to the next, in milliseconds (0 to disable)
+    SetCrossfade(u32),
+    /// ReplayGain mode and pre-amp (in dB). Used for songs which are loaded after this change.
+    SetReplayGain(ReplayGainMode, f32),
+    /// Pause in this many seconds, or, if the `bool` is true, once the song which is playing at that time ends.
+    /// `None` removes the timer. Replaces any previous timer. Broadcast again (with `None`) when the timer expires.
+    SetSleepTimer(Option<u64>, bool),
+    /// Sent by clients: save the queue element at the path as a playlist with this name, replacing any playlist
+    /// with the same name. The server reports the result using an `ErrorInfo`, see `playlist`.
+    PlaylistSave(Vec<usize>, String),
+    /// Sent by clients: add the playlist to the end of the queue (`true`), or replace the queue with it (`false`).
+    /// The server then sends a `QueueAdd` or `QueueUpdate`, and an `ErrorInfo` with the number of skipped songs.
+    PlaylistLoad(String, bool),
+    /// Sent by clients: delete the playlist. The server reports the result using an `ErrorInfo`.
+    PlaylistDelete(String),
+    /// Sent by clients: rename the playlist, unless another playlist already has the new name.
+    /// The server reports the result using an `ErrorInfo`.
+    PlaylistRename(String, String),
 
     /// .id field is ignored!
     AddSong(Song, Req),
@@ -228,6 +349,21 @@ pub enum Action {
 
     InitComplete,
     Save,
+    /// Sent by the server regularly to detect dead connections. Does nothing.
+    Ping,
+    /// Sent by clients: search the library directory for new and changed songs, see `scan::start_rescan`.
+    /// Handled by the server, not by the database.
+    RescanLibrary,
+    /// Sent by clients: start (`true`) or abort (`false`) determining the duration of songs whose duration is 0,
+    /// see `scan::set_duration_backfill`. Handled by the server, not by the database.
+    SetDurationBackfill(bool),
+    /// Sent by clients: start (`true`) or abort (`false`) checking that every song's file exists and is readable,
+    /// see `scan::set_library_verification`. Handled by the server, not by the database.
+    VerifyLibrary(bool),
+    /// Sent by a client on an `output` connection (the audio output of a `--no-playback` server):
+    /// the position (in milliseconds) in the song it is playing. Handled by the server's `Relay`,
+    /// the database only remembers it as its `playback_position`.
+    PlaybackPosition(SongId, u64),
     ErrorInfo(String, String),
 
     /// The server denied a request or an action.
@@ -252,7 +388,7 @@ impl Command {
     }
 }
 
-/// starts handling database.command_sender events and optionally spawns a tcp server.
+/// starts handling database.command_sender events and optionally spawns a tcp and/or unix socket server.
 /// this function creates a new command_sender.
 /// if you wish to implement your own server, set db.command_sender to None,
 /// start a new thread running this function,
@@ -271,11 +407,21 @@ pub fn run_server(
     sender_sender: Option<Box<dyn FnOnce(mpsc::Sender<(Command, Option<u64>)>)>>,
     play_audio: bool,
 ) {
-    run_server_caching_thread_opt(database, addr_tcp, sender_sender, None, play_audio)
+    run_server_caching_thread_opt(
+        database,
+        addr_tcp.map(|addr| (addr, Permission::Full)),
+        None,
+        None,
+        sender_sender,
+        None,
+        play_audio,
+    )
 }
 pub fn run_server_caching_thread_opt(
     database: Arc<Mutex<Database>>,
-    addr_tcp: Option<SocketAddr>,
+    addr_tcp: Option<(SocketAddr, Permission)>,
+    addr_unix: Option<(PathBuf, Permission)>,
+    auth: Option<Auth>,
     sender_sender: Option<Box<dyn FnOnce(mpsc::Sender<(Command, Option<u64>)>)>>,
     caching_thread: Option<Box<dyn FnOnce(&mut crate::data::cache_manager::CacheManager)>>,
     play_audio: bool,
@@ -313,6 +459,9 @@ pub fn run_server_caching_thread_opt(
     } else {
         None
     };
+    // without audio output, the relay advances the queue, see `relay::Relay`
+    let mut relay = (database.lock().unwrap().relay && !play_audio).then(relay::Relay::new);
+    let mut relay_remaining = None;
     #[allow(unused)]
     let cache_manager = if let Some(func) = caching_thread {
         let mut cm = CacheManager::new(Arc::clone(&database));
@@ -325,54 +474,41 @@ pub fn run_server_caching_thread_opt(
         s(command_sender.clone())
     }
     database.lock().unwrap().command_sender = Some(command_sender.clone());
-    if let Some(addr) = addr_tcp {
+    let mut listeners: Vec<(Box<dyn Listener>, Permission)> = vec![];
+    if let Some((addr, permission)) = addr_tcp {
         match TcpListener::bind(addr) {
-            Ok(v) => {
-                let command_sender = command_sender.clone();
-                let db = Arc::clone(&database);
-                thread::spawn(move || loop {
-                    if let Ok((connection, _con_addr)) = v.accept() {
-                        let command_sender = command_sender.clone();
-                        let db = Arc::clone(&db);
-                        thread::spawn(move || {
-                            // each connection first has to send one line to tell us what it wants
-                            let mut connection = BufReader::new(connection);
-                            let mut line = String::new();
-                            if connection.read_line(&mut line).is_ok() {
-                                // based on that line, we adjust behavior
-                                match line.as_str().trim() {
-                                    // sends all updates to this connection and reads commands from it
-                                    "main" => {
-                                        let connection = connection.into_inner();
-                                        _ = handle_one_connection_as_main(
-                                            db,
-                                            &mut connection.try_clone().unwrap(),
-                                            connection,
-                                            &command_sender,
-                                        )
-                                    }
-                                    // reads commands from the connection, but (unlike main) doesn't send any updates
-                                    "control" => handle_one_connection_as_control(
-                                        &mut connection,
-                                        &command_sender,
-                                        None,
-                                    ),
-                                    "get" => _ = handle_one_connection_as_get(db, &mut connection),
-                                    _ => {
-                                        _ = connection
-                                            .into_inner()
-                                            .shutdown(std::net::Shutdown::Both)
-                                    }
-                                }
-                            }
-                        });
-                    }
-                });
+            Ok(v) => listeners.push((Box::new(v), permission)),
+            Err(e) => {
+                log_error!("Couldn't start TCP listener: {e}");
             }
+        }
+    }
+    if let Some((path, permission)) = addr_unix {
+        #[cfg(unix)]
+        match connection::bind_unix(&path) {
+            Ok(v) => listeners.push((Box::new(v), permission)),
             Err(e) => {
-                eprintln!("[{}] Couldn't start TCP listener: {e}", "ERR!".red());
+                log_error!("Couldn't start unix socket listener: {e}");
             }
         }
+        #[cfg(not(unix))]
+        log_error!("Can't listen on {path:?}: unix sockets are not supported on this platform");
+    }
+    let auth = auth.map(Arc::new);
+    for (listener, permission) in listeners {
+        let command_sender = command_sender.clone();
+        let db = Arc::clone(&database);
+        let auth = auth.clone();
+        thread::spawn(move || loop {
+            if let Ok(connection) = listener.accept_connection() {
+                let command_sender = command_sender.clone();
+                let db = Arc::clone(&db);
+                let auth = auth.clone();
+                thread::spawn(move || {
+                    handle_connection(connection, db, &command_sender, auth.as_deref(), permission)
+                });
+            }
+        });
     }
     #[cfg(feature = "playback")]
     let song_done_polling = player
@@ -387,7 +523,26 @@ pub fn run_server_caching_thread_opt(
     };
     let mut check = 0;
     let mut checkf = true;
+    let mut last_ping = Instant::now();
     loop {
+        if last_ping.elapsed() >= PING_INTERVAL {
+            last_ping = Instant::now();
+            database.lock().unwrap().ping_clients();
+        }
+        // pause once the sleep timer expires, unless it waits for the song to end (see `Action::NextSong`)
+        let sleep_timer = database
+            .lock()
+            .unwrap()
+            .sleep_timer_remaining()
+            .filter(|(_, after_song)| !after_song);
+        if sleep_timer.is_some_and(|(remaining, _)| remaining.is_zero()) {
+            database
+                .lock()
+                .unwrap()
+                .apply_action_unchecked_seq(Action::SetSleepTimer(None, false), None);
+            // through the channel, so the player pauses too
+            _ = command_sender.send((Action::Pause.cmd(0xFF), None));
+        }
         check += 1;
         #[cfg(feature = "playback")]
         let song_finished = player.as_ref().is_some_and(|p| p.backend.song_finished());
@@ -406,37 +561,198 @@ pub fn run_server_caching_thread_opt(
                 } else {
                     player.update(&mut db);
                 }
+                db.playback_position = player
+                    .song_position()
+                    .map(|(id, pos)| (id, pos, Instant::now()));
+            }
+            if let Some(relay) = &mut relay {
+                relay_remaining = relay.update(&mut db);
+                db.playback_position = relay
+                    .song_position()
+                    .map(|(id, pos)| (id, pos, Instant::now()));
             }
             // autosave if necessary
-            if let Some((first, last)) = db.times_data_modified {
-                let now = Instant::now();
-                if (now - first).as_secs_f32() > 60.0 && (now - last).as_secs_f32() > 5.0 {
-                    if let Err(e) = db.save_database(None) {
-                        eprintln!("[{}] Autosave failed: {e}", "ERR!".red());
-                    }
-                }
+            if let Err(e) = db.autosave() {
+                log_error!("Autosave failed: {e}");
             }
         }
-        if let Ok((command, client)) = command_receiver.recv_timeout(dur) {
+        let timeout = sleep_timer
+            .map(|(remaining, _)| remaining)
+            .into_iter()
+            .chain(relay_remaining)
+            .fold(dur, Duration::min);
+        if let Ok((command, client)) = command_receiver.recv_timeout(timeout) {
             checkf = true;
-            #[cfg(feature = "playback")]
-            if let Some(player) = &mut player {
-                player.handle_action(&command.action);
+            // commands which arrive shortly after this one are broadcast together
+            let batch_start = Instant::now();
+            database.lock().unwrap().batch_broadcasts();
+            let mut next = Some((command, client));
+            while let Some((command, client)) = next.take() {
+                #[cfg(feature = "playback")]
+                if let Some(player) = &mut player {
+                    player.handle_action(&command.action);
+                }
+                if let Some(relay) = &mut relay {
+                    relay.handle_action(&command.action);
+                }
+                match command.action {
+                    Action::RescanLibrary => crate::data::scan::start_rescan(&database, client),
+                    Action::SetDurationBackfill(enable) => {
+                        crate::data::scan::set_duration_backfill(&database, enable)
+                    }
+                    Action::VerifyLibrary(enable) => {
+                        crate::data::scan::set_library_verification(&database, enable, client)
+                    }
+                    _ => {}
+                }
+                database.lock().unwrap().apply_command(command, client);
+                if batch_start.elapsed() < BATCH_MAX_DURATION {
+                    next = command_receiver.recv_timeout(BATCH_WINDOW).ok();
+                }
             }
-            database.lock().unwrap().apply_command(command, client);
+            database.lock().unwrap().flush_broadcasts();
+        }
+    }
+}
+
+/// how long writing to a client may block before the client is considered dead
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// how often `Ping` is sent to all clients, so dead connections are detected even if nothing else is sent
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// after a command, wait this long for more commands before sending the broadcasts to all clients
+const BATCH_WINDOW: Duration = Duration::from_millis(2);
+/// broadcasts are never delayed by more than this (plus `BATCH_WINDOW`)
+const BATCH_MAX_DURATION: Duration = Duration::from_millis(20);
+
+/// see `Database::open_connections`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionCounts {
+    pub main: usize,
+    pub control: usize,
+    pub get: usize,
+    /// clients which play the audio for a `--no-playback` server, see `Relay`
+    pub output: usize,
+}
+impl ConnectionCounts {
+    /// the counter for the connection type requested by the first line of a connection
+    fn count_mut(&mut self, kind: &str) -> Option<&mut usize> {
+        match kind {
+            "main" => Some(&mut self.main),
+            "control" => Some(&mut self.control),
+            "get" => Some(&mut self.get),
+            "output" => Some(&mut self.output),
+            _ => None,
         }
     }
 }
 
+/// handles a new connection to the server (tcp or unix socket)
+pub fn handle_connection(
+    connection: Box<dyn Connection>,
+    db: Arc<Mutex<Database>>,
+    command_sender: &mpsc::Sender<(Command, Option<u64>)>,
+    auth: Option<&Auth>,
+    mut permission: Permission,
+) {
+    let mut connection = BufReader::new(connection);
+    if let Some(auth) = auth {
+        if let Some(token_permission) = auth.authenticate(&mut connection) {
+            // the listener's permission can't be exceeded using a token
+            permission = permission.min(token_permission);
+        } else {
+            _ = connection.into_inner().shutdown_connection();
+            return;
+        }
+    }
+    // each connection first has to send one line to tell us what it wants
+    let mut line = String::new();
+    if connection.read_line(&mut line).is_ok() {
+        // clients may authenticate even if the server doesn't require it
+        if auth.is_none() && line.starts_with("auth ") {
+            line.clear();
+            if writeln!(connection.get_mut(), "auth ok").is_err()
+                || connection.read_line(&mut line).is_err()
+            {
+                return;
+            }
+        }
+        // the type of connection, optionally followed by options, for example `main compression=deflate`
+        let mut options = line.split_whitespace();
+        let kind = options.next().unwrap_or("");
+        if let Some(count) = db.lock().unwrap().open_connections.count_mut(kind) {
+            *count += 1;
+        }
+        let database = Arc::clone(&db);
+        // based on that line, we adjust behavior
+        match kind {
+            // sends all updates to this connection and reads commands from it
+            "main" => {
+                let mut connection = connection.into_inner();
+                if let Ok(send_to) = connection.try_clone_connection() {
+                    // a client which doesn't read its updates shouldn't block broadcasts to everyone else for long.
+                    // if a write fails, the client is removed from the update endpoints and the connection is closed.
+                    _ = send_to.set_write_timeout_connection(Some(WRITE_TIMEOUT));
+                    _ = handle_one_connection_as_main(
+                        db,
+                        &mut connection,
+                        connection::ShutdownOnDrop(send_to),
+                        command_sender,
+                        permission,
+                        options.any(|option| option == compression::OPTION_DEFLATE),
+                    )
+                }
+            }
+            // reads commands from the connection, but (unlike main) doesn't send any updates
+            // like control, but while this connection is open, the client plays the audio.
+            // see `relay::Relay`, which lets this client decide when a song ends.
+            "control" | "output" => handle_one_connection_as_control(
+                &db,
+                &mut connection,
+                command_sender,
+                None,
+                permission,
+            ),
+            "get" => {
+                let framed = options.any(|option| option == framing::OPTION_FRAMED);
+                let limit = db
+                    .lock()
+                    .unwrap()
+                    .transfer_limit
+                    .clone()
+                    .filter(|limit| limit.limit_local || !connection.get_ref().is_local());
+                if let Some(limit) = limit {
+                    let mut connection = BufReader::new(limit.throttle(connection));
+                    _ = handle_one_connection_as_get(db, &mut connection, framed);
+                } else {
+                    _ = handle_one_connection_as_get(db, &mut connection, framed);
+                }
+            }
+            _ => _ = connection.into_inner().shutdown_connection(),
+        }
+        if let Some(count) = database.lock().unwrap().open_connections.count_mut(kind) {
+            *count -= 1;
+        };
+    }
+}
 pub fn handle_one_connection_as_main(
     db: Arc<Mutex<Database>>,
     connection: &mut impl Read,
-    mut send_to: (impl Write + Sync + Send + 'static),
+    send_to: (impl Write + Sync + Send + 'static),
     command_sender: &mpsc::Sender<(Command, Option<u64>)>,
+    permission: Permission,
+    compression: bool,
 ) -> Result<(), std::io::Error> {
+    // buffered so that the initial sync and batched broadcasts are sent using as few writes as possible
+    let mut send_to = BufWriter::new(send_to);
+    if compression {
+        writeln!(send_to, "{}", compression::OPTION_DEFLATE)?;
+    }
     // sync database
-    let mut db = db.lock().unwrap();
-    db.init_connection(&mut send_to)?;
+    let database = db;
+    let mut db = database.lock().unwrap();
+    db.init_connection_opt(&mut send_to, compression)?;
+    send_to.flush()?;
     // keep the client in sync:
     // the db will send all updates to the client once it is added to update_endpoints
     let udepid = db.update_endpoints_id;
@@ -450,17 +766,34 @@ pub fn handle_one_connection_as_main(
     ));
     // drop the mutex lock
     drop(db);
-    handle_one_connection_as_control(connection, command_sender, Some(udepid));
+    handle_one_connection_as_control(
+        &database,
+        connection,
+        command_sender,
+        Some(udepid),
+        permission,
+    );
     Ok(())
 }
 pub fn handle_one_connection_as_control(
+    db: &Arc<Mutex<Database>>,
     connection: &mut impl Read,
     command_sender: &mpsc::Sender<(Command, Option<u64>)>,
     client: Option<u64>,
+    permission: Permission,
 ) {
     // read updates from the tcp stream and send them to the database, exit on EOF or Err
     loop {
-        if let Ok(command) = Command::from_bytes(connection) {
+        if let Ok(mut command) = Command::from_bytes(connection) {
+            if let Some(error) = permission.error_if_denied(&command.action) {
+                // only tell this client, control connections don't receive anything
+                if let Some(client) = client {
+                    let mut db = db.lock().unwrap();
+                    db.send_to_client(client, error.cmd(0xFFu8));
+                    db.deny(&mut command.action, client);
+                }
+                continue;
+            }
             command_sender.send((command, client)).unwrap();
         } else {
             break;
@@ -481,13 +814,29 @@ const BYTE_RESUME: u8 = 0b01_000_000;
 const BYTE_PAUSE: u8 = 0b01_000_001;
 const BYTE_STOP: u8 = 0b01_000_010;
 const BYTE_NEXT_SONG: u8 = 0b01_000_100;
+const BYTE_NEXT_SONG_IF_CURRENT: u8 = 0b01_000_101;
+const BYTE_PLAYBACK_POSITION: u8 = 0b01_000_011;
+const BYTE_QUEUE_FINISHED: u8 = 0b01_001_000;
+const BYTE_SET_AUTOPLAY_ON_EMPTY: u8 = 0b01_001_001;
+const BYTE_SEEK: u8 = 0b01_001_010;
+const BYTE_SEEK_BY: u8 = 0b01_001_100;
+const BYTE_SET_SLEEP_TIMER: u8 = 0b01_001_011;
+const BYTE_SET_VOLUME: u8 = 0b01_010_011;
+const BYTE_SET_CROSSFADE: u8 = 0b01_010_101;
+const BYTE_SET_REPLAY_GAIN: u8 = 0b01_010_110;
 
 const BYTE_MULTIPLE: u8 = 0b01_010_100;
 const BYTE_INIT_COMPLETE: u8 = 0b01_010_000;
 const BYTE_SET_SONG_DURATION: u8 = 0b01_010_001;
 const BYTE_SAVE: u8 = 0b01_010_010;
+const BYTE_RESCAN_LIBRARY: u8 = 0b01_100_101;
+const BYTE_SET_DURATION_BACKFILL: u8 = 0b01_100_110;
+const BYTE_VERIFY_LIBRARY: u8 = 0b01_100_111;
+const BYTE_PING: u8 = 0b01_010_111;
 const BYTE_ERRORINFO: u8 = 0b01_100_010;
 const BYTE_DENIED: u8 = 0b01_100_011;
+/// followed by a compressed action, see `Action::compressed_to_bytes`
+const BYTE_COMPRESSED: u8 = 0b01_100_100;
 
 const BYTE_QUEUE_UPDATE: u8 = 0b10_000_000;
 const BYTE_QUEUE_ADD: u8 = 0b10_000_001;
@@ -500,8 +849,20 @@ const BYTE_QUEUE_ACTION: u8 = 0b10_001_100;
 const SUBBYTE_ACTION_SHUFFLE: u8 = 0b01_000_001;
 const SUBBYTE_ACTION_SET_SHUFFLE: u8 = 0b01_000_010;
 const SUBBYTE_ACTION_UNSHUFFLE: u8 = 0b01_000_100;
+const SUBBYTE_ACTION_CLEAR: u8 = 0b01_001_000;
+const SUBBYTE_ACTION_SHUFFLE_IN_PLACE: u8 = 0b01_001_001;
+const SUBBYTE_ACTION_DUPLICATE: u8 = 0b01_001_010;
+const SUBBYTE_ACTION_LOOP_BREAK: u8 = 0b01_001_100;
+const SUBBYTE_ACTION_DEDUP_SONGS: u8 = 0b01_010_000;
+const SUBBYTE_ACTION_PLAY_NEXT: u8 = 0b01_010_001;
+const SUBBYTE_ACTION_SET_LABEL: u8 = 0b01_010_010;
 
 const BYTE_SYNC_DATABASE: u8 = 0b10_010_100;
+const BYTE_PLAYLIST_ACTION: u8 = 0b10_010_000;
+const SUBBYTE_PLAYLIST_SAVE: u8 = 0b01_000_001;
+const SUBBYTE_PLAYLIST_LOAD: u8 = 0b01_000_010;
+const SUBBYTE_PLAYLIST_DELETE: u8 = 0b01_000_100;
+const SUBBYTE_PLAYLIST_RENAME: u8 = 0b01_001_000;
 
 const BYTE_LIB_ADD: u8 = 0b10_100_000;
 const BYTE_LIB_MODIFY: u8 = 0b10_100_001;
@@ -524,6 +885,50 @@ const SUBBYTE_TAG_ALBUM_PROPERTY_UNSET: u8 = 0b10_100_001;
 const SUBBYTE_TAG_ARTIST_PROPERTY_SET: u8 = 0b10_100_010;
 const SUBBYTE_TAG_ARTIST_PROPERTY_UNSET: u8 = 0b10_100_100;
 
+impl Action {
+    /// Writes the same bytes as `Action::SyncDatabase(artists, albums, songs).to_bytes(s)`, without having to clone everything first.
+    pub fn sync_database_to_bytes<'a, T: Write>(
+        artists: impl ExactSizeIterator<Item = &'a Artist>,
+        albums: impl ExactSizeIterator<Item = &'a Album>,
+        songs: impl ExactSizeIterator<Item = &'a Song>,
+        s: &mut T,
+    ) -> Result<(), std::io::Error> {
+        s.write_all(&[BYTE_SYNC_DATABASE])?;
+        artists.len().to_bytes(s)?;
+        for artist in artists {
+            artist.to_bytes(s)?;
+        }
+        albums.len().to_bytes(s)?;
+        for album in albums {
+            album.to_bytes(s)?;
+        }
+        songs.len().to_bytes(s)?;
+        for song in songs {
+            song.to_bytes(s)?;
+        }
+        Ok(())
+    }
+    /// Writes an action, which is compressed while `write_action` writes it.
+    /// Only clients which sent `compression::OPTION_DEFLATE` can decode this.
+    pub fn compressed_to_bytes<T: Write>(
+        s: &mut T,
+        write_action: impl FnOnce(&mut compression::CompressedWriter<&mut T>) -> std::io::Result<()>,
+    ) -> Result<(), std::io::Error> {
+        s.write_all(&[BYTE_COMPRESSED])?;
+        let mut writer = compression::CompressedWriter::new(s);
+        write_action(&mut writer)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+/// not generic, so that decoding an action doesn't recurse infinitely at compile time
+fn action_from_compressed_bytes(s: &mut dyn Read) -> Result<Action, std::io::Error> {
+    let mut reader = compression::CompressedReader::new(s);
+    let action = Action::from_bytes(&mut reader)?;
+    reader.finish()?;
+    Ok(action)
+}
+
 impl ToFromBytes for Command {
     fn to_bytes<T>(&self, s: &mut T) -> Result<(), std::io::Error>
     where
@@ -568,6 +973,41 @@ impl ToFromBytes for Action {
             Self::Pause => s.write_all(&[BYTE_PAUSE])?,
             Self::Stop => s.write_all(&[BYTE_STOP])?,
             Self::NextSong => s.write_all(&[BYTE_NEXT_SONG])?,
+            Self::NextSongIfCurrent(path, id) => {
+                s.write_all(&[BYTE_NEXT_SONG_IF_CURRENT])?;
+                path.to_bytes(s)?;
+                id.to_bytes(s)?;
+            }
+            Self::QueueFinished => s.write_all(&[BYTE_QUEUE_FINISHED])?,
+            Self::Seek(pos) => {
+                s.write_all(&[BYTE_SEEK])?;
+                pos.to_bytes(s)?;
+            }
+            Self::SeekBy(by) => {
+                s.write_all(&[BYTE_SEEK_BY])?;
+                by.to_bytes(s)?;
+            }
+            Self::SetVolume(volume) => {
+                s.write_all(&[BYTE_SET_VOLUME])?;
+                volume.to_bits().to_bytes(s)?;
+            }
+            Self::SetCrossfade(millis) => {
+                s.write_all(&[BYTE_SET_CROSSFADE])?;
+                millis.to_bytes(s)?;
+            }
+            Self::SetReplayGain(mode, preamp_db) => {
+                s.write_all(&[BYTE_SET_REPLAY_GAIN])?;
+                mode.to_bytes(s)?;
+                preamp_db.to_bits().to_bytes(s)?;
+            }
+            Self::SetSleepTimer(secs, after_song) => {
+                s.write_all(&[BYTE_SET_SLEEP_TIMER])?;
+                secs.to_bytes(s)?;
+                s.write_all(&[*after_song as u8])?;
+            }
+            Self::SetAutoplayOnEmpty(autoplay) => {
+                s.write_all(&[BYTE_SET_AUTOPLAY_ON_EMPTY, *autoplay as u8])?
+            }
             Self::SyncDatabase(a, b, c) => {
                 s.write_all(&[BYTE_SYNC_DATABASE])?;
                 a.to_bytes(s)?;
@@ -627,6 +1067,65 @@ impl ToFromBytes for Action {
                 s.write_all(&[SUBBYTE_ACTION_UNSHUFFLE])?;
                 path.to_bytes(s)?;
             }
+            Self::QueueClear(path) => {
+                s.write_all(&[BYTE_QUEUE_ACTION])?;
+                s.write_all(&[SUBBYTE_ACTION_CLEAR])?;
+                path.to_bytes(s)?;
+            }
+            Self::QueueShuffleInPlace(path) => {
+                s.write_all(&[BYTE_QUEUE_ACTION])?;
+                s.write_all(&[SUBBYTE_ACTION_SHUFFLE_IN_PLACE])?;
+                path.to_bytes(s)?;
+            }
+            Self::QueueDuplicate(path) => {
+                s.write_all(&[BYTE_QUEUE_ACTION])?;
+                s.write_all(&[SUBBYTE_ACTION_DUPLICATE])?;
+                path.to_bytes(s)?;
+            }
+            Self::QueueLoopBreak(path) => {
+                s.write_all(&[BYTE_QUEUE_ACTION])?;
+                s.write_all(&[SUBBYTE_ACTION_LOOP_BREAK])?;
+                path.to_bytes(s)?;
+            }
+            Self::QueueDedupSongs(path) => {
+                s.write_all(&[BYTE_QUEUE_ACTION])?;
+                s.write_all(&[SUBBYTE_ACTION_DEDUP_SONGS])?;
+                path.to_bytes(s)?;
+            }
+            Self::QueuePlayNext(elem) => {
+                s.write_all(&[BYTE_QUEUE_ACTION])?;
+                s.write_all(&[SUBBYTE_ACTION_PLAY_NEXT])?;
+                elem.to_bytes(s)?;
+            }
+            Self::QueueSetLabel(path, label) => {
+                s.write_all(&[BYTE_QUEUE_ACTION])?;
+                s.write_all(&[SUBBYTE_ACTION_SET_LABEL])?;
+                path.to_bytes(s)?;
+                label.to_bytes(s)?;
+            }
+            Self::PlaylistSave(path, name) => {
+                s.write_all(&[BYTE_PLAYLIST_ACTION])?;
+                s.write_all(&[SUBBYTE_PLAYLIST_SAVE])?;
+                path.to_bytes(s)?;
+                name.to_bytes(s)?;
+            }
+            Self::PlaylistLoad(name, append) => {
+                s.write_all(&[BYTE_PLAYLIST_ACTION])?;
+                s.write_all(&[SUBBYTE_PLAYLIST_LOAD])?;
+                name.to_bytes(s)?;
+                s.write_all(&[*append as u8])?;
+            }
+            Self::PlaylistDelete(name) => {
+                s.write_all(&[BYTE_PLAYLIST_ACTION])?;
+                s.write_all(&[SUBBYTE_PLAYLIST_DELETE])?;
+                name.to_bytes(s)?;
+            }
+            Self::PlaylistRename(name, new_name) => {
+                s.write_all(&[BYTE_PLAYLIST_ACTION])?;
+                s.write_all(&[SUBBYTE_PLAYLIST_RENAME])?;
+                name.to_bytes(s)?;
+                new_name.to_bytes(s)?;
+            }
             Self::AddSong(song, req) => {
                 s.write_all(&[BYTE_LIB_ADD])?;
                 s.write_all(&[SUBBYTE_SONG])?;
@@ -772,6 +1271,17 @@ impl ToFromBytes for Action {
                 s.write_all(&[BYTE_INIT_COMPLETE])?;
             }
             Self::Save => s.write_all(&[BYTE_SAVE])?,
+            Self::RescanLibrary => s.write_all(&[BYTE_RESCAN_LIBRARY])?,
+            Self::SetDurationBackfill(enable) => {
+                s.write_all(&[BYTE_SET_DURATION_BACKFILL, *enable as u8])?
+            }
+            Self::VerifyLibrary(enable) => s.write_all(&[BYTE_VERIFY_LIBRARY, *enable as u8])?,
+            Self::PlaybackPosition(id, pos) => {
+                s.write_all(&[BYTE_PLAYBACK_POSITION])?;
+                id.to_bytes(s)?;
+                pos.to_bytes(s)?;
+            }
+            Self::Ping => s.write_all(&[BYTE_PING])?,
             Self::ErrorInfo(t, d) => {
                 s.write_all(&[BYTE_ERRORINFO])?;
                 t.to_bytes(s)?;
@@ -797,7 +1307,19 @@ impl ToFromBytes for Action {
             BYTE_RESUME => Self::Resume,
             BYTE_PAUSE => Self::Pause,
             BYTE_STOP => Self::Stop,
+            BYTE_QUEUE_FINISHED => Self::QueueFinished,
+            BYTE_SEEK => Self::Seek(from_bytes!()),
+            BYTE_SEEK_BY => Self::SeekBy(from_bytes!()),
+            BYTE_SET_VOLUME => Self::SetVolume(f32::from_bits(from_bytes!())),
+            BYTE_SET_CROSSFADE => Self::SetCrossfade(from_bytes!()),
+            BYTE_SET_REPLAY_GAIN => {
+                Self::SetReplayGain(from_bytes!(), f32::from_bits(from_bytes!()))
+            }
+            BYTE_SET_AUTOPLAY_ON_EMPTY => Self::SetAutoplayOnEmpty(s.read_byte()? != 0),
+            BYTE_SET_SLEEP_TIMER => Self::SetSleepTimer(from_bytes!(), s.read_byte()? != 0),
             BYTE_NEXT_SONG => Self::NextSong,
+            BYTE_NEXT_SONG_IF_CURRENT => Self::NextSongIfCurrent(from_bytes!(), from_bytes!()),
+            BYTE_PLAYBACK_POSITION => Self::PlaybackPosition(from_bytes!(), from_bytes!()),
             BYTE_SYNC_DATABASE => Self::SyncDatabase(from_bytes!(), from_bytes!(), from_bytes!()),
             BYTE_QUEUE_UPDATE => Self::QueueUpdate(from_bytes!(), from_bytes!(), from_bytes!()),
             BYTE_QUEUE_ADD => Self::QueueAdd(from_bytes!(), from_bytes!(), from_bytes!()),
@@ -812,10 +1334,28 @@ impl ToFromBytes for Action {
                 SUBBYTE_ACTION_SHUFFLE => Self::QueueShuffle(from_bytes!()),
                 SUBBYTE_ACTION_SET_SHUFFLE => Self::QueueSetShuffle(from_bytes!(), from_bytes!()),
                 SUBBYTE_ACTION_UNSHUFFLE => Self::QueueUnshuffle(from_bytes!()),
+                SUBBYTE_ACTION_CLEAR => Self::QueueClear(from_bytes!()),
+                SUBBYTE_ACTION_SHUFFLE_IN_PLACE => Self::QueueShuffleInPlace(from_bytes!()),
+                SUBBYTE_ACTION_DUPLICATE => Self::QueueDuplicate(from_bytes!()),
+                SUBBYTE_ACTION_LOOP_BREAK => Self::QueueLoopBreak(from_bytes!()),
+                SUBBYTE_ACTION_DEDUP_SONGS => Self::QueueDedupSongs(from_bytes!()),
+                SUBBYTE_ACTION_PLAY_NEXT => Self::QueuePlayNext(from_bytes!()),
+                SUBBYTE_ACTION_SET_LABEL => Self::QueueSetLabel(from_bytes!(), from_bytes!()),
+                _ => {
+                    log_warn!(
+                        "unexpected byte when reading command:queueAction; stopping playback."
+                    );
+                    Self::Stop
+                }
+            },
+            BYTE_PLAYLIST_ACTION => match s.read_byte()? {
+                SUBBYTE_PLAYLIST_SAVE => Self::PlaylistSave(from_bytes!(), from_bytes!()),
+                SUBBYTE_PLAYLIST_LOAD => Self::PlaylistLoad(from_bytes!(), s.read_byte()? != 0),
+                SUBBYTE_PLAYLIST_DELETE => Self::PlaylistDelete(from_bytes!()),
+                SUBBYTE_PLAYLIST_RENAME => Self::PlaylistRename(from_bytes!(), from_bytes!()),
                 _ => {
-                    eprintln!(
-                        "[{}] unexpected byte when reading command:queueAction; stopping playback.",
-                        "WARN".yellow()
+                    log_warn!(
+                        "unexpected byte when reading command:playlistAction; stopping playback."
                     );
                     Self::Stop
                 }
@@ -826,10 +1366,7 @@ impl ToFromBytes for Action {
                 SUBBYTE_ARTIST => Self::AddArtist(from_bytes!(), from_bytes!()),
                 SUBBYTE_COVER => Self::AddCover(from_bytes!(), from_bytes!()),
                 _ => {
-                    eprintln!(
-                        "[{}] unexpected byte when reading command:libAdd; stopping playback.",
-                        "WARN".yellow()
-                    );
+                    log_warn!("unexpected byte when reading command:libAdd; stopping playback.");
                     Self::Stop
                 }
             },
@@ -838,10 +1375,7 @@ impl ToFromBytes for Action {
                 SUBBYTE_ALBUM => Self::ModifyAlbum(from_bytes!(), from_bytes!()),
                 SUBBYTE_ARTIST => Self::ModifyArtist(from_bytes!(), from_bytes!()),
                 _ => {
-                    eprintln!(
-                        "[{}] unexpected byte when reading command:libModify; stopping playback.",
-                        "WARN".yellow()
-                    );
+                    log_warn!("unexpected byte when reading command:libModify; stopping playback.");
                     Self::Stop
                 }
             },
@@ -850,10 +1384,7 @@ impl ToFromBytes for Action {
                 SUBBYTE_ALBUM => Self::RemoveAlbum(from_bytes!()),
                 SUBBYTE_ARTIST => Self::RemoveArtist(from_bytes!()),
                 _ => {
-                    eprintln!(
-                        "[{}] unexpected byte when reading command:libRemove; stopping playback.",
-                        "WARN".yellow()
-                    );
+                    log_warn!("unexpected byte when reading command:libRemove; stopping playback.");
                     Self::Stop
                 }
             },
@@ -887,24 +1418,23 @@ impl ToFromBytes for Action {
                     Self::TagArtistPropertyUnset(from_bytes!(), from_bytes!())
                 }
                 _ => {
-                    eprintln!(
-                        "[{}] unexpected byte when reading command:libTag; stopping playback.",
-                        "WARN".yellow()
-                    );
+                    log_warn!("unexpected byte when reading command:libTag; stopping playback.");
                     Self::Stop
                 }
             },
             BYTE_SET_SONG_DURATION => Self::SetSongDuration(from_bytes!(), from_bytes!()),
             BYTE_MULTIPLE => Self::Multiple(from_bytes!()),
+            BYTE_COMPRESSED => action_from_compressed_bytes(s)?,
             BYTE_INIT_COMPLETE => Self::InitComplete,
             BYTE_SAVE => Self::Save,
+            BYTE_RESCAN_LIBRARY => Self::RescanLibrary,
+            BYTE_SET_DURATION_BACKFILL => Self::SetDurationBackfill(s.read_byte()? != 0),
+            BYTE_VERIFY_LIBRARY => Self::VerifyLibrary(s.read_byte()? != 0),
+            BYTE_PING => Self::Ping,
             BYTE_ERRORINFO => Self::ErrorInfo(from_bytes!(), from_bytes!()),
             BYTE_DENIED => Self::Denied(from_bytes!()),
             _ => {
-                eprintln!(
-                    "[{}] unexpected byte when reading command; stopping playback.",
-                    "WARN".yellow()
-                );
+                log_warn!("unexpected byte when reading command; stopping playback.");
                 Self::Stop
             }
         })
@@ -931,9 +1461,37 @@ fn test_to_from_bytes() {
         Action::Pause,
         Action::Stop,
         Action::NextSong,
+        Action::NextSongIfCurrent(vec![2, 0], 12),
+        Action::QueueFinished,
+        Action::SetAutoplayOnEmpty(true),
+        Action::SetAutoplayOnEmpty(false),
+        Action::Seek(150_000),
+        Action::SeekBy(-10_000),
+        Action::SetVolume(0.5),
+        Action::SetCrossfade(3000),
+        Action::SetReplayGain(ReplayGainMode::Album, -3.5),
+        Action::SetSleepTimer(Some(1800), true),
+        Action::SetSleepTimer(None, false),
         Action::SyncDatabase(vec![], vec![], vec![]),
         Action::QueueUpdate(vec![], QueueContent::Song(12).into(), Req::none()),
         Action::QueueAdd(vec![], vec![], Req::none()),
+        Action::QueueAdd(
+            vec![],
+            vec![
+                QueueContent::AlbumRef(3, 1).into(),
+                QueueContent::Random(
+                    crate::data::queue::RandomFilter::TagWithValueInt(
+                        "Year=".to_owned(),
+                        1990,
+                        2000,
+                    ),
+                    crate::data::queue::RandomWeighting::PreferFavorites,
+                    Default::default(),
+                )
+                .into(),
+            ],
+            Req::none(),
+        ),
         Action::QueueInsert(vec![], 5, vec![], Req::none()),
         Action::QueueRemove(vec![]),
         Action::QueueMove(vec![], vec![]),
@@ -942,6 +1500,28 @@ fn test_to_from_bytes() {
         Action::QueueShuffle(vec![]),
         Action::QueueSetShuffle(vec![], vec![]),
         Action::QueueUnshuffle(vec![]),
+        Action::QueueClear(vec![2, 0]),
+        Action::QueueShuffleInPlace(vec![1]),
+        Action::QueueDuplicate(vec![0, 3]),
+        Action::QueueLoopBreak(vec![2]),
+        Action::QueueDedupSongs(vec![]),
+        Action::QueuePlayNext(QueueContent::Song(12).into()),
+        Action::QueueSetLabel(vec![], None),
+        Action::QueueSetLabel(vec![1, 2], Some("label".to_owned())),
+        Action::PlaylistSave(vec![0, 1], "road trip".to_owned()),
+        Action::PlaylistLoad("road trip".to_owned(), true),
+        Action::PlaylistLoad("road trip".to_owned(), false),
+        Action::PlaylistDelete("road trip".to_owned()),
+        Action::PlaylistRename("road trip".to_owned(), "holiday".to_owned()),
+        Action::QueueUpdate(
+            vec![0],
+            {
+                let mut q: Queue = QueueContent::Song(5).into();
+                q.set_label(Some("song".to_owned()));
+                q
+            },
+            Req::none(),
+        ),
         // Action::AddSong(Song, Req),
         // Action::AddAlbum(Album, Req),
         // Action::AddArtist(Artist, Req),
@@ -967,6 +1547,11 @@ fn test_to_from_bytes() {
         // Action::TagArtistPropertyUnset(ArtistId, String),
         Action::InitComplete,
         Action::Save,
+        Action::Ping,
+        Action::RescanLibrary,
+        Action::SetDurationBackfill(false),
+        Action::VerifyLibrary(true),
+        Action::PlaybackPosition(3, 61_000),
         Action::ErrorInfo(format!("some error"), format!("with a message")),
         Action::Denied(Req::none()),
     ] {
@@ -979,3 +1564,43 @@ fn test_to_from_bytes() {
         );
     }
 }
+
+#[test]
+fn test_compressed_sync_database() {
+    use crate::{data::database::Database, test_util::test_song};
+    let mut db = Database::new_clientside();
+    for i in 0..1000 {
+        let mut song = test_song(&format!("song {i}"), None, 0, 0);
+        song.id = i;
+        song.location = format!("artist/album/{i}.mp3").into();
+        db.update_or_add_song(song);
+    }
+    let (mut plain, mut compressed) = (vec![], vec![]);
+    db.init_connection_opt(&mut plain, false).unwrap();
+    db.init_connection_opt(&mut compressed, true).unwrap();
+    assert!(compressed.len() < plain.len() / 2);
+    let (mut plain, mut compressed) = (&plain[..], &compressed[..]);
+    loop {
+        let a = Command::from_bytes(&mut plain).unwrap();
+        let b = Command::from_bytes(&mut compressed).unwrap();
+        assert_eq!(a.seq, b.seq);
+        assert_eq!(a.action, b.action);
+        if a.action == Action::InitComplete {
+            break;
+        }
+    }
+    assert!(plain.is_empty() && compressed.is_empty());
+}
+
+#[test]
+fn test_changes_queue() {
+    assert!(Action::NextSong.changes_queue());
+    assert!(Action::QueueGoto(vec![0]).changes_queue());
+    assert!(!Action::Pause.changes_queue());
+    assert!(Action::PlaylistLoad("mix".to_owned(), true).changes_queue());
+    assert!(!Action::PlaylistSave(vec![], "mix".to_owned()).changes_queue());
+    assert!(!Action::SetSongDuration(0, 1000).changes_queue());
+    assert!(!Action::TagSongFlagSet(0, "Fav".to_owned()).changes_queue());
+    assert!(!Action::Multiple(vec![Action::Pause, Action::Resume]).changes_queue());
+    assert!(Action::Multiple(vec![Action::Pause, Action::QueueRemove(vec![0])]).changes_queue());
+}
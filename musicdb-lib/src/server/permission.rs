@@ -0,0 +1,212 @@
+use std::{fmt::Display, str::FromStr};
+
+use super::Action;
+
+/// What a connection is allowed to do. Levels are ordered, each one includes the ones before it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    /// can see the database and the queue, and download songs and covers, but can't change anything
+    ReadOnly,
+    /// can also control playback and edit the queue
+    QueueAndPlayback,
+    /// can also add, modify and remove songs, albums and artists
+    #[default]
+    Full,
+}
+
+impl Permission {
+    /// the permission a connection needs to send this action
+    pub fn required_for(action: &Action) -> Self {
+        match action {
+            Action::Resume
+            | Action::Pause
+            | Action::Stop
+            | Action::NextSong
+            | Action::NextSongIfCurrent(..)
+            | Action::Seek(_)
+            | Action::SeekBy(_)
+            | Action::SetVolume(_)
+            | Action::SetCrossfade(_)
+            | Action::SetReplayGain(..)
+            | Action::SetSleepTimer(..)
+            | Action::PlaybackPosition(..)
+            | Action::QueueFinished
+            | Action::QueueUpdate(..)
+            | Action::QueueAdd(..)
+            | Action::QueueInsert(..)
+            | Action::QueueRemove(_)
+            | Action::QueueMove(..)
+            | Action::QueueMoveInto(..)
+            | Action::QueueGoto(_)
+            | Action::QueueShuffle(_)
+            | Action::QueueSetShuffle(..)
+            | Action::QueueUnshuffle(_)
+            | Action::QueueClear(_)
+            | Action::QueueShuffleInPlace(_)
+            | Action::QueueDuplicate(_)
+            | Action::QueueLoopBreak(_)
+            | Action::QueueDedupSongs(_)
+            | Action::QueuePlayNext(_)
+            | Action::QueueSetLabel(..)
+            | Action::PlaylistSave(..)
+            | Action::PlaylistLoad(..)
+            | Action::PlaylistDelete(_)
+            | Action::PlaylistRename(..)
+            | Action::SetAutoplayOnEmpty(_)
+            | Action::ErrorInfo(..) => Self::QueueAndPlayback,
+            Action::SyncDatabase(..)
+            | Action::AddSong(..)
+            | Action::AddAlbum(..)
+            | Action::AddArtist(..)
+            | Action::AddCover(..)
+            | Action::ModifySong(..)
+            | Action::ModifyAlbum(..)
+            | Action::ModifyArtist(..)
+            | Action::RemoveSong(_)
+            | Action::RemoveAlbum(_)
+            | Action::RemoveArtist(_)
+            | Action::SetSongDuration(..)
+            | Action::TagSongFlagSet(..)
+            | Action::TagSongFlagUnset(..)
+            | Action::TagAlbumFlagSet(..)
+            | Action::TagAlbumFlagUnset(..)
+            | Action::TagArtistFlagSet(..)
+            | Action::TagArtistFlagUnset(..)
+            | Action::TagSongPropertySet(..)
+            | Action::TagSongPropertyUnset(..)
+            | Action::TagAlbumPropertySet(..)
+            | Action::TagAlbumPropertyUnset(..)
+            | Action::TagArtistPropertySet(..)
+            | Action::TagArtistPropertyUnset(..)
+            | Action::InitComplete
+            | Action::Save
+            | Action::RescanLibrary
+            | Action::SetDurationBackfill(_)
+            | Action::VerifyLibrary(_)
+            | Action::Denied(_) => Self::Full,
+            Action::Ping => Self::ReadOnly,
+            Action::Multiple(actions) => actions
+                .iter()
+                .map(Self::required_for)
+                .max()
+                .unwrap_or(Self::ReadOnly),
+        }
+    }
+    pub fn allows(&self, action: &Action) -> bool {
+        *self >= Self::required_for(action)
+    }
+    /// `None` if the action is allowed, otherwise the `ErrorInfo` which should be sent back to the connection.
+    pub fn error_if_denied(&self, action: &Action) -> Option<Action> {
+        if self.allows(action) {
+            None
+        } else {
+            Some(Action::ErrorInfo(
+                "Permission denied".to_owned(),
+                match self {
+                    Self::ReadOnly => "This connection is read-only.",
+                    Self::QueueAndPlayback | Self::Full => {
+                        "This connection can only control playback and the queue."
+                    }
+                }
+                .to_owned(),
+            ))
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "queue-and-playback" => Ok(Self::QueueAndPlayback),
+            "read-only" => Ok(Self::ReadOnly),
+            _ => Err(format!(
+                "expected full, queue-and-playback or read-only, got {s:?}"
+            )),
+        }
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full => write!(f, "full"),
+            Self::QueueAndPlayback => write!(f, "queue-and-playback"),
+            Self::ReadOnly => write!(f, "read-only"),
+        }
+    }
+}
+
+#[test]
+fn test_permission_playback() {
+    for action in [
+        Action::Resume,
+        Action::Pause,
+        Action::NextSong,
+        Action::Seek(1000),
+        Action::SetVolume(0.5),
+    ] {
+        assert!(Permission::Full.allows(&action));
+        assert!(Permission::QueueAndPlayback.allows(&action));
+        assert!(!Permission::ReadOnly.allows(&action));
+    }
+}
+
+#[test]
+fn test_permission_queue() {
+    for action in [
+        Action::QueueClear(vec![]),
+        Action::QueueRemove(vec![0]),
+        Action::QueueGoto(vec![1]),
+        Action::SetAutoplayOnEmpty(true),
+        Action::PlaylistSave(vec![], "mix".to_owned()),
+        Action::PlaylistDelete("mix".to_owned()),
+    ] {
+        assert!(Permission::Full.allows(&action));
+        assert!(Permission::QueueAndPlayback.allows(&action));
+        assert!(!Permission::ReadOnly.allows(&action));
+    }
+}
+
+#[test]
+fn test_permission_library() {
+    for action in [
+        Action::RemoveSong(0),
+        Action::TagSongFlagSet(0, "Fav".to_owned()),
+        Action::TagAlbumPropertyUnset(0, "Year=".to_owned()),
+        Action::Save,
+        Action::SyncDatabase(vec![], vec![], vec![]),
+    ] {
+        assert!(Permission::Full.allows(&action));
+        assert!(!Permission::QueueAndPlayback.allows(&action));
+        assert!(!Permission::ReadOnly.allows(&action));
+    }
+}
+
+#[test]
+fn test_permission_multiple() {
+    let playback = Action::Multiple(vec![Action::Pause, Action::QueueClear(vec![])]);
+    assert!(Permission::QueueAndPlayback.allows(&playback));
+    assert!(!Permission::ReadOnly.allows(&playback));
+    // one library action makes the whole thing require full permissions
+    let mixed = Action::Multiple(vec![Action::Pause, Action::RemoveSong(0)]);
+    assert!(Permission::Full.allows(&mixed));
+    assert!(!Permission::QueueAndPlayback.allows(&mixed));
+    assert!(Permission::ReadOnly.allows(&Action::Multiple(vec![])));
+    assert!(Permission::ReadOnly
+        .error_if_denied(&Action::Pause)
+        .is_some());
+}
+
+#[test]
+fn test_permission_from_str() {
+    for p in [
+        Permission::Full,
+        Permission::QueueAndPlayback,
+        Permission::ReadOnly,
+    ] {
+        assert_eq!(p.to_string().parse::<Permission>(), Ok(p));
+    }
+    assert!("admin".parse::<Permission>().is_err());
+}
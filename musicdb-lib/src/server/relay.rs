@@ -0,0 +1,205 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "playback")]
+use crate::player::{Player, PlayerBackend, SongCustomData};
+use crate::{
+    data::{database::Database, SongId},
+    server::Action,
+};
+
+/// Advances the queue on a server which doesn't play audio itself (`--no-playback`).
+/// The position in the current song is tracked using a timer, and when it reaches the song's duration,
+/// `NextSong` is applied. Songs with an unknown duration (0) don't end on their own.
+///
+/// While an `output` connection is open, the client on the other end plays the audio (see `OutputReporter`)
+/// and sends `NextSongIfCurrent` when a song ends, so the timer doesn't skip songs on its own.
+/// The client's `PlaybackPosition`s keep the timer in sync, so it can take over if the client disconnects.
+pub struct Relay {
+    /// the current song and its path in the queue
+    song: Option<(Vec<usize>, SongId)>,
+    /// the position in the current song at `since`, in milliseconds
+    pos: u64,
+    since: Instant,
+    playing: bool,
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Relay {
+    pub fn new() -> Self {
+        Self {
+            song: None,
+            pos: 0,
+            since: Instant::now(),
+            playing: false,
+        }
+    }
+    /// the position in the current song, in milliseconds
+    pub fn position(&self) -> u64 {
+        if self.playing {
+            self.pos + self.since.elapsed().as_millis() as u64
+        } else {
+            self.pos
+        }
+    }
+    /// the current song and the position in it, see `Database::playback_position`
+    pub fn song_position(&self) -> Option<(SongId, u64)> {
+        self.song.as_ref().map(|(_, id)| (*id, self.position()))
+    }
+    fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+        self.since = Instant::now();
+    }
+    /// call this for every action, before it is applied to the database
+    pub fn handle_action(&mut self, action: &Action) {
+        match action {
+            Action::Seek(pos) => self.set_position(*pos),
+            Action::SeekBy(by) => self.set_position(self.position().saturating_add_signed(*by)),
+            Action::PlaybackPosition(id, pos) => {
+                if self.song.as_ref().is_some_and(|(_, song)| song == id) {
+                    self.set_position(*pos);
+                }
+            }
+            // the song may be the same, for example in a loop with only one song
+            Action::NextSong => self.song = None,
+            Action::Multiple(actions) => {
+                for action in actions {
+                    self.handle_action(action);
+                }
+            }
+            _ => {}
+        }
+    }
+    /// Applies `NextSong` if the current song ended.
+    /// Returns how long the current song will keep playing, if it is playing and its duration is known,
+    /// so the caller knows when to call this again.
+    pub fn update(&mut self, db: &mut Database) -> Option<Duration> {
+        let song = db
+            .queue
+            .get_current_path()
+            .zip(db.queue.get_current_song(db).copied());
+        if song != self.song {
+            self.song = song;
+            self.set_position(0);
+        }
+        if db.playing != self.playing {
+            let pos = self.position();
+            self.playing = db.playing;
+            self.set_position(pos);
+        }
+        if !self.playing {
+            return None;
+        }
+        let (_, id) = self.song.as_ref()?;
+        let duration = db
+            .get_song(id)
+            .map(|song| song.duration_millis)
+            .filter(|duration| *duration > 0)?;
+        let remaining = duration.saturating_sub(self.position());
+        if remaining > 0 {
+            Some(Duration::from_millis(remaining))
+        } else if db.open_connections.output == 0 {
+            db.apply_action_unchecked_seq(Action::NextSong, None);
+            self.song = None;
+            Some(Duration::ZERO)
+        } else {
+            // the output decides when the song ends
+            None
+        }
+    }
+}
+
+/// how often an output reports its position while playing
+#[cfg(feature = "playback")]
+const POSITION_REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Used by a client which plays the audio for a `--no-playback` server (a syncplayer on an `output` connection).
+/// Its player doesn't send commands itself, so this creates the actions which have to be sent to the server.
+#[cfg(feature = "playback")]
+pub struct OutputReporter {
+    last_report: Instant,
+    /// `true` once `NextSongIfCurrent` was sent for the song which ended, until the player moves on
+    finished: bool,
+}
+
+#[cfg(feature = "playback")]
+impl Default for OutputReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "playback")]
+impl OutputReporter {
+    pub fn new() -> Self {
+        Self {
+            last_report: Instant::now(),
+            finished: false,
+        }
+    }
+    /// call this regularly, then send the returned actions to the server
+    pub fn update<T: PlayerBackend<SongCustomData>>(
+        &mut self,
+        player: &Player<T>,
+        db: &Database,
+    ) -> Vec<Action> {
+        let mut actions = vec![];
+        let current = db.queue.get_current_song(db).copied();
+        if player.backend.song_finished() {
+            if current.is_some() && !self.finished {
+                self.finished = true;
+                actions.push(db.next_song_action());
+            }
+            return actions;
+        }
+        self.finished = false;
+        if db.playing && self.last_report.elapsed() >= POSITION_REPORT_INTERVAL {
+            self.last_report = Instant::now();
+            if let (Some(id), Some(pos)) =
+                (current, player.backend.current_song_playback_position())
+            {
+                actions.push(Action::PlaybackPosition(id, pos));
+            }
+        }
+        actions
+    }
+}
+
+#[test]
+fn test_relay() {
+    use crate::data::queue::QueueContent;
+    use crate::{server::Req, test_util::test_song};
+    let mut db = Database::new_empty_in_dir(Default::default(), Default::default());
+    db.command_sender = Some(std::sync::mpsc::channel().0);
+    let songs = (0..2)
+        .map(|_| db.add_song_new(test_song("", None, 0, 1000)))
+        .collect::<Vec<_>>();
+    let queue = songs
+        .iter()
+        .map(|id| QueueContent::Song(*id).into())
+        .collect();
+    db.apply_action_unchecked_seq(Action::QueueAdd(vec![], queue, Req::none()), None);
+    let mut relay = Relay::new();
+    // paused
+    assert_eq!(relay.update(&mut db), None);
+    db.apply_action_unchecked_seq(Action::Resume, None);
+    assert!(relay
+        .update(&mut db)
+        .is_some_and(|r| r > Duration::from_millis(900)));
+    // seeking to the end skips to the next song
+    relay.handle_action(&Action::Seek(1000));
+    assert_eq!(relay.update(&mut db), Some(Duration::ZERO));
+    assert_eq!(db.queue.get_current_song(&db), Some(&songs[1]));
+    assert!(relay
+        .update(&mut db)
+        .is_some_and(|r| r > Duration::from_millis(900)));
+    // an output reports the position, but decides when the song ends
+    db.open_connections.output = 1;
+    relay.handle_action(&Action::PlaybackPosition(songs[1], 1000));
+    assert_eq!(relay.update(&mut db), None);
+    assert_eq!(db.queue.get_current_song(&db), Some(&songs[1]));
+}
@@ -0,0 +1,148 @@
+use std::{
+    io::{BufReader, Read, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// how many bytes are written at once, so that transfers share the global limit fairly
+const CHUNK_SIZE: usize = 16 * 1024;
+/// how much unused bandwidth may be saved up, in seconds
+const BURST_SECS: f64 = 0.25;
+
+/// Bandwidth limits for get connections (song files, covers, custom files), see `Database::transfer_limit`.
+/// Main and control connections are never throttled.
+#[derive(Clone, Debug)]
+pub struct TransferLimit {
+    /// bytes per second for each connection
+    pub per_connection: Option<u64>,
+    /// bytes per second, shared by all throttled connections
+    global: Option<Arc<Mutex<TokenBucket>>>,
+    /// if `false`, connections from loopback addresses and unix sockets aren't throttled
+    pub limit_local: bool,
+}
+
+impl TransferLimit {
+    /// `None` if neither limit is set. Limits are in bytes per second.
+    pub fn new(
+        per_connection: Option<u64>,
+        global: Option<u64>,
+        limit_local: bool,
+    ) -> Option<Self> {
+        if per_connection.is_none() && global.is_none() {
+            return None;
+        }
+        Some(Self {
+            per_connection,
+            global: global.map(|rate| Arc::new(Mutex::new(TokenBucket::new(rate)))),
+            limit_local,
+        })
+    }
+    /// wraps the connection so that writes to it are throttled.
+    /// takes a `BufReader` so that data which was already read into its buffer isn't lost.
+    pub fn throttle<C>(&self, connection: BufReader<C>) -> Throttled<C> {
+        Throttled {
+            inner: connection,
+            own: self.per_connection.map(TokenBucket::new),
+            global: self.global.clone(),
+        }
+    }
+}
+
+/// A token bucket: bytes can be sent while tokens are available, and tokens are refilled at `rate` bytes per second.
+/// Taking more tokens than available puts the bucket into debt, which the caller has to wait for,
+/// so that waiting doesn't require holding the lock of a shared bucket.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            tokens: 0.0,
+            last_refill: Instant::now(),
+        }
+    }
+    /// takes `bytes` tokens and returns how long the caller has to wait before sending them
+    pub fn take(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let rate = self.rate as f64;
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * rate)
+            .min(rate * BURST_SECS);
+        self.last_refill = now;
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// A connection whose writes are limited by its own and by a shared token bucket.
+/// Reads aren't throttled.
+pub struct Throttled<C> {
+    inner: BufReader<C>,
+    own: Option<TokenBucket>,
+    global: Option<Arc<Mutex<TokenBucket>>>,
+}
+
+impl<C> Throttled<C> {
+    pub fn get_ref(&self) -> &C {
+        self.inner.get_ref()
+    }
+}
+
+impl<C: Read> Read for Throttled<C> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<C: Write> Write for Throttled<C> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let buf = &buf[..buf.len().min(CHUNK_SIZE)];
+        let own = self
+            .own
+            .as_mut()
+            .map_or(Duration::ZERO, |b| b.take(buf.len()));
+        let global = self
+            .global
+            .as_ref()
+            .map_or(Duration::ZERO, |b| b.lock().unwrap().take(buf.len()));
+        let wait = own.max(global);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        self.inner.get_mut().write_all(buf)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.get_mut().flush()
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    let mut bucket = TokenBucket::new(1000);
+    // starts empty, so 500 bytes take half a second
+    let wait = bucket.take(500);
+    assert!(wait > Duration::from_millis(450) && wait <= Duration::from_millis(500));
+    // the debt adds up
+    let wait = bucket.take(500);
+    assert!(wait > Duration::from_millis(950) && wait <= Duration::from_millis(1000));
+}
+
+#[test]
+fn test_throttled_write() {
+    let limit = TransferLimit::new(Some(100_000), Some(1_000_000), true).unwrap();
+    let mut con = limit.throttle(BufReader::new(std::io::Cursor::new(Vec::new())));
+    let start = Instant::now();
+    con.write_all(&[0; 20_000]).unwrap();
+    // 20kB at 100kB/s, limited by the per-connection limit
+    assert!(start.elapsed() >= Duration::from_millis(190));
+    assert_eq!(con.get_ref().get_ref().len(), 20_000);
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, GenericImageView};
+
+use crate::data::CoverId;
+
+/// the smallest and largest size which can be requested, in pixels
+const MIN_SIZE: u32 = 16;
+const MAX_SIZE: u32 = 4096;
+const JPEG_QUALITY: u8 = 85;
+/// if the cached thumbnails are bigger than this (in bytes), the ones which weren't used for the longest time are removed
+const CACHE_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+/// the thumbnails for each cover and size, and when they were last used
+type Thumbnails = HashMap<(CoverId, u32), (Instant, Arc<Vec<u8>>)>;
+
+/// Scaled down covers for `cover-thumb` get requests, see `Database::cover_thumbnails`.
+#[derive(Clone, Debug, Default)]
+pub struct ThumbnailCache(Arc<Mutex<Thumbnails>>);
+
+impl ThumbnailCache {
+    /// returns the cached thumbnail, or uses `create` to create it (without locking the cache)
+    pub fn get_or_create(
+        &self,
+        id: CoverId,
+        max_px: u32,
+        create: impl FnOnce() -> Option<Vec<u8>>,
+    ) -> Option<Arc<Vec<u8>>> {
+        if let Some((accessed, bytes)) = self.0.lock().unwrap().get_mut(&(id, max_px)) {
+            *accessed = Instant::now();
+            return Some(Arc::clone(bytes));
+        }
+        let bytes = Arc::new(create()?);
+        let mut cache = self.0.lock().unwrap();
+        cache.insert((id, max_px), (Instant::now(), Arc::clone(&bytes)));
+        let mut size = cache.values().map(|(_, bytes)| bytes.len()).sum::<usize>();
+        while size > CACHE_MAX_BYTES {
+            let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, (accessed, _))| *accessed)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            size -= cache.remove(&oldest).map_or(0, |(_, bytes)| bytes.len());
+        }
+        Some(bytes)
+    }
+}
+
+/// limits a requested size to a reasonable range
+pub fn clamp_size(max_px: u32) -> u32 {
+    max_px.clamp(MIN_SIZE, MAX_SIZE)
+}
+
+/// Scales the image down so that neither its width nor its height are bigger than `max_px`, keeping its aspect ratio,
+/// and encodes it as a JPEG. If the image is small enough or can't be decoded, the original bytes are returned.
+pub fn create(bytes: &[u8], max_px: u32) -> Vec<u8> {
+    let image = match image::load_from_memory(bytes) {
+        Ok(image) => image,
+        Err(e) => {
+            log_debug!("can't decode cover, sending it at full size: {e}");
+            return bytes.to_vec();
+        }
+    };
+    let (width, height) = image.dimensions();
+    if width <= max_px && height <= max_px {
+        return bytes.to_vec();
+    }
+    let thumbnail = image.resize(max_px, max_px, FilterType::Triangle).to_rgb8();
+    let mut out = vec![];
+    match JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY).encode_image(&thumbnail) {
+        Ok(()) => out,
+        Err(e) => {
+            log_warn!("can't encode cover thumbnail, sending it at full size: {e}");
+            bytes.to_vec()
+        }
+    }
+}
+
+/// Decodes the image, scales it so that its bigger side is `max_px` long and blurs it (see `DynamicImage::blur`).
+/// Returns the width, height and RGBA pixels, for clients which show covers as blurry backgrounds.
+pub fn blurred(bytes: &[u8], max_px: u32, sigma: f32) -> Option<(u32, u32, Vec<u8>)> {
+    let image = match image::load_from_memory(bytes) {
+        Ok(image) => image,
+        Err(e) => {
+            log_debug!("can't decode cover to blur it: {e}");
+            return None;
+        }
+    };
+    let blurred = image
+        .resize(max_px, max_px, FilterType::Triangle)
+        .blur(sigma)
+        .to_rgba8();
+    Some((blurred.width(), blurred.height(), blurred.into_raw()))
+}
+
+#[test]
+fn test_thumbnail() {
+    let mut png = vec![];
+    image::DynamicImage::new_rgb8(300, 150)
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .unwrap();
+    let thumbnail = image::load_from_memory(&create(&png, 100)).unwrap();
+    assert_eq!(thumbnail.dimensions(), (100, 50));
+    // small enough
+    assert_eq!(create(&png, 300), png);
+    // not an image
+    assert_eq!(create(b"abc", 100), b"abc");
+    let cache = ThumbnailCache::default();
+    assert!(cache.get_or_create(0, 100, || None).is_none());
+    assert_eq!(*cache.get_or_create(0, 100, || Some(vec![1])).unwrap(), [1]);
+    assert_eq!(*cache.get_or_create(0, 100, || Some(vec![2])).unwrap(), [1]);
+}
+
+#[test]
+fn test_blurred() {
+    let mut png = vec![];
+    image::DynamicImage::new_rgb8(300, 150)
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .unwrap();
+    let (width, height, pixels) = blurred(&png, 64, 2.0).unwrap();
+    assert_eq!((width, height), (64, 32));
+    assert_eq!(pixels.len(), 64 * 32 * 4);
+    assert!(blurred(b"abc", 64, 2.0).is_none());
+}
@@ -0,0 +1,35 @@
+#![cfg(test)]
+//! Fixtures shared by the tests of this crate.
+
+use std::{fs, path::PathBuf};
+
+use crate::data::{song::Song, AlbumId, ArtistId};
+
+/// a song called `title`, with no cover, tags or other artists
+pub fn test_song(
+    title: &str,
+    album: Option<AlbumId>,
+    artist: ArtistId,
+    duration_millis: u64,
+) -> Song {
+    Song::new(
+        "song".into(),
+        None,
+        title.to_owned(),
+        album,
+        artist,
+        vec![],
+        None,
+        0,
+        duration_millis,
+        Default::default(),
+    )
+}
+
+/// an empty directory for the test called `name`, the test removes it when it's done
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("musicdb-test-{name}-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
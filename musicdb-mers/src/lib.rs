@@ -71,6 +71,10 @@ pub fn add(
                     handle(&handler_next_song, move || (Data::empty_tuple(), ()));
                     handle(&handler_queue_changed, move || (Data::empty_tuple(), ()));
                 }
+                Command::QueueFinished => {
+                    handle(&handler_pause, move || (Data::empty_tuple(), ()));
+                    handle(&handler_queue_changed, move || (Data::empty_tuple(), ()));
+                }
                 Command::SyncDatabase(..) => {
                     handle(&handler_library_changed, move || (Data::empty_tuple(), ()));
                 }
@@ -83,7 +87,14 @@ pub fn add(
                 | Command::QueueGoto(..)
                 | Command::QueueShuffle(..)
                 | Command::QueueSetShuffle(..)
-                | Command::QueueUnshuffle(..) => {
+                | Command::QueueUnshuffle(..)
+                | Command::QueueClear(..)
+                | Command::QueueShuffleInPlace(..)
+                | Command::QueueDuplicate(..)
+                | Command::QueueLoopBreak(..)
+                | Command::QueueDedupSongs(..)
+                | Command::QueuePlayNext(..)
+                | Command::QueueSetLabel(..) => {
                     handle(&handler_queue_changed, move || (Data::empty_tuple(), ()));
                 }
                 Command::AddSong(_)
@@ -116,7 +127,20 @@ pub fn add(
                     handle(&handler_library_changed, move || (Data::empty_tuple(), ()));
                 }
                 Command::InitComplete => (),
-                Command::Save => (),
+                Command::SetAutoplayOnEmpty(..) => (),
+                Command::Seek(..)
+                | Command::SeekBy(..)
+                | Command::SetVolume(..)
+                | Command::SetCrossfade(..)
+                | Command::SetReplayGain(..)
+                | Command::SetSleepTimer(..) => (),
+                Command::Save
+                | Command::Ping
+                | Command::RescanLibrary
+                | Command::SetDurationBackfill(..)
+                | Command::VerifyLibrary(..)
+                | Command::PlaybackPosition(..)
+                | Command::NextSongIfCurrent(..) => (),
                 Command::ErrorInfo(title, body) => {
                     handle(&handler_notification_received, move || {
                         (
@@ -316,7 +340,8 @@ pub fn add(
             {
                 let db = Arc::clone(db);
                 move |_, _| {
-                    Ok(match db.lock().unwrap().queue.get_current_song() {
+                    let db = db.lock().unwrap();
+                    Ok(match db.queue.get_current_song(&db) {
                         Some(id) => Data::new(MusicDbId(*id)),
                         None => Data::empty_tuple(),
                     })
@@ -340,7 +365,8 @@ pub fn add(
             {
                 let db = Arc::clone(db);
                 move |_, _| {
-                    Ok(match db.lock().unwrap().queue.get_next_song() {
+                    let db = db.lock().unwrap();
+                    Ok(match db.queue.get_next_song(&db) {
                         Some(id) => Data::new(MusicDbId(*id)),
                         None => Data::empty_tuple(),
                     })
@@ -1066,6 +1092,26 @@ fn gen_queue_elem(queue_elem: &Queue, i: DisplayInfo) -> Data {
                     ),
                 ])),
             ),
+            QueueContent::Random(_, _, q) => (
+                i.object_fields.get_or_add_field("random"),
+                Data::new(data::object::Object::new(vec![(
+                    i.object_fields.get_or_add_field("length"),
+                    Data::new(data::int::Int(q.len() as _)),
+                )])),
+            ),
+            QueueContent::AlbumRef(id, index) => (
+                i.object_fields.get_or_add_field("album"),
+                Data::new(data::object::Object::new(vec![
+                    (
+                        i.object_fields.get_or_add_field("id"),
+                        Data::new(MusicDbId(*id)),
+                    ),
+                    (
+                        i.object_fields.get_or_add_field("index"),
+                        Data::new(data::int::Int(*index as _)),
+                    ),
+                ])),
+            ),
         },
     ]))
 }
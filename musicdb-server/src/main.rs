@@ -1,31 +1,89 @@
+#[cfg(feature = "mpris")]
+mod mpris;
+#[cfg(feature = "website")]
+mod stream;
+#[cfg(feature = "watch")]
+mod watch;
 #[cfg(feature = "website")]
 mod web;
+#[cfg(feature = "website")]
+mod web_auth;
 
 use std::{
-    io::{BufReader, Write},
+    io::Write,
     net::{SocketAddr, TcpStream},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::exit,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, TryLockError},
+    time::{Duration, Instant},
 };
 
 use clap::{Parser, Subcommand};
-use musicdb_lib::{load::ToFromBytes, server::run_server_caching_thread_opt};
+use musicdb_lib::{
+    load::ToFromBytes,
+    log_error, log_info, log_warn,
+    logging::{self, Level},
+    server::{
+        auth::Auth, get::Status, permission::Permission, run_server_caching_thread_opt,
+        throttle::TransferLimit,
+    },
+};
 
-use musicdb_lib::data::database::Database;
+use musicdb_lib::data::{database::Database, scan};
 
 #[derive(Parser, Debug)]
 struct Args {
     /// optional address for tcp connections to the server
     #[arg(long)]
     tcp: Option<SocketAddr>,
+    /// optional path of a unix domain socket to listen on, in addition to or instead of tcp. handled exactly like tcp connections.
+    #[arg(long, value_name = "path")]
+    unix: Option<PathBuf>,
+    /// if set, tcp and unix socket clients have to send this token before they can do anything.
+    #[arg(
+        long,
+        value_name = "token",
+        env = "MUSICDB_AUTH_TOKEN",
+        hide_env_values = true
+    )]
+    auth_token: Option<String>,
+    /// additional tokens which only grant the given permission (queue-and-playback or read-only), for example `read-only:<token>`. can be used multiple times.
+    #[arg(long, value_name = "permission>:<token", value_parser = parse_restricted_token)]
+    restricted_token: Vec<(Permission, String)>,
+    /// what clients connecting via tcp may do: full, queue-and-playback or read-only. tokens can't grant more than this.
+    #[arg(long, value_name = "permission", default_value_t = Permission::Full)]
+    tcp_permission: Permission,
+    /// like --tcp-permission, but for the unix socket
+    #[arg(long, value_name = "permission", default_value_t = Permission::Full)]
+    unix_permission: Permission,
+    /// like --tcp-permission, but for the website
+    #[arg(long, value_name = "permission", default_value_t = Permission::Full)]
+    web_permission: Permission,
     /// optional address on which to start a website which can be used on devices without `musicdb-client` to control playback.
     /// requires the `assets/` folder to be present!
     #[arg(long)]
     web: Option<SocketAddr>,
+    /// serve the server's playback as an mp3 stream at /stream.mp3 on the website, for players like vlc or a browser.
+    #[arg(long)]
+    web_stream: bool,
+    /// if set, browsers have to enter this code once (on /login) before they can change anything on the website.
+    #[arg(
+        long,
+        value_name = "code",
+        env = "MUSICDB_WEB_PASSWORD",
+        hide_env_values = true
+    )]
+    web_password: Option<String>,
+    /// with --web-password, also require the code to see the library, queue and current song.
+    #[arg(long, requires = "web_password")]
+    web_password_read: bool,
     /// play audio instead of acting like a server
     #[arg(long)]
     play_audio: bool,
+    /// don't play audio, but still advance the queue when a song ends (using the songs' durations).
+    /// a syncplayer client started with `--audio-output` can play the audio and decide when songs end instead.
+    #[arg(long, conflicts_with = "play_audio")]
+    no_playback: bool,
 
     /// allow clients to access files in this directory, or the lib_dir if not specified.
     ///
@@ -41,10 +99,69 @@ struct Args {
     /// Only does something if `--advanced-cache` is used. If available system memory drops below this amount (in MiB), remove songs from cache.
     #[arg(long, value_name = "min_avail_mem_in_mib", default_value_t = 1024)]
     advanced_cache_min_mem: u64,
+    /// Only does something if `--advanced-cache` is used. Never use more than this many MiB for cached songs, even if enough system memory is available. When over budget, the songs which haven't been needed for the longest time are removed from cache first.
+    #[arg(long, value_name = "mib")]
+    advanced_cache_max_size: Option<u64>,
     /// Only does something if `--advanced-cache` is used. CacheManager will cache the current, next, ..., songs in the queue, but at most this many songs.
     #[arg(long, value_name = "number_of_songs", default_value_t = 10)]
     advanced_cache_song_lookahead_limit: u32,
 
+    /// Random elements in the queue won't pick any of the last this many songs again, unless the library is too small.
+    #[arg(long, value_name = "number_of_songs", default_value_t = 50)]
+    random_no_repeat: usize,
+
+    /// Fade from one song to the next, with this many milliseconds of overlap (at most 10000). 0 disables crossfading.
+    #[arg(long, value_name = "millis", default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=10000))]
+    crossfade: u32,
+    /// Only crossfade when a song ends, not when skipping to the next song.
+    #[arg(long)]
+    crossfade_natural_only: bool,
+
+    /// Don't determine the duration of songs whose duration is 0 in the background.
+    /// Clients can still start it later.
+    #[arg(long)]
+    no_duration_backfill: bool,
+    /// Check that the files of all songs exist and are readable after starting, and tag missing ones (FileMissing).
+    /// Clients can also start this later.
+    #[arg(long)]
+    verify_library: bool,
+
+    /// Limit song file and cover transfers to each remote client to this many kbit/s.
+    #[arg(long, value_name = "kbit/s")]
+    max_transfer_kbps: Option<u64>,
+    /// Limit song file and cover transfers to all remote clients combined to this many kbit/s.
+    #[arg(long, value_name = "kbit/s")]
+    max_total_transfer_kbps: Option<u64>,
+    /// Also apply --max-transfer-kbps and --max-total-transfer-kbps to loopback and unix socket connections.
+    #[arg(long)]
+    limit_local_transfers: bool,
+
+    /// Save the database every this many minutes, if it was modified. By default, it is saved shortly after it was modified.
+    #[arg(long, value_name = "minutes", value_parser = clap::value_parser!(u64).range(1..))]
+    save_every: Option<u64>,
+
+    /// Print the server's status (the same as the `status` get-request) to stderr every this many seconds.
+    #[arg(long, value_name = "seconds")]
+    status_log_interval: Option<u64>,
+
+    /// Don't log messages below this level: debug, info, warn or error.
+    #[arg(long, value_name = "level", default_value_t = Level::Info)]
+    log_level: Level,
+    /// Write log messages to this file instead of stderr. Warnings and errors are still also written to stderr.
+    #[arg(long, value_name = "path")]
+    log_file: Option<PathBuf>,
+    /// Only does something if `--log-file` is used. Once the log file is bigger than this, it is renamed to `<path>.1` and a new one is started.
+    #[arg(long, value_name = "mib", default_value_t = 10)]
+    log_file_max_size: u64,
+    /// Only does something if `--log-file` is used. How many old log files (`<path>.1`, `<path>.2`, ...) to keep.
+    #[arg(long, value_name = "number_of_files", default_value_t = 5)]
+    log_file_keep: usize,
+
+    /// Watch the library directory and add, update, move or tag (FileMissing) songs when files change. Only works with source=local.
+    #[cfg(feature = "watch")]
+    #[arg(long)]
+    watch: bool,
+
     // db and song file source
     #[command(subcommand)]
     source: Source,
@@ -101,9 +218,65 @@ enum Source {
 //     advanced_cache_song_lookahead_limit: u32,
 // }
 
+/// how long to wait for the database to be unlocked before exiting without saving
+const SHUTDOWN_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// saves the database, notifies clients, and exits.
+fn shutdown(database: &Mutex<Database>, unix_socket: Option<&Path>) {
+    log_info!("Shutting down...");
+    // stop accepting connections on the socket
+    if let Some(path) = unix_socket {
+        _ = std::fs::remove_file(path);
+    }
+    // the database may be locked, for example while an update is sent to a slow client,
+    // so wait for a while, but don't hang forever.
+    let start = Instant::now();
+    let mut db = loop {
+        match database.try_lock() {
+            Ok(db) => break db,
+            Err(TryLockError::Poisoned(e)) => break e.into_inner(),
+            Err(TryLockError::WouldBlock) if start.elapsed() < SHUTDOWN_LOCK_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(TryLockError::WouldBlock) => {
+                log_error!(
+                    "Database still locked after {}s, exiting without saving!",
+                    SHUTDOWN_LOCK_TIMEOUT.as_secs()
+                );
+                exit(1);
+            }
+        }
+    };
+    // the lock is held until the process exits, so new connections and commands
+    // won't be handled anymore.
+    if let Err(e) = db.shutdown() {
+        log_error!("Couldn't save the database: {e}");
+        exit(1);
+    }
+    exit(0);
+}
+
+fn parse_restricted_token(s: &str) -> Result<(Permission, String), String> {
+    let (permission, token) = s
+        .split_once(':')
+        .ok_or_else(|| "expected <permission>:<token>".to_owned())?;
+    Ok((permission.parse()?, token.to_owned()))
+}
+
 fn main() {
     // parse args
     let args = Args::parse();
+    logging::set_level(args.log_level);
+    if let Some(path) = &args.log_file {
+        if let Err(e) = logging::set_log_file(
+            path,
+            args.log_file_max_size * 1024 * 1024,
+            args.log_file_keep,
+        ) {
+            log_error!("Couldn't open log file {path:?}: {e}");
+            exit(1);
+        }
+    }
     let mut remote_source_addr = None;
     let mut database = match args.source {
         Source::Local {
@@ -117,10 +290,9 @@ fn main() {
                 match Database::load_database_from_dir(db_dir.clone(), lib_dir.clone()) {
                     Ok(db) => db,
                     Err(e) => {
-                        eprintln!("Couldn't load database!");
-                        eprintln!("  dbfile: {:?}", db_dir);
-                        eprintln!("  libdir: {:?}", lib_dir);
-                        eprintln!("  err: {}", e);
+                        log_error!(
+                            "Couldn't load database (dbfile: {db_dir:?}, libdir: {lib_dir:?}): {e}"
+                        );
                         exit(1);
                     }
                 }
@@ -128,17 +300,30 @@ fn main() {
         }
         Source::Remote { addr } => {
             let mut db = Database::new_clientside();
-            db.remote_server_as_song_file_source = Some(Arc::new(Mutex::new(
-                musicdb_lib::server::get::Client::new(BufReader::new(Box::new(
-                    TcpStream::connect(&addr).unwrap(),
-                ) as _))
+            db.remote_server_as_song_file_source = Some(Arc::new(
+                musicdb_lib::server::get::Pool::connect(
+                    Box::new(move || Ok(Box::new(TcpStream::connect(addr)?) as _)),
+                    false,
+                )
                 .unwrap(),
-            )));
+            ));
             remote_source_addr = Some(addr);
             db
         }
     };
     database.custom_files = args.custom_files;
+    database.random_no_repeat = args.random_no_repeat;
+    database.crossfade_millis = args.crossfade;
+    database.crossfade_natural_only = args.crossfade_natural_only;
+    database.relay = args.no_playback;
+    database.save_every = args
+        .save_every
+        .map(|minutes| Duration::from_secs(minutes * 60));
+    database.transfer_limit = TransferLimit::new(
+        args.max_transfer_kbps.map(|kbps| kbps * 1000 / 8),
+        args.max_total_transfer_kbps.map(|kbps| kbps * 1000 / 8),
+        args.limit_local_transfers,
+    );
     // database can be shared by multiple threads using Arc<Mutex<_>>
     let database = Arc::new(Mutex::new(database));
     // thread to communicate with the remote server
@@ -153,10 +338,42 @@ fn main() {
                 fn sanitize_actions(action: Action) -> Option<Action> {
                     match action {
                         // ignore playback and queue commands, and denials
-                        Resume | Pause | Stop | NextSong | QueueUpdate(..) | QueueAdd(..)
-                        | QueueInsert(..) | QueueRemove(..) | QueueMove(..) | QueueMoveInto(..)
-                        | QueueGoto(..) | QueueShuffle(..) | QueueSetShuffle(..)
-                        | QueueUnshuffle(..) | Denied(..) => None,
+                        Resume
+                        | Pause
+                        | Stop
+                        | NextSong
+                        | NextSongIfCurrent(..)
+                        | Seek(..)
+                        | SeekBy(..)
+                        | SetVolume(..)
+                        | SetCrossfade(..)
+                        | SetReplayGain(..)
+                        | SetSleepTimer(..)
+                        | QueueFinished
+                        | QueueUpdate(..)
+                        | QueueAdd(..)
+                        | QueueInsert(..)
+                        | QueueRemove(..)
+                        | QueueMove(..)
+                        | QueueMoveInto(..)
+                        | QueueGoto(..)
+                        | QueueShuffle(..)
+                        | QueueSetShuffle(..)
+                        | QueueUnshuffle(..)
+                        | QueueClear(..)
+                        | QueueShuffleInPlace(..)
+                        | QueueDuplicate(..)
+                        | QueueLoopBreak(..)
+                        | QueueDedupSongs(..)
+                        | QueuePlayNext(..)
+                        | QueueSetLabel(..)
+                        | PlaylistSave(..)
+                        | PlaylistLoad(..)
+                        | PlaylistDelete(..)
+                        | PlaylistRename(..)
+                        | SetAutoplayOnEmpty(..)
+                        | Ping
+                        | Denied(..) => None,
                         SyncDatabase(..)
                         | AddSong(..)
                         | AddAlbum(..)
@@ -184,6 +401,10 @@ fn main() {
                         | InitComplete
                         | Save
                         | ErrorInfo(..) => Some(action),
+                        // the remote server scans its own library
+                        RescanLibrary | SetDurationBackfill(..) | VerifyLibrary(..) => None,
+                        // only relevant for this server's own audio output
+                        PlaybackPosition(..) => None,
                         Multiple(actions) => {
                             let actions = actions
                                 .into_iter()
@@ -206,20 +427,75 @@ fn main() {
             }
         });
     }
-    if args.tcp.is_some() || args.web.is_some() {
+    #[cfg(feature = "mpris")]
+    if args.tcp.is_some() || args.unix.is_some() || args.web.is_some() {
+        mpris::start(Arc::clone(&database));
+    }
+    #[cfg(feature = "watch")]
+    if args.watch {
+        if remote_source_addr.is_some() {
+            log_warn!("--watch only works with source=local, not watching.");
+        } else {
+            let lib_dir = database.lock().unwrap().lib_directory.clone();
+            watch::start(Arc::clone(&database), lib_dir);
+        }
+    }
+    if !args.no_duration_backfill && remote_source_addr.is_none() {
+        scan::set_duration_backfill(&database, true);
+    }
+    if args.verify_library {
+        scan::set_library_verification(&database, true, None);
+    }
+    if let Some(secs) = args.status_log_interval.filter(|secs| *secs > 0) {
+        let database = Arc::clone(&database);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(secs));
+            log_info!("Status:\n{}", Status::get(&database).to_string().trim_end());
+        });
+    }
+    if args.tcp.is_some() || args.unix.is_some() || args.web.is_some() {
         let mem_min = args.advanced_cache_min_mem;
         let cache_limit = args.advanced_cache_song_lookahead_limit;
-        let args_tcp = args.tcp;
+        let cache_max_size = args.advanced_cache_max_size;
+        let args_tcp = args.tcp.map(|addr| (addr, args.tcp_permission));
+        let args_unix = args.unix.clone().map(|path| (path, args.unix_permission));
+        let tokens = args
+            .auth_token
+            .map(|token| (token, Permission::Full))
+            .into_iter()
+            .chain(
+                args.restricted_token
+                    .into_iter()
+                    .map(|(permission, token)| (token, permission)),
+            )
+            .collect::<Vec<_>>();
+        let auth = (!tokens.is_empty()).then(|| Auth::new(tokens));
+        {
+            let database = Arc::clone(&database);
+            let unix_socket = args.unix.clone();
+            // SIGINT and SIGTERM on unix, Ctrl+C on windows
+            if let Err(e) = ctrlc::set_handler(move || shutdown(&database, unix_socket.as_deref()))
+            {
+                log_warn!(
+                    "Couldn't set signal handler, the database won't be saved on shutdown: {e}"
+                );
+            }
+        }
         let run_server = move |database, sender_sender| {
             run_server_caching_thread_opt(
                 database,
                 args_tcp,
+                args_unix,
+                auth,
                 sender_sender,
                 args.advanced_cache.map(|max| {
                     Box::new(
                         move |cm: &mut musicdb_lib::data::cache_manager::CacheManager| {
                             cm.set_memory_mib(mem_min, max.max(mem_min + 128));
                             cm.set_cache_songs_count(cache_limit);
+                            if let Some(max) = cache_max_size {
+                                cm.set_max_mib(max);
+                            }
                         },
                     ) as _
                 }),
@@ -245,12 +521,21 @@ fn main() {
                     .enable_all()
                     .build()
                     .unwrap()
-                    .block_on(web::main(db, sender, *addr));
+                    .block_on(web::main(
+                        db,
+                        sender,
+                        *addr,
+                        args.web_permission,
+                        args.web_stream,
+                        args.web_password.map(|password| {
+                            web_auth::WebAuth::new(password, args.web_password_read)
+                        }),
+                    ));
             }
         } else {
             run_server(database, None);
         }
     } else {
-        eprintln!("nothing to do, not starting the server.");
+        log_warn!("nothing to do, not starting the server.");
     }
 }
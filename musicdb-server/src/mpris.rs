@@ -0,0 +1,163 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use musicdb_lib::{
+    data::{
+        database::{Database, UpdateEndpoint},
+        SongId,
+    },
+    log_warn,
+    server::Action,
+};
+use souvlaki::{
+    MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition, PlatformConfig,
+    SeekDirection,
+};
+
+/// Exposes org.mpris.MediaPlayer2 on the session bus, so that media keys and desktop widgets can control playback.
+/// Runs on its own thread and is informed about changes through an update endpoint.
+pub fn start(database: Arc<Mutex<Database>>) {
+    std::thread::spawn(move || {
+        if let Err(e) = run(database) {
+            log_warn!("MPRIS: {e}");
+        }
+    });
+}
+
+fn run(database: Arc<Mutex<Database>>) -> Result<(), String> {
+    // the command sender is set once the server is running
+    let command_sender = loop {
+        if let Some(sender) = database.lock().unwrap().command_sender.clone() {
+            break sender;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    let mut controls = MediaControls::new(PlatformConfig {
+        display_name: "MusicDb",
+        dbus_name: "musicdb",
+        hwnd: None,
+    })
+    .map_err(|e| format!("Couldn't create media controls: {e:?}"))?;
+    let db = Arc::clone(&database);
+    controls
+        .attach(move |event| {
+            let action = match event {
+                MediaControlEvent::Play => Action::Resume,
+                MediaControlEvent::Pause => Action::Pause,
+                MediaControlEvent::Toggle => {
+                    if db.lock().unwrap().playing {
+                        Action::Pause
+                    } else {
+                        Action::Resume
+                    }
+                }
+                MediaControlEvent::Next => db.lock().unwrap().next_song_action(),
+                // there is no previous song in the queue, so restart the current one
+                MediaControlEvent::Previous => Action::Seek(0),
+                MediaControlEvent::Stop => Action::Stop,
+                MediaControlEvent::Seek(direction) => {
+                    Action::SeekBy(seek_offset(direction, Duration::from_secs(10)))
+                }
+                MediaControlEvent::SeekBy(direction, by) => {
+                    Action::SeekBy(seek_offset(direction, by))
+                }
+                MediaControlEvent::SetPosition(MediaPosition(pos)) => {
+                    Action::Seek(pos.as_millis() as _)
+                }
+                MediaControlEvent::SetVolume(volume) => Action::SetVolume(volume as _),
+                MediaControlEvent::OpenUri(_)
+                | MediaControlEvent::Raise
+                | MediaControlEvent::Quit => return,
+            };
+            let _ = command_sender.send((action.cmd(0xFFu8), None));
+        })
+        .map_err(|e| format!("Couldn't attach media controls: {e:?}"))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut state = State::default();
+    {
+        let mut db = database.lock().unwrap();
+        state.update(&db, &mut controls);
+        let udepid = db.update_endpoints_id;
+        db.update_endpoints_id += 1;
+        db.update_endpoints
+            .push((udepid, UpdateEndpoint::CmdChannel(sender)));
+    }
+    while receiver.recv().is_ok() {
+        // handle all commands which arrived in the meantime at once
+        while receiver.try_recv().is_ok() {}
+        state.update(&database.lock().unwrap(), &mut controls);
+    }
+    Ok(())
+}
+
+fn seek_offset(direction: SeekDirection, by: Duration) -> i64 {
+    let by = by.as_millis() as i64;
+    match direction {
+        SeekDirection::Forward => by,
+        SeekDirection::Backward => -by,
+    }
+}
+
+/// what was last sent to the media controls
+#[derive(Default)]
+struct State {
+    song: Option<Option<SongId>>,
+    playing: Option<bool>,
+    volume: Option<f32>,
+}
+
+impl State {
+    fn update(&mut self, db: &Database, controls: &mut MediaControls) {
+        let song = db.queue.get_current_song(db).copied();
+        if self.song != Some(song) {
+            self.song = Some(song);
+            // force an update of the playback status
+            self.playing = None;
+            let song = song.and_then(|id| db.get_song(&id));
+            let album = song
+                .and_then(|s| s.album)
+                .and_then(|id| db.albums().get(&id));
+            let cover_path = song
+                .and_then(|s| s.cover.or_else(|| album.and_then(|a| a.cover)))
+                .and_then(|id| db.covers().get(&id))
+                .map(|cover| format!("file://{}", db.get_path(&cover.location).display()));
+            let metadata = if let Some(song) = song {
+                MediaMetadata {
+                    title: Some(&song.title),
+                    album: album.map(|a| a.name.as_str()),
+                    artist: db.artists().get(&song.artist).map(|a| a.name.as_str()),
+                    cover_url: cover_path.as_deref(),
+                    duration: (song.duration_millis > 0)
+                        .then(|| Duration::from_millis(song.duration_millis)),
+                }
+            } else {
+                MediaMetadata::default()
+            };
+            if let Err(e) = controls.set_metadata(metadata) {
+                log_warn!("MPRIS: Couldn't set metadata: {e:?}");
+            }
+        }
+        if self.playing != Some(db.playing) {
+            self.playing = Some(db.playing);
+            let playback = if self.song.flatten().is_none() {
+                MediaPlayback::Stopped
+            } else if db.playing {
+                MediaPlayback::Playing { progress: None }
+            } else {
+                MediaPlayback::Paused { progress: None }
+            };
+            if let Err(e) = controls.set_playback(playback) {
+                log_warn!("MPRIS: Couldn't set playback status: {e:?}");
+            }
+        }
+        if self.volume != Some(db.volume) {
+            self.volume = Some(db.volume);
+            if let Err(e) = controls.set_volume(db.volume as _) {
+                log_warn!("MPRIS: Couldn't set volume: {e:?}");
+            }
+        }
+    }
+}
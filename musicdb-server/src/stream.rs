@@ -0,0 +1,236 @@
+use std::{
+    ops::Range,
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use musicdb_lib::{
+    data::{
+        database::{Database, UpdateEndpoint},
+        SongId,
+    },
+    server::Action,
+};
+use tokio::sync::broadcast;
+
+/// how often audio is sent to listeners
+const TICK: Duration = Duration::from_millis(100);
+/// how many chunks a listener may fall behind before it skips ahead
+const MAX_LAG_CHUNKS: usize = 50;
+/// header of a 128kbit/s, 44.1kHz, stereo MPEG-1 Layer III frame, used for silence if no song was played yet
+const DEFAULT_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+
+/// Streams the server's playback as mp3 to any number of listeners, see `/stream.mp3` in `web.rs`.
+/// The current song's file is sent as-is, one frame at a time, at the speed at which it would be played.
+/// While paused, or if the song's file ended before the queue moved on to the next song, silent frames are sent,
+/// so that connected players keep playing instead of running into timeouts.
+/// Listeners receive whole frames, so they can join at any time and start at the live position.
+pub fn start(database: Arc<Mutex<Database>>) -> broadcast::Sender<Arc<[u8]>> {
+    let (sender, _) = broadcast::channel(MAX_LAG_CHUNKS);
+    let listeners = sender.clone();
+    let (command_sender, commands) = mpsc::channel();
+    {
+        let mut db = database.lock().unwrap();
+        let udepid = db.update_endpoints_id;
+        db.update_endpoints_id += 1;
+        db.update_endpoints
+            .push((udepid, UpdateEndpoint::CmdChannel(command_sender)));
+    }
+    std::thread::spawn(move || {
+        let mut state = State {
+            song: None,
+            mp3: None,
+            pos: 0.0,
+            next_frame: None,
+            silence: silence_frame(DEFAULT_HEADER).unwrap(),
+            silence_millis: 0.0,
+        };
+        let mut last_tick = Instant::now();
+        loop {
+            std::thread::sleep(TICK);
+            let elapsed = last_tick.elapsed().as_secs_f64() * 1000.0;
+            last_tick = Instant::now();
+            for command in commands.try_iter() {
+                state.handle_action(&command.action);
+            }
+            let chunk = state.update(&database, elapsed, listeners.receiver_count() > 0);
+            if !chunk.is_empty() {
+                _ = listeners.send(chunk.into());
+            }
+        }
+    });
+    sender
+}
+
+struct State {
+    song: Option<SongId>,
+    mp3: Option<Mp3>,
+    /// the live position in the current song, in milliseconds
+    pos: f64,
+    /// the next frame of `mp3` to send, `None` if it has to be found using `pos`
+    next_frame: Option<usize>,
+    /// a silent frame with the same format as the current song
+    silence: Vec<u8>,
+    /// how much silence has to be sent, in milliseconds
+    silence_millis: f64,
+}
+
+impl State {
+    fn handle_action(&mut self, action: &Action) {
+        match action {
+            Action::Seek(pos) => self.pos = *pos as f64,
+            Action::SeekBy(by) => self.pos = (self.pos + *by as f64).max(0.0),
+            Action::Multiple(actions) => {
+                for action in actions {
+                    self.handle_action(action);
+                }
+                return;
+            }
+            _ => return,
+        }
+        self.next_frame = None;
+    }
+
+    /// advances the live position and returns the audio which has to be sent to listeners
+    fn update(&mut self, database: &Mutex<Database>, elapsed: f64, listening: bool) -> Vec<u8> {
+        let db = database.lock().unwrap();
+        let song = db.queue.get_current_song(&db).copied();
+        if song != self.song {
+            self.song = song;
+            self.mp3 = None;
+            self.pos = 0.0;
+            self.next_frame = None;
+        }
+        let playing = db.playing && song.is_some();
+        if playing {
+            self.pos += elapsed;
+        }
+        if !listening {
+            self.silence_millis = 0.0;
+            return vec![];
+        }
+        if self.mp3.is_none() {
+            self.mp3 = song
+                .and_then(|id| db.get_song(&id))
+                .and_then(|song| song.cached_data().get_data_or_maybe_start_thread(&db, song))
+                .map(Mp3::new);
+            if let Some(silence) = self
+                .mp3
+                .as_ref()
+                .and_then(|mp3| mp3.frames.first())
+                .and_then(|frame| silence_frame(frame.header))
+            {
+                self.silence = silence;
+            }
+        }
+        drop(db);
+        let mut chunk = vec![];
+        let frames = self.mp3.as_ref().filter(|_| playing);
+        let next_frame = frames.map(|mp3| {
+            *self
+                .next_frame
+                .get_or_insert_with(|| mp3.frames.partition_point(|f| f.start < self.pos))
+        });
+        if let (Some(mp3), Some(mut next_frame)) = (frames, next_frame) {
+            while let Some(frame) = mp3.frames.get(next_frame).filter(|f| f.start < self.pos) {
+                chunk.extend_from_slice(&mp3.data[frame.bytes.clone()]);
+                next_frame += 1;
+            }
+            self.next_frame = Some(next_frame);
+            if next_frame < mp3.frames.len() {
+                self.silence_millis = 0.0;
+                return chunk;
+            }
+        }
+        // paused, song still loading, or end of the file reached
+        self.silence_millis += elapsed;
+        let silence_duration = frame_info(&self.silence).map_or(26.0, |(_, millis)| millis);
+        while self.silence_millis >= silence_duration {
+            self.silence_millis -= silence_duration;
+            chunk.extend_from_slice(&self.silence);
+        }
+        chunk
+    }
+}
+
+struct Mp3 {
+    data: Arc<Vec<u8>>,
+    frames: Vec<Frame>,
+}
+struct Frame {
+    bytes: Range<usize>,
+    /// in milliseconds
+    start: f64,
+    header: [u8; 4],
+}
+
+impl Mp3 {
+    /// finds all frames in the file, skipping the id3 tag and anything else which isn't a frame
+    fn new(data: Arc<Vec<u8>>) -> Self {
+        let mut frames = vec![];
+        let mut i = 0;
+        if data.len() >= 10 && data.starts_with(b"ID3") {
+            let size = data[6..10]
+                .iter()
+                .fold(0, |size, b| (size << 7) | (*b as usize & 0x7F));
+            // header, tag, and a footer if the footer flag is set
+            i = 10 + size + if data[5] & 0x10 != 0 { 10 } else { 0 };
+        }
+        let mut start = 0.0;
+        while i + 4 <= data.len() {
+            let header = [data[i], data[i + 1], data[i + 2], data[i + 3]];
+            match frame_info(&header) {
+                Some((len, millis)) if i + len <= data.len() => {
+                    frames.push(Frame {
+                        bytes: i..i + len,
+                        start,
+                        header,
+                    });
+                    start += millis;
+                    i += len;
+                }
+                _ => i += 1,
+            }
+        }
+        Self { data, frames }
+    }
+}
+
+/// the length in bytes and the duration in milliseconds of an MPEG Layer III frame with this header
+fn frame_info(header: &[u8]) -> Option<(usize, f64)> {
+    const BITRATES_V1: [usize; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const BITRATES_V2: [usize; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    if header.len() < 4 || header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    // 3 = MPEG-1, 2 = MPEG-2, 0 = MPEG-2.5
+    let version = (header[1] >> 3) & 3;
+    let layer = (header[1] >> 1) & 3;
+    let bitrate = (header[2] >> 4) as usize;
+    let sample_rate = ((header[2] >> 2) & 3) as usize;
+    let padding = ((header[2] >> 1) & 1) as usize;
+    if version == 1 || layer != 1 || bitrate == 0 || bitrate == 15 || sample_rate == 3 {
+        return None;
+    }
+    let sample_rate = [44100, 48000, 32000][sample_rate] >> [2, 0, 1, 0][version as usize];
+    let (samples, bitrate) = if version == 3 {
+        (1152, BITRATES_V1[bitrate])
+    } else {
+        (576, BITRATES_V2[bitrate])
+    };
+    let len = samples / 8 * bitrate * 1000 / sample_rate + padding;
+    Some((len, samples as f64 * 1000.0 / sample_rate as f64))
+}
+
+/// A frame with the given header's format which decodes to silence:
+/// no CRC, and all-zero side information, so no audio data is used.
+fn silence_frame(header: [u8; 4]) -> Option<Vec<u8>> {
+    // no padding, no CRC
+    let header = [header[0], header[1] | 1, header[2] & !2, header[3]];
+    let (len, _) = frame_info(&header)?;
+    let mut frame = vec![0; len];
+    frame[..4].copy_from_slice(&header);
+    Some(frame)
+}
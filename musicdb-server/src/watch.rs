@@ -0,0 +1,106 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use musicdb_lib::{
+    data::{
+        database::Database,
+        scan::{apply_library_changes, is_song_file, LibraryChanges},
+    },
+    log_info, log_warn,
+};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecursiveMode, Watcher,
+};
+
+/// changes are applied once no new events arrived for this long...
+const DEBOUNCE: Duration = Duration::from_secs(2);
+/// ...or once the first event is this old, even if events keep arriving
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// how long to wait before trying again if a rescan is running
+const RESCAN_RUNNING_RETRY: Duration = Duration::from_secs(5);
+
+/// Watches the library directory and updates the database when song files are added, changed, moved or removed.
+/// Runs on its own thread. See `scan::apply_library_changes`.
+pub fn start(database: Arc<Mutex<Database>>, lib_dir: PathBuf) {
+    std::thread::spawn(move || {
+        if let Err(e) = run(database, &lib_dir) {
+            log_warn!("Watching the library directory: {e}");
+        }
+    });
+}
+
+fn run(database: Arc<Mutex<Database>>, lib_dir: &Path) -> Result<(), notify::Error> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(lib_dir, RecursiveMode::Recursive)?;
+    log_info!("Watching {lib_dir:?} for changes.");
+    let mut changes = LibraryChanges::default();
+    while let Ok(event) = receiver.recv() {
+        add_event(&mut changes, event?);
+        let start = Instant::now();
+        while let Some(remaining) = MAX_DELAY.checked_sub(start.elapsed()) {
+            match receiver.recv_timeout(remaining.min(DEBOUNCE)) {
+                Ok(Ok(event)) => add_event(&mut changes, event),
+                Ok(Err(e)) => log_warn!("Watching the library directory: {e}"),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        if changes.is_empty() {
+            continue;
+        }
+        loop {
+            match apply_library_changes(&database, std::mem::take(&mut changes)) {
+                Ok(()) => break,
+                Err(c) => {
+                    changes = c;
+                    std::thread::sleep(RESCAN_RUNNING_RETRY);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds the event to the changes, unless it is about a file which isn't a song file,
+/// like the dbfile or a cover, so that the server's own writes don't trigger updates.
+/// Removals can't be checked, because the paths don't exist anymore, but they only
+/// cause updates if there were songs at the removed path.
+fn add_event(changes: &mut LibraryChanges, event: Event) {
+    let relevant = |path: &Path| is_song_file(path) || path.is_dir();
+    match event.kind {
+        EventKind::Create(_)
+        | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(_) | ModifyKind::Any)
+        | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            for path in event.paths {
+                if relevant(&path) {
+                    changes.changed.insert(path);
+                }
+            }
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            changes.removed.extend(event.paths);
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            if let [from, to] = &event.paths[..] {
+                if is_song_file(from) || relevant(to) {
+                    changes.renamed.push((from.clone(), to.clone()));
+                }
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(_)) => {
+            for path in event.paths {
+                if !path.exists() {
+                    changes.removed.insert(path);
+                } else if relevant(&path) {
+                    changes.changed.insert(path);
+                }
+            }
+        }
+        _ => {}
+    }
+}
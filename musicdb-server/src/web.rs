@@ -3,13 +3,25 @@ use std::sync::{mpsc, Arc, Mutex};
 
 use musicdb_lib::data::album::Album;
 use musicdb_lib::data::artist::Artist;
-use musicdb_lib::data::database::Database;
+use musicdb_lib::data::database::{cover_version, Database, UpdateEndpoint};
+use musicdb_lib::data::playlist;
 use musicdb_lib::data::queue::{Queue, QueueContent, QueueFolder};
+use musicdb_lib::data::search::{SearchResult, SearchResultKind};
 use musicdb_lib::data::song::Song;
-use musicdb_lib::data::SongId;
-use musicdb_lib::server::{Action, Command, Req};
+use musicdb_lib::data::{AlbumId, ArtistId, CoverId, SongId};
+use musicdb_lib::server::{get, permission::Permission, Action, Command, Req};
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
 use rocket::response::content::RawHtml;
-use rocket::{get, routes, Config, State};
+use rocket::response::stream::{ByteStream, Event, EventStream};
+use rocket::response::Redirect;
+use rocket::{catchers, get, routes, Config, Either, FromForm, Responder, State};
+use tokio::sync::{broadcast, watch};
+
+mod api;
+pub mod templates;
+
+use crate::web_auth::{login, login_form, unauthorized, Control, NeedsLogin, View, WebAuth};
 
 /*
 
@@ -33,134 +45,315 @@ use rocket::{get, routes, Config, State};
 
 */
 
-const HTML_START: &'static str =
-    "<!DOCTYPE html><html><head><meta charset=\"UTF-8\"><meta name=\"color-scheme\" content=\"light dark\">";
-const HTML_SEP: &'static str = "</head><body>";
-const HTML_END: &'static str = "</body></html>";
-
 struct Data {
     db: Arc<Mutex<Database>>,
     command_sender: mpsc::Sender<(Command, Option<u64>)>,
+    permission: Permission,
+    /// incremented whenever an action changes the upcoming songs, see `upcoming_html`
+    upcoming_version: watch::Receiver<u64>,
 }
+impl Data {
+    fn send(&self, action: Action) -> Status {
+        if self.permission.allows(&action) {
+            self.command_sender
+                .send((action.cmd(0xFFu8), None))
+                .unwrap();
+            Status::Ok
+        } else {
+            Status::Forbidden
+        }
+    }
+    /// sends the action if it could be created
+    fn try_send(&self, action: Result<Action, Status>) -> Status {
+        action.map_or_else(|status| status, |action| self.send(action))
+    }
+}
+/// see `stream::start`, only managed if `--web-stream` is used
+struct StreamData(broadcast::Sender<Arc<[u8]>>);
 
 #[get("/")]
-fn index(data: &State<Data>) -> RawHtml<String> {
-    dbg!(());
-    let script = r#"<script>
-const sleep = ms => new Promise(r => setTimeout(r, ms));
-async function performSearch() {
-    var searchResultDiv = document.getElementById("searchResultDiv");
-    searchResultDiv.innerHTML = "Loading...";
-    var sfArtist = document.getElementById("searchFieldArtist").value;
-    var sfAlbum = document.getElementById("searchFieldAlbum").value;
-    var sfTitle = document.getElementById("searchFieldTitle").value;
-    var query = "";
-    if (sfArtist) {
-        query += "artist=" + encodeURIComponent(sfArtist);
-    }
-    if (sfAlbum) {
-        if (query) {
-            query += "&";
+fn index(_view: View, data: &State<Data>, needs_login: NeedsLogin) -> RawHtml<String> {
+    let db = data.db.lock().unwrap();
+    templates::index(
+        &db,
+        data.permission,
+        *data.upcoming_version.borrow(),
+        needs_login.0,
+    )
+}
+/// Server-sent events for the page's inline script, see `web_events`.
+/// The endpoint is removed from the database once the browser disconnects and the stream is dropped.
+#[get("/events")]
+fn events(_view: View, data: &State<Data>) -> EventStream![] {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    {
+        let mut db = data.db.lock().unwrap();
+        let udepid = db.update_endpoints_id;
+        db.update_endpoints_id += 1;
+        db.update_endpoints
+            .push((udepid, UpdateEndpoint::CmdChannelTokio(sender)));
+    }
+    let db = Arc::clone(&data.db);
+    EventStream! {
+        while let Some(command) = receiver.recv().await {
+            let mut events = vec![];
+            web_events(&command.action, &db.lock().unwrap(), &mut events);
+            for (event, data) in events {
+                yield Event::data(data).event(event);
+            }
         }
-        query += "album=" + encodeURIComponent(sfAlbum);
     }
-    if (sfTitle) {
-        if (query) {
-            query += "&";
+}
+/// the events the page reacts to:
+/// `song` (the current song changed), `queue` (see `changes_upcoming`), `playback` and `volume` (with the new value),
+/// and `position` (see `position_text`) whenever the position may have jumped, and with every `Ping` to stay in sync.
+fn web_events(action: &Action, db: &Database, events: &mut Vec<(&'static str, String)>) {
+    match action {
+        Action::Multiple(actions) => {
+            for action in actions {
+                web_events(action, db, events);
+            }
         }
-        query += "title=" + encodeURIComponent(sfTitle);
+        Action::Resume => events.push(("playback", "playing".to_owned())),
+        Action::Pause => events.push(("playback", "paused".to_owned())),
+        Action::Stop => events.push(("playback", "stopped".to_owned())),
+        Action::SetVolume(volume) => events.push(("volume", volume_percent(*volume))),
+        Action::NextSong | Action::NextSongIfCurrent(..) => events.push(("song", String::new())),
+        action if changes_upcoming(action) => events.push(("queue", String::new())),
+        _ => {}
+    }
+    match action {
+        // the player may not have seeked yet, so `playback_position` could still be the old position
+        Action::Seek(pos) => events.push(("position", position_text(db, Some(*pos)))),
+        Action::SeekBy(..)
+        | Action::Resume
+        | Action::Pause
+        | Action::Stop
+        | Action::NextSong
+        | Action::NextSongIfCurrent(..)
+        | Action::QueueGoto(..)
+        | Action::Ping => events.push(("position", position_text(db, None))),
+        _ => {}
     }
-    if (query || confirm("You didn't search for anything specific. If you continue, the whole library will be loaded, which can take a while, use a lot of bandwidth, and may crash your browser!")) {
-        console.log("Performing search with query '" + query + "'.");
-        var r1 = await fetch("/search?" + query);
-        var r2 = await r1.text();
-        searchResultDiv.innerHTML = r2;
+}
+/// `{elapsed} {duration} {playing}` (in milliseconds, `playing` is 0 or 1) for the page's seek bar,
+/// or an empty string if the position or the duration of the current song isn't known.
+fn position_text(db: &Database, seek: Option<u64>) -> String {
+    let Some(duration) = db
+        .queue
+        .get_current_song(db)
+        .and_then(|id| db.get_song(id))
+        .map(|song| song.duration_millis)
+        .filter(|duration| *duration > 0)
+    else {
+        return String::new();
+    };
+    // after a song change, the position is only updated once the new song started,
+    // but if any position is known, playback will report one for the new song as well.
+    let elapsed = match seek {
+        Some(pos) => pos,
+        None if db.playback_position.is_some() => db.playback_position_now().unwrap_or(0),
+        None => return String::new(),
+    };
+    format!("{} {duration} {}", elapsed.min(duration), db.playing as u8)
+}
+fn playback_state(db: &Database) -> &'static str {
+    if db.playing {
+        "playing"
+    } else if db.queue.get_current_song(db).is_some() {
+        "paused"
     } else {
-        searchResultDiv.innerHTML = "";
-    }
-}
-async function addSong(id) {
-    await fetch("/add-song/" + id);
-}
-</script>"#;
-    let script2 = r#"<script>
-const searchDiv = document.getElementById("searchDiv");
-searchDiv.style.display = "";
-document.getElementById("warnLag").innerText = "connecting...";
-const nowPlayingDiv = document.getElementById("nowPlayingDiv");
-const queueDiv = document.getElementById("queueDiv");
-var didFinish = false;
-var averageLoopTimeMs = 250;
-async function runLoop() {
-    while (true) {
-        await sleep(1000);
-        didFinish = false;
-        var startTime = new Date();
-        sleep(averageLoopTimeMs*2).then(async function() {
-            while (!didFinish) {
-                var elapsed = new Date() - startTime;
-                document.getElementById("warnLag").innerText = "Warning: slow connection, server may be busy. be patient. (" + Math.round(averageLoopTimeMs) + "ms exceeded by " + Math.round((elapsed-averageLoopTimeMs)/averageLoopTimeMs) + "x)";
-                await sleep(100);
-            }
-        });
-        nowPlayingDiv.innerHTML = await (await fetch("/now-playing-html")).text();
-        queueDiv.innerHTML = await (await fetch("/queue-html")).text();
-        var elapsedTime = new Date() - startTime;
-        didFinish = true;
-        averageLoopTimeMs = ((averageLoopTimeMs * 4) + elapsedTime) / 5;
-        document.getElementById("warnLag").innerText = "Average update time: " + Math.round(averageLoopTimeMs) + "ms";
-    }
-}
-runLoop();</script>"#;
-    let buttons = "<button onclick=\"fetch('/play')\">play</button><button onclick=\"fetch('/pause')\">pause</button><button onclick=\"fetch('/stop')\">stop</button><button onclick=\"fetch('/skip')\">skip</button><button onclick=\"fetch('/clear-queue')\">clear queue</button>";
-    let search = "<input id=\"searchFieldArtist\" placeholder=\"artist\"><input id=\"searchFieldAlbum\" placeholder=\"album\"><input id=\"searchFieldTitle\" placeholder=\"title\">
-<button onclick=\"performSearch()\">search</button><div id=\"searchResultDiv\"></div>";
-    let db = data.db.lock().unwrap();
-    let now_playing = gen_now_playing(&db);
-    let mut queue = String::new();
-    gen_queue_html(&db.queue, &mut queue, &db);
-    dbg!(&queue);
-    drop(db);
-    RawHtml(format!(
-        "{HTML_START}<title>MusicDb</title>{script}{HTML_SEP}<small><small><div id=\"warnLag\">no javascript? reload to see updated information.</div></small></small><div id=\"nowPlayingDiv\">{now_playing}</div><div>{buttons}</div><div id=\"searchDiv\" style=\"display:none;\">{search}</div><div id=\"queueDiv\">{queue}</div>{script2}{HTML_END}",
-    ))
+        "stopped"
+    }
+}
+fn volume_percent(volume: f32) -> String {
+    format!("{}%", (volume * 100.0).round())
+}
+#[get("/position")]
+fn position(_view: View, data: &State<Data>) -> String {
+    position_text(&data.db.lock().unwrap(), None)
 }
 #[get("/now-playing-html")]
-fn now_playing_html(data: &State<Data>) -> RawHtml<String> {
-    RawHtml(gen_now_playing(&*data.db.lock().unwrap()))
+fn now_playing_html(_view: View, data: &State<Data>) -> RawHtml<String> {
+    RawHtml(templates::now_playing(&data.db.lock().unwrap()))
 }
 #[get("/queue-html")]
-fn queue_html(data: &State<Data>) -> RawHtml<String> {
+fn queue_html(_view: View, data: &State<Data>) -> RawHtml<String> {
     let mut str = String::new();
     let db = data.db.lock().unwrap();
     gen_queue_html(&db.queue, &mut str, &db);
     RawHtml(str)
 }
-fn gen_now_playing(db: &Database) -> String {
-    if let Some(current_song) = db.queue.get_current_song().and_then(|id| db.get_song(id)) {
-        format!(
-            "<h1>Now Playing</h1><h4>{}</h4>",
-            html_escape::encode_safe(&current_song.title),
-        )
+/// If `since` is the current version, waits until the upcoming songs change, so the page can update immediately.
+#[get("/upcoming-html?<since>")]
+async fn upcoming_html(_view: View, data: &State<Data>, since: Option<u64>) -> RawHtml<String> {
+    let mut version = data.upcoming_version.clone();
+    if let Some(since) = since {
+        _ = version.wait_for(|version| *version != since).await;
+    }
+    let version = *version.borrow();
+    RawHtml(gen_upcoming_html(&data.db.lock().unwrap(), version))
+}
+/// how many songs `gen_upcoming_html` shows after the current one
+const UPCOMING_SONGS: usize = 50;
+/// the current song and the next `UPCOMING_SONGS`, see `Queue::upcoming_songs`
+fn gen_upcoming_html(db: &Database, version: u64) -> String {
+    let mut str = format!("<div data-version=\"{version}\"><h3>Up next</h3>");
+    let current = db.queue.get_current_song(db).copied();
+    let songs = current
+        .into_iter()
+        .chain(db.queue.upcoming_songs(db, UPCOMING_SONGS))
+        .collect::<Vec<_>>();
+    if songs.is_empty() {
+        str.push_str("<p>nothing</p>");
     } else {
-        format!("<h1>Now Playing</h1><p>nothing</p>",)
+        str.push_str("<ol>");
+        for (i, id) in songs.iter().enumerate() {
+            let Some(song) = db.get_song(id) else {
+                str.push_str("<li><small>unknown song</small></li>");
+                continue;
+            };
+            let highlight = i == 0 && current.is_some();
+            str.push_str("<li>");
+            if highlight {
+                str.push_str("<b>");
+            }
+            str.push_str(&html_escape::encode_text(&song.title));
+            if highlight {
+                str.push_str("</b>");
+            }
+            str.push_str("<small>");
+            if let Some(artist) = db.artists().get(&song.artist) {
+                str.push_str(" by ");
+                str.push_str(&html_escape::encode_text(&artist.name));
+            }
+            if song.duration_millis > 0 {
+                let secs = song.duration_millis / 1000;
+                str.push_str(&format!(" ({}:{:02})", secs / 60, secs % 60));
+            }
+            str.push_str("</small></li>");
+        }
+        str.push_str("</ol>");
+    }
+    str.push_str("</div>");
+    str
+}
+/// if the action can change which songs are shown by `gen_upcoming_html`
+fn changes_upcoming(action: &Action) -> bool {
+    match action {
+        Action::Multiple(actions) => actions.iter().any(changes_upcoming),
+        Action::NextSong
+        | Action::NextSongIfCurrent(..)
+        | Action::QueueFinished
+        | Action::QueueUpdate(..)
+        | Action::QueueAdd(..)
+        | Action::QueueInsert(..)
+        | Action::QueueRemove(..)
+        | Action::QueueMove(..)
+        | Action::QueueMoveInto(..)
+        | Action::QueueGoto(..)
+        | Action::QueueShuffle(..)
+        | Action::QueueSetShuffle(..)
+        | Action::QueueUnshuffle(..)
+        | Action::QueueClear(..)
+        | Action::QueueShuffleInPlace(..)
+        | Action::QueueDuplicate(..)
+        | Action::QueueLoopBreak(..)
+        | Action::QueueDedupSongs(..)
+        | Action::QueuePlayNext(..)
+        | Action::SyncDatabase(..)
+        | Action::ModifySong(..)
+        | Action::ModifyArtist(..)
+        | Action::RemoveSong(..)
+        | Action::SetSongDuration(..) => true,
+        _ => false,
+    }
+}
+/// The cover's bytes. The ETag is the cover's version (see `cover_version`), so browsers can revalidate cached covers.
+/// If the cover can't be loaded, a placeholder is sent instead.
+#[get("/cover/<id>")]
+fn cover(
+    _view: View,
+    data: &State<Data>,
+    id: CoverId,
+    if_none_match: IfNoneMatch,
+) -> CoverResponse {
+    let (_, bytes) = get::covers_bytes(&data.db, &[id]).pop().unwrap();
+    let (bytes, path) = match bytes {
+        Ok(bytes) => (
+            bytes,
+            data.db
+                .lock()
+                .unwrap()
+                .covers()
+                .get(&id)
+                .map(|cover| cover.location.rel_path.clone()),
+        ),
+        Err(_) => {
+            return CoverResponse::Placeholder(
+                templates::placeholder_svg(""),
+                ContentType::SVG,
+                Header::new("Cache-Control", "no-cache"),
+            )
+        }
+    };
+    let etag = format!("\"{:x}\"", cover_version(&bytes));
+    if if_none_match.0.as_deref() == Some(etag.as_str()) {
+        return CoverResponse::NotModified(());
+    }
+    let content_type = path
+        .as_ref()
+        .and_then(|path| path.extension())
+        .and_then(|ext| ContentType::from_extension(&ext.to_string_lossy().to_lowercase()))
+        .unwrap_or(ContentType::Binary);
+    CoverResponse::Cover(
+        bytes,
+        content_type,
+        Header::new("Cache-Control", "no-cache"),
+        Header::new("ETag", etag),
+    )
+}
+#[derive(Responder)]
+enum CoverResponse {
+    Cover(Vec<u8>, ContentType, Header<'static>, Header<'static>),
+    Placeholder(String, ContentType, Header<'static>),
+    #[response(status = 304)]
+    NotModified(()),
+}
+/// the `If-None-Match` header, see `cover`
+struct IfNoneMatch(Option<String>);
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = std::convert::Infallible;
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self(
+            request
+                .headers()
+                .get_one("If-None-Match")
+                .map(|etag| etag.to_owned()),
+        ))
     }
 }
+
 fn gen_queue_html(queue: &Queue, str: &mut String, db: &Database) {
-    gen_queue_html_impl(queue, str, db, true, &mut "".to_owned());
+    gen_queue_html_impl(queue, str, db, true, &mut "".to_owned(), None);
 }
+/// `siblings` is the element's index and the length of its folder, if it is directly in a folder (so it can be moved)
 fn gen_queue_html_impl(
     queue: &Queue,
     str: &mut String,
     db: &Database,
     active_highlight: bool,
     path: &mut String,
+    siblings: Option<(usize, usize)>,
 ) {
     match queue.content() {
         QueueContent::Song(id) => {
             if let Some(song) = db.songs().get(id) {
                 str.push_str("<div>");
-                str.push_str(&format!("<button onclick=\"fetch('/queue-goto/{path}')\">"));
+                str.push_str(&format!(
+                    "<button onclick=\"queueAction('/queue-goto/{path}/{id}')\" title=\"play from here\">"
+                ));
                 if active_highlight {
                     str.push_str("<b>");
                 }
@@ -178,8 +371,20 @@ fn gen_queue_html_impl(
                     str.push_str(" on ");
                     str.push_str(&html_escape::encode_text(&album.name));
                 }
+                if let Some((i, len)) = siblings {
+                    if i > 0 {
+                        str.push_str(&format!(
+                            "<button onclick=\"queueAction('/queue-move/{path}/{id}/up')\">&uarr;</button>"
+                        ));
+                    }
+                    if i + 1 < len {
+                        str.push_str(&format!(
+                            "<button onclick=\"queueAction('/queue-move/{path}/{id}/down')\">&darr;</button>"
+                        ));
+                    }
+                }
                 str.push_str(&format!(
-                    "<button onclick=\"fetch('/queue-remove/{path}')\">rm</button>"
+                    "<button onclick=\"queueAction('/queue-remove/{path}/{id}')\">rm</button>"
                 ));
                 str.push_str("</small></div>");
             } else {
@@ -219,7 +424,14 @@ fn gen_queue_html_impl(
                         path.push('_');
                     }
                     path.push_str(&format!("{i}"));
-                    gen_queue_html_impl(v, str, db, active_highlight && i == f.index, path);
+                    gen_queue_html_impl(
+                        v,
+                        str,
+                        db,
+                        active_highlight && i == f.index,
+                        path,
+                        Some((i, f.content.len())),
+                    );
                     while !(path.is_empty() || path.ends_with('_')) {
                         path.pop();
                     }
@@ -229,14 +441,14 @@ fn gen_queue_html_impl(
                 str.push_str("</ol>");
             }
         }
-        QueueContent::Loop(d, t, i) => {
+        QueueContent::Loop(_, done, i) => {
             if active_highlight {
                 str.push_str("<b>");
             }
-            if *t == 0 {
-                str.push_str(&format!("<small>[{}/&infin;]</small>", d + 1));
+            if let Some(Some(total)) = queue.content().loop_total() {
+                str.push_str(&format!("<small>[{}/{}]</small>", done + 1, total));
             } else {
-                str.push_str(&format!("<small>[{}/{}]</small>", d + 1, t));
+                str.push_str(&format!("<small>[{}/&infin;]</small>", done + 1));
             }
             if active_highlight {
                 str.push_str("</b>");
@@ -245,97 +457,715 @@ fn gen_queue_html_impl(
                 path.push('_');
             }
             path.push('0');
-            gen_queue_html_impl(i, str, db, active_highlight, path);
+            gen_queue_html_impl(i, str, db, active_highlight, path, None);
             while !(path.is_empty() || path.ends_with('_')) {
                 path.pop();
             }
             path.pop();
         }
+        QueueContent::Random(filter, weighting, q) => {
+            if active_highlight {
+                str.push_str("<b>");
+            }
+            str.push_str("<small>[random]</small> ");
+            str.push_str(&html_escape::encode_text(&filter.describe(db)));
+            let weighting = weighting.describe();
+            if !weighting.is_empty() {
+                str.push(' ');
+                str.push_str(weighting);
+            }
+            if active_highlight {
+                str.push_str("</b>");
+            }
+            str.push_str("<ol>");
+            for (i, v) in q.iter().enumerate() {
+                str.push_str("<li>");
+                if !path.is_empty() {
+                    path.push('_');
+                }
+                path.push_str(&format!("{i}"));
+                gen_queue_html_impl(v, str, db, active_highlight && i == 0, path, None);
+                while !(path.is_empty() || path.ends_with('_')) {
+                    path.pop();
+                }
+                path.pop();
+                str.push_str("</li>");
+            }
+            str.push_str("</ol>");
+        }
+        QueueContent::AlbumRef(id, index) => {
+            if let Some(album) = db.albums().get(id) {
+                str.push_str(&format!("[{}/{}] ", index + 1, album.songs.len()));
+                if active_highlight {
+                    str.push_str("<b>");
+                }
+                str.push_str(&html_escape::encode_text(&album.name));
+                if active_highlight {
+                    str.push_str("</b>");
+                }
+                str.push_str("<ol>");
+                for (i, song) in album.songs.iter().enumerate() {
+                    let song_path = if path.is_empty() {
+                        format!("{i}")
+                    } else {
+                        format!("{path}_{i}")
+                    };
+                    str.push_str(&format!(
+                        "<li><button onclick=\"queueAction('/queue-goto/{song_path}/{song}')\">"
+                    ));
+                    let highlight = active_highlight && i == *index;
+                    if highlight {
+                        str.push_str("<b>");
+                    }
+                    if let Some(song) = db.songs().get(song) {
+                        str.push_str(&html_escape::encode_text(&song.title));
+                    } else {
+                        str.push_str("unknown song");
+                    }
+                    if highlight {
+                        str.push_str("</b>");
+                    }
+                    str.push_str("</button></li>");
+                }
+                str.push_str("</ol>");
+            } else {
+                str.push_str("<div><small>unknown album</small></div>");
+            }
+        }
     }
 }
 
-#[get("/queue-remove/<path>")]
-fn queue_remove(data: &State<Data>, path: &str) {
-    if let Some(path) = path.split('_').map(|v| v.parse().ok()).collect() {
-        data.command_sender
-            .send((Action::QueueRemove(path).cmd(0xFFu8), None))
-            .unwrap();
+#[get("/stream.mp3")]
+fn stream(_view: View, data: &State<StreamData>) -> (ContentType, ByteStream![Arc<[u8]>]) {
+    let mut receiver = data.0.subscribe();
+    (
+        ContentType::new("audio", "mpeg"),
+        ByteStream! {
+            loop {
+                match receiver.recv().await {
+                    Ok(chunk) => yield chunk,
+                    // chunks only contain whole frames, so some can be skipped
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        },
+    )
+}
+
+// the queue may have changed since the page was generated, so these endpoints get the song which was at `path`
+// and look for it again (see `resolve_song_path`) instead of acting on whatever is at `path` now.
+#[get("/queue-remove/<path>/<id>")]
+fn queue_remove(_control: Control, data: &State<Data>, path: &str, id: SongId) -> Status {
+    let action = queue_remove_action(&data.db.lock().unwrap(), path, id);
+    data.try_send(action)
+}
+/// `direction` is `up` or `down`, songs can only be moved within their folder
+#[get("/queue-move/<path>/<id>/<direction>")]
+fn queue_move(
+    _control: Control,
+    data: &State<Data>,
+    path: &str,
+    id: SongId,
+    direction: &str,
+) -> Status {
+    let action = queue_move_action(&data.db.lock().unwrap(), path, id, direction);
+    data.try_send(action)
+}
+#[get("/queue-goto/<path>/<id>")]
+fn queue_goto(_control: Control, data: &State<Data>, path: &str, id: SongId) -> Status {
+    let action = queue_goto_action(&data.db.lock().unwrap(), path, id);
+    data.try_send(action)
+}
+fn queue_remove_action(db: &Database, path: &str, id: SongId) -> Result<Action, Status> {
+    match resolve_song_path(db, path, id)? {
+        path if is_song_element(&db.queue, &path) => Ok(Action::QueueRemove(path)),
+        _ => Err(Status::BadRequest),
     }
 }
-#[get("/queue-goto/<path>")]
-fn queue_goto(data: &State<Data>, path: &str) {
-    if let Some(path) = path.split('_').map(|v| v.parse().ok()).collect() {
-        data.command_sender
-            .send((Action::QueueGoto(path).cmd(0xFFu8), None))
-            .unwrap();
+fn queue_move_action(
+    db: &Database,
+    path: &str,
+    id: SongId,
+    direction: &str,
+) -> Result<Action, Status> {
+    let path = match resolve_song_path(db, path, id)? {
+        path if is_song_element(&db.queue, &path) => path,
+        _ => return Err(Status::BadRequest),
+    };
+    let (i, parent) = path.split_last().unwrap();
+    let len = match db.queue.get_item_at_index(parent, 0).map(|q| q.content()) {
+        Some(QueueContent::Folder(folder)) => folder.content.len(),
+        _ => return Err(Status::BadRequest),
+    };
+    // `QueueMove` inserts the element before the target index
+    let target = match direction {
+        "up" if *i > 0 => i - 1,
+        "down" if i + 1 < len => i + 2,
+        _ => return Err(Status::BadRequest),
+    };
+    let mut target_path = parent.to_vec();
+    target_path.push(target);
+    Ok(Action::QueueMove(path, target_path))
+}
+fn queue_goto_action(db: &Database, path: &str, id: SongId) -> Result<Action, Status> {
+    Ok(Action::QueueGoto(resolve_song_path(db, path, id)?))
+}
+/// The path of the song `id` which was at `path` (as used in `gen_queue_html`) when the page was generated.
+/// If something else is there now, the occurrence of the song closest to `path` is used instead.
+/// `Conflict` if the song isn't in the queue anymore, the page should then reload the queue.
+fn resolve_song_path(db: &Database, path: &str, id: SongId) -> Result<Vec<usize>, Status> {
+    let Some(path) = path
+        .split('_')
+        .map(|v| v.parse().ok())
+        .collect::<Option<Vec<usize>>>()
+    else {
+        return Err(Status::BadRequest);
+    };
+    let mut candidates = vec![];
+    song_paths(&db.queue, db, id, &mut vec![], &mut candidates);
+    candidates
+        .into_iter()
+        .min_by_key(|candidate| {
+            let common = candidate
+                .iter()
+                .zip(&path)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let distance = match (candidate.get(common), path.get(common)) {
+                (Some(a), Some(b)) => a.abs_diff(*b),
+                _ => 0,
+            };
+            (std::cmp::Reverse(common), distance)
+        })
+        .ok_or(Status::Conflict)
+}
+/// all paths at which the song `id` is in the queue, including songs of `AlbumRef`s
+fn song_paths(
+    queue: &Queue,
+    db: &Database,
+    id: SongId,
+    path: &mut Vec<usize>,
+    out: &mut Vec<Vec<usize>>,
+) {
+    match queue.content() {
+        QueueContent::Song(song) => {
+            if *song == id {
+                out.push(path.clone());
+            }
+        }
+        QueueContent::Folder(folder) => {
+            for (i, v) in folder.iter().enumerate() {
+                path.push(i);
+                song_paths(v, db, id, path, out);
+                path.pop();
+            }
+        }
+        QueueContent::Loop(_, _, inner) => {
+            path.push(0);
+            song_paths(inner, db, id, path, out);
+            path.pop();
+        }
+        QueueContent::Random(_, _, q) => {
+            for (i, v) in q.iter().enumerate() {
+                path.push(i);
+                song_paths(v, db, id, path, out);
+                path.pop();
+            }
+        }
+        QueueContent::AlbumRef(album, _) => {
+            if let Some(album) = db.albums().get(album) {
+                for (i, song) in album.songs.iter().enumerate() {
+                    if *song == id {
+                        path.push(i);
+                        out.push(path.clone());
+                        path.pop();
+                    }
+                }
+            }
+        }
     }
 }
+/// false for songs of `AlbumRef`s, which can't be removed or moved on their own
+fn is_song_element(queue: &Queue, path: &[usize]) -> bool {
+    queue
+        .get_item_at_index(path, 0)
+        .is_some_and(|q| matches!(q.content(), QueueContent::Song(_)))
+}
 
 #[get("/play")]
-fn play(data: &State<Data>) {
-    data.command_sender
-        .send((Action::Resume.cmd(0xFFu8), None))
-        .unwrap();
+fn play(_control: Control, data: &State<Data>) -> Status {
+    data.send(Action::Resume)
 }
 #[get("/pause")]
-fn pause(data: &State<Data>) {
-    data.command_sender
-        .send((Action::Pause.cmd(0xFFu8), None))
-        .unwrap();
+fn pause(_control: Control, data: &State<Data>) -> Status {
+    data.send(Action::Pause)
 }
 #[get("/stop")]
-fn stop(data: &State<Data>) {
-    data.command_sender
-        .send((Action::Stop.cmd(0xFFu8), None))
-        .unwrap();
+fn stop(_control: Control, data: &State<Data>) -> Status {
+    data.send(Action::Stop)
 }
 #[get("/skip")]
-fn skip(data: &State<Data>) {
-    data.command_sender
-        .send((Action::NextSong.cmd(0xFFu8), None))
-        .unwrap();
+fn skip(_control: Control, data: &State<Data>) -> Status {
+    let action = data.db.lock().unwrap().next_song_action();
+    data.send(action)
+}
+/// `ms` is the position in the current song, in milliseconds
+#[get("/seek/<ms>")]
+fn seek(_control: Control, data: &State<Data>, ms: u64) -> Status {
+    data.send(Action::Seek(ms))
+}
+/// `percent` is clamped to 0..=100
+#[get("/volume/<percent>")]
+fn volume(_control: Control, data: &State<Data>, percent: f32) -> Status {
+    data.try_send(volume_action(percent))
+}
+fn volume_action(percent: f32) -> Result<Action, Status> {
+    if percent.is_finite() {
+        Ok(Action::SetVolume(percent.clamp(0.0, 100.0) / 100.0))
+    } else {
+        Err(Status::BadRequest)
+    }
 }
 #[get("/clear-queue")]
-fn clear_queue(data: &State<Data>) {
-    data.command_sender
-        .send((
-            Action::QueueUpdate(
-                vec![],
-                QueueContent::Folder(QueueFolder {
-                    index: 0,
-                    content: vec![],
-                    name: String::new(),
-                    order: None,
-                })
-                .into(),
-                Req::none(),
-            )
-            .cmd(0xFFu8),
-            None,
-        ))
-        .unwrap();
+fn clear_queue(_control: Control, data: &State<Data>) -> Status {
+    data.send(Action::QueueClear(vec![]))
 }
 
 #[get("/add-song/<id>")]
-fn add_song(data: &State<Data>, id: SongId) {
-    data.command_sender
-        .send((
-            Action::QueueAdd(vec![], vec![QueueContent::Song(id).into()], Req::none()).cmd(0xFFu8),
-            None,
-        ))
-        .unwrap();
+fn add_song(_control: Control, data: &State<Data>, id: SongId) -> Status {
+    data.send(Action::QueueAdd(
+        vec![],
+        vec![QueueContent::Song(id).into()],
+        Req::none(),
+    ))
+}
+
+#[get("/add-album/<id>")]
+fn add_album(_control: Control, data: &State<Data>, id: AlbumId) -> (Status, String) {
+    let db = data.db.lock().unwrap();
+    let Some(album) = db.albums().get(&id) else {
+        return (Status::NotFound, "album not found".to_owned());
+    };
+    let queue = album_folder(&db, album);
+    let message = format!("added {} ({} songs)", album.name, queue.len(&db));
+    drop(db);
+    send_with_message(
+        data,
+        Action::QueueAdd(vec![], vec![queue], Req::none()),
+        message,
+    )
+}
+#[get("/add-artist/<id>")]
+fn add_artist(_control: Control, data: &State<Data>, id: ArtistId) -> (Status, String) {
+    let db = data.db.lock().unwrap();
+    let Some(artist) = db.artists().get(&id) else {
+        return (Status::NotFound, "artist not found".to_owned());
+    };
+    let queue = artist_folder(&db, artist);
+    let message = format!("added {} ({} songs)", artist.name, queue.len(&db));
+    drop(db);
+    send_with_message(
+        data,
+        Action::QueueAdd(vec![], vec![queue], Req::none()),
+        message,
+    )
+}
+/// `message` if the action was sent, otherwise why not
+fn send_with_message(data: &Data, action: Action, message: String) -> (Status, String) {
+    let status = data.send(action);
+    if status == Status::Ok {
+        (status, message)
+    } else {
+        (status, "not allowed".to_owned())
+    }
+}
+/// a folder named after the album, with its songs in the album's order. songs which don't exist are skipped.
+fn album_folder(db: &Database, album: &Album) -> Queue {
+    QueueContent::Folder(QueueFolder {
+        index: 0,
+        content: album
+            .songs
+            .iter()
+            .filter(|id| db.get_song(id).is_some())
+            .map(|id| QueueContent::Song(*id).into())
+            .collect(),
+        name: album.name.clone(),
+        order: None,
+    })
+    .into()
+}
+/// a folder named after the artist, with a folder for each album (see `album_folder`), then the artist's singles
+fn artist_folder(db: &Database, artist: &Artist) -> Queue {
+    QueueContent::Folder(QueueFolder {
+        index: 0,
+        content: artist
+            .albums
+            .iter()
+            .filter_map(|id| db.albums().get(id))
+            .map(|album| album_folder(db, album))
+            .chain(
+                artist
+                    .singles
+                    .iter()
+                    .filter(|id| db.get_song(id).is_some())
+                    .map(|id| QueueContent::Song(*id).into()),
+            )
+            .collect(),
+        name: artist.name.clone(),
+        order: None,
+    })
+    .into()
+}
+
+/// `back` is set by the forms on the library pages (see `queue_buttons`), which also work without javascript
+#[get("/queue-add/<kind>/<id>?<back>")]
+fn queue_add(
+    _control: Control,
+    data: &State<Data>,
+    kind: &str,
+    id: u64,
+    back: Option<&str>,
+) -> Either<Redirect, Status> {
+    let Some(queue) = search_result_queue(&data.db.lock().unwrap(), kind, id) else {
+        return Either::Right(Status::NotFound);
+    };
+    go_back(
+        data.send(Action::QueueAdd(vec![], vec![queue], Req::none())),
+        back,
+    )
+}
+#[get("/play-next/<kind>/<id>?<back>")]
+fn play_next(
+    _control: Control,
+    data: &State<Data>,
+    kind: &str,
+    id: u64,
+    back: Option<&str>,
+) -> Either<Redirect, Status> {
+    let Some(queue) = search_result_queue(&data.db.lock().unwrap(), kind, id) else {
+        return Either::Right(Status::NotFound);
+    };
+    go_back(data.send(Action::QueuePlayNext(queue)), back)
+}
+/// redirects to `back` if the action was sent, but only to pages on this website
+/// (browsers treat `//host` and `/\host` as other websites)
+fn go_back(status: Status, back: Option<&str>) -> Either<Redirect, Status> {
+    let is_local = |back: &str| back.starts_with('/') && !back[1..].starts_with(['/', '\\']);
+    match back {
+        Some(back) if status == Status::Ok && is_local(back) => {
+            Either::Left(Redirect::to(back.to_owned()))
+        }
+        _ => Either::Right(status),
+    }
+}
+/// the song, or a folder with the album's or artist's songs, see `album_folder` and `artist_folder`
+fn search_result_queue(db: &Database, kind: &str, id: u64) -> Option<Queue> {
+    Some(match kind.parse().ok()? {
+        SearchResultKind::Song => QueueContent::Song(db.get_song(&id)?.id).into(),
+        SearchResultKind::Album => album_folder(db, db.albums().get(&id)?),
+        SearchResultKind::Artist => artist_folder(db, db.artists().get(&id)?),
+    })
 }
 
-#[get("/search?<artist>&<album>&<title>&<artist_tags>&<album_tags>&<song_tags>")]
-fn search(
+/// how many results `library_search` shows per page
+const LIBRARY_SEARCH_PAGE_SIZE: usize = 20;
+/// results after this many aren't shown, even on later pages
+const LIBRARY_SEARCH_MAX_RESULTS: usize = 200;
+/// Searches the library (see `Database::search`) and returns one page of results,
+/// each with buttons to add it to the queue or to play it next.
+#[get("/library-search?<q>&<page>")]
+fn library_search(
+    _view: View,
     data: &State<Data>,
-    artist: Option<&str>,
-    album: Option<&str>,
-    title: Option<&str>,
-    artist_tags: Vec<&str>,
-    album_tags: Vec<&str>,
-    song_tags: Vec<&str>,
+    q: String,
+    page: Option<usize>,
 ) -> RawHtml<String> {
+    let page = page.unwrap_or(0);
+    let (results, more) = library_search_page(&data.db.lock().unwrap(), &q, page);
+    let mut out = String::new();
+    if results.is_empty() {
+        if page == 0 && !q.trim().is_empty() {
+            out.push_str("<p>no results</p>");
+        }
+        return RawHtml(out);
+    }
+    out.push_str("<ul>");
+    for result in &results {
+        let (kind, id) = (result.kind, result.id);
+        out.push_str(&format!(
+            "<li><small>{kind}</small> {} <button onclick=\"fetch('/queue-add/{kind}/{id}')\">add to queue</button><button onclick=\"fetch('/play-next/{kind}/{id}')\">play next</button></li>",
+            html_escape::encode_text(&result.name),
+        ));
+    }
+    out.push_str("</ul>");
+    if page > 0 {
+        out.push_str(&format!(
+            "<button onclick=\"librarySearch({})\">previous</button>",
+            page - 1
+        ));
+    }
+    if more {
+        out.push_str(&format!(
+            "<button onclick=\"librarySearch({})\">next</button>",
+            page + 1
+        ));
+    }
+    RawHtml(out)
+}
+/// one page of `Database::search`'s results, and whether there is another page
+fn library_search_page(db: &Database, q: &str, page: usize) -> (Vec<SearchResult>, bool) {
+    let start = page.saturating_mul(LIBRARY_SEARCH_PAGE_SIZE);
+    if start >= LIBRARY_SEARCH_MAX_RESULTS {
+        return (vec![], false);
+    }
+    // one more than necessary, to know if there is another page
+    let limit = (start + LIBRARY_SEARCH_PAGE_SIZE + 1).min(LIBRARY_SEARCH_MAX_RESULTS);
+    let mut results = db.search(q, limit);
+    let more = results.len() > start + LIBRARY_SEARCH_PAGE_SIZE;
+    results.truncate(start + LIBRARY_SEARCH_PAGE_SIZE);
+    results.drain(..start.min(results.len()));
+    (results, more)
+}
+
+fn not_found_page(what: &str) -> (Status, RawHtml<String>) {
+    (
+        Status::NotFound,
+        templates::page(
+            &format!("{what} not found"),
+            "<p>It may have been removed.</p>",
+        ),
+    )
+}
+/// forms (instead of buttons with `fetch`, so they work without javascript) which add to the queue, then reload `back`
+fn queue_buttons(kind: SearchResultKind, id: u64, back: &str) -> String {
+    let back = html_escape::encode_double_quoted_attribute(back);
+    format!(
+        "<form method=\"get\" action=\"/queue-add/{kind}/{id}\" style=\"display:inline\"><input type=\"hidden\" name=\"back\" value=\"{back}\"><button>add to queue</button></form><form method=\"get\" action=\"/play-next/{kind}/{id}\" style=\"display:inline\"><input type=\"hidden\" name=\"back\" value=\"{back}\"><button>play next</button></form>"
+    )
+}
+/// `m:ss`, or an empty string if the duration isn't known
+fn format_duration(millis: u64) -> String {
+    if millis == 0 {
+        return String::new();
+    }
+    let secs = millis / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+/// a list of songs which exist, with their durations and queue buttons
+fn songs_html(db: &Database, songs: &[SongId], back: &str, out: &mut String) {
+    out.push_str("<ol>");
+    for song in songs.iter().filter_map(|id| db.get_song(id)) {
+        out.push_str(&format!(
+            "<li>{} <small>{}</small> {}</li>",
+            html_escape::encode_text(&song.title),
+            format_duration(song.duration_millis),
+            queue_buttons(SearchResultKind::Song, song.id, back),
+        ));
+    }
+    out.push_str("</ol>");
+}
+#[get("/artists")]
+fn artists_page(_view: View, data: &State<Data>) -> RawHtml<String> {
+    let db = data.db.lock().unwrap();
+    let mut artists = db.artists().values().collect::<Vec<_>>();
+    artists.sort_by_cached_key(|artist| artist.name.to_lowercase());
+    let mut out = String::from("<ul>");
+    for artist in artists {
+        out.push_str(&format!(
+            "<li><a href=\"/artist/{}\">{}</a> <small>{} albums, {} songs</small></li>",
+            artist.id,
+            html_escape::encode_text(&artist.name),
+            artist.albums.len(),
+            artist_song_count(&db, artist),
+        ));
+    }
+    out.push_str("</ul>");
+    templates::page("Artists", &out)
+}
+/// the artist's singles and the songs on their albums
+fn artist_song_count(db: &Database, artist: &Artist) -> usize {
+    artist.singles.len()
+        + artist
+            .albums
+            .iter()
+            .filter_map(|id| db.albums().get(id))
+            .map(|album| album.songs.len())
+            .sum::<usize>()
+}
+#[get("/artist/<id>")]
+fn artist_page(_view: View, data: &State<Data>, id: ArtistId) -> (Status, RawHtml<String>) {
+    let db = data.db.lock().unwrap();
+    let Some(artist) = db.artists().get(&id) else {
+        return not_found_page("Artist");
+    };
+    let back = format!("/artist/{id}");
+    let mut out = format!(
+        "{}<br>{}",
+        templates::cover_html(artist.cover, &artist.name),
+        queue_buttons(SearchResultKind::Artist, id, &back),
+    );
+    out.push_str("<h2>Albums</h2><ul>");
+    for album in artist.albums.iter().filter_map(|id| db.albums().get(id)) {
+        out.push_str(&format!(
+            "<li><a href=\"/album/{}\">{}<br>{}</a> <small>{} songs</small> {}</li>",
+            album.id,
+            templates::cover_html(album.cover, &album.name),
+            html_escape::encode_text(&album.name),
+            album.songs.len(),
+            queue_buttons(SearchResultKind::Album, album.id, &back),
+        ));
+    }
+    out.push_str("</ul>");
+    if !artist.singles.is_empty() {
+        out.push_str("<h2>Singles</h2>");
+        songs_html(&db, &artist.singles, &back, &mut out);
+    }
+    (Status::Ok, templates::page(&artist.name, &out))
+}
+#[get("/album/<id>")]
+fn album_page(_view: View, data: &State<Data>, id: AlbumId) -> (Status, RawHtml<String>) {
+    let db = data.db.lock().unwrap();
+    let Some(album) = db.albums().get(&id) else {
+        return not_found_page("Album");
+    };
+    let back = format!("/album/{id}");
+    let mut out = String::new();
+    if let Some(artist) = db.artists().get(&album.artist) {
+        out.push_str(&format!(
+            "<p>by <a href=\"/artist/{}\">{}</a></p>",
+            artist.id,
+            html_escape::encode_text(&artist.name),
+        ));
+    }
+    out.push_str(&format!(
+        "{}<br>{}",
+        templates::cover_html(album.cover, &album.name),
+        queue_buttons(SearchResultKind::Album, id, &back),
+    ));
+    songs_html(&db, &album.songs, &back, &mut out);
+    (Status::Ok, templates::page(&album.name, &out))
+}
+
+/// Saved queues (see `playlist`), with forms to load, delete and save them.
+/// `notice` is the result of the last form, see `playlist_form`.
+#[get("/playlists?<notice>")]
+fn playlists_page(_view: View, data: &State<Data>, notice: Option<&str>) -> RawHtml<String> {
+    let mut out = String::new();
+    if let Some(notice) = notice {
+        out.push_str(&format!("<p>{}</p>", html_escape::encode_text(notice)));
+    }
+    let form = |action: &str, name: &str, extra: &str, button: &str| {
+        format!(
+            "<form method=\"post\" action=\"/playlist/{action}\" style=\"display:inline\"><input type=\"hidden\" name=\"name\" value=\"{}\">{extra}<button>{button}</button></form>",
+            html_escape::encode_double_quoted_attribute(name),
+        )
+    };
+    match playlist::list(&data.db.lock().unwrap()) {
+        Ok(names) if names.is_empty() => out.push_str("<p>no saved playlists</p>"),
+        Ok(names) => {
+            out.push_str("<ul>");
+            for name in names {
+                out.push_str(&format!(
+                    "<li>{} {}{}{}</li>",
+                    html_escape::encode_text(&name),
+                    form("load", &name, "", "replace queue"),
+                    form(
+                        "load",
+                        &name,
+                        "<input type=\"hidden\" name=\"append\" value=\"true\">",
+                        "add to queue"
+                    ),
+                    form("delete", &name, "", "delete"),
+                ));
+            }
+            out.push_str("</ul>");
+        }
+        Err(e) => out.push_str(&format!(
+            "<p>couldn't list playlists: {}</p>",
+            html_escape::encode_text(&e.to_string())
+        )),
+    }
+    out.push_str("<h2>Save the queue</h2><form method=\"post\" action=\"/playlist/save\"><input name=\"name\" placeholder=\"name\"><button>save</button></form>");
+    templates::page("Playlists", &out)
+}
+#[derive(FromForm)]
+struct PlaylistForm<'r> {
+    name: &'r str,
+    append: bool,
+}
+/// `action` is `save`, `load` or `delete`, see `playlist_action`. redirects to `/playlists` with the result.
+#[rocket::post("/playlist/<action>", data = "<form>")]
+fn playlist_form(
+    _control: Control,
+    data: &State<Data>,
+    action: &str,
+    form: rocket::form::Form<PlaylistForm<'_>>,
+) -> Redirect {
+    let notice = match playlist_action(data, action, form.name, form.append) {
+        Ok(message) | Err((_, message)) => message,
+    };
+    Redirect::to(rocket::uri!(playlists_page(Some(notice))))
+}
+/// Saves the current queue under `name`, loads the playlist (replacing the queue, or adding it to the end if `append`),
+/// or deletes it, see `playlist::apply_action`. Returns a message for the user.
+fn playlist_action(
+    data: &Data,
+    action: &str,
+    name: &str,
+    append: bool,
+) -> Result<String, (Status, String)> {
+    let name = name.to_owned();
+    let action = match action {
+        "save" => Action::PlaylistSave(vec![], name),
+        "load" => Action::PlaylistLoad(name, append),
+        "delete" => Action::PlaylistDelete(name),
+        _ => return Err((Status::NotFound, format!("unknown action {action}"))),
+    };
+    if !data.permission.allows(&action) {
+        return Err((Status::Forbidden, "not allowed".to_owned()));
+    }
+    let result = playlist::apply_action(&data.db.lock().unwrap(), &action);
+    match result {
+        Ok((message, queue_action)) => {
+            if let Some(queue_action) = queue_action {
+                let status = data.send(queue_action);
+                if status != Status::Ok {
+                    return Err((status, "not allowed".to_owned()));
+                }
+            }
+            Ok(message)
+        }
+        Err(e) => Err((
+            match e.kind() {
+                std::io::ErrorKind::InvalidInput => Status::BadRequest,
+                std::io::ErrorKind::NotFound => Status::NotFound,
+                _ => Status::InternalServerError,
+            },
+            e.to_string(),
+        )),
+    }
+}
+
+#[derive(FromForm)]
+struct SearchQuery<'r> {
+    artist: Option<&'r str>,
+    album: Option<&'r str>,
+    title: Option<&'r str>,
+    artist_tags: Vec<&'r str>,
+    album_tags: Vec<&'r str>,
+    song_tags: Vec<&'r str>,
+}
+#[get("/search?<query..>")]
+fn search(_view: View, data: &State<Data>, query: SearchQuery<'_>) -> RawHtml<String> {
+    let SearchQuery {
+        artist,
+        album,
+        title,
+        artist_tags,
+        album_tags,
+        song_tags,
+    } = query;
     let db = data.db.lock().unwrap();
     let mut out = String::new();
     let artist = artist.map(|v| v.to_lowercase());
@@ -408,6 +1238,10 @@ fn search(
                 let mut func_artist = Some(|out: &mut String| {
                     out.push_str("<h3>");
                     out.push_str(&artist.name);
+                    out.push_str(&format!(
+                        " <button onclick=\"addToQueue('/add-artist/{}')\">add artist</button>",
+                        artist.id
+                    ));
                     out.push_str("</h3>");
                 });
                 let mut func_album = None;
@@ -468,6 +1302,10 @@ fn search(
                 let mut func_album = Some(|out: &mut String| {
                     out.push_str("<h4>");
                     out.push_str(&album.name);
+                    out.push_str(&format!(
+                        " <button onclick=\"addToQueue('/add-album/{}')\">add album</button>",
+                        album.id
+                    ));
                     out.push_str("</h4>");
                 });
                 find4(
@@ -549,14 +1387,37 @@ pub async fn main(
     db: Arc<Mutex<Database>>,
     command_sender: mpsc::Sender<(Command, Option<u64>)>,
     addr: SocketAddr,
+    permission: Permission,
+    stream: bool,
+    auth: Option<WebAuth>,
 ) {
-    rocket::build()
+    let stream = stream.then(|| StreamData(crate::stream::start(Arc::clone(&db))));
+    let (upcoming_version_sender, upcoming_version) = watch::channel(0);
+    {
+        let mut db = db.lock().unwrap();
+        let udepid = db.update_endpoints_id;
+        db.update_endpoints_id += 1;
+        db.update_endpoints.push((
+            udepid,
+            UpdateEndpoint::Custom(Box::new(move |command| {
+                if changes_upcoming(&command.action) {
+                    upcoming_version_sender.send_modify(|version| *version += 1);
+                }
+            })),
+        ));
+    }
+    let mut rocket = rocket::build()
         .configure(Config {
             address: addr.ip(),
             port: addr.port(),
             ..Default::default()
         })
-        .manage(Data { db, command_sender })
+        .manage(Data {
+            db,
+            command_sender,
+            permission,
+            upcoming_version,
+        })
         .mount(
             "/",
             routes![
@@ -565,16 +1426,43 @@ pub async fn main(
                 pause,
                 stop,
                 skip,
+                seek,
+                volume,
                 clear_queue,
                 queue_goto,
                 queue_remove,
+                queue_move,
                 add_song,
+                add_album,
+                add_artist,
+                queue_add,
+                play_next,
+                library_search,
+                artists_page,
+                artist_page,
+                album_page,
+                playlists_page,
+                playlist_form,
                 search,
                 now_playing_html,
-                queue_html
+                events,
+                position,
+                cover,
+                queue_html,
+                upcoming_html
             ],
-        )
-        .launch()
-        .await
-        .unwrap();
+        );
+    rocket = rocket
+        .mount("/api", api::routes())
+        .register("/api", api::catchers());
+    if let Some(stream) = stream {
+        rocket = rocket.manage(stream).mount("/", routes![stream]);
+    }
+    if let Some(auth) = auth {
+        rocket = rocket
+            .manage(auth)
+            .mount("/", routes![login_form, login])
+            .register("/", catchers![unauthorized]);
+    }
+    rocket.launch().await.unwrap();
 }
@@ -0,0 +1,399 @@
+use musicdb_lib::data::database::Database;
+use musicdb_lib::data::playlist;
+use musicdb_lib::data::queue::{Queue, QueueContent};
+use musicdb_lib::data::{AlbumId, ArtistId, SongId};
+use musicdb_lib::server::{get, Action, Req};
+use rocket::http::{ContentType, Status};
+use rocket::{catch, catchers, get, post, routes, Catcher, Request, Route, State};
+use serde_json::{json, Value};
+
+use super::{
+    artist_song_count, library_search_page, playback_state, queue_goto_action, queue_move_action,
+    queue_remove_action, search_result_queue, volume_action, Data,
+};
+use crate::web_auth::{Control, View};
+
+/// Incremented whenever a response changes in a way which could break existing users of the api.
+/// Adding fields doesn't count.
+const API_VERSION: u32 = 1;
+
+/// Every response is a json object with a `version` field (see `API_VERSION`).
+/// Errors have the matching http status and an `error` field with a short description.
+///
+/// - `GET /api/now-playing`: `state` (`playing`, `paused` or `stopped`), `volume` (in percent),
+///   `song` (see `song_json`, or null), `elapsed_ms` (or null if unknown) and the `upcoming` songs
+/// - `GET /api/queue`: the queue as a tree of elements with a `type` (see `queue_json`)
+/// - `GET /api/search?q=<query>&page=<page>`: `results` (`kind`, `id` and `name`) and `more` if there is another page
+/// - `GET /api/artists`, `GET /api/artist/<id>`, `GET /api/album/<id>`: the library, covers can be loaded from `/cover/<id>`
+/// - `POST /api/play`, `pause`, `stop`, `skip`, `clear-queue`, `seek/<ms>` and `volume/<percent>`
+/// - `POST /api/queue-add/<kind>/<id>` and `play-next/<kind>/<id>` where `kind` is `song`, `album` or `artist`
+/// - `POST /api/queue-remove/<path>/<id>`, `queue-move/<path>/<id>/<up|down>` and `queue-goto/<path>/<id>`,
+///   with the `path` and `id` of a song in `/api/queue`. If the queue changed in the meantime, the song is searched for,
+///   and if it isn't in the queue anymore, the status is 409.
+/// - `GET /api/playlists`: the `playlists`' names
+/// - `POST /api/playlist/<save|load|delete>/<name>`, `load` replaces the queue unless `?append=true` is used.
+///   the response has a `message` for the user, which mentions songs that were skipped because they were removed.
+pub fn routes() -> Vec<Route> {
+    routes![
+        now_playing,
+        queue,
+        search,
+        artists,
+        artist,
+        album,
+        play,
+        pause,
+        stop,
+        skip,
+        clear_queue,
+        seek,
+        volume,
+        queue_add,
+        play_next,
+        queue_remove,
+        queue_move,
+        queue_goto,
+        playlists,
+        playlist_action,
+    ]
+}
+pub fn catchers() -> Vec<Catcher> {
+    catchers![error_catcher]
+}
+
+type ApiResponse = (Status, (ContentType, String));
+
+fn respond(status: Status, mut value: Value) -> ApiResponse {
+    value["version"] = API_VERSION.into();
+    (status, (ContentType::JSON, value.to_string()))
+}
+fn error(status: Status) -> ApiResponse {
+    respond(
+        status,
+        json!({ "error": status.reason().unwrap_or("unknown error") }),
+    )
+}
+/// the response to a command, see `Data::send`
+fn sent(status: Status) -> ApiResponse {
+    if status == Status::Ok {
+        respond(status, json!({}))
+    } else {
+        error(status)
+    }
+}
+/// so that failing request guards and unknown urls also get json responses
+#[catch(default)]
+fn error_catcher(status: Status, _: &Request) -> ApiResponse {
+    error(status)
+}
+
+/// `id`, `title`, `artist` and `album` (`id` and `name`, or null), `cover` (or null) and `duration_ms` (or null if unknown)
+fn song_json(db: &Database, id: &SongId) -> Value {
+    let Some(song) = db.get_song(id) else {
+        return json!({ "id": id, "title": null });
+    };
+    let artist = db.artists().get(&song.artist);
+    let album = song.album.and_then(|id| db.albums().get(&id));
+    json!({
+        "id": song.id,
+        "title": song.title,
+        "artist": artist.map(|artist| json!({ "id": artist.id, "name": artist.name })),
+        "album": album.map(|album| json!({ "id": album.id, "name": album.name })),
+        "cover": song.cover.or_else(|| album.and_then(|album| album.cover)),
+        "duration_ms": (song.duration_millis > 0).then_some(song.duration_millis),
+    })
+}
+/// `song` elements have a `path` and `id` for the queue commands, `current` is true for the current element
+/// and the elements which contain it. other types are `folder`, `loop`, `random` and `album`.
+fn queue_json(queue: &Queue, db: &Database, current: bool, path: &mut Vec<usize>) -> Value {
+    let path_str = |path: &[usize]| {
+        path.iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("_")
+    };
+    match queue.content() {
+        QueueContent::Song(id) => {
+            let mut song = song_json(db, id);
+            song["type"] = "song".into();
+            song["path"] = path_str(path).into();
+            song["current"] = current.into();
+            song
+        }
+        QueueContent::Folder(folder) => {
+            let children = folder
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    path.push(i);
+                    let v = queue_json(v, db, current && i == folder.index, path);
+                    path.pop();
+                    v
+                })
+                .collect::<Vec<_>>();
+            json!({
+                "type": "folder",
+                "name": folder.name,
+                "index": folder.index,
+                "shuffled": folder.order.is_some(),
+                "current": current,
+                "children": children,
+            })
+        }
+        QueueContent::Loop(total, done, inner) => {
+            path.push(0);
+            let inner = queue_json(inner, db, current, path);
+            path.pop();
+            json!({
+                "type": "loop",
+                "total": (*total > 0).then_some(*total),
+                "done": done,
+                "current": current,
+                "inner": inner,
+            })
+        }
+        QueueContent::Random(filter, _, q) => {
+            let children = q
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    path.push(i);
+                    let v = queue_json(v, db, current && i == 0, path);
+                    path.pop();
+                    v
+                })
+                .collect::<Vec<_>>();
+            json!({
+                "type": "random",
+                "filter": filter.describe(db),
+                "current": current,
+                "children": children,
+            })
+        }
+        QueueContent::AlbumRef(id, index) => {
+            let songs = db.albums().get(id).map_or(vec![], |album| {
+                album
+                    .songs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| {
+                        path.push(i);
+                        let mut song = song_json(db, id);
+                        song["type"] = "song".into();
+                        song["path"] = path_str(path).into();
+                        song["current"] = (current && i == *index).into();
+                        path.pop();
+                        song
+                    })
+                    .collect()
+            });
+            json!({
+                "type": "album",
+                "id": id,
+                "index": index,
+                "current": current,
+                "children": songs,
+            })
+        }
+    }
+}
+
+#[get("/now-playing")]
+fn now_playing(_view: View, data: &State<Data>) -> ApiResponse {
+    let db = data.db.lock().unwrap();
+    let current = db.queue.get_current_song(&db).copied();
+    let upcoming = db
+        .queue
+        .upcoming_songs(&db, get::NOW_PLAYING_UPCOMING)
+        .iter()
+        .map(|id| song_json(&db, id))
+        .collect::<Vec<_>>();
+    respond(
+        Status::Ok,
+        json!({
+            "state": playback_state(&db),
+            "volume": (db.volume * 100.0).round(),
+            "song": current.map(|id| song_json(&db, &id)),
+            "elapsed_ms": db.playback_position_now(),
+            "upcoming": upcoming,
+        }),
+    )
+}
+#[get("/queue")]
+fn queue(_view: View, data: &State<Data>) -> ApiResponse {
+    let db = data.db.lock().unwrap();
+    let queue = queue_json(&db.queue, &db, true, &mut vec![]);
+    respond(Status::Ok, json!({ "queue": queue }))
+}
+#[get("/search?<q>&<page>")]
+fn search(_view: View, data: &State<Data>, q: &str, page: Option<usize>) -> ApiResponse {
+    let (results, more) = library_search_page(&data.db.lock().unwrap(), q, page.unwrap_or(0));
+    let results = results
+        .into_iter()
+        .map(|result| json!({ "kind": result.kind.to_string(), "id": result.id, "name": result.name }))
+        .collect::<Vec<_>>();
+    respond(Status::Ok, json!({ "results": results, "more": more }))
+}
+#[get("/artists")]
+fn artists(_view: View, data: &State<Data>) -> ApiResponse {
+    let db = data.db.lock().unwrap();
+    let mut artists = db.artists().values().collect::<Vec<_>>();
+    artists.sort_by_cached_key(|artist| artist.name.to_lowercase());
+    let artists = artists
+        .into_iter()
+        .map(|artist| {
+            json!({
+                "id": artist.id,
+                "name": artist.name,
+                "albums": artist.albums.len(),
+                "songs": artist_song_count(&db, artist),
+            })
+        })
+        .collect::<Vec<_>>();
+    respond(Status::Ok, json!({ "artists": artists }))
+}
+#[get("/artist/<id>")]
+fn artist(_view: View, data: &State<Data>, id: ArtistId) -> ApiResponse {
+    let db = data.db.lock().unwrap();
+    let Some(artist) = db.artists().get(&id) else {
+        return error(Status::NotFound);
+    };
+    let albums = artist
+        .albums
+        .iter()
+        .filter_map(|id| db.albums().get(id))
+        .map(|album| {
+            json!({
+                "id": album.id,
+                "name": album.name,
+                "cover": album.cover,
+                "songs": album.songs.len(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let singles = artist
+        .singles
+        .iter()
+        .filter(|id| db.get_song(id).is_some())
+        .map(|id| song_json(&db, id))
+        .collect::<Vec<_>>();
+    respond(
+        Status::Ok,
+        json!({
+            "id": artist.id,
+            "name": artist.name,
+            "cover": artist.cover,
+            "albums": albums,
+            "singles": singles,
+        }),
+    )
+}
+#[get("/album/<id>")]
+fn album(_view: View, data: &State<Data>, id: AlbumId) -> ApiResponse {
+    let db = data.db.lock().unwrap();
+    let Some(album) = db.albums().get(&id) else {
+        return error(Status::NotFound);
+    };
+    let artist = db.artists().get(&album.artist);
+    let songs = album
+        .songs
+        .iter()
+        .filter(|id| db.get_song(id).is_some())
+        .map(|id| song_json(&db, id))
+        .collect::<Vec<_>>();
+    respond(
+        Status::Ok,
+        json!({
+            "id": album.id,
+            "name": album.name,
+            "artist": artist.map(|artist| json!({ "id": artist.id, "name": artist.name })),
+            "cover": album.cover,
+            "songs": songs,
+        }),
+    )
+}
+
+#[post("/play")]
+fn play(_control: Control, data: &State<Data>) -> ApiResponse {
+    sent(data.send(Action::Resume))
+}
+#[post("/pause")]
+fn pause(_control: Control, data: &State<Data>) -> ApiResponse {
+    sent(data.send(Action::Pause))
+}
+#[post("/stop")]
+fn stop(_control: Control, data: &State<Data>) -> ApiResponse {
+    sent(data.send(Action::Stop))
+}
+#[post("/skip")]
+fn skip(_control: Control, data: &State<Data>) -> ApiResponse {
+    let action = data.db.lock().unwrap().next_song_action();
+    sent(data.send(action))
+}
+#[post("/clear-queue")]
+fn clear_queue(_control: Control, data: &State<Data>) -> ApiResponse {
+    sent(data.send(Action::QueueClear(vec![])))
+}
+#[post("/seek/<ms>")]
+fn seek(_control: Control, data: &State<Data>, ms: u64) -> ApiResponse {
+    sent(data.send(Action::Seek(ms)))
+}
+#[post("/volume/<percent>")]
+fn volume(_control: Control, data: &State<Data>, percent: f32) -> ApiResponse {
+    sent(data.try_send(volume_action(percent)))
+}
+#[post("/queue-add/<kind>/<id>")]
+fn queue_add(_control: Control, data: &State<Data>, kind: &str, id: u64) -> ApiResponse {
+    let Some(queue) = search_result_queue(&data.db.lock().unwrap(), kind, id) else {
+        return error(Status::NotFound);
+    };
+    sent(data.send(Action::QueueAdd(vec![], vec![queue], Req::none())))
+}
+#[post("/play-next/<kind>/<id>")]
+fn play_next(_control: Control, data: &State<Data>, kind: &str, id: u64) -> ApiResponse {
+    let Some(queue) = search_result_queue(&data.db.lock().unwrap(), kind, id) else {
+        return error(Status::NotFound);
+    };
+    sent(data.send(Action::QueuePlayNext(queue)))
+}
+#[post("/queue-remove/<path>/<id>")]
+fn queue_remove(_control: Control, data: &State<Data>, path: &str, id: SongId) -> ApiResponse {
+    let action = queue_remove_action(&data.db.lock().unwrap(), path, id);
+    sent(data.try_send(action))
+}
+#[post("/queue-move/<path>/<id>/<direction>")]
+fn queue_move(
+    _control: Control,
+    data: &State<Data>,
+    path: &str,
+    id: SongId,
+    direction: &str,
+) -> ApiResponse {
+    let action = queue_move_action(&data.db.lock().unwrap(), path, id, direction);
+    sent(data.try_send(action))
+}
+#[post("/queue-goto/<path>/<id>")]
+fn queue_goto(_control: Control, data: &State<Data>, path: &str, id: SongId) -> ApiResponse {
+    let action = queue_goto_action(&data.db.lock().unwrap(), path, id);
+    sent(data.try_send(action))
+}
+#[get("/playlists")]
+fn playlists(_view: View, data: &State<Data>) -> ApiResponse {
+    match playlist::list(&data.db.lock().unwrap()) {
+        Ok(names) => respond(Status::Ok, json!({ "playlists": names })),
+        Err(_) => error(Status::InternalServerError),
+    }
+}
+#[post("/playlist/<action>/<name>?<append>")]
+fn playlist_action(
+    _control: Control,
+    data: &State<Data>,
+    action: &str,
+    name: &str,
+    append: bool,
+) -> ApiResponse {
+    match super::playlist_action(data, action, name, append) {
+        Ok(message) => respond(Status::Ok, json!({ "message": message })),
+        Err((status, message)) => respond(status, json!({ "error": message })),
+    }
+}
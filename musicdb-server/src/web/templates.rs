@@ -0,0 +1,374 @@
+use musicdb_lib::data::database::Database;
+use musicdb_lib::data::CoverId;
+use musicdb_lib::server::{permission::Permission, Action};
+use rocket::response::content::RawHtml;
+
+use super::{gen_queue_html, gen_upcoming_html, playback_state, position_text, volume_percent};
+
+// Render functions for the website's pages. Every page uses `layout`, and the parts of the main page
+// which are updated while it is open (`now_playing`, the queue and the upcoming songs) can be rendered on their own.
+
+/// one column which uses the whole width on phones, bigger buttons on touch screens,
+/// and the browser's light or dark colors (see `color-scheme`).
+const STYLE: &str = r#"
+body { font-family: sans-serif; max-width: 50em; margin: 0 auto; padding: 0.5em; }
+nav { display: flex; flex-wrap: wrap; gap: 1em; padding-bottom: 0.5em; border-bottom: 1px solid gray; }
+h1 { font-size: 1.6em; }
+img, svg { max-width: 100%; height: auto; }
+input:not([type]) { min-width: 0; max-width: 100%; }
+input[type="range"] { width: 100%; max-width: 30em; }
+ol, ul { padding-left: 1.5em; }
+li { margin: 0.2em 0; }
+.nowPlaying { display: flex; flex-wrap: wrap; align-items: center; gap: 1em; }
+.controls { display: flex; flex-wrap: wrap; align-items: center; gap: 0.3em; margin: 0.5em 0; }
+.controls > div, .controls > small { flex-basis: 100%; }
+@media (pointer: coarse) {
+    button { min-height: 2.5em; min-width: 2.5em; }
+}
+@media (max-width: 30em) {
+    body { padding: 0.3em; }
+    h1 { font-size: 1.3em; }
+}
+"#;
+
+/// The page: navigation links, then `body`. `head` is added to the head, for example scripts.
+pub fn layout(title: &str, head: &str, body: &str) -> RawHtml<String> {
+    let title = if title.is_empty() {
+        "MusicDb".to_owned()
+    } else {
+        format!("MusicDb - {}", html_escape::encode_text(title))
+    };
+    RawHtml(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"UTF-8\"><meta name=\"color-scheme\" content=\"light dark\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>{title}</title><style>{STYLE}</style>{head}</head><body><nav><a href=\"/\">now playing</a><a href=\"/artists\">artists</a><a href=\"/playlists\">playlists</a></nav>{body}</body></html>"
+    ))
+}
+/// a page with a heading
+pub fn page(title: &str, body: &str) -> RawHtml<String> {
+    layout(
+        title,
+        "",
+        &format!("<h1>{}</h1>{body}", html_escape::encode_text(title)),
+    )
+}
+
+/// The main page. Without javascript, it shows the state at the time it was loaded,
+/// with javascript, it is updated by the `/events` stream (or by polling, if that isn't available).
+pub fn index(
+    db: &Database,
+    permission: Permission,
+    upcoming_version: u64,
+    needs_login: bool,
+) -> RawHtml<String> {
+    let login = if needs_login {
+        "<p><a href=\"/login\">Log in</a> to control playback and the queue.</p>"
+    } else {
+        ""
+    };
+    let now_playing = now_playing(db);
+    let controls = controls(db, permission);
+    let upcoming = gen_upcoming_html(db, upcoming_version);
+    let mut queue = String::new();
+    gen_queue_html(&db.queue, &mut queue, db);
+    layout(
+        "",
+        &format!("<script>{INDEX_HEAD_SCRIPT}</script>"),
+        &format!(
+            "<small><small><div id=\"warnLag\">no javascript? reload to see updated information.</div></small></small>{login}<div id=\"nowPlayingDiv\">{now_playing}</div>{controls}<div id=\"searchDiv\" style=\"display:none;\"><small id=\"addStatus\"></small>{SEARCH}</div><div id=\"upcomingDiv\">{upcoming}</div><div id=\"queueDiv\">{queue}</div><script>{INDEX_SCRIPT}</script>"
+        ),
+    )
+}
+
+/// the current song and its cover, see `/now-playing-html`
+pub fn now_playing(db: &Database) -> String {
+    if db.queue_finished {
+        "<h1>Now Playing</h1><p>end of queue</p>".to_owned()
+    } else if let Some(current_song) = db.queue.get_current_song(db).and_then(|id| db.get_song(id))
+    {
+        let album = current_song.album.and_then(|id| db.albums().get(&id));
+        let cover = cover_html(
+            current_song
+                .cover
+                .or_else(|| album.and_then(|album| album.cover)),
+            album.map_or(&current_song.title, |album| &album.name),
+        );
+        format!(
+            "<h1>Now Playing</h1><div class=\"nowPlaying\">{cover}<h4>{}</h4></div>",
+            html_escape::encode_safe(&current_song.title),
+        )
+    } else {
+        "<h1>Now Playing</h1><p>nothing</p>".to_owned()
+    }
+}
+
+/// The seek bar and volume slider (only if the permission allows seeking and changing the volume),
+/// the playback state and the playback buttons. The ids are used by `INDEX_SCRIPT`.
+pub fn controls(db: &Database, permission: Permission) -> String {
+    let mut out = String::from("<div class=\"controls\">");
+    if permission.allows(&Action::Seek(0)) {
+        out.push_str(&format!(
+            "<div id=\"seekBar\" data-position=\"{}\" style=\"display:none;\"><input type=\"range\" id=\"seekSlider\" min=\"0\" max=\"0\" value=\"0\"> <small id=\"seekTime\"></small></div>",
+            position_text(db, None),
+        ));
+    }
+    out.push_str(&format!(
+        "<small><span id=\"playbackState\">{}</span>, volume <span id=\"volume\">{}</span></small>",
+        playback_state(db),
+        volume_percent(db.volume),
+    ));
+    if permission.allows(&Action::SetVolume(0.0)) {
+        out.push_str(&format!(
+            "<div><input type=\"range\" id=\"volumeSlider\" min=\"0\" max=\"100\" value=\"{}\"></div>",
+            (db.volume * 100.0).round(),
+        ));
+    }
+    out.push_str("<button onclick=\"fetch('/play')\">play</button><button onclick=\"fetch('/pause')\">pause</button><button onclick=\"fetch('/stop')\">stop</button><button onclick=\"fetch('/skip')\">skip</button><button onclick=\"fetch('/clear-queue')\">clear queue</button></div>");
+    out
+}
+
+/// the size at which covers are shown, in css pixels
+pub const COVER_SIZE: u32 = 128;
+/// the cover, or a placeholder with the first letter of `name`
+pub fn cover_html(cover: Option<CoverId>, name: &str) -> String {
+    match cover {
+        Some(id) => {
+            format!("<img src=\"/cover/{id}\" width=\"{COVER_SIZE}\" height=\"{COVER_SIZE}\">")
+        }
+        None => placeholder_svg(name),
+    }
+}
+/// a square with the first letter of `name`, in a color which depends on `name`, for songs without a cover
+pub fn placeholder_svg(name: &str) -> String {
+    let letter = name
+        .chars()
+        .find(|c| c.is_alphanumeric())
+        .map_or('?', |c| c.to_uppercase().next().unwrap_or(c));
+    let hue = name
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32))
+        % 360;
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{COVER_SIZE}\" height=\"{COVER_SIZE}\" viewBox=\"0 0 100 100\"><rect width=\"100\" height=\"100\" fill=\"hsl({hue}, 40%, 40%)\"/><text x=\"50\" y=\"50\" font-size=\"50\" font-family=\"sans-serif\" fill=\"white\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text></svg>",
+        html_escape::encode_text(&letter.to_string()),
+    )
+}
+
+/// the library search, which is hidden until `INDEX_SCRIPT` runs
+const SEARCH: &str = r#"<input id="librarySearchField" placeholder="search" oninput="librarySearchLater()"><div id="librarySearchResultDiv"></div>
+<input id="searchFieldArtist" placeholder="artist"><input id="searchFieldAlbum" placeholder="album"><input id="searchFieldTitle" placeholder="title">
+<button onclick="performSearch()">search</button><div id="searchResultDiv"></div>"#;
+/// the functions used by the buttons
+const INDEX_HEAD_SCRIPT: &str = r#"
+const sleep = ms => new Promise(r => setTimeout(r, ms));
+async function performSearch() {
+    var searchResultDiv = document.getElementById("searchResultDiv");
+    searchResultDiv.innerHTML = "Loading...";
+    var sfArtist = document.getElementById("searchFieldArtist").value;
+    var sfAlbum = document.getElementById("searchFieldAlbum").value;
+    var sfTitle = document.getElementById("searchFieldTitle").value;
+    var query = "";
+    if (sfArtist) {
+        query += "artist=" + encodeURIComponent(sfArtist);
+    }
+    if (sfAlbum) {
+        if (query) {
+            query += "&";
+        }
+        query += "album=" + encodeURIComponent(sfAlbum);
+    }
+    if (sfTitle) {
+        if (query) {
+            query += "&";
+        }
+        query += "title=" + encodeURIComponent(sfTitle);
+    }
+    if (query || confirm("You didn't search for anything specific. If you continue, the whole library will be loaded, which can take a while, use a lot of bandwidth, and may crash your browser!")) {
+        console.log("Performing search with query '" + query + "'.");
+        var r1 = await fetch("/search?" + query);
+        var r2 = await r1.text();
+        searchResultDiv.innerHTML = r2;
+    } else {
+        searchResultDiv.innerHTML = "";
+    }
+}
+// the queue may have changed, reload it if the song isn't where it was
+async function queueAction(url) {
+    if ((await fetch(url)).status == 409) {
+        refreshQueue();
+    }
+}
+async function addSong(id) {
+    await fetch("/add-song/" + id);
+}
+async function addToQueue(url) {
+    var r = await fetch(url);
+    document.getElementById("addStatus").innerText = await r.text();
+}
+var librarySearchTimeout = null;
+function librarySearchLater() {
+    clearTimeout(librarySearchTimeout);
+    librarySearchTimeout = setTimeout(() => librarySearch(0), 300);
+}
+async function librarySearch(page) {
+    var query = document.getElementById("librarySearchField").value;
+    var r = await fetch("/library-search?q=" + encodeURIComponent(query) + "&page=" + page);
+    document.getElementById("librarySearchResultDiv").innerHTML = await r.text();
+}
+"#;
+/// keeps the page up to date, see `/events`
+const INDEX_SCRIPT: &str = r#"
+const searchDiv = document.getElementById("searchDiv");
+searchDiv.style.display = "";
+document.getElementById("warnLag").innerText = "connecting...";
+const nowPlayingDiv = document.getElementById("nowPlayingDiv");
+const queueDiv = document.getElementById("queueDiv");
+var lastNowPlaying = null;
+var didFinish = false;
+var averageLoopTimeMs = 250;
+// while connected to /events, the page is updated when something changes instead of polling
+var liveUpdates = false;
+async function refreshNowPlaying() {
+    var nowPlaying = await (await fetch("/now-playing-html")).text();
+    // replacing the cover image every time would reload it
+    if (nowPlaying != lastNowPlaying) {
+        nowPlayingDiv.innerHTML = nowPlaying;
+        lastNowPlaying = nowPlaying;
+    }
+}
+async function refreshQueue() {
+    queueDiv.innerHTML = await (await fetch("/queue-html")).text();
+}
+const events = new EventSource("/events");
+events.onopen = function() {
+    liveUpdates = true;
+    document.getElementById("warnLag").innerText = "live";
+    refreshNowPlaying();
+    refreshQueue();
+    refreshPosition();
+};
+events.onerror = function() {
+    // the browser reconnects automatically
+    liveUpdates = false;
+};
+events.addEventListener("song", refreshNowPlaying);
+events.addEventListener("queue", function() {
+    refreshNowPlaying();
+    refreshQueue();
+});
+events.addEventListener("playback", e => document.getElementById("playbackState").innerText = e.data);
+events.addEventListener("volume", function(e) {
+    document.getElementById("volume").innerText = e.data;
+    if (volumeSlider && document.activeElement != volumeSlider) {
+        volumeSlider.value = parseFloat(e.data);
+    }
+});
+events.addEventListener("position", e => setPosition(e.data));
+// the seek bar and volume slider only exist if this page may seek and change the volume
+const seekBar = document.getElementById("seekBar");
+const seekSlider = document.getElementById("seekSlider");
+const volumeSlider = document.getElementById("volumeSlider");
+var seekAvailable = seekBar != null;
+var seeking = false;
+var position = null;
+function setPosition(text) {
+    var parts = text.split(" ");
+    if (parts.length == 3) {
+        position = { elapsed: Number(parts[0]), duration: Number(parts[1]), playing: parts[2] == "1", at: Date.now() };
+    } else {
+        position = null;
+    }
+    showPosition();
+}
+function formatTime(ms) {
+    var s = Math.floor(ms / 1000);
+    return Math.floor(s / 60) + ":" + String(s % 60).padStart(2, "0");
+}
+function showPosition() {
+    if (!seekAvailable || position == null) {
+        if (seekBar) {
+            seekBar.style.display = "none";
+        }
+        return;
+    }
+    seekBar.style.display = "";
+    var elapsed = position.elapsed;
+    if (position.playing) {
+        elapsed += Date.now() - position.at;
+    }
+    elapsed = Math.min(elapsed, position.duration);
+    seekSlider.max = position.duration;
+    // don't move the slider while the user is dragging it
+    if (!seeking) {
+        seekSlider.value = elapsed;
+    }
+    document.getElementById("seekTime").innerText = formatTime(seeking ? seekSlider.value : elapsed) + " / " + formatTime(position.duration);
+}
+async function refreshPosition() {
+    if (seekAvailable) {
+        setPosition(await (await fetch("/position")).text());
+    }
+}
+if (seekAvailable) {
+    seekSlider.addEventListener("input", function() {
+        seeking = true;
+        showPosition();
+    });
+    seekSlider.addEventListener("change", async function() {
+        seeking = false;
+        if (!(await fetch("/seek/" + seekSlider.value)).ok) {
+            seekAvailable = false;
+        }
+        showPosition();
+    });
+    setPosition(seekBar.dataset.position);
+    setInterval(showPosition, 500);
+}
+var volumeTimeout = null;
+if (volumeSlider) {
+    // only send the last value after the slider stopped moving
+    volumeSlider.addEventListener("input", function() {
+        clearTimeout(volumeTimeout);
+        volumeTimeout = setTimeout(async function() {
+            if (!(await fetch("/volume/" + volumeSlider.value)).ok) {
+                volumeSlider.style.display = "none";
+            }
+        }, 200);
+    });
+}
+async function runLoop() {
+    while (true) {
+        await sleep(1000);
+        if (liveUpdates) {
+            continue;
+        }
+        didFinish = false;
+        var startTime = new Date();
+        sleep(averageLoopTimeMs*2).then(async function() {
+            while (!didFinish) {
+                var elapsed = new Date() - startTime;
+                document.getElementById("warnLag").innerText = "Warning: slow connection, server may be busy. be patient. (" + Math.round(averageLoopTimeMs) + "ms exceeded by " + Math.round((elapsed-averageLoopTimeMs)/averageLoopTimeMs) + "x)";
+                await sleep(100);
+            }
+        });
+        await refreshNowPlaying();
+        await refreshQueue();
+        await refreshPosition();
+        var elapsedTime = new Date() - startTime;
+        didFinish = true;
+        averageLoopTimeMs = ((averageLoopTimeMs * 4) + elapsedTime) / 5;
+        document.getElementById("warnLag").innerText = "Average update time: " + Math.round(averageLoopTimeMs) + "ms";
+    }
+}
+runLoop();
+const upcomingDiv = document.getElementById("upcomingDiv");
+async function runUpcomingLoop() {
+    while (true) {
+        try {
+            // waits until the upcoming songs change
+            var version = upcomingDiv.firstElementChild.dataset.version;
+            upcomingDiv.innerHTML = await (await fetch("/upcoming-html?since=" + version)).text();
+        } catch (e) {
+            await sleep(5000);
+        }
+    }
+}
+runUpcomingLoop();"#;
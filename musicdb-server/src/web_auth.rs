@@ -0,0 +1,189 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use musicdb_lib::{log_info, log_warn, server::auth::tokens_eq};
+use rand::Rng;
+use rocket::{
+    form::{Form, FromForm},
+    get,
+    http::{Cookie, CookieJar, SameSite, Status},
+    post,
+    request::{FromRequest, Outcome, Request},
+    response::{content::RawHtml, Redirect},
+    State,
+};
+
+/// the cookie which holds the session token
+const SESSION_COOKIE: &str = "musicdb_session";
+/// how long a browser stays logged in after entering the code
+const SESSION_DURATION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// after this many failed attempts, logins from the same address are rejected ...
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+/// ... until this long after the last failed attempt.
+const FAILED_ATTEMPTS_WINDOW: Duration = Duration::from_secs(60);
+
+/// The website's optional access code (`--web-password`), only managed by rocket if one is set.
+/// Browsers enter the code once on `/login` (it is sent in the request body, never in the url)
+/// and then get a session cookie, which the `Control` and `View` request guards check.
+pub struct WebAuth {
+    password: String,
+    /// if false, everyone can see the library, queue and current song, and only changes require a session
+    protect_reading: bool,
+    /// session tokens and when they were created
+    sessions: Mutex<HashMap<String, Instant>>,
+    /// number of failed attempts and the time of the last one, per address
+    failed_attempts: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+}
+
+impl WebAuth {
+    pub fn new(password: String, protect_reading: bool) -> Self {
+        Self {
+            password,
+            protect_reading,
+            sessions: Mutex::new(HashMap::new()),
+            failed_attempts: Mutex::new(HashMap::new()),
+        }
+    }
+    fn has_session(&self, cookies: &CookieJar<'_>) -> bool {
+        let Some(cookie) = cookies.get(SESSION_COOKIE) else {
+            return false;
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, created| created.elapsed() < SESSION_DURATION);
+        sessions.contains_key(cookie.value())
+    }
+    fn is_blocked(&self, addr: IpAddr) -> bool {
+        self.failed_attempts
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .is_some_and(|(count, last)| {
+                *count >= MAX_FAILED_ATTEMPTS && last.elapsed() < FAILED_ATTEMPTS_WINDOW
+            })
+    }
+    /// records a failed attempt and returns the number of recent failed attempts from this address
+    fn failed(&self, addr: IpAddr) -> u32 {
+        let mut failed_attempts = self.failed_attempts.lock().unwrap();
+        failed_attempts.retain(|_, (_, last)| last.elapsed() < FAILED_ATTEMPTS_WINDOW);
+        let entry = failed_attempts.entry(addr).or_insert((0, Instant::now()));
+        entry.0 += 1;
+        entry.1 = Instant::now();
+        entry.0
+    }
+    /// creates a new session and returns its token
+    fn new_session(&self) -> String {
+        let token = rand::thread_rng()
+            .gen::<[u8; 32]>()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(token.clone(), Instant::now());
+        token
+    }
+}
+
+/// Request guard for endpoints which change something: requires a session if an access code is set.
+pub struct Control;
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Control {
+    type Error = ();
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.rocket().state::<WebAuth>() {
+            Some(auth) if !auth.has_session(request.cookies()) => {
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+            _ => Outcome::Success(Self),
+        }
+    }
+}
+/// Request guard for endpoints which only show something: requires a session if an access code is set
+/// and `--web-password-read` was used.
+pub struct View;
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for View {
+    type Error = ();
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.rocket().state::<WebAuth>() {
+            Some(auth) if auth.protect_reading && !auth.has_session(request.cookies()) => {
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+            _ => Outcome::Success(Self),
+        }
+    }
+}
+
+/// Request guard which is true if an access code is set and this browser hasn't entered it yet,
+/// so the page can show a link to `/login`. Never fails.
+pub struct NeedsLogin(pub bool);
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for NeedsLogin {
+    type Error = ();
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self(
+            request
+                .rocket()
+                .state::<WebAuth>()
+                .is_some_and(|auth| !auth.has_session(request.cookies())),
+        ))
+    }
+}
+
+fn login_page(message: &str) -> RawHtml<String> {
+    crate::web::templates::page(
+        "Log in",
+        &format!("<p>{message}</p><form method=\"post\" action=\"/login\"><input type=\"password\" name=\"password\" placeholder=\"access code\" autofocus><button type=\"submit\">log in</button></form>"),
+    )
+}
+#[get("/login")]
+pub fn login_form() -> RawHtml<String> {
+    login_page("Enter the access code to control playback and the queue.")
+}
+#[derive(FromForm)]
+pub struct Login<'r> {
+    password: &'r str,
+}
+#[post("/login", data = "<login>")]
+pub async fn login(
+    auth: &State<WebAuth>,
+    addr: IpAddr,
+    cookies: &CookieJar<'_>,
+    login: Form<Login<'_>>,
+) -> Result<Redirect, (Status, RawHtml<String>)> {
+    if auth.is_blocked(addr) {
+        return Err((
+            Status::TooManyRequests,
+            login_page("Too many failed attempts, try again later."),
+        ));
+    }
+    if tokens_eq(login.password.as_bytes(), auth.password.as_bytes()) {
+        auth.failed_attempts.lock().unwrap().remove(&addr);
+        log_info!("Website login from {addr}");
+        cookies.add(
+            Cookie::build((SESSION_COOKIE, auth.new_session()))
+                .http_only(true)
+                .same_site(SameSite::Strict)
+                .max_age(rocket::time::Duration::seconds(
+                    SESSION_DURATION.as_secs() as i64
+                )),
+        );
+        Ok(Redirect::to("/"))
+    } else {
+        let attempts = auth.failed(addr);
+        log_warn!("Failed website login from {addr} ({attempts} in a row)");
+        // slow down guessing
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        Err((Status::Unauthorized, login_page("Wrong access code.")))
+    }
+}
+/// shown instead of pages which require a session
+#[rocket::catch(401)]
+pub fn unauthorized() -> RawHtml<String> {
+    login_page("This requires the access code.")
+}